@@ -0,0 +1,109 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use chrono::{DateTime, FixedOffset};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use transit_model::{gtfs, model::Model, ntfs, testing::SyntheticDataset, transfers};
+
+fn dataset() -> SyntheticDataset {
+    SyntheticDataset {
+        lines: 50,
+        ..Default::default()
+    }
+}
+
+fn model() -> Model {
+    dataset().model().expect("the synthetic dataset is valid")
+}
+
+fn datetime() -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339("2020-01-01T12:00:00+01:00").unwrap()
+}
+
+fn read(c: &mut Criterion) {
+    let ntfs_dir = tempfile::tempdir().unwrap();
+    ntfs::write(&model(), ntfs_dir.path(), datetime()).unwrap();
+    let gtfs_dir = tempfile::tempdir().unwrap();
+    gtfs::write(model(), gtfs_dir.path(), false).unwrap();
+
+    c.bench_function("ntfs::read", |b| {
+        b.iter(|| ntfs::read(ntfs_dir.path()).unwrap())
+    });
+    c.bench_function("gtfs::read", |b| {
+        b.iter(|| gtfs::read(gtfs_dir.path()).unwrap())
+    });
+
+    let gtfs_zip = gtfs_dir.path().join("gtfs.zip");
+    gtfs::write_to_zip(model(), &gtfs_zip, false).unwrap();
+    c.bench_function("gtfs::read zip", |b| {
+        b.iter(|| gtfs::read(&gtfs_zip).unwrap())
+    });
+    c.bench_function("gtfs::read zip in memory", |b| {
+        b.iter(|| {
+            let configuration = gtfs::Configuration {
+                zip_memory_budget: Some(1 << 30),
+                ..Default::default()
+            };
+            gtfs::Reader::new(configuration).parse(&gtfs_zip).unwrap()
+        })
+    });
+}
+
+fn write(c: &mut Criterion) {
+    let model = model();
+    c.bench_function("ntfs::write", |b| {
+        b.iter_batched(
+            || tempfile::tempdir().unwrap(),
+            |dir| ntfs::write(&model, dir.path(), datetime()).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    c.bench_function("gtfs::write", |b| {
+        b.iter_batched(
+            || (dataset().model().unwrap(), tempfile::tempdir().unwrap()),
+            |(model, dir)| gtfs::write(model, dir.path(), false).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+fn generate_transfers(c: &mut Criterion) {
+    c.bench_function("transfers::generates_transfers", |b| {
+        b.iter_batched(
+            model,
+            |model| transfers::generates_transfers(model, 500., 0.785, 60, None).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn restrict_period(c: &mut Criterion) {
+    let dataset = dataset();
+    let start_date = dataset.start_date + chrono::Duration::days(7);
+    let end_date = start_date + chrono::Duration::days(6);
+    c.bench_function("Collections::restrict_period", |b| {
+        b.iter_batched(
+            || dataset.collections(),
+            |mut collections| collections.restrict_period(start_date, end_date).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = read, write, generate_transfers, restrict_period
+}
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+// Copyright 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset};
+use std::{fs::File, path::PathBuf};
+use structopt::StructOpt;
+use tracing::info;
+use transit_model::{
+    gtfs::clean::{CleanOptions, Enhancer},
+    timing::{self, TimingLayer},
+    Result,
+};
+
+lazy_static::lazy_static! {
+    pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
+}
+
+fn get_version() -> &'static str {
+    &GIT_VERSION
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "gtfs2gtfs",
+    about = "Clean a GTFS, keeping its identifiers.",
+    version = get_version()
+)]
+struct Opt {
+    /// Input directory or ZIP archive.
+    #[structopt(short, long, parse(from_os_str), default_value = ".")]
+    input: PathBuf,
+
+    /// Output directory, or ZIP archive if it has the '.zip' extension.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Write the JSON report of the enhancers applied in this file.
+    #[structopt(short, long, parse(from_os_str))]
+    report: PathBuf,
+
+    /// Apply this enhancer: 'deduplicate_calendars', 'fill_colors',
+    /// 'drop_dead_services' or 'sanitize'. Can be repeated, the enhancers
+    /// being applied in this order.
+    #[structopt(long = "enhancer", number_of_values = 1)]
+    enhancers: Vec<Enhancer>,
+
+    #[structopt(
+        long,
+        help = "Support a more rich set of route types. \
+                For more information, see \
+                https://developers.google.com/transit/gtfs/reference/extended-route-types"
+    )]
+    extend_route_type: bool,
+
+    /// Current datetime, the services without any date from this day being
+    /// removed by 'drop_dead_services'.
+    #[structopt(
+        short = "x",
+        long,
+        parse(try_from_str),
+        default_value = &transit_model::CURRENT_DATETIME
+    )]
+    current_datetime: DateTime<FixedOffset>,
+
+    /// Print the top time consumers at the end of the conversion.
+    #[structopt(long)]
+    timing_summary: bool,
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("Launching gtfs2gtfs...");
+
+    let options = CleanOptions {
+        enhancers: opt.enhancers,
+        current_date: opt.current_datetime.date_naive(),
+        extend_route_type: opt.extend_route_type,
+    };
+    let report = transit_model::gtfs::clean::clean(opt.input, opt.output, &options)?;
+    let report_path = opt.report;
+    let file =
+        File::create(&report_path).with_context(|| format!("Error creating {:?}", report_path))?;
+    serde_json::to_writer_pretty(file, &report)
+        .with_context(|| format!("Error writing {:?}", report_path))?;
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let timing = opt.timing_summary.then(TimingLayer::default);
+    timing::init_logger(timing.clone());
+    let result = run(opt);
+    if let Some(timing) = &timing {
+        timing.print_summary();
+    }
+    if let Err(err) = result {
+        for cause in err.chain() {
+            eprintln!("{}", cause);
+        }
+        std::process::exit(1);
+    }
+}
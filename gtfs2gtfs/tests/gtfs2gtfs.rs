@@ -0,0 +1,122 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use assert_cmd::prelude::*;
+use pretty_assertions::assert_eq;
+use std::{collections::BTreeSet, fs, path::Path, process::Command};
+use tempfile::TempDir;
+
+// The values of the column `column` of the GTFS file `path`
+fn column(path: &Path, column: &str) -> BTreeSet<String> {
+    let mut reader = csv::Reader::from_path(path).unwrap();
+    let index = reader
+        .headers()
+        .unwrap()
+        .iter()
+        .position(|header| header == column)
+        .unwrap();
+    reader
+        .records()
+        .map(|record| record.unwrap()[index].to_string())
+        .collect()
+}
+
+fn gtfs2gtfs(output_dir: &Path, enhancers: &[&str]) -> serde_json::Value {
+    let report = output_dir.join("report.json");
+    let mut command = Command::cargo_bin("gtfs2gtfs").expect("Failed to find binary 'gtfs2gtfs'");
+    command
+        .arg("--input")
+        .arg("tests/fixtures/input")
+        .arg("--output")
+        .arg(output_dir.join("gtfs"))
+        .arg("--report")
+        .arg(&report)
+        .arg("--current-datetime")
+        .arg("2020-06-01T10:00:00Z");
+    for enhancer in enhancers {
+        command.arg("--enhancer").arg(enhancer);
+    }
+    command.assert().success();
+    serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap()
+}
+
+fn set(values: &[&str]) -> BTreeSet<String> {
+    values.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn test_gtfs2gtfs_keeps_identifiers() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = gtfs2gtfs(output_dir.path(), &[]);
+    let input = Path::new("tests/fixtures/input");
+    let gtfs = output_dir.path().join("gtfs");
+
+    for (file, id) in &[
+        ("agency.txt", "agency_id"),
+        ("stops.txt", "stop_id"),
+        ("stops.txt", "parent_station"),
+        ("routes.txt", "route_id"),
+        ("trips.txt", "trip_id"),
+        ("trips.txt", "service_id"),
+        ("stop_times.txt", "stop_id"),
+    ] {
+        assert_eq!(
+            column(&input.join(file), id),
+            column(&gtfs.join(file), id),
+            "{} of {}",
+            id,
+            file
+        );
+    }
+    assert_eq!(0, report["enhancers"].as_array().unwrap().len());
+    assert_eq!(4, report["output_object_counts"]["trip"]);
+}
+
+#[test]
+fn test_gtfs2gtfs_with_enhancers() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = gtfs2gtfs(
+        output_dir.path(),
+        &["deduplicate_calendars", "fill_colors", "drop_dead_services"],
+    );
+    let gtfs = output_dir.path().join("gtfs");
+
+    assert_eq!(
+        set(&["t:1", "t:2", "t:4"]),
+        column(&gtfs.join("trips.txt"), "trip_id")
+    );
+    assert_eq!(
+        set(&["s:week"]),
+        column(&gtfs.join("trips.txt"), "service_id")
+    );
+    assert_eq!(
+        set(&["FF0000", "FFFFFF"]),
+        column(&gtfs.join("routes.txt"), "route_color")
+    );
+    assert_eq!(
+        set(&["000000", "FFFFFF"]),
+        column(&gtfs.join("routes.txt"), "route_text_color")
+    );
+
+    let enhancers = report["enhancers"].as_array().unwrap();
+    assert_eq!(3, enhancers.len());
+    assert_eq!("deduplicate_calendars", enhancers[0]["enhancer"]);
+    assert_eq!(1, enhancers[0]["changed_objects"]);
+    assert_eq!("fill_colors", enhancers[1]["enhancer"]);
+    assert_eq!(2, enhancers[1]["changed_objects"]);
+    assert_eq!("drop_dead_services", enhancers[2]["enhancer"]);
+    assert_eq!(1, enhancers[2]["changed_objects"]);
+    assert_eq!(4, report["input_object_counts"]["trip"]);
+    assert_eq!(3, report["output_object_counts"]["trip"]);
+}
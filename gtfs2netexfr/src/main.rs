@@ -23,7 +23,7 @@ use tracing_subscriber::{
     layer::SubscriberExt as _,
     util::SubscriberInitExt as _,
 };
-use transit_model::{configuration, Result};
+use transit_model::{configuration, gtfs::OnDemandTransportCommentScope, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -60,6 +60,11 @@ struct Opt {
     #[structopt(long = "odt-comment")]
     odt_comment: Option<String>,
 
+    /// Create one On-Demand Transport comment for each 'agency' or for each
+    /// 'line'.
+    #[structopt(long = "odt-comment-scope", default_value = "agency")]
+    odt_comment_scope: OnDemandTransportCommentScope,
+
     /// Name for the participant.
     ///
     /// For more information, see
@@ -113,6 +118,7 @@ fn run(opt: Opt) -> Result<()> {
         feed_infos,
         on_demand_transport: opt.odt,
         on_demand_transport_comment: opt.odt_comment,
+        on_demand_transport_comment_scope: opt.odt_comment_scope,
         ..Default::default()
     };
 
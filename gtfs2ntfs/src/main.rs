@@ -18,12 +18,15 @@ use chrono::{DateTime, FixedOffset};
 use std::path::PathBuf;
 use structopt::StructOpt;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    configuration,
+    gtfs::{DuplicateStopSequenceStrategy, OnDemandTransportCommentScope},
+    model::ContactFieldsMode,
+    timing::{self, TimingLayer},
+    transfers::{generates_reverse_transfers, generates_transfers},
+    validator::{validate, IssueCode},
+    PrefixConfiguration, Result,
 };
-use transit_model::{configuration, transfers::generates_transfers, PrefixConfiguration, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -68,12 +71,67 @@ struct Opt {
     #[structopt(long = "odt-comment")]
     odt_comment: Option<String>,
 
+    /// Create one On-Demand Transport comment for each 'agency' or for each
+    /// 'line'.
+    #[structopt(long = "odt-comment-scope", default_value = "agency")]
+    odt_comment_scope: OnDemandTransportCommentScope,
+
     /// If true, each GTFS `Route` will generate a different `Line`.
     /// Else we group the routes by `agency_id` and `route_short_name`
     /// (or `route_long_name` if the short name is empty) and create a `Line` for each group.
     #[structopt(long = "read-as-line")]
     read_as_line: bool,
 
+    /// Fail on an invalid agency URL or email instead of removing it.
+    #[structopt(long = "strict-contact-fields")]
+    strict_contact_fields: bool,
+
+    /// Agency of the routes without 'agency_id' when there are several
+    /// agencies, instead of failing.
+    #[structopt(long = "default-agency-id")]
+    default_agency_id: Option<String>,
+
+    /// Identify the trips generated from 'frequencies.txt' by their departure
+    /// time instead of numbering them.
+    #[structopt(long = "stable-ids")]
+    stable_ids: bool,
+
+    /// Consider the times of 'stop_times.txt' at a stop with a
+    /// 'stop_timezone' to be in this timezone, and convert them into the
+    /// timezone of the agencies.
+    #[structopt(long = "apply-stop-timezones")]
+    apply_stop_timezones: bool,
+
+    /// Simplify the shapes of 'shapes.txt', removing the points within this
+    /// tolerance in meters.
+    #[structopt(long = "shape-simplification-tolerance")]
+    shape_simplification_tolerance: Option<f64>,
+
+    /// CSV file of the modes of some route types, with the columns
+    /// 'route_type', 'physical_mode_id', 'commercial_mode_id' and
+    /// 'commercial_mode_name', replacing the default ones.
+    #[structopt(long = "route-type-mapping", parse(from_os_str))]
+    route_type_mapping: Option<PathBuf>,
+
+    /// Remove the dates of the calendars outside of the 'feed_start_date'
+    /// and the 'feed_end_date' of 'feed_info.txt'.
+    #[structopt(long = "restrict-to-feed-period")]
+    restrict_to_feed_period: bool,
+
+    /// Detect the encoding of the files (UTF-8, UTF-16 or Windows-1252)
+    /// instead of failing on the files not in UTF-8.
+    #[structopt(long = "detect-encoding")]
+    detect_encoding: bool,
+
+    /// What to do with the stop times of a trip with the same
+    /// 'stop_sequence': 'keep_first', 'renumber' or 'fail'.
+    #[structopt(long = "duplicate-stop-sequences", default_value = "keep_first")]
+    duplicate_stop_sequences: DuplicateStopSequenceStrategy,
+
+    /// Keep the trips with less than 2 stop times instead of removing them.
+    #[structopt(long = "keep-incomplete-trips")]
+    keep_incomplete_trips: bool,
+
     /// Current datetime.
     #[structopt(
         short = "x",
@@ -95,12 +153,40 @@ struct Opt {
     /// Waiting time at stop in seconds.
     #[structopt(long, short = "t", default_value = transit_model::TRANSFER_WAITING_TIME)]
     waiting_time: u32,
+
+    /// Generate the missing reverse transfers of the input transfers.
+    #[structopt(long = "reverse-transfers")]
+    reverse_transfers: bool,
+
+    /// Check the consistency of the input and write a JSON report in this file.
+    #[structopt(long, parse(from_os_str))]
+    validate: Option<PathBuf>,
+
+    /// Ignore the issues with this code in the report written with '--validate' (for example
+    /// 'unused_calendar'). Can be repeated.
+    #[structopt(long = "ignore-issue", number_of_values = 1)]
+    ignore_issues: Vec<IssueCode>,
+
+    /// Also write a KML file of each network in this directory, for a
+    /// visual inspection.
+    #[structopt(long, parse(from_os_str))]
+    kml: Option<PathBuf>,
+
+    /// Also write the mapping between the NTFS identifiers and the GTFS ones
+    /// of the trips, routes, stops and agencies in this CSV file (for
+    /// example 'mapping.csv'), to align a GTFS-RT feed.
+    #[structopt(long, parse(from_os_str))]
+    mapping: Option<PathBuf>,
+
+    /// Print the top time consumers at the end of the conversion.
+    #[structopt(long)]
+    timing_summary: bool,
 }
 
 fn run(opt: Opt) -> Result<()> {
     info!("Launching gtfs2ntfs...");
 
-    let (contributor, dataset, feed_infos) = configuration::read_config(opt.config)?;
+    let datasets = configuration::read_datasets_config(opt.config)?;
     let mut prefix_conf = PrefixConfiguration::default();
     if let Some(data_prefix) = opt.prefix {
         prefix_conf.set_data_prefix(data_prefix);
@@ -108,18 +194,54 @@ fn run(opt: Opt) -> Result<()> {
     if let Some(schedule_subprefix) = opt.schedule_subprefix {
         prefix_conf.set_schedule_subprefix(schedule_subprefix);
     }
+    let route_type_modes = match opt.route_type_mapping {
+        Some(path) => transit_model::gtfs::read_route_type_modes(path)?,
+        None => vec![],
+    };
     let configuration = transit_model::gtfs::Configuration {
-        contributor,
-        dataset,
-        feed_infos,
+        feed_infos: datasets.feed_infos.clone(),
         prefix_conf: Some(prefix_conf),
         on_demand_transport: opt.odt,
         on_demand_transport_comment: opt.odt_comment,
+        on_demand_transport_comment_scope: opt.odt_comment_scope,
         read_as_line: opt.read_as_line,
+        contact_fields_mode: if opt.strict_contact_fields {
+            ContactFieldsMode::Strict
+        } else {
+            ContactFieldsMode::Loose
+        },
+        default_agency_id: opt.default_agency_id,
+        skip_geometries: false,
+        zip_memory_budget: None,
+        skip_calendar_deduplication: false,
+        stable_ids: opt.stable_ids,
+        apply_stop_timezones: opt.apply_stop_timezones,
+        shape_simplification_tolerance: opt.shape_simplification_tolerance,
+        route_type_modes,
+        restrict_to_feed_period: opt.restrict_to_feed_period,
+        detect_encoding: opt.detect_encoding,
+        duplicate_stop_sequences: opt.duplicate_stop_sequences,
+        keep_incomplete_trips: opt.keep_incomplete_trips,
+        datasets: Some(datasets),
+        ..Default::default()
     };
 
-    let model = transit_model::gtfs::Reader::new(configuration).parse(opt.input)?;
+    let reader = transit_model::gtfs::Reader::new(configuration);
+    let model = if let Some(report_path) = opt.validate {
+        let collections = reader.parse_collections(opt.input)?;
+        let (mut report, model) = validate(collections);
+        report.ignore(&opt.ignore_issues);
+        report.write(report_path)?;
+        model?
+    } else {
+        reader.parse(opt.input)?
+    };
 
+    let model = if opt.reverse_transfers {
+        generates_reverse_transfers(model, opt.walking_speed, opt.waiting_time)?
+    } else {
+        model
+    };
     let model = generates_transfers(
         model,
         opt.max_distance,
@@ -128,6 +250,12 @@ fn run(opt: Opt) -> Result<()> {
         None,
     )?;
 
+    if let Some(kml_path) = opt.kml {
+        transit_model::kml::write_to_dir(&model, kml_path)?;
+    }
+    if let Some(mapping_path) = opt.mapping {
+        transit_model::gtfs::write_id_mapping(&model, mapping_path)?;
+    }
     match opt.output.extension() {
         Some(ext) if ext == "zip" => {
             transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
@@ -139,28 +267,15 @@ fn run(opt: Opt) -> Result<()> {
     Ok(())
 }
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
-}
-
 fn main() {
-    init_logger();
-    if let Err(err) = run(Opt::from_args()) {
+    let opt = Opt::from_args();
+    let timing = opt.timing_summary.then(TimingLayer::default);
+    timing::init_logger(timing.clone());
+    let result = run(opt);
+    if let Some(timing) = &timing {
+        timing.print_summary();
+    }
+    if let Err(err) = result {
         for cause in err.chain() {
             eprintln!("{}", cause);
         }
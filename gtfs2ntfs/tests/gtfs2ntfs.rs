@@ -70,3 +70,36 @@ fn test_gtfs2ntfs_create_not_zip_extension() {
         .success();
     assert!(ntfs_foobar.join("feed_infos.txt").is_file());
 }
+
+#[test]
+fn test_gtfs2ntfs_with_mapping() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let mapping = output_dir.path().join("mapping.csv");
+    Command::cargo_bin("gtfs2ntfs")
+        .expect("Failed to find binary 'gtfs2ntfs'")
+        .arg("--input")
+        .arg("../tests/fixtures/gtfs2ntfs/minimal/input")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--prefix")
+        .arg("PRE")
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .arg("--mapping")
+        .arg(mapping.to_str().unwrap())
+        .assert()
+        .success();
+    let mapping = std::fs::read_to_string(mapping).unwrap();
+    let mut lines = mapping.lines();
+    assert_eq!(
+        Some("object_type,ntfs_id,source_code_system,source_value"),
+        lines.next()
+    );
+    let lines: Vec<_> = lines.collect();
+    assert!(lines.contains(&"network,PRE:1,source,1"));
+    assert!(lines.contains(&"route,PRE:route:2,source,route:2"));
+    assert!(lines.contains(&"trip,PRE:trip:3,source,trip:3"));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("stop_point,PRE:") && line.contains(",source,")));
+}
@@ -0,0 +1,173 @@
+// Copyright 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use std::{fs::File, path::PathBuf};
+use structopt::StructOpt;
+use tracing::info;
+use transit_model::{
+    merge::{ConflictStrategy, MergeOptions, TransfersConfiguration},
+    timing::{self, TimingLayer},
+    PrefixConfiguration, Result,
+};
+
+lazy_static::lazy_static! {
+    pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
+}
+
+fn get_version() -> &'static str {
+    &GIT_VERSION
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "merge-ntfs", about = "Merge several NTFS into one.", version = get_version())]
+struct Opt {
+    /// Configuration file listing the inputs and their prefixes.
+    #[structopt(short, long, parse(from_os_str))]
+    config: PathBuf,
+
+    /// Output directory, or ZIP archive if it has the '.zip' extension.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Write the JSON report of the merge in this file.
+    #[structopt(short, long, parse(from_os_str))]
+    report: PathBuf,
+
+    /// How to handle the objects with the same identifier in several
+    /// inputs: 'fail', 'keep_first' or 'keep_last'.
+    #[structopt(long, default_value = "fail")]
+    conflict_strategy: ConflictStrategy,
+
+    /// Keep the identifiers of the inputs before their prefix as object codes
+    /// of system 'source'.
+    #[structopt(long = "keep-source-codes")]
+    keep_source_codes: bool,
+
+    /// Generate the transfers between the stop points of different inputs.
+    #[structopt(long = "generate-transfers")]
+    generate_transfers: bool,
+
+    /// The maximum distance in meters to compute the tranfer.
+    #[structopt(long, short = "d", default_value = transit_model::TRANSFER_MAX_DISTANCE)]
+    max_distance: f64,
+
+    /// The walking speed in meters per second. You may want to divide your
+    /// initial speed by sqrt(2) to simulate Manhattan distances.
+    #[structopt(long, short = "s", default_value = transit_model::TRANSFER_WALKING_SPEED)]
+    walking_speed: f64,
+
+    /// Waiting time at stop in seconds.
+    #[structopt(long, short = "t", default_value = transit_model::TRANSFER_WAITING_TIME)]
+    waiting_time: u32,
+
+    /// Current datetime.
+    #[structopt(
+        short = "x",
+        long,
+        parse(try_from_str),
+        default_value = &transit_model::CURRENT_DATETIME
+    )]
+    current_datetime: DateTime<FixedOffset>,
+
+    /// Print the top time consumers at the end of the merge.
+    #[structopt(long)]
+    timing_summary: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputConfig {
+    path: PathBuf,
+    data_prefix: Option<String>,
+    schedule_subprefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    inputs: Vec<InputConfig>,
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("Launching merge-ntfs...");
+
+    let file =
+        File::open(&opt.config).with_context(|| format!("Error reading {:?}", opt.config))?;
+    let config: Config =
+        serde_json::from_reader(file).with_context(|| format!("Error reading {:?}", opt.config))?;
+    // The paths are relative to the configuration file
+    let config_dir = opt.config.parent().unwrap_or_else(|| "".as_ref());
+    let mut inputs = Vec::with_capacity(config.inputs.len());
+    let mut prefixes = Vec::with_capacity(config.inputs.len());
+    for input in config.inputs {
+        inputs.push(config_dir.join(input.path));
+        let mut prefix_conf = PrefixConfiguration::default();
+        if let Some(data_prefix) = input.data_prefix {
+            prefix_conf.set_data_prefix(data_prefix);
+        }
+        if let Some(schedule_subprefix) = input.schedule_subprefix {
+            prefix_conf.set_schedule_subprefix(schedule_subprefix);
+        }
+        prefix_conf.set_keep_source_codes(opt.keep_source_codes);
+        prefixes.push(prefix_conf);
+    }
+    let options = MergeOptions {
+        prefixes,
+        conflict_strategy: opt.conflict_strategy,
+        transfers: if opt.generate_transfers {
+            Some(TransfersConfiguration {
+                max_distance: opt.max_distance,
+                walking_speed: opt.walking_speed,
+                waiting_time: opt.waiting_time,
+            })
+        } else {
+            None
+        },
+    };
+
+    let (model, report) = transit_model::merge_datasets(inputs, &options)?;
+    let file =
+        File::create(&opt.report).with_context(|| format!("Error creating {:?}", opt.report))?;
+    serde_json::to_writer_pretty(file, &report)
+        .with_context(|| format!("Error writing {:?}", opt.report))?;
+
+    match opt.output.extension() {
+        Some(ext) if ext == "zip" => {
+            transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
+        }
+        _ => {
+            transit_model::ntfs::write(&model, opt.output, opt.current_datetime)?;
+        }
+    };
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let timing = opt.timing_summary.then(TimingLayer::default);
+    timing::init_logger(timing.clone());
+    let result = run(opt);
+    if let Some(timing) = &timing {
+        timing.print_summary();
+    }
+    if let Err(err) = result {
+        for cause in err.chain() {
+            eprintln!("{}", cause);
+        }
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,88 @@
+use assert_cmd::prelude::*;
+use std::{fs, path::Path, process::Command};
+use tempfile::TempDir;
+
+fn write_config(dir: &Path, prefixes: &[&str]) -> std::path::PathBuf {
+    let input = fs::canonicalize("../tests/fixtures/minimal_ntfs").unwrap();
+    let inputs: Vec<String> = prefixes
+        .iter()
+        .map(|prefix| format!(r#"{{"path": {:?}, "data_prefix": "{}"}}"#, input, prefix))
+        .collect();
+    let config = dir.join("config.json");
+    fs::write(&config, format!(r#"{{"inputs": [{}]}}"#, inputs.join(", "))).unwrap();
+    config
+}
+
+#[test]
+fn test_merge_ntfs() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let config = write_config(output_dir.path(), &["A", "B"]);
+    let ntfs = output_dir.path().join("ntfs");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("merge-ntfs")
+        .expect("Failed to find binary 'merge-ntfs'")
+        .arg("--config")
+        .arg(config.to_str().unwrap())
+        .arg("--output")
+        .arg(ntfs.to_str().unwrap())
+        .arg("--report")
+        .arg(report.to_str().unwrap())
+        .arg("--generate-transfers")
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .assert()
+        .success();
+    let networks = fs::read_to_string(ntfs.join("networks.txt")).unwrap();
+    assert!(networks.contains("A:TGN"));
+    assert!(networks.contains("B:TGN"));
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap();
+    assert_eq!("fail", report["conflict_strategy"]);
+    assert_eq!(2, report["inputs"].as_array().unwrap().len());
+    assert_eq!(
+        2 * report["inputs"][0]["object_counts"]["trip"]
+            .as_u64()
+            .unwrap(),
+        report["object_counts"]["trip"].as_u64().unwrap()
+    );
+}
+
+#[test]
+fn test_merge_ntfs_with_collisions() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let config = write_config(output_dir.path(), &["A", "A"]);
+    let ntfs = output_dir.path().join("ntfs.zip");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("merge-ntfs")
+        .expect("Failed to find binary 'merge-ntfs'")
+        .arg("--config")
+        .arg(config.to_str().unwrap())
+        .arg("--output")
+        .arg(ntfs.to_str().unwrap())
+        .arg("--report")
+        .arg(report.to_str().unwrap())
+        .assert()
+        .failure();
+
+    Command::cargo_bin("merge-ntfs")
+        .expect("Failed to find binary 'merge-ntfs'")
+        .arg("--config")
+        .arg(config.to_str().unwrap())
+        .arg("--output")
+        .arg(ntfs.to_str().unwrap())
+        .arg("--report")
+        .arg(report.to_str().unwrap())
+        .arg("--conflict-strategy")
+        .arg("keep_first")
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .assert()
+        .success();
+    assert!(ntfs.is_file());
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap();
+    let collisions = report["inputs"][1]["collisions"].as_array().unwrap();
+    assert!(collisions.iter().any(
+        |collision| collision["object_type"] == "network" && collision["object_id"] == "A:TGN"
+    ));
+}
@@ -336,6 +336,10 @@ impl<'a> VehicleJourneyBuilder<'a> {
                 drop_off_type: 0u8,
                 local_zone_id: None,
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             };
             st_muter(&mut stop_time);
 
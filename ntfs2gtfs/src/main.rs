@@ -18,12 +18,11 @@ use ntfs2gtfs::add_mode_to_line_code;
 use std::path::PathBuf;
 use structopt::StructOpt;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    timing::{self, TimingLayer},
+    validator::{validate, IssueCode},
+    Model, Result,
 };
-use transit_model::{Model, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -55,25 +54,52 @@ struct Opt {
                 https://developers.google.com/transit/gtfs/reference/extended-route-types"
     )]
     extend_route_type: bool,
-}
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
+    /// Write a single GTFS route for each line, instead of one for each of its
+    /// physical modes, the direction_id of the trips being given by the
+    /// direction_type of their route.
+    #[structopt(long)]
+    one_route_per_line: bool,
+
+    /// Export the tickets that can be expressed with them in fare_attributes.txt
+    /// and fare_rules.txt.
+    #[structopt(long)]
+    fares_v1: bool,
+
+    /// Export the tickets as GTFS-Fares v2 (fare_products.txt,
+    /// fare_leg_rules.txt, fare_media.txt, ...), along with fare_attributes.txt
+    /// and fare_rules.txt.
+    #[structopt(long)]
+    fares_v2: bool,
+
+    /// Write attributions.txt, with a producer for each contributor and an
+    /// operator for each company having a URL.
+    #[structopt(long)]
+    attributions: bool,
+
+    /// The system of the object codes to write as the stop_code of the stops
+    /// (for example 'gtfs_stop_code').
+    #[structopt(long)]
+    stop_code_source: Option<String>,
+
+    /// Replace the characters of the identifiers which are not ASCII letters,
+    /// digits, '_', ':' or '-' by '_', the rewritten identifiers being listed
+    /// in sanitized_ids.txt.
+    #[structopt(long)]
+    sanitize_ids: bool,
+
+    /// Check the consistency of the input and write a JSON report in this file.
+    #[structopt(long, parse(from_os_str))]
+    validate: Option<PathBuf>,
+
+    /// Ignore the issues with this code in the report written with '--validate' (for example
+    /// 'unused_calendar'). Can be repeated.
+    #[structopt(long = "ignore-issue", number_of_values = 1)]
+    ignore_issues: Vec<IssueCode>,
+
+    /// Print the top time consumers at the end of the conversion.
+    #[structopt(long)]
+    timing_summary: bool,
 }
 
 fn run(opt: Opt) -> Result<()> {
@@ -81,26 +107,55 @@ fn run(opt: Opt) -> Result<()> {
     let mut collections = transit_model::ntfs::read_collections(opt.input)?;
     collections.remove_stop_zones();
     collections.remove_route_points();
-    let mut model = Model::new(collections)?;
+    let mut model = if let Some(report_path) = opt.validate {
+        let (mut report, model) = validate(collections);
+        report.ignore(&opt.ignore_issues);
+        report.write(report_path)?;
+        model?
+    } else {
+        Model::new(collections)?
+    };
 
     if opt.mode_in_route_short_name {
         model = add_mode_to_line_code(model)?;
     }
 
+    let configuration = transit_model::gtfs::WriteConfiguration {
+        extend_route_type: opt.extend_route_type,
+        one_route_per_line: opt.one_route_per_line,
+        fares_v1: opt.fares_v1,
+        fares_v2: opt.fares_v2,
+        attributions: opt.attributions,
+        stop_code_source: opt.stop_code_source,
+        id_sanitization: opt
+            .sanitize_ids
+            .then(transit_model::gtfs::IdSanitization::default),
+        ..Default::default()
+    };
     match opt.output.extension() {
         Some(ext) if ext == "zip" => {
-            transit_model::gtfs::write_to_zip(model, opt.output, opt.extend_route_type)?;
+            transit_model::gtfs::write_to_zip_with_configuration(
+                model,
+                opt.output,
+                &configuration,
+            )?;
         }
         _ => {
-            transit_model::gtfs::write(model, opt.output, opt.extend_route_type)?;
+            transit_model::gtfs::write_with_configuration(model, opt.output, &configuration)?;
         }
     };
     Ok(())
 }
 
 fn main() {
-    init_logger();
-    if let Err(err) = run(Opt::from_args()) {
+    let opt = Opt::from_args();
+    let timing = opt.timing_summary.then(TimingLayer::default);
+    timing::init_logger(timing.clone());
+    let result = run(opt);
+    if let Some(timing) = &timing {
+        timing.print_summary();
+    }
+    if let Err(err) = result {
         for cause in err.chain() {
             eprintln!("{}", cause);
         }
@@ -176,3 +176,50 @@ fn test_ntfs2gtfs_split_route_by_mode_extended() {
         "./tests/fixtures/output_split_route_by_mode_extended",
     );
 }
+
+#[test]
+fn test_fares_v2() {
+    test_in_tmp_dir(|path| {
+        let input = "./tests/fixtures/input_fares_v2";
+        let model = transit_model::ntfs::read(input).unwrap();
+        let configuration = transit_model::gtfs::WriteConfiguration {
+            fares_v2: true,
+            ..Default::default()
+        };
+        transit_model::gtfs::write_with_configuration(model, path, &configuration).unwrap();
+        compare_output_dir_with_expected(
+            path,
+            Some(vec![
+                "fare_media.txt",
+                "fare_products.txt",
+                "fare_leg_rules.txt",
+                "areas.txt",
+                "stop_areas.txt",
+                "networks.txt",
+                "route_networks.txt",
+                "fare_attributes.txt",
+                "fare_rules.txt",
+            ]),
+            "./tests/fixtures/output_fares_v2",
+        );
+    });
+}
+
+#[test]
+fn test_fares_v1() {
+    test_in_tmp_dir(|path| {
+        let input = "./tests/fixtures/input_fares_v2";
+        let model = transit_model::ntfs::read(input).unwrap();
+        let configuration = transit_model::gtfs::WriteConfiguration {
+            fares_v1: true,
+            ..Default::default()
+        };
+        transit_model::gtfs::write_with_configuration(model, path, &configuration).unwrap();
+        assert!(!path.join("fare_products.txt").exists());
+        compare_output_dir_with_expected(
+            path,
+            Some(vec!["fare_attributes.txt", "fare_rules.txt"]),
+            "./tests/fixtures/output_fares_v2",
+        );
+    });
+}
@@ -18,12 +18,14 @@ use chrono::{DateTime, FixedOffset};
 use std::path::PathBuf;
 use structopt::StructOpt;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    anonymize::AnonymizeOptions,
+    model::{Model, SchoolPeriodsConfiguration},
+    timing::{self, TimingLayer},
+    transfers::{generates_reverse_transfers, generates_transfers},
+    validator::{validate, IssueCode},
+    Result,
 };
-use transit_model::{transfers::generates_transfers, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -65,31 +67,110 @@ struct Opt {
     /// Waiting time at stop in seconds.
     #[structopt(long, short = "t", default_value = transit_model::TRANSFER_WAITING_TIME)]
     waiting_time: u32,
-}
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
+    /// Generate the missing reverse transfers of the input transfers.
+    #[structopt(long = "reverse-transfers")]
+    reverse_transfers: bool,
+
+    /// Check the consistency of the input and write a JSON report in this file.
+    #[structopt(long, parse(from_os_str))]
+    validate: Option<PathBuf>,
+
+    /// Ignore the issues with this code in the report written with '--validate' (for example
+    /// 'unused_calendar'). Can be repeated.
+    #[structopt(long = "ignore-issue", number_of_values = 1)]
+    ignore_issues: Vec<IssueCode>,
+
+    /// Also write a KML file of each network in this directory, for a
+    /// visual inspection.
+    #[structopt(long, parse(from_os_str))]
+    kml: Option<PathBuf>,
+
+    /// Anonymize the dataset with this seed, to share it without leaking
+    /// commercial data: the names are replaced by pseudonyms, the
+    /// coordinates are moved randomly and the contact data are removed, the
+    /// same seed giving the same result.
+    #[structopt(long)]
+    anonymize: Option<u64>,
+
+    /// Maximum distance in meters the coordinates are moved by '--anonymize'.
+    #[structopt(long, default_value = "200")]
+    anonymize_radius: f64,
+
+    /// Replace the frequencies with 'exact_times=1' by a trip for each
+    /// departure.
+    #[structopt(long)]
+    expand_exact_frequencies: bool,
+
+    /// Tag each trip with the object property 'school_period', whose value is
+    /// 'school_only', 'holidays_only' or 'mixed' depending on how much of its
+    /// dates are dates of the grid calendars of its line.
+    #[structopt(long)]
+    tag_school_periods: bool,
+
+    /// Minimal ratio of school dates of a trip tagged 'school_only' by
+    /// '--tag-school-periods'.
+    #[structopt(long, default_value = "0.95")]
+    school_only_ratio: f64,
+
+    /// Maximal ratio of school dates of a trip tagged 'holidays_only' by
+    /// '--tag-school-periods'.
+    #[structopt(long, default_value = "0.05")]
+    holidays_only_ratio: f64,
+
+    /// Write the calendars only in 'calendar_dates.txt', without any
+    /// 'calendar.txt'.
+    #[structopt(long)]
+    calendar_dates_only: bool,
+
+    /// The version of the NTFS written, '0.11' or '0.12', the content
+    /// introduced by a later version being left out.
+    #[structopt(long, default_value = "0.12")]
+    ntfs_version: transit_model::ntfs::NtfsVersion,
+
+    /// Read back the written NTFS and check that it contains as many objects
+    /// as the model (always done by the debug builds).
+    #[structopt(long)]
+    verify: bool,
+
+    /// Print the top time consumers at the end of the conversion.
+    #[structopt(long)]
+    timing_summary: bool,
 }
 
 fn run(opt: Opt) -> Result<()> {
     info!("Launching ntfs2ntfs...");
 
-    let model = transit_model::ntfs::read(opt.input)?;
+    let mut collections = transit_model::ntfs::read_collections(opt.input)?;
+    if opt.expand_exact_frequencies {
+        collections.expand_exact_frequencies()?;
+    }
+    if opt.tag_school_periods {
+        let configuration = SchoolPeriodsConfiguration {
+            school_only_ratio: opt.school_only_ratio,
+            holidays_only_ratio: opt.holidays_only_ratio,
+        };
+        collections.tag_school_periods(&configuration);
+    }
+    if let Some(seed) = opt.anonymize {
+        let options = AnonymizeOptions {
+            jitter_radius: opt.anonymize_radius,
+        };
+        collections.anonymize(seed, &options);
+    }
+    let model = if let Some(report_path) = opt.validate {
+        let (mut report, model) = validate(collections);
+        report.ignore(&opt.ignore_issues);
+        report.write(report_path)?;
+        model?
+    } else {
+        Model::new(collections)?
+    };
+    let model = if opt.reverse_transfers {
+        generates_reverse_transfers(model, opt.walking_speed, opt.waiting_time)?
+    } else {
+        model
+    };
     let model = generates_transfers(
         model,
         opt.max_distance,
@@ -98,13 +179,31 @@ fn run(opt: Opt) -> Result<()> {
         None,
     )?;
 
+    if let Some(kml_path) = opt.kml {
+        transit_model::kml::write_to_dir(&model, kml_path)?;
+    }
     if let Some(output) = opt.output {
+        let configuration = transit_model::ntfs::WriteConfiguration {
+            calendar_dates_only: opt.calendar_dates_only,
+            version: opt.ntfs_version,
+            verify: opt.verify || cfg!(debug_assertions),
+        };
         match output.extension() {
             Some(ext) if ext == "zip" => {
-                transit_model::ntfs::write_to_zip(&model, output, opt.current_datetime)?;
+                transit_model::ntfs::write_to_zip_with_configuration(
+                    &model,
+                    output,
+                    opt.current_datetime,
+                    &configuration,
+                )?;
             }
             _ => {
-                transit_model::ntfs::write(&model, output, opt.current_datetime)?;
+                transit_model::ntfs::write_with_configuration(
+                    &model,
+                    output,
+                    opt.current_datetime,
+                    &configuration,
+                )?;
             }
         };
     }
@@ -112,8 +211,14 @@ fn run(opt: Opt) -> Result<()> {
 }
 
 fn main() {
-    init_logger();
-    if let Err(err) = run(Opt::from_args()) {
+    let opt = Opt::from_args();
+    let timing = opt.timing_summary.then(TimingLayer::default);
+    timing::init_logger(timing.clone());
+    let result = run(opt);
+    if let Some(timing) = &timing {
+        timing.print_summary();
+    }
+    if let Err(err) = result {
         for cause in err.chain() {
             eprintln!("{}", cause);
         }
@@ -70,3 +70,93 @@ fn test_ntfs2ntfs_create_foobar() {
         .success();
     assert!(ntfs_foobar.join("feed_infos.txt").is_file());
 }
+
+#[test]
+fn test_ntfs2ntfs_with_kml() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let kml_dir = output_dir.path().join("kml");
+    Command::cargo_bin("ntfs2ntfs")
+        .expect("Failed to find binary 'ntfs2ntfs'")
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .arg("--kml")
+        .arg(kml_dir.to_str().unwrap())
+        .assert()
+        .success();
+    let kml = std::fs::read_to_string(kml_dir.join("TGN.kml")).unwrap();
+    assert!(kml.contains("<name>The Great Network</name>"));
+}
+
+#[test]
+fn test_ntfs2ntfs_timing_summary() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let output = Command::cargo_bin("ntfs2ntfs")
+        .expect("Failed to find binary 'ntfs2ntfs'")
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .arg("--timing-summary")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Top time consumers:"));
+    assert!(stderr.contains("  build_model: "));
+}
+
+#[test]
+fn test_ntfs2ntfs_anonymize() {
+    let anonymize = |output_dir: &TempDir| {
+        Command::cargo_bin("ntfs2ntfs")
+            .expect("Failed to find binary 'ntfs2ntfs'")
+            .arg("--input")
+            .arg("../tests/fixtures/minimal_ntfs/")
+            .arg("--output")
+            .arg(output_dir.path().to_str().unwrap())
+            .arg("--current-datetime")
+            .arg("2019-04-03T17:19:00Z")
+            .arg("--anonymize")
+            .arg("42")
+            .assert()
+            .success();
+        std::fs::read_to_string(output_dir.path().join("stops.txt")).unwrap()
+    };
+    let first_dir = TempDir::new().expect("create temp dir failed");
+    let second_dir = TempDir::new().expect("create temp dir failed");
+    let stops = anonymize(&first_dir);
+    assert_eq!(stops, anonymize(&second_dir));
+    let input_stops = std::fs::read_to_string("../tests/fixtures/minimal_ntfs/stops.txt").unwrap();
+    assert!(input_stops.contains("Gare de Lyon"));
+    assert!(!stops.contains("Gare de Lyon"));
+    assert!(stops.contains("Stop point "));
+}
+
+#[test]
+fn test_ntfs2ntfs_tag_school_periods() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    Command::cargo_bin("ntfs2ntfs")
+        .expect("Failed to find binary 'ntfs2ntfs'")
+        .arg("--input")
+        .arg("../tests/fixtures/ntfs/")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--current-datetime")
+        .arg("2019-04-03T17:19:00Z")
+        .arg("--tag-school-periods")
+        .assert()
+        .success();
+    let object_properties =
+        std::fs::read_to_string(output_dir.path().join("object_properties.txt")).unwrap();
+    // The trips of the line M1 run in 2018, the grid calendar of the line in
+    // 2019
+    assert!(object_properties.contains("trip,M1F1,school_period,holidays_only"));
+    assert!(!object_properties.contains("B42F1"));
+}
@@ -14,14 +14,20 @@
 
 //! A trait for every structure that needs to be updated with a prefix
 
-use crate::model::Collections;
+use crate::{
+    id_string::{IdString, Interner},
+    model::Collections,
+    objects::Codes,
+};
 use derivative::Derivative;
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 use typed_index_collection::{Collection, CollectionWithId, Id};
 
+type IdRewriter = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
 /// Metadata for building the prefix.
-#[derive(Derivative, Debug)]
-#[derivative(Default)]
+#[derive(Derivative)]
+#[derivative(Default, Debug)]
 pub struct PrefixConfiguration {
     /// Separator used in the prefix, usually ':'.
     #[derivative(Default(value = "\":\".to_string()"))]
@@ -34,6 +40,12 @@ pub struct PrefixConfiguration {
     /// objects (like Calendar).  Usually useful to avoid collisions when
     /// merging datasets from the same contributor.
     schedule_subprefix: Option<String>,
+    /// Rewriting of the identifiers, applied before adding the prefix.
+    #[derivative(Debug = "ignore")]
+    id_rewriter: Option<IdRewriter>,
+    /// Keep the identifier of the objects before the prefix as an object
+    /// code of system `source`.
+    keep_source_codes: bool,
 }
 
 impl PrefixConfiguration {
@@ -61,6 +73,28 @@ impl PrefixConfiguration {
         self.schedule_subprefix = Some(schedule_subprefix.to_string());
     }
 
+    /// Keep the identifier of the objects with codes before the prefix as an
+    /// object code of system `source`, unless they already have such a code.
+    pub fn set_keep_source_codes(&mut self, keep_source_codes: bool) {
+        self.keep_source_codes = keep_source_codes;
+    }
+
+    /// Set a rewriting of every identifier, applied before adding the
+    /// prefix (see `gtfs::sanitize_ids`).
+    pub(crate) fn set_id_rewriter<F>(&mut self, id_rewriter: F)
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.id_rewriter = Some(Arc::new(id_rewriter));
+    }
+
+    fn rewrite<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        match &self.id_rewriter {
+            Some(id_rewriter) => Cow::Owned(id_rewriter(id)),
+            None => Cow::Borrowed(id),
+        }
+    }
+
     /// Add prefix for referential-type object.
     ///
     /// Example of objects from the referential are Line or StopPoint.
@@ -69,7 +103,7 @@ impl PrefixConfiguration {
         if let Some(data_prefix) = self.data_prefix.as_ref() {
             prefix = prefix + data_prefix + &self.sep;
         }
-        prefix + id
+        prefix + &self.rewrite(id)
     }
 
     /// Add prefix for schedule-type object.
@@ -83,7 +117,7 @@ impl PrefixConfiguration {
         if let Some(schedule_subprefix) = self.schedule_subprefix.as_ref() {
             prefix = prefix + schedule_subprefix + &self.sep;
         }
-        prefix + id
+        prefix + &self.rewrite(id)
     }
 }
 
@@ -96,6 +130,8 @@ pub trait AddPrefix {
             sep: String::new(),
             data_prefix: Some(prefix.to_string()),
             schedule_subprefix: None,
+            id_rewriter: None,
+            keep_source_codes: false,
         };
         self.prefix(&prefix_conf);
     }
@@ -108,6 +144,8 @@ pub trait AddPrefix {
             sep: String::from(sep),
             data_prefix: Some(prefix.to_string()),
             schedule_subprefix: None,
+            id_rewriter: None,
+            keep_source_codes: false,
         };
         self.prefix(&prefix_conf);
     }
@@ -141,41 +179,75 @@ where
 }
 
 fn add_prefix_on_vehicle_journey_ids(
-    vehicle_journey_ids: &HashMap<(String, u32), String>,
+    vehicle_journey_ids: &HashMap<(IdString, u32), IdString>,
     prefix_conf: &PrefixConfiguration,
-) -> HashMap<(String, u32), String> {
+) -> HashMap<(IdString, u32), IdString> {
+    let mut interner = Interner::default();
     vehicle_journey_ids
         .iter()
         .map(|((trip_id, sequence), value)| {
             (
-                (prefix_conf.schedule_prefix(trip_id.as_str()), *sequence),
-                value.to_string(),
+                (
+                    interner.intern(&prefix_conf.schedule_prefix(trip_id)),
+                    *sequence,
+                ),
+                value.clone(),
             )
         })
         .collect()
 }
 
 fn add_prefix_on_vehicle_journey_ids_and_values(
-    vehicle_journey_ids: &HashMap<(String, u32), String>,
+    vehicle_journey_ids: &HashMap<(IdString, u32), IdString>,
     prefix_conf: &PrefixConfiguration,
-) -> HashMap<(String, u32), String> {
+) -> HashMap<(IdString, u32), IdString> {
+    let mut interner = Interner::default();
     vehicle_journey_ids
         .iter()
         .map(|((trip_id, sequence), value)| {
             (
-                (prefix_conf.schedule_prefix(trip_id.as_str()), *sequence),
-                prefix_conf.schedule_prefix(value.as_str()),
+                (
+                    interner.intern(&prefix_conf.schedule_prefix(trip_id)),
+                    *sequence,
+                ),
+                interner.intern(&prefix_conf.schedule_prefix(value)),
             )
         })
         .collect()
 }
 
+fn add_source_codes<T>(collection: &mut CollectionWithId<T>)
+where
+    T: Id<T> + Codes,
+{
+    for index in collection.indexes() {
+        let mut object = collection.index_mut(index);
+        if object.codes().iter().all(|(system, _)| system != "source") {
+            let id = object.id().to_string();
+            object.codes_mut().insert(("source".into(), id));
+        }
+    }
+}
+
 impl AddPrefix for Collections {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        // The modes are never prefixed
+        if prefix_conf.keep_source_codes {
+            add_source_codes(&mut self.contributors);
+            add_source_codes(&mut self.networks);
+            add_source_codes(&mut self.companies);
+            add_source_codes(&mut self.lines);
+            add_source_codes(&mut self.routes);
+            add_source_codes(&mut self.vehicle_journeys);
+            add_source_codes(&mut self.stop_areas);
+            add_source_codes(&mut self.stop_points);
+        }
         self.contributors.prefix(prefix_conf);
         self.datasets.prefix(prefix_conf);
         self.networks.prefix(prefix_conf);
         self.lines.prefix(prefix_conf);
+        self.line_groups.prefix(prefix_conf);
+        self.line_group_links.prefix(prefix_conf);
         self.routes.prefix(prefix_conf);
         self.vehicle_journeys.prefix(prefix_conf);
         self.frequencies.prefix(prefix_conf);
@@ -187,6 +259,7 @@ impl AddPrefix for Collections {
         self.comments.prefix(prefix_conf);
         self.equipments.prefix(prefix_conf);
         self.transfers.prefix(prefix_conf);
+        self.trip_transfers.prefix(prefix_conf);
         self.trip_properties.prefix(prefix_conf);
         self.geometries.prefix(prefix_conf);
         self.admin_stations.prefix(prefix_conf);
@@ -200,10 +273,15 @@ impl AddPrefix for Collections {
         self.ticket_use_restrictions.prefix(prefix_conf);
         self.pathways.prefix(prefix_conf);
         self.levels.prefix(prefix_conf);
+        self.booking_rules.prefix(prefix_conf);
         self.grid_calendars.prefix(prefix_conf);
         self.grid_exception_dates.prefix(prefix_conf);
         self.grid_periods.prefix(prefix_conf);
         self.grid_rel_calendar_line.prefix(prefix_conf);
+        self.occupancies.prefix(prefix_conf);
+        self.addresses.prefix(prefix_conf);
+        self.brandings.prefix(prefix_conf);
+        self.translations.prefix(prefix_conf);
         self.stop_time_headsigns =
             add_prefix_on_vehicle_journey_ids(&self.stop_time_headsigns, prefix_conf);
         self.stop_time_ids =
@@ -216,6 +294,7 @@ impl AddPrefix for Collections {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::objects::{Line, Network, PhysicalMode, StopPoint};
     use pretty_assertions::assert_eq;
 
     struct Obj(String);
@@ -233,6 +312,72 @@ mod tests {
         }
     }
 
+    fn collections() -> Collections {
+        Collections {
+            networks: CollectionWithId::from(Network {
+                id: "N1".to_string(),
+                ..Default::default()
+            }),
+            lines: CollectionWithId::from(Line {
+                id: "L1".to_string(),
+                network_id: "N1".to_string(),
+                ..Default::default()
+            }),
+            stop_points: CollectionWithId::from(StopPoint {
+                id: "SP1".to_string(),
+                ..Default::default()
+            }),
+            physical_modes: CollectionWithId::from(PhysicalMode {
+                id: "Bus".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keep_source_codes_of_the_prefixed_objects() {
+        let mut collections = collections();
+        collections
+            .stop_points
+            .get_mut("SP1")
+            .unwrap()
+            .codes
+            .insert(("source".into(), "original".to_string()));
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix("pre");
+        prefix_conf.set_keep_source_codes(true);
+        collections.prefix(&prefix_conf);
+
+        let source_codes = |codes: &crate::objects::KeysValues| {
+            codes
+                .iter()
+                .filter(|(system, _)| system == "source")
+                .map(|(_, code)| code.clone())
+                .collect::<Vec<_>>()
+        };
+        let network = collections.networks.get("pre:N1").unwrap();
+        assert_eq!(vec!["N1"], source_codes(&network.codes));
+        let line = collections.lines.get("pre:L1").unwrap();
+        assert_eq!(vec!["L1"], source_codes(&line.codes));
+        let stop_point = collections.stop_points.get("pre:SP1").unwrap();
+        assert_eq!(vec!["original"], source_codes(&stop_point.codes));
+        let mode = collections.physical_modes.get("Bus").unwrap();
+        assert!(mode.codes.is_empty());
+    }
+
+    #[test]
+    fn no_source_codes_by_default() {
+        let mut collections = collections();
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix("pre");
+        collections.prefix(&prefix_conf);
+        assert!(collections
+            .networks
+            .values()
+            .all(|network| network.codes.is_empty()));
+    }
+
     #[test]
     fn collection_referential() {
         let obj1 = Obj(String::from("some_id"));
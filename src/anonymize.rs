@@ -0,0 +1,302 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Anonymization of a dataset, to share it for the reproduction of a bug
+//! without leaking commercial data.
+//!
+//! ```
+//! use transit_model::anonymize::AnonymizeOptions;
+//!
+//! # fn main() -> transit_model::Result<()> {
+//! let mut collections = transit_model::ntfs::read("tests/fixtures/minimal_ntfs")?.into_collections();
+//! collections.anonymize(42, &AnonymizeOptions::default());
+//! assert_eq!("Stop area", &collections.stop_areas.values().next().unwrap().name[..9]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{id_string::IdString, model::Collections, objects::Coord, testing::Rng};
+use geo::algorithm::map_coords::MapCoordsInplace;
+use std::f64::consts::PI;
+use typed_index_collection::{CollectionWithId, Id};
+
+/// Options of [Collections::anonymize]
+#[derive(Debug, Clone)]
+pub struct AnonymizeOptions {
+    /// Maximum distance in meters between the original coordinates and the
+    /// anonymized ones
+    pub jitter_radius: f64,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        AnonymizeOptions {
+            jitter_radius: 200.0,
+        }
+    }
+}
+
+// Meters by degree of latitude
+const METERS_BY_DEGREE: f64 = 111_320.0;
+
+// A number derived from the seed and a value, independent of the order in
+// which the objects are anonymized
+fn hash(seed: u64, kind: &str, value: &str) -> u64 {
+    let digest = md5::compute(format!("{}\0{}\0{}", seed, kind, value));
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+struct Anonymizer {
+    seed: u64,
+    jitter_radius: f64,
+}
+
+impl Anonymizer {
+    fn pseudonym(&self, label: &str, value: &str) -> String {
+        format!("{} {:08x}", label, hash(self.seed, label, value) >> 32)
+    }
+
+    fn jitter(&self, kind: &str, value: &str, coord: Coord) -> Coord {
+        // The coordinates not set yet (computed later from the stop points
+        // for a stop area) are kept
+        if coord == Coord::default() {
+            return coord;
+        }
+        let mut rng = Rng::new(hash(self.seed, kind, value));
+        // Uniform distribution in the disk
+        let distance = self.jitter_radius * rng.gen_f64().sqrt();
+        let angle = 2.0 * PI * rng.gen_f64();
+        let lat = coord.lat + distance * angle.sin() / METERS_BY_DEGREE;
+        let lon =
+            coord.lon + distance * angle.cos() / (METERS_BY_DEGREE * coord.lat.to_radians().cos());
+        Coord { lon, lat }
+    }
+}
+
+// Update each object of the collection, the identifiers staying the same
+fn update<T: Id<T>>(collection: &mut CollectionWithId<T>, f: impl FnMut(&mut T)) {
+    let mut objects = collection.take();
+    objects.iter_mut().for_each(f);
+    *collection = CollectionWithId::new(objects).unwrap();
+}
+
+impl Collections {
+    /// Replaces the names of the stops, lines, routes, networks, companies,
+    /// contributors, tickets, addresses and brandings, the headsigns, the
+    /// comments and the translations by pseudonyms, moves the coordinates of
+    /// the stops and the geometries randomly within `options.jitter_radius`,
+    /// and removes the urls, logos, emails, phone numbers, postal addresses
+    /// and descriptions.
+    ///
+    /// The identifiers, the topology, the times and the calendars are kept so
+    /// that a bug can still be reproduced, and the result only depends on the
+    /// `seed`: two people anonymizing the same dataset with the same seed get
+    /// the same result. The pseudonym of an object is derived from its
+    /// identifier, and the pseudonym of a headsign from its text so that equal
+    /// headsigns stay equal.
+    pub fn anonymize(&mut self, seed: u64, options: &AnonymizeOptions) {
+        let anonymizer = Anonymizer {
+            seed,
+            jitter_radius: options.jitter_radius,
+        };
+        let anonymizer = &anonymizer;
+
+        update(&mut self.contributors, |contributor| {
+            contributor.name = anonymizer.pseudonym("Contributor", &contributor.id);
+            contributor.website = None;
+        });
+        update(&mut self.datasets, |dataset| dataset.desc = None);
+        update(&mut self.networks, |network| {
+            network.name = anonymizer.pseudonym("Network", &network.id);
+            network.url = None;
+            network.phone = None;
+            network.address = None;
+        });
+        update(&mut self.companies, |company| {
+            company.name = anonymizer.pseudonym("Company", &company.id);
+            company.address = None;
+            company.postcode = None;
+            company.city = None;
+            company.registration_number = None;
+            company.url = None;
+            company.mail = None;
+            company.phone = None;
+        });
+        update(&mut self.lines, |line| {
+            line.name = anonymizer.pseudonym("Line", &line.id);
+            if line.forward_name.is_some() {
+                line.forward_name = Some(anonymizer.pseudonym("Line forward", &line.id));
+            }
+            if line.backward_name.is_some() {
+                line.backward_name = Some(anonymizer.pseudonym("Line backward", &line.id));
+            }
+        });
+        update(&mut self.routes, |route| {
+            route.name = anonymizer.pseudonym("Route", &route.id);
+        });
+        update(&mut self.vehicle_journeys, |vehicle_journey| {
+            if let Some(headsign) = &mut vehicle_journey.headsign {
+                *headsign = anonymizer.pseudonym("Headsign", headsign);
+            }
+        });
+        for headsign in self.stop_time_headsigns.values_mut() {
+            *headsign = IdString::from(anonymizer.pseudonym("Headsign", headsign.as_str()));
+        }
+        update(&mut self.stop_areas, |stop_area| {
+            stop_area.name = anonymizer.pseudonym("Stop area", &stop_area.id);
+            stop_area.coord = anonymizer.jitter("stop_area", &stop_area.id, stop_area.coord);
+        });
+        update(&mut self.stop_points, |stop_point| {
+            stop_point.name = anonymizer.pseudonym("Stop point", &stop_point.id);
+            stop_point.coord = anonymizer.jitter("stop_point", &stop_point.id, stop_point.coord);
+        });
+        update(&mut self.stop_locations, |stop_location| {
+            stop_location.name = anonymizer.pseudonym("Stop location", &stop_location.id);
+            stop_location.coord =
+                anonymizer.jitter("stop_location", &stop_location.id, stop_location.coord);
+        });
+        update(&mut self.geometries, |geometry| {
+            // Derived from the point, a point shared by several geometries
+            // stays shared
+            geometry.geometry.map_coords_inplace(|&(lon, lat)| {
+                let value = format!("{}:{}", lon, lat);
+                let coord = anonymizer.jitter("geometry", &value, Coord { lon, lat });
+                (coord.lon, coord.lat)
+            });
+        });
+        update(&mut self.comments, |comment| {
+            comment.name = anonymizer.pseudonym("Comment", &comment.id);
+            if comment.label.is_some() {
+                comment.label = Some(anonymizer.pseudonym("Comment label", &comment.id));
+            }
+            comment.url = None;
+        });
+        update(&mut self.tickets, |ticket| {
+            ticket.name = anonymizer.pseudonym("Ticket", &ticket.id);
+            ticket.comment = None;
+        });
+        update(&mut self.addresses, |address| {
+            address.street_name = anonymizer.pseudonym("Street", &address.id);
+            address.house_number = None;
+        });
+        update(&mut self.brandings, |branding| {
+            branding.name = anonymizer.pseudonym("Branding", &branding.id);
+            branding.url = None;
+            branding.image = None;
+        });
+        for translation in self.translations.values_mut() {
+            let value = format!(
+                "{}:{}:{}:{}",
+                translation.object_type.as_str(),
+                translation.object_id,
+                translation.field_name,
+                translation.language
+            );
+            translation.translation = anonymizer.pseudonym("Translation", &value);
+        }
+        self.feed_infos.retain(|key, _| {
+            !(key.contains("url") || key.contains("mail") || key.contains("phone"))
+        });
+        if let Some(publisher) = self.feed_infos.get_mut("feed_publisher_name") {
+            *publisher = anonymizer.pseudonym("Publisher", "feed_publisher_name");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn collections() -> Collections {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        {
+            let mut network = collections.networks.get_mut("TGN").unwrap();
+            network.url = Some("http://www.example.com".to_string());
+            network.phone = Some("01 23 45 67 89".to_string());
+        }
+        collections
+    }
+
+    fn names(collections: &Collections) -> Vec<String> {
+        collections
+            .stop_points
+            .values()
+            .map(|stop_point| stop_point.name.clone())
+            .chain(collections.lines.values().map(|line| line.name.clone()))
+            .chain(collections.networks.values().map(|n| n.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn deterministic_given_a_seed() {
+        let options = AnonymizeOptions::default();
+        let mut first = collections();
+        first.anonymize(42, &options);
+        let mut second = collections();
+        second.anonymize(42, &options);
+        let mut other_seed = collections();
+        other_seed.anonymize(43, &options);
+
+        assert_eq!(names(&first), names(&second));
+        let coords =
+            |c: &Collections| -> Vec<Coord> { c.stop_points.values().map(|sp| sp.coord).collect() };
+        assert_eq!(coords(&first), coords(&second));
+        let first_names = names(&first);
+        assert!(names(&other_seed)
+            .iter()
+            .zip(&first_names)
+            .all(|(other, first)| other != first));
+    }
+
+    #[test]
+    fn keeps_ids_times_and_jitters_within_radius() {
+        let original = collections();
+        let mut anonymized = collections();
+        let options = AnonymizeOptions {
+            jitter_radius: 100.0,
+        };
+        anonymized.anonymize(42, &options);
+
+        let network = anonymized.networks.values().next().unwrap();
+        assert!(network.name.starts_with("Network "));
+        assert_eq!(None, network.url);
+        assert_eq!(None, network.phone);
+        for (original, anonymized) in original
+            .stop_points
+            .values()
+            .zip(anonymized.stop_points.values())
+        {
+            assert_eq!(original.id, anonymized.id);
+            assert_eq!(original.stop_area_id, anonymized.stop_area_id);
+            assert_ne!(original.name, anonymized.name);
+            let distance = original.coord.distance_to(&anonymized.coord);
+            assert!(distance > 0.0 && distance <= 100.0, "{}", distance);
+        }
+        for (original, anonymized) in original
+            .vehicle_journeys
+            .values()
+            .zip(anonymized.vehicle_journeys.values())
+        {
+            assert_eq!(original.id, anonymized.id);
+            assert_eq!(original.service_id, anonymized.service_id);
+            assert_eq!(original.stop_times, anonymized.stop_times);
+        }
+        assert_eq!(original.calendars, anonymized.calendars);
+    }
+}
@@ -17,20 +17,20 @@
 //! - calendar.txt and calendar_dates.txt format are identical between the GTFS
 //!   and NTFS
 
-use crate::file_handler::FileHandler;
+use crate::file_handler::{FileHandler, FileWriter};
 use crate::model::Collections;
 use crate::objects::{self, Date, ExceptionType};
 use crate::parser::read_objects;
 use crate::serde_utils::*;
-use crate::vptranslator::translate;
+use crate::timing;
+use crate::utils::collection_with_id_skipping_duplicates;
 use crate::Result;
-use anyhow::{anyhow, bail, Context};
+use anyhow::{bail, Context};
 use chrono::{self, Datelike, Weekday};
+use num_traits::cast::FromPrimitive;
 use serde::{Deserialize, Serialize};
-use skip_error::skip_error_and_warn;
-use std::collections::BTreeSet;
-use std::path;
-use tracing::info;
+use std::collections::{BTreeSet, HashMap};
+use tracing::{info, warn};
 use typed_index_collection::*;
 
 /// Structure to serialize/deserialize the file calendar_dates.txt
@@ -140,30 +140,47 @@ where
     if calendar_dates.is_empty() && !calendar_exists {
         bail!("calendar_dates.txt or calendar.txt not found");
     }
+    // The dates are applied to the calendars taken out of the collection,
+    // which is then built again at once
+    let mut all_calendars = calendars.take();
+    let mut calendar_positions: HashMap<String, usize> =
+        HashMap::with_capacity(all_calendars.len());
+    for (position, calendar) in all_calendars.iter().enumerate() {
+        calendar_positions.insert(calendar.id.clone(), position);
+    }
     for calendar_date in calendar_dates {
-        let is_inserted = calendars
-            .get_mut(&calendar_date.service_id)
-            .map(|mut calendar| match calendar_date.exception_type {
-                ExceptionType::Add => {
-                    calendar.dates.insert(calendar_date.date);
-                }
-                ExceptionType::Remove => {
-                    calendar.dates.remove(&calendar_date.date);
+        match calendar_positions.get(&calendar_date.service_id) {
+            Some(&position) => {
+                let dates = &mut all_calendars[position].dates;
+                match calendar_date.exception_type {
+                    ExceptionType::Add => {
+                        dates.insert(calendar_date.date);
+                    }
+                    ExceptionType::Remove => {
+                        dates.remove(&calendar_date.date);
+                    }
                 }
-            });
-        is_inserted.unwrap_or_else(|| {
-            if calendar_date.exception_type == ExceptionType::Add {
-                let mut dates = BTreeSet::new();
-                dates.insert(calendar_date.date);
-                calendars
-                    .push(objects::Calendar {
-                        id: calendar_date.service_id,
-                        dates,
-                    })
-                    .unwrap();
             }
-        });
+            None if calendar_date.exception_type == ExceptionType::Add => {
+                calendar_positions.insert(calendar_date.service_id.clone(), all_calendars.len());
+                all_calendars.push(objects::Calendar {
+                    id: calendar_date.service_id,
+                    dates: std::iter::once(calendar_date.date).collect(),
+                });
+            }
+            None => {}
+        }
     }
+    all_calendars.retain(|calendar| {
+        if calendar.dates.is_empty() {
+            warn!(
+                "calendar {:?} has no date left after calendar_dates.txt and is removed",
+                calendar.id
+            );
+        }
+        !calendar.dates.is_empty()
+    });
+    *calendars = CollectionWithId::new(all_calendars)?;
     Ok(())
 }
 
@@ -174,19 +191,17 @@ pub(crate) fn _manage_calendars<H>(
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let mut calendars: CollectionWithId<objects::Calendar> = CollectionWithId::default();
     let ntfs_calendars = read_objects::<_, Calendar>(file_handler, "calendar.txt", false)?;
     let calendar_exists = !ntfs_calendars.is_empty();
-    for calendar in ntfs_calendars {
-        let dates = calendar.get_valid_dates();
-        if !dates.is_empty() {
-            skip_error_and_warn!(calendars.push(objects::Calendar {
-                id: calendar.id.clone(),
-                dates,
-            }));
-        }
-    }
-    collections.calendars = calendars;
+    let calendars = ntfs_calendars
+        .into_iter()
+        .map(|calendar| objects::Calendar {
+            id: calendar.id.clone(),
+            dates: calendar.get_valid_dates(),
+        })
+        .filter(|calendar| !calendar.dates.is_empty())
+        .collect();
+    collections.calendars = collection_with_id_skipping_duplicates(calendars);
     manage_calendar_dates(&mut collections.calendars, file_handler, calendar_exists)?;
 
     Ok(())
@@ -208,45 +223,163 @@ where
     _manage_calendars(file_handler, collections)
 }
 
-/// Write the calendar_dates.txt file into a Path from a list of Calendar
-pub fn write_calendar_dates(
-    path: &path::Path,
+/// A weekly pattern applied between two dates, as expressed by a row of
+/// calendar.txt
+#[derive(Debug, PartialEq, Clone)]
+pub struct WeeklyPattern {
+    /// Days of the week on which the service operates, ordered from Monday
+    pub operating_days: Vec<Weekday>,
+    /// First date on which the pattern applies
+    pub start_date: Date,
+    /// Last date on which the pattern applies
+    pub end_date: Date,
+}
+
+impl WeeklyPattern {
+    /// Returns true if the pattern makes the service operate at this date
+    pub fn contains(&self, date: Date) -> bool {
+        date >= self.start_date
+            && date <= self.end_date
+            && self.operating_days.contains(&date.weekday())
+    }
+}
+
+fn mask_to_weekdays(mask: u8) -> Vec<Weekday> {
+    (0..7u32)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| Weekday::from_u32(i).unwrap())
+        .collect()
+}
+
+/// Find the smallest representation of a set of dates as an optional weekly
+/// pattern (one row of calendar.txt) plus a list of exceptions (rows of
+/// calendar_dates.txt).
+///
+/// Every weekly pattern is tried. For a given pattern, each day between the
+/// first and the last date scores `+1` if it is an operating day of the
+/// pattern and is in `dates` (an addition saved), `-1` if it is an operating
+/// day not in `dates` (a removal needed) and `0` otherwise. The best bounds
+/// are then the maximum sum subarray of these scores. On equal cost, the
+/// widest bounds and the pattern with fewer operating days are preferred, and
+/// several dates are only expressed as exceptions if a weekly pattern does not
+/// save any row. The exceptions are sorted by date.
+pub fn optimize(dates: &BTreeSet<Date>) -> (Option<WeeklyPattern>, Vec<(Date, ExceptionType)>) {
+    let (first, last) = match (dates.iter().next(), dates.iter().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return (None, vec![]),
+    };
+    let length = (last - first).num_days() as usize + 1;
+    let days: Vec<(u8, bool)> = (0..length)
+        .map(|i| {
+            let date = first + chrono::Duration::days(i as i64);
+            (
+                1 << date.weekday().num_days_from_monday(),
+                dates.contains(&date),
+            )
+        })
+        .collect();
+
+    // (score, mask, start index, end index)
+    let mut best: Option<(i64, u8, usize, usize)> = None;
+    for mask in 1..128u8 {
+        let mut prefix = 0i64;
+        // minimum prefix sum seen so far and the index following it
+        let mut min_prefix = (0i64, 0usize);
+        let mut mask_best: Option<(i64, usize, usize)> = None;
+        for (i, (day, active)) in days.iter().enumerate() {
+            if mask & day != 0 {
+                prefix += if *active { 1 } else { -1 };
+            }
+            let score = prefix - min_prefix.0;
+            let is_better = match mask_best {
+                None => true,
+                Some((best_score, start, end)) => {
+                    score > best_score || (score == best_score && i - min_prefix.1 > end - start)
+                }
+            };
+            if is_better {
+                mask_best = Some((score, min_prefix.1, i));
+            }
+            if prefix < min_prefix.0 {
+                min_prefix = (prefix, i + 1);
+            }
+        }
+        if let Some((score, start, end)) = mask_best {
+            let is_better = match best {
+                None => true,
+                Some((best_score, best_mask, _, _)) => {
+                    score > best_score
+                        || (score == best_score && mask.count_ones() < best_mask.count_ones())
+                }
+            };
+            if is_better {
+                best = Some((score, mask, start, end));
+            }
+        }
+    }
+
+    let (score, mask, start, end) = best.expect("at least one weekly pattern has been evaluated");
+    // the calendar.txt row costs one row by itself
+    if score <= 1 && dates.len() > 1 {
+        let exceptions = dates.iter().map(|d| (*d, ExceptionType::Add)).collect();
+        return (None, exceptions);
+    }
+    let pattern = WeeklyPattern {
+        operating_days: mask_to_weekdays(mask),
+        start_date: first + chrono::Duration::days(start as i64),
+        end_date: first + chrono::Duration::days(end as i64),
+    };
+    let exceptions = (0..length)
+        .map(|i| first + chrono::Duration::days(i as i64))
+        .filter_map(
+            |date| match (pattern.contains(date), dates.contains(&date)) {
+                (true, false) => Some((date, ExceptionType::Remove)),
+                (false, true) => Some((date, ExceptionType::Add)),
+                _ => None,
+            },
+        )
+        .collect();
+    (Some(pattern), exceptions)
+}
+
+/// Write the calendar_dates.txt file with a FileWriter from a list of Calendar
+pub fn write_calendar_dates<W: FileWriter>(
+    file_writer: &W,
     calendars: &CollectionWithId<objects::Calendar>,
 ) -> Result<()> {
     info!("Writing calendar_dates.txt");
-    let calendar_dates_path = path.join("calendar_dates.txt");
+    let stage = timing::write_file("calendar_dates.txt");
     let mut translations: Vec<Calendar> = vec![];
     let mut exceptions: Vec<CalendarDate> = vec![];
-    for c in calendars.values() {
-        let translation = translate(&c.dates);
-        if !translation.operating_days.is_empty() {
-            let validity_period = skip_error_and_warn!(translation.validity_period.ok_or_else(
-                || anyhow!("Validity period not found for service id {}", c.id.clone())
-            ));
+    for c in crate::utils::sorted_by_id(calendars) {
+        let (pattern, calendar_exceptions) = optimize(&c.dates);
+        if let Some(pattern) = pattern {
+            let is_operating = |weekday| pattern.operating_days.contains(&weekday);
             translations.push(Calendar {
                 id: c.id.clone(),
-                monday: translation.operating_days.contains(&Weekday::Mon),
-                tuesday: translation.operating_days.contains(&Weekday::Tue),
-                wednesday: translation.operating_days.contains(&Weekday::Wed),
-                thursday: translation.operating_days.contains(&Weekday::Thu),
-                friday: translation.operating_days.contains(&Weekday::Fri),
-                saturday: translation.operating_days.contains(&Weekday::Sat),
-                sunday: translation.operating_days.contains(&Weekday::Sun),
-                start_date: validity_period.start_date,
-                end_date: validity_period.end_date,
+                monday: is_operating(Weekday::Mon),
+                tuesday: is_operating(Weekday::Tue),
+                wednesday: is_operating(Weekday::Wed),
+                thursday: is_operating(Weekday::Thu),
+                friday: is_operating(Weekday::Fri),
+                saturday: is_operating(Weekday::Sat),
+                sunday: is_operating(Weekday::Sun),
+                start_date: pattern.start_date,
+                end_date: pattern.end_date,
             });
         };
-        for e in translation.exceptions {
+        for (date, exception_type) in calendar_exceptions {
             exceptions.push(CalendarDate {
                 service_id: c.id.clone(),
-                date: e.date,
-                exception_type: e.exception_type,
+                date,
+                exception_type,
             });
         }
     }
     if !exceptions.is_empty() {
-        let mut wtr = csv::Writer::from_path(&calendar_dates_path)
-            .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
+        stage.record_rows(exceptions.len());
+        let (writer, calendar_dates_path) = file_writer.create_file("calendar_dates.txt")?;
+        let mut wtr = csv::Writer::from_writer(writer);
         for e in exceptions {
             wtr.serialize(&e)
                 .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
@@ -254,19 +387,51 @@ pub fn write_calendar_dates(
         wtr.flush()
             .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
     }
-    write_calendar(path, &translations)
+    drop(stage);
+    write_calendar(file_writer, &translations)
 }
 
-/// Write the calendar.txt file into a Path from a list of Calendar
-pub fn write_calendar(path: &path::Path, calendars: &[Calendar]) -> Result<()> {
+/// Write the calendar_dates.txt file with a FileWriter from a list of
+/// Calendar, every date being an addition, without any calendar.txt
+pub fn write_calendar_dates_only<W: FileWriter>(
+    file_writer: &W,
+    calendars: &CollectionWithId<objects::Calendar>,
+) -> Result<()> {
+    info!("Writing calendar_dates.txt");
+    let stage = timing::write_file("calendar_dates.txt");
+    let calendars = crate::utils::sorted_by_id(calendars);
+    stage.record_rows(calendars.iter().map(|c| c.dates.len()).sum());
+    if calendars.iter().all(|c| c.dates.is_empty()) {
+        return Ok(());
+    }
+    let (writer, calendar_dates_path) = file_writer.create_file("calendar_dates.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for c in calendars {
+        for date in &c.dates {
+            wtr.serialize(CalendarDate {
+                service_id: c.id.clone(),
+                date: *date,
+                exception_type: ExceptionType::Add,
+            })
+            .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
+        }
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
+    Ok(())
+}
+
+/// Write the calendar.txt file with a FileWriter from a list of Calendar
+pub fn write_calendar<W: FileWriter>(file_writer: &W, calendars: &[Calendar]) -> Result<()> {
     info!("Writing calendar.txt");
     if calendars.is_empty() {
         return Ok(());
     }
 
-    let calendar_path = path.join("calendar.txt");
-    let mut wtr = csv::Writer::from_path(&calendar_path)
-        .with_context(|| format!("Error reading {:?}", calendar_path))?;
+    let stage = timing::write_file("calendar.txt");
+    stage.record_rows(calendars.len());
+    let (writer, calendar_path) = file_writer.create_file("calendar.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
     for calendar in calendars {
         wtr.serialize(calendar)
             .with_context(|| format!("Error reading {:?}", calendar_path))?;
@@ -275,3 +440,174 @@ pub fn write_calendar(path: &path::Path, calendars: &[Calendar]) -> Result<()> {
         .with_context(|| format!("Error reading {:?}", calendar_path))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vptranslator::translate;
+    use pretty_assertions::assert_eq;
+
+    fn expand(
+        pattern: &Option<WeeklyPattern>,
+        exceptions: &[(Date, ExceptionType)],
+    ) -> BTreeSet<Date> {
+        let mut dates = BTreeSet::new();
+        if let Some(pattern) = pattern {
+            let mut date = pattern.start_date;
+            while date <= pattern.end_date {
+                if pattern.contains(date) {
+                    dates.insert(date);
+                }
+                date += chrono::Duration::days(1);
+            }
+        }
+        for (date, exception_type) in exceptions {
+            match exception_type {
+                ExceptionType::Add => dates.insert(*date),
+                ExceptionType::Remove => dates.remove(date),
+            };
+        }
+        dates
+    }
+
+    fn nb_rows(pattern: &Option<WeeklyPattern>, exceptions: &[(Date, ExceptionType)]) -> usize {
+        pattern.iter().count() + exceptions.len()
+    }
+
+    #[test]
+    fn optimize_empty_dates() {
+        let (pattern, exceptions) = optimize(&BTreeSet::new());
+        assert_eq!(None, pattern);
+        assert!(exceptions.is_empty());
+    }
+
+    #[test]
+    fn optimize_single_date() {
+        let date = Date::from_ymd(2012, 7, 4);
+        let dates: BTreeSet<Date> = vec![date].into_iter().collect();
+        let (pattern, exceptions) = optimize(&dates);
+        assert_eq!(
+            Some(WeeklyPattern {
+                operating_days: vec![Weekday::Wed],
+                start_date: date,
+                end_date: date,
+            }),
+            pattern
+        );
+        assert!(exceptions.is_empty());
+    }
+
+    #[test]
+    fn optimize_full_weeks_with_one_removal() {
+        // Monday to Friday during 3 weeks, except Wednesday 2012-07-11
+        let dates: BTreeSet<Date> = (2..=20)
+            .map(|d| Date::from_ymd(2012, 7, d))
+            .filter(|d| d.weekday().number_from_monday() <= 5)
+            .filter(|d| *d != Date::from_ymd(2012, 7, 11))
+            .collect();
+        let (pattern, exceptions) = optimize(&dates);
+        assert_eq!(
+            Some(WeeklyPattern {
+                operating_days: vec![
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri
+                ],
+                start_date: Date::from_ymd(2012, 7, 2),
+                end_date: Date::from_ymd(2012, 7, 20),
+            }),
+            pattern
+        );
+        assert_eq!(
+            vec![(Date::from_ymd(2012, 7, 11), ExceptionType::Remove)],
+            exceptions
+        );
+    }
+
+    #[test]
+    fn optimize_isolated_date_far_from_pattern() {
+        // Every Monday of 2012-07 and 2012-08 then a single Monday in November:
+        // stretching the weekly pattern up to the last date would require
+        // many removals whereas adding the isolated date is a single row.
+        let mut dates: BTreeSet<Date> = (0..9)
+            .map(|w| Date::from_ymd(2012, 7, 2) + chrono::Duration::weeks(w))
+            .collect();
+        dates.insert(Date::from_ymd(2012, 11, 5));
+        let (pattern, exceptions) = optimize(&dates);
+        assert_eq!(
+            Some(WeeklyPattern {
+                operating_days: vec![Weekday::Mon],
+                start_date: Date::from_ymd(2012, 7, 2),
+                // days without any operating weekday widen the bounds for free
+                end_date: Date::from_ymd(2012, 9, 2),
+            }),
+            pattern
+        );
+        assert_eq!(
+            vec![(Date::from_ymd(2012, 11, 5), ExceptionType::Add)],
+            exceptions
+        );
+
+        let translation = translate(&dates);
+        assert!(nb_rows(&pattern, &exceptions) < 1 + translation.exceptions.len());
+    }
+
+    #[test]
+    fn optimize_is_equivalent_and_never_worse_than_translate() {
+        // every subset of the 2 weeks starting on a Thursday
+        let first = Date::from_ymd(2012, 7, 5);
+        for subset in 0..(1u32 << 14) {
+            let dates: BTreeSet<Date> = (0..14)
+                .filter(|i| subset & (1 << i) != 0)
+                .map(|i| first + chrono::Duration::days(i64::from(i)))
+                .collect();
+            let (pattern, exceptions) = optimize(&dates);
+            assert_eq!(dates, expand(&pattern, &exceptions));
+
+            let translation = translate(&dates);
+            let translate_rows = if translation.operating_days.is_empty() {
+                translation.exceptions.len()
+            } else {
+                1 + translation.exceptions.len()
+            };
+            assert!(nb_rows(&pattern, &exceptions) <= translate_rows);
+        }
+    }
+
+    #[test]
+    fn calendar_dates_grouped_by_service() {
+        use crate::{file_handler::PathFileHandler, test_utils::*};
+        let calendars_content = "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+                                 1,1,1,1,1,1,0,0,20180501,20180502\n\
+                                 1,0,0,0,0,0,1,1,20180505,20180506";
+        let calendar_dates_content = "service_id,date,exception_type\n\
+                                      2,20180510,2\n\
+                                      1,20180501,2\n\
+                                      2,20180511,1\n\
+                                      2,20180512,1\n\
+                                      2,20180511,2";
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "calendar.txt", calendars_content);
+            create_file_with_content(path, "calendar_dates.txt", calendar_dates_content);
+            let mut collections = Collections::default();
+            manage_calendars(&mut handler, &mut collections).unwrap();
+            let date = |day| Date::from_ymd(2018, 5, day);
+            assert_eq!(
+                vec![
+                    objects::Calendar {
+                        id: "1".to_string(),
+                        dates: std::iter::once(date(2)).collect(),
+                    },
+                    objects::Calendar {
+                        id: "2".to_string(),
+                        dates: std::iter::once(date(12)).collect(),
+                    },
+                ],
+                collections.calendars.into_vec()
+            );
+        });
+    }
+}
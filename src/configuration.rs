@@ -14,27 +14,124 @@
 //! Some utilities for input dataset to the library.
 
 use crate::{
-    objects::{self, Contributor},
+    model::Collections,
+    objects::{self, Contributor, Dataset},
     Result,
 };
+use anyhow::{anyhow, bail, ensure};
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::path;
 use tracing::info;
+use typed_index_collection::CollectionWithId;
 
 #[derive(Deserialize, Debug)]
 struct ConfigDataset {
     dataset_id: String,
+    contributor_id: Option<String>,
+    #[serde(default)]
+    network_ids: BTreeSet<String>,
 }
 
 #[derive(Deserialize, Debug)]
 struct Config {
-    contributor: objects::Contributor,
-    dataset: ConfigDataset,
+    contributor: Option<objects::Contributor>,
+    #[serde(default)]
+    contributors: Vec<objects::Contributor>,
+    dataset: Option<ConfigDataset>,
+    #[serde(default)]
+    datasets: Vec<ConfigDataset>,
     feed_infos: Option<BTreeMap<String, String>>,
 }
 
+/// A dataset of the configuration, and the networks whose vehicle journeys
+/// are assigned to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetAssignment {
+    /// The dataset, linked to one of the contributors of the configuration
+    pub dataset: Dataset,
+    /// Identifiers of the networks in the input data
+    pub network_ids: BTreeSet<String>,
+}
+
+/// The contributors and the datasets of a configuration file, see
+/// [read_datasets_config]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetsConfig {
+    /// The contributors, at least one
+    pub contributors: Vec<Contributor>,
+    /// The datasets, at least one. The first one gets the vehicle journeys of
+    /// the networks listed by no dataset.
+    pub datasets: Vec<DatasetAssignment>,
+    /// The key/values of 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+}
+
+impl Default for DatasetsConfig {
+    fn default() -> Self {
+        DatasetsConfig {
+            contributors: vec![Contributor::default()],
+            datasets: vec![DatasetAssignment {
+                dataset: Dataset::default(),
+                network_ids: BTreeSet::new(),
+            }],
+            feed_infos: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    fn into_datasets_config(self) -> Result<DatasetsConfig> {
+        ensure!(
+            self.contributor.is_none() || self.contributors.is_empty(),
+            "both 'contributor' and 'contributors' are defined"
+        );
+        ensure!(
+            self.dataset.is_none() || self.datasets.is_empty(),
+            "both 'dataset' and 'datasets' are defined"
+        );
+        let contributors: Vec<Contributor> = self
+            .contributor
+            .into_iter()
+            .chain(self.contributors)
+            .collect();
+        let config_datasets: Vec<ConfigDataset> =
+            self.dataset.into_iter().chain(self.datasets).collect();
+        ensure!(!contributors.is_empty(), "no contributor defined");
+        ensure!(!config_datasets.is_empty(), "no dataset defined");
+        let mut datasets = Vec::with_capacity(config_datasets.len());
+        for config_dataset in config_datasets {
+            let contributor_id = match (config_dataset.contributor_id, &contributors[..]) {
+                (Some(contributor_id), _) => {
+                    if !contributors.iter().any(|c| c.id == contributor_id) {
+                        bail!(
+                            "contributor {:?} of dataset {:?} not found",
+                            contributor_id,
+                            config_dataset.dataset_id
+                        );
+                    }
+                    contributor_id
+                }
+                (None, [contributor]) => contributor.id.clone(),
+                (None, _) => bail!(
+                    "'contributor_id' of dataset {:?} is required with several contributors",
+                    config_dataset.dataset_id
+                ),
+            };
+            datasets.push(DatasetAssignment {
+                dataset: Dataset::new(config_dataset.dataset_id, contributor_id),
+                network_ids: config_dataset.network_ids,
+            });
+        }
+        Ok(DatasetsConfig {
+            contributors,
+            datasets,
+            feed_infos: self.feed_infos.unwrap_or_default(),
+        })
+    }
+}
+
 /// Read a JSON configuration file to facilitate the creation of:
 /// - a Contributor
 /// - a Dataset
@@ -60,6 +157,9 @@ struct Config {
 ///     }
 /// }
 /// ```
+///
+/// With several contributors or datasets (see [read_datasets_config]),
+/// the first dataset and its contributor are returned.
 pub fn read_config<P: AsRef<path::Path>>(
     config_path: Option<P>,
 ) -> Result<(
@@ -67,25 +167,224 @@ pub fn read_config<P: AsRef<path::Path>>(
     objects::Dataset,
     BTreeMap<String, String>,
 )> {
-    let contributor;
-    let dataset;
-    let mut feed_infos = BTreeMap::default();
-
-    if let Some(config_path) = config_path {
-        let config_path = config_path.as_ref();
-        info!("Reading dataset and contributor from {:?}", config_path);
-        let json_config_file = File::open(config_path)?;
-        let config: Config = serde_json::from_reader(json_config_file)?;
-
-        contributor = config.contributor;
-        dataset = objects::Dataset::new(config.dataset.dataset_id, contributor.id.clone());
-        if let Some(config_feed_infos) = config.feed_infos {
-            feed_infos = config_feed_infos;
+    let config = read_datasets_config(config_path)?;
+    let dataset = config.datasets[0].dataset.clone();
+    let contributor = config
+        .contributors
+        .into_iter()
+        .find(|contributor| contributor.id == dataset.contributor_id)
+        .ok_or_else(|| anyhow!("contributor {:?} not found", dataset.contributor_id))?;
+    Ok((contributor, dataset, config.feed_infos))
+}
+
+/// Read a JSON configuration file with one or several contributors and
+/// datasets, each dataset gathering the vehicle journeys of some networks
+/// (see [Collections::assign_datasets]). Besides the shape of [read_config],
+/// the contributors and the datasets can be given as arrays, a dataset
+/// being linked to its contributor by `contributor_id`:
+/// ```text
+/// {
+///     "contributors": [
+///         { "contributor_id": "bus", "contributor_name": "Bus operator" },
+///         { "contributor_id": "tram", "contributor_name": "Tram operator" }
+///     ],
+///     "datasets": [
+///         { "dataset_id": "bus", "contributor_id": "bus" },
+///         { "dataset_id": "tram", "contributor_id": "tram", "network_ids": ["tram_agency"] }
+///     ]
+/// }
+/// ```
+/// The `contributor_id` can be omitted with a single contributor.
+pub fn read_datasets_config<P: AsRef<path::Path>>(
+    config_path: Option<P>,
+) -> Result<DatasetsConfig> {
+    match config_path {
+        Some(config_path) => {
+            let config_path = config_path.as_ref();
+            info!("Reading datasets and contributors from {:?}", config_path);
+            let json_config_file = File::open(config_path)?;
+            let config: Config = serde_json::from_reader(json_config_file)?;
+            config.into_datasets_config()
         }
-    } else {
-        contributor = Contributor::default();
-        dataset = objects::Dataset::default();
+        None => Ok(DatasetsConfig::default()),
+    }
+}
+
+impl Collections {
+    /// Replaces the contributors and the datasets by the ones of `config`,
+    /// each vehicle journey being assigned to the dataset listing the
+    /// network of its line, or else to the first dataset. The validity
+    /// periods of the datasets are then computed from their calendars.
+    pub fn assign_datasets(&mut self, config: &DatasetsConfig) -> Result<()> {
+        let default_dataset_id = &config
+            .datasets
+            .first()
+            .ok_or_else(|| anyhow!("no dataset defined"))?
+            .dataset
+            .id;
+        let mut dataset_by_network: HashMap<&str, &str> = HashMap::new();
+        for assignment in &config.datasets {
+            for network_id in &assignment.network_ids {
+                if let Some(other) = dataset_by_network.insert(network_id, &assignment.dataset.id) {
+                    bail!(
+                        "network {:?} assigned to both datasets {:?} and {:?}",
+                        network_id,
+                        other,
+                        assignment.dataset.id
+                    );
+                }
+            }
+        }
+        let mut vehicle_journeys = self.vehicle_journeys.take();
+        for vehicle_journey in &mut vehicle_journeys {
+            let dataset_id = self
+                .routes
+                .get(&vehicle_journey.route_id)
+                .and_then(|route| self.lines.get(&route.line_id))
+                .and_then(|line| dataset_by_network.get(line.network_id.as_str()))
+                .copied()
+                .unwrap_or(default_dataset_id);
+            vehicle_journey.dataset_id = dataset_id.to_string();
+        }
+        self.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+        self.contributors = CollectionWithId::new(config.contributors.clone())?;
+        self.datasets = CollectionWithId::new(
+            config
+                .datasets
+                .iter()
+                .map(|assignment| assignment.dataset.clone())
+                .collect(),
+        )?;
+        self.update_validity_period_from_calendars();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    fn read(content: &str) -> Result<DatasetsConfig> {
+        let mut config = None;
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "config.json", content);
+            config = Some(read_datasets_config(Some(path.join("config.json"))));
+        });
+        config.unwrap()
     }
 
-    Ok((contributor, dataset, feed_infos))
+    #[test]
+    fn single_contributor_and_dataset() {
+        let config = read(
+            r#"{
+                "contributor": { "contributor_id": "c", "contributor_name": "C" },
+                "dataset": { "dataset_id": "d" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["c"],
+            config
+                .contributors
+                .iter()
+                .map(|c| &c.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, config.datasets.len());
+        assert_eq!("d", config.datasets[0].dataset.id);
+        assert_eq!("c", config.datasets[0].dataset.contributor_id);
+        assert!(config.feed_infos.is_empty());
+    }
+
+    #[test]
+    fn several_contributors_and_datasets() {
+        let config = read(
+            r#"{
+                "contributors": [
+                    { "contributor_id": "c1", "contributor_name": "C1" },
+                    { "contributor_id": "c2", "contributor_name": "C2" }
+                ],
+                "datasets": [
+                    { "dataset_id": "d1", "contributor_id": "c1" },
+                    { "dataset_id": "d2", "contributor_id": "c2", "network_ids": ["n2", "n3"] }
+                ],
+                "feed_infos": { "feed_publisher_name": "P" }
+            }"#,
+        )
+        .unwrap();
+        let datasets: Vec<_> = config
+            .datasets
+            .iter()
+            .map(|a| {
+                (
+                    &a.dataset.id[..],
+                    &a.dataset.contributor_id[..],
+                    a.network_ids.len(),
+                )
+            })
+            .collect();
+        assert_eq!(vec![("d1", "c1", 0), ("d2", "c2", 2)], datasets);
+        assert_eq!("P", config.feed_infos["feed_publisher_name"]);
+    }
+
+    #[test]
+    fn invalid_contributor_of_dataset() {
+        let contributors = r#""contributors": [
+            { "contributor_id": "c1", "contributor_name": "C1" },
+            { "contributor_id": "c2", "contributor_name": "C2" }
+        ]"#;
+        let error = read(&format!(
+            r#"{{ {}, "datasets": [{{ "dataset_id": "d" }}] }}"#,
+            contributors
+        ))
+        .unwrap_err();
+        assert_eq!(
+            "'contributor_id' of dataset \"d\" is required with several contributors",
+            error.to_string()
+        );
+        let error = read(&format!(
+            r#"{{ {}, "datasets": [{{ "dataset_id": "d", "contributor_id": "c3" }}] }}"#,
+            contributors
+        ))
+        .unwrap_err();
+        assert_eq!(
+            "contributor \"c3\" of dataset \"d\" not found",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn assign_datasets_by_network() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.lines.get_mut("B42").unwrap().network_id = "other_network".to_string();
+        let config = DatasetsConfig {
+            contributors: vec![Contributor::default()],
+            datasets: vec![
+                DatasetAssignment {
+                    dataset: Dataset::new("default".into(), "default_contributor".into()),
+                    network_ids: BTreeSet::new(),
+                },
+                DatasetAssignment {
+                    dataset: Dataset::new("other".into(), "default_contributor".into()),
+                    network_ids: vec!["other_network".to_string()].into_iter().collect(),
+                },
+            ],
+            feed_infos: BTreeMap::new(),
+        };
+        collections.assign_datasets(&config).unwrap();
+
+        for vehicle_journey in collections.vehicle_journeys.values() {
+            let expected = if vehicle_journey.route_id.starts_with("B42") {
+                "other"
+            } else {
+                "default"
+            };
+            assert_eq!(expected, vehicle_journey.dataset_id);
+        }
+        assert_eq!(2, collections.datasets.len());
+    }
 }
@@ -0,0 +1,319 @@
+use crate::{model::Collections, Result};
+use anyhow::bail;
+use tracing::{info, warn};
+
+/// What to do with the invalid URLs and emails of the networks, companies and
+/// contributors
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ContactFieldsMode {
+    /// Remove the invalid value with a warning
+    #[default]
+    Loose,
+    /// Fail on any invalid value
+    Strict,
+}
+
+/// Issue found on a URL or an email
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ContactFieldIssue {
+    /// The value is not a valid URL or email
+    Invalid,
+    /// The value is valid once normalized to the given one (surrounding
+    /// spaces removed, `http://` added to a URL without scheme)
+    Normalized(String),
+}
+
+/// A URL or an email of a network, a company or a contributor with an issue
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ContactField {
+    pub(crate) object_type: &'static str,
+    pub(crate) object_id: String,
+    pub(crate) field: &'static str,
+    pub(crate) value: String,
+    pub(crate) issue: ContactFieldIssue,
+}
+
+impl ContactField {
+    /// Description of the issue, for logs and reports
+    pub(crate) fn message(&self) -> String {
+        match &self.issue {
+            ContactFieldIssue::Invalid => format!(
+                "{} {:?}: invalid {} {:?}",
+                self.object_type, self.object_id, self.field, self.value
+            ),
+            ContactFieldIssue::Normalized(normalized) => format!(
+                "{} {:?}: {} {:?} normalized to {:?}",
+                self.object_type, self.object_id, self.field, self.value, normalized
+            ),
+        }
+    }
+
+    /// True if the issue is on an email
+    pub(crate) fn is_email(&self) -> bool {
+        self.field.ends_with("mail")
+    }
+}
+
+// A host name (each label being made of letters, digits and hyphens), an IPv4
+// or an IPv6 between brackets
+fn is_valid_host(host: &str) -> bool {
+    if let Some(ipv6) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return ipv6.parse::<std::net::Ipv6Addr>().is_ok();
+    }
+    !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+}
+
+fn is_valid_url(url: &str) -> bool {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(split) => split,
+        None => return false,
+    };
+    let is_valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if !is_valid_scheme || rest.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    let authority = rest.split(['/', '?', '#']).next();
+    let host_and_port = match authority.map(|a| a.rsplit_once('@').map_or(a, |(_, h)| h)) {
+        Some(host_and_port) => host_and_port,
+        None => return false,
+    };
+    let host = match host_and_port.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => {
+            if !port.chars().all(|c| c.is_ascii_digit()) {
+                return false;
+            }
+            host
+        }
+        _ => host_and_port,
+    };
+    is_valid_host(host)
+}
+
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !local
+                    .chars()
+                    .any(|c| c.is_whitespace() || c.is_control() || c == '@')
+                && domain.contains('.')
+                && is_valid_host(domain)
+        }
+        None => false,
+    }
+}
+
+fn check_url(url: &str) -> Option<ContactFieldIssue> {
+    let trimmed = url.trim();
+    if is_valid_url(trimmed) {
+        return (trimmed != url).then(|| ContactFieldIssue::Normalized(trimmed.to_string()));
+    }
+    // Only a value looking like a domain is considered to lack a scheme
+    let with_scheme = format!("http://{}", trimmed);
+    if !trimmed.contains("://") && trimmed.contains('.') && is_valid_url(&with_scheme) {
+        Some(ContactFieldIssue::Normalized(with_scheme))
+    } else {
+        Some(ContactFieldIssue::Invalid)
+    }
+}
+
+fn check_email(email: &str) -> Option<ContactFieldIssue> {
+    let trimmed = email.trim();
+    if !is_valid_email(trimmed) {
+        Some(ContactFieldIssue::Invalid)
+    } else if trimmed != email {
+        Some(ContactFieldIssue::Normalized(trimmed.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Find the `network_url`, `company_url`, `company_mail` and
+/// `contributor_website` that are not valid or that need to be normalized.
+pub(crate) fn find_invalid_contact_fields(collections: &Collections) -> Vec<ContactField> {
+    let mut fields = Vec::new();
+    let mut check = |object_type, object_id: &str, field, value: &Option<String>, is_email| {
+        let value = match value {
+            Some(value) if !value.is_empty() => value,
+            _ => return,
+        };
+        let issue = if is_email {
+            check_email(value)
+        } else {
+            check_url(value)
+        };
+        if let Some(issue) = issue {
+            fields.push(ContactField {
+                object_type,
+                object_id: object_id.to_string(),
+                field,
+                value: value.clone(),
+                issue,
+            });
+        }
+    };
+    for network in collections.networks.values() {
+        check("network", &network.id, "network_url", &network.url, false);
+    }
+    for company in collections.companies.values() {
+        check("company", &company.id, "company_url", &company.url, false);
+        check("company", &company.id, "company_mail", &company.mail, true);
+    }
+    for contributor in collections.contributors.values() {
+        check(
+            "contributor",
+            &contributor.id,
+            "contributor_website",
+            &contributor.website,
+            false,
+        );
+    }
+    fields
+}
+
+/// Normalize the fields found by [find_invalid_contact_fields] and remove the
+/// invalid ones, or fail on invalid values in [ContactFieldsMode::Strict].
+pub(crate) fn check_contact_fields(
+    collections: &mut Collections,
+    mode: ContactFieldsMode,
+) -> Result<()> {
+    let fields = find_invalid_contact_fields(collections);
+    if mode == ContactFieldsMode::Strict {
+        let invalid_fields: Vec<String> = fields
+            .iter()
+            .filter(|field| field.issue == ContactFieldIssue::Invalid)
+            .map(ContactField::message)
+            .collect();
+        if !invalid_fields.is_empty() {
+            bail!(
+                "{} invalid contact fields:\n{}",
+                invalid_fields.len(),
+                invalid_fields.join("\n")
+            );
+        }
+    }
+    for field in fields {
+        let new_value = match &field.issue {
+            ContactFieldIssue::Invalid => {
+                warn!("{}, the value is removed", field.message());
+                None
+            }
+            ContactFieldIssue::Normalized(normalized) => {
+                info!("{}", field.message());
+                Some(normalized.clone())
+            }
+        };
+        match field.field {
+            "network_url" => {
+                if let Some(mut network) = collections.networks.get_mut(&field.object_id) {
+                    network.url = new_value;
+                }
+            }
+            "company_url" | "company_mail" => {
+                if let Some(mut company) = collections.companies.get_mut(&field.object_id) {
+                    if field.is_email() {
+                        company.mail = new_value;
+                    } else {
+                        company.url = new_value;
+                    }
+                }
+            }
+            _ => {
+                if let Some(mut contributor) = collections.contributors.get_mut(&field.object_id) {
+                    contributor.website = new_value;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Company, Contributor, Network};
+    use pretty_assertions::assert_eq;
+    use typed_index_collection::CollectionWithId;
+
+    fn collections() -> Collections {
+        Collections {
+            networks: CollectionWithId::new(vec![
+                Network {
+                    id: "N1".to_string(),
+                    url: Some("https://example.com/network?id=1".to_string()),
+                    ..Default::default()
+                },
+                Network {
+                    id: "N2".to_string(),
+                    url: Some("www.example.com".to_string()),
+                    ..Default::default()
+                },
+            ])
+            .unwrap(),
+            companies: CollectionWithId::from(Company {
+                id: "C1".to_string(),
+                url: Some("http://exa mple.com".to_string()),
+                mail: Some(" contact@example.com ".to_string()),
+                ..Default::default()
+            }),
+            contributors: CollectionWithId::from(Contributor {
+                id: "CT1".to_string(),
+                website: Some("not a website".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn urls_and_emails() {
+        assert!(is_valid_url("http://localhost:8080/path#anchor"));
+        assert!(is_valid_url("https://user@[::1]/"));
+        assert!(!is_valid_url("http://"));
+        assert!(!is_valid_url("http://example.com:port"));
+        assert!(!is_valid_url("http://-example.com"));
+        assert!(is_valid_email("first.last+tag@example.co.uk"));
+        assert!(!is_valid_email("contact@localhost"));
+        assert!(!is_valid_email("contact.example.com"));
+        assert!(!is_valid_email("con tact@example.com"));
+    }
+
+    #[test]
+    fn loose_mode() {
+        let mut collections = collections();
+        check_contact_fields(&mut collections, ContactFieldsMode::Loose).unwrap();
+        let url = |id| collections.networks.get(id).unwrap().url.clone();
+        assert_eq!(
+            Some("https://example.com/network?id=1".to_string()),
+            url("N1")
+        );
+        assert_eq!(Some("http://www.example.com".to_string()), url("N2"));
+        let company = collections.companies.get("C1").unwrap();
+        assert_eq!(None, company.url);
+        assert_eq!(Some("contact@example.com"), company.mail.as_deref());
+        assert_eq!(None, collections.contributors.get("CT1").unwrap().website);
+    }
+
+    #[test]
+    fn strict_mode() {
+        let mut collections = collections();
+        let error = check_contact_fields(&mut collections, ContactFieldsMode::Strict)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            "2 invalid contact fields:\n\
+             company \"C1\": invalid company_url \"http://exa mple.com\"\n\
+             contributor \"CT1\": invalid contributor_website \"not a website\"",
+            error
+        );
+    }
+}
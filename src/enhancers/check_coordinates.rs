@@ -0,0 +1,722 @@
+use crate::{
+    model::Collections,
+    objects::{Coord, IdString, StopPoint},
+    report::{IssueCode, Report, ReportIssue, Severity},
+};
+use geo::{algorithm::centroid::Centroid, MultiPoint};
+use std::collections::{HashMap, HashSet};
+use typed_index_collection::Idx;
+
+/// Ratio of the stop points inside the bounding box computed from the data,
+/// before its enlargement
+const BOUNDING_BOX_RATIO: f64 = 0.95;
+/// Minimal enlargement, in degrees, on each side of the bounding box computed
+/// from the data
+const MIN_BOUNDING_BOX_MARGIN: f64 = 0.1;
+
+/// What to do with a stop whose coordinates have an issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordinatesStrategy {
+    /// Only report the issue
+    Report,
+    /// Swap back swapped coordinates, otherwise use the coordinates of the
+    /// parent stop area for a stop point, or the centroid of the valid stop
+    /// points for a stop area
+    Fix,
+    /// Remove the stop, with the stop points of a stop area and the stop
+    /// times, transfers, pathways and stop locations of the removed stops
+    Drop,
+}
+
+/// A geographic bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// South-west corner
+    pub min: Coord,
+    /// North-east corner
+    pub max: Coord,
+}
+
+impl BoundingBox {
+    /// Whether `coord` is inside the bounding box (borders included)
+    pub fn contains(&self, coord: &Coord) -> bool {
+        self.min.lon <= coord.lon
+            && coord.lon <= self.max.lon
+            && self.min.lat <= coord.lat
+            && coord.lat <= self.max.lat
+    }
+
+    /// Bounding box containing the central 95% of `coords` in both
+    /// directions, enlarged on each side by its own size (or at least 0.1
+    /// degree) so that only the outliers are outside. `None` if `coords` is
+    /// empty.
+    pub fn from_percentile<'a, I>(coords: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a Coord>,
+    {
+        let (mut lons, mut lats): (Vec<f64>, Vec<f64>) = coords
+            .into_iter()
+            .map(|coord| (coord.lon, coord.lat))
+            .unzip();
+        if lons.is_empty() {
+            return None;
+        }
+        let bounds = |values: &mut Vec<f64>| {
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let last = (values.len() - 1) as f64;
+            let excluded = (1. - BOUNDING_BOX_RATIO) / 2.;
+            let min = values[(last * excluded).round() as usize];
+            let max = values[(last * (1. - excluded)).round() as usize];
+            let margin = (max - min).max(MIN_BOUNDING_BOX_MARGIN);
+            (min - margin, max + margin)
+        };
+        let (min_lon, max_lon) = bounds(&mut lons);
+        let (min_lat, max_lat) = bounds(&mut lats);
+        Some(BoundingBox {
+            min: Coord {
+                lon: min_lon,
+                lat: min_lat,
+            },
+            max: Coord {
+                lon: max_lon,
+                lat: max_lat,
+            },
+        })
+    }
+}
+
+/// Strategy for each type of coordinates issue, and the bounding box of the
+/// dataset
+#[derive(Debug, Clone)]
+pub struct CoordinatesConfiguration {
+    /// Strategy for the coordinates (0, 0)
+    pub null: CoordinatesStrategy,
+    /// Strategy for a longitude or a latitude out of range
+    pub out_of_range: CoordinatesStrategy,
+    /// Strategy for probably swapped longitude and latitude
+    pub swapped: CoordinatesStrategy,
+    /// Strategy for the coordinates outside the bounding box
+    pub outside_bounding_box: CoordinatesStrategy,
+    /// Bounding box of the dataset, computed with
+    /// [BoundingBox::from_percentile] from the stop points if `None`
+    pub bounding_box: Option<BoundingBox>,
+}
+
+impl Default for CoordinatesConfiguration {
+    fn default() -> Self {
+        CoordinatesConfiguration {
+            null: CoordinatesStrategy::Report,
+            out_of_range: CoordinatesStrategy::Report,
+            swapped: CoordinatesStrategy::Report,
+            outside_bounding_box: CoordinatesStrategy::Report,
+            bounding_box: None,
+        }
+    }
+}
+
+impl CoordinatesConfiguration {
+    fn strategy(&self, code: IssueCode) -> CoordinatesStrategy {
+        match code {
+            IssueCode::NullCoordinates => self.null,
+            IssueCode::OutOfRangeCoordinates => self.out_of_range,
+            IssueCode::SwappedCoordinates => self.swapped,
+            _ => self.outside_bounding_box,
+        }
+    }
+}
+
+fn swap(coord: &Coord) -> Coord {
+    Coord {
+        lon: coord.lat,
+        lat: coord.lon,
+    }
+}
+
+fn find_issue(coord: &Coord, bounding_box: Option<&BoundingBox>) -> Option<IssueCode> {
+    let is_swapped = match bounding_box {
+        Some(bounding_box) => !bounding_box.contains(coord) && bounding_box.contains(&swap(coord)),
+        None => !coord.is_valid() && swap(coord).is_valid(),
+    };
+    if *coord == Coord::default() {
+        Some(IssueCode::NullCoordinates)
+    } else if is_swapped {
+        Some(IssueCode::SwappedCoordinates)
+    } else if !coord.is_valid() {
+        Some(IssueCode::OutOfRangeCoordinates)
+    } else if bounding_box.is_some_and(|bounding_box| !bounding_box.contains(coord)) {
+        Some(IssueCode::OutsideBoundingBox)
+    } else {
+        None
+    }
+}
+
+fn describe(code: IssueCode, object_type: &str, id: &str, coord: &Coord) -> String {
+    let issue = match code {
+        IssueCode::NullCoordinates => "null",
+        IssueCode::OutOfRangeCoordinates => "out of range",
+        IssueCode::SwappedCoordinates => "probably swapped",
+        _ => "outside the bounding box",
+    };
+    format!(
+        "{} {:?} has {} coordinates (lon {}, lat {})",
+        object_type, id, issue, coord.lon, coord.lat
+    )
+}
+
+fn coordinates_issue(
+    code: IssueCode,
+    object_type: &str,
+    id: &str,
+    message: String,
+    modification: Option<String>,
+) -> ReportIssue {
+    ReportIssue {
+        code,
+        severity: Severity::Warning,
+        object_type: Some(object_type.to_string()),
+        object_id: Some(id.to_string()),
+        message,
+        modification,
+    }
+}
+
+fn cascade_issue(object_type: &str, id: Option<&str>, message: String) -> ReportIssue {
+    ReportIssue {
+        code: IssueCode::CascadeRemoval,
+        severity: Severity::Info,
+        object_type: Some(object_type.to_string()),
+        object_id: id.map(str::to_string),
+        message,
+        modification: Some("removed".to_string()),
+    }
+}
+
+/// Remove the stop points and stop areas, with their stop times, the trips
+/// without stop time left, and their transfers, pathways and stop locations.
+fn drop_stops(
+    collections: &mut Collections,
+    stop_point_ids: &HashSet<String>,
+    stop_area_ids: &HashSet<String>,
+    report: &mut Report,
+) {
+    let stop_point_idxs: HashSet<Idx<StopPoint>> = stop_point_ids
+        .iter()
+        .filter_map(|id| collections.stop_points.get_idx(id))
+        .collect();
+    let mut removed_stop_times = HashSet::new();
+    let mut empty_vehicle_journeys = HashSet::new();
+    for vj_idx in collections.vehicle_journeys.indexes() {
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        let nb_stop_times = vj.stop_times.len();
+        let vj_id = vj.id.clone();
+        let vj_key = IdString::from(&vj_id);
+        vj.stop_times.retain(|stop_time| {
+            let is_removed = stop_point_idxs.contains(&stop_time.stop_point_idx);
+            if is_removed {
+                removed_stop_times.insert((vj_key.clone(), stop_time.sequence));
+            }
+            !is_removed
+        });
+        if vj.stop_times.len() == nb_stop_times {
+            continue;
+        }
+        report.push(cascade_issue(
+            "stop_time",
+            None,
+            format!(
+                "{} stop times of trip {:?} are at removed stop points",
+                nb_stop_times - vj.stop_times.len(),
+                vj.id
+            ),
+        ));
+        if vj.stop_times.is_empty() {
+            empty_vehicle_journeys.insert(vj_id);
+        }
+    }
+    for vj_id in &empty_vehicle_journeys {
+        report.push(cascade_issue(
+            "trip",
+            Some(vj_id),
+            format!("trip {:?} has no stop time left", vj_id),
+        ));
+    }
+    collections
+        .vehicle_journeys
+        .retain(|vj| !empty_vehicle_journeys.contains(&vj.id));
+    collections
+        .stop_time_ids
+        .retain(|key, _| !removed_stop_times.contains(key));
+    collections
+        .stop_time_headsigns
+        .retain(|key, _| !removed_stop_times.contains(key));
+    collections
+        .stop_time_comments
+        .retain(|key, _| !removed_stop_times.contains(key));
+
+    let mut removed_stop_ids: HashSet<String> =
+        stop_point_ids.union(stop_area_ids).cloned().collect();
+    let mut removed_stop_locations = Vec::new();
+    collections.stop_locations.retain(|stop_location| {
+        let is_removed = stop_location
+            .parent_id
+            .as_ref()
+            .is_some_and(|parent_id| removed_stop_ids.contains(parent_id));
+        if is_removed {
+            removed_stop_locations.push(stop_location.id.clone());
+        }
+        !is_removed
+    });
+    for stop_location_id in removed_stop_locations {
+        report.push(cascade_issue(
+            "stop_location",
+            Some(&stop_location_id),
+            format!("stop location {:?} is in a removed stop", stop_location_id),
+        ));
+        removed_stop_ids.insert(stop_location_id);
+    }
+
+    collections.pathways.retain(|pathway| {
+        let is_removed = removed_stop_ids.contains(&pathway.from_stop_id)
+            || removed_stop_ids.contains(&pathway.to_stop_id);
+        if is_removed {
+            report.push(cascade_issue(
+                "pathway",
+                Some(&pathway.id),
+                format!("pathway {:?} links a removed stop", pathway.id),
+            ));
+        }
+        !is_removed
+    });
+    collections.transfers.retain(|transfer| {
+        let is_removed = stop_point_ids.contains(&transfer.from_stop_id)
+            || stop_point_ids.contains(&transfer.to_stop_id);
+        if is_removed {
+            report.push(cascade_issue(
+                "transfer",
+                None,
+                format!(
+                    "transfer from {:?} to {:?} links a removed stop point",
+                    transfer.from_stop_id, transfer.to_stop_id
+                ),
+            ));
+        }
+        !is_removed
+    });
+
+    let stop_point_id_to_old_idx = collections.stop_points.get_id_to_idx().clone();
+    collections
+        .stop_points
+        .retain(|stop_point| !stop_point_ids.contains(&stop_point.id));
+    let stop_point_old_idx_to_new_idx: HashMap<Idx<StopPoint>, Idx<StopPoint>> = collections
+        .stop_points
+        .iter()
+        .map(|(new_idx, stop_point)| (stop_point_id_to_old_idx[&stop_point.id], new_idx))
+        .collect();
+    for vj_idx in collections.vehicle_journeys.indexes() {
+        let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+        for stop_time in vj.stop_times.iter_mut() {
+            stop_time.stop_point_idx = stop_point_old_idx_to_new_idx[&stop_time.stop_point_idx];
+        }
+    }
+    collections
+        .stop_areas
+        .retain(|stop_area| !stop_area_ids.contains(&stop_area.id));
+}
+
+/// Check the coordinates of the stop areas and stop points, handle each issue
+/// with the strategy configured for its type and add it to `report`, with
+/// the modification made, if any.
+pub(crate) fn check_coordinates(
+    collections: &mut Collections,
+    config: &CoordinatesConfiguration,
+    report: &mut Report,
+) {
+    let bounding_box = config.bounding_box.or_else(|| {
+        BoundingBox::from_percentile(
+            collections
+                .stop_points
+                .values()
+                .map(|stop_point| &stop_point.coord)
+                .filter(|coord| **coord != Coord::default() && coord.is_valid()),
+        )
+    });
+    let bounding_box = bounding_box.as_ref();
+
+    let mut dropped_stop_areas = HashSet::new();
+    let mut stop_area_coords = Vec::new();
+    for stop_area in collections.stop_areas.values() {
+        let code = match find_issue(&stop_area.coord, bounding_box) {
+            Some(code) => code,
+            None => continue,
+        };
+        let mut message = describe(code, "stop area", &stop_area.id, &stop_area.coord);
+        let modification = match config.strategy(code) {
+            CoordinatesStrategy::Report => None,
+            CoordinatesStrategy::Fix => {
+                let coord = if code == IssueCode::SwappedCoordinates {
+                    Some(swap(&stop_area.coord))
+                } else {
+                    collections
+                        .stop_points
+                        .values()
+                        .filter(|sp| sp.stop_area_id == stop_area.id)
+                        .filter(|sp| find_issue(&sp.coord, bounding_box).is_none())
+                        .map(|sp| (sp.coord.lon, sp.coord.lat))
+                        .collect::<MultiPoint<_>>()
+                        .centroid()
+                        .map(Coord::from)
+                };
+                match coord {
+                    Some(coord) => {
+                        stop_area_coords.push((stop_area.id.clone(), coord));
+                        Some(format!(
+                            "coordinates set to (lon {}, lat {})",
+                            coord.lon, coord.lat
+                        ))
+                    }
+                    None => {
+                        message.push_str(", not fixed as none of its stop points is valid");
+                        None
+                    }
+                }
+            }
+            CoordinatesStrategy::Drop => {
+                dropped_stop_areas.insert(stop_area.id.clone());
+                Some("removed with its stop points".to_string())
+            }
+        };
+        report.push(coordinates_issue(
+            code,
+            "stop_area",
+            &stop_area.id,
+            message,
+            modification,
+        ));
+    }
+    for (stop_area_id, coord) in stop_area_coords {
+        if let Some(mut stop_area) = collections.stop_areas.get_mut(&stop_area_id) {
+            stop_area.coord = coord;
+        }
+    }
+
+    let mut dropped_stop_points = HashSet::new();
+    let mut stop_point_coords = Vec::new();
+    for stop_point in collections.stop_points.values() {
+        if dropped_stop_areas.contains(&stop_point.stop_area_id) {
+            dropped_stop_points.insert(stop_point.id.clone());
+            report.push(cascade_issue(
+                "stop_point",
+                Some(&stop_point.id),
+                format!(
+                    "stop point {:?} is in the removed stop area {:?}",
+                    stop_point.id, stop_point.stop_area_id
+                ),
+            ));
+            continue;
+        }
+        let code = match find_issue(&stop_point.coord, bounding_box) {
+            Some(code) => code,
+            None => continue,
+        };
+        let mut message = describe(code, "stop point", &stop_point.id, &stop_point.coord);
+        let modification = match config.strategy(code) {
+            CoordinatesStrategy::Report => None,
+            CoordinatesStrategy::Fix => {
+                let coord = if code == IssueCode::SwappedCoordinates {
+                    Some(swap(&stop_point.coord))
+                } else {
+                    collections
+                        .stop_areas
+                        .get(&stop_point.stop_area_id)
+                        .map(|stop_area| stop_area.coord)
+                        .filter(|coord| find_issue(coord, bounding_box).is_none())
+                };
+                match coord {
+                    Some(coord) => {
+                        stop_point_coords.push((stop_point.id.clone(), coord));
+                        Some(format!(
+                            "coordinates set to (lon {}, lat {})",
+                            coord.lon, coord.lat
+                        ))
+                    }
+                    None => {
+                        message.push_str(", not fixed as its stop area is not valid");
+                        None
+                    }
+                }
+            }
+            CoordinatesStrategy::Drop => {
+                dropped_stop_points.insert(stop_point.id.clone());
+                Some("removed".to_string())
+            }
+        };
+        report.push(coordinates_issue(
+            code,
+            "stop_point",
+            &stop_point.id,
+            message,
+            modification,
+        ));
+    }
+    for (stop_point_id, coord) in stop_point_coords {
+        if let Some(mut stop_point) = collections.stop_points.get_mut(&stop_point_id) {
+            stop_point.coord = coord;
+        }
+    }
+
+    if !dropped_stop_points.is_empty() || !dropped_stop_areas.is_empty() {
+        drop_stops(
+            collections,
+            &dropped_stop_points,
+            &dropped_stop_areas,
+            report,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{StopArea, StopTime, Time, Transfer, TransferType, VehicleJourney};
+    use pretty_assertions::assert_eq;
+
+    const PARIS: Coord = Coord {
+        lon: 2.35,
+        lat: 48.85,
+    };
+
+    fn paris_bounding_box() -> BoundingBox {
+        BoundingBox {
+            min: Coord { lon: 2., lat: 48.5 },
+            max: Coord {
+                lon: 2.7,
+                lat: 49.2,
+            },
+        }
+    }
+
+    fn collections() -> Collections {
+        let mut collections = Collections::default();
+        let coords = [
+            ("SP1", PARIS),
+            ("SP2", Coord { lon: 0., lat: 0. }),
+            (
+                "SP3",
+                Coord {
+                    lon: 48.85,
+                    lat: 2.35,
+                },
+            ),
+            (
+                "SP4",
+                Coord {
+                    lon: 2.35,
+                    lat: 148.85,
+                },
+            ),
+            (
+                "SP5",
+                Coord {
+                    lon: -73.9,
+                    lat: 40.7,
+                },
+            ),
+        ];
+        for (id, coord) in coords.iter() {
+            collections
+                .stop_areas
+                .push(StopArea {
+                    id: format!("sa:{}", id),
+                    coord: PARIS,
+                    ..Default::default()
+                })
+                .unwrap();
+            collections
+                .stop_points
+                .push(StopPoint {
+                    id: id.to_string(),
+                    stop_area_id: format!("sa:{}", id),
+                    coord: *coord,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        for (vj_id, stop_point_ids) in &[
+            ("vj1", vec!["SP1", "SP2", "SP3"]),
+            ("vj2", vec!["SP4", "SP5"]),
+        ] {
+            let stop_times = stop_point_ids
+                .iter()
+                .enumerate()
+                .map(|(sequence, stop_point_id)| StopTime {
+                    stop_point_idx: collections.stop_points.get_idx(stop_point_id).unwrap(),
+                    sequence: sequence as u32,
+                    arrival_time: Time::new(10, sequence as u32, 0),
+                    departure_time: Time::new(10, sequence as u32, 0),
+                    boarding_duration: 0,
+                    alighting_duration: 0,
+                    pickup_type: 0,
+                    drop_off_type: 0,
+                    local_zone_id: None,
+                    precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
+                })
+                .collect();
+            collections
+                .vehicle_journeys
+                .push(VehicleJourney {
+                    id: vj_id.to_string(),
+                    stop_times,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        collections.transfers.push(Transfer {
+            from_stop_id: "SP1".to_string(),
+            to_stop_id: "SP2".to_string(),
+            min_transfer_time: None,
+            real_min_transfer_time: None,
+            equipment_id: None,
+            transfer_type: TransferType::Possible,
+        });
+        collections
+    }
+
+    fn config(strategy: CoordinatesStrategy) -> CoordinatesConfiguration {
+        CoordinatesConfiguration {
+            null: strategy,
+            out_of_range: strategy,
+            swapped: strategy,
+            outside_bounding_box: strategy,
+            bounding_box: Some(paris_bounding_box()),
+        }
+    }
+
+    fn issues(report: &Report) -> Vec<(IssueCode, &str, bool)> {
+        report
+            .issues
+            .iter()
+            .map(|issue| {
+                (
+                    issue.code,
+                    issue.object_id.as_deref().unwrap_or_default(),
+                    issue.modification.is_some(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bounding_box_from_percentile() {
+        let mut coords: Vec<Coord> = (0..100)
+            .map(|i| Coord {
+                lon: 2.3 + f64::from(i) * 0.001,
+                lat: 48.8 + f64::from(i) * 0.001,
+            })
+            .collect();
+        coords.push(Coord {
+            lon: -73.9,
+            lat: 40.7,
+        });
+        let bounding_box = BoundingBox::from_percentile(&coords).unwrap();
+        assert!(coords[..100].iter().all(|c| bounding_box.contains(c)));
+        assert!(!bounding_box.contains(&coords[100]));
+        assert_eq!(None, BoundingBox::from_percentile(&[]));
+    }
+
+    #[test]
+    fn report_coordinates_issues() {
+        let mut collections = collections();
+        let mut report = Report::default();
+        let config = CoordinatesConfiguration {
+            bounding_box: Some(paris_bounding_box()),
+            ..Default::default()
+        };
+        check_coordinates(&mut collections, &config, &mut report);
+        assert_eq!(
+            vec![
+                (IssueCode::NullCoordinates, "SP2", false),
+                (IssueCode::SwappedCoordinates, "SP3", false),
+                (IssueCode::OutOfRangeCoordinates, "SP4", false),
+                (IssueCode::OutsideBoundingBox, "SP5", false),
+            ],
+            issues(&report)
+        );
+        assert_eq!(Some(&4), report.counts.get(&Severity::Warning));
+        assert_eq!(5, collections.stop_points.len());
+        assert_eq!(
+            Coord { lon: 0., lat: 0. },
+            collections.stop_points.get("SP2").unwrap().coord
+        );
+    }
+
+    #[test]
+    fn fix_coordinates() {
+        let mut collections = collections();
+        collections.stop_areas.get_mut("sa:SP1").unwrap().coord = Coord { lon: 0., lat: 0. };
+        let mut report = Report::default();
+        check_coordinates(
+            &mut collections,
+            &config(CoordinatesStrategy::Fix),
+            &mut report,
+        );
+        assert_eq!(
+            vec![
+                (IssueCode::NullCoordinates, "sa:SP1", true),
+                (IssueCode::NullCoordinates, "SP2", true),
+                (IssueCode::SwappedCoordinates, "SP3", true),
+                (IssueCode::OutOfRangeCoordinates, "SP4", true),
+                (IssueCode::OutsideBoundingBox, "SP5", true),
+            ],
+            issues(&report)
+        );
+        assert!(collections
+            .stop_points
+            .values()
+            .all(|stop_point| stop_point.coord == PARIS));
+        assert_eq!(PARIS, collections.stop_areas.get("sa:SP1").unwrap().coord);
+    }
+
+    #[test]
+    fn drop_stops_with_cascade() {
+        let mut collections = collections();
+        let mut report = Report::default();
+        check_coordinates(
+            &mut collections,
+            &config(CoordinatesStrategy::Drop),
+            &mut report,
+        );
+        assert_eq!(
+            vec![
+                (IssueCode::NullCoordinates, "SP2", true),
+                (IssueCode::SwappedCoordinates, "SP3", true),
+                (IssueCode::OutOfRangeCoordinates, "SP4", true),
+                (IssueCode::OutsideBoundingBox, "SP5", true),
+                (IssueCode::CascadeRemoval, "", true),
+                (IssueCode::CascadeRemoval, "", true),
+                (IssueCode::CascadeRemoval, "vj2", true),
+                (IssueCode::CascadeRemoval, "", true),
+            ],
+            issues(&report)
+        );
+        assert_eq!(
+            vec!["SP1"],
+            collections
+                .stop_points
+                .values()
+                .map(|stop_point| stop_point.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(!collections.vehicle_journeys.contains_id("vj2"));
+        let vj1 = collections.vehicle_journeys.get("vj1").unwrap();
+        assert_eq!(1, vj1.stop_times.len());
+        assert_eq!(
+            "SP1",
+            collections.stop_points[vj1.stop_times[0].stop_point_idx].id
+        );
+        assert!(collections.transfers.is_empty());
+    }
+}
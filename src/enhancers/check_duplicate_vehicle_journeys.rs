@@ -0,0 +1,333 @@
+use crate::{
+    model::Collections,
+    objects::{Calendar, Date, VehicleJourney},
+    report::{IssueCode, Report, ReportIssue, Severity},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+use typed_index_collection::Idx;
+
+// Hash of the route, the stop points and the times of a vehicle journey,
+// computed without building the whole key
+fn schedule_hash(vj: &VehicleJourney) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    vj.route_id.hash(&mut hasher);
+    for stop_time in &vj.stop_times {
+        stop_time.stop_point_idx.hash(&mut hasher);
+        stop_time.arrival_time.hash(&mut hasher);
+        stop_time.departure_time.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn same_schedule(vj: &VehicleJourney, other: &VehicleJourney) -> bool {
+    vj.route_id == other.route_id
+        && vj.stop_times.len() == other.stop_times.len()
+        && vj
+            .stop_times
+            .iter()
+            .zip(other.stop_times.iter())
+            .all(|(stop_time, other)| {
+                stop_time.stop_point_idx == other.stop_point_idx
+                    && stop_time.arrival_time == other.arrival_time
+                    && stop_time.departure_time == other.departure_time
+            })
+}
+
+/// Find the groups of vehicle journeys with the same route, the same stop
+/// points and the same arrival and departure times, whose calendars overlap.
+///
+/// The vehicle journeys of a group are in the order of the collection, the
+/// first one being kept when merging.
+pub(crate) fn find_duplicate_vehicle_journeys(
+    collections: &Collections,
+) -> Vec<Vec<Idx<VehicleJourney>>> {
+    let mut vjs_by_hash: HashMap<u64, Vec<Idx<VehicleJourney>>> = HashMap::new();
+    for (vj_idx, vj) in collections.vehicle_journeys.iter() {
+        vjs_by_hash
+            .entry(schedule_hash(vj))
+            .or_default()
+            .push(vj_idx);
+    }
+    let dates = |vj_idx: Idx<VehicleJourney>| -> BTreeSet<Date> {
+        collections
+            .calendars
+            .get(&collections.vehicle_journeys[vj_idx].service_id)
+            .map(|calendar| calendar.dates.clone())
+            .unwrap_or_default()
+    };
+
+    let mut groups = Vec::new();
+    for vj_idxs in vjs_by_hash.values().filter(|vj_idxs| vj_idxs.len() > 1) {
+        // Different schedules may have the same hash
+        let mut schedules: Vec<Vec<Idx<VehicleJourney>>> = Vec::new();
+        for &vj_idx in vj_idxs {
+            let vj = &collections.vehicle_journeys[vj_idx];
+            match schedules
+                .iter_mut()
+                .find(|schedule| same_schedule(&collections.vehicle_journeys[schedule[0]], vj))
+            {
+                Some(schedule) => schedule.push(vj_idx),
+                None => schedules.push(vec![vj_idx]),
+            }
+        }
+        for schedule in schedules.into_iter().filter(|s| s.len() > 1) {
+            // Journeys are grouped as soon as their calendars overlap,
+            // directly or through another journey of the group
+            let mut clusters: Vec<(Vec<Idx<VehicleJourney>>, BTreeSet<Date>)> = Vec::new();
+            for vj_idx in schedule {
+                let mut members = vec![vj_idx];
+                let mut dates = dates(vj_idx);
+                let (overlapping, others): (Vec<_>, Vec<_>) = clusters
+                    .into_iter()
+                    .partition(|(_, cluster_dates)| !cluster_dates.is_disjoint(&dates));
+                for (cluster, cluster_dates) in overlapping {
+                    members.extend(cluster);
+                    dates.extend(cluster_dates);
+                }
+                members.sort();
+                clusters = others;
+                clusters.push((members, dates));
+            }
+            groups.extend(
+                clusters
+                    .into_iter()
+                    .map(|(members, _)| members)
+                    .filter(|members| members.len() > 1),
+            );
+        }
+    }
+    groups.sort();
+    groups
+}
+
+/// Report the duplicate vehicle journeys found by
+/// [find_duplicate_vehicle_journeys] in `report`. If `merge` is true, only
+/// the first vehicle journey of each group is kept: it runs on the dates of
+/// all the calendars of the group and gets the codes of the removed ones.
+pub(crate) fn check_duplicate_vehicle_journeys(
+    collections: &mut Collections,
+    merge: bool,
+    report: &mut Report,
+) {
+    let groups = find_duplicate_vehicle_journeys(collections);
+    let mut removed_vjs = HashSet::new();
+    for group in groups {
+        let survivor_idx = group[0];
+        let survivor_id = collections.vehicle_journeys[survivor_idx].id.clone();
+        let mut dates = BTreeSet::new();
+        let mut codes = BTreeSet::new();
+        for &vj_idx in &group {
+            let vj = &collections.vehicle_journeys[vj_idx];
+            if let Some(calendar) = collections.calendars.get(&vj.service_id) {
+                dates.extend(calendar.dates.iter().copied());
+            }
+            if vj_idx == survivor_idx {
+                continue;
+            }
+            codes.extend(vj.codes.iter().cloned());
+            report.push(ReportIssue {
+                code: IssueCode::DuplicateVehicleJourney,
+                severity: Severity::Warning,
+                object_type: Some("trip".to_string()),
+                object_id: Some(vj.id.clone()),
+                message: format!(
+                    "trip {:?} has the same route, stops and times as trip {:?} on overlapping dates",
+                    vj.id, survivor_id
+                ),
+                modification: if merge {
+                    Some(format!("merged into trip {:?}", survivor_id))
+                } else {
+                    None
+                },
+            });
+            removed_vjs.insert(vj.id.clone());
+        }
+        if !merge {
+            continue;
+        }
+
+        let service_id = collections.vehicle_journeys[survivor_idx]
+            .service_id
+            .clone();
+        let has_all_dates = collections
+            .calendars
+            .get(&service_id)
+            .is_some_and(|calendar| calendar.dates == dates);
+        let mut survivor = collections.vehicle_journeys.index_mut(survivor_idx);
+        survivor.codes.extend(codes);
+        if has_all_dates {
+            continue;
+        }
+        // The calendar may be shared with other journeys, so a new one is created
+        let mut calendar_id = format!("{}:merged", survivor_id);
+        let mut suffix = 1;
+        while collections.calendars.contains_id(&calendar_id) {
+            calendar_id = format!("{}:merged:{}", survivor_id, suffix);
+            suffix += 1;
+        }
+        survivor.service_id = calendar_id.clone();
+        collections
+            .calendars
+            .push(Calendar {
+                id: calendar_id,
+                dates,
+            })
+            .expect("the calendar identifier is unique");
+    }
+
+    if merge && !removed_vjs.is_empty() {
+        collections
+            .vehicle_journeys
+            .retain(|vj| !removed_vjs.contains(&vj.id));
+        collections
+            .frequencies
+            .retain(|frequency| !removed_vjs.contains(&frequency.vehicle_journey_id));
+        collections.trip_transfers.retain(|trip_transfer| {
+            !removed_vjs.contains(&trip_transfer.from_vehicle_journey_id)
+                && !removed_vjs.contains(&trip_transfer.to_vehicle_journey_id)
+        });
+        collections
+            .stop_time_ids
+            .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+        collections
+            .stop_time_headsigns
+            .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+        collections
+            .stop_time_comments
+            .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{StopPoint, StopTime, Time};
+    use pretty_assertions::assert_eq;
+    use typed_index_collection::CollectionWithId;
+
+    fn collections() -> Collections {
+        let mut collections = Collections {
+            stop_points: CollectionWithId::new(vec![
+                StopPoint {
+                    id: "SP1".to_string(),
+                    ..Default::default()
+                },
+                StopPoint {
+                    id: "SP2".to_string(),
+                    ..Default::default()
+                },
+            ])
+            .unwrap(),
+            ..Default::default()
+        };
+        for (id, days) in &[("c1", vec![1, 2]), ("c2", vec![2, 3]), ("c3", vec![10])] {
+            collections
+                .calendars
+                .push(Calendar {
+                    id: id.to_string(),
+                    dates: days.iter().map(|d| Date::from_ymd(2020, 1, *d)).collect(),
+                })
+                .unwrap();
+        }
+        let stop_time = |stop_point_id: &str, sequence: u32, minutes: u32| StopTime {
+            stop_point_idx: collections.stop_points.get_idx(stop_point_id).unwrap(),
+            sequence,
+            arrival_time: Time::new(10, minutes, 0),
+            departure_time: Time::new(10, minutes, 0),
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: 0,
+            drop_off_type: 0,
+            local_zone_id: None,
+            precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
+        };
+        let vjs = [
+            ("vj1", "c1", 0, "a"),
+            ("vj2", "c2", 0, "b"),
+            ("vj3", "c3", 0, "c"),
+            ("vj4", "c1", 5, "d"),
+        ];
+        let vjs = vjs
+            .iter()
+            .map(|(id, service_id, minutes, code)| VehicleJourney {
+                id: id.to_string(),
+                route_id: "route".to_string(),
+                service_id: service_id.to_string(),
+                codes: vec![("source".into(), code.to_string())]
+                    .into_iter()
+                    .collect(),
+                stop_times: vec![
+                    stop_time("SP1", 0, *minutes),
+                    stop_time("SP2", 1, minutes + 10),
+                ],
+                ..Default::default()
+            })
+            .collect();
+        collections.vehicle_journeys = CollectionWithId::new(vjs).unwrap();
+        collections
+    }
+
+    fn ids(collections: &Collections, group: &[Idx<VehicleJourney>]) -> Vec<String> {
+        group
+            .iter()
+            .map(|vj_idx| collections.vehicle_journeys[*vj_idx].id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn find_journeys_with_overlapping_calendars() {
+        let collections = collections();
+        let groups = find_duplicate_vehicle_journeys(&collections);
+        assert_eq!(1, groups.len());
+        assert_eq!(vec!["vj1", "vj2"], ids(&collections, &groups[0]));
+    }
+
+    #[test]
+    fn report_without_merging() {
+        let mut collections = collections();
+        let mut report = Report::default();
+        check_duplicate_vehicle_journeys(&mut collections, false, &mut report);
+        assert_eq!(1, report.issues.len());
+        assert_eq!(IssueCode::DuplicateVehicleJourney, report.issues[0].code);
+        assert_eq!(Some("vj2"), report.issues[0].object_id.as_deref());
+        assert_eq!(None, report.issues[0].modification);
+        assert_eq!(4, collections.vehicle_journeys.len());
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let mut collections = collections();
+        let mut report = Report::default();
+        check_duplicate_vehicle_journeys(&mut collections, true, &mut report);
+        assert!(report.issues[0].modification.is_some());
+        assert!(!collections.vehicle_journeys.contains_id("vj2"));
+        let vj1 = collections.vehicle_journeys.get("vj1").unwrap();
+        assert_eq!("vj1:merged", vj1.service_id);
+        assert_eq!(
+            vec![
+                ("source".into(), "a".to_string()),
+                ("source".into(), "b".to_string())
+            ],
+            vj1.codes.iter().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (1..=3)
+                .map(|d| Date::from_ymd(2020, 1, d))
+                .collect::<BTreeSet<_>>(),
+            collections.calendars.get("vj1:merged").unwrap().dates
+        );
+        // The original calendar is still used by vj4
+        assert_eq!(2, collections.calendars.get("c1").unwrap().dates.len());
+        assert_eq!(
+            "c1",
+            collections.vehicle_journeys.get("vj4").unwrap().service_id
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{
+    model::Collections,
+    objects::{ObjectType, RestrictionType, TicketUsePerimeter, TicketUseRestriction},
+};
+use std::collections::HashSet;
+use tracing::warn;
+
+/// A `ticket_use_restriction` or a `ticket_use_perimeter` referencing an
+/// object that doesn't exist (or that can't be referenced).
+pub(crate) enum InvalidTicketUseReference {
+    Restriction(TicketUseRestriction, String),
+    Perimeter(TicketUsePerimeter, String),
+}
+
+impl InvalidTicketUseReference {
+    /// Description of the invalid reference, for logs and reports
+    pub(crate) fn message(&self, collections: &Collections) -> String {
+        let ticket_id = |ticket_use_id: &str| {
+            collections
+                .ticket_uses
+                .get(ticket_use_id)
+                .map(|ticket_use| ticket_use.ticket_id.clone())
+                .unwrap_or_default()
+        };
+        match self {
+            InvalidTicketUseReference::Restriction(restriction, reason) => format!(
+                "ticket_use_restriction of ticket {:?} (ticket_use {:?}): {}",
+                ticket_id(&restriction.ticket_use_id),
+                restriction.ticket_use_id,
+                reason
+            ),
+            InvalidTicketUseReference::Perimeter(perimeter, reason) => format!(
+                "ticket_use_perimeter of ticket {:?} (ticket_use {:?}): {}",
+                ticket_id(&perimeter.ticket_use_id),
+                perimeter.ticket_use_id,
+                reason
+            ),
+        }
+    }
+}
+
+/// Find the `ticket_use_restrictions` of type OD whose origin or destination
+/// is not an existing stop area, and the `ticket_use_perimeters` referencing a
+/// missing network, commercial mode or line, or another type of object.
+pub(crate) fn find_invalid_ticket_use_references(
+    collections: &Collections,
+) -> Vec<InvalidTicketUseReference> {
+    let mut invalid_references = Vec::new();
+    for restriction in collections.ticket_use_restrictions.values() {
+        if restriction.restriction_type != RestrictionType::OriginDestination {
+            continue;
+        }
+        for stop_area_id in &[&restriction.use_origin, &restriction.use_destination] {
+            if !collections.stop_areas.contains_id(stop_area_id) {
+                invalid_references.push(InvalidTicketUseReference::Restriction(
+                    restriction.clone(),
+                    format!("stop area {:?} not found", stop_area_id),
+                ));
+            }
+        }
+    }
+
+    for perimeter in collections.ticket_use_perimeters.values() {
+        let exists = match perimeter.object_type {
+            ObjectType::Line => collections.lines.contains_id(&perimeter.object_id),
+            ObjectType::Network => collections.networks.contains_id(&perimeter.object_id),
+            ObjectType::CommercialMode => collections
+                .commercial_modes
+                .contains_id(&perimeter.object_id),
+            _ => {
+                invalid_references.push(InvalidTicketUseReference::Perimeter(
+                    perimeter.clone(),
+                    format!(
+                        "object_type {} is not supported",
+                        perimeter.object_type.as_str()
+                    ),
+                ));
+                continue;
+            }
+        };
+        if !exists {
+            invalid_references.push(InvalidTicketUseReference::Perimeter(
+                perimeter.clone(),
+                format!(
+                    "{} {:?} not found",
+                    perimeter.object_type.as_str(),
+                    perimeter.object_id
+                ),
+            ));
+        }
+    }
+    invalid_references
+}
+
+/// Remove the `ticket_use_restrictions` and `ticket_use_perimeters` found by
+/// [find_invalid_ticket_use_references].
+pub fn check_ticket_use_references(collections: &mut Collections) {
+    let mut invalid_restrictions = HashSet::new();
+    let mut invalid_perimeters = HashSet::new();
+    for invalid_reference in find_invalid_ticket_use_references(collections) {
+        warn!("{} is removed", invalid_reference.message(collections));
+        match invalid_reference {
+            InvalidTicketUseReference::Restriction(restriction, _) => {
+                invalid_restrictions.insert(restriction);
+            }
+            InvalidTicketUseReference::Perimeter(perimeter, _) => {
+                invalid_perimeters.insert(perimeter);
+            }
+        }
+    }
+    collections
+        .ticket_use_restrictions
+        .retain(|restriction| !invalid_restrictions.contains(restriction));
+    collections
+        .ticket_use_perimeters
+        .retain(|perimeter| !invalid_perimeters.contains(perimeter));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::Model,
+        objects::{CommercialMode, Line, Network, PerimeterAction, StopArea, Ticket, TicketUse},
+        AddPrefix, PrefixConfiguration,
+    };
+    use pretty_assertions::assert_eq;
+    use typed_index_collection::{Collection, CollectionWithId};
+
+    fn collections() -> Collections {
+        let mut collections = Collections {
+            stop_areas: CollectionWithId::from(StopArea {
+                id: "SA1".to_string(),
+                ..Default::default()
+            }),
+            lines: CollectionWithId::from(Line {
+                id: "L1".to_string(),
+                ..Default::default()
+            }),
+            networks: CollectionWithId::from(Network {
+                id: "N1".to_string(),
+                ..Default::default()
+            }),
+            commercial_modes: CollectionWithId::from(CommercialMode {
+                id: "CM1".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        collections.ticket_uses = CollectionWithId::from(TicketUse {
+            id: "TU1".to_string(),
+            ticket_id: "T1".to_string(),
+            max_transfers: None,
+            boarding_time_limit: None,
+            alighting_time_limit: None,
+        });
+        collections
+    }
+
+    fn restriction(restriction_type: RestrictionType, destination: &str) -> TicketUseRestriction {
+        TicketUseRestriction {
+            ticket_use_id: "TU1".to_string(),
+            restriction_type,
+            use_origin: "SA1".to_string(),
+            use_destination: destination.to_string(),
+        }
+    }
+
+    fn perimeter(object_type: ObjectType, object_id: &str) -> TicketUsePerimeter {
+        TicketUsePerimeter {
+            ticket_use_id: "TU1".to_string(),
+            object_type,
+            object_id: object_id.to_string(),
+            perimeter_action: PerimeterAction::Included,
+        }
+    }
+
+    #[test]
+    fn remove_od_restrictions_with_unknown_stop_areas() {
+        let mut collections = collections();
+        collections.ticket_use_restrictions = Collection::new(vec![
+            restriction(RestrictionType::OriginDestination, "SA1"),
+            restriction(RestrictionType::OriginDestination, "unknown"),
+            restriction(RestrictionType::Zone, "unknown"),
+        ]);
+        check_ticket_use_references(&mut collections);
+        assert_eq!(
+            vec![
+                restriction(RestrictionType::OriginDestination, "SA1"),
+                restriction(RestrictionType::Zone, "unknown"),
+            ],
+            collections.ticket_use_restrictions.take()
+        );
+    }
+
+    #[test]
+    fn remove_perimeters_with_unknown_objects() {
+        let mut collections = collections();
+        collections.ticket_use_perimeters = Collection::new(vec![
+            perimeter(ObjectType::Line, "L1"),
+            perimeter(ObjectType::Line, "unknown"),
+            perimeter(ObjectType::Network, "N1"),
+            perimeter(ObjectType::Network, "unknown"),
+            perimeter(ObjectType::CommercialMode, "CM1"),
+            perimeter(ObjectType::CommercialMode, "unknown"),
+            perimeter(ObjectType::StopArea, "SA1"),
+        ]);
+        check_ticket_use_references(&mut collections);
+        assert_eq!(
+            vec![
+                perimeter(ObjectType::Line, "L1"),
+                perimeter(ObjectType::Network, "N1"),
+                perimeter(ObjectType::CommercialMode, "CM1"),
+            ],
+            collections.ticket_use_perimeters.take()
+        );
+    }
+
+    #[test]
+    fn perimeters_through_prefix_and_filter() {
+        let mut collections = collections();
+        collections
+            .lines
+            .push(Line {
+                id: "L2".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        collections.ticket_use_perimeters = Collection::new(vec![
+            perimeter(ObjectType::Network, "N1"),
+            perimeter(ObjectType::CommercialMode, "CM1"),
+            perimeter(ObjectType::Line, "L1"),
+            perimeter(ObjectType::Line, "L2"),
+        ]);
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix("PRE");
+        collections.prefix(&prefix_conf);
+        // Filter out a line, as a restriction of the data would do
+        collections.lines.retain(|line| line.id != "PRE:L2");
+        check_ticket_use_references(&mut collections);
+
+        let perimeter = |object_type, object_id: &str| TicketUsePerimeter {
+            ticket_use_id: "PRE:TU1".to_string(),
+            ..perimeter(object_type, object_id)
+        };
+        assert_eq!(
+            vec![
+                perimeter(ObjectType::Network, "PRE:N1"),
+                perimeter(ObjectType::CommercialMode, "CM1"),
+                perimeter(ObjectType::Line, "PRE:L1"),
+            ],
+            collections.ticket_use_perimeters.take()
+        );
+    }
+
+    #[test]
+    fn od_restrictions_through_prefix_and_network_filter() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .networks
+            .push(Network {
+                id: "other_network".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        collections.lines.get_mut("RERA").unwrap().network_id = "other_network".to_string();
+        collections.tickets = CollectionWithId::from(Ticket {
+            id: "T1".to_string(),
+            name: "Ticket".to_string(),
+            comment: None,
+        });
+        collections.ticket_uses = self::collections().ticket_uses;
+        let od = |origin: &str, destination: &str| TicketUseRestriction {
+            use_origin: origin.to_string(),
+            ..restriction(RestrictionType::OriginDestination, destination)
+        };
+        collections.ticket_use_restrictions = Collection::new(vec![
+            od("NAT", "GDL"),
+            od("NAT", "DEF"),
+            od("DEF", "CDG"),
+            restriction(RestrictionType::Zone, "Z2"),
+        ]);
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix("PRE");
+        collections.prefix(&prefix_conf);
+        // Keep only the lines of a network with their routes and trips, the
+        // stops of La Défense, only served by the RER A, being removed in
+        // cascade when building the model
+        collections
+            .lines
+            .retain(|line| line.network_id == "PRE:TGN");
+        let lines = &collections.lines;
+        collections
+            .routes
+            .retain(|route| lines.contains_id(&route.line_id));
+        let routes = &collections.routes;
+        collections
+            .vehicle_journeys
+            .retain(|vj| routes.contains_id(&vj.route_id));
+        let model = Model::new(collections).unwrap();
+
+        assert!(model
+            .ticket_use_restrictions
+            .values()
+            .filter(|restriction| {
+                restriction.restriction_type == RestrictionType::OriginDestination
+            })
+            .all(|restriction| {
+                model.stop_areas.contains_id(&restriction.use_origin)
+                    && model.stop_areas.contains_id(&restriction.use_destination)
+            }));
+        let ticket_use_id = "PRE:TU1".to_string();
+        assert_eq!(
+            vec![
+                TicketUseRestriction {
+                    ticket_use_id: ticket_use_id.clone(),
+                    use_origin: "SA1".to_string(),
+                    ..restriction(RestrictionType::Zone, "Z2")
+                },
+                TicketUseRestriction {
+                    ticket_use_id,
+                    ..od("PRE:NAT", "PRE:GDL")
+                },
+            ],
+            model
+                .ticket_use_restrictions
+                .values()
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -206,6 +206,10 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
         };
         // First vehicle journey, second stop time
         let stop_time_2 = StopTime {
@@ -219,6 +223,10 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
         };
         // Second vehicle journey, first stop time
         let next_vj_config_time_1 = StopTime {
@@ -232,6 +240,10 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
         };
         // Second vehicle journey, second stop time
         let next_vj_config_time_2 = StopTime {
@@ -245,6 +257,10 @@ mod tests {
             drop_off_type: 0,
             local_zone_id: None,
             precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
         };
 
         let vj1 = VehicleJourney {
@@ -456,6 +472,10 @@ mod tests {
             drop_off_type: 3,
             local_zone_id: None,
             precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
         });
         drop(vj_mut);
         let mut dates = BTreeSet::new();
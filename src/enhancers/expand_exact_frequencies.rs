@@ -0,0 +1,304 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use crate::{
+    id_string::IdString,
+    model::Collections,
+    objects::{Frequency, StopTime, Time, VehicleJourney},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+use typed_index_collection::{Collection, CollectionWithId};
+
+// The departures from the first stop generated by a frequency
+fn departures(frequency: &Frequency) -> Vec<Time> {
+    let headway = Time::new(0, 0, frequency.headway_secs);
+    let mut departures = vec![];
+    let mut departure = frequency.start_time;
+    while departure < frequency.end_time {
+        departures.push(departure);
+        departure = departure + headway;
+    }
+    departures
+}
+
+// The stop times of the template shifted to depart from the first stop at
+// `departure`
+fn shifted_stop_times(template: &VehicleJourney, departure: Time) -> Option<Vec<StopTime>> {
+    let first_arrival = template
+        .stop_times
+        .iter()
+        .min_by_key(|stop_time| stop_time.sequence)?
+        .arrival_time;
+    let shifted = template
+        .stop_times
+        .iter()
+        .map(|stop_time| StopTime {
+            arrival_time: stop_time.arrival_time + departure - first_arrival,
+            departure_time: stop_time.departure_time + departure - first_arrival,
+            ..stop_time.clone()
+        })
+        .collect();
+    Some(shifted)
+}
+
+// Copy the stop time properties of `template_id` to `generated_id`
+fn copy_stop_time_properties(
+    properties: &mut HashMap<(IdString, u32), IdString>,
+    template_id: &str,
+    generated_id: &IdString,
+    value: impl Fn(&IdString) -> IdString,
+) {
+    let copies: Vec<_> = properties
+        .iter()
+        .filter(|((vj_id, _), _)| vj_id == template_id)
+        .map(|((_, sequence), property)| ((generated_id.clone(), *sequence), value(property)))
+        .collect();
+    properties.extend(copies);
+}
+
+/// Materialize the frequencies with `exact_times` into vehicle journeys.
+///
+/// Each departure of such a frequency becomes a copy of the vehicle journey
+/// of the frequency, identified by `<trip_id>:freq:<n>`, `n` counting the
+/// copies of the same vehicle journey from 1. The stop times are shifted to
+/// depart from the first stop at the time of the departure, the copy keeping
+/// the calendar, the trip property, the comments, the codes and the object
+/// properties of the original one. Its stop times get the headsigns and the
+/// comments of the original ones and the identifiers
+/// `<stop_time_id>:freq:<n>`.
+///
+/// The expanded frequencies are removed, and so is the original vehicle
+/// journey unless a headway-based frequency still references it.
+pub fn expand_exact_frequencies(collections: &mut Collections) -> Result<()> {
+    let (exact_frequencies, frequencies): (Vec<_>, Vec<_>) = collections
+        .frequencies
+        .take()
+        .into_iter()
+        .partition(|frequency| frequency.exact_times);
+    collections.frequencies = Collection::new(frequencies);
+    if exact_frequencies.is_empty() {
+        return Ok(());
+    }
+    let still_referenced: HashSet<String> = collections
+        .frequencies
+        .values()
+        .map(|frequency| frequency.vehicle_journey_id.clone())
+        .collect();
+
+    let mut counters: HashMap<String, usize> = HashMap::new();
+    let mut expanded_vjs: HashSet<String> = HashSet::new();
+    let mut generated_vjs: Vec<VehicleJourney> = vec![];
+    let mut generated_ids: HashSet<String> = HashSet::new();
+    // The original vehicle journey and the suffix of each copy
+    let mut copies: Vec<(String, String)> = vec![];
+    for frequency in &exact_frequencies {
+        let template = match collections
+            .vehicle_journeys
+            .get(&frequency.vehicle_journey_id)
+        {
+            Some(template) => template,
+            None => {
+                warn!(
+                    "frequency mapped to an unexisting trip {:?}",
+                    frequency.vehicle_journey_id
+                );
+                continue;
+            }
+        };
+        expanded_vjs.insert(template.id.clone());
+        for departure in departures(frequency) {
+            let stop_times = match shifted_stop_times(template, departure) {
+                Some(stop_times) => stop_times,
+                None => {
+                    warn!(
+                        "frequency mapped to trip {:?} with no stop_times",
+                        template.id
+                    );
+                    break;
+                }
+            };
+            let counter = counters.entry(template.id.clone()).or_insert(0);
+            *counter += 1;
+            let suffix = format!(":freq:{}", counter);
+            let id = format!("{}{}", template.id, suffix);
+            if collections.vehicle_journeys.contains_id(&id) || !generated_ids.insert(id.clone()) {
+                warn!("trip {:?} already exists, the departure is ignored", id);
+                continue;
+            }
+            copies.push((template.id.clone(), suffix));
+            generated_vjs.push(VehicleJourney {
+                id,
+                stop_times,
+                ..template.clone()
+            });
+        }
+    }
+
+    for (template_id, suffix) in &copies {
+        let generated_id = IdString::from(format!("{}{}", template_id, suffix));
+        copy_stop_time_properties(
+            &mut collections.stop_time_headsigns,
+            template_id,
+            &generated_id,
+            |headsign| headsign.clone(),
+        );
+        copy_stop_time_properties(
+            &mut collections.stop_time_comments,
+            template_id,
+            &generated_id,
+            |comment_id| comment_id.clone(),
+        );
+        copy_stop_time_properties(
+            &mut collections.stop_time_ids,
+            template_id,
+            &generated_id,
+            |stop_time_id| IdString::from(format!("{}{}", stop_time_id, suffix)),
+        );
+    }
+
+    let removed_vjs: HashSet<String> = expanded_vjs
+        .into_iter()
+        .filter(|vj_id| !still_referenced.contains(vj_id))
+        .collect();
+    let mut vehicle_journeys = collections.vehicle_journeys.take();
+    vehicle_journeys.retain(|vj| !removed_vjs.contains(&vj.id));
+    vehicle_journeys.append(&mut generated_vjs);
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+    collections
+        .stop_time_ids
+        .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+    collections
+        .stop_time_headsigns
+        .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+    collections
+        .stop_time_comments
+        .retain(|(vj_id, _), _| !removed_vjs.contains(vj_id.as_str()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::StopPoint;
+    use pretty_assertions::assert_eq;
+
+    fn collections(exact_times: bool) -> Collections {
+        let mut collections = Collections {
+            stop_points: CollectionWithId::new(vec![
+                StopPoint {
+                    id: "sp1".to_string(),
+                    ..Default::default()
+                },
+                StopPoint {
+                    id: "sp2".to_string(),
+                    ..Default::default()
+                },
+            ])
+            .unwrap(),
+            ..Default::default()
+        };
+        let stop_time = |stop_point_id: &str, sequence: u32, time: Time| StopTime {
+            stop_point_idx: collections.stop_points.get_idx(stop_point_id).unwrap(),
+            sequence,
+            arrival_time: time,
+            departure_time: time,
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: 0,
+            drop_off_type: 0,
+            local_zone_id: None,
+            precision: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
+            pickup_booking_rule_id: None,
+            drop_off_booking_rule_id: None,
+        };
+        collections.vehicle_journeys = CollectionWithId::from(VehicleJourney {
+            id: "vj1".to_string(),
+            stop_times: vec![
+                stop_time("sp1", 1, Time::new(10, 0, 0)),
+                stop_time("sp2", 2, Time::new(10, 12, 0)),
+            ],
+            ..Default::default()
+        });
+        let vj_id = IdString::from("vj1");
+        collections.frequencies.push(Frequency {
+            vehicle_journey_id: "vj1".to_string(),
+            start_time: Time::new(8, 0, 0),
+            end_time: Time::new(9, 0, 0),
+            headway_secs: 1200,
+            exact_times,
+        });
+        collections
+            .stop_time_ids
+            .insert((vj_id.clone(), 1), "st".into());
+        collections
+            .stop_time_headsigns
+            .insert((vj_id, 1), "Somewhere".into());
+        collections
+    }
+
+    #[test]
+    fn expand_departures() {
+        let mut collections = collections(true);
+        let template = collections
+            .vehicle_journeys
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        expand_exact_frequencies(&mut collections).unwrap();
+
+        assert_eq!(0, collections.frequencies.len());
+        let ids: Vec<&str> = collections
+            .vehicle_journeys
+            .values()
+            .map(|vj| vj.id.as_str())
+            .filter(|id| id.starts_with(&template.id))
+            .collect();
+        let expected: Vec<String> = (1..=3)
+            .map(|n| format!("{}:freq:{}", template.id, n))
+            .collect();
+        assert_eq!(expected, ids);
+        let third = collections.vehicle_journeys.get(&expected[2]).unwrap();
+        assert_eq!(template.service_id, third.service_id);
+        assert_eq!(Time::new(8, 40, 0), third.stop_times[0].arrival_time);
+        let duration = template.stop_times[1].arrival_time - template.stop_times[0].arrival_time;
+        assert_eq!(
+            Time::new(8, 40, 0) + duration,
+            third.stop_times[1].arrival_time
+        );
+        let key = (
+            IdString::from(expected[2].as_str()),
+            third.stop_times[0].sequence,
+        );
+        assert_eq!("st:freq:3", collections.stop_time_ids[&key].as_str());
+        assert_eq!("Somewhere", collections.stop_time_headsigns[&key].as_str());
+        assert!(!collections
+            .stop_time_ids
+            .keys()
+            .any(|(vj_id, _)| vj_id.as_str() == template.id));
+    }
+
+    #[test]
+    fn keep_headway_based_frequencies() {
+        let expected = collections(false);
+        let mut collections = collections(false);
+        expand_exact_frequencies(&mut collections).unwrap();
+        assert_eq!(expected.frequencies, collections.frequencies);
+        assert_eq!(expected.vehicle_journeys, collections.vehicle_journeys);
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     model::{self, Collections},
     objects::PhysicalMode,
 };
+use std::collections::HashMap;
 use typed_index_collection::CollectionWithId;
 
 lazy_static::lazy_static! {
@@ -35,13 +36,27 @@ lazy_static::lazy_static! {
     };
 }
 
+// The CO2 emission of a physical mode, from the overrides or else the defaults
+fn co2_emission(overrides: &HashMap<String, f32>, physical_mode_id: &str) -> Option<f32> {
+    overrides
+        .get(physical_mode_id)
+        .or_else(|| CO2_EMISSIONS.get(physical_mode_id))
+        .copied()
+}
+
 /// Physical mode should contains CO2 emissions. If the values are not present
-/// in the NTFS, some default values will be used.
-pub fn fill_co2(collections: &mut Collections) {
+/// in the data, the ones of `overrides` (in grams per kilometer, by physical
+/// mode identifier) are used, and otherwise some default values. The values
+/// already present are never overwritten.
+///
+/// As [Model::new](crate::Model::new) fills the CO2 emissions with the
+/// default values, the overrides have to be applied to the collections
+/// before building the model.
+pub fn fill_co2(collections: &mut Collections, overrides: &HashMap<String, f32>) {
     let mut physical_modes = collections.physical_modes.take();
     for physical_mode in &mut physical_modes {
         if physical_mode.co2_emission.is_none() {
-            physical_mode.co2_emission = CO2_EMISSIONS.get(physical_mode.id.as_str()).copied();
+            physical_mode.co2_emission = co2_emission(overrides, &physical_mode.id);
         }
     }
     collections.physical_modes = CollectionWithId::new(physical_modes).unwrap();
@@ -58,7 +73,8 @@ pub fn fill_co2(collections: &mut Collections) {
                 .push(PhysicalMode {
                     id: fallback_mode.to_string(),
                     name: fallback_mode.to_string(),
-                    co2_emission: CO2_EMISSIONS.get(fallback_mode).copied(),
+                    co2_emission: co2_emission(overrides, fallback_mode),
+                    ..Default::default()
                 })
                 .unwrap();
         }
@@ -82,7 +98,7 @@ mod test {
                 ..Default::default()
             })
             .unwrap();
-        fill_co2(&mut collections);
+        fill_co2(&mut collections, &HashMap::new());
 
         let bus_mode = collections
             .physical_modes
@@ -100,9 +116,10 @@ mod test {
                 id: String::from(model::BUS_PHYSICAL_MODE),
                 name: String::from("Bus"),
                 co2_emission: Some(42.0f32),
+                ..Default::default()
             })
             .unwrap();
-        fill_co2(&mut collections);
+        fill_co2(&mut collections, &HashMap::new());
 
         let bus_mode = collections
             .physical_modes
@@ -114,7 +131,7 @@ mod test {
     #[test]
     fn add_fallback_modes() {
         let mut collections = Collections::default();
-        fill_co2(&mut collections);
+        fill_co2(&mut collections, &HashMap::new());
 
         assert_eq!(3, collections.physical_modes.len());
         let bike_mode = collections
@@ -133,4 +150,41 @@ mod test {
             .unwrap();
         assert_relative_eq!(car_mode.co2_emission.unwrap(), 184.0f32);
     }
+
+    #[test]
+    fn enhance_with_overrides() {
+        let mut collections = Collections::default();
+        for (id, co2_emission) in [
+            (model::BUS_PHYSICAL_MODE, None),
+            (model::METRO_PHYSICAL_MODE, None),
+            (model::TRAMWAY_PHYSICAL_MODE, Some(5.0f32)),
+        ]
+        .iter()
+        {
+            collections
+                .physical_modes
+                .push(PhysicalMode {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    co2_emission: *co2_emission,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let overrides: HashMap<String, f32> = [
+            (model::BUS_PHYSICAL_MODE, 100.0f32),
+            (model::TRAMWAY_PHYSICAL_MODE, 2.0f32),
+            (model::CAR_PHYSICAL_MODE, 150.0f32),
+        ]
+        .iter()
+        .map(|(id, co2_emission)| (id.to_string(), *co2_emission))
+        .collect();
+        fill_co2(&mut collections, &overrides);
+
+        let co2_emission = |id| collections.physical_modes.get(id).unwrap().co2_emission;
+        assert_relative_eq!(co2_emission(model::BUS_PHYSICAL_MODE).unwrap(), 100.0f32);
+        assert_relative_eq!(co2_emission(model::METRO_PHYSICAL_MODE).unwrap(), 3.0f32);
+        assert_relative_eq!(co2_emission(model::TRAMWAY_PHYSICAL_MODE).unwrap(), 5.0f32);
+        assert_relative_eq!(co2_emission(model::CAR_PHYSICAL_MODE).unwrap(), 150.0f32);
+    }
 }
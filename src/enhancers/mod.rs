@@ -1,13 +1,34 @@
 //! This module contains various functions that enhance / cleanup `Collections`
 
 mod adjust_lines_names;
+mod check_contact_fields;
+mod check_coordinates;
+mod check_duplicate_vehicle_journeys;
 mod check_stop_times_order;
+mod check_ticket_use_references;
 mod enhance_pickup_dropoff;
+mod expand_exact_frequencies;
 mod fill_co2;
 mod memory_shrink;
+mod tag_school_periods;
 
 pub(crate) use adjust_lines_names::adjust_lines_names;
+pub use check_contact_fields::ContactFieldsMode;
+pub(crate) use check_contact_fields::{
+    check_contact_fields, find_invalid_contact_fields, ContactFieldIssue,
+};
+pub(crate) use check_coordinates::check_coordinates;
+pub use check_coordinates::{BoundingBox, CoordinatesConfiguration, CoordinatesStrategy};
+pub(crate) use check_duplicate_vehicle_journeys::check_duplicate_vehicle_journeys;
 pub(crate) use check_stop_times_order::check_stop_times_order;
+pub(crate) use check_ticket_use_references::{
+    check_ticket_use_references, find_invalid_ticket_use_references, InvalidTicketUseReference,
+};
 pub(crate) use enhance_pickup_dropoff::enhance_pickup_dropoff;
-pub(crate) use fill_co2::fill_co2;
+pub(crate) use expand_exact_frequencies::expand_exact_frequencies;
+pub use fill_co2::fill_co2;
 pub(crate) use memory_shrink::memory_shrink;
+pub(crate) use tag_school_periods::tag_school_periods;
+pub use tag_school_periods::{
+    SchoolPeriodsConfiguration, HOLIDAYS_ONLY, MIXED, SCHOOL_ONLY, SCHOOL_PERIOD_PROPERTY,
+};
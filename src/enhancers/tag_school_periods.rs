@@ -0,0 +1,313 @@
+use crate::{model::Collections, objects::Date};
+use std::collections::{BTreeSet, HashMap};
+use typed_index_collection::CollectionWithId;
+
+/// Key of the object property set on the vehicle journeys
+pub const SCHOOL_PERIOD_PROPERTY: &str = "school_period";
+/// Value of the object property for a journey running during school periods
+pub const SCHOOL_ONLY: &str = "school_only";
+/// Value of the object property for a journey running during holidays
+pub const HOLIDAYS_ONLY: &str = "holidays_only";
+/// Value of the object property for a journey running during both
+pub const MIXED: &str = "mixed";
+
+/// Thresholds used to classify the vehicle journeys, expressed as the ratio
+/// of their dates that are inside the school periods.
+#[derive(Debug, Clone)]
+pub struct SchoolPeriodsConfiguration {
+    /// A journey with at least this ratio of dates inside the school periods
+    /// is `school_only`
+    pub school_only_ratio: f64,
+    /// A journey with at most this ratio of dates inside the school periods
+    /// is `holidays_only`
+    pub holidays_only_ratio: f64,
+}
+
+impl Default for SchoolPeriodsConfiguration {
+    fn default() -> Self {
+        SchoolPeriodsConfiguration {
+            school_only_ratio: 0.95,
+            holidays_only_ratio: 0.05,
+        }
+    }
+}
+
+/// Tag each vehicle journey with the object property `school_period` whose
+/// value is `school_only`, `holidays_only` or `mixed`.
+///
+/// The school dates of a vehicle journey are the dates of the grid calendars
+/// associated to its line by `grid_rel_calendar_line`: the days of the week
+/// of their `grid_periods`, with their `grid_exception_dates` (see
+/// [GridCalendar::dates](crate::objects::GridCalendar::dates)). Vehicle
+/// journeys without any school date or without any date are not tagged, and
+/// the tags of a previous run are replaced.
+pub fn tag_school_periods(collections: &mut Collections, config: &SchoolPeriodsConfiguration) {
+    let dates_by_grid_calendar: HashMap<&str, BTreeSet<Date>> = collections
+        .grid_calendars
+        .values()
+        .map(|grid_calendar| {
+            let dates = grid_calendar.dates(
+                collections.grid_periods.values(),
+                collections.grid_exception_dates.values(),
+            );
+            (grid_calendar.id.as_str(), dates)
+        })
+        .collect();
+    let mut school_dates_by_line: HashMap<&str, BTreeSet<Date>> = HashMap::new();
+    for rel in collections.grid_rel_calendar_line.values() {
+        if let Some(dates) = dates_by_grid_calendar.get(rel.grid_calendar_id.as_str()) {
+            school_dates_by_line
+                .entry(rel.line_id.as_str())
+                .or_default()
+                .extend(dates);
+        }
+    }
+
+    let mut tags: Vec<(String, &'static str)> = Vec::new();
+    for vj in collections.vehicle_journeys.values() {
+        let school_dates = match collections
+            .routes
+            .get(&vj.route_id)
+            .and_then(|route| school_dates_by_line.get(route.line_id.as_str()))
+        {
+            Some(school_dates) if !school_dates.is_empty() => school_dates,
+            _ => continue,
+        };
+        let dates: &BTreeSet<Date> = match collections.calendars.get(&vj.service_id) {
+            Some(calendar) if !calendar.dates.is_empty() => &calendar.dates,
+            _ => continue,
+        };
+        let nb_in_periods = dates.intersection(school_dates).count();
+        let ratio = nb_in_periods as f64 / dates.len() as f64;
+        let tag = if ratio >= config.school_only_ratio {
+            SCHOOL_ONLY
+        } else if ratio <= config.holidays_only_ratio {
+            HOLIDAYS_ONLY
+        } else {
+            MIXED
+        };
+        tags.push((vj.id.clone(), tag));
+    }
+
+    let mut vehicle_journeys = collections.vehicle_journeys.take();
+    let tags: HashMap<String, &str> = tags.into_iter().collect();
+    for vj in &mut vehicle_journeys {
+        vj.object_properties.remove(SCHOOL_PERIOD_PROPERTY);
+        if let Some(tag) = tags.get(&vj.id) {
+            vj.object_properties
+                .insert(SCHOOL_PERIOD_PROPERTY.to_string(), tag.to_string());
+        }
+    }
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::objects::{
+        Calendar, GridCalendar, GridExceptionDate, GridPeriod, GridRelCalendarLine, Route,
+        VehicleJourney,
+    };
+    use pretty_assertions::assert_eq;
+    use typed_index_collection::Collection;
+
+    fn date(day: u32) -> Date {
+        Date::from_ymd_opt(2020, 1, day).unwrap()
+    }
+
+    fn grid_calendar() -> GridCalendar {
+        GridCalendar {
+            id: String::from("grid_1"),
+            name: String::from("School"),
+            monday: true,
+            tuesday: true,
+            wednesday: true,
+            thursday: true,
+            friday: true,
+            saturday: true,
+            sunday: true,
+        }
+    }
+
+    fn collections() -> Collections {
+        let mut collections = Collections::default();
+        collections
+            .routes
+            .push(Route {
+                id: String::from("route_1"),
+                line_id: String::from("line_1"),
+                ..Default::default()
+            })
+            .unwrap();
+        collections
+            .routes
+            .push(Route {
+                id: String::from("route_2"),
+                line_id: String::from("line_2"),
+                ..Default::default()
+            })
+            .unwrap();
+        collections
+            .grid_rel_calendar_line
+            .push(GridRelCalendarLine {
+                grid_calendar_id: String::from("grid_1"),
+                line_id: String::from("line_1"),
+                line_external_code: None,
+            });
+        collections.grid_calendars = CollectionWithId::from(grid_calendar());
+        collections.grid_periods.push(GridPeriod {
+            grid_calendar_id: String::from("grid_1"),
+            start_date: date(1),
+            end_date: date(10),
+        });
+        let services = [
+            ("school", vec![1, 2, 3]),
+            ("holidays", vec![20, 21]),
+            ("mixed", vec![9, 10, 11, 12]),
+        ];
+        for (id, days) in services.iter() {
+            collections
+                .calendars
+                .push(Calendar {
+                    id: id.to_string(),
+                    dates: days.iter().map(|d| date(*d)).collect(),
+                })
+                .unwrap();
+        }
+        for (id, route_id, service_id) in &[
+            ("vj_school", "route_1", "school"),
+            ("vj_holidays", "route_1", "holidays"),
+            ("vj_mixed", "route_1", "mixed"),
+            ("vj_other_line", "route_2", "school"),
+        ] {
+            collections
+                .vehicle_journeys
+                .push(VehicleJourney {
+                    id: id.to_string(),
+                    route_id: route_id.to_string(),
+                    service_id: service_id.to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        collections
+    }
+
+    fn tag<'a>(collections: &'a Collections, vj_id: &str) -> Option<&'a str> {
+        collections
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .object_properties
+            .get(SCHOOL_PERIOD_PROPERTY)
+            .map(String::as_str)
+    }
+
+    #[test]
+    fn tag_with_default_configuration() {
+        let mut collections = collections();
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        assert_eq!(Some(SCHOOL_ONLY), tag(&collections, "vj_school"));
+        assert_eq!(Some(HOLIDAYS_ONLY), tag(&collections, "vj_holidays"));
+        assert_eq!(Some(MIXED), tag(&collections, "vj_mixed"));
+        assert_eq!(None, tag(&collections, "vj_other_line"));
+    }
+
+    #[test]
+    fn tag_with_custom_thresholds() {
+        let mut collections = collections();
+        let config = SchoolPeriodsConfiguration {
+            school_only_ratio: 0.5,
+            holidays_only_ratio: 0.0,
+        };
+        tag_school_periods(&mut collections, &config);
+        assert_eq!(Some(SCHOOL_ONLY), tag(&collections, "vj_mixed"));
+    }
+
+    #[test]
+    fn days_of_week_and_exception_dates() {
+        let mut collections = collections();
+        // 2020-01-03 is a Friday
+        collections.grid_calendars = CollectionWithId::from(GridCalendar {
+            friday: false,
+            ..grid_calendar()
+        });
+        collections.grid_exception_dates.push(GridExceptionDate {
+            grid_calendar_id: String::from("grid_1"),
+            date: date(2),
+            r#type: false,
+        });
+        collections.grid_exception_dates.push(GridExceptionDate {
+            grid_calendar_id: String::from("grid_1"),
+            date: date(20),
+            r#type: true,
+        });
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        // Of the 1st, 2nd and 3rd, only the 1st is a school date
+        assert_eq!(Some(MIXED), tag(&collections, "vj_school"));
+        // The 20th is a school date
+        assert_eq!(Some(MIXED), tag(&collections, "vj_holidays"));
+    }
+
+    #[test]
+    fn previous_tags_are_replaced() {
+        let mut collections = collections();
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        collections.grid_rel_calendar_line = Collection::default();
+        collections
+            .grid_rel_calendar_line
+            .push(GridRelCalendarLine {
+                grid_calendar_id: String::from("grid_1"),
+                line_id: String::from("line_2"),
+                line_external_code: None,
+            });
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        assert_eq!(None, tag(&collections, "vj_school"));
+        assert_eq!(Some(SCHOOL_ONLY), tag(&collections, "vj_other_line"));
+    }
+
+    #[test]
+    fn unknown_grid_calendar() {
+        let mut collections = collections();
+        collections.grid_calendars = CollectionWithId::default();
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        assert_eq!(None, tag(&collections, "vj_school"));
+    }
+
+    #[test]
+    fn leap_day() {
+        let mut collections = collections();
+        let leap_year_date = |month, day| Date::from_ymd_opt(2024, month, day).unwrap();
+        collections.grid_periods.push(GridPeriod {
+            grid_calendar_id: String::from("grid_1"),
+            start_date: leap_year_date(2, 28),
+            end_date: leap_year_date(3, 1),
+        });
+        collections
+            .calendars
+            .push(Calendar {
+                id: String::from("leap_day"),
+                dates: vec![leap_year_date(2, 29)].into_iter().collect(),
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .push(VehicleJourney {
+                id: String::from("vj_leap_day"),
+                route_id: String::from("route_1"),
+                service_id: String::from("leap_day"),
+                ..Default::default()
+            })
+            .unwrap();
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        assert_eq!(Some(SCHOOL_ONLY), tag(&collections, "vj_leap_day"));
+
+        collections.grid_exception_dates.push(GridExceptionDate {
+            grid_calendar_id: String::from("grid_1"),
+            date: leap_year_date(2, 29),
+            r#type: false,
+        });
+        tag_school_periods(&mut collections, &SchoolPeriodsConfiguration::default());
+        assert_eq!(Some(HOLIDAYS_ONLY), tag(&collections, "vj_leap_day"));
+    }
+}
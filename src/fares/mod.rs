@@ -0,0 +1,23 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Conversions and readers of fares into the fares v2 of the NTFS
+//! (`tickets.txt`, `ticket_prices.txt`, `ticket_uses.txt`,
+//! `ticket_use_perimeters.txt` and `ticket_use_restrictions.txt`).
+
+mod netex;
+mod v1_to_v2;
+
+pub use netex::read_netex_fare_frame;
+pub(crate) use v1_to_v2::fares_v1_to_v2;
@@ -0,0 +1,535 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reader of standalone NeTEx `FareFrame`s (French and German profiles).
+//!
+//! Each `PreassignedFareProduct` becomes a ticket, with its price and the
+//! validity of its `Tariff`, and a ticket use with the same identifier whose
+//! perimeter is the lines of the `Tariff`. The NeTEx lines are matched with
+//! the lines of the model through their object codes.
+//!
+//! Only flat products of a single operator are supported for now.
+
+use crate::{
+    model::Collections,
+    objects::{
+        Date, ObjectType, PerimeterAction, Ticket, TicketPrice, TicketUse, TicketUsePerimeter,
+    },
+    serde_utils::normalize_currency_code,
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use minidom::Element;
+use minidom_ext::OnlyChildElementExt;
+use rust_decimal::Decimal;
+use skip_error::skip_error_and_warn;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Read,
+};
+use tracing::warn;
+
+const DEFAULT_CURRENCY: &str = "EUR";
+
+// Structure of the price of a product, only flat fares are supported for now
+enum FareStructure {
+    Flat,
+    Zonal,
+}
+
+struct FareFrame<'a> {
+    currency: String,
+    tariffs: HashMap<&'a str, &'a Element>,
+    prices: HashMap<&'a str, Vec<&'a Element>>,
+}
+
+struct Fare {
+    ticket: Ticket,
+    price: TicketPrice,
+    line_ids: BTreeSet<String>,
+}
+
+fn descendants<'a>(element: &'a Element, name: &str) -> Vec<&'a Element> {
+    let mut found = Vec::new();
+    for child in element.children() {
+        if child.name() == name {
+            found.push(child);
+        }
+        found.extend(descendants(child, name));
+    }
+    found
+}
+
+fn find_fare_frames(element: &Element) -> Vec<&Element> {
+    if element.name() == "FareFrame" {
+        vec![element]
+    } else {
+        descendants(element, "FareFrame")
+    }
+}
+
+fn reference<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+    element.only_child(name).and_then(|e| e.attr("ref"))
+}
+
+fn parse_date(element: &Element, name: &str) -> Result<Date> {
+    let text = element
+        .try_only_child(name)
+        .map_err(|e| anyhow!("{}", e))?
+        .text();
+    let date = text.trim().get(0..10).unwrap_or_default();
+    Date::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("Failed to parse {} '{}'", name, text))
+}
+
+fn parse_validity(tariff: &Element) -> Result<(Date, Date)> {
+    let valid_between = descendants(tariff, "ValidBetween")
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Tariff {:?} has no ValidBetween", tariff.attr("id")))?;
+    let start = parse_date(valid_between, "FromDate")?;
+    let end = parse_date(valid_between, "ToDate")?;
+    if end < start {
+        bail!(
+            "Tariff {:?} has a ToDate before its FromDate",
+            tariff.attr("id")
+        );
+    }
+    Ok((start, end))
+}
+
+fn parse_fare_frame(fare_frame: &Element) -> FareFrame<'_> {
+    let currency = descendants(fare_frame, "DefaultCurrency")
+        .into_iter()
+        .next()
+        .map(Element::text)
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+    let tariffs = descendants(fare_frame, "Tariff")
+        .into_iter()
+        .filter_map(|tariff| tariff.attr("id").map(|id| (id, tariff)))
+        .collect();
+    let mut prices: HashMap<&str, Vec<&Element>> = HashMap::new();
+    for price in descendants(fare_frame, "FareProductPrice") {
+        if let Some(product_id) = reference(price, "PreassignedFareProductRef") {
+            prices.entry(product_id).or_default().push(price);
+        }
+    }
+    FareFrame {
+        currency,
+        tariffs,
+        prices,
+    }
+}
+
+fn fare_structure(tariff: &Element, product: &Element) -> FareStructure {
+    let is_zonal = |element| {
+        ["FareZoneRef", "TariffZoneRef", "DistanceMatrixElement"]
+            .iter()
+            .any(|name| !descendants(element, name).is_empty())
+    };
+    if is_zonal(tariff) || is_zonal(product) {
+        FareStructure::Zonal
+    } else {
+        FareStructure::Flat
+    }
+}
+
+fn parse_price(
+    product_id: &str,
+    product: &Element,
+    fare_frame: &FareFrame<'_>,
+) -> Result<(Decimal, String)> {
+    let mut prices = descendants(product, "FareProductPrice");
+    if let Some(frame_prices) = fare_frame.prices.get(product_id) {
+        prices.extend(frame_prices.iter().copied());
+    }
+    let price = match prices.as_slice() {
+        [price] => *price,
+        [] => bail!("PreassignedFareProduct {} has no price", product_id),
+        _ => bail!(
+            "PreassignedFareProduct {} has several prices, only flat fares are supported",
+            product_id
+        ),
+    };
+    let amount = price
+        .try_only_child("Amount")
+        .map_err(|e| anyhow!("{}", e))?
+        .text();
+    let amount: Decimal = amount.trim().parse().with_context(|| {
+        format!(
+            "PreassignedFareProduct {}: failed to parse amount '{}'",
+            product_id, amount
+        )
+    })?;
+    if amount.is_sign_negative() {
+        bail!(
+            "PreassignedFareProduct {} has a negative amount",
+            product_id
+        );
+    }
+    let currency = price
+        .only_child("Currency")
+        .map(Element::text)
+        .unwrap_or_else(|| fare_frame.currency.clone());
+    let currency = normalize_currency_code(&currency).ok_or_else(|| {
+        anyhow!(
+            "PreassignedFareProduct {}: unknown currency '{}'",
+            product_id,
+            currency
+        )
+    })?;
+    Ok((amount, currency.to_string()))
+}
+
+fn parse_flat_fare(
+    product_id: &str,
+    product: &Element,
+    tariff: &Element,
+    fare_frame: &FareFrame<'_>,
+    lines_by_code: &HashMap<&str, Vec<&str>>,
+) -> Result<Fare> {
+    let operators: BTreeSet<_> = descendants(tariff, "OperatorRef")
+        .into_iter()
+        .filter_map(|operator| operator.attr("ref"))
+        .collect();
+    if operators.len() > 1 {
+        bail!(
+            "PreassignedFareProduct {} has several operators, only single-operator products are supported",
+            product_id
+        );
+    }
+    let mut line_ids = BTreeSet::new();
+    for line_ref in descendants(tariff, "LineRef") {
+        let code = line_ref.attr("ref").unwrap_or_default();
+        match lines_by_code.get(code) {
+            Some(ids) => line_ids.extend(ids.iter().map(|id| id.to_string())),
+            None => bail!(
+                "PreassignedFareProduct {} is skipped: line {:?} not found",
+                product_id,
+                code
+            ),
+        }
+    }
+    if line_ids.is_empty() {
+        bail!("PreassignedFareProduct {} has no line", product_id);
+    }
+    let (ticket_validity_start, ticket_validity_end) = parse_validity(tariff)?;
+    let (price, currency) = parse_price(product_id, product, fare_frame)?;
+    let name = product
+        .only_child("Name")
+        .map(Element::text)
+        .unwrap_or_else(|| product_id.to_string());
+    Ok(Fare {
+        ticket: Ticket {
+            id: product_id.to_string(),
+            name,
+            comment: None,
+        },
+        price: TicketPrice {
+            ticket_id: product_id.to_string(),
+            price,
+            currency,
+            ticket_validity_start,
+            ticket_validity_end,
+        },
+        line_ids,
+    })
+}
+
+fn parse_product(
+    product: &Element,
+    fare_frame: &FareFrame<'_>,
+    lines_by_code: &HashMap<&str, Vec<&str>>,
+) -> Result<Fare> {
+    let product_id = product
+        .attr("id")
+        .ok_or_else(|| anyhow!("PreassignedFareProduct without id"))?;
+    let tariff_id = reference(product, "TariffRef")
+        .ok_or_else(|| anyhow!("PreassignedFareProduct {} has no TariffRef", product_id))?;
+    let tariff = fare_frame.tariffs.get(tariff_id).ok_or_else(|| {
+        anyhow!(
+            "PreassignedFareProduct {}: Tariff {} not found",
+            product_id,
+            tariff_id
+        )
+    })?;
+    match fare_structure(tariff, product) {
+        FareStructure::Flat => {
+            parse_flat_fare(product_id, product, tariff, fare_frame, lines_by_code)
+        }
+        FareStructure::Zonal => bail!(
+            "PreassignedFareProduct {} is skipped: zonal fares are not supported yet",
+            product_id
+        ),
+    }
+}
+
+/// Read the `PreassignedFareProduct`s of the NeTEx `FareFrame`s of `reader`
+/// and add them as tickets to `collections`.
+///
+/// The lines of the `Tariff` of a product are looked for in the object codes
+/// of the lines of `collections`. Products that can't be converted, for
+/// example because they reference a missing line, are reported and skipped.
+pub fn read_netex_fare_frame<R: Read>(mut reader: R, collections: &mut Collections) -> Result<()> {
+    let mut xml = String::new();
+    reader
+        .read_to_string(&mut xml)
+        .context("Failed to read the NeTEx FareFrame")?;
+    let root: Element = xml
+        .parse()
+        .map_err(|e| anyhow!("Failed to parse the NeTEx FareFrame: {}", e))?;
+
+    let mut lines_by_code: HashMap<&str, Vec<&str>> = HashMap::new();
+    for line in collections.lines.values() {
+        for (_, code) in &line.codes {
+            lines_by_code
+                .entry(code.as_str())
+                .or_default()
+                .push(line.id.as_str());
+        }
+    }
+
+    let mut fares = Vec::new();
+    for fare_frame in find_fare_frames(&root) {
+        let fare_frame_content = parse_fare_frame(fare_frame);
+        for product in descendants(fare_frame, "PreassignedFareProduct") {
+            let fare =
+                skip_error_and_warn!(parse_product(product, &fare_frame_content, &lines_by_code));
+            fares.push(fare);
+        }
+    }
+
+    for fare in fares {
+        let ticket_id = fare.ticket.id.clone();
+        if collections.tickets.contains_id(&ticket_id)
+            || collections.ticket_uses.contains_id(&ticket_id)
+        {
+            warn!(
+                "PreassignedFareProduct {} is skipped: ticket {} already exists",
+                ticket_id, ticket_id
+            );
+            continue;
+        }
+        collections.tickets.push(fare.ticket)?;
+        collections.ticket_prices.push(fare.price);
+        collections.ticket_uses.push(TicketUse {
+            id: ticket_id.clone(),
+            ticket_id: ticket_id.clone(),
+            max_transfers: None,
+            boarding_time_limit: None,
+            alighting_time_limit: None,
+        })?;
+        for line_id in fare.line_ids {
+            collections.ticket_use_perimeters.push(TicketUsePerimeter {
+                ticket_use_id: ticket_id.clone(),
+                object_type: ObjectType::Line,
+                object_id: line_id,
+                perimeter_action: PerimeterAction::Included,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Line;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+    use typed_index_collection::CollectionWithId;
+
+    const FARE_FRAME: &str = r#"<PublicationDelivery xmlns="http://www.netex.org.uk/netex">
+        <dataObjects>
+            <CompositeFrame id="FR:CompositeFrame:1">
+                <frames>
+                    <SiteFrame id="FR:SiteFrame:1" />
+                    <FareFrame id="FR:FareFrame:1">
+                        <FrameDefaults>
+                            <DefaultCurrency>EUR</DefaultCurrency>
+                        </FrameDefaults>
+                        <tariffs>
+                            <Tariff id="FR:Tariff:flat">
+                                <validityConditions>
+                                    <ValidBetween>
+                                        <FromDate>2020-01-01T00:00:00</FromDate>
+                                        <ToDate>2020-12-31T23:59:59</ToDate>
+                                    </ValidBetween>
+                                </validityConditions>
+                                <OperatorRef ref="FR:Operator:1" />
+                                <lines>
+                                    <LineRef ref="FR:Line:A" />
+                                    <LineRef ref="FR:Line:B" />
+                                </lines>
+                            </Tariff>
+                            <Tariff id="FR:Tariff:unknown_line">
+                                <validityConditions>
+                                    <ValidBetween>
+                                        <FromDate>2020-01-01T00:00:00</FromDate>
+                                        <ToDate>2020-12-31T00:00:00</ToDate>
+                                    </ValidBetween>
+                                </validityConditions>
+                                <lines>
+                                    <LineRef ref="FR:Line:unknown" />
+                                </lines>
+                            </Tariff>
+                            <Tariff id="FR:Tariff:zonal">
+                                <validityConditions>
+                                    <ValidBetween>
+                                        <FromDate>2020-01-01T00:00:00</FromDate>
+                                        <ToDate>2020-12-31T00:00:00</ToDate>
+                                    </ValidBetween>
+                                </validityConditions>
+                                <lines>
+                                    <LineRef ref="FR:Line:A" />
+                                </lines>
+                                <fareStructureElements>
+                                    <FareStructureElement id="FR:FareStructureElement:1">
+                                        <TariffZoneRef ref="FR:TariffZone:1" />
+                                    </FareStructureElement>
+                                </fareStructureElements>
+                            </Tariff>
+                        </tariffs>
+                        <fareProducts>
+                            <PreassignedFareProduct id="FR:PreassignedFareProduct:single">
+                                <Name>Single ticket</Name>
+                                <TariffRef ref="FR:Tariff:flat" />
+                                <prices>
+                                    <FareProductPrice id="FR:FareProductPrice:single">
+                                        <Amount>1.90</Amount>
+                                    </FareProductPrice>
+                                </prices>
+                            </PreassignedFareProduct>
+                            <PreassignedFareProduct id="FR:PreassignedFareProduct:day">
+                                <Name>Day ticket</Name>
+                                <TariffRef ref="FR:Tariff:flat" />
+                            </PreassignedFareProduct>
+                            <PreassignedFareProduct id="FR:PreassignedFareProduct:unknown_line">
+                                <TariffRef ref="FR:Tariff:unknown_line" />
+                                <prices>
+                                    <FareProductPrice id="FR:FareProductPrice:unknown_line">
+                                        <Amount>2</Amount>
+                                    </FareProductPrice>
+                                </prices>
+                            </PreassignedFareProduct>
+                            <PreassignedFareProduct id="FR:PreassignedFareProduct:zonal">
+                                <TariffRef ref="FR:Tariff:zonal" />
+                                <prices>
+                                    <FareProductPrice id="FR:FareProductPrice:zonal">
+                                        <Amount>3</Amount>
+                                    </FareProductPrice>
+                                </prices>
+                            </PreassignedFareProduct>
+                        </fareProducts>
+                        <priceGroups>
+                            <PriceGroup id="FR:PriceGroup:1">
+                                <members>
+                                    <FareProductPrice id="FR:FareProductPrice:day">
+                                        <Amount>5</Amount>
+                                        <Currency>chf</Currency>
+                                        <PreassignedFareProductRef ref="FR:PreassignedFareProduct:day" />
+                                    </FareProductPrice>
+                                </members>
+                            </PriceGroup>
+                        </priceGroups>
+                    </FareFrame>
+                </frames>
+            </CompositeFrame>
+        </dataObjects>
+    </PublicationDelivery>"#;
+
+    fn collections() -> Collections {
+        let line = |id: &str, code: &str| Line {
+            id: id.to_string(),
+            codes: vec![("source".into(), code.to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        Collections {
+            lines: CollectionWithId::new(vec![line("A", "FR:Line:A"), line("B", "FR:Line:B")])
+                .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn read_flat_products() {
+        let mut collections = collections();
+        read_netex_fare_frame(FARE_FRAME.as_bytes(), &mut collections).unwrap();
+
+        let ticket_ids: Vec<_> = collections.tickets.values().map(|t| &t.id).collect();
+        assert_eq!(
+            vec![
+                "FR:PreassignedFareProduct:single",
+                "FR:PreassignedFareProduct:day"
+            ],
+            ticket_ids
+        );
+        let single = collections
+            .tickets
+            .get("FR:PreassignedFareProduct:single")
+            .unwrap();
+        assert_eq!("Single ticket", single.name);
+
+        let prices = collections.ticket_prices.take();
+        assert_eq!(2, prices.len());
+        assert_eq!(dec!(1.90), prices[0].price);
+        assert_eq!("EUR", prices[0].currency);
+        assert_eq!(Date::from_ymd(2020, 1, 1), prices[0].ticket_validity_start);
+        assert_eq!(Date::from_ymd(2020, 12, 31), prices[0].ticket_validity_end);
+        assert_eq!(dec!(5), prices[1].price);
+        assert_eq!("CHF", prices[1].currency);
+
+        assert!(collections
+            .ticket_uses
+            .contains_id("FR:PreassignedFareProduct:single"));
+        let perimeters: Vec<_> = collections
+            .ticket_use_perimeters
+            .values()
+            .filter(|p| p.ticket_use_id == "FR:PreassignedFareProduct:single")
+            .map(|p| p.object_id.as_str())
+            .collect();
+        assert_eq!(vec!["A", "B"], perimeters);
+    }
+
+    #[test]
+    fn skip_existing_ticket() {
+        let mut collections = collections();
+        collections
+            .tickets
+            .push(Ticket {
+                id: "FR:PreassignedFareProduct:single".to_string(),
+                name: "Existing".to_string(),
+                comment: None,
+            })
+            .unwrap();
+        read_netex_fare_frame(FARE_FRAME.as_bytes(), &mut collections).unwrap();
+        assert_eq!(
+            "Existing",
+            collections
+                .tickets
+                .get("FR:PreassignedFareProduct:single")
+                .unwrap()
+                .name
+        );
+        assert_eq!(1, collections.ticket_prices.len());
+    }
+
+    #[test]
+    fn invalid_xml() {
+        let mut collections = collections();
+        assert!(read_netex_fare_frame("not xml".as_bytes(), &mut collections).is_err());
+    }
+}
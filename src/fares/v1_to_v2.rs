@@ -0,0 +1,499 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Conversion of fares v1 (`prices.csv`, `od_fares.csv` and `fares.csv`) into
+//! fares v2 (`tickets.txt`, `ticket_prices.txt`, `ticket_uses.txt`,
+//! `ticket_use_perimeters.txt` and `ticket_use_restrictions.txt`).
+//!
+//! This is the reverse of the conversion done when writing fares v1 from
+//! fares v2 in the NTFS writer: each ticket of fares v1 becomes both a
+//! ticket and a ticket use with the same identifier.
+
+use crate::{
+    model::Collections,
+    objects::{
+        FareV1, ObjectType, PerimeterAction, PriceV1, RestrictionType, Ticket, TicketPrice,
+        TicketUse, TicketUsePerimeter, TicketUseRestriction,
+    },
+    Result,
+};
+use anyhow::{anyhow, bail, ensure};
+use chrono::Duration;
+use rust_decimal::Decimal;
+use skip_error::skip_error_and_warn;
+use std::collections::{BTreeMap, BTreeSet};
+use typed_index_collection::{Collection, CollectionWithId};
+
+#[derive(Default)]
+struct TicketUseRules {
+    perimeters: Vec<TicketUsePerimeter>,
+    restrictions: Vec<TicketUseRestriction>,
+    max_transfers: Option<u32>,
+    boarding_time_limit: Option<u32>,
+    alighting_time_limit: Option<u32>,
+    has_purchase: bool,
+    transfer_allowed: bool,
+}
+
+fn convert_price(price_v1: &PriceV1) -> Result<TicketPrice> {
+    let price = match price_v1.currency_type.as_deref() {
+        None | Some("centime") => Decimal::from(price_v1.price) / Decimal::from(100),
+        Some("euro") => Decimal::from(price_v1.price),
+        Some(currency_type) => bail!(
+            "unsupported currency type {:?} for price {:?}",
+            currency_type,
+            price_v1
+        ),
+    };
+    Ok(TicketPrice {
+        ticket_id: price_v1.id.clone(),
+        price,
+        currency: "EUR".to_string(),
+        ticket_validity_start: price_v1.start_date,
+        // in fare v1 end_date is excluded, whereas in fare v2 ticket_validity_end is included
+        ticket_validity_end: price_v1.end_date - Duration::days(1),
+    })
+}
+
+fn strip<'a>(value: &'a str, prefix: &str) -> &'a str {
+    value.strip_prefix(prefix).unwrap_or(value)
+}
+
+fn parse_limit(value: &str) -> Option<u32> {
+    value.parse::<u32>().ok().and_then(|v| v.checked_sub(1))
+}
+
+fn set_once(field: &mut Option<u32>, value: u32) -> Result<()> {
+    match *field {
+        Some(previous) if previous != value => {
+            bail!("conflicting values {} and {}", previous, value)
+        }
+        _ => {
+            *field = Some(value);
+            Ok(())
+        }
+    }
+}
+
+fn add_perimeter(rules: &mut TicketUseRules, perimeter: TicketUsePerimeter) {
+    if !rules.perimeters.contains(&perimeter) {
+        rules.perimeters.push(perimeter);
+    }
+}
+
+/// Restriction of a start or end condition (`zone=` or `stoparea=`)
+enum Location<'a> {
+    Zone(&'a str),
+    StopArea(&'a str),
+}
+
+fn apply_purchase(rules: &mut TicketUseRules, ticket_id: &str, fare: &FareV1) -> Result<()> {
+    ensure!(
+        fare.before_change == "*",
+        "unsupported 'avant changement' {:?}",
+        fare.before_change
+    );
+    ensure!(
+        fare.global_condition.is_empty(),
+        "unsupported 'condition globale' {:?}",
+        fare.global_condition
+    );
+    let state = fare.after_change.as_str();
+    let included = if let Some(network) = state.strip_prefix("network=") {
+        Some((ObjectType::Network, strip(network, "network:")))
+    } else if let Some(line) = state.strip_prefix("line=") {
+        Some((ObjectType::Line, strip(line, "line:")))
+    } else if state == "*" {
+        None
+    } else {
+        bail!("unsupported 'après changement' {:?}", state);
+    };
+
+    let mut origin = None;
+    let mut max_transfers = None;
+    let mut boarding_time_limit = None;
+    let mut excluded_lines = Vec::new();
+    for condition in fare.start_trip.split('&').filter(|c| !c.is_empty()) {
+        if let Some(line) = condition.strip_prefix("line!=") {
+            excluded_lines.push(strip(line, "line:"));
+        } else if let Some(nb) = condition.strip_prefix("nb_changes<") {
+            max_transfers = Some(parse_limit(nb).ok_or_else(|| anyhow!("invalid {:?}", nb))?);
+        } else if let Some(duration) = condition.strip_prefix("duration<") {
+            boarding_time_limit =
+                Some(parse_limit(duration).ok_or_else(|| anyhow!("invalid {:?}", duration))?);
+        } else if let Some(zone) = condition.strip_prefix("zone=") {
+            origin = Some(Location::Zone(zone));
+        } else if let Some(stop_area) = condition.strip_prefix("stoparea=") {
+            origin = Some(Location::StopArea(strip(stop_area, "stop_area:")));
+        } else {
+            bail!("unsupported 'début trajet' condition {:?}", condition);
+        }
+    }
+    let mut destination = None;
+    let mut alighting_time_limit = None;
+    for condition in fare.end_trip.split('&').filter(|c| !c.is_empty()) {
+        if let Some(duration) = condition.strip_prefix("duration<") {
+            alighting_time_limit =
+                Some(parse_limit(duration).ok_or_else(|| anyhow!("invalid {:?}", duration))?);
+        } else if let Some(zone) = condition.strip_prefix("zone=") {
+            destination = Some(Location::Zone(zone));
+        } else if let Some(stop_area) = condition.strip_prefix("stoparea=") {
+            destination = Some(Location::StopArea(strip(stop_area, "stop_area:")));
+        } else {
+            bail!("unsupported 'fin trajet' condition {:?}", condition);
+        }
+    }
+    let restriction = match (origin, destination) {
+        (None, None) => None,
+        (Some(Location::Zone(origin)), Some(Location::Zone(destination))) => {
+            Some((RestrictionType::Zone, origin, destination))
+        }
+        (Some(Location::StopArea(origin)), Some(Location::StopArea(destination))) => {
+            Some((RestrictionType::OriginDestination, origin, destination))
+        }
+        _ => bail!("origin and destination conditions do not match"),
+    };
+
+    // everything has been parsed, the rules can now be updated
+    if let Some(value) = max_transfers {
+        set_once(&mut rules.max_transfers, value)?;
+    }
+    if let Some(value) = boarding_time_limit {
+        set_once(&mut rules.boarding_time_limit, value)?;
+    }
+    if let Some(value) = alighting_time_limit {
+        set_once(&mut rules.alighting_time_limit, value)?;
+    }
+    if let Some((object_type, object_id)) = included {
+        add_perimeter(
+            rules,
+            TicketUsePerimeter {
+                ticket_use_id: ticket_id.to_string(),
+                object_type,
+                object_id: object_id.to_string(),
+                perimeter_action: PerimeterAction::Included,
+            },
+        );
+    }
+    for line in excluded_lines {
+        add_perimeter(
+            rules,
+            TicketUsePerimeter {
+                ticket_use_id: ticket_id.to_string(),
+                object_type: ObjectType::Line,
+                object_id: line.to_string(),
+                perimeter_action: PerimeterAction::Excluded,
+            },
+        );
+    }
+    if let Some((restriction_type, origin, destination)) = restriction {
+        let restriction = TicketUseRestriction {
+            ticket_use_id: ticket_id.to_string(),
+            restriction_type,
+            use_origin: origin.to_string(),
+            use_destination: destination.to_string(),
+        };
+        if !rules.restrictions.contains(&restriction) {
+            rules.restrictions.push(restriction);
+        }
+    }
+    rules.has_purchase = true;
+    Ok(())
+}
+
+pub(crate) fn fares_v1_to_v2(collections: &mut Collections) -> Result<()> {
+    ensure!(
+        collections.tickets.is_empty() && collections.ticket_uses.is_empty(),
+        "fares v2 are already defined, fares v1 are not converted"
+    );
+
+    let mut tickets: BTreeMap<String, Ticket> = BTreeMap::new();
+    let mut ticket_prices = Vec::new();
+    for price_v1 in collections.prices_v1.values() {
+        let ticket_price = skip_error_and_warn!(convert_price(price_v1));
+        tickets
+            .entry(price_v1.id.clone())
+            .or_insert_with(|| Ticket {
+                id: price_v1.id.clone(),
+                name: price_v1.name.clone(),
+                comment: Some(price_v1.comment.clone()).filter(|c| !c.is_empty()),
+            });
+        ticket_prices.push(ticket_price);
+    }
+
+    let mut rules: BTreeMap<String, TicketUseRules> = tickets
+        .keys()
+        .map(|id| (id.clone(), TicketUseRules::default()))
+        .collect();
+    for fare in collections.fares_v1.values() {
+        if fare.ticket_id.is_empty() {
+            // a transition without purchase is only used to allow transfers
+            let ticket_id = fare
+                .start_trip
+                .split('&')
+                .find_map(|condition| condition.strip_prefix("ticket="));
+            match ticket_id.and_then(|id| rules.get_mut(id)) {
+                Some(ticket_rules) => ticket_rules.transfer_allowed = true,
+                None => tracing::warn!("fare v1 cannot be converted into fares v2: {:?}", fare),
+            }
+            continue;
+        }
+        let ticket_rules = skip_error_and_warn!(rules.get_mut(&fare.ticket_id).ok_or_else(|| {
+            anyhow!(
+                "fare v1 {:?} cannot be converted, ticket {} has no price",
+                fare,
+                fare.ticket_id
+            )
+        }));
+        if let Err(e) = apply_purchase(ticket_rules, &fare.ticket_id, fare) {
+            tracing::warn!(
+                "fare v1 cannot be converted into fares v2 ({}): {:?}",
+                e,
+                fare
+            );
+        }
+    }
+    for od_fare in collections.od_fares_v1.values() {
+        let ticket_rules = match rules.get_mut(&od_fare.ticket_id) {
+            Some(ticket_rules)
+                if od_fare.origin_mode == "stop" && od_fare.destination_mode == "stop" =>
+            {
+                ticket_rules
+            }
+            _ => {
+                tracing::warn!(
+                    "od fare v1 cannot be converted into fares v2: {:?}",
+                    od_fare
+                );
+                continue;
+            }
+        };
+        let restriction = TicketUseRestriction {
+            ticket_use_id: od_fare.ticket_id.clone(),
+            restriction_type: RestrictionType::OriginDestination,
+            use_origin: strip(&od_fare.origin_stop_area_id, "stop_area:").to_string(),
+            use_destination: strip(&od_fare.destination_stop_area_id, "stop_area:").to_string(),
+        };
+        if !ticket_rules.restrictions.contains(&restriction) {
+            ticket_rules.restrictions.push(restriction);
+        }
+    }
+
+    let mut ticket_uses = Vec::new();
+    let mut ticket_use_perimeters = Vec::new();
+    let mut ticket_use_restrictions = Vec::new();
+    let ticket_ids: BTreeSet<&String> = tickets.keys().collect();
+    for (ticket_id, ticket_rules) in rules {
+        if !ticket_ids.contains(&ticket_id) {
+            continue;
+        }
+        let max_transfers = match ticket_rules.max_transfers {
+            None if ticket_rules.has_purchase && !ticket_rules.transfer_allowed => Some(0),
+            max_transfers => max_transfers,
+        };
+        ticket_uses.push(TicketUse {
+            id: ticket_id.clone(),
+            ticket_id,
+            max_transfers,
+            boarding_time_limit: ticket_rules.boarding_time_limit,
+            alighting_time_limit: ticket_rules.alighting_time_limit,
+        });
+        ticket_use_perimeters.extend(ticket_rules.perimeters);
+        ticket_use_restrictions.extend(ticket_rules.restrictions);
+    }
+
+    collections.tickets = CollectionWithId::new(tickets.into_values().collect())?;
+    collections.ticket_prices = Collection::new(ticket_prices);
+    collections.ticket_uses = CollectionWithId::new(ticket_uses)?;
+    collections.ticket_use_perimeters = Collection::new(ticket_use_perimeters);
+    collections.ticket_use_restrictions = Collection::new(ticket_use_restrictions);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Date, OdFareV1};
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    fn price(id: &str, price: u32) -> PriceV1 {
+        PriceV1 {
+            id: id.to_string(),
+            start_date: Date::from_ymd(2019, 1, 1),
+            end_date: Date::from_ymd(2020, 1, 1),
+            price,
+            name: format!("Ticket {}", id),
+            ignored: String::new(),
+            comment: String::new(),
+            currency_type: Some("centime".to_string()),
+        }
+    }
+
+    fn fare(before: &str, after: &str, start: &str, end: &str, ticket_id: &str) -> FareV1 {
+        FareV1 {
+            before_change: before.to_string(),
+            after_change: after.to_string(),
+            start_trip: start.to_string(),
+            end_trip: end.to_string(),
+            global_condition: String::new(),
+            ticket_id: ticket_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn convert_prices_and_rules() {
+        let mut collections = Collections {
+            prices_v1: Collection::new(vec![price("T1", 150), price("T2", 90)]),
+            ..Default::default()
+        };
+        collections.fares_v1 = Collection::new(vec![
+            fare(
+                "*",
+                "network=network:N1",
+                "line!=line:L2&nb_changes<2&duration<31",
+                "duration<91",
+                "T1",
+            ),
+            fare(
+                "network=network:N1",
+                "network=network:N1",
+                "ticket=T1&line!=line:L2",
+                "",
+                "",
+            ),
+            fare(
+                "*",
+                "line=line:L1",
+                "stoparea=stop_area:SA1",
+                "stoparea=stop_area:SA2",
+                "T2",
+            ),
+        ]);
+        collections.fares_v1_to_v2().unwrap();
+
+        assert_eq!(2, collections.tickets.len());
+        let prices: Vec<_> = collections
+            .ticket_prices
+            .values()
+            .map(|p| (p.ticket_id.as_str(), p.price, p.ticket_validity_end))
+            .collect();
+        assert_eq!(
+            vec![
+                ("T1", dec!(1.5), Date::from_ymd(2019, 12, 31)),
+                ("T2", dec!(0.9), Date::from_ymd(2019, 12, 31)),
+            ],
+            prices
+        );
+        let t1 = collections.ticket_uses.get("T1").unwrap();
+        assert_eq!(
+            (Some(1), Some(30), Some(90)),
+            (
+                t1.max_transfers,
+                t1.boarding_time_limit,
+                t1.alighting_time_limit
+            )
+        );
+        let t2 = collections.ticket_uses.get("T2").unwrap();
+        assert_eq!(Some(0), t2.max_transfers);
+        let perimeters: Vec<_> = collections
+            .ticket_use_perimeters
+            .values()
+            .map(|p| {
+                (
+                    p.ticket_use_id.as_str(),
+                    p.object_id.as_str(),
+                    p.perimeter_action.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("T1", "N1", PerimeterAction::Included),
+                ("T1", "L2", PerimeterAction::Excluded),
+                ("T2", "L1", PerimeterAction::Included),
+            ],
+            perimeters
+        );
+        let restriction = collections.ticket_use_restrictions.values().next().unwrap();
+        assert_eq!(
+            TicketUseRestriction {
+                ticket_use_id: "T2".to_string(),
+                restriction_type: RestrictionType::OriginDestination,
+                use_origin: "SA1".to_string(),
+                use_destination: "SA2".to_string(),
+            },
+            *restriction
+        );
+    }
+
+    #[test]
+    fn convert_od_fares() {
+        let mut collections = Collections {
+            prices_v1: Collection::new(vec![price("T1", 150)]),
+            ..Default::default()
+        };
+        let od_fare = |origin_mode: &str| OdFareV1 {
+            origin_stop_area_id: "stop_area:SA1".to_string(),
+            origin_name: None,
+            origin_mode: origin_mode.to_string(),
+            destination_stop_area_id: "stop_area:SA2".to_string(),
+            destination_name: None,
+            destination_mode: "stop".to_string(),
+            ticket_id: "T1".to_string(),
+        };
+        collections.od_fares_v1 = Collection::new(vec![od_fare("stop"), od_fare("zone")]);
+        collections.fares_v1_to_v2().unwrap();
+
+        assert_eq!(1, collections.ticket_use_restrictions.len());
+        let restriction = collections.ticket_use_restrictions.values().next().unwrap();
+        assert_eq!("SA1", restriction.use_origin);
+        assert_eq!("SA2", restriction.use_destination);
+    }
+
+    #[test]
+    fn unconvertible_rows_are_skipped() {
+        let mut unknown_currency = price("T2", 10);
+        unknown_currency.currency_type = Some("dollar".to_string());
+        let mut collections = Collections {
+            prices_v1: Collection::new(vec![price("T1", 150), unknown_currency]),
+            ..Default::default()
+        };
+        collections.fares_v1 = Collection::new(vec![
+            fare("*", "mode=physical_mode:Bus", "", "", "T1"),
+            fare("*", "line=line:L1", "", "", "unknown"),
+        ]);
+        collections.fares_v1_to_v2().unwrap();
+
+        assert_eq!(1, collections.tickets.len());
+        assert!(collections.ticket_use_perimeters.is_empty());
+    }
+
+    #[test]
+    fn fares_v2_already_defined() {
+        let mut collections = Collections {
+            prices_v1: Collection::new(vec![price("T1", 150)]),
+            ..Default::default()
+        };
+        collections
+            .tickets
+            .push(Ticket {
+                id: "T".to_string(),
+                name: "T".to_string(),
+                comment: None,
+            })
+            .unwrap();
+        assert!(collections.fares_v1_to_v2().is_err());
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Detection of the encoding of the files, transcoded into UTF-8 while read.
+
+use super::{FileHandler, PathFileHandler, ZipHandler};
+use crate::Result;
+use std::{
+    fmt,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+// The characters of the bytes 0x80 to 0x9F in Windows-1252, the other bytes
+// being the same as in Latin-1. The 5 undefined bytes are kept as the
+// control characters of Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Encoding of a file read by a [TranscodingReader]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark
+    Utf8,
+    /// UTF-16 little endian
+    Utf16Le,
+    /// UTF-16 big endian
+    Utf16Be,
+    /// Windows-1252, a superset of Latin-1 (ISO-8859-1)
+    Windows1252,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "Windows-1252",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Reader transcoding a file into UTF-8.
+///
+/// The encoding is detected from the byte order mark, removed, or from the
+/// first bytes for UTF-16. A file without a byte order mark is read as UTF-8
+/// until the first invalid byte, the rest being read as Windows-1252. The
+/// detected encoding is logged.
+pub struct TranscodingReader<R> {
+    inner: R,
+    path: PathBuf,
+    encoding: Option<Encoding>,
+    logged: bool,
+    eof: bool,
+    // The bytes read and not decoded yet
+    input: Vec<u8>,
+    // The decoded bytes and the position of the next one to return
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> TranscodingReader<R> {
+    /// Constructs a new TranscodingReader of the file at `path`, only used
+    /// for the logs
+    pub fn new<P: AsRef<Path>>(inner: R, path: P) -> Self {
+        TranscodingReader {
+            inner,
+            path: path.as_ref().to_path_buf(),
+            encoding: None,
+            logged: false,
+            eof: false,
+            input: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn log(&mut self, encoding: Encoding) {
+        info!("Reading {:?} as {}", self.path, encoding);
+        self.logged = true;
+    }
+
+    fn fill(&mut self) -> std::io::Result<()> {
+        let start = self.input.len();
+        self.input.resize(start + CHUNK_SIZE, 0);
+        let read = self.inner.read(&mut self.input[start..])?;
+        self.input.truncate(start + read);
+        if read == 0 {
+            self.eof = true;
+        }
+        Ok(())
+    }
+
+    fn detect(&mut self) -> Encoding {
+        let (encoding, bom_len, with_bom) = match self.input.as_slice() {
+            [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3, true),
+            [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2, true),
+            [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2, true),
+            // A CSV file starts with a character of the ASCII range
+            [first, 0, ..] if *first != 0 => (Encoding::Utf16Le, 0, false),
+            [0, second, ..] if *second != 0 => (Encoding::Utf16Be, 0, false),
+            _ => (Encoding::Utf8, 0, false),
+        };
+        self.input.drain(..bom_len);
+        // A file without a byte order mark may not be UTF-8, it is logged
+        // once read
+        if with_bom || encoding != Encoding::Utf8 {
+            self.log(encoding);
+        }
+        encoding
+    }
+
+    fn decode(&mut self, encoding: Encoding) {
+        self.output.clear();
+        self.position = 0;
+        match encoding {
+            Encoding::Utf8 => match std::str::from_utf8(&self.input) {
+                Ok(_) => self.output.append(&mut self.input),
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    self.output.extend(self.input.drain(..valid));
+                    // An incomplete character at the end of the read bytes
+                    // is completed by the next ones
+                    if e.error_len().is_none() && !self.eof {
+                        return;
+                    }
+                    self.encoding = Some(Encoding::Windows1252);
+                    self.log(Encoding::Windows1252);
+                    self.decode_windows_1252();
+                }
+            },
+            Encoding::Windows1252 => self.decode_windows_1252(),
+            Encoding::Utf16Le | Encoding::Utf16Be => self.decode_utf16(encoding),
+        }
+    }
+
+    fn decode_windows_1252(&mut self) {
+        let mut buffer = [0; 4];
+        for byte in self.input.drain(..) {
+            let c = match byte {
+                0x80..=0x9F => WINDOWS_1252[usize::from(byte - 0x80)],
+                _ => char::from(byte),
+            };
+            self.output
+                .extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+        }
+    }
+
+    fn decode_utf16(&mut self, encoding: Encoding) {
+        let mut units: Vec<u16> = self
+            .input
+            .chunks_exact(2)
+            .map(|bytes| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([bytes[0], bytes[1]]),
+                _ => u16::from_be_bytes([bytes[0], bytes[1]]),
+            })
+            .collect();
+        let mut decoded_len = units.len() * 2;
+        // A high surrogate at the end of the read bytes is completed by the
+        // next ones
+        if !self.eof && matches!(units.last(), Some(0xD800..=0xDBFF)) {
+            units.pop();
+            decoded_len -= 2;
+        }
+        if self.eof {
+            decoded_len = self.input.len();
+        }
+        let mut buffer = [0; 4];
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.output
+                .extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+        }
+        self.input.drain(..decoded_len);
+    }
+}
+
+impl<R: Read> Read for TranscodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.position < self.output.len() {
+                let len = std::cmp::min(buf.len(), self.output.len() - self.position);
+                buf[..len].copy_from_slice(&self.output[self.position..self.position + len]);
+                self.position += len;
+                return Ok(len);
+            }
+            if self.eof && self.input.is_empty() {
+                if !self.logged {
+                    self.log(self.encoding.unwrap_or(Encoding::Utf8));
+                }
+                return Ok(0);
+            }
+            self.fill()?;
+            let encoding = match self.encoding {
+                Some(encoding) => encoding,
+                // The byte order mark is looked for in the first bytes
+                None if self.input.len() < 3 && !self.eof => continue,
+                None => {
+                    let encoding = self.detect();
+                    self.encoding = Some(encoding);
+                    encoding
+                }
+            };
+            self.decode(encoding);
+        }
+    }
+}
+
+/// [FileHandler] transcoding the files of another one into UTF-8 with a
+/// [TranscodingReader]
+pub struct TranscodingFileHandler<'h, H> {
+    inner: &'h mut H,
+}
+
+impl<'h, H> TranscodingFileHandler<'h, H> {
+    /// Constructs a new TranscodingFileHandler
+    pub fn new(inner: &'h mut H) -> Self {
+        TranscodingFileHandler { inner }
+    }
+}
+
+macro_rules! impl_transcoding_file_handler {
+    ($file_handler:ty, $($generics:tt)*) => {
+        impl<'a, 'h, $($generics)*> FileHandler for &'a mut TranscodingFileHandler<'h, $file_handler>
+        where
+            &'a mut $file_handler: FileHandler,
+        {
+            type Reader = TranscodingReader<<&'a mut $file_handler as FileHandler>::Reader>;
+            fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
+                let (reader, path) = self.inner.get_file_if_exists(name)?;
+                let reader = reader.map(|reader| TranscodingReader::new(reader, &path));
+                Ok((reader, path))
+            }
+            fn source_name(&self) -> &str {
+                self.inner.source_name()
+            }
+        }
+    };
+}
+
+// Implemented for each file handler, a generic implementation making the
+// resolution of the `FileHandler` bounds recursive
+impl_transcoding_file_handler!(PathFileHandler<P>, P: AsRef<Path>);
+impl_transcoding_file_handler!(ZipHandler<R>, R: Seek + Read);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Reads byte by byte, to check the characters split between reads
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn transcode(bytes: &[u8]) -> (String, Option<Encoding>) {
+        let mut reader = TranscodingReader::new(bytes, "stops.txt");
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        let mut byte_reader = TranscodingReader::new(ByteReader(bytes), "stops.txt");
+        let mut byte_content = String::new();
+        byte_reader.read_to_string(&mut byte_content).unwrap();
+        assert_eq!(content, byte_content);
+        assert_eq!(reader.encoding, byte_reader.encoding);
+        (content, reader.encoding)
+    }
+
+    #[test]
+    fn utf8() {
+        let expected = (
+            "stop_name\nGare Saint-Lazare – Parvis\n".to_string(),
+            Some(Encoding::Utf8),
+        );
+        assert_eq!(
+            expected,
+            transcode("stop_name\nGare Saint-Lazare – Parvis\n".as_bytes())
+        );
+        let with_bom = [
+            &[0xEF, 0xBB, 0xBF],
+            "stop_name\nGare Saint-Lazare – Parvis\n".as_bytes(),
+        ]
+        .concat();
+        assert_eq!(expected, transcode(&with_bom));
+        assert_eq!((String::new(), Some(Encoding::Utf8)), transcode(&[]));
+    }
+
+    #[test]
+    fn utf16() {
+        let content = "stop_name\nHôtel de Ville 𝄞\n";
+        let mut le = vec![0xFF, 0xFE];
+        let mut be = vec![0xFE, 0xFF];
+        for unit in content.encode_utf16() {
+            le.extend_from_slice(&unit.to_le_bytes());
+            be.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!(
+            (content.to_string(), Some(Encoding::Utf16Le)),
+            transcode(&le)
+        );
+        assert_eq!(
+            (content.to_string(), Some(Encoding::Utf16Be)),
+            transcode(&be)
+        );
+        // Without a byte order mark
+        assert_eq!(
+            (content.to_string(), Some(Encoding::Utf16Le)),
+            transcode(&le[2..])
+        );
+    }
+
+    #[test]
+    fn windows_1252() {
+        let bytes = b"stop_name\nH\xf4tel de Ville \x80 \x93Nord\x94\n";
+        assert_eq!(
+            (
+                "stop_name\nHôtel de Ville € “Nord”\n".to_string(),
+                Some(Encoding::Windows1252)
+            ),
+            transcode(bytes)
+        );
+        // An incomplete UTF-8 character at the end of the file
+        assert_eq!(
+            ("Caf\u{c3}".to_string(), Some(Encoding::Windows1252)),
+            transcode(b"Caf\xc3")
+        );
+    }
+}
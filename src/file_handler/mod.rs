@@ -1,12 +1,21 @@
 //! Provides an easy way to access directory or flat zip archive
-use crate::Result;
-use anyhow::{anyhow, Context};
+use crate::{utils::parallel_map, Result};
+use anyhow::{anyhow, bail, Context};
 use std::{
     collections::BTreeMap,
     fs::File,
-    io::{Read, Seek},
+    io::{Cursor, Read, Seek, Write},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
 };
+#[cfg(feature = "mmap")]
+use tracing::debug;
+use zip::CompressionMethod;
+
+mod encoding;
+pub use encoding::TranscodingFileHandler;
+#[cfg(feature = "parser")]
+pub use encoding::{Encoding, TranscodingReader};
 
 /// Allows files in a directory or ZipArchive to be read either
 pub trait FileHandler
@@ -30,31 +39,88 @@ where
 
     /// Allows to have nicer error messages
     fn source_name(&self) -> &str;
+
+    /// The remaining content of a file already in memory (a memory-mapped
+    /// file), for the parsers to read it from this slice instead of copying
+    /// it. `None` by default.
+    fn as_slice(_reader: &Self::Reader) -> Option<&[u8]> {
+        None
+    }
 }
 
 /// PathFileHandler is used to read files for a directory
 pub struct PathFileHandler<P: AsRef<Path>> {
     base_path: P,
+    #[cfg(feature = "mmap")]
+    mmap: bool,
 }
 
 impl<P: AsRef<Path>> PathFileHandler<P> {
     /// Constructs a new PathFileHandler
     pub fn new(path: P) -> Self {
-        PathFileHandler { base_path: path }
+        PathFileHandler {
+            base_path: path,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+        }
+    }
+
+    /// Constructs a new PathFileHandler memory-mapping the files instead of
+    /// reading them. A file that can't be mapped is read as usual.
+    ///
+    /// The files must not be modified while they are read.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(path: P) -> Self {
+        PathFileHandler {
+            base_path: path,
+            mmap: true,
+        }
+    }
+}
+
+/// A file of a [PathFileHandler]
+pub enum PathReader {
+    /// File read as usual
+    File(File),
+    /// Memory-mapped file
+    #[cfg(feature = "mmap")]
+    Mmap(Cursor<memmap2::Mmap>),
+}
+
+impl Read for PathReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            PathReader::File(file) => file.read(buf),
+            #[cfg(feature = "mmap")]
+            PathReader::Mmap(mmap) => mmap.read(buf),
+        }
     }
 }
 
 impl<'a, P: AsRef<Path>> FileHandler for &'a mut PathFileHandler<P> {
-    type Reader = File;
+    type Reader = PathReader;
     fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
         let f = self.base_path.as_ref().join(name);
-        if f.exists() {
-            Ok((
-                Some(File::open(&f).with_context(|| format!("Error reading {:?}", &f))?),
-                f,
-            ))
-        } else {
-            Ok((None, f))
+        if !f.exists() {
+            return Ok((None, f));
+        }
+        let file = File::open(&f).with_context(|| format!("Error reading {:?}", &f))?;
+        #[cfg(feature = "mmap")]
+        if self.mmap {
+            // Safety: the files of the input are not expected to be
+            // modified while being read
+            match unsafe { memmap2::Mmap::map(&file) } {
+                Ok(mmap) => return Ok((Some(PathReader::Mmap(Cursor::new(mmap))), f)),
+                Err(e) => debug!("{:?} can't be memory-mapped, reading it: {}", f, e),
+            }
+        }
+        Ok((Some(PathReader::File(file)), f))
+    }
+    #[cfg(feature = "mmap")]
+    fn as_slice(reader: &PathReader) -> Option<&[u8]> {
+        match reader {
+            PathReader::File(_) => None,
+            PathReader::Mmap(mmap) => Some(&mmap.get_ref()[mmap.position() as usize..]),
         }
     }
     fn source_name(&self) -> &str {
@@ -77,6 +143,8 @@ pub struct ZipHandler<R: Seek + Read> {
     archive: zip::ZipArchive<R>,
     archive_path: PathBuf,
     index_by_name: BTreeMap<String, usize>,
+    // Entries decompressed by `decompress_in_memory`, by index
+    buffers: BTreeMap<usize, Vec<u8>>,
 }
 
 /// ZipHandler is used to read files from an archive
@@ -91,8 +159,81 @@ where
             index_by_name: Self::files_by_name(&mut archive),
             archive,
             archive_path: path.as_ref().to_path_buf(),
+            buffers: BTreeMap::new(),
         })
     }
+
+    /// Decompress the files of the archive in parallel, keeping them in
+    /// memory until they are read.
+    ///
+    /// The files are taken in the order of their names as long as the
+    /// buffers needed to decompress them fit in `memory_budget` bytes: both
+    /// the compressed and the uncompressed content of a deflated file, the
+    /// content of a stored one. The other files, and the ones compressed
+    /// with another method than deflate, are still decompressed while being
+    /// read. The files are decompressed by a bounded number of threads.
+    pub fn decompress_in_memory(&mut self, memory_budget: usize) -> Result<()> {
+        let archive_path = &self.archive_path;
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+        for (name, &index) in &self.index_by_name {
+            let mut file = self.archive.by_index_raw(index)?;
+            let size = file.size() as usize;
+            let compressed_size = file.compressed_size() as usize;
+            let footprint = match file.compression() {
+                CompressionMethod::Stored => compressed_size,
+                CompressionMethod::Deflated => compressed_size.saturating_add(size),
+                _ => continue,
+            };
+            if footprint > memory_budget - total_size {
+                continue;
+            }
+            total_size += footprint;
+            // Both sizes come from the archive, the buffers being preallocated
+            // only once they fit in the budget
+            let mut compressed = Vec::with_capacity(compressed_size);
+            file.read_to_end(&mut compressed)
+                .with_context(|| format!("Error reading {:?}", archive_path.join(name)))?;
+            entries.push((
+                name,
+                index,
+                file.compression(),
+                size,
+                file.crc32(),
+                compressed,
+            ));
+        }
+
+        let results = parallel_map(
+            entries,
+            |(name, index, compression, size, crc32, compressed)| -> Result<(usize, Vec<u8>)> {
+                let buffer = if compression == CompressionMethod::Deflated {
+                    let mut buffer = Vec::with_capacity(size);
+                    // Not to decompress more than announced by the archive
+                    flate2::read::DeflateDecoder::new(compressed.as_slice())
+                        .take(size as u64 + 1)
+                        .read_to_end(&mut buffer)
+                        .with_context(|| {
+                            format!("Error decompressing {:?}", archive_path.join(name))
+                        })?;
+                    buffer
+                } else {
+                    compressed
+                };
+                if buffer.len() != size || crc32fast::hash(&buffer) != crc32 {
+                    bail!("Invalid checksum for {:?}", archive_path.join(name));
+                }
+                Ok((index, buffer))
+            },
+        );
+        self.buffers = results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| Err(anyhow!("the decompressing thread panicked")))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        Ok(())
+    }
     fn files_by_name(archive: &mut zip::ZipArchive<R>) -> BTreeMap<String, usize> {
         (0..archive.len())
             .filter_map(|i| {
@@ -106,16 +247,41 @@ where
     }
 }
 
+/// A file of a [ZipHandler]
+pub enum ZipReader<'a> {
+    /// File decompressed by [ZipHandler::decompress_in_memory]
+    Buffer(Cursor<Vec<u8>>),
+    /// File decompressed while being read
+    Stream(Box<zip::read::ZipFile<'a>>),
+}
+
+impl Read for ZipReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ZipReader::Buffer(buffer) => buffer.read(buf),
+            ZipReader::Stream(file) => file.read(buf),
+        }
+    }
+}
+
 impl<'a, R> FileHandler for &'a mut ZipHandler<R>
 where
     R: Seek + Read,
 {
-    type Reader = zip::read::ZipFile<'a>;
+    type Reader = ZipReader<'a>;
     fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
         let p = self.archive_path.join(name);
         match self.index_by_name.get(name) {
             None => Ok((None, p)),
-            Some(i) => Ok((Some(self.archive.by_index(*i)?), p)),
+            // A file decompressed in advance is freed once read, it would
+            // be decompressed again if read another time
+            Some(i) => match self.buffers.remove(i) {
+                Some(buffer) => Ok((Some(ZipReader::Buffer(Cursor::new(buffer))), p)),
+                None => Ok((
+                    Some(ZipReader::Stream(Box::new(self.archive.by_index(*i)?))),
+                    p,
+                )),
+            },
         }
     }
     fn source_name(&self) -> &str {
@@ -125,6 +291,115 @@ where
     }
 }
 
+/// Allows files to be written in a directory or in memory
+///
+/// The files being written in parallel, a file writer is shared between
+/// threads.
+pub trait FileWriter: Sync {
+    /// Writer
+    type Writer: Write;
+
+    /// Create a file, replacing it if it exists
+    fn create_file(&self, name: &str) -> Result<(Self::Writer, PathBuf)>;
+}
+
+/// PathFileWriter is used to write files in a directory
+pub struct PathFileWriter<P: AsRef<Path>> {
+    base_path: P,
+}
+
+impl<P: AsRef<Path>> PathFileWriter<P> {
+    /// Constructs a new PathFileWriter, the directory being expected to exist
+    pub fn new(path: P) -> Self {
+        PathFileWriter { base_path: path }
+    }
+}
+
+impl<P: AsRef<Path> + Sync> FileWriter for PathFileWriter<P> {
+    type Writer = File;
+    fn create_file(&self, name: &str) -> Result<(Self::Writer, PathBuf)> {
+        let f = self.base_path.as_ref().join(name);
+        let file = File::create(&f).with_context(|| format!("Error writing {:?}", &f))?;
+        Ok((file, f))
+    }
+}
+
+/// A file of a [MemoryFileWriter]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFile(Arc<Mutex<Vec<u8>>>);
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// MemoryFileWriter is used to write files in memory, for example to build a
+/// ZIP archive without any temporary file
+#[derive(Debug, Default)]
+pub struct MemoryFileWriter {
+    files: Mutex<BTreeMap<String, MemoryFile>>,
+}
+
+impl MemoryFileWriter {
+    /// Constructs a new MemoryFileWriter without any file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The content of the files written, by name
+    pub fn into_files(self) -> BTreeMap<String, Vec<u8>> {
+        self.files
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .map(|(name, file)| {
+                let content = match Arc::try_unwrap(file.0) {
+                    Ok(content) => content.into_inner().unwrap_or_else(PoisonError::into_inner),
+                    // A writer of the file is still alive
+                    Err(content) => content
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clone(),
+                };
+                (name, content)
+            })
+            .collect()
+    }
+
+    /// Writes the files in a ZIP archive, sorted by name, and returns the
+    /// writer of the archive
+    pub fn write_zip<W: Write + Seek>(self, writer: W) -> Result<W> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options =
+            zip::write::FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, content) in self.into_files() {
+            zip.start_file(name, options)?;
+            zip.write_all(&content)?;
+        }
+        Ok(zip.finish()?)
+    }
+}
+
+impl FileWriter for MemoryFileWriter {
+    type Writer = MemoryFile;
+    fn create_file(&self, name: &str) -> Result<(Self::Writer, PathBuf)> {
+        let file = MemoryFile::default();
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), file.clone());
+        Ok((file, PathBuf::from(name)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,6 +421,51 @@ mod tests {
         assert_eq!("world\n", world_str);
     }
 
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn path_file_handler_with_mmap() {
+        let mut file_handler =
+            PathFileHandler::with_mmap(PathBuf::from("tests/fixtures/file-handler"));
+
+        let (mut hello, _) = file_handler.get_file("hello.txt").unwrap();
+        assert_eq!(
+            Some(&b"hello\n"[..]),
+            <&mut PathFileHandler<PathBuf>>::as_slice(&hello)
+        );
+        let mut hello_str = String::new();
+        hello.read_to_string(&mut hello_str).unwrap();
+        assert_eq!("hello\n", hello_str);
+        assert_eq!(
+            Some(&b""[..]),
+            <&mut PathFileHandler<PathBuf>>::as_slice(&hello)
+        );
+        // A directory can't be memory-mapped, it is opened as usual
+        let (folder, _) = file_handler.get_file("folder").unwrap();
+        assert!(matches!(folder, PathReader::File(_)));
+        assert_eq!(None, <&mut PathFileHandler<PathBuf>>::as_slice(&folder));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn path_file_handler_slice_of_the_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("stops.txt"), "stop_id\nA\n").unwrap();
+        let mut file_handler = PathFileHandler::with_mmap(dir.path());
+        let (stops, _) = file_handler.get_file("stops.txt").unwrap();
+        // The file is modified in place once mapped: the slice shows the
+        // new content, being the mapping and not a copy of the file
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("stops.txt"))
+            .unwrap();
+        file.write_all(b"stop_id\nB\n").unwrap();
+        file.sync_all().unwrap();
+        assert_eq!(
+            Some(&b"stop_id\nB\n"[..]),
+            <&mut PathFileHandler<&Path>>::as_slice(&stops)
+        );
+    }
+
     #[test]
     fn zip_file_handler() {
         let p = "tests/fixtures/file-handler.zip";
@@ -166,4 +486,74 @@ mod tests {
             assert_eq!("world\n", world_str);
         }
     }
+
+    #[test]
+    fn zip_file_handler_in_memory() {
+        let p = "tests/fixtures/file-handler.zip";
+        let reader = File::open(p).unwrap();
+        let mut file_handler = ZipHandler::new(reader, p).unwrap();
+        // Only "folder" and "hello.txt" fit, "world.txt" is streamed
+        file_handler.decompress_in_memory(8).unwrap();
+        assert_eq!(2, file_handler.buffers.len());
+
+        for (name, content) in &[("hello.txt", "hello\n"), ("world.txt", "world\n")] {
+            let (mut file, _) = file_handler.get_file(name).unwrap();
+            let mut file_str = String::new();
+            file.read_to_string(&mut file_str).unwrap();
+            assert_eq!(*content, file_str);
+        }
+        // A file read once is read again from the archive
+        let (hello, _) = file_handler.get_file("hello.txt").unwrap();
+        assert!(matches!(hello, ZipReader::Stream(_)));
+    }
+
+    #[test]
+    fn deflated_file_in_memory() {
+        let file_writer = MemoryFileWriter::new();
+        file_writer
+            .create_file("stops.txt")
+            .unwrap()
+            .0
+            .write_all(&[b'a'; 1000])
+            .unwrap();
+        let zip = file_writer.write_zip(Cursor::new(Vec::new())).unwrap();
+        let mut file_handler = ZipHandler::new(zip, "memory.zip").unwrap();
+        let compressed_size = file_handler
+            .archive
+            .by_index_raw(0)
+            .unwrap()
+            .compressed_size();
+        let footprint = 1000 + compressed_size as usize;
+
+        // The compressed content doesn't fit
+        file_handler.decompress_in_memory(footprint - 1).unwrap();
+        assert!(file_handler.buffers.is_empty());
+        file_handler.decompress_in_memory(footprint).unwrap();
+        assert_eq!(vec![b'a'; 1000], file_handler.buffers[&0]);
+    }
+
+    #[test]
+    fn memory_file_writer() {
+        let file_writer = MemoryFileWriter::new();
+        {
+            let (mut world, _) = file_writer.create_file("world.txt").unwrap();
+            world.write_all(b"world\n").unwrap();
+            let (mut hello, _) = file_writer.create_file("hello.txt").unwrap();
+            hello.write_all(b"hello\n").unwrap();
+        }
+        let zip = file_writer.write_zip(Cursor::new(Vec::new())).unwrap();
+
+        let mut file_handler = ZipHandler::new(zip, "memory.zip").unwrap();
+        for (name, content) in &[("hello.txt", "hello\n"), ("world.txt", "world\n")] {
+            let (mut file, _) = file_handler.get_file(name).unwrap();
+            let mut file_str = String::new();
+            file.read_to_string(&mut file_str).unwrap();
+            assert_eq!(*content, file_str);
+        }
+        // The files are sorted by name
+        assert_eq!(
+            "hello.txt",
+            file_handler.archive.by_index(0).unwrap().name()
+        );
+    }
 }
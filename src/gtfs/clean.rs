@@ -0,0 +1,382 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Cleaning of a GTFS, written back as a GTFS with its original identifiers.
+//!
+//! The GTFS is read without prefix, each GTFS route being a line, and the
+//! calendars are not merged unless [Enhancer::DeduplicateCalendars] is
+//! applied. The stop areas generated for the stops without parent station
+//! are not written, so the routes, trips, stops and services keep their
+//! identifiers. The trips of `frequencies.txt` are however written as one
+//! trip for each departure (see the [GTFS to NTFS
+//! specifications](https://github.com/hove-io/transit_model/blob/master/documentation/gtfs_to_ntfs_specs.md#reading-frequenciestxt)).
+//!
+//! Building the model always removes the objects that are not referenced,
+//! [Enhancer::Sanitize] only reporting what it removed at its position in
+//! the pipeline.
+
+use super::{write_to_zip_with_configuration, write_with_configuration, Configuration, Reader};
+use crate::{
+    merge::object_counts,
+    model::{Collections, Model},
+    objects::{Date, Rgb},
+    timing, Result,
+};
+use anyhow::bail;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
+use tracing::info;
+use typed_index_collection::CollectionWithId;
+
+const WHITE: Rgb = Rgb {
+    red: 0xFF,
+    green: 0xFF,
+    blue: 0xFF,
+};
+const BLACK: Rgb = Rgb {
+    red: 0x00,
+    green: 0x00,
+    blue: 0x00,
+};
+
+/// A step of the cleaning of a GTFS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Enhancer {
+    /// Merge the calendars with the same dates, the trips using the first
+    /// of them
+    DeduplicateCalendars,
+    /// Give the routes without color the default white color of the GTFS,
+    /// and the routes without text color, or with a text color equal to
+    /// their color, a black or white text color readable on their color
+    FillColors,
+    /// Remove the services without any date from the current date, and
+    /// their trips
+    DropDeadServices,
+    /// Remove the objects that are not referenced
+    Sanitize,
+}
+
+impl FromStr for Enhancer {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deduplicate_calendars" => Ok(Enhancer::DeduplicateCalendars),
+            "fill_colors" => Ok(Enhancer::FillColors),
+            "drop_dead_services" => Ok(Enhancer::DropDeadServices),
+            "sanitize" => Ok(Enhancer::Sanitize),
+            _ => bail!(
+                "unknown enhancer {:?}, expected 'deduplicate_calendars', 'fill_colors', \
+                 'drop_dead_services' or 'sanitize'",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Enhancer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Enhancer::DeduplicateCalendars => write!(f, "deduplicate_calendars"),
+            Enhancer::FillColors => write!(f, "fill_colors"),
+            Enhancer::DropDeadServices => write!(f, "drop_dead_services"),
+            Enhancer::Sanitize => write!(f, "sanitize"),
+        }
+    }
+}
+
+/// Parameters of [clean]
+#[derive(Debug, Clone)]
+pub struct CleanOptions {
+    /// The enhancers to apply, in this order
+    pub enhancers: Vec<Enhancer>,
+    /// The services without any date from this date are dead
+    pub current_date: Date,
+    /// Write the route types as
+    /// [extended route types](https://developers.google.com/transit/gtfs/reference/extended-route-types)
+    pub extend_route_type: bool,
+}
+
+/// What an enhancer changed
+#[derive(Debug, Serialize)]
+pub struct EnhancerReport {
+    /// The enhancer applied
+    pub enhancer: Enhancer,
+    /// Number of objects modified or removed by the enhancer
+    pub changed_objects: usize,
+}
+
+/// Report of [clean]
+#[derive(Debug, Serialize)]
+pub struct CleanReport {
+    /// The enhancers applied, in this order
+    pub enhancers: Vec<EnhancerReport>,
+    /// Number of objects read by type
+    pub input_object_counts: BTreeMap<&'static str, usize>,
+    /// Number of objects written by type
+    pub output_object_counts: BTreeMap<&'static str, usize>,
+}
+
+// A black text on the light colors, a white text on the dark ones
+fn readable_text_color(color: &Rgb) -> Rgb {
+    let brightness =
+        (299 * u32::from(color.red) + 587 * u32::from(color.green) + 114 * u32::from(color.blue))
+            / 1000;
+    if brightness >= 128 {
+        BLACK
+    } else {
+        WHITE
+    }
+}
+
+fn fill_colors(collections: &mut Collections) -> Result<usize> {
+    let mut changed_lines = 0;
+    let mut lines = collections.lines.take();
+    for line in &mut lines {
+        let color = line.color.clone().unwrap_or(WHITE);
+        let text_color = match &line.text_color {
+            Some(text_color) if *text_color != color => text_color.clone(),
+            _ => readable_text_color(&color),
+        };
+        if line.color.as_ref() != Some(&color) || line.text_color.as_ref() != Some(&text_color) {
+            line.color = Some(color);
+            line.text_color = Some(text_color);
+            changed_lines += 1;
+        }
+    }
+    collections.lines = CollectionWithId::new(lines)?;
+    Ok(changed_lines)
+}
+
+// The dates of the dead services are removed, the empty calendars and their
+// trips being removed with the objects that are not referenced
+fn drop_dead_services(collections: &mut Collections, current_date: Date) -> Result<usize> {
+    let mut dead_services = 0;
+    let mut calendars = collections.calendars.take();
+    for calendar in &mut calendars {
+        if !calendar.dates.is_empty() && calendar.dates.range(current_date..).next().is_none() {
+            calendar.dates.clear();
+            dead_services += 1;
+        }
+    }
+    collections.calendars = CollectionWithId::new(calendars)?;
+    Ok(dead_services)
+}
+
+/// Applies the `enhancers` to the collections in this order, the dead
+/// services being the ones without any date from `current_date`.
+pub fn apply_enhancers(
+    collections: &mut Collections,
+    enhancers: &[Enhancer],
+    current_date: Date,
+) -> Result<Vec<EnhancerReport>> {
+    let mut reports = Vec::with_capacity(enhancers.len());
+    for &enhancer in enhancers {
+        info!("Applying {}", enhancer);
+        let changed_objects = timing::enhancer(&enhancer.to_string(), || -> Result<usize> {
+            Ok(match enhancer {
+                Enhancer::DeduplicateCalendars => {
+                    let calendars = collections.calendars.len();
+                    collections.calendar_deduplication();
+                    calendars - collections.calendars.len()
+                }
+                Enhancer::FillColors => fill_colors(collections)?,
+                Enhancer::DropDeadServices => drop_dead_services(collections, current_date)?,
+                Enhancer::Sanitize => {
+                    let objects: usize = object_counts(collections).values().sum();
+                    collections.sanitize()?;
+                    objects - object_counts(collections).values().sum::<usize>()
+                }
+            })
+        })?;
+        reports.push(EnhancerReport {
+            enhancer,
+            changed_objects,
+        });
+    }
+    Ok(reports)
+}
+
+/// Reads the GTFS `input` (directory or ZIP archive), applies the enhancers
+/// of the options with [apply_enhancers] and writes the result in the GTFS
+/// `output`, a ZIP archive if it has the `.zip` extension or else a
+/// directory, with the original identifiers.
+///
+/// Returns the report of the enhancers applied.
+pub fn clean<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    options: &CleanOptions,
+) -> Result<CleanReport> {
+    let configuration = Configuration {
+        read_as_line: true,
+        skip_calendar_deduplication: true,
+        ..Default::default()
+    };
+    let mut collections = Reader::new(configuration).parse_collections(input)?;
+    let input_object_counts = object_counts(&collections);
+    let enhancers = apply_enhancers(&mut collections, &options.enhancers, options.current_date)?;
+    let model = Model::new(collections)?;
+    let output_object_counts = object_counts(&model);
+
+    let write_configuration = super::WriteConfiguration {
+        extend_route_type: options.extend_route_type,
+        skip_generated_stop_areas: true,
+        ..Default::default()
+    };
+    let output = output.as_ref();
+    match output.extension() {
+        Some(ext) if ext == "zip" => {
+            write_to_zip_with_configuration(model, output, &write_configuration)?
+        }
+        _ => write_with_configuration(model, output, &write_configuration)?,
+    }
+    Ok(CleanReport {
+        enhancers,
+        input_object_counts,
+        output_object_counts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Calendar;
+    use pretty_assertions::assert_eq;
+
+    // The trips of the fixture run on the weekdays of 2018
+    fn collections() -> Collections {
+        crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections()
+    }
+
+    #[test]
+    fn deduplicate_calendars() {
+        let mut collections = collections();
+        let week = collections.calendars.get("Week").unwrap().dates.clone();
+        collections
+            .calendars
+            .push(Calendar {
+                id: "Week_copy".to_string(),
+                dates: week,
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("M1F1")
+            .unwrap()
+            .service_id = "Week_copy".to_string();
+        let calendars_used = |collections: &Collections| {
+            collections
+                .vehicle_journeys
+                .values()
+                .filter(|vj| vj.service_id.starts_with("Week"))
+                .count()
+        };
+        let before = calendars_used(&collections);
+
+        let reports = apply_enhancers(
+            &mut collections,
+            &[Enhancer::DeduplicateCalendars],
+            Date::from_ymd(2018, 7, 1),
+        )
+        .unwrap();
+
+        assert_eq!(1, reports[0].changed_objects);
+        assert_eq!(1, collections.calendars.len());
+        assert_eq!(before, calendars_used(&collections));
+    }
+
+    #[test]
+    fn fill_colors() {
+        let mut collections = collections();
+        let navy = Rgb {
+            red: 0x00,
+            green: 0x00,
+            blue: 0x80,
+        };
+        collections.lines.get_mut("RERA").unwrap().color = Some(navy.clone());
+        collections.lines.get_mut("RERA").unwrap().text_color = Some(navy.clone());
+
+        let reports = apply_enhancers(
+            &mut collections,
+            &[Enhancer::FillColors],
+            Date::from_ymd(2018, 7, 1),
+        )
+        .unwrap();
+
+        assert_eq!(3, reports[0].changed_objects);
+        let line = collections.lines.get("M1").unwrap();
+        assert_eq!(Some(WHITE), line.color);
+        assert_eq!(Some(BLACK), line.text_color);
+        let line = collections.lines.get("RERA").unwrap();
+        assert_eq!(Some(navy), line.color);
+        assert_eq!(Some(WHITE), line.text_color);
+        // Nothing left to fill
+        let reports = apply_enhancers(
+            &mut collections,
+            &[Enhancer::FillColors],
+            Date::from_ymd(2018, 7, 1),
+        )
+        .unwrap();
+        assert_eq!(0, reports[0].changed_objects);
+    }
+
+    #[test]
+    fn drop_dead_services_then_sanitize() {
+        let mut collections = collections();
+        collections
+            .calendars
+            .push(Calendar {
+                id: "past".to_string(),
+                dates: std::iter::once(Date::from_ymd(2018, 6, 29)).collect(),
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("M1F1")
+            .unwrap()
+            .service_id = "past".to_string();
+
+        let reports = apply_enhancers(
+            &mut collections,
+            &[Enhancer::DropDeadServices, Enhancer::Sanitize],
+            Date::from_ymd(2018, 7, 1),
+        )
+        .unwrap();
+
+        assert_eq!(Enhancer::DropDeadServices, reports[0].enhancer);
+        assert_eq!(1, reports[0].changed_objects);
+        // The calendar, the trip, its 4 stop times and its route, the only
+        // one of the trip
+        assert_eq!(Enhancer::Sanitize, reports[1].enhancer);
+        assert_eq!(1 + 1 + 4 + 1, reports[1].changed_objects);
+        assert!(!collections.calendars.contains_id("past"));
+        assert!(!collections.vehicle_journeys.contains_id("M1F1"));
+    }
+
+    #[test]
+    fn parse_enhancers() {
+        for enhancer in [
+            Enhancer::DeduplicateCalendars,
+            Enhancer::FillColors,
+            Enhancer::DropDeadServices,
+            Enhancer::Sanitize,
+        ] {
+            assert_eq!(enhancer, enhancer.to_string().parse().unwrap());
+        }
+        assert!("dedup".parse::<Enhancer>().is_err());
+    }
+}
@@ -0,0 +1,858 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reading of the GTFS fares, [GTFS-Fares v2] when `fare_products.txt` is
+//! present, `fare_attributes.txt` and `fare_rules.txt` otherwise, into the
+//! NTFS fares v2 collections.
+//!
+//! [GTFS-Fares v2]: https://gtfs.org/reference/static#fare_productstxt
+
+use super::{AreaStop, FareAttribute, FareLegRule, FareProduct, FareRule, RouteNetwork};
+use crate::{
+    file_handler::FileHandler,
+    model::Collections,
+    objects::{
+        Date, ObjectType, PerimeterAction, RestrictionType, Ticket, TicketPrice, TicketUse,
+        TicketUsePerimeter, TicketUseRestriction,
+    },
+    parser::read_objects_loose,
+    Result,
+};
+use anyhow::{anyhow, bail};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use skip_error::skip_error_and_warn;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use tracing::{info, warn};
+use typed_index_collection::{Collection, CollectionWithId};
+
+// The `network_id` of a route in `routes.txt`, an alternative to
+// `route_networks.txt`
+#[derive(Deserialize)]
+struct RouteNetworkId {
+    route_id: String,
+    network_id: Option<String>,
+}
+
+// The fares being read, assigned to the collections at the end
+#[derive(Default)]
+struct Fares {
+    tickets: Vec<Ticket>,
+    ticket_prices: Vec<TicketPrice>,
+    ticket_uses: Vec<TicketUse>,
+    ticket_use_perimeters: Vec<TicketUsePerimeter>,
+    ticket_use_restrictions: Vec<TicketUseRestriction>,
+}
+
+impl Fares {
+    fn push_perimeters(
+        &mut self,
+        ticket_use_id: &str,
+        object_type: ObjectType,
+        object_ids: impl IntoIterator<Item = String>,
+    ) {
+        self.ticket_use_perimeters
+            .extend(object_ids.into_iter().map(|object_id| TicketUsePerimeter {
+                ticket_use_id: ticket_use_id.to_string(),
+                object_type: object_type.clone(),
+                object_id,
+                perimeter_action: PerimeterAction::Included,
+            }));
+    }
+
+    fn push_restrictions(
+        &mut self,
+        ticket_use_id: &str,
+        restrictions: impl IntoIterator<Item = (RestrictionType, String, String)>,
+    ) {
+        self.ticket_use_restrictions
+            .extend(restrictions.into_iter().map(
+                |(restriction_type, use_origin, use_destination)| TicketUseRestriction {
+                    ticket_use_id: ticket_use_id.to_string(),
+                    restriction_type,
+                    use_origin,
+                    use_destination,
+                },
+            ));
+    }
+
+    fn into_collections(self, collections: &mut Collections) -> Result<()> {
+        collections.tickets = CollectionWithId::new(self.tickets)?;
+        collections.ticket_prices = Collection::new(self.ticket_prices);
+        collections.ticket_uses = CollectionWithId::new(self.ticket_uses)?;
+        collections.ticket_use_perimeters = Collection::new(self.ticket_use_perimeters);
+        collections.ticket_use_restrictions = Collection::new(self.ticket_use_restrictions);
+        Ok(())
+    }
+}
+
+// What the fares reference in the collections already read
+struct Context<'c> {
+    // The line of each GTFS route, the `route_id` being the `source` code of
+    // the routes
+    route_lines: HashMap<&'c str, &'c str>,
+    fare_zones: HashSet<&'c str>,
+    network_ids: Vec<String>,
+    // The prices are valid over the whole validity period of the calendars
+    validity_period: (Date, Date),
+}
+
+impl<'c> Context<'c> {
+    fn new(collections: &'c Collections) -> Option<Self> {
+        let route_lines = collections
+            .routes
+            .values()
+            .flat_map(|route| {
+                route
+                    .codes
+                    .iter()
+                    .filter(|(system, _)| system == "source")
+                    .map(move |(_, route_id)| (route_id.as_str(), route.line_id.as_str()))
+            })
+            .collect();
+        let fare_zones = collections
+            .stop_points
+            .values()
+            .filter_map(|stop_point| stop_point.fare_zone_id.as_deref())
+            .collect();
+        let network_ids = collections
+            .networks
+            .values()
+            .map(|network| network.id.clone())
+            .collect();
+        let dates = collections
+            .calendars
+            .values()
+            .flat_map(|calendar| calendar.dates.iter());
+        let validity_period = (*dates.clone().min()?, *dates.max()?);
+        Some(Context {
+            route_lines,
+            fare_zones,
+            network_ids,
+            validity_period,
+        })
+    }
+
+    fn ticket_price(&self, ticket_id: &str, amount: &str, currency: String) -> Result<TicketPrice> {
+        let price = amount
+            .parse::<Decimal>()
+            .ok()
+            .filter(|price| price.is_sign_positive())
+            .ok_or_else(|| anyhow!("invalid price {:?} of fare {}", amount, ticket_id))?;
+        Ok(TicketPrice {
+            ticket_id: ticket_id.to_string(),
+            price,
+            currency,
+            ticket_validity_start: self.validity_period.0,
+            ticket_validity_end: self.validity_period.1,
+        })
+    }
+}
+
+/// Reading of the fares, see the [module documentation](self).
+///
+/// The fares referencing objects unknown or that can't be expressed in the
+/// NTFS are skipped with a warning.
+pub(crate) fn read_fares<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let fare_products =
+        read_objects_loose::<_, FareProduct>(file_handler, "fare_products.txt", false)?;
+    let fare_attributes = if fare_products.is_empty() {
+        read_objects_loose::<_, FareAttribute>(file_handler, "fare_attributes.txt", false)?
+    } else {
+        info!("fare_attributes.txt is ignored: the fares v2 files are read instead");
+        Vec::new()
+    };
+    if fare_products.is_empty() && fare_attributes.is_empty() {
+        return Ok(());
+    }
+    let context = match Context::new(collections) {
+        Some(context) => context,
+        None => {
+            warn!("the fares are ignored: there is no calendar");
+            return Ok(());
+        }
+    };
+    let fares = if fare_products.is_empty() {
+        read_fares_v1(file_handler, &context, fare_attributes)?
+    } else {
+        read_fares_v2(file_handler, collections, &context, fare_products)?
+    };
+    fares.into_collections(collections)
+}
+
+// The lines and the zones of a fare read from its `fare_rules.txt`, with the
+// number of rules that can't be expressed
+#[derive(Default)]
+struct FarePerimeter {
+    line_ids: BTreeSet<String>,
+    zones: BTreeSet<(String, String)>,
+    skipped_rules: usize,
+}
+
+impl FarePerimeter {
+    fn add_rule(&mut self, rule: &FareRule, context: &Context) -> Result<()> {
+        if rule.contains_id.is_some() {
+            bail!(
+                "fare_rules.txt: contains_id of fare {} is not supported",
+                rule.fare_id
+            );
+        }
+        let line_id = match &rule.route_id {
+            Some(route_id) => {
+                Some(context.route_lines.get(route_id.as_str()).ok_or_else(|| {
+                    anyhow!(
+                        "fare_rules.txt: route_id={:?} of fare {} not found",
+                        route_id,
+                        rule.fare_id
+                    )
+                })?)
+            }
+            None => None,
+        };
+        let zones = match (&rule.origin_id, &rule.destination_id) {
+            (Some(origin), Some(destination)) => {
+                for zone in &[origin, destination] {
+                    if !context.fare_zones.contains(zone.as_str()) {
+                        bail!(
+                            "fare_rules.txt: zone {:?} of fare {} not found",
+                            zone,
+                            rule.fare_id
+                        );
+                    }
+                }
+                Some((origin.clone(), destination.clone()))
+            }
+            (None, None) => None,
+            _ => bail!(
+                "fare_rules.txt: fare {} must have both an origin_id and a destination_id",
+                rule.fare_id
+            ),
+        };
+        self.line_ids
+            .extend(line_id.map(|line_id| line_id.to_string()));
+        self.zones.extend(zones);
+        Ok(())
+    }
+}
+
+// A fare of `fare_attributes.txt` becomes a ticket with a single ticket use of
+// the same identifier. Its `route_id` rules become lines in the perimeter,
+// otherwise the perimeter is its agency (or all the networks without
+// `agency_id`), and its `origin_id` and `destination_id` rules become zone
+// restrictions. A fare with only rules that can't be expressed is skipped.
+fn read_fares_v1<H>(
+    file_handler: &mut H,
+    context: &Context,
+    fare_attributes: Vec<FareAttribute>,
+) -> Result<Fares>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let fare_rules = read_objects_loose::<_, FareRule>(file_handler, "fare_rules.txt", false)?;
+    let mut perimeters: BTreeMap<&str, FarePerimeter> = BTreeMap::new();
+    for rule in &fare_rules {
+        if !fare_attributes.iter().any(|fare| fare.id == rule.fare_id) {
+            warn!("fare_rules.txt: fare {} not found", rule.fare_id);
+            continue;
+        }
+        let perimeter = perimeters.entry(rule.fare_id.as_str()).or_default();
+        if let Err(error) = perimeter.add_rule(rule, context) {
+            warn!("{}", error);
+            perimeter.skipped_rules += 1;
+        }
+    }
+
+    let mut fares = Fares::default();
+    for fare in fare_attributes {
+        let perimeter = perimeters.remove(fare.id.as_str()).unwrap_or_default();
+        if perimeter.skipped_rules > 0
+            && perimeter.line_ids.is_empty()
+            && perimeter.zones.is_empty()
+        {
+            warn!("fare {} is ignored: none of its rules can be read", fare.id);
+            continue;
+        }
+        let ticket_price = skip_error_and_warn!(context
+            .ticket_price(&fare.id, &fare.price, fare.currency)
+            .map_err(|e| e.context("fare_attributes.txt")));
+        let network_ids = match &fare.agency_id {
+            Some(agency_id) if context.network_ids.contains(agency_id) => vec![agency_id.clone()],
+            Some(agency_id) => {
+                warn!(
+                    "fare_attributes.txt: agency_id={:?} of fare {} not found",
+                    agency_id, fare.id
+                );
+                continue;
+            }
+            None => context.network_ids.clone(),
+        };
+        if perimeter.line_ids.is_empty() {
+            fares.push_perimeters(&fare.id, ObjectType::Network, network_ids);
+        } else {
+            fares.push_perimeters(&fare.id, ObjectType::Line, perimeter.line_ids);
+        }
+        fares.push_restrictions(
+            &fare.id,
+            perimeter
+                .zones
+                .into_iter()
+                .map(|(origin, destination)| (RestrictionType::Zone, origin, destination)),
+        );
+        fares.tickets.push(Ticket {
+            id: fare.id.clone(),
+            name: fare.id.clone(),
+            comment: None,
+        });
+        fares.ticket_prices.push(ticket_price);
+        fares.ticket_uses.push(TicketUse {
+            id: fare.id.clone(),
+            ticket_id: fare.id,
+            // An empty `transfers` allows unlimited transfers
+            max_transfers: fare.transfers,
+            // Time limits are in seconds in the GTFS and in minutes in the
+            // NTFS, a partial minute being counted
+            boarding_time_limit: fare.transfer_duration.map(|duration| duration.div_ceil(60)),
+            alighting_time_limit: None,
+        });
+    }
+    Ok(fares)
+}
+
+// The perimeter and the restrictions of a ticket use read from the
+// `fare_leg_rules.txt` of a leg group
+#[derive(Default)]
+struct LegGroup {
+    networks: BTreeSet<String>,
+    line_ids: BTreeSet<String>,
+    restrictions: Vec<(RestrictionType, String, String)>,
+}
+
+// Resolution of the `network_id` and the areas of the leg rules
+struct AreasAndNetworks<'c> {
+    context: &'c Context<'c>,
+    collections: &'c Collections,
+    area_stops: BTreeMap<String, Vec<String>>,
+    network_lines: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl<'c> AreasAndNetworks<'c> {
+    fn new<H>(
+        file_handler: &mut H,
+        collections: &'c Collections,
+        context: &'c Context<'c>,
+    ) -> Result<Self>
+    where
+        for<'a> &'a mut H: FileHandler,
+    {
+        let mut area_stops: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for area_stop in read_objects_loose::<_, AreaStop>(file_handler, "stop_areas.txt", false)? {
+            area_stops
+                .entry(area_stop.area_id)
+                .or_default()
+                .push(area_stop.stop_id);
+        }
+        let route_networks =
+            read_objects_loose::<_, RouteNetwork>(file_handler, "route_networks.txt", false)?
+                .into_iter()
+                .map(|rn| (rn.route_id, rn.network_id));
+        let route_network_ids =
+            read_objects_loose::<_, RouteNetworkId>(file_handler, "routes.txt", false)?
+                .into_iter()
+                .filter_map(|route| Some((route.route_id, route.network_id?)));
+        let mut network_lines: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (route_id, network_id) in route_networks.chain(route_network_ids) {
+            let line_id = skip_error_and_warn!(context
+                .route_lines
+                .get(route_id.as_str())
+                .ok_or_else(|| anyhow!(
+                    "route_id={:?} of network {} not found",
+                    route_id,
+                    network_id
+                )));
+            network_lines
+                .entry(network_id)
+                .or_default()
+                .insert(line_id.to_string());
+        }
+        Ok(AreasAndNetworks {
+            context,
+            collections,
+            area_stops,
+            network_lines,
+        })
+    }
+
+    // A fare zone, or the stop area gathering all the stops of the area
+    fn area(&self, area_id: &str) -> Result<(RestrictionType, String)> {
+        if self.context.fare_zones.contains(area_id) {
+            return Ok((RestrictionType::Zone, area_id.to_string()));
+        }
+        let stop_areas: BTreeSet<&str> = self
+            .area_stops
+            .get(area_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|stop_id| {
+                if self.collections.stop_areas.contains_id(stop_id) {
+                    Some(stop_id.as_str())
+                } else {
+                    self.collections
+                        .stop_points
+                        .get(stop_id)
+                        .map(|stop_point| stop_point.stop_area_id.as_str())
+                }
+            })
+            .collect();
+        match stop_areas.into_iter().collect::<Vec<_>>().as_slice() {
+            [stop_area_id] => Ok((RestrictionType::OriginDestination, stop_area_id.to_string())),
+            [] => bail!("area {} has no known stop", area_id),
+            _ => bail!(
+                "area {} is not supported: its stops belong to several stop areas",
+                area_id
+            ),
+        }
+    }
+
+    fn add_rule(&self, leg_group: &mut LegGroup, rule: &FareLegRule) -> Result<()> {
+        let restriction = match (&rule.from_area_id, &rule.to_area_id) {
+            (Some(from_area_id), Some(to_area_id)) => {
+                let (from_type, origin) = self.area(from_area_id)?;
+                let (to_type, destination) = self.area(to_area_id)?;
+                if from_type != to_type {
+                    bail!(
+                        "areas {} and {} are not supported: a zone and a stop area",
+                        from_area_id,
+                        to_area_id
+                    );
+                }
+                Some((from_type, origin, destination))
+            }
+            (None, None) => None,
+            _ => bail!("both a from_area_id and a to_area_id are required"),
+        };
+        match &rule.network_id {
+            Some(network_id) if self.context.network_ids.contains(network_id) => {
+                leg_group.networks.insert(network_id.clone());
+            }
+            Some(network_id) => {
+                let line_ids = self
+                    .network_lines
+                    .get(network_id)
+                    .ok_or_else(|| anyhow!("network_id={:?} not found", network_id))?;
+                leg_group.line_ids.extend(line_ids.iter().cloned());
+            }
+            None => leg_group
+                .networks
+                .extend(self.context.network_ids.iter().cloned()),
+        }
+        if let Some(restriction) = restriction {
+            if !leg_group.restrictions.contains(&restriction) {
+                leg_group.restrictions.push(restriction);
+            }
+        }
+        Ok(())
+    }
+}
+
+// A fare product becomes a ticket, and each of its leg groups a ticket use
+// identified by the `leg_group_id` (or the `fare_product_id` without one,
+// and both when the leg group is shared by several products). The networks
+// of the leg rules become the perimeter, the lines of the networks defined in
+// `route_networks.txt` or `routes.txt`, and the areas become zone
+// restrictions when they are fare zones, OD restrictions when their stops
+// belong to a single stop area.
+fn read_fares_v2<H>(
+    file_handler: &mut H,
+    collections: &Collections,
+    context: &Context,
+    fare_products: Vec<FareProduct>,
+) -> Result<Fares>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    for file in &[
+        "fare_transfer_rules.txt",
+        "timeframes.txt",
+        "rider_categories.txt",
+    ] {
+        if file_handler.get_file_if_exists(file)?.0.is_some() {
+            warn!("{} is not supported and ignored", file);
+        }
+    }
+    let mut fares = Fares::default();
+    // A product may have several lines, one for each fare media
+    let mut products = BTreeMap::new();
+    for product in fare_products {
+        let ticket_price = context
+            .ticket_price(&product.id, &product.amount, product.currency)
+            .map_err(|e| e.context("fare_products.txt"));
+        match products.get(&product.id) {
+            Some(TicketPrice {
+                price, currency, ..
+            }) => {
+                if !matches!(ticket_price, Ok(p) if &p.price == price && &p.currency == currency) {
+                    warn!(
+                        "fare_products.txt: fare product {} has several prices, only the first one is kept",
+                        product.id
+                    );
+                }
+            }
+            None => {
+                let ticket_price = skip_error_and_warn!(ticket_price);
+                let id = product.id.clone();
+                fares.tickets.push(Ticket {
+                    name: product.name.unwrap_or_else(|| id.clone()),
+                    id,
+                    comment: None,
+                });
+                products.insert(product.id, ticket_price);
+            }
+        }
+    }
+
+    let resolver = AreasAndNetworks::new(file_handler, collections, context)?;
+    let fare_leg_rules =
+        read_objects_loose::<_, FareLegRule>(file_handler, "fare_leg_rules.txt", false)?;
+    let mut leg_groups: BTreeMap<(String, Option<String>), LegGroup> = BTreeMap::new();
+    for rule in fare_leg_rules {
+        if !products.contains_key(&rule.fare_product_id) {
+            warn!(
+                "fare_leg_rules.txt: fare product {} not found",
+                rule.fare_product_id
+            );
+            continue;
+        }
+        let leg_group = leg_groups
+            .entry((rule.fare_product_id.clone(), rule.leg_group_id.clone()))
+            .or_default();
+        if let Err(error) = resolver.add_rule(leg_group, &rule) {
+            warn!(
+                "fare_leg_rules.txt: rule of fare product {} is ignored: {}",
+                rule.fare_product_id, error
+            );
+        }
+    }
+
+    let mut products_by_group: HashMap<&str, usize> = HashMap::new();
+    for (_, leg_group_id) in leg_groups.keys() {
+        if let Some(leg_group_id) = leg_group_id {
+            *products_by_group.entry(leg_group_id).or_default() += 1;
+        }
+    }
+    let mut used_products = HashSet::new();
+    for ((product_id, leg_group_id), leg_group) in &leg_groups {
+        if leg_group.networks.is_empty() && leg_group.line_ids.is_empty() {
+            warn!(
+                "fare product {} has a leg group ignored: none of its rules can be read",
+                product_id
+            );
+            continue;
+        }
+        let ticket_use_id = match leg_group_id {
+            Some(leg_group_id) if products_by_group[leg_group_id.as_str()] == 1 => {
+                leg_group_id.clone()
+            }
+            Some(leg_group_id) => format!("{}:{}", product_id, leg_group_id),
+            None => product_id.clone(),
+        };
+        fares.push_perimeters(
+            &ticket_use_id,
+            ObjectType::Network,
+            leg_group.networks.iter().cloned(),
+        );
+        fares.push_perimeters(
+            &ticket_use_id,
+            ObjectType::Line,
+            leg_group.line_ids.iter().cloned(),
+        );
+        fares.push_restrictions(&ticket_use_id, leg_group.restrictions.iter().cloned());
+        fares.ticket_uses.push(TicketUse {
+            id: ticket_use_id,
+            ticket_id: product_id.clone(),
+            max_transfers: None,
+            boarding_time_limit: None,
+            alighting_time_limit: None,
+        });
+        used_products.insert(product_id.as_str());
+    }
+
+    fares.tickets.retain(|ticket| {
+        let is_used = used_products.contains(ticket.id.as_str());
+        if !is_used {
+            warn!("fare product {} is ignored: it has no leg rule", ticket.id);
+        }
+        is_used
+    });
+    fares.ticket_prices = products
+        .into_iter()
+        .filter(|(product_id, _)| used_products.contains(product_id.as_str()))
+        .map(|(_, ticket_price)| ticket_price)
+        .collect();
+    Ok(fares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_handler::PathFileHandler,
+        objects::{Calendar, Line, Network, Route, StopArea, StopPoint},
+        test_utils::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    // Two lines `L0` and `L1` whose GTFS routes are `R0` and `R1`, the stop
+    // points `SP1` and `SP2` being in the fare zones `Z1` and `Z2`, and a
+    // calendar giving the validity period of the fares
+    fn collections() -> Collections {
+        let lines = ["L0", "L1"].iter().map(|id| Line {
+            id: id.to_string(),
+            network_id: "network".to_string(),
+            ..Default::default()
+        });
+        let routes = [("R0", "L0"), ("R1", "L1")]
+            .iter()
+            .map(|(source, line_id)| Route {
+                id: format!("{}F", line_id),
+                line_id: line_id.to_string(),
+                codes: vec![("source".into(), source.to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            });
+        let stop_areas = ["SA1", "SA2"].iter().map(|id| StopArea {
+            id: id.to_string(),
+            ..Default::default()
+        });
+        let stop_points = [("SP1", "SA1", "Z1"), ("SP2", "SA2", "Z2")].iter().map(
+            |(id, stop_area_id, fare_zone_id)| StopPoint {
+                id: id.to_string(),
+                stop_area_id: stop_area_id.to_string(),
+                fare_zone_id: Some(fare_zone_id.to_string()),
+                ..Default::default()
+            },
+        );
+        Collections {
+            networks: CollectionWithId::from(Network {
+                id: "network".to_string(),
+                ..Default::default()
+            }),
+            lines: CollectionWithId::new(lines.collect()).unwrap(),
+            routes: CollectionWithId::new(routes.collect()).unwrap(),
+            stop_areas: CollectionWithId::new(stop_areas.collect()).unwrap(),
+            stop_points: CollectionWithId::new(stop_points.collect()).unwrap(),
+            calendars: CollectionWithId::from(Calendar {
+                id: "C1".to_string(),
+                dates: std::iter::once(Date::from_ymd_opt(2020, 1, 1).unwrap()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn read(files: &[(&str, &str)]) -> Collections {
+        let mut collections = collections();
+        test_in_tmp_dir(|path| {
+            for (file, content) in files {
+                create_file_with_content(path, file, content);
+            }
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            read_fares(&mut handler, &mut collections).unwrap();
+        });
+        collections
+    }
+
+    fn perimeters(collections: &Collections) -> Vec<(&str, &ObjectType, &str)> {
+        collections
+            .ticket_use_perimeters
+            .values()
+            .map(|p| (&p.ticket_use_id[..], &p.object_type, &p.object_id[..]))
+            .collect()
+    }
+
+    fn restrictions(collections: &Collections) -> Vec<(&str, &RestrictionType, &str, &str)> {
+        collections
+            .ticket_use_restrictions
+            .values()
+            .map(|r| {
+                (
+                    &r.ticket_use_id[..],
+                    &r.restriction_type,
+                    &r.use_origin[..],
+                    &r.use_destination[..],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_fare_attributes_and_rules() {
+        let collections = read(&[
+            (
+                "fare_attributes.txt",
+                "fare_id,price,currency_type,payment_method,transfers,agency_id,transfer_duration\n\
+                 on_line,1.50,EUR,0,0,,\n\
+                 zones,2.10,EUR,1,,network,5430\n\
+                 unknown_route,1.00,EUR,0,,,\n\
+                 unknown_agency,1.00,EUR,0,,agency,",
+            ),
+            (
+                "fare_rules.txt",
+                "fare_id,route_id,origin_id,destination_id,contains_id\n\
+                 on_line,R1,,,\n\
+                 on_line,R9,,,\n\
+                 zones,,Z1,Z2,\n\
+                 zones,,Z1,Z3,\n\
+                 zones,,,Z2,\n\
+                 unknown_route,R9,,,\n\
+                 unknown_fare,R0,,,",
+            ),
+        ]);
+
+        let tickets: Vec<_> = collections.tickets.values().map(|t| &t.id[..]).collect();
+        assert_eq!(vec!["on_line", "zones"], tickets);
+        let prices: Vec<_> = collections
+            .ticket_prices
+            .values()
+            .map(|p| (&p.ticket_id[..], p.price.to_string(), &p.currency[..]))
+            .collect();
+        assert_eq!(
+            vec![
+                ("on_line", "1.50".to_string(), "EUR"),
+                ("zones", "2.10".to_string(), "EUR")
+            ],
+            prices
+        );
+        let on_line = collections.ticket_uses.get("on_line").unwrap();
+        assert_eq!(
+            (Some(0), None),
+            (on_line.max_transfers, on_line.boarding_time_limit)
+        );
+        // 5430 seconds are rounded up to 91 minutes
+        let zones = collections.ticket_uses.get("zones").unwrap();
+        assert_eq!(
+            (None, Some(91)),
+            (zones.max_transfers, zones.boarding_time_limit)
+        );
+        assert_eq!(
+            vec![
+                ("on_line", &ObjectType::Line, "L1"),
+                ("zones", &ObjectType::Network, "network")
+            ],
+            perimeters(&collections)
+        );
+        assert_eq!(
+            vec![("zones", &RestrictionType::Zone, "Z1", "Z2")],
+            restrictions(&collections)
+        );
+    }
+
+    #[test]
+    fn read_fares_v2_files() {
+        let collections = read(&[
+            (
+                "fare_products.txt",
+                "fare_product_id,fare_product_name,fare_media_id,amount,currency\n\
+                 flat,Flat fare,,1.50,EUR\n\
+                 flat,Flat fare,card,1.50,EUR\n\
+                 od,,,3.00,EUR\n\
+                 line,,,2.00,EUR\n\
+                 no_rule,,,1.00,EUR\n\
+                 invalid,,,abc,EUR",
+            ),
+            (
+                "fare_leg_rules.txt",
+                "leg_group_id,network_id,from_area_id,to_area_id,fare_product_id\n\
+                 flat_group,network,,,flat\n\
+                 od_group,,station,other_station,od\n\
+                 od_group,,station,Z2,od\n\
+                 line_group,metro,,,line\n\
+                 unknown_group,unknown_network,,,line\n\
+                 ,network,,,invalid\n\
+                 ,network,,,unknown_product",
+            ),
+            (
+                "stop_areas.txt",
+                "area_id,stop_id\n\
+                 station,SP1\n\
+                 other_station,SA2",
+            ),
+            ("route_networks.txt", "network_id,route_id\nmetro,R1"),
+            (
+                "fare_transfer_rules.txt",
+                "from_leg_group_id,to_leg_group_id,fare_transfer_type\nflat_group,flat_group,0",
+            ),
+            // Ignored in favor of the fares v2 files
+            (
+                "fare_attributes.txt",
+                "fare_id,price,currency_type,payment_method,transfers\nv1,1.00,EUR,0,",
+            ),
+        ]);
+
+        let tickets: Vec<_> = collections
+            .tickets
+            .values()
+            .map(|t| (&t.id[..], &t.name[..]))
+            .collect();
+        assert_eq!(
+            vec![("flat", "Flat fare"), ("od", "od"), ("line", "line")],
+            tickets
+        );
+        let prices: Vec<_> = collections
+            .ticket_prices
+            .values()
+            .map(|p| (&p.ticket_id[..], p.price.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("flat", "1.50".to_string()),
+                ("line", "2.00".to_string()),
+                ("od", "3.00".to_string())
+            ],
+            prices
+        );
+        let ticket_uses: Vec<_> = collections
+            .ticket_uses
+            .values()
+            .map(|tu| (&tu.id[..], &tu.ticket_id[..]))
+            .collect();
+        assert_eq!(
+            vec![
+                ("flat_group", "flat"),
+                ("line_group", "line"),
+                ("od_group", "od")
+            ],
+            ticket_uses
+        );
+        assert_eq!(
+            vec![
+                ("flat_group", &ObjectType::Network, "network"),
+                ("line_group", &ObjectType::Line, "L1"),
+                ("od_group", &ObjectType::Network, "network")
+            ],
+            perimeters(&collections)
+        );
+        assert_eq!(
+            vec![(
+                "od_group",
+                &RestrictionType::OriginDestination,
+                "SA1",
+                "SA2"
+            )],
+            restrictions(&collections)
+        );
+    }
+}
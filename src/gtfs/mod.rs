@@ -14,33 +14,52 @@
 
 //! [GTFS](https://gtfs.org/reference/static) format management.
 
+pub mod clean;
+mod fares;
 mod read;
+mod sanitize_ids;
 mod write;
 
 use crate::{
     calendars::{manage_calendars, write_calendar_dates},
-    file_handler::{FileHandler, PathFileHandler, ZipHandler},
-    model::{Collections, Model},
+    configuration::DatasetsConfig,
+    enhancers,
+    file_handler::{
+        FileHandler, FileWriter, MemoryFileWriter, PathFileHandler, PathFileWriter,
+        TranscodingFileHandler, ZipHandler,
+    },
+    id_string::IdString,
+    merge,
+    model::{Collections, ContactFieldsMode, Model},
     objects::{self, Availability, Contributor, Dataset, StopType, Time},
     parser::read_opt_collection,
     serde_utils::*,
+    timing,
     utils::*,
     validity_period, AddPrefix, PrefixConfiguration, Result,
 };
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, ensure, Context};
 use chrono_tz::Tz;
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::{Seek, Write},
+    path::Path,
+    str::FromStr,
+};
 
-use tracing::info;
-use typed_index_collection::CollectionWithId;
+use tracing::{info, warn};
+use typed_index_collection::{Collection, CollectionWithId};
 
 #[cfg(all(feature = "gtfs", feature = "parser"))]
 pub use read::{
     manage_frequencies, manage_pathways, manage_shapes, manage_stop_times, read_agency,
-    read_routes, read_stops, read_transfers, EquipmentList,
+    read_attributions, read_routes, read_stops, read_transfers, read_translations,
+    read_trip_transfers, EquipmentList,
 };
+pub use sanitize_ids::{sanitize_ids, IdSanitization};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Agency {
@@ -162,7 +181,9 @@ enum DirectionType {
     Backward = 1,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// Written to trips.txt, the reading being done from the borrowed
+// `read::TripRecord`
+#[derive(Serialize, Debug, Clone, PartialEq)]
 struct Trip {
     route_id: String,
     service_id: String,
@@ -183,34 +204,58 @@ struct Trip {
     shape_id: Option<String>,
     #[serde(deserialize_with = "de_with_empty_default", default)]
     wheelchair_accessible: Availability,
-    #[serde(deserialize_with = "de_with_empty_default", default)]
+    #[serde(
+        deserialize_with = "de_with_empty_default",
+        default,
+        serialize_with = "ser_availability_with_empty_default"
+    )]
     bikes_allowed: Availability,
 }
 
+// An unknown availability is written as an empty field
+fn ser_availability_with_empty_default<S>(
+    availability: &Availability,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match availability {
+        Availability::InformationNotAvailable => serializer.serialize_none(),
+        _ => availability.serialize(serializer),
+    }
+}
+
 fn default_true_bool() -> bool {
     true
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// Read from the borrowed `read::StopTimeRecord`, the identifiers and the
+// headsign being interned
+#[derive(Serialize, Debug, Clone)]
 struct StopTime {
-    trip_id: String,
+    trip_id: IdString,
     arrival_time: Option<Time>,
     departure_time: Option<Time>,
-    #[serde(deserialize_with = "de_without_slashes")]
-    stop_id: String,
+    stop_id: IdString,
     stop_sequence: u32,
-    #[serde(deserialize_with = "de_with_empty_default", default)]
     pickup_type: u8,
-    #[serde(deserialize_with = "de_with_empty_default", default)]
     drop_off_type: u8,
     local_zone_id: Option<u16>,
-    stop_headsign: Option<String>,
-    #[serde(
-        deserialize_with = "de_from_u8_with_true_default",
-        serialize_with = "ser_from_bool",
-        default = "default_true_bool"
-    )]
+    stop_headsign: Option<IdString>,
+    #[serde(serialize_with = "ser_from_bool")]
     timepoint: bool,
+    // Written by `write::write_stop_times` only when used
+    #[serde(skip_serializing)]
+    continuous_pickup: Option<u8>,
+    #[serde(skip_serializing)]
+    continuous_drop_off: Option<u8>,
+    #[serde(skip_serializing)]
+    shape_dist_traveled: Option<f64>,
+    #[serde(skip_serializing)]
+    pickup_booking_rule_id: Option<IdString>,
+    #[serde(skip_serializing)]
+    drop_off_booking_rule_id: Option<IdString>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Derivative, PartialEq, Clone)]
@@ -225,26 +270,52 @@ enum TransferType {
     WithTransferTime,
     #[serde(rename = "3")]
     NotPossible,
+    #[serde(rename = "4")]
+    InSeat,
+    #[serde(rename = "5")]
+    InSeatNotAllowed,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 struct Transfer {
-    #[serde(deserialize_with = "de_without_slashes")]
-    from_stop_id: String,
-    #[serde(deserialize_with = "de_without_slashes")]
-    to_stop_id: String,
+    #[serde(default, deserialize_with = "de_option_without_slashes")]
+    from_stop_id: Option<String>,
+    #[serde(default, deserialize_with = "de_option_without_slashes")]
+    to_stop_id: Option<String>,
     #[serde(deserialize_with = "de_with_empty_default")]
     transfer_type: TransferType,
     min_transfer_time: Option<u32>,
+    // Written by `write::write_transfers` only when used
+    #[serde(
+        default,
+        deserialize_with = "de_option_without_slashes",
+        skip_serializing
+    )]
+    from_trip_id: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "de_option_without_slashes",
+        skip_serializing
+    )]
+    to_trip_id: Option<String>,
 }
 
 impl<'a> From<&'a objects::Transfer> for Transfer {
     fn from(obj: &objects::Transfer) -> Transfer {
+        // The `min_transfer_time` is only given with `transfer_type` 2
+        let (transfer_type, min_transfer_time) = match (obj.transfer_type, obj.min_transfer_time) {
+            (objects::TransferType::NotPossible, _) => (TransferType::NotPossible, None),
+            (_, Some(0)) => (TransferType::Timed, None),
+            (_, Some(time)) => (TransferType::WithTransferTime, Some(time)),
+            (_, None) => (TransferType::Recommended, None),
+        };
         Transfer {
-            from_stop_id: obj.from_stop_id.clone(),
-            to_stop_id: obj.to_stop_id.clone(),
-            transfer_type: TransferType::WithTransferTime,
-            min_transfer_time: obj.min_transfer_time,
+            from_stop_id: Some(obj.from_stop_id.clone()),
+            to_stop_id: Some(obj.to_stop_id.clone()),
+            transfer_type,
+            min_transfer_time,
+            from_trip_id: None,
+            to_trip_id: None,
         }
     }
 }
@@ -261,6 +332,256 @@ struct Shape {
     sequence: u32,
 }
 
+// Columns of `feed_info.txt`, filled from the `feed_infos` of the model
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct FeedInfo {
+    feed_publisher_name: Option<String>,
+    feed_publisher_url: Option<String>,
+    feed_lang: Option<String>,
+    default_lang: Option<String>,
+    feed_start_date: Option<String>,
+    feed_end_date: Option<String>,
+    feed_version: Option<String>,
+    feed_contact_email: Option<String>,
+    feed_contact_url: Option<String>,
+}
+
+// Columns of `attributions.txt`, the attributions applying to the whole feed
+#[derive(Serialize, Debug, PartialEq)]
+struct Attribution {
+    attribution_id: String,
+    organization_name: String,
+    #[serde(serialize_with = "ser_from_bool")]
+    is_producer: bool,
+    #[serde(serialize_with = "ser_from_bool")]
+    is_operator: bool,
+    attribution_url: Option<String>,
+    attribution_email: Option<String>,
+    attribution_phone: Option<String>,
+    attribution_license: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Derivative, PartialEq, Clone)]
+#[derivative(Default)]
+enum FareMediaType {
+    #[derivative(Default)]
+    #[serde(rename = "0")]
+    NoMedia,
+    #[serde(rename = "1")]
+    PaperTicket,
+    #[serde(rename = "2")]
+    TransitCard,
+    #[serde(rename = "3")]
+    ContactlessEmv,
+    #[serde(rename = "4")]
+    MobileApp,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct FareMedia {
+    #[serde(rename = "fare_media_id")]
+    id: String,
+    #[serde(rename = "fare_media_name")]
+    name: Option<String>,
+    #[serde(rename = "fare_media_type", deserialize_with = "de_with_empty_default")]
+    media_type: FareMediaType,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct FareProduct {
+    #[serde(rename = "fare_product_id")]
+    id: String,
+    #[serde(rename = "fare_product_name")]
+    name: Option<String>,
+    fare_media_id: Option<String>,
+    amount: String,
+    #[serde(
+        serialize_with = "ser_currency_code",
+        deserialize_with = "de_currency_code"
+    )]
+    currency: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct FareLegRule {
+    leg_group_id: Option<String>,
+    network_id: Option<String>,
+    from_area_id: Option<String>,
+    to_area_id: Option<String>,
+    fare_product_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Derivative, PartialEq, Clone)]
+#[derivative(Default)]
+enum PaymentMethod {
+    #[derivative(Default)]
+    #[serde(rename = "0")]
+    OnBoard,
+    #[serde(rename = "1")]
+    BeforeBoarding,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct FareAttribute {
+    #[serde(rename = "fare_id")]
+    id: String,
+    price: String,
+    #[serde(
+        rename = "currency_type",
+        serialize_with = "ser_currency_code",
+        deserialize_with = "de_currency_code"
+    )]
+    currency: String,
+    #[serde(deserialize_with = "de_with_empty_default")]
+    payment_method: PaymentMethod,
+    transfers: Option<u32>,
+    agency_id: Option<String>,
+    transfer_duration: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct FareRule {
+    fare_id: String,
+    route_id: Option<String>,
+    origin_id: Option<String>,
+    destination_id: Option<String>,
+    contains_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct Area {
+    #[serde(rename = "area_id")]
+    id: String,
+    #[serde(rename = "area_name")]
+    name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct AreaStop {
+    area_id: String,
+    stop_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct GtfsNetwork {
+    #[serde(rename = "network_id")]
+    id: String,
+    #[serde(rename = "network_name")]
+    name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct RouteNetwork {
+    network_id: String,
+    route_id: String,
+}
+
+/// The modes of the routes of a `route_type`, replacing the default ones
+/// (see [Configuration::route_type_modes])
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RouteTypeModes {
+    /// The `route_type` of `routes.txt`
+    pub route_type: u16,
+    /// Identifier of the physical mode of the trips
+    pub physical_mode_id: String,
+    /// Identifier of the commercial mode of the lines
+    pub commercial_mode_id: String,
+    /// Name of the commercial mode
+    pub commercial_mode_name: String,
+}
+
+/// Read the modes of the route types from a CSV file with the columns
+/// `route_type`, `physical_mode_id`, `commercial_mode_id` and
+/// `commercial_mode_name`:
+/// ```text
+/// route_type,physical_mode_id,commercial_mode_id,commercial_mode_name
+/// 800,Tramway,Trolleybus,Trolleybus
+/// 1200,Ferry,Ferry,Ferry
+/// ```
+pub fn read_route_type_modes<P: AsRef<Path>>(path: P) -> Result<Vec<RouteTypeModes>> {
+    let path = path.as_ref();
+    info!("Reading the modes of the route types from {:?}", path);
+    let mut reader =
+        csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let route_type_modes = reader
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(route_type_modes)
+}
+
+/// What to do with the stop times of a trip with the same `stop_sequence`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateStopSequenceStrategy {
+    /// Fail the conversion
+    Fail,
+    /// Keep the first of these stop times in the file, ignoring the others
+    #[default]
+    KeepFirst,
+    /// Keep all these stop times in the order of the file, the following
+    /// stop times of the trip being renumbered so that the `stop_sequence`
+    /// are strictly increasing
+    Renumber,
+}
+
+impl FromStr for DuplicateStopSequenceStrategy {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fail" => Ok(DuplicateStopSequenceStrategy::Fail),
+            "keep_first" => Ok(DuplicateStopSequenceStrategy::KeepFirst),
+            "renumber" => Ok(DuplicateStopSequenceStrategy::Renumber),
+            _ => bail!(
+                "unknown duplicate stop_sequence strategy {:?}, expected 'fail', 'keep_first' or 'renumber'",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DuplicateStopSequenceStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DuplicateStopSequenceStrategy::Fail => write!(f, "fail"),
+            DuplicateStopSequenceStrategy::KeepFirst => write!(f, "keep_first"),
+            DuplicateStopSequenceStrategy::Renumber => write!(f, "renumber"),
+        }
+    }
+}
+
+/// Which objects share an on demand transport comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDemandTransportCommentScope {
+    /// One comment for each agency, identified by `ODT:<agency_id>`
+    #[default]
+    Agency,
+    /// One comment for each line, identified by `ODT:<line_id>`
+    Line,
+}
+
+impl FromStr for OnDemandTransportCommentScope {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "agency" => Ok(OnDemandTransportCommentScope::Agency),
+            "line" => Ok(OnDemandTransportCommentScope::Line),
+            _ => bail!(
+                "unknown on demand transport comment scope {:?}, expected 'agency' or 'line'",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for OnDemandTransportCommentScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnDemandTransportCommentScope::Agency => write!(f, "agency"),
+            OnDemandTransportCommentScope::Line => write!(f, "line"),
+        }
+    }
+}
+
 ///parameters consolidation
 #[derive(Default)]
 pub struct Configuration {
@@ -274,17 +595,114 @@ pub struct Configuration {
     pub prefix_conf: Option<PrefixConfiguration>,
     /// stop time precision management
     pub on_demand_transport: bool,
-    /// on demand transport comment template
+    /// on demand transport comment template, where `{agency_name}` and
+    /// `{agency_phone}` are replaced by the name and the phone of the agency
+    /// of the trip. No comment is created if not set
     pub on_demand_transport_comment: Option<String>,
+    /// Whether one on demand transport comment is created for each agency or
+    /// for each line
+    pub on_demand_transport_comment_scope: OnDemandTransportCommentScope,
     /// If true, each GTFS `Route` will generate a different `Line`.
     /// Else we group the routes by `agency_id` and `route_short_name`
     /// (or `route_long_name` if the short name is empty) and create a `Line` for each group.
     pub read_as_line: bool,
+    /// What to do with the invalid URLs and emails of the agencies
+    pub contact_fields_mode: ContactFieldsMode,
+    /// The agency of the routes without `agency_id` when there are several
+    /// agencies. If not set, these routes make the reading fail
+    pub default_agency_id: Option<String>,
+    /// If true, `shapes.txt` is not read: the `shape_id` of the trips are kept
+    /// without being checked, and the model can't be written (see
+    /// `Collections::skipped_geometries`)
+    pub skip_geometries: bool,
+    /// If set, the files of a zipped GTFS are decompressed in parallel before
+    /// being parsed, as long as they fit in this number of bytes (see
+    /// `ZipHandler::decompress_in_memory`)
+    pub zip_memory_budget: Option<usize>,
+    /// If true, the calendars with the same dates are not merged, so that
+    /// each trip keeps its original `service_id`
+    pub skip_calendar_deduplication: bool,
+    /// If true, the calendars used by no trip are kept instead of being
+    /// removed (see `Collections::remove_unused_calendars`)
+    pub keep_unused_calendars: bool,
+    /// If true, the identifiers of the trips generated from `frequencies.txt`
+    /// are derived from their departure time rather than numbered, so that
+    /// they stay the same when another frequency is added or removed (see
+    /// `manage_frequencies`)
+    pub stable_ids: bool,
+    /// If true, the times of the stop times at a stop with a `stop_timezone`
+    /// are considered to be in this timezone instead of the one of the
+    /// agency, and are converted into the timezone of the dataset (see
+    /// `read::apply_stop_timezones`)
+    pub apply_stop_timezones: bool,
+    /// If set, the shapes are simplified with the Douglas-Peucker algorithm,
+    /// the points within this tolerance in meters being removed (see
+    /// `read::manage_shapes`)
+    pub shape_simplification_tolerance: Option<f64>,
+    /// The modes of the routes of some `route_type`s, instead of the default
+    /// ones. The routes of an extended `route_type` not listed here keep the
+    /// default modes, with a warning
+    pub route_type_modes: Vec<RouteTypeModes>,
+    /// Also remove the dates of the calendars outside of the `feed_start_date`
+    /// and the `feed_end_date` of 'feed_info.txt'. The validity period of the
+    /// dataset is restricted to these dates in any case
+    pub restrict_to_feed_period: bool,
+    /// Detect the encoding of each file (UTF-8 with or without a byte order
+    /// mark, UTF-16 or Windows-1252) and transcode it into UTF-8 while
+    /// reading it, instead of failing on the files not in UTF-8
+    pub detect_encoding: bool,
+    /// What to do with the stop times of a trip with the same
+    /// `stop_sequence`
+    pub duplicate_stop_sequences: DuplicateStopSequenceStrategy,
+    /// If true, the trips with less than 2 stop times are kept instead of
+    /// being removed (see `read::remove_trips_without_enough_stop_times`)
+    pub keep_incomplete_trips: bool,
+    /// If set, the contributors and the datasets replacing `contributor` and
+    /// `dataset`, the vehicle journeys being assigned to the datasets by
+    /// network (see `Collections::assign_datasets`)
+    pub datasets: Option<DatasetsConfig>,
+}
+
+/// Parameters of the GTFS export
+#[derive(Debug, Default, Clone)]
+pub struct WriteConfiguration {
+    /// Support a more rich set of route types. For more information, see
+    /// <https://developers.google.com/transit/gtfs/reference/extended-route-types>
+    pub extend_route_type: bool,
+    /// Write a single GTFS route for each line, even when its trips have
+    /// several physical modes, the `direction_id` of the trips telling its
+    /// routes apart (`0` for the `forward`, `clockwise` and `inbound`
+    /// routes, `1` for the `backward`, `anticlockwise` and `outbound` ones).
+    /// By default, a line has a GTFS route for each of its physical modes.
+    pub one_route_per_line: bool,
+    /// Export the tickets of the model that can be expressed with them in
+    /// `fare_attributes.txt` and `fare_rules.txt` (GTFS fares v1)
+    pub fares_v1: bool,
+    /// Export the fares v2 of the model (tickets, ticket uses, ...) as
+    /// [GTFS-Fares v2](https://gtfs.org/reference/static#fare_productstxt)
+    /// files, along with the fares v1 files (see `fares_v1`)
+    pub fares_v2: bool,
+    /// Don't write the stop areas generated for the stops without
+    /// `parent_station` when reading a GTFS (see [Reader]), so that a GTFS
+    /// read then written keeps its stops as they were
+    pub skip_generated_stop_areas: bool,
+    /// The system of the object codes giving the `stop_code` of the stop
+    /// points and stop areas (for example `gtfs_stop_code`). The smallest
+    /// code is used when a stop has several codes of this system, and the
+    /// stop points without such code keep their NTFS `stop_code`.
+    pub stop_code_source: Option<String>,
+    /// Rewrite the identifiers with the given rule (see [sanitize_ids]),
+    /// the rewritten identifiers being listed in `sanitized_ids.txt`
+    pub id_sanitization: Option<IdSanitization>,
+    /// Write `attributions.txt`, with a producer for each contributor and an
+    /// operator for each company having a URL
+    pub attributions: bool,
 }
 
 fn read_file_handler<H>(file_handler: &mut H, configuration: Configuration) -> Result<Model>
 where
     for<'a> &'a mut H: FileHandler,
+    for<'a, 'h> &'a mut TranscodingFileHandler<'h, H>: FileHandler,
 {
     let collections = read_file_handler_to_collections(file_handler, configuration)?;
     Model::new(collections)
@@ -296,7 +714,25 @@ fn read_file_handler_to_collections<H>(
 ) -> Result<Collections>
 where
     for<'a> &'a mut H: FileHandler,
+    for<'a, 'h> &'a mut TranscodingFileHandler<'h, H>: FileHandler,
 {
+    if configuration.detect_encoding {
+        let mut file_handler = TranscodingFileHandler::new(file_handler);
+        read_transcoded_file_handler_to_collections(&mut file_handler, configuration)
+    } else {
+        read_transcoded_file_handler_to_collections(file_handler, configuration)
+    }
+}
+
+// The files of the file handler being in UTF-8
+fn read_transcoded_file_handler_to_collections<H>(
+    file_handler: &mut H,
+    configuration: Configuration,
+) -> Result<Collections>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let _stage = timing::read_collections("gtfs");
     let mut collections = Collections::default();
     let mut equipments = read::EquipmentList::default();
 
@@ -307,11 +743,32 @@ where
         prefix_conf,
         on_demand_transport,
         on_demand_transport_comment,
+        on_demand_transport_comment_scope,
         read_as_line,
+        contact_fields_mode,
+        default_agency_id,
+        skip_geometries,
+        zip_memory_budget: _,
+        skip_calendar_deduplication,
+        keep_unused_calendars,
+        stable_ids,
+        apply_stop_timezones,
+        shape_simplification_tolerance,
+        route_type_modes,
+        restrict_to_feed_period,
+        detect_encoding: _,
+        duplicate_stop_sequences,
+        keep_incomplete_trips,
+        datasets,
     } = configuration;
 
     manage_calendars(file_handler, &mut collections)?;
     validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+    let feed_period = read::read_feed_period(file_handler)?;
+    restrict_to_feed(&mut dataset, feed_period)?;
+    // The calendars are restricted once the trips are read, as a dataset
+    // without any dated trip is removed
+    let restricted_period = restrict_to_feed_period.then(|| (dataset.start_date, dataset.end_date));
 
     collections.contributors = CollectionWithId::from(contributor);
     collections.datasets = CollectionWithId::from(dataset);
@@ -320,36 +777,120 @@ where
     let (networks, companies) = read::read_agency(file_handler)?;
     collections.networks = networks;
     collections.companies = companies;
+    enhancers::check_contact_fields(&mut collections, contact_fields_mode)?;
     let (stop_areas, stop_points, stop_locations) =
         read::read_stops(file_handler, &mut collections.comments, &mut equipments)?;
     collections.transfers = read::read_transfers(file_handler, &stop_points, &stop_areas)?;
     collections.stop_areas = stop_areas;
     collections.stop_points = stop_points;
     collections.stop_locations = stop_locations;
+    read::read_location_groups(file_handler, &mut collections)?;
 
-    read::manage_shapes(&mut collections, file_handler)?;
+    if skip_geometries {
+        collections.skipped_geometries = true;
+    } else {
+        read::manage_shapes(
+            &mut collections,
+            file_handler,
+            shape_simplification_tolerance,
+        )?;
+    }
 
-    read::read_routes(file_handler, &mut collections, read_as_line)?;
+    read::read_routes(
+        file_handler,
+        &mut collections,
+        read_as_line,
+        &route_type_modes,
+        default_agency_id.as_deref(),
+    )?;
     collections.equipments = CollectionWithId::new(equipments.into_equipments())?;
+    collections.booking_rules = read_opt_collection(file_handler, "booking_rules.txt")?;
     read::manage_stop_times(
         &mut collections,
         file_handler,
         on_demand_transport,
         on_demand_transport_comment,
+        on_demand_transport_comment_scope,
+        duplicate_stop_sequences,
     )?;
-    read::manage_frequencies(&mut collections, file_handler)?;
+    if !keep_incomplete_trips {
+        read::remove_trips_without_enough_stop_times(&mut collections)?;
+    }
+    if apply_stop_timezones {
+        read::apply_stop_timezones(&mut collections)?;
+    }
+    read::manage_frequencies(&mut collections, file_handler, stable_ids)?;
+    read::read_trip_transfers(file_handler, &mut collections)?;
     read::manage_pathways(&mut collections, file_handler)?;
     collections.levels = read_opt_collection(file_handler, "levels.txt")?;
+    read::remove_unknown_levels(&mut collections)?;
+    fares::read_fares(file_handler, &mut collections)?;
+    read::read_translations(file_handler, &mut collections)?;
+    if let Some(datasets) = datasets {
+        collections.assign_datasets(&datasets)?;
+    }
+    read::read_attributions(file_handler, &mut collections)?;
 
     //add prefixes
     if let Some(prefix_conf) = prefix_conf {
         collections.prefix(&prefix_conf);
     }
 
-    collections.calendar_deduplication();
+    if let Some((start_date, end_date)) = restricted_period {
+        collections.restrict_period(start_date, end_date)?;
+    }
+    if !skip_calendar_deduplication {
+        timing::enhancer("calendar_deduplication", || {
+            collections.calendar_deduplication()
+        });
+    }
+    if keep_unused_calendars {
+        collections.update_validity_period_from_calendars();
+    } else {
+        let removed_calendars = collections.remove_unused_calendars();
+        info!("Removed {} calendars used by no trip", removed_calendars);
+    }
+    // The calendars can extend beyond the period of the feed
+    let mut datasets = collections.datasets.take();
+    for dataset in &mut datasets {
+        restrict_to_feed(dataset, feed_period)?;
+    }
+    collections.datasets = CollectionWithId::new(datasets)?;
     Ok(collections)
 }
 
+// Restrict the validity period of the dataset to the `feed_start_date` and
+// the `feed_end_date` of 'feed_info.txt'
+fn restrict_to_feed(
+    dataset: &mut Dataset,
+    (feed_start_date, feed_end_date): (Option<objects::Date>, Option<objects::Date>),
+) -> Result<()> {
+    if feed_start_date.is_none() && feed_end_date.is_none() {
+        return Ok(());
+    }
+    let start_date = feed_start_date.map_or(dataset.start_date, |date| {
+        std::cmp::max(date, dataset.start_date)
+    });
+    let end_date = feed_end_date.map_or(dataset.end_date, |date| {
+        std::cmp::min(date, dataset.end_date)
+    });
+    let format = |date: Option<objects::Date>| {
+        date.map_or_else(|| "-".to_string(), |date| date.format("%Y%m%d").to_string())
+    };
+    ensure!(
+        start_date <= end_date,
+        "the validity period of dataset {} from {} to {} does not intersect the period of feed_info.txt from {} to {}",
+        dataset.id,
+        dataset.start_date.format("%Y%m%d"),
+        dataset.end_date.format("%Y%m%d"),
+        format(feed_start_date),
+        format(feed_end_date),
+    );
+    dataset.start_date = start_date;
+    dataset.end_date = end_date;
+    Ok(())
+}
+
 /// Imports a `Model` from the [GTFS](https://gtfs.org/reference/static)
 /// files in the `path` directory.
 ///
@@ -396,6 +937,40 @@ pub fn read<P: AsRef<Path>>(p: P) -> Result<Model> {
     Reader::default().parse(p)
 }
 
+/// Imports a `Model` from several [GTFS](https://gtfs.org/reference/static)
+/// (directories or zipped archives), each one being prefixed with its
+/// `PrefixConfiguration` and merged in this order with
+/// [merge_collections](crate::merge::merge_collections).
+///
+/// The import fails if objects of several inputs still have the same
+/// identifier after the prefixes. The physical and commercial modes, which
+/// are never prefixed, are shared by the inputs. The feed infos with a
+/// different value in several inputs keep the first one, with a warning.
+pub fn read_many<P: AsRef<Path>>(inputs: Vec<(P, PrefixConfiguration)>) -> Result<Model> {
+    let mut merged = Collections::default();
+    for (input, prefix_conf) in inputs {
+        let path = input.as_ref();
+        info!("Reading and merging the GTFS {:?}", path);
+        let configuration = Configuration {
+            prefix_conf: Some(prefix_conf),
+            ..Default::default()
+        };
+        let collections = Reader::new(configuration).parse_collections(path)?;
+        let collisions =
+            merge::merge_collections(&mut merged, collections, merge::ConflictStrategy::Fail)
+                .with_context(|| format!("Error merging the GTFS {:?}", path))?;
+        for collision in collisions {
+            warn!(
+                "{}: the {} {:?} is already defined by a previous GTFS, the first value is kept",
+                path.display(),
+                collision.object_type,
+                collision.object_id
+            );
+        }
+    }
+    Model::new(merged)
+}
+
 /// Structure to configure the GTFS reading
 #[derive(Default)]
 pub struct Reader {
@@ -481,6 +1056,9 @@ impl Reader {
     fn parse_zip_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
         let reader = std::fs::File::open(path.as_ref())?;
         let mut file_handler = ZipHandler::new(reader, path)?;
+        if let Some(memory_budget) = self.configuration.zip_memory_budget {
+            file_handler.decompress_in_memory(memory_budget)?;
+        }
         read_file_handler_to_collections(&mut file_handler, self.configuration)
     }
 
@@ -504,6 +1082,9 @@ impl Reader {
         R: std::io::Seek + std::io::Read,
     {
         let mut file_handler = ZipHandler::new(reader, source_name)?;
+        if let Some(memory_budget) = self.configuration.zip_memory_budget {
+            file_handler.decompress_in_memory(memory_budget)?;
+        }
         read_file_handler(&mut file_handler, self.configuration)
     }
 }
@@ -557,6 +1138,11 @@ struct Route {
     text_color: Option<objects::Rgb>,
     #[serde(rename = "route_sort_order")]
     sort_order: Option<u32>,
+    // Written by `write::write_routes` only when used
+    #[serde(default, skip_serializing, deserialize_with = "de_with_invalid_option")]
+    continuous_pickup: Option<u8>,
+    #[serde(default, skip_serializing, deserialize_with = "de_with_invalid_option")]
+    continuous_drop_off: Option<u8>,
 }
 
 /// Use to serialize extended route type
@@ -610,63 +1196,122 @@ impl From<Route> for ExtendedRoute {
     }
 }
 
-fn to_gtfs_extended_value(route_type: &RouteType) -> String {
-    match *route_type {
-        RouteType::Tramway => "900".to_string(),
-        RouteType::Metro => "400".to_string(),
-        RouteType::Train => "100".to_string(),
-        RouteType::Bus | RouteType::UnknownMode => "700".to_string(),
-        RouteType::Ferry => "1200".to_string(),
-        RouteType::Funicular => "1400".to_string(),
-        RouteType::CableCar | RouteType::SuspendedCableCar => "1300".to_string(),
-        RouteType::Coach => "200".to_string(),
-        RouteType::Air => "1100".to_string(),
-        RouteType::Taxi => "1500".to_string(),
-    }
-}
-
 fn ser_from_route_type_extended<S>(r: &RouteType, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
-    serializer.serialize_str(&to_gtfs_extended_value(r))
+    serializer.serialize_str(&r.to_gtfs_extended_value())
 }
 
 /// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
 /// in the given directory.
 /// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
 pub fn write<P: AsRef<Path>>(model: Model, path: P, extend_route_type: bool) -> Result<()> {
+    let configuration = WriteConfiguration {
+        extend_route_type,
+        ..Default::default()
+    };
+    write_with_configuration(model, path, &configuration)
+}
+
+/// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
+/// in the given directory, using the given `WriteConfiguration`.
+pub fn write_with_configuration<P: AsRef<Path>>(
+    model: Model,
+    path: P,
+    configuration: &WriteConfiguration,
+) -> Result<()> {
     let path = path.as_ref();
     std::fs::create_dir_all(path)?;
     info!("Writing GTFS to {:?}", path);
+    write_file_writer(model, &PathFileWriter::new(path), configuration)
+}
 
-    write::write_transfers(path, &model.transfers)?;
-    write::write_agencies(path, &model.networks)?;
-    write_calendar_dates(path, &model.calendars)?;
+fn write_file_writer<W: FileWriter>(
+    model: Model,
+    file_writer: &W,
+    configuration: &WriteConfiguration,
+) -> Result<()> {
+    ensure!(
+        !model.skipped_geometries,
+        "the geometries were skipped when reading, the model can't be written"
+    );
+    let model = match &configuration.id_sanitization {
+        Some(id_sanitization) => {
+            let (model, rewrites) = sanitize_ids(model, id_sanitization)?;
+            if !rewrites.is_empty() {
+                sanitize_ids::write_sanitized_ids(file_writer, &rewrites)?;
+            }
+            model
+        }
+        None => model,
+    };
+
+    write::write_transfers(
+        file_writer,
+        &model.transfers,
+        &model.trip_transfers,
+        &model.vehicle_journeys,
+        &model.stop_points,
+    )?;
+    write::write_agencies(file_writer, &model)?;
+    write::write_feed_info(file_writer, &model)?;
+    if configuration.attributions {
+        write::write_attributions(file_writer, &model)?;
+    }
+    write_calendar_dates(file_writer, &model.calendars)?;
     write::write_stops(
-        path,
+        file_writer,
         &model.stop_points,
         &model.stop_areas,
         &model.stop_locations,
         &model.comments,
         &model.equipments,
+        write::StopsOptions {
+            skip_generated_stop_areas: configuration.skip_generated_stop_areas,
+            stop_code_source: configuration.stop_code_source.as_deref(),
+        },
+    )?;
+    write::write_trips(file_writer, &model, configuration.one_route_per_line)?;
+    write::write_routes(
+        file_writer,
+        &model,
+        configuration.extend_route_type,
+        configuration.one_route_per_line,
     )?;
-    write::write_trips(path, &model)?;
-    write::write_routes(path, &model, extend_route_type)?;
-    write::write_stop_extensions(path, &model.stop_points, &model.stop_areas)?;
+    write::write_stop_extensions(file_writer, &model.stop_points, &model.stop_areas)?;
     write::write_stop_times(
-        path,
+        file_writer,
         &model.vehicle_journeys,
         &model.stop_points,
         &model.stop_time_headsigns,
+        &model.frequencies,
     )?;
-    write::write_shapes(path, &model.geometries)?;
-    write_collection_with_id(path, "pathways.txt", &model.pathways)?;
-    write_collection_with_id(path, "levels.txt", &model.levels)?;
+    write::write_frequencies(file_writer, &model)?;
+    write::write_shapes(file_writer, &model)?;
+    write::write_pathways(file_writer, &model)?;
+    write_collection_with_id(file_writer, "levels.txt", &model.levels)?;
+    if configuration.fares_v1 || configuration.fares_v2 {
+        write::write_fares_v1(file_writer, &model)?;
+    }
+    if configuration.fares_v2 {
+        write::write_fares_v2(file_writer, &model)?;
+    }
 
     Ok(())
 }
 
+/// Writes the mapping between the identifiers of the `Model` and their codes
+/// in the CSV file `path`, with the columns `object_type`, `ntfs_id`,
+/// `source_code_system` and `source_value`.
+///
+/// The networks, stop areas, stop points, routes and trips read from a GTFS
+/// have a `source` code with their GTFS identifier, so that the mapping can
+/// be used to align a GTFS-RT feed published for the original GTFS.
+pub fn write_id_mapping<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    write::write_id_mapping(path.as_ref(), model)
+}
+
 /// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
 /// in the given ZIP archive.
 /// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
@@ -674,12 +1319,369 @@ pub fn write_to_zip<P: AsRef<std::path::Path>>(
     model: Model,
     path: P,
     extend_route_type: bool,
+) -> Result<()> {
+    let configuration = WriteConfiguration {
+        extend_route_type,
+        ..Default::default()
+    };
+    write_to_zip_with_configuration(model, path, &configuration)
+}
+
+/// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
+/// in the given ZIP archive, using the given `WriteConfiguration`.
+pub fn write_to_zip_with_configuration<P: AsRef<std::path::Path>>(
+    model: Model,
+    path: P,
+    configuration: &WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     info!("Writing GTFS to ZIP File {:?}", path);
-    let input_tmp_dir = tempfile::tempdir()?;
-    write(model, input_tmp_dir.path(), extend_route_type)?;
-    zip_to(input_tmp_dir.path(), path)?;
-    input_tmp_dir.close()?;
+    // The files are written in a temporary directory, then copied in the
+    // archive, not to hold the whole GTFS in memory
+    let output_dir = tempfile::tempdir()?;
+    write_file_writer(
+        model,
+        &PathFileWriter::new(output_dir.path()),
+        configuration,
+    )?;
+    zip_to(output_dir.path(), path)?;
+    output_dir.close()?;
+    Ok(())
+}
+
+/// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
+/// in a ZIP archive written in `writer`, using the given
+/// `WriteConfiguration`, the files being prepared in memory without any
+/// temporary file.
+///
+/// ```
+/// # fn main() -> transit_model::Result<()> {
+/// let model = transit_model::ntfs::read("tests/fixtures/minimal_ntfs")?;
+/// let zip = transit_model::gtfs::write_to_zip_writer(
+///     model,
+///     std::io::Cursor::new(Vec::new()),
+///     &transit_model::gtfs::WriteConfiguration::default(),
+/// )?;
+/// let model = transit_model::gtfs::from_zip_reader(zip, "in-memory GTFS")?;
+/// assert_eq!(1, model.networks.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_to_zip_writer<W: Write + Seek>(
+    model: Model,
+    writer: W,
+    configuration: &WriteConfiguration,
+) -> Result<W> {
+    let file_writer = MemoryFileWriter::new();
+    write_file_writer(model, &file_writer, configuration)?;
+    file_writer.write_zip(writer)
+}
+
+// The collections with only the trips of the lines of the network, with
+// their calendars, frequencies, transfers, geometries and stop time values.
+// The referentials are cloned, their objects unused by the trips being
+// removed when building the model. The stop locations are restricted to the
+// ones of the stops of the trips or linked to them by pathways, as they keep
+// the stops they reference, and the pathways to the ones between these stops
+// and locations.
+fn restrict_to_network(collections: &Collections, network_id: &str) -> Result<Collections> {
+    let line_ids: HashSet<&str> = collections
+        .lines
+        .values()
+        .filter(|line| line.network_id == network_id)
+        .map(|line| line.id.as_str())
+        .collect();
+    let route_ids: HashSet<&str> = collections
+        .routes
+        .values()
+        .filter(|route| line_ids.contains(route.line_id.as_str()))
+        .map(|route| route.id.as_str())
+        .collect();
+    let vehicle_journeys: Vec<_> = collections
+        .vehicle_journeys
+        .values()
+        .filter(|vj| route_ids.contains(vj.route_id.as_str()))
+        .collect();
+    let vj_ids: HashSet<&str> = vehicle_journeys.iter().map(|vj| vj.id.as_str()).collect();
+    let service_ids: HashSet<&str> = vehicle_journeys
+        .iter()
+        .map(|vj| vj.service_id.as_str())
+        .collect();
+    let mut stop_point_ids: HashSet<&str> = HashSet::new();
+    let mut stop_ids: HashSet<&str> = HashSet::new();
+    for vj in &vehicle_journeys {
+        for stop_time in &vj.stop_times {
+            let stop_point = &collections.stop_points[stop_time.stop_point_idx];
+            stop_point_ids.insert(&stop_point.id);
+            stop_ids.insert(&stop_point.id);
+            stop_ids.insert(&stop_point.stop_area_id);
+        }
+    }
+
+    let mut location_ids: HashSet<&str> = collections
+        .stop_locations
+        .values()
+        .filter(|stop_location| {
+            stop_location
+                .parent_id
+                .as_ref()
+                .is_some_and(|parent_id| stop_ids.contains(parent_id.as_str()))
+        })
+        .map(|stop_location| stop_location.id.as_str())
+        .collect();
+    // A location can be linked to the stops through other locations
+    loop {
+        let is_kept = |id: &str| stop_ids.contains(id) || location_ids.contains(id);
+        let linked_ids: Vec<&str> = collections
+            .pathways
+            .values()
+            .flat_map(|pathway| {
+                [
+                    (&pathway.from_stop_id, &pathway.to_stop_id),
+                    (&pathway.to_stop_id, &pathway.from_stop_id),
+                ]
+            })
+            .filter(|(stop_id, linked_id)| {
+                is_kept(stop_id)
+                    && !is_kept(linked_id)
+                    && collections.stop_locations.contains_id(linked_id)
+            })
+            .map(|(_, linked_id)| linked_id.as_str())
+            .collect();
+        if linked_ids.is_empty() {
+            break;
+        }
+        location_ids.extend(linked_ids);
+    }
+    stop_ids.extend(&location_ids);
+
+    let mut geometry_ids: HashSet<&str> = HashSet::new();
+    geometry_ids.extend(
+        collections
+            .lines
+            .values()
+            .filter(|line| line_ids.contains(line.id.as_str()))
+            .filter_map(|line| line.geometry_id.as_deref()),
+    );
+    geometry_ids.extend(
+        collections
+            .routes
+            .values()
+            .filter(|route| route_ids.contains(route.id.as_str()))
+            .filter_map(|route| route.geometry_id.as_deref()),
+    );
+    geometry_ids.extend(
+        vehicle_journeys
+            .iter()
+            .filter_map(|vj| vj.geometry_id.as_deref()),
+    );
+    // The stops are cloned with their geometries
+    geometry_ids.extend(
+        collections
+            .stop_points
+            .values()
+            .filter_map(|stop_point| stop_point.geometry_id.as_deref()),
+    );
+    geometry_ids.extend(
+        collections
+            .stop_areas
+            .values()
+            .filter_map(|stop_area| stop_area.geometry_id.as_deref()),
+    );
+    geometry_ids.extend(
+        collections
+            .stop_locations
+            .values()
+            .filter(|stop_location| location_ids.contains(stop_location.id.as_str()))
+            .filter_map(|stop_location| stop_location.geometry_id.as_deref()),
+    );
+
+    let stop_time_values = |values: &HashMap<(IdString, u32), IdString>| {
+        values
+            .iter()
+            .filter(|((vj_id, _), _)| vj_ids.contains(vj_id.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    };
+    Ok(Collections {
+        contributors: collections.contributors.clone(),
+        datasets: collections.datasets.clone(),
+        networks: CollectionWithId::new(
+            collections
+                .networks
+                .values()
+                .filter(|network| network.id == network_id)
+                .cloned()
+                .collect(),
+        )?,
+        commercial_modes: collections.commercial_modes.clone(),
+        lines: CollectionWithId::new(
+            collections
+                .lines
+                .values()
+                .filter(|line| line_ids.contains(line.id.as_str()))
+                .cloned()
+                .collect(),
+        )?,
+        line_groups: collections.line_groups.clone(),
+        line_group_links: collections.line_group_links.clone(),
+        routes: CollectionWithId::new(
+            collections
+                .routes
+                .values()
+                .filter(|route| route_ids.contains(route.id.as_str()))
+                .cloned()
+                .collect(),
+        )?,
+        vehicle_journeys: CollectionWithId::new(
+            vehicle_journeys.iter().map(|&vj| vj.clone()).collect(),
+        )?,
+        frequencies: Collection::new(
+            collections
+                .frequencies
+                .values()
+                .filter(|frequency| vj_ids.contains(frequency.vehicle_journey_id.as_str()))
+                .cloned()
+                .collect(),
+        ),
+        physical_modes: collections.physical_modes.clone(),
+        stop_areas: collections.stop_areas.clone(),
+        stop_points: collections.stop_points.clone(),
+        stop_locations: CollectionWithId::new(
+            collections
+                .stop_locations
+                .values()
+                .filter(|stop_location| location_ids.contains(stop_location.id.as_str()))
+                .cloned()
+                .collect(),
+        )?,
+        feed_infos: collections.feed_infos.clone(),
+        calendars: CollectionWithId::new(
+            collections
+                .calendars
+                .values()
+                .filter(|calendar| service_ids.contains(calendar.id.as_str()))
+                .cloned()
+                .collect(),
+        )?,
+        companies: collections.companies.clone(),
+        comments: collections.comments.clone(),
+        equipments: collections.equipments.clone(),
+        transfers: Collection::new(
+            collections
+                .transfers
+                .values()
+                .filter(|transfer| {
+                    stop_point_ids.contains(transfer.from_stop_id.as_str())
+                        && stop_point_ids.contains(transfer.to_stop_id.as_str())
+                })
+                .cloned()
+                .collect(),
+        ),
+        trip_transfers: Collection::new(
+            collections
+                .trip_transfers
+                .values()
+                .filter(|trip_transfer| {
+                    vj_ids.contains(trip_transfer.from_vehicle_journey_id.as_str())
+                        && vj_ids.contains(trip_transfer.to_vehicle_journey_id.as_str())
+                })
+                .cloned()
+                .collect(),
+        ),
+        trip_properties: collections.trip_properties.clone(),
+        geometries: CollectionWithId::new(
+            collections
+                .geometries
+                .values()
+                .filter(|geometry| geometry_ids.contains(geometry.id.as_str()))
+                .cloned()
+                .collect(),
+        )?,
+        admin_stations: collections.admin_stations.clone(),
+        stop_time_headsigns: stop_time_values(&collections.stop_time_headsigns),
+        stop_time_ids: stop_time_values(&collections.stop_time_ids),
+        stop_time_comments: stop_time_values(&collections.stop_time_comments),
+        prices_v1: collections.prices_v1.clone(),
+        od_fares_v1: collections.od_fares_v1.clone(),
+        fares_v1: collections.fares_v1.clone(),
+        tickets: collections.tickets.clone(),
+        ticket_uses: collections.ticket_uses.clone(),
+        ticket_prices: collections.ticket_prices.clone(),
+        ticket_use_perimeters: collections.ticket_use_perimeters.clone(),
+        ticket_use_restrictions: collections.ticket_use_restrictions.clone(),
+        pathways: CollectionWithId::new(
+            collections
+                .pathways
+                .values()
+                .filter(|pathway| {
+                    stop_ids.contains(pathway.from_stop_id.as_str())
+                        && stop_ids.contains(pathway.to_stop_id.as_str())
+                })
+                .cloned()
+                .collect(),
+        )?,
+        levels: collections.levels.clone(),
+        booking_rules: collections.booking_rules.clone(),
+        grid_calendars: collections.grid_calendars.clone(),
+        grid_exception_dates: collections.grid_exception_dates.clone(),
+        grid_periods: collections.grid_periods.clone(),
+        grid_rel_calendar_line: collections.grid_rel_calendar_line.clone(),
+        occupancies: collections.occupancies.clone(),
+        addresses: collections.addresses.clone(),
+        brandings: collections.brandings.clone(),
+        translations: collections.translations.clone(),
+        skipped_geometries: collections.skipped_geometries,
+    })
+}
+
+/// Exports a `Model` to one [GTFS](https://gtfs.org/reference/static) ZIP
+/// archive per network in the `path` directory, named after the network
+/// identifier (the characters other than letters, digits, `-` and `_` being
+/// replaced by `_`).
+///
+/// Each archive only contains the lines of the network with their routes,
+/// trips, calendars and stops, the stop areas shared by several networks
+/// being written in each of their archives. The transfers are restricted to
+/// the stops of the archive. No archive is written for a network without
+/// trips. The archives are written with the given `WriteConfiguration`.
+pub fn write_split_by_network<P: AsRef<Path>>(
+    model: Model,
+    path: P,
+    configuration: &WriteConfiguration,
+) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    let collections = model.into_collections();
+    let mut file_names = HashSet::new();
+    for network in collections.networks.values() {
+        let file_name = format!(
+            "{}.zip",
+            network
+                .id
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                    _ => '_',
+                })
+                .collect::<String>()
+        );
+        ensure!(
+            file_names.insert(file_name.clone()),
+            "network {} can't be written in {}, already used by another network",
+            network.id,
+            file_name
+        );
+        let network_collections = restrict_to_network(&collections, &network.id)?;
+        if network_collections.vehicle_journeys.is_empty() {
+            info!("No GTFS written for network {}: it has no trip", network.id);
+            continue;
+        }
+        write_to_zip_with_configuration(
+            Model::new(network_collections)?,
+            path.join(file_name),
+            configuration,
+        )?;
+    }
     Ok(())
 }
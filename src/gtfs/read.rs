@@ -13,30 +13,33 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, DirectionType, Route, RouteType, Shape, Stop, StopLocationType, StopTime, Transfer,
-    TransferType, Trip,
+    Agency, DirectionType, DuplicateStopSequenceStrategy, OnDemandTransportCommentScope, Route,
+    RouteType, RouteTypeModes, Shape, Stop, StopLocationType, StopTime, Transfer, TransferType,
 };
 use crate::{
     file_handler::FileHandler,
+    id_string::{IdString, Interner},
     model::Collections,
     objects::{
-        self, Availability, CommentLinksT, Coord, KeysValues, Pathway, PropertiesMap, StopLocation,
-        StopPoint, StopTime as NtfsStopTime, StopTimePrecision, StopType, Time, TransportType,
-        VehicleJourney,
+        self, Availability, CommentLinksT, Coord, Date, KeysValues, ObjectType, Pathway,
+        PropertiesMap, StopLocation, StopPoint, StopTime as NtfsStopTime, StopTimePrecision,
+        StopType, Time, Translation, TransportType, VehicleJourney,
     },
-    parser::{read_collection, read_objects, read_objects_loose},
-    serde_utils::de_with_empty_default,
+    parser::{read_all_records, read_collection, read_objects, read_objects_loose, read_records},
+    serde_utils::{de_from_u8_with_true_default, de_with_empty_default, de_with_invalid_option},
     Result,
 };
-use anyhow::{anyhow, bail, Error};
+use anyhow::{anyhow, bail, ensure, Context, Error};
+use chrono::{Offset, TimeZone};
+use chrono_tz::Tz;
 use derivative::Derivative;
-use geo::{LineString, Point};
+use geo::{algorithm::simplify::SimplifyIdx, LineString, Point};
 use serde::Deserialize;
 use skip_error::{skip_error_and_warn, SkipError};
 use std::convert::TryFrom;
 use std::{
     cmp,
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 };
 use tracing::{info, warn};
 use typed_index_collection::{impl_id, Collection, CollectionWithId, Idx};
@@ -57,11 +60,53 @@ fn get_agency_id(route: &Route, networks: &CollectionWithId<objects::Network>) -
         })
 }
 
+// The routes without `agency_id` get the only agency of the feed, or else
+// the `default_agency_id`. Without them, all these routes are listed in the
+// error.
+fn fill_route_agency_ids(
+    mut routes: CollectionWithId<Route>,
+    networks: &CollectionWithId<objects::Network>,
+    default_agency_id: Option<&str>,
+) -> Result<CollectionWithId<Route>> {
+    let route_ids: Vec<&str> = routes
+        .values()
+        .filter(|route| route.agency_id.is_none())
+        .map(|route| route.id.as_str())
+        .collect();
+    if route_ids.is_empty() {
+        return Ok(routes);
+    }
+    let agency_id = match (networks.values().next(), default_agency_id) {
+        (Some(network), _) if networks.len() == 1 => network.id.clone(),
+        (_, Some(default_agency_id)) => {
+            ensure!(
+                networks.contains_id(default_agency_id),
+                "the default agency {:?} of the routes without agency_id is not in agency.txt",
+                default_agency_id
+            );
+            default_agency_id.to_string()
+        }
+        (Some(_), None) => bail!(
+            "Impossible to get agency id, several networks found, for the routes {}",
+            route_ids.join(", ")
+        ),
+        (None, None) => bail!(
+            "Impossible to get agency id, no network found, for the routes {}",
+            route_ids.join(", ")
+        ),
+    };
+    let mut routes_with_agency = routes.take();
+    for route in &mut routes_with_agency {
+        route.agency_id.get_or_insert_with(|| agency_id.clone());
+    }
+    Ok(CollectionWithId::new(routes_with_agency)?)
+}
+
 impl From<Agency> for objects::Network {
     fn from(agency: Agency) -> objects::Network {
         let id = agency.id.unwrap_or_else(default_agency_id);
         let mut codes = KeysValues::default();
-        codes.insert(("source".to_string(), id.clone()));
+        codes.insert(("source".into(), id.clone()));
         objects::Network {
             id,
             name: agency.name,
@@ -72,6 +117,7 @@ impl From<Agency> for objects::Network {
             phone: agency.phone,
             address: None,
             sort_order: None,
+            branding_id: None,
         }
     }
 }
@@ -81,6 +127,9 @@ impl From<Agency> for objects::Company {
             id: agency.id.unwrap_or_else(default_agency_id),
             name: agency.name,
             address: None,
+            postcode: None,
+            city: None,
+            registration_number: None,
             url: Some(agency.url),
             mail: agency.email,
             phone: agency.phone,
@@ -93,9 +142,9 @@ impl TryFrom<Stop> for objects::StopArea {
     type Error = Error;
     fn try_from(stop: Stop) -> Result<Self> {
         let mut codes: KeysValues = BTreeSet::new();
-        codes.insert(("source".to_string(), stop.id.clone()));
+        codes.insert(("source".into(), stop.id.clone()));
         if let Some(c) = stop.code.as_ref() {
-            codes.insert(("gtfs_stop_code".to_string(), c.clone()));
+            codes.insert(("gtfs_stop_code".into(), c.clone()));
         }
         if stop.name.is_empty() {
             warn!("stop_id: {}: for station stop_name is required", stop.id);
@@ -126,9 +175,9 @@ impl TryFrom<Stop> for objects::StopPoint {
     type Error = Error;
     fn try_from(stop: Stop) -> Result<Self> {
         let mut codes: KeysValues = BTreeSet::new();
-        codes.insert(("source".to_string(), stop.id.clone()));
+        codes.insert(("source".into(), stop.id.clone()));
         if let Some(c) = stop.code.as_ref() {
-            codes.insert(("gtfs_stop_code".to_string(), c.clone()));
+            codes.insert(("gtfs_stop_code".into(), c.clone()));
         }
         if stop.name.is_empty() {
             warn!("stop_id: {}: for platform name is required", stop.id);
@@ -218,7 +267,7 @@ impl TryFrom<Stop> for objects::StopLocation {
 }
 
 impl RouteType {
-    fn to_gtfs_value(&self) -> String {
+    pub(super) fn to_gtfs_value(&self) -> String {
         match *self {
             RouteType::Tramway => "0".to_string(),
             RouteType::Metro => "1".to_string(),
@@ -234,6 +283,23 @@ impl RouteType {
             RouteType::Funicular => "7".to_string(),
         }
     }
+
+    /// Extended GTFS route_type, see
+    /// https://developers.google.com/transit/gtfs/reference/extended-route-types
+    pub(super) fn to_gtfs_extended_value(&self) -> String {
+        match *self {
+            RouteType::Tramway => "900".to_string(),
+            RouteType::Metro => "400".to_string(),
+            RouteType::Train => "100".to_string(),
+            RouteType::Bus | RouteType::UnknownMode => "700".to_string(),
+            RouteType::Ferry => "1200".to_string(),
+            RouteType::Funicular => "1400".to_string(),
+            RouteType::CableCar | RouteType::SuspendedCableCar => "1300".to_string(),
+            RouteType::Coach => "200".to_string(),
+            RouteType::Air => "1100".to_string(),
+            RouteType::Taxi => "1500".to_string(),
+        }
+    }
 }
 
 impl ::serde::Serialize for RouteType {
@@ -295,7 +361,32 @@ impl Route {
     }
 }
 
-impl Trip {
+// A row of trips.txt, the strings being borrowed from the CSV record
+#[derive(Deserialize)]
+struct TripRecord<'a> {
+    route_id: &'a str,
+    service_id: &'a str,
+    #[serde(rename = "trip_id")]
+    id: &'a str,
+    #[serde(rename = "trip_headsign")]
+    headsign: Option<&'a str>,
+    #[serde(rename = "trip_short_name")]
+    short_name: Option<&'a str>,
+    #[serde(
+        default,
+        deserialize_with = "de_with_empty_default",
+        rename = "direction_id"
+    )]
+    direction: DirectionType,
+    block_id: Option<&'a str>,
+    shape_id: Option<&'a str>,
+    #[serde(deserialize_with = "de_with_empty_default", default)]
+    wheelchair_accessible: Availability,
+    #[serde(deserialize_with = "de_with_empty_default", default)]
+    bikes_allowed: Availability,
+}
+
+impl TripRecord<'_> {
     fn to_ntfs_vehicle_journey(
         &self,
         routes: &CollectionWithId<Route>,
@@ -303,37 +394,67 @@ impl Trip {
         trip_property_id: &Option<String>,
         networks: &CollectionWithId<objects::Network>,
     ) -> Result<objects::VehicleJourney> {
-        let route = match routes.get(&self.route_id) {
+        let route = match routes.get(self.route_id) {
             Some(route) => route,
             None => bail!("Coudn't find route {} for trip {}", self.route_id, self.id),
         };
         let physical_mode = get_physical_mode(&route.route_type);
         let mut codes = KeysValues::default();
-        codes.insert(("source".to_string(), self.id.clone()));
+        codes.insert(("source".into(), self.id.to_string()));
 
         Ok(objects::VehicleJourney {
-            id: self.id.clone(),
+            id: self.id.to_string(),
             codes,
             object_properties: PropertiesMap::default(),
             comment_links: CommentLinksT::default(),
             route_id: route.get_id_by_direction(self.direction),
             physical_mode_id: physical_mode.id,
             dataset_id: dataset.id.clone(),
-            service_id: self.service_id.clone(),
-            headsign: self.short_name.clone().or_else(|| self.headsign.clone()),
+            service_id: self.service_id.to_string(),
+            headsign: self.short_name.or(self.headsign).map(str::to_string),
             short_name: None,
-            block_id: self.block_id.clone(),
+            block_id: self.block_id.map(str::to_string),
             company_id: get_agency_id(route, networks)?,
             trip_property_id: trip_property_id.clone(),
-            geometry_id: self.shape_id.clone(),
+            geometry_id: self.shape_id.map(|shape_id| shape_id.replace('/', "")),
             stop_times: Vec::with_capacity(crate::STOP_TIMES_INIT_CAPACITY),
             journey_pattern_id: None,
         })
     }
 }
 
+// Meters by degree of latitude
+const METERS_BY_DEGREE: f64 = 111_320.0;
+
+// Douglas-Peucker simplification of the points of a shape, with a tolerance
+// in meters. The distances are computed on an equirectangular projection
+// around the first point, accurate enough at the scale of a shape, and the
+// points kept are the original ones.
+fn simplify_shape(points: &[Point<f64>], tolerance: f64) -> Vec<Point<f64>> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let lon_scale = METERS_BY_DEGREE * points[0].y().to_radians().cos();
+    let projected: LineString<f64> = points
+        .iter()
+        .map(|point| (point.x() * lon_scale, point.y() * METERS_BY_DEGREE))
+        .collect();
+    projected
+        .simplify_idx(&tolerance)
+        .into_iter()
+        .map(|idx| points[idx])
+        .collect()
+}
+
 /// Reading rules for mapping vehicle travel paths, sometimes referred to as route alignments.
-pub fn manage_shapes<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
+///
+/// Each shape is simplified with a tolerance of `simplification_tolerance`
+/// meters if set, keeping its first and last points.
+pub fn manage_shapes<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+    simplification_tolerance: Option<f64>,
+) -> Result<()>
 where
     for<'a> &'a mut H: FileHandler,
 {
@@ -351,7 +472,11 @@ where
         map.iter()
             .filter(|(_, points)| !points.is_empty())
             .map(|(id, points)| {
-                let linestring: LineString<f64> = points.to_vec().into();
+                let points = match simplification_tolerance {
+                    Some(tolerance) => simplify_shape(points, tolerance),
+                    None => points.to_vec(),
+                };
+                let linestring: LineString<f64> = points.into();
                 objects::Geometry {
                     id: id.to_string(),
                     geometry: linestring.into(),
@@ -363,54 +488,171 @@ where
     Ok(())
 }
 
+// A row of stop_times.txt, the identifiers and the headsign being borrowed
+// from the CSV record
+#[derive(Deserialize)]
+struct StopTimeRecord<'a> {
+    trip_id: &'a str,
+    arrival_time: Option<Time>,
+    departure_time: Option<Time>,
+    stop_id: &'a str,
+    stop_sequence: u32,
+    #[serde(deserialize_with = "de_with_empty_default", default)]
+    pickup_type: u8,
+    #[serde(deserialize_with = "de_with_empty_default", default)]
+    drop_off_type: u8,
+    local_zone_id: Option<u16>,
+    stop_headsign: Option<&'a str>,
+    #[serde(
+        deserialize_with = "de_from_u8_with_true_default",
+        default = "super::default_true_bool"
+    )]
+    timepoint: bool,
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    continuous_pickup: Option<u8>,
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    continuous_drop_off: Option<u8>,
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    shape_dist_traveled: Option<f64>,
+    // GTFS-Flex: the group of stops of 'location_groups.txt' served when
+    // `stop_id` is empty
+    #[serde(default)]
+    location_group_id: Option<&'a str>,
+    #[serde(default)]
+    pickup_booking_rule_id: Option<&'a str>,
+    #[serde(default)]
+    drop_off_booking_rule_id: Option<&'a str>,
+}
+
 /// Reading times that a vehicle arrives at and departs from stops for each trip
 pub fn manage_stop_times<H>(
     collections: &mut Collections,
     file_handler: &mut H,
     on_demand_transport: bool,
     on_demand_transport_comment: Option<String>,
+    on_demand_transport_comment_scope: OnDemandTransportCommentScope,
+    duplicate_stop_sequences: DuplicateStopSequenceStrategy,
 ) -> Result<()>
 where
     for<'a> &'a mut H: FileHandler,
 {
     let file_name = "stop_times.txt";
     let mut headsigns = HashMap::new();
+    let mut interner = Interner::default();
     let mut tmp_vjs = BTreeMap::new();
-    let stop_times = read_objects::<_, StopTime>(file_handler, file_name, true)?;
-
-    for stop_time in stop_times {
-        if let Some(vj_idx) = collections.vehicle_journeys.get_idx(&stop_time.trip_id) {
-            tmp_vjs
-                .entry(vj_idx)
-                .or_insert_with(Vec::new)
-                .push(stop_time);
+    read_records(file_handler, file_name, true, |headers, record| {
+        let stop_time: StopTimeRecord = record.deserialize(Some(headers))?;
+        let vj_idx = match collections.vehicle_journeys.get_idx(stop_time.trip_id) {
+            Some(vj_idx) => vj_idx,
+            None => {
+                warn!(
+                    "Problem reading {:?}: trip_id={:?} not found. Skipping this stop_time",
+                    file_name, stop_time.trip_id
+                );
+                return Ok(());
+            }
+        };
+        let stop_id = match stop_time.location_group_id {
+            Some(location_group_id) if stop_time.stop_id.is_empty() => location_group_id,
+            _ => stop_time.stop_id,
+        };
+        let stop_id = if stop_id.contains('/') {
+            interner.intern(&stop_id.replace('/', ""))
         } else {
-            warn!(
-                "Problem reading {:?}: trip_id={:?} not found. Skipping this stop_time",
-                file_name, stop_time.trip_id
-            )
-        }
-    }
-
-    for (vj_idx, mut stop_times) in tmp_vjs {
-        stop_times.sort_unstable_by_key(|st| st.stop_sequence);
-        stop_times.dedup_by(|st2, st1| {
-            let is_same_seq = st2.stop_sequence == st1.stop_sequence;
-            if is_same_seq {
+            interner.intern(stop_id)
+        };
+        let mut booking_rule = |field: &str, booking_rule_id: Option<&str>| {
+            let booking_rule_id = booking_rule_id?;
+            if collections.booking_rules.contains_id(booking_rule_id) {
+                Some(interner.intern(booking_rule_id))
+            } else {
                 warn!(
-                    "remove duplicated stop_sequence '{}' of trip '{}'",
-                    st2.stop_sequence, st2.trip_id
+                    "Problem reading {:?}: {}={:?} of trip {:?} not found in booking_rules.txt",
+                    file_name, field, booking_rule_id, stop_time.trip_id
                 );
+                None
             }
-            is_same_seq
-        });
-        let st_values = interpolate_undefined_stop_times(
-            &collections.vehicle_journeys[vj_idx].id,
-            &stop_times,
-        )?;
+        };
+        let pickup_booking_rule_id =
+            booking_rule("pickup_booking_rule_id", stop_time.pickup_booking_rule_id);
+        let drop_off_booking_rule_id = booking_rule(
+            "drop_off_booking_rule_id",
+            stop_time.drop_off_booking_rule_id,
+        );
+        tmp_vjs
+            .entry(vj_idx)
+            .or_insert_with(Vec::new)
+            .push(StopTime {
+                trip_id: interner.intern(stop_time.trip_id),
+                arrival_time: stop_time.arrival_time,
+                departure_time: stop_time.departure_time,
+                stop_id,
+                stop_sequence: stop_time.stop_sequence,
+                pickup_type: stop_time.pickup_type,
+                drop_off_type: stop_time.drop_off_type,
+                local_zone_id: stop_time.local_zone_id,
+                stop_headsign: stop_time.stop_headsign.map(|h| interner.intern(h)),
+                timepoint: stop_time.timepoint,
+                continuous_pickup: continuous_stopping(
+                    file_name,
+                    "continuous_pickup",
+                    stop_time.trip_id,
+                    stop_time.continuous_pickup,
+                ),
+                continuous_drop_off: continuous_stopping(
+                    file_name,
+                    "continuous_drop_off",
+                    stop_time.trip_id,
+                    stop_time.continuous_drop_off,
+                ),
+                shape_dist_traveled: stop_time.shape_dist_traveled,
+                pickup_booking_rule_id,
+                drop_off_booking_rule_id,
+            });
+        Ok(())
+    })?;
+
+    let mut rejected_vjs = HashSet::new();
+    let mut repaired_vjs = 0;
+    let mut repaired_stop_times = 0;
+    for (vj_idx, mut stop_times) in tmp_vjs {
+        let vj_id = &collections.vehicle_journeys[vj_idx].id;
+        let repaired = repair_stop_sequences(vj_id, &mut stop_times, duplicate_stop_sequences)?;
+        if repaired > 0 {
+            repaired_vjs += 1;
+            repaired_stop_times += repaired;
+        }
+        let st_values = match interpolate_undefined_stop_times(vj_id, &stop_times) {
+            Ok(st_values) => st_values,
+            Err(e) => {
+                warn!("{}, the trip '{}' is ignored", e, vj_id);
+                rejected_vjs.insert(vj_id.clone());
+                continue;
+            }
+        };
         let company_idx = collections
             .companies
             .get_idx(&collections.vehicle_journeys[vj_idx].company_id);
+        // The stop times without continuous stopping take the one of the line
+        let line = collections
+            .routes
+            .get(&collections.vehicle_journeys[vj_idx].route_id)
+            .and_then(|route| collections.lines.get(&route.line_id));
+        let line_properties = line.map(|line| &line.object_properties);
+        let line_continuous_stopping = |name: &str| {
+            line_properties
+                .and_then(|properties| properties.get(name))
+                .and_then(|value| value.parse::<u8>().ok())
+        };
+        let line_continuous_pickup = line_continuous_stopping("continuous_pickup");
+        let line_continuous_drop_off = line_continuous_stopping("continuous_drop_off");
+        let odt_comment_id = match on_demand_transport_comment_scope {
+            OnDemandTransportCommentScope::Agency => {
+                company_idx.map(|company_idx| &collections.companies[company_idx].id)
+            }
+            OnDemandTransportCommentScope::Line => line.map(|line| &line.id),
+        }
+        .map(|id| format!("ODT:{}", id));
 
         for (stop_time, st_values) in stop_times.iter().zip(st_values) {
             if let Some(stop_point_idx) = collections.stop_points.get_idx(&stop_time.stop_id) {
@@ -431,10 +673,14 @@ where
 
                 if let Some(message) = on_demand_transport_comment.as_ref() {
                     if stop_time.pickup_type == 2 || stop_time.drop_off_type == 2 {
-                        if let Some(company_idx) = company_idx {
+                        if let (Some(company_idx), Some(comment_id)) =
+                            (company_idx, odt_comment_id.as_ref())
+                        {
                             manage_odt_comment_from_stop_time(
                                 collections,
+                                &mut interner,
                                 message,
+                                comment_id,
                                 company_idx,
                                 vj_idx,
                                 stop_time,
@@ -466,6 +712,18 @@ where
                         drop_off_type,
                         local_zone_id: stop_time.local_zone_id,
                         precision,
+                        continuous_pickup: stop_time.continuous_pickup.or(line_continuous_pickup),
+                        continuous_drop_off: stop_time
+                            .continuous_drop_off
+                            .or(line_continuous_drop_off),
+                        pickup_booking_rule_id: stop_time
+                            .pickup_booking_rule_id
+                            .as_ref()
+                            .map(IdString::to_string),
+                        drop_off_booking_rule_id: stop_time
+                            .drop_off_booking_rule_id
+                            .as_ref()
+                            .map(IdString::to_string),
                     });
             } else {
                 warn!(
@@ -476,26 +734,201 @@ where
         }
     }
 
+    if repaired_vjs > 0 {
+        info!(
+            "{} stop_times with a duplicated stop_sequence repaired ({}) in {} trips",
+            repaired_stop_times, duplicate_stop_sequences, repaired_vjs
+        );
+    }
     collections.stop_time_headsigns = headsigns;
+    if !rejected_vjs.is_empty() {
+        let mut vehicle_journeys = collections.vehicle_journeys.take();
+        vehicle_journeys.retain(|vj| !rejected_vjs.contains(&vj.id));
+        collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+    }
+
+    Ok(())
+}
 
+// Sort the stop times of a trip by `stop_sequence`, the ones with the same
+// `stop_sequence` staying in the order of the file, and repair them with the
+// strategy. Return the number of stop times sharing the `stop_sequence` of a
+// previous one.
+fn repair_stop_sequences(
+    vj_id: &str,
+    stop_times: &mut Vec<StopTime>,
+    strategy: DuplicateStopSequenceStrategy,
+) -> Result<usize> {
+    stop_times.sort_by_key(|st| st.stop_sequence);
+    let duplicates = stop_times
+        .windows(2)
+        .filter(|pair| pair[0].stop_sequence == pair[1].stop_sequence)
+        .count();
+    if duplicates == 0 {
+        return Ok(0);
+    }
+    match strategy {
+        DuplicateStopSequenceStrategy::Fail => bail!(
+            "the trip '{}' has {} stop_times with a duplicated stop_sequence",
+            vj_id,
+            duplicates
+        ),
+        DuplicateStopSequenceStrategy::KeepFirst => {
+            warn!(
+                "the trip '{}' has {} stop_times with a duplicated stop_sequence, only the first ones are kept",
+                vj_id, duplicates
+            );
+            stop_times.dedup_by_key(|st| st.stop_sequence);
+        }
+        DuplicateStopSequenceStrategy::Renumber => {
+            warn!(
+                "the trip '{}' has {} stop_times with a duplicated stop_sequence, the stop_times are renumbered",
+                vj_id, duplicates
+            );
+            let mut previous: Option<u32> = None;
+            for st in stop_times.iter_mut() {
+                if let Some(previous) = previous.filter(|previous| st.stop_sequence <= *previous) {
+                    st.stop_sequence = previous + 1;
+                }
+                previous = Some(st.stop_sequence);
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Conversion of the times of the stop times at a stop with a `stop_timezone`
+/// into the timezone of the dataset (the one of the agencies), for the GTFS
+/// giving these times in the timezone of the stop.
+///
+/// The difference between the timezones is the one of the first date of the
+/// trip. A time earlier than the previous one of the trip, the trip crossing
+/// midnight in a timezone, is moved to the next day. A trip with a time
+/// before midnight of its first day once converted is kept unchanged with a
+/// warning.
+pub fn apply_stop_timezones(collections: &mut Collections) -> Result<()> {
+    const DAY: i64 = 24 * 60 * 60;
+    let dataset_timezone = match collections
+        .networks
+        .values()
+        .find_map(|network| network.timezone)
+    {
+        Some(timezone) => timezone,
+        None => return Ok(()),
+    };
+    let offset = |timezone: Tz, date: objects::Date| -> i64 {
+        let noon = date.and_hms_opt(12, 0, 0).unwrap_or_default();
+        i64::from(
+            timezone
+                .offset_from_utc_datetime(&noon)
+                .fix()
+                .local_minus_utc(),
+        )
+    };
+    let mut vehicle_journeys = collections.vehicle_journeys.take();
+    for vehicle_journey in &mut vehicle_journeys {
+        let timezones: Vec<Option<Tz>> = vehicle_journey
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                collections.stop_points[stop_time.stop_point_idx]
+                    .timezone
+                    .filter(|timezone| *timezone != dataset_timezone)
+            })
+            .collect();
+        if timezones.iter().all(Option::is_none) {
+            continue;
+        }
+        let date = match collections
+            .calendars
+            .get(&vehicle_journey.service_id)
+            .and_then(|calendar| calendar.dates.iter().next())
+        {
+            Some(date) => *date,
+            None => continue,
+        };
+        let dataset_offset = offset(dataset_timezone, date);
+        let mut day_shift = 0;
+        let mut previous = i64::MIN;
+        let mut times = Vec::with_capacity(vehicle_journey.stop_times.len());
+        for (stop_time, timezone) in vehicle_journey.stop_times.iter().zip(&timezones) {
+            let shift = timezone.map_or(0, |timezone| offset(timezone, date) - dataset_offset);
+            let mut arrival = i64::from(stop_time.arrival_time.total_seconds()) - shift;
+            let mut departure = i64::from(stop_time.departure_time.total_seconds()) - shift;
+            while arrival + day_shift < previous {
+                day_shift += DAY;
+            }
+            arrival += day_shift;
+            departure += day_shift;
+            previous = departure;
+            times.push((arrival, departure));
+        }
+        if times.iter().any(|(arrival, _)| *arrival < 0) {
+            warn!(
+                "trip {}: the times converted from the stop timezones are before midnight, the times are kept unchanged",
+                vehicle_journey.id
+            );
+            continue;
+        }
+        let time = |seconds: i64| Time::new(0, 0, seconds as u32);
+        for (stop_time, (arrival, departure)) in vehicle_journey.stop_times.iter_mut().zip(times) {
+            stop_time.arrival_time = time(arrival);
+            stop_time.departure_time = time(departure);
+        }
+    }
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
     Ok(())
 }
 
+// The values of `continuous_pickup` and `continuous_drop_off` other than 0
+// to 3 are considered as not available
+fn continuous_stopping(file_name: &str, field: &str, id: &str, value: Option<u8>) -> Option<u8> {
+    match value {
+        Some(value) if value > 3 => {
+            warn!(
+                "Problem reading {:?}: {}={} of {:?} is invalid, considered as not available",
+                file_name, field, value, id
+            );
+            None
+        }
+        value => value,
+    }
+}
+
+// The times of the stop times without departure/arrival between `before` and
+// `after`, proportional to the `shape_dist_traveled` when all of them are
+// defined and increasing, evenly distributed otherwise
 fn ventilate_stop_times(
     undefined_stop_times: &[&StopTime],
     before: &StopTimesValues,
     after: &StopTimesValues,
 ) -> Vec<StopTimesValues> {
-    let duration = after.arrival_time - before.departure_time;
+    let duration = (after.arrival_time - before.departure_time).total_seconds();
+    let distances: Option<Vec<f64>> = std::iter::once(before.shape_dist_traveled)
+        .chain(undefined_stop_times.iter().map(|st| st.shape_dist_traveled))
+        .chain(std::iter::once(after.shape_dist_traveled))
+        .collect();
+    let distances = distances.filter(|distances| {
+        distances.windows(2).all(|pair| pair[0] <= pair[1])
+            && distances[0] < distances[distances.len() - 1]
+    });
     let step = duration / (undefined_stop_times.len() + 1) as u32;
     let mut res = vec![];
     for idx in 0..undefined_stop_times.len() {
-        let num = idx as u32 + 1u32;
-        let time = before.departure_time + objects::Time::new(0, 0, num * step.total_seconds());
+        let offset = match &distances {
+            Some(distances) => {
+                let ratio = (distances[idx + 1] - distances[0])
+                    / (distances[distances.len() - 1] - distances[0]);
+                (f64::from(duration) * ratio).round() as u32
+            }
+            None => (idx as u32 + 1) * step,
+        };
+        let time = before.departure_time + objects::Time::new(0, 0, offset);
         res.push(StopTimesValues {
             departure_time: time,
             arrival_time: time,
             precision: StopTimePrecision::Approximate,
+            shape_dist_traveled: undefined_stop_times[idx].shape_dist_traveled,
         });
     }
     res
@@ -506,6 +939,7 @@ struct StopTimesValues {
     arrival_time: Time,
     departure_time: Time,
     precision: StopTimePrecision,
+    shape_dist_traveled: Option<f64>,
 }
 
 // in the GTFS some stoptime can have undefined departure/arrival (all stop_times but the first and the last)
@@ -543,6 +977,7 @@ fn interpolate_undefined_stop_times(
             } else {
                 StopTimePrecision::Exact
             },
+            shape_dist_traveled: st.shape_dist_traveled,
         };
 
         if !undefined_stops_bulk.is_empty() {
@@ -642,15 +1077,16 @@ fn insert_comment<'c, T: typed_index_collection::Id<T> + objects::CommentLinks>(
 
 fn manage_odt_comment_from_stop_time(
     collections: &mut Collections,
+    interner: &mut Interner,
     on_demand_transport_comment: &str,
+    comment_id: &str,
     company_idx: Idx<objects::Company>,
     vj_idx: Idx<objects::VehicleJourney>,
     stop_time: &StopTime,
 ) {
-    let comment_id = format!("ODT:{}", collections.companies[company_idx].id);
-    if !collections.comments.contains_id(&comment_id) {
+    if !collections.comments.contains_id(comment_id) {
         let comment = objects::Comment {
-            id: comment_id.clone(),
+            id: comment_id.to_string(),
             comment_type: objects::CommentType::OnDemandTransport,
             label: None,
             name: on_demand_transport_comment
@@ -667,21 +1103,15 @@ fn manage_odt_comment_from_stop_time(
         // Ok to unwrap since we already tested for existence of the identifier
         collections.comments.push(comment).unwrap();
     }
+    let vj_id = interner.intern(&collections.vehicle_journeys[vj_idx].id);
     collections.stop_time_comments.insert(
-        (
-            collections.vehicle_journeys[vj_idx].id.to_string(),
-            stop_time.stop_sequence,
-        ),
-        comment_id,
+        (vj_id.clone(), stop_time.stop_sequence),
+        interner.intern(comment_id),
     );
     let stop_time_id = format!("{}-{}", stop_time.trip_id, stop_time.stop_sequence);
-    collections.stop_time_ids.insert(
-        (
-            collections.vehicle_journeys[vj_idx].id.to_string(),
-            stop_time.stop_sequence,
-        ),
-        stop_time_id,
-    );
+    collections
+        .stop_time_ids
+        .insert((vj_id, stop_time.stop_sequence), stop_time_id.into());
 }
 
 /// To associate a list of equipment with a stop
@@ -752,7 +1182,8 @@ where
 {
     info!("Reading stops.txt");
     let file = "stops.txt";
-    let gtfs_stops = read_objects::<_, Stop>(file_handler, file, true)?;
+    let mut gtfs_stops = read_objects::<_, Stop>(file_handler, file, true)?;
+    break_parent_station_cycles(&mut gtfs_stops);
     let mut stop_areas = vec![];
     let mut stop_points = vec![];
     let mut stop_locations = vec![];
@@ -779,6 +1210,12 @@ where
                 stop_points.push(stop_point);
             }
             StopLocationType::StopArea => {
+                if let Some(platform_code) = &stop.platform_code {
+                    warn!(
+                        "Problem reading {:?}: platform_code={:?} of the station {:?} is ignored, only a platform has one",
+                        file, platform_code, stop.id
+                    );
+                }
                 let mut stop_area = skip_error_and_warn!(objects::StopArea::try_from(stop));
                 stop_area.comment_links = comment_links;
                 stop_area.equipment_id = equipment_id;
@@ -792,12 +1229,133 @@ where
             }
         }
     }
+    inherit_station_equipments(&mut stop_points, &stop_areas);
     let stoppoints = CollectionWithId::new(stop_points)?;
     let stopareas = CollectionWithId::new(stop_areas)?;
+    let stop_locations = resolve_stop_location_parents(stop_locations, &stoppoints, &stopareas);
     let stoplocations = CollectionWithId::new(stop_locations)?;
     Ok((stopareas, stoppoints, stoplocations))
 }
 
+// A stop being its own ancestor through the `parent_station`, the parent of
+// the last stop of the cycle is removed, so that a stop area is generated for
+// it if it is a platform. The stations end the chains of parents, their
+// `parent_station` being ignored.
+fn break_parent_station_cycles(stops: &mut [Stop]) {
+    let orphans: HashSet<String> = {
+        let parents: HashMap<&str, &str> = stops
+            .iter()
+            .filter(|stop| stop.location_type != StopLocationType::StopArea)
+            .filter_map(|stop| Some((stop.id.as_str(), stop.parent_station.as_deref()?)))
+            .collect();
+        let mut checked: HashSet<&str> = HashSet::new();
+        let mut orphans = HashSet::new();
+        for stop in stops.iter() {
+            let mut chain: Vec<&str> = vec![];
+            let mut current = Some(stop.id.as_str());
+            while let Some(id) = current {
+                if checked.contains(id) {
+                    break;
+                }
+                if let Some(position) = chain.iter().position(|chained| *chained == id) {
+                    let cycle = &chain[position..];
+                    let last = cycle[cycle.len() - 1];
+                    warn!(
+                        "stops.txt: the parent_station of the stops {} -> {} form a cycle, the parent_station of {} is removed",
+                        cycle.join(" -> "),
+                        id,
+                        last
+                    );
+                    orphans.insert(last.to_string());
+                    break;
+                }
+                chain.push(id);
+                current = parents.get(id).copied();
+            }
+            checked.extend(chain);
+        }
+        orphans
+    };
+    for stop in stops {
+        if orphans.contains(&stop.id) {
+            stop.parent_station = None;
+        }
+    }
+}
+
+// A platform with an empty `wheelchair_boarding` inherits the one of its
+// station. The equipment of the station, holding only its
+// `wheelchair_boarding`, is shared by its platforms.
+fn inherit_station_equipments(
+    stop_points: &mut [objects::StopPoint],
+    stop_areas: &[objects::StopArea],
+) {
+    let equipment_ids: HashMap<&str, &String> = stop_areas
+        .iter()
+        .filter_map(|stop_area| {
+            let equipment_id = stop_area.equipment_id.as_ref()?;
+            Some((stop_area.id.as_str(), equipment_id))
+        })
+        .collect();
+    for stop_point in stop_points
+        .iter_mut()
+        .filter(|stop_point| stop_point.equipment_id.is_none())
+    {
+        if let Some(equipment_id) = equipment_ids.get(stop_point.stop_area_id.as_str()) {
+            stop_point.equipment_id = Some(equipment_id.to_string());
+        }
+    }
+}
+
+// The parent of an entrance or a generic node must be a station and the one
+// of a boarding area a platform: an entrance or a generic node in a platform
+// is moved to the station of the platform, and a boarding area out of a
+// platform is removed. The unknown parents are left to the checking of the
+// references of the model.
+fn resolve_stop_location_parents(
+    stop_locations: Vec<objects::StopLocation>,
+    stop_points: &CollectionWithId<objects::StopPoint>,
+    stop_areas: &CollectionWithId<objects::StopArea>,
+) -> Vec<objects::StopLocation> {
+    let stop_location_ids: HashSet<String> = stop_locations
+        .iter()
+        .map(|stop_location| stop_location.id.clone())
+        .collect();
+    stop_locations
+        .into_iter()
+        .filter_map(|mut stop_location| {
+            let parent_id = match &stop_location.parent_id {
+                Some(parent_id) => parent_id,
+                None => return Some(stop_location),
+            };
+            match stop_location.stop_type {
+                StopType::BoardingArea => {
+                    if !stop_points.contains_id(parent_id)
+                        && (stop_areas.contains_id(parent_id)
+                            || stop_location_ids.contains(parent_id))
+                    {
+                        warn!(
+                            "stop_id: {}: the parent_station {} of a boarding area must be a platform, the boarding area is ignored",
+                            stop_location.id, parent_id
+                        );
+                        return None;
+                    }
+                }
+                _ => {
+                    if let Some(stop_point) = stop_points.get(parent_id) {
+                        warn!(
+                            "stop_id: {}: the parent_station {} is a platform, the station {} is used instead",
+                            stop_location.id, parent_id, stop_point.stop_area_id
+                        );
+                        stop_location.parent_id = Some(stop_point.stop_area_id.clone());
+                    }
+                }
+            }
+            Some(stop_location)
+        })
+        .collect()
+}
+
 /// Reading pathways linking together locations within stations.
 pub fn manage_pathways<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
 where
@@ -839,12 +1397,186 @@ where
                     pathway.to_stop_id
                 )
             }));
+        // Only a bidirectional pathway can be walked in the reversed direction
+        if !pathway.is_bidirectional && pathway.reversed_signposted_as.is_some() {
+            warn!(
+                "Problem reading {:?}: reversed_signposted_as of the unidirectional pathway {:?} ignored",
+                file, pathway.id
+            );
+            pathway.reversed_signposted_as = None;
+        }
         pathways.push(pathway);
     }
     collections.pathways = CollectionWithId::new(pathways)?;
     Ok(())
 }
 
+// A row of the GTFS-Flex 'location_groups.txt', a stop of a group
+#[derive(Deserialize)]
+struct LocationGroupRecord {
+    location_group_id: String,
+    #[serde(alias = "stop_id")]
+    location_id: String,
+    location_group_name: Option<String>,
+}
+
+/// Reading the groups of stops of the GTFS-Flex 'location_groups.txt'. Each
+/// group becomes a zonal stop point, at the centroid of its stops, in a
+/// generated stop area, so that the stop times of the on demand transport
+/// serving the group can refer to it.
+pub fn read_location_groups<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file_name = "location_groups.txt";
+    let records: Vec<LocationGroupRecord> = read_objects(file_handler, file_name, false)?;
+    let mut groups: BTreeMap<String, (Option<String>, Vec<Coord>)> = BTreeMap::new();
+    for record in records {
+        let group = groups
+            .entry(record.location_group_id.clone())
+            .or_insert_with(|| (None, vec![]));
+        if group.0.is_none() {
+            group.0 = record.location_group_name;
+        }
+        match collections.stop_points.get(&record.location_id) {
+            Some(stop_point) => group.1.push(stop_point.coord),
+            None => warn!(
+                "Problem reading {:?}: stop {:?} of the group {:?} not found",
+                file_name, record.location_id, record.location_group_id
+            ),
+        }
+    }
+    for (id, (name, coords)) in groups {
+        if coords.is_empty() {
+            warn!(
+                "Problem reading {:?}: the group {:?} has no stop, it is ignored",
+                file_name, id
+            );
+            continue;
+        }
+        if collections.stop_points.contains_id(&id) {
+            warn!(
+                "Problem reading {:?}: the group {:?} has the identifier of a stop, it is ignored",
+                file_name, id
+            );
+            continue;
+        }
+        let count = coords.len() as f64;
+        let coord = Coord {
+            lon: coords.iter().map(|coord| coord.lon).sum::<f64>() / count,
+            lat: coords.iter().map(|coord| coord.lat).sum::<f64>() / count,
+        };
+        let stop_point = StopPoint {
+            name: name.unwrap_or_else(|| id.clone()),
+            id,
+            visible: true,
+            coord,
+            stop_type: StopType::Zone,
+            ..Default::default()
+        };
+        let stop_area = objects::StopArea::from(stop_point.clone());
+        let stop_point = StopPoint {
+            stop_area_id: stop_area.id.clone(),
+            ..stop_point
+        };
+        if !collections.stop_areas.contains_id(&stop_area.id) {
+            collections.stop_areas.push(stop_area)?;
+        }
+        collections.stop_points.push(stop_point)?;
+    }
+    Ok(())
+}
+
+/// Removing the references of the stops to the levels not in 'levels.txt',
+/// with a warning.
+pub fn remove_unknown_levels(collections: &mut Collections) -> Result<()> {
+    fn remove_unknown_level(
+        levels: &CollectionWithId<objects::Level>,
+        stop_id: &str,
+        level_id: &mut Option<String>,
+    ) {
+        if let Some(id) = level_id {
+            if !levels.contains_id(id) {
+                warn!(
+                    "Problem reading \"stops.txt\": level_id={:?} of stop {:?} not found in levels.txt",
+                    id, stop_id
+                );
+                *level_id = None;
+            }
+        }
+    }
+
+    let levels = &collections.levels;
+    let mut stop_points = collections.stop_points.take();
+    for stop_point in &mut stop_points {
+        remove_unknown_level(levels, &stop_point.id, &mut stop_point.level_id);
+    }
+    collections.stop_points = CollectionWithId::new(stop_points)?;
+    let mut stop_areas = collections.stop_areas.take();
+    for stop_area in &mut stop_areas {
+        remove_unknown_level(levels, &stop_area.id, &mut stop_area.level_id);
+    }
+    collections.stop_areas = CollectionWithId::new(stop_areas)?;
+    let mut stop_locations = collections.stop_locations.take();
+    for stop_location in &mut stop_locations {
+        remove_unknown_level(levels, &stop_location.id, &mut stop_location.level_id);
+    }
+    collections.stop_locations = CollectionWithId::new(stop_locations)?;
+    Ok(())
+}
+
+/// Removing the trips with less than 2 stop times, which can't be traveled,
+/// with the frequencies, the stop time identifiers, headsigns and comments of
+/// these trips and the trip properties they were the only ones to use.
+pub fn remove_trips_without_enough_stop_times(collections: &mut Collections) -> Result<()> {
+    let mut vehicle_journeys = collections.vehicle_journeys.take();
+    let mut removed_ids = HashSet::new();
+    vehicle_journeys.retain(|vj| {
+        if vj.stop_times.len() < 2 {
+            removed_ids.insert(vj.id.clone());
+            false
+        } else {
+            true
+        }
+    });
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+    if removed_ids.is_empty() {
+        return Ok(());
+    }
+    let mut sorted_ids: Vec<&str> = removed_ids.iter().map(String::as_str).collect();
+    sorted_ids.sort_unstable();
+    warn!(
+        "removed {} trips with insufficient stop_times",
+        removed_ids.len()
+    );
+    tracing::debug!(
+        "trips with insufficient stop_times: {}",
+        sorted_ids.join(", ")
+    );
+
+    collections
+        .frequencies
+        .retain(|frequency| !removed_ids.contains(&frequency.vehicle_journey_id));
+    collections
+        .stop_time_ids
+        .retain(|(vj_id, _), _| !removed_ids.contains(vj_id.as_str()));
+    collections
+        .stop_time_headsigns
+        .retain(|(vj_id, _), _| !removed_ids.contains(vj_id.as_str()));
+    collections
+        .stop_time_comments
+        .retain(|(vj_id, _), _| !removed_ids.contains(vj_id.as_str()));
+    let trip_property_ids: HashSet<&str> = collections
+        .vehicle_journeys
+        .values()
+        .filter_map(|vj| vj.trip_property_id.as_deref())
+        .collect();
+    let mut trip_properties = collections.trip_properties.take();
+    trip_properties.retain(|trip_property| trip_property_ids.contains(trip_property.id.as_str()));
+    collections.trip_properties = CollectionWithId::new(trip_properties)?;
+    Ok(())
+}
+
 /// Reading rules for making connections at transfer points between routes.
 pub fn read_transfers<H>(
     file_handler: &mut H,
@@ -859,7 +1591,20 @@ where
 
     let mut transfers = vec![];
     for transfer in gtfs_transfers {
-        let expand_stop_area = |stop_id: &str| -> Result<Vec<&StopPoint>> {
+        // The transfers between trips are read by `read_trip_transfers`
+        if matches!(
+            transfer.transfer_type,
+            TransferType::InSeat | TransferType::InSeatNotAllowed
+        ) {
+            continue;
+        }
+        let expand_stop_area = |stop_id: &Option<String>| -> Result<Vec<&StopPoint>> {
+            let stop_id = stop_id.as_deref().ok_or_else(|| {
+                anyhow!(
+                    "Problem reading {:?}: from_stop_id and to_stop_id are required",
+                    file
+                )
+            })?;
             if stop_areas.get(stop_id).is_some() {
                 let list_stop_points = stop_points
                     .values()
@@ -879,9 +1624,8 @@ where
                     .map(|stop_point| vec![stop_point])
             }
         };
-        let from_stop_points =
-            skip_error_and_warn!(expand_stop_area(transfer.from_stop_id.as_str()));
-        let to_stop_points = skip_error_and_warn!(expand_stop_area(transfer.to_stop_id.as_str()));
+        let from_stop_points = skip_error_and_warn!(expand_stop_area(&transfer.from_stop_id));
+        let to_stop_points = skip_error_and_warn!(expand_stop_area(&transfer.to_stop_id));
         for from_stop_point in &from_stop_points {
             let approx = from_stop_point.coord.approx();
             for to_stop_point in &to_stop_points {
@@ -903,6 +1647,7 @@ where
                         (transfer.min_transfer_time, transfer.min_transfer_time)
                     }
                     TransferType::NotPossible => (Some(86400), Some(86400)),
+                    TransferType::InSeat | TransferType::InSeatNotAllowed => unreachable!(),
                 };
 
                 transfers.push(objects::Transfer {
@@ -911,6 +1656,11 @@ where
                     min_transfer_time,
                     real_min_transfer_time,
                     equipment_id: None,
+                    transfer_type: if transfer.transfer_type == TransferType::NotPossible {
+                        objects::TransferType::NotPossible
+                    } else {
+                        objects::TransferType::Possible
+                    },
                 });
             }
         }
@@ -919,6 +1669,51 @@ where
     Ok(Collection::new(transfers))
 }
 
+/// Reading the transfers between trips of `transfers.txt`, the passengers
+/// staying on board (`transfer_type` 4) or having to alight and board again
+/// (`transfer_type` 5).
+///
+/// The rows referencing an unknown trip are ignored.
+pub fn read_trip_transfers<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file = "transfers.txt";
+    let gtfs_transfers = read_objects_loose::<_, Transfer>(file_handler, file, false)?;
+    let mut trip_transfers = vec![];
+    for transfer in gtfs_transfers {
+        let in_seat = match transfer.transfer_type {
+            TransferType::InSeat => true,
+            TransferType::InSeatNotAllowed => false,
+            _ => continue,
+        };
+        let trip_id = |trip_id: Option<String>| -> Result<String> {
+            match trip_id {
+                Some(trip_id) if collections.vehicle_journeys.contains_id(&trip_id) => Ok(trip_id),
+                Some(trip_id) => bail!(
+                    "Problem reading {:?}: trip_id={:?} not found",
+                    file,
+                    trip_id
+                ),
+                None => bail!(
+                    "Problem reading {:?}: from_trip_id and to_trip_id are required for the transfer_type {}",
+                    file,
+                    if in_seat { 4 } else { 5 }
+                ),
+            }
+        };
+        let from_vehicle_journey_id = skip_error_and_warn!(trip_id(transfer.from_trip_id));
+        let to_vehicle_journey_id = skip_error_and_warn!(trip_id(transfer.to_trip_id));
+        trip_transfers.push(objects::TripTransfer {
+            from_vehicle_journey_id,
+            to_vehicle_journey_id,
+            in_seat,
+        });
+    }
+    collections.trip_transfers = Collection::new(trip_transfers);
+    Ok(())
+}
+
 fn get_commercial_mode(route_type: &RouteType) -> objects::CommercialMode {
     objects::CommercialMode {
         id: route_type.to_string(),
@@ -929,6 +1724,7 @@ fn get_commercial_mode(route_type: &RouteType) -> objects::CommercialMode {
             RouteType::Air => "Airplane".to_string(),
             _ => route_type.to_string(),
         },
+        ..Default::default()
     }
 }
 
@@ -942,6 +1738,7 @@ fn get_physical_mode(route_type: &RouteType) -> objects::PhysicalMode {
         id: repres.clone(),
         name: repres,
         co2_emission: None,
+        ..Default::default()
     }
 }
 
@@ -969,7 +1766,7 @@ type MapLineRoutes<'a> = BTreeMap<(Option<String>, String, Option<Idx<Route>>),
 
 fn map_line_routes<'a>(
     gtfs_routes: &'a CollectionWithId<Route>,
-    gtfs_trips: &[Trip],
+    gtfs_trips: &[TripRecord<'_>],
     read_as_line: bool,
 ) -> MapLineRoutes<'a> {
     let mut map = BTreeMap::new();
@@ -1003,12 +1800,22 @@ fn make_lines(
 
     for routes in map_line_routes.values() {
         let r = get_route_with_smallest_name(routes);
+        let mut object_properties = PropertiesMap::default();
+        let continuous_stoppings = [
+            ("continuous_pickup", r.continuous_pickup),
+            ("continuous_drop_off", r.continuous_drop_off),
+        ];
+        for (field, value) in &continuous_stoppings {
+            if let Some(value) = continuous_stopping("routes.txt", field, &r.id, *value) {
+                object_properties.insert(field.to_string(), value.to_string());
+            }
+        }
 
         lines.push(objects::Line {
             id: r.id.clone(),
             code: line_code(r),
             codes: KeysValues::default(),
-            object_properties: PropertiesMap::default(),
+            object_properties,
             comment_links: CommentLinksT::default(),
             name: r.long_name.to_string(),
             forward_name: None,
@@ -1021,13 +1828,17 @@ fn make_lines(
             geometry_id: None,
             opening_time: None,
             closing_time: None,
+            branding_id: None,
         });
     }
 
     Ok(lines)
 }
 
-fn make_routes(gtfs_trips: &[Trip], map_line_routes: &MapLineRoutes<'_>) -> Vec<objects::Route> {
+fn make_routes(
+    gtfs_trips: &[TripRecord<'_>],
+    map_line_routes: &MapLineRoutes<'_>,
+) -> Vec<objects::Route> {
     let mut routes = vec![];
 
     let get_direction_name = |d: DirectionType| match d {
@@ -1061,7 +1872,7 @@ fn make_routes(gtfs_trips: &[Trip], map_line_routes: &MapLineRoutes<'_>) -> Vec<
                         String::new()
                     },
                     direction_type: Some(get_direction_name(d)),
-                    codes: KeysValues::default(),
+                    codes: std::iter::once(("source".into(), r.id.clone())).collect(),
                     object_properties: PropertiesMap::default(),
                     comment_links: CommentLinksT::default(),
                     line_id: sr.id.clone(),
@@ -1075,7 +1886,7 @@ fn make_routes(gtfs_trips: &[Trip], map_line_routes: &MapLineRoutes<'_>) -> Vec<
 }
 
 fn make_ntfs_vehicle_journeys(
-    gtfs_trips: &[Trip],
+    gtfs_trips: &[TripRecord<'_>],
     routes: &CollectionWithId<Route>,
     datasets: &CollectionWithId<objects::Dataset>,
     networks: &CollectionWithId<objects::Network>,
@@ -1084,7 +1895,8 @@ fn make_ntfs_vehicle_journeys(
     let (_, dataset) = datasets.iter().next().unwrap();
     let mut vehicle_journeys: Vec<objects::VehicleJourney> = vec![];
     let mut trip_properties: Vec<objects::TripProperty> = vec![];
-    let mut map_tps_trips: BTreeMap<(Availability, Availability), Vec<&Trip>> = BTreeMap::new();
+    let mut map_tps_trips: BTreeMap<(Availability, Availability), Vec<&TripRecord>> =
+        BTreeMap::new();
     let mut id_incr: u8 = 1;
     let mut property_id: Option<String>;
 
@@ -1126,21 +1938,31 @@ fn make_ntfs_vehicle_journeys(
 }
 
 /// Reading transit routes. A route is a group of trips that are displayed to riders as a single service.
+///
+/// The routes whose `route_type` is in `route_type_modes` get these modes
+/// instead of the default ones.
 pub fn read_routes<H>(
     file_handler: &mut H,
     collections: &mut Collections,
     read_as_line: bool,
+    route_type_modes: &[RouteTypeModes],
+    default_agency_id: Option<&str>,
 ) -> Result<()>
 where
     for<'a> &'a mut H: FileHandler,
 {
     info!("Reading routes.txt");
-    let gtfs_routes_collection = read_collection(file_handler, "routes.txt")?;
+    let gtfs_routes_collection = fill_route_agency_ids(
+        read_collection(file_handler, "routes.txt")?,
+        &collections.networks,
+        default_agency_id,
+    )?;
     let (commercial_modes, physical_modes) = get_modes_from_gtfs(&gtfs_routes_collection);
     collections.commercial_modes = CollectionWithId::new(commercial_modes)?;
     collections.physical_modes = CollectionWithId::new(physical_modes)?;
 
-    let gtfs_trips = read_objects(file_handler, "trips.txt", true)?;
+    let trip_records = read_all_records(file_handler, "trips.txt", true)?;
+    let gtfs_trips: Vec<TripRecord> = trip_records.deserialize()?;
     let map_line_routes = map_line_routes(&gtfs_routes_collection, &gtfs_trips, read_as_line);
     let lines = make_lines(&map_line_routes, &collections.networks)?;
     collections.lines = CollectionWithId::new(lines)?;
@@ -1175,17 +1997,104 @@ where
     collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
     collections.trip_properties = CollectionWithId::new(trip_properties)?;
 
+    if !route_type_modes.is_empty() {
+        apply_route_type_modes(file_handler, collections, &gtfs_trips, route_type_modes)?;
+    }
+
     Ok(())
 }
 
-#[derive(Derivative, Deserialize, Debug, Clone, PartialEq)]
-#[derivative(Default)]
-enum FrequencyPrecision {
-    #[derivative(Default)]
-    #[serde(rename = "0")]
-    Inexact,
-    #[serde(rename = "1")]
-    Exact,
+#[derive(Deserialize)]
+struct RouteTypeRecord {
+    route_id: String,
+    route_type: u16,
+}
+
+// Replace the default modes of the lines and the vehicle journeys of the
+// routes whose `route_type` is in `route_type_modes`. A line gets the modes
+// of the route it is named after, as for the default modes.
+fn apply_route_type_modes<H>(
+    file_handler: &mut H,
+    collections: &mut Collections,
+    gtfs_trips: &[TripRecord<'_>],
+    route_type_modes: &[RouteTypeModes],
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let modes_by_route_type: HashMap<u16, &RouteTypeModes> = route_type_modes
+        .iter()
+        .map(|modes| (modes.route_type, modes))
+        .collect();
+    let records = read_objects_loose::<_, RouteTypeRecord>(file_handler, "routes.txt", true)?;
+    let mut modes_by_route: HashMap<String, &RouteTypeModes> = HashMap::new();
+    let mut unknown_route_types: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for record in records {
+        match modes_by_route_type.get(&record.route_type) {
+            Some(modes) => {
+                modes_by_route.insert(record.route_id, modes);
+            }
+            // The extended route types
+            None if record.route_type >= 100 => unknown_route_types
+                .entry(record.route_type)
+                .or_default()
+                .push(record.route_id),
+            None => {}
+        }
+    }
+    for (route_type, route_ids) in unknown_route_types {
+        warn!(
+            "route_type {} is not in the modes of the route types, the routes {:?} get the default modes",
+            route_type, route_ids
+        );
+    }
+
+    for modes in modes_by_route.values() {
+        if !collections
+            .physical_modes
+            .contains_id(&modes.physical_mode_id)
+        {
+            collections.physical_modes.push(objects::PhysicalMode {
+                id: modes.physical_mode_id.clone(),
+                name: modes.physical_mode_id.clone(),
+                co2_emission: None,
+                ..Default::default()
+            })?;
+        }
+        if !collections
+            .commercial_modes
+            .contains_id(&modes.commercial_mode_id)
+        {
+            collections.commercial_modes.push(objects::CommercialMode {
+                id: modes.commercial_mode_id.clone(),
+                name: modes.commercial_mode_name.clone(),
+                ..Default::default()
+            })?;
+        }
+    }
+    for (route_id, modes) in &modes_by_route {
+        if let Some(mut line) = collections.lines.get_mut(route_id) {
+            line.commercial_mode_id = modes.commercial_mode_id.clone();
+        }
+    }
+    for trip in gtfs_trips {
+        if let Some(modes) = modes_by_route.get(trip.route_id) {
+            if let Some(mut vj) = collections.vehicle_journeys.get_mut(trip.id) {
+                vj.physical_mode_id = modes.physical_mode_id.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Derivative, Deserialize, Debug, Clone, PartialEq)]
+#[derivative(Default)]
+enum FrequencyPrecision {
+    #[derivative(Default)]
+    #[serde(rename = "0")]
+    Inexact,
+    #[serde(rename = "1")]
+    Exact,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -1198,14 +2107,45 @@ struct Frequency {
     exact_times: FrequencyPrecision,
 }
 
+// Identifier of a trip generated from a frequency, derived from the trip and
+// the departure time. Overlapping frequencies of the same trip can generate
+// two trips at the same time, the second one being suffixed.
+fn stable_trip_id(trip_id: &str, start_time: Time, generated: &HashSet<String>) -> String {
+    let id = format!(
+        "{}-{:02}{:02}{:02}",
+        trip_id,
+        start_time.hours(),
+        start_time.minutes(),
+        start_time.seconds()
+    );
+    if !generated.contains(&id) {
+        return id;
+    }
+    (1..)
+        .map(|n| format!("{}-{}", id, n))
+        .find(|id| !generated.contains(id))
+        .unwrap()
+}
+
 ///Reading headway (time between trips) for headway-based service or a compressed representation of fixed-schedule service.
-pub fn manage_frequencies<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
+///
+/// The generated trips are identified by `<trip_id>-<n>`, `n` counting the
+/// trips generated from the same trip. With `stable_ids`, they are identified
+/// by `<trip_id>-<HHMMSS>` instead, the time being the departure from the
+/// first stop, so that adding or removing a frequency doesn't change the
+/// identifiers of the trips generated by the other ones.
+pub fn manage_frequencies<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+    stable_ids: bool,
+) -> Result<()>
 where
     for<'a> &'a mut H: FileHandler,
 {
     let file = "frequencies.txt";
     let gtfs_frequencies = read_objects::<_, Frequency>(file_handler, file, false)?;
     let mut trip_id_sequence: HashMap<String, u32> = HashMap::new();
+    let mut generated_trip_ids: HashSet<String> = HashSet::new();
     let mut new_vehicle_journeys: Vec<VehicleJourney> = vec![];
     for frequency in &gtfs_frequencies {
         if frequency.start_time == frequency.end_time {
@@ -1239,14 +2179,19 @@ where
                 .entry(frequency.trip_id.clone())
                 .and_modify(|counter| *counter += 1)
                 .or_insert(0);
-            let generated_trip_id = format!(
-                "{}-{}",
-                frequency.trip_id, trip_id_sequence[&frequency.trip_id]
-            );
+            let generated_trip_id = if stable_ids {
+                stable_trip_id(&frequency.trip_id, start_time, &generated_trip_ids)
+            } else {
+                format!(
+                    "{}-{}",
+                    frequency.trip_id, trip_id_sequence[&frequency.trip_id]
+                )
+            };
+            generated_trip_ids.insert(generated_trip_id.clone());
             // the following handles generated trip starting after midnight, we need to generate a
             // new service in case the next day is not covered
-            let service_id = if start_time.hours() >= 24 {
-                let nb_days = start_time.hours() / 24;
+            let service_id = if start_time.days_offset() > 0 {
+                let nb_days = start_time.days_offset();
                 let service = collections
                     .calendars
                     .get(&corresponding_vj.service_id)
@@ -1255,15 +2200,9 @@ where
                 let new_service_id = format!("{}:+{}days", service.id, nb_days);
                 if !collections.calendars.contains_id(&new_service_id) {
                     arrival_time_delta = arrival_time_delta + Time::new(24, 0, 0);
-                    let new_dates: BTreeSet<_> = service
-                        .dates
-                        .iter()
-                        .map(|d| *d + chrono::Duration::days(i64::from(nb_days)))
-                        .collect();
-
                     let new_service = objects::Calendar {
                         id: new_service_id.clone(),
-                        dates: new_dates,
+                        ..service.shifted_by(i64::from(nb_days))
                     };
                     collections.calendars.push(new_service)?;
                 }
@@ -1285,6 +2224,10 @@ where
                     drop_off_type: stop_time.drop_off_type,
                     local_zone_id: stop_time.local_zone_id,
                     precision: stop_time.precision.clone(),
+                    continuous_pickup: stop_time.continuous_pickup,
+                    continuous_drop_off: stop_time.continuous_drop_off,
+                    pickup_booking_rule_id: stop_time.pickup_booking_rule_id.clone(),
+                    drop_off_booking_rule_id: stop_time.drop_off_booking_rule_id.clone(),
                 })
                 .collect();
             start_time = start_time + Time::new(0, 0, frequency.headway_secs);
@@ -1295,28 +2238,30 @@ where
                 ..corresponding_vj.clone()
             };
             new_vehicle_journeys.push(generated_vj);
-            let stop_time_comments: HashMap<(String, u32), String> = corresponding_vj
+            let trip_id = IdString::from(&frequency.trip_id);
+            let generated_trip_id = IdString::from(generated_trip_id);
+            let stop_time_comments: HashMap<(IdString, u32), IdString> = corresponding_vj
                 .stop_times
                 .iter()
                 .filter(|stop_time| stop_time.pickup_type == 2 || stop_time.drop_off_type == 2)
                 .filter_map(|stop_time| {
                     collections
                         .stop_time_comments
-                        .get(&(frequency.trip_id.clone(), stop_time.sequence))
+                        .get(&(trip_id.clone(), stop_time.sequence))
                         .map(|comment_id| {
                             (
                                 (generated_trip_id.clone(), stop_time.sequence),
-                                comment_id.to_string(),
+                                comment_id.clone(),
                             )
                         })
                 })
                 .collect();
-            let stop_time_ids: HashMap<(String, u32), String> = stop_time_comments
+            let stop_time_ids: HashMap<(IdString, u32), IdString> = stop_time_comments
                 .keys()
                 .map(|(trip_id, sequence)| {
                     (
-                        (trip_id.to_string(), *sequence),
-                        format!("{}-{}", trip_id, sequence),
+                        (trip_id.clone(), *sequence),
+                        format!("{}-{}", trip_id, sequence).into(),
                     )
                 })
                 .collect();
@@ -1329,16 +2274,336 @@ where
     vehicle_journeys.retain(|vj| !trip_ids_to_remove.contains(&&vj.id));
     collections
         .stop_time_ids
-        .retain(|(vj_id, _), _| !trip_ids_to_remove.contains(&vj_id));
+        .retain(|(vj_id, _), _| !trip_ids_to_remove.iter().any(|id| vj_id == *id));
     collections
         .stop_time_comments
-        .retain(|(vj_id, _), _| !trip_ids_to_remove.contains(&vj_id));
+        .retain(|(vj_id, _), _| !trip_ids_to_remove.iter().any(|id| vj_id == *id));
 
     vehicle_journeys.append(&mut new_vehicle_journeys);
     collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
     Ok(())
 }
 
+#[derive(Deserialize)]
+struct GtfsTranslation {
+    table_name: String,
+    field_name: String,
+    language: String,
+    translation: String,
+    record_id: Option<String>,
+    field_value: Option<String>,
+}
+
+// The objects of the model matching a GTFS record
+type TranslationObjects<'c> = Vec<(ObjectType, &'c str)>;
+
+// The objects a translation can apply to, by GTFS table and identifier or by
+// GTFS table, field and value
+#[derive(Default)]
+struct TranslationTargets<'c> {
+    by_record: HashMap<(&'static str, &'c str), TranslationObjects<'c>>,
+    by_value: HashMap<(&'static str, &'static str, &'c str), TranslationObjects<'c>>,
+}
+
+impl<'c> TranslationTargets<'c> {
+    fn new(collections: &'c Collections) -> Self {
+        fn source_id(codes: &KeysValues) -> Option<&str> {
+            codes
+                .iter()
+                .find(|(system, _)| system == "source")
+                .map(|(_, code)| code.as_str())
+        }
+        let mut targets = TranslationTargets::default();
+        let mut add = |table: &'static str,
+                       record_id: &'c str,
+                       values: &[(&'static str, Option<&'c str>)],
+                       object: (ObjectType, &'c str)| {
+            for (field, value) in values {
+                if let Some(value) = value {
+                    targets
+                        .by_value
+                        .entry((table, field, value))
+                        .or_default()
+                        .push(object.clone());
+                }
+            }
+            targets
+                .by_record
+                .entry((table, record_id))
+                .or_default()
+                .push(object);
+        };
+        for network in collections.networks.values() {
+            let values = [("agency_name", Some(network.name.as_str()))];
+            add(
+                "agency",
+                &network.id,
+                &values,
+                (ObjectType::Network, &network.id),
+            );
+        }
+        for company in collections.companies.values() {
+            let values = [("agency_name", Some(company.name.as_str()))];
+            add(
+                "agency",
+                &company.id,
+                &values,
+                (ObjectType::Company, &company.id),
+            );
+        }
+        for stop_area in collections.stop_areas.values() {
+            let values = [("stop_name", Some(stop_area.name.as_str()))];
+            let object = (ObjectType::StopArea, stop_area.id.as_str());
+            add("stops", &stop_area.id, &values, object);
+        }
+        for stop_point in collections.stop_points.values() {
+            let values = [("stop_name", Some(stop_point.name.as_str()))];
+            let object = (ObjectType::StopPoint, stop_point.id.as_str());
+            add("stops", &stop_point.id, &values, object);
+        }
+        // A line is identified by the route its name comes from
+        for line in collections.lines.values() {
+            let values = [
+                ("route_long_name", Some(line.name.as_str())),
+                ("route_short_name", line.code.as_deref()),
+            ];
+            add("routes", &line.id, &values, (ObjectType::Line, &line.id));
+        }
+        for vehicle_journey in collections.vehicle_journeys.values() {
+            // The trips generated from a frequency keep the source trip_id
+            let trip_id = source_id(&vehicle_journey.codes).unwrap_or(&vehicle_journey.id);
+            let values = [("trip_headsign", vehicle_journey.headsign.as_deref())];
+            let object = (ObjectType::VehicleJourney, vehicle_journey.id.as_str());
+            add("trips", trip_id, &values, object);
+        }
+        // The other routes of a line are known, without being translated
+        for route in collections.routes.values() {
+            if let Some(route_id) = source_id(&route.codes) {
+                targets.by_record.entry(("routes", route_id)).or_default();
+            }
+        }
+        targets
+    }
+
+    fn get<'t>(
+        &'t self,
+        translation: &'t GtfsTranslation,
+    ) -> Result<(&'static str, &'t [(ObjectType, &'t str)])> {
+        let (table, field, ntfs_field) = match (
+            translation.table_name.as_str(),
+            translation.field_name.as_str(),
+        ) {
+            ("agency", "agency_name") => ("agency", "agency_name", "name"),
+            ("stops", "stop_name") => ("stops", "stop_name", "name"),
+            ("routes", "route_long_name") => ("routes", "route_long_name", "name"),
+            ("routes", "route_short_name") => ("routes", "route_short_name", "code"),
+            ("trips", "trip_headsign") => ("trips", "trip_headsign", "headsign"),
+            (table, field) => bail!(
+                "Problem reading \"translations.txt\": translation of {}.{} is not supported",
+                table,
+                field
+            ),
+        };
+        let objects = match (&translation.record_id, &translation.field_value) {
+            (Some(record_id), _) => self.by_record.get(&(table, record_id.as_str())),
+            (None, Some(field_value)) => self.by_value.get(&(table, field, field_value.as_str())),
+            (None, None) => bail!(
+                "Problem reading \"translations.txt\": a translation of {}.{} has neither record_id nor field_value",
+                table,
+                field
+            ),
+        };
+        let objects = objects.ok_or_else(|| {
+            anyhow!(
+                "Problem reading \"translations.txt\": {} {:?} not found",
+                table,
+                translation
+                    .record_id
+                    .as_ref()
+                    .or(translation.field_value.as_ref())
+            )
+        })?;
+        Ok((ntfs_field, objects))
+    }
+}
+
+/// Reading of the translations of the names of the agencies, stops and
+/// routes, and of the headsigns of the trips, from `translations.txt`.
+///
+/// A translation is identified by `record_id`, or by `field_value` to apply
+/// to all the objects with this value. The translations of the other fields,
+/// and the ones referencing unknown objects, are skipped with a warning.
+pub fn read_translations<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let gtfs_translations =
+        read_objects_loose::<_, GtfsTranslation>(file_handler, "translations.txt", false)?;
+    if gtfs_translations.is_empty() {
+        return Ok(());
+    }
+    let targets = TranslationTargets::new(collections);
+    let mut translations = Vec::new();
+    for gtfs_translation in &gtfs_translations {
+        let (field_name, objects) = skip_error_and_warn!(targets.get(gtfs_translation));
+        translations.extend(objects.iter().map(|(object_type, object_id)| Translation {
+            object_type: object_type.clone(),
+            object_id: object_id.to_string(),
+            field_name: field_name.to_string(),
+            language: gtfs_translation.language.clone(),
+            translation: gtfs_translation.translation.clone(),
+        }));
+    }
+    collections.translations = Collection::new(translations);
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct FeedInfo {
+    #[serde(default, deserialize_with = "de_with_empty_default")]
+    feed_start_date: Option<String>,
+    #[serde(default, deserialize_with = "de_with_empty_default")]
+    feed_end_date: Option<String>,
+}
+
+/// Reading the `feed_start_date` and the `feed_end_date` of 'feed_info.txt',
+/// if any.
+pub fn read_feed_period<H>(file_handler: &mut H) -> Result<(Option<Date>, Option<Date>)>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let parse = |date: Option<String>, field: &str| {
+        date.filter(|date| !date.is_empty())
+            .map(|date| {
+                Date::parse_from_str(&date, "%Y%m%d")
+                    .with_context(|| format!("feed_info.txt: invalid {} {:?}", field, date))
+            })
+            .transpose()
+    };
+    let feed_infos = read_objects_loose::<_, FeedInfo>(file_handler, "feed_info.txt", false)?;
+    match feed_infos.into_iter().next() {
+        Some(feed_info) => Ok((
+            parse(feed_info.feed_start_date, "feed_start_date")?,
+            parse(feed_info.feed_end_date, "feed_end_date")?,
+        )),
+        None => Ok((None, None)),
+    }
+}
+
+#[derive(Deserialize)]
+struct Attribution {
+    agency_id: Option<String>,
+    route_id: Option<String>,
+    trip_id: Option<String>,
+    organization_name: String,
+    #[serde(default, deserialize_with = "de_with_empty_default")]
+    is_producer: u8,
+    #[serde(default, deserialize_with = "de_with_empty_default")]
+    is_operator: u8,
+    attribution_url: Option<String>,
+    attribution_email: Option<String>,
+    attribution_phone: Option<String>,
+}
+
+// Add the organization of an attribution as a code of the objects created
+// from the GTFS record `source_id`, returns false if there is none
+fn add_attribution_code<T>(
+    collection: &mut CollectionWithId<T>,
+    source_id: &str,
+    organization_name: &str,
+) -> bool
+where
+    T: typed_index_collection::Id<T> + objects::Codes,
+{
+    let indexes: Vec<Idx<T>> = collection
+        .iter()
+        .filter(|(_, object)| {
+            object
+                .codes()
+                .iter()
+                .any(|(system, code)| system == "source" && code == source_id)
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    for idx in &indexes {
+        collection
+            .index_mut(*idx)
+            .codes_mut()
+            .insert(("attribution".into(), organization_name.to_string()));
+    }
+    !indexes.is_empty()
+}
+
+/// Reading of the optional `attributions.txt`.
+///
+/// An attribution of a `route_id` or a `trip_id` is kept as an `attribution`
+/// code of the routes or vehicle journeys created from it. Otherwise, a
+/// producer completes the website of the contributors (and names the default
+/// contributor), and an operator completes the contact fields of the company
+/// of its `agency_id`, or of the company with its name.
+pub fn read_attributions<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let attributions =
+        read_objects_loose::<_, Attribution>(file_handler, "attributions.txt", false)?;
+    for attribution in attributions {
+        let name = &attribution.organization_name;
+        if let Some(route_id) = &attribution.route_id {
+            if !add_attribution_code(&mut collections.routes, route_id, name) {
+                warn!("attributions.txt: route_id={} not found", route_id);
+            }
+            continue;
+        }
+        if let Some(trip_id) = &attribution.trip_id {
+            if !add_attribution_code(&mut collections.vehicle_journeys, trip_id, name) {
+                warn!("attributions.txt: trip_id={} not found", trip_id);
+            }
+            continue;
+        }
+        if attribution.is_producer == 1 {
+            let mut contributors = collections.contributors.take();
+            for contributor in &mut contributors {
+                if contributor.name == objects::Contributor::default().name {
+                    contributor.name = name.clone();
+                }
+                if contributor.website.is_none() {
+                    contributor.website = attribution.attribution_url.clone();
+                }
+            }
+            collections.contributors = CollectionWithId::new(contributors)?;
+        }
+        if attribution.is_operator == 1 {
+            let company_id = match &attribution.agency_id {
+                Some(agency_id) => Some(agency_id.clone()),
+                None => collections
+                    .companies
+                    .values()
+                    .find(|company| &company.name == name)
+                    .map(|company| company.id.clone()),
+            };
+            let mut company = skip_error_and_warn!(company_id
+                .as_deref()
+                .and_then(|company_id| collections.companies.get_mut(company_id))
+                .ok_or_else(|| anyhow!(
+                    "attributions.txt: no company found for the operator {:?}",
+                    name
+                )));
+            company.codes.insert(("attribution".into(), name.clone()));
+            if company.url.is_none() {
+                company.url = attribution.attribution_url.clone();
+            }
+            if company.mail.is_none() {
+                company.mail = attribution.attribution_email.clone();
+            }
+            if company.phone.is_none() {
+                company.phone = attribution.attribution_phone.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1413,7 +2678,13 @@ mod tests {
             assert_eq!(1, networks.len());
             let network = networks.iter().next().unwrap().1;
             assert_eq!("id_1", network.id);
+            assert_eq!(Some("0123456789".to_string()), network.phone);
             assert_eq!(1, companies.len());
+            let company = companies.iter().next().unwrap().1;
+            assert_eq!("id_1", company.id);
+            assert_eq!(Some("http://my-agency_url.com".to_string()), company.url);
+            assert_eq!(Some("0123456789".to_string()), company.phone);
+            assert_eq!(Some("my-mail@example.com".to_string()), company.mail);
         });
     }
 
@@ -1443,6 +2714,14 @@ mod tests {
             create_file_with_content(path, "agency.txt", agency_content);
             super::read_agency(&mut handler).unwrap();
             testing_logger::validate(|captured_logs| {
+                // The spans of `timing` are also logged
+                let captured_logs: Vec<_> = captured_logs
+                    .iter()
+                    .filter(|log| {
+                        log.target != "transit_model::timing"
+                            && !log.target.starts_with("tracing::span")
+                    })
+                    .collect();
                 assert_eq!(captured_logs.len(), 2);
                 assert!(captured_logs[1].body.contains(
                     "different agency timezone: Europe/London (id_1) - Europe/Paris (id_2)"
@@ -1502,7 +2781,7 @@ mod tests {
             collections.stop_areas = stop_areas;
             collections.stop_points = stop_points;
             collections.stop_locations = stop_locations;
-            super::manage_shapes(&mut collections, &mut handler).unwrap();
+            super::manage_shapes(&mut collections, &mut handler, None).unwrap();
             let stop_area = collections.stop_areas.iter().next().unwrap().1;
             assert_eq!("stoparea01", stop_area.id);
             assert_eq!(
@@ -1606,7 +2885,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(4, collections.lines.len());
             assert_eq!(
                 vec!["agency_1", "agency_2", "agency_3", "agency_4"],
@@ -1675,7 +2954,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(3, collections.lines.len());
 
             assert_eq!(5, collections.routes.len());
@@ -1718,7 +2997,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(
                 vec![
@@ -1790,7 +3069,90 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+        });
+    }
+
+    #[test]
+    fn gtfs_routes_without_agency_id_and_2_agencies_with_default_agency() {
+        let agency_content = "agency_id,agency_name,agency_url,agency_timezone\n\
+                              id_agency1,My agency 1,http://my-agency_url1.com,Europe/London\n\
+                              id_agency2,My agency 2,http://my-agency_url2.com,Europe/London";
+
+        let routes_content =
+            "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
+             route_1,,1,My line 1,3,8F7A32,FFFFFF\n\
+             route_2,id_agency1,2,My line 2,2,7BC142,000000\n\
+             route_3,,3,My line 3,8,,";
+
+        let trips_content =
+            "trip_id,route_id,direction_id,service_id,wheelchair_accessible,bikes_allowed\n\
+             1,route_1,0,service_1,,\n\
+             2,route_2,0,service_1,,\n\
+             3,route_3,0,service_1,,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "agency.txt", agency_content);
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+
+            let mut collections = Collections::default();
+            let (networks, _) = super::read_agency(&mut handler).unwrap();
+            collections.networks = networks;
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            let error =
+                super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap_err();
+            assert_eq!(
+                "Impossible to get agency id, several networks found, for the routes route_1, route_3",
+                error.to_string()
+            );
+            let error = super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                &[],
+                Some("unknown_agency"),
+            )
+            .unwrap_err();
+            assert_eq!(
+                "the default agency \"unknown_agency\" of the routes without agency_id is not in agency.txt",
+                error.to_string()
+            );
+
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                &[],
+                Some("id_agency2"),
+            )
+            .unwrap();
+            assert_eq!(
+                vec![
+                    ("route_1", "id_agency2"),
+                    ("route_2", "id_agency1"),
+                    ("route_3", "id_agency2"),
+                ],
+                extract(
+                    |line| (line.id.as_str(), line.network_id.as_str()),
+                    &collections.lines
+                )
+            );
+            assert_eq!(
+                vec![
+                    ("1", "id_agency2"),
+                    ("2", "id_agency1"),
+                    ("3", "id_agency2")
+                ],
+                extract(
+                    |vj| (vj.id.as_str(), vj.company_id.as_str()),
+                    &collections.vehicle_journeys
+                )
+            );
         });
     }
 
@@ -1821,7 +3183,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
         });
     }
 
@@ -1856,7 +3218,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
 
             assert_eq!(3, collections.lines.len());
             assert_eq!(
@@ -1899,7 +3261,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
 
             assert_eq!(2, collections.lines.len());
 
@@ -1932,7 +3294,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
 
             assert_eq!(2, collections.lines.len());
             assert_eq!(vec!["route_1", "route_3"], extract_ids(&collections.lines));
@@ -1966,7 +3328,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(1, collections.lines.len());
             assert_eq!(1, collections.routes.len());
         });
@@ -2036,8 +3398,8 @@ mod tests {
             collections.networks = networks;
             collections.companies = companies;
             collections.comments = comments;
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_shapes(&mut collections, &mut handler).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_shapes(&mut collections, &mut handler, None).unwrap();
             calendars::manage_calendars(&mut handler, &mut collections).unwrap();
 
             let mut prefix_conf = PrefixConfiguration::default();
@@ -2203,7 +3565,7 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
             assert_eq!(3, collections.vehicle_journeys.len());
@@ -2244,7 +3606,7 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
             assert_eq!(3, collections.vehicle_journeys.len());
@@ -2277,7 +3639,7 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
 
@@ -2292,6 +3654,56 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtfs_trips_with_bikes_allowed_on_some_trips() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
+                              route_1,agency_1,1,My line 1,3,8F7A32,FFFFFF";
+        let trips_content = "trip_id,route_id,service_id,wheelchair_accessible,bikes_allowed\n\
+                             1,route_1,service_1,1\n\
+                             2,route_1,service_1,1,1\n\
+                             3,route_1,service_1,1,1\n\
+                             4,route_1,service_1,,2";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            assert_eq!(3, collections.trip_properties.len());
+            let trip_property = |vj_id: &str| {
+                let vj = collections.vehicle_journeys.get(vj_id).unwrap();
+                let trip_property_id = vj.trip_property_id.as_ref().unwrap();
+                let trip_property = collections.trip_properties.get(trip_property_id).unwrap();
+                (
+                    trip_property.wheelchair_accessible,
+                    trip_property.bike_accepted,
+                )
+            };
+            use objects::Availability::*;
+            assert_eq!((Available, InformationNotAvailable), trip_property("1"));
+            assert_eq!((Available, Available), trip_property("2"));
+            assert_eq!(
+                collections
+                    .vehicle_journeys
+                    .get("2")
+                    .unwrap()
+                    .trip_property_id,
+                collections
+                    .vehicle_journeys
+                    .get("3")
+                    .unwrap()
+                    .trip_property_id
+            );
+            assert_eq!((InformationNotAvailable, NotAvailable), trip_property("4"));
+        });
+    }
+
     #[test]
     fn gtfs_trips_with_no_accessibility_information() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -2311,7 +3723,7 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             assert_eq!(2, collections.vehicle_journeys.len());
             assert_eq!(0, collections.trip_properties.len());
             for vj in collections.vehicle_journeys.values() {
@@ -2411,6 +3823,42 @@ mod tests {
         });
     }
 
+    #[test]
+    fn stops_inherit_station_equipments() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station,wheelchair_boarding\n\
+                             sa:01,my stop area name,0.1,1.2,1,,1\n\
+                             sp:01,my stop point name 1,0.1,1.2,0,sa:01,\n\
+                             sp:02,my stop point name 2,0.1,1.2,0,sa:01,0\n\
+                             sp:03,my stop point name 3,0.1,1.2,0,sa:01,2\n\
+                             sa:02,my other stop area name,0.3,2.2,1,,\n\
+                             sp:04,my stop point name 4,0.3,2.2,0,sa:02,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (stop_areas, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let equipments_collection =
+                CollectionWithId::new(equipments.into_equipments()).unwrap();
+            assert_eq!(2, equipments_collection.len());
+
+            let station_equipment_id = stop_areas.get("sa:01").unwrap().equipment_id.clone();
+            assert!(station_equipment_id.is_some());
+            let equipment_id = |id: &str| stop_points.get(id).unwrap().equipment_id.clone();
+            assert_eq!(station_equipment_id, equipment_id("sp:01"));
+            assert_eq!(station_equipment_id, equipment_id("sp:02"));
+            assert_eq!(Some("1".to_string()), equipment_id("sp:03"));
+            assert_eq!(None, equipment_id("sp:04"));
+            assert_eq!(
+                Availability::NotAvailable,
+                equipments_collection.get("1").unwrap().wheelchair_boarding
+            );
+        });
+    }
+
     #[test]
     fn stops_do_not_generate_duplicate_equipments() {
         let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station,wheelchair_boarding\n\
@@ -2498,8 +3946,16 @@ mod tests {
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -2514,6 +3970,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Approximate),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -2526,6 +3986,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -2538,6 +4002,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -2546,31 +4014,18 @@ mod tests {
     }
 
     #[test]
-    fn gtfs_stop_times_deduplicated() {
+    fn gtfs_stop_times_trimmed_flexible_and_without_slashes() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
                               route_1,agency_1,1,My line 1,3,8F7A32,FFFFFF";
-
-        let stops_content =
-            "stop_id,stop_name,stop_desc,stop_lat,stop_lon,location_type,parent_station\n\
-             sp:01,my stop point name 1,,0.1,1.1,0,\n\
-             sp:02,my stop point name 2,,0.2,1.2,0,\n\
-             sp:03,my stop point name 3,,0.3,1.3,0,\n\
-             sp:04,my stop point name 4,,0.4,1.4,0,\n\
-             sp:05,my stop point name 5,,0.5,1.5,0,";
-
-        let trips_content =
-            "trip_id,route_id,direction_id,service_id,wheelchair_accessible,bikes_allowed\n\
-             1,route_1,0,service_1,,";
-
-        // Duplicated stops sequences 1 and 2
-        // Duplicates should not be taken into account ("first come" rule)
-        // Uniqueness is on trip_id / stop_sequence
-        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
-                                  1,06:00:00,06:00:00,sp:01,1\n\
-                                  1,06:00:10,06:00:10,sp:04,1\n\
-                                  1,06:11:00,06:11:00,sp:02,2\n\
-                                  1,06:11:10,06:11:10,sp:05,2\n\
-                                  1,06:22:00,06:22:00,sp:03,3";
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop point name 1,0.1,1.2,0\n\
+                             sp:02,my stop point name 2,0.2,1.5,0";
+        let trips_content = "trip_id,route_id,direction_id,service_id\n\
+                             1,route_1,0,service_1";
+        let stop_times_content =
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,stop_headsign\n\
+                                  1 , 06:00:00,06:00:00,sp/:01,1, over there \n\
+                                  1,06:10:00,06:10:00,sp:02,2,,extra cell";
 
         test_in_tmp_dir(|path| {
             let mut handler = PathFileHandler::new(path.to_path_buf());
@@ -2583,15 +4038,116 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            collections.stop_points = stop_points;
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
+
+            let stop_times = &collections
+                .vehicle_journeys
+                .values()
+                .next()
+                .unwrap()
+                .stop_times;
+            assert_eq!(2, stop_times.len());
+            assert_eq!(
+                collections.stop_points.get_idx("sp:01").unwrap(),
+                stop_times[0].stop_point_idx
+            );
+            assert_eq!(
+                Some(&IdString::from("over there")),
+                collections
+                    .stop_time_headsigns
+                    .get(&(IdString::from("1"), 1))
+            );
+
+            let invalid_stop_times_content =
+                "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                                              1,06:00:00,06:00:00,sp:01,1\n\
+                                              1,06:70:00,06:10:00,sp:02,2";
+            create_file_with_content(path, "stop_times.txt", invalid_stop_times_content);
+            let error = super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap_err();
+            let message = format!("{:#}", error);
+            assert!(message.starts_with("Error reading"), "{}", message);
+            assert!(message.contains("stop_times.txt"), "{}", message);
+            assert!(message.contains("line: 3"), "{}", message);
+        });
+    }
+
+    #[test]
+    fn gtfs_stop_times_deduplicated() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
+                              route_1,agency_1,1,My line 1,3,8F7A32,FFFFFF";
+
+        let stops_content =
+            "stop_id,stop_name,stop_desc,stop_lat,stop_lon,location_type,parent_station\n\
+             sp:01,my stop point name 1,,0.1,1.1,0,\n\
+             sp:02,my stop point name 2,,0.2,1.2,0,\n\
+             sp:03,my stop point name 3,,0.3,1.3,0,\n\
+             sp:04,my stop point name 4,,0.4,1.4,0,\n\
+             sp:05,my stop point name 5,,0.5,1.5,0,";
+
+        let trips_content =
+            "trip_id,route_id,direction_id,service_id,wheelchair_accessible,bikes_allowed\n\
+             1,route_1,0,service_1,,";
+
+        // Duplicated stops sequences 1 and 2
+        // Duplicates should not be taken into account ("first come" rule)
+        // Uniqueness is on trip_id / stop_sequence
+        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                                  1,06:00:00,06:00:00,sp:01,1\n\
+                                  1,06:00:10,06:00:10,sp:04,1\n\
+                                  1,06:11:00,06:11:00,sp:02,2\n\
+                                  1,06:11:10,06:11:10,sp:05,2\n\
+                                  1,06:22:00,06:22:00,sp:03,3";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
             let (_, stop_points, _) =
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -2606,6 +4162,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -2618,6 +4178,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -2630,6 +4194,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -2673,8 +4241,16 @@ mod tests {
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -2689,6 +4265,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -2701,12 +4281,19 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
             );
-            let headsigns: Vec<String> =
-                collections.stop_time_headsigns.values().cloned().collect();
+            let headsigns: Vec<String> = collections
+                .stop_time_headsigns
+                .values()
+                .map(IdString::to_string)
+                .collect();
             assert_eq!(vec!["over there".to_string()], headsigns);
         });
     }
@@ -2748,6 +4335,7 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(0),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:01".to_string(),
@@ -2755,6 +4343,7 @@ mod tests {
                         min_transfer_time: Some(160),
                         real_min_transfer_time: Some(280),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:01".to_string(),
@@ -2762,6 +4351,7 @@ mod tests {
                         min_transfer_time: Some(60),
                         real_min_transfer_time: Some(60),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2769,6 +4359,7 @@ mod tests {
                         min_transfer_time: Some(160),
                         real_min_transfer_time: Some(280),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2776,6 +4367,7 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(0),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2783,6 +4375,7 @@ mod tests {
                         min_transfer_time: Some(86400),
                         real_min_transfer_time: Some(86400),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::NotPossible,
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2790,6 +4383,7 @@ mod tests {
                         min_transfer_time: Some(247),
                         real_min_transfer_time: Some(367),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2797,6 +4391,7 @@ mod tests {
                         min_transfer_time: None,
                         real_min_transfer_time: None,
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2804,10 +4399,18 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(120),
                         equipment_id: None,
+                        transfer_type: objects::TransferType::Possible,
                     },
                 ],
                 transfers.values().collect::<Vec<_>>()
             );
+            // `transfer_type` is not compared by `Transfer::eq`
+            let not_possible: Vec<_> = transfers
+                .values()
+                .filter(|t| t.transfer_type == objects::TransferType::NotPossible)
+                .map(|t| (t.from_stop_id.as_str(), t.to_stop_id.as_str()))
+                .collect();
+            assert_eq!(vec![("sp:02", "sp:03")], not_possible);
         });
     }
 
@@ -2886,17 +4489,12 @@ mod tests {
             let mut dates = BTreeSet::new();
             dates.insert(chrono::NaiveDate::from_ymd(2018, 5, 6));
             dates.insert(chrono::NaiveDate::from_ymd(2018, 5, 7));
+            // The calendar 2 has no date left and is removed
             assert_eq!(
-                vec![
-                    Calendar {
-                        id: "1".to_string(),
-                        dates,
-                    },
-                    Calendar {
-                        id: "2".to_string(),
-                        dates: BTreeSet::new(),
-                    },
-                ],
+                vec![Calendar {
+                    id: "1".to_string(),
+                    dates,
+                }],
                 collections.calendars.into_vec()
             );
         });
@@ -2930,7 +4528,7 @@ mod tests {
             create_file_with_content(path, "shapes.txt", shapes_content);
 
             let mut collections = Collections::default();
-            super::manage_shapes(&mut collections, &mut handler).unwrap();
+            super::manage_shapes(&mut collections, &mut handler, None).unwrap();
             let mut geometries = collections.geometries.into_vec();
             geometries.sort_unstable_by_key(|s| s.id.clone());
 
@@ -2950,12 +4548,57 @@ mod tests {
         });
     }
 
+    #[test]
+    fn read_shapes_with_simplification() {
+        let shapes_content = "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\n\
+                              1,48.0,2.0,1\n\
+                              1,48.00001,2.001,2\n\
+                              1,48.0,2.002,3\n\
+                              2,48.0,2.0,1\n\
+                              2,48.001,2.001,2\n\
+                              2,48.0,2.002,3\n\
+                              3,48.0,2.0,1";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "shapes.txt", shapes_content);
+
+            let mut collections = Collections::default();
+            super::manage_shapes(&mut collections, &mut handler, Some(10.0)).unwrap();
+            let mut geometries = collections.geometries.into_vec();
+            geometries.sort_unstable_by_key(|s| s.id.clone());
+
+            assert_eq!(
+                vec![
+                    Geometry {
+                        id: "1".to_string(),
+                        geometry: line_string![(x: 2.0, y: 48.0), (x: 2.002, y: 48.0)].into(),
+                    },
+                    Geometry {
+                        id: "2".to_string(),
+                        geometry: line_string![
+                            (x: 2.0, y: 48.0),
+                            (x: 2.001, y: 48.001),
+                            (x: 2.002, y: 48.0)
+                        ]
+                        .into(),
+                    },
+                    Geometry {
+                        id: "3".to_string(),
+                        geometry: line_string![(x: 2.0, y: 48.0)].into(),
+                    },
+                ],
+                geometries
+            );
+        });
+    }
+
     #[test]
     fn read_shapes_with_no_shapes_file() {
         test_in_tmp_dir(|path| {
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let mut collections = Collections::default();
-            super::manage_shapes(&mut collections, &mut handler).unwrap();
+            super::manage_shapes(&mut collections, &mut handler, None).unwrap();
             let geometries = collections.geometries.into_vec();
             assert_eq!(Vec::<Geometry>::new(), geometries);
         });
@@ -2984,7 +4627,7 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
             // physical mode file should contain only three modes
             // (5,7 => funicular; 2 => train; 6 => suspended cable car)
             assert_eq!(4, collections.lines.len());
@@ -2996,6 +4639,86 @@ mod tests {
         });
     }
 
+    #[test]
+    fn read_routes_with_route_type_modes() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_desc,route_type,route_url,route_color,route_text_color\n\
+                                 route:1,agency:1,T1,T 1,,800,,ffea00,000000\n\
+                                 route:2,agency:1,L2,L 2,,1500,,ffea00,000000\n\
+                                 route:3,agency:1,L3,L 3,,3,,ffea00,000000";
+        let trips_content = "route_id,service_id,trip_id,trip_headsign,direction_id,shape_id\n\
+                             route:1,service:1,trip:1,pouet,0,\n\
+                             route:2,service:1,trip:2,pouet,0,\n\
+                             route:3,service:1,trip:3,pouet,0,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            let route_type_modes = vec![RouteTypeModes {
+                route_type: 800,
+                physical_mode_id: "Tramway".to_string(),
+                commercial_mode_id: "Trolleybus".to_string(),
+                commercial_mode_name: "Trolleybus".to_string(),
+            }];
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                &route_type_modes,
+                None,
+            )
+            .unwrap();
+            let line = collections.lines.get("route:1").unwrap();
+            assert_eq!("Trolleybus", line.commercial_mode_id);
+            assert_eq!(
+                "Trolleybus",
+                collections.commercial_modes.get("Trolleybus").unwrap().name
+            );
+            let vj = collections.vehicle_journeys.get("trip:1").unwrap();
+            assert_eq!("Tramway", vj.physical_mode_id);
+            // Not in the modes of the route types, the default modes are kept
+            let vj = collections.vehicle_journeys.get("trip:3").unwrap();
+            assert_eq!("Bus", vj.physical_mode_id);
+            let line = collections.lines.get("route:2").unwrap();
+            assert_eq!("Taxi", line.commercial_mode_id);
+        });
+    }
+
+    #[test]
+    fn read_routes_with_sort_order() {
+        let routes_content =
+            "route_id,agency_id,route_short_name,route_long_name,route_type,route_sort_order\n\
+                              route:1,agency:1,1,My line 1,3,20\n\
+                              route:2,agency:1,2,My line 2,3,";
+        let trips_content = "route_id,service_id,trip_id\n\
+                             route:1,service:1,trip:1\n\
+                             route:2,service:1,trip:2";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            assert_eq!(
+                Some(20),
+                collections.lines.get("route:1").unwrap().sort_order
+            );
+            assert_eq!(None, collections.lines.get("route:2").unwrap().sort_order);
+        });
+    }
+
     #[test]
     fn location_type_default_value() {
         let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
@@ -3017,6 +4740,92 @@ mod tests {
         });
     }
 
+    #[test]
+    fn read_stops_with_platform_code() {
+        let stops_content =
+            "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station,platform_code\n\
+                             stop:1,Gare,48.8,2.3,1,,A\n\
+                             stop:2,Gare voie 12,48.8,2.3,0,stop:1,12\n\
+                             stop:3,Gare voie 13,48.8,2.3,0,stop:1,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+            let mut equipments = EquipmentList::default();
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let (stop_areas, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            assert!(stop_areas.contains_id("stop:1"));
+            let platform_code = |id: &str| stop_points.get(id).unwrap().platform_code.clone();
+            assert_eq!(Some("12".to_string()), platform_code("stop:2"));
+            assert_eq!(None, platform_code("stop:3"));
+        });
+    }
+
+    fn read_stop_parents(stops_content: &str) -> (Vec<String>, Vec<(String, String)>) {
+        let mut parents = vec![];
+        let mut stop_area_ids = vec![];
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+            let mut equipments = EquipmentList::default();
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let (stop_areas, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            stop_area_ids = extract(|sa| sa.id.clone(), &stop_areas);
+            parents = extract(|sp| (sp.id.clone(), sp.stop_area_id.clone()), &stop_points);
+        });
+        (stop_area_ids, parents)
+    }
+
+    #[test]
+    fn read_stops_with_parent_station_self_reference() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                             stop:A,A,48.8,2.3,0,stop:A";
+        let (stop_area_ids, parents) = read_stop_parents(stops_content);
+        assert_eq!(vec!["Navitia:stop:A"], stop_area_ids);
+        assert_eq!(
+            vec![("stop:A".to_string(), "Navitia:stop:A".to_string())],
+            parents
+        );
+    }
+
+    #[test]
+    fn read_stops_with_parent_station_2_cycle() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                             stop:A,A,48.8,2.3,0,stop:B\n\
+                             stop:B,B,48.8,2.3,0,stop:A";
+        let (stop_area_ids, parents) = read_stop_parents(stops_content);
+        assert_eq!(vec!["Navitia:stop:B"], stop_area_ids);
+        assert_eq!(
+            vec![
+                ("stop:A".to_string(), "stop:B".to_string()),
+                ("stop:B".to_string(), "Navitia:stop:B".to_string()),
+            ],
+            parents
+        );
+    }
+
+    #[test]
+    fn read_stops_with_parent_station_3_cycle() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                             stop:A,A,48.8,2.3,0,stop:B\n\
+                             stop:B,B,48.8,2.3,0,stop:C\n\
+                             stop:C,C,48.8,2.3,0,stop:A\n\
+                             stop:D,D,48.8,2.3,0,stop:C";
+        let (stop_area_ids, parents) = read_stop_parents(stops_content);
+        assert_eq!(vec!["Navitia:stop:C"], stop_area_ids);
+        assert_eq!(
+            vec![
+                ("stop:A".to_string(), "stop:B".to_string()),
+                ("stop:B".to_string(), "stop:C".to_string()),
+                ("stop:C".to_string(), "Navitia:stop:C".to_string()),
+                ("stop:D".to_string(), "stop:C".to_string()),
+            ],
+            parents
+        );
+    }
+
     #[test]
     fn location_with_space_proof() {
         let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
@@ -3095,8 +4904,16 @@ mod tests {
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -3166,12 +4983,255 @@ mod tests {
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            let val = super::manage_stop_times(&mut collections, &mut handler, false, None);
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
+
+            // the first stop time of the vj has no departure/arrival, the trip is ignored
+            assert!(collections.vehicle_journeys.is_empty());
+        });
+    }
+
+    #[test]
+    fn gtfs_duplicated_stop_sequences() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                              route_1,agency_1,1,My line 1,3";
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop point name 1,0.1,1.2,0\n\
+                             sp:02,my stop point name 2,0.1,1.2,0\n\
+                             sp:03,my stop point name 3,0.1,1.2,0\n\
+                             sp:04,my stop point name 4,0.1,1.2,0";
+        let trips_content = "trip_id,route_id,service_id\n\
+                             1,route_1,service_1";
+        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                                  1,06:00:00,06:00:00,sp:01,1\n\
+                                  1,06:20:00,06:20:00,sp:03,2\n\
+                                  1,06:10:00,06:10:00,sp:02,2\n\
+                                  1,06:30:00,06:30:00,sp:04,3";
+
+        let read = |strategy| {
+            let mut collections = Collections::default();
+            let mut result = Ok(());
+            test_in_tmp_dir(|path| {
+                let mut handler = PathFileHandler::new(path.to_path_buf());
+                create_file_with_content(path, "routes.txt", routes_content);
+                create_file_with_content(path, "trips.txt", trips_content);
+                create_file_with_content(path, "stop_times.txt", stop_times_content);
+                create_file_with_content(path, "stops.txt", stops_content);
+
+                let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+                collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+                collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+                let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+                let mut equipments = EquipmentList::default();
+                let (_, stop_points, _) =
+                    super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+                collections.stop_points = stop_points;
+                super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+                result = super::manage_stop_times(
+                    &mut collections,
+                    &mut handler,
+                    false,
+                    None,
+                    OnDemandTransportCommentScope::Agency,
+                    strategy,
+                );
+            });
+            result.map(|_| {
+                collections
+                    .vehicle_journeys
+                    .values()
+                    .next()
+                    .unwrap()
+                    .stop_times
+                    .iter()
+                    .map(|st| {
+                        (
+                            collections.stop_points[st.stop_point_idx].id.clone(),
+                            st.sequence,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let error = read(DuplicateStopSequenceStrategy::Fail).unwrap_err();
+        assert_eq!(
+            "the trip '1' has 1 stop_times with a duplicated stop_sequence",
+            error.to_string()
+        );
+        assert_eq!(
+            vec![
+                ("sp:01".to_string(), 1),
+                ("sp:03".to_string(), 2),
+                ("sp:04".to_string(), 3),
+            ],
+            read(DuplicateStopSequenceStrategy::KeepFirst).unwrap()
+        );
+        assert_eq!(
+            vec![
+                ("sp:01".to_string(), 1),
+                ("sp:03".to_string(), 2),
+                ("sp:02".to_string(), 3),
+                ("sp:04".to_string(), 4),
+            ],
+            read(DuplicateStopSequenceStrategy::Renumber).unwrap()
+        );
+    }
+
+    #[test]
+    fn gtfs_flex_booking_rules_and_location_groups() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                              route_1,agency_1,1,My line 1,3";
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop point name 1,48.0,2.0,0\n\
+                             sp:02,my stop point name 2,48.2,2.4,0";
+        let location_groups_content = "location_group_id,location_id,location_group_name\n\
+                                       zone:1,sp:01,My zone\n\
+                                       zone:1,sp:02,My zone\n\
+                                       zone:1,sp:unknown,My zone";
+        let booking_rules_content =
+            "booking_rule_id,booking_type,prior_notice_duration_min,phone_number,message\n\
+                                     br:1,1,30,+33 1 23 45 67 89,Call us";
+        let trips_content = "trip_id,route_id,service_id\n\
+                             1,route_1,service_1";
+        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence,pickup_booking_rule_id,drop_off_booking_rule_id\n\
+                                  1,06:00:00,06:00:00,sp:01,1,br:1,\n\
+                                  1,07:00:00,07:00:00,zone:1,2,,br:unknown";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+            create_file_with_content(path, "location_groups.txt", location_groups_content);
+            create_file_with_content(path, "booking_rules.txt", booking_rules_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (stop_areas, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            collections.stop_areas = stop_areas;
+            collections.stop_points = stop_points;
+            super::read_location_groups(&mut handler, &mut collections).unwrap();
+            collections.booking_rules =
+                read_opt_collection(&mut handler, "booking_rules.txt").unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
+
+            let zone = collections.stop_points.get("zone:1").unwrap();
+            assert_eq!(StopType::Zone, zone.stop_type);
+            assert_eq!("My zone", zone.name);
+            assert_eq!("Navitia:zone:1", zone.stop_area_id);
+            assert!(collections.stop_areas.contains_id("Navitia:zone:1"));
+            approx::assert_relative_eq!(48.1, zone.coord.lat);
+            approx::assert_relative_eq!(2.2, zone.coord.lon);
+
+            let booking_rule = collections.booking_rules.get("br:1").unwrap();
+            assert_eq!(BookingType::SameDay, booking_rule.booking_type);
+            assert_eq!(Some(30), booking_rule.prior_notice_duration_min);
+            assert_eq!(Some("Call us"), booking_rule.message.as_deref());
+
+            let stop_times = &collections.vehicle_journeys.get("1").unwrap().stop_times;
+            assert_eq!(
+                Some("br:1"),
+                stop_times[0].pickup_booking_rule_id.as_deref()
+            );
+            assert_eq!(
+                collections.stop_points.get_idx("zone:1").unwrap(),
+                stop_times[1].stop_point_idx
+            );
+            // Not in booking_rules.txt
+            assert_eq!(None, stop_times[1].drop_off_booking_rule_id);
+        });
+    }
+
+    #[test]
+    fn gtfs_undefined_stop_times_with_shape_dist_traveled() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                              route_1,agency_1,1,My line 1,3";
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type\n\
+                             sp:01,my stop point name 1,0.1,1.2,0\n\
+                             sp:02,my stop point name 2,0.1,1.2,0\n\
+                             sp:03,my stop point name 3,0.1,1.2,0\n\
+                             sp:04,my stop point name 4,0.1,1.2,0";
+        let trips_content = "trip_id,route_id,service_id\n\
+                             1,route_1,service_1\n\
+                             2,route_1,service_1";
+        let stop_times_content =
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,shape_dist_traveled\n\
+                                  1,06:00:00,06:00:00,sp:01,1,0\n\
+                                  1,,,sp:02,2,1\n\
+                                  1,,,sp:03,3,4\n\
+                                  1,07:00:00,07:00:00,sp:04,4,5\n\
+                                  2,06:00:00,06:00:00,sp:01,1,0\n\
+                                  2,,,sp:02,2,1\n\
+                                  2,,,sp:03,3,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            collections.stop_points = stop_points;
+
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
-            // the first stop time of the vj has no departure/arrival, it's an error
-            let err = val.unwrap_err();
-            assert_eq!( "the first stop time of the vj '1' has no departure/arrival, the stop_times.txt file is not valid",format!("{}", err));
+            // the last stop time of the trip '2' has no departure/arrival
+            assert_eq!(1, collections.vehicle_journeys.len());
+            let vj = collections.vehicle_journeys.get("1").unwrap();
+            assert_eq!(
+                vec![
+                    (Time::new(6, 0, 0), Some(StopTimePrecision::Exact)),
+                    (Time::new(6, 12, 0), Some(StopTimePrecision::Approximate)),
+                    (Time::new(6, 48, 0), Some(StopTimePrecision::Approximate)),
+                    (Time::new(7, 0, 0), Some(StopTimePrecision::Exact)),
+                ],
+                vj.stop_times
+                    .iter()
+                    .map(|st| (st.departure_time, st.precision.clone()))
+                    .collect::<Vec<_>>()
+            );
         });
     }
     #[test]
@@ -3200,11 +5260,44 @@ mod tests {
             assert_eq!(1, stop_node.count());
             let stop_boarding = stop_locations
                 .values()
-                .filter(|sl| sl.stop_type == StopType::GenericNode);
+                .filter(|sl| sl.stop_type == StopType::BoardingArea);
             assert_eq!(1, stop_boarding.count());
         });
     }
     #[test]
+    fn stop_location_parents() {
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+             stoparea_id,stop area name,0.1,1.2,1,\n\
+             stoppoint_id,my stop name,0.1,1.2,0,stoparea_id\n\
+             entrance_id,entrance name,0.1,1.2,2,stoppoint_id\n\
+             node_id,node name,,,3,stoparea_id\n\
+             node_in_platform_id,node name,,,3,stoppoint_id\n\
+             boarding_id,boarding name,,,4,stoppoint_id\n\
+             boarding_in_station_id,boarding name,,,4,stoparea_id\n\
+             boarding_in_node_id,boarding name,,,4,node_id";
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+            let mut equipments = EquipmentList::default();
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let (_, _, stop_locations) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let parents: Vec<(&str, &str)> = stop_locations
+                .values()
+                .map(|sl| (sl.id.as_str(), sl.parent_id.as_deref().unwrap()))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("entrance_id", "stoparea_id"),
+                    ("node_id", "stoparea_id"),
+                    ("node_in_platform_id", "stoparea_id"),
+                    ("boarding_id", "stoppoint_id"),
+                ],
+                parents
+            );
+        });
+    }
+    #[test]
     fn filter_pathway() {
         let stops_content =
             "stop_id,stop_code,stop_name,stop_lat,stop_lon,location_type,parent_station,level_id\n\
@@ -3236,6 +5329,51 @@ mod tests {
         })
     }
     #[test]
+    fn read_pathway_properties() {
+        let stops_content =
+            "stop_id,stop_code,stop_name,stop_lat,stop_lon,location_type,parent_station,level_id\n\
+             stoppoint_id,1234,my stop name,0.1,1.2,0,stoparea_id,2\n\
+             stoparea_id,5678,stop area name,0.1,1.2,1,,\n\
+             entrance_id,,entrance name,0.1,1.2,2,stoparea_id,1\n\
+             node_id,,node name,0.1,1.2,3,stoparea_id,2";
+        let pathway_content = "pathway_id,from_stop_id,to_stop_id,pathway_mode,is_bidirectional,length,traversal_time,stair_count,max_slope,min_width,signposted_as,reversed_signposted_as\n\
+                               1,entrance_id,node_id,2,1,12.5,60,-20,,1.5,Platforms,Exit\n\
+                               2,node_id,stoppoint_id,4,0,-3,fast,0,0.08,-1,Platform 1,Exit\n";
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+            create_file_with_content(path, "pathways.txt", pathway_content);
+            let mut collections = Collections::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, stop_locations) =
+                super::read_stops(&mut handler, &mut collections.comments, &mut equipments)
+                    .unwrap();
+            collections.stop_points = stop_points;
+            collections.stop_locations = stop_locations;
+
+            super::manage_pathways(&mut collections, &mut handler).unwrap();
+            assert_eq!(2, collections.pathways.len());
+            let stairs = collections.pathways.get("1").unwrap();
+            assert!(stairs.is_bidirectional);
+            assert_eq!(Some(rust_decimal_macros::dec!(12.5)), stairs.length);
+            assert_eq!(Some(60), stairs.traversal_time);
+            assert_eq!(Some(-20), stairs.stair_count);
+            assert_eq!(None, stairs.max_slope);
+            assert_eq!(Some(1.5), stairs.min_width);
+            assert_eq!(Some("Platforms".to_string()), stairs.signposted_as);
+            assert_eq!(Some("Exit".to_string()), stairs.reversed_signposted_as);
+            // The invalid values are ignored, the pathway being kept
+            let escalator = collections.pathways.get("2").unwrap();
+            assert_eq!(None, escalator.length);
+            assert_eq!(None, escalator.traversal_time);
+            assert_eq!(None, escalator.stair_count);
+            assert_eq!(Some(0.08), escalator.max_slope);
+            assert_eq!(None, escalator.min_width);
+            assert_eq!(Some("Platform 1".to_string()), escalator.signposted_as);
+            assert_eq!(None, escalator.reversed_signposted_as);
+        })
+    }
+    #[test]
     fn read_levels() {
         let stops_content =
             "stop_id,stop_code,stop_name,stop_lat,stop_lon,location_type,parent_station,level_id\n\
@@ -3258,6 +5396,110 @@ mod tests {
             assert_eq!(4, levels.len());
         })
     }
+    #[test]
+    fn remove_unknown_levels() {
+        let stops_content =
+            "stop_id,stop_code,stop_name,stop_lat,stop_lon,location_type,parent_station,level_id\n\
+             stoppoint_id,1234,my stop name,0.1,1.2,0,stoparea_id,2\n\
+             stoparea_id,5678,stop area name,0.1,1.2,1,,3\n\
+             entrance_id,,entrance name,0.1,1.2,2,stoparea_id,1\n\
+             node_id,,node name,0.1,1.2,3,stoparea_id,";
+        let level_content = "level_id,level_index\n\
+                             1,0\n\
+                             2,2";
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "stops.txt", stops_content);
+            create_file_with_content(path, "levels.txt", level_content);
+            let mut collections = Collections::default();
+            let mut equipments = EquipmentList::default();
+            let (stop_areas, stop_points, stop_locations) =
+                super::read_stops(&mut handler, &mut collections.comments, &mut equipments)
+                    .unwrap();
+            collections.stop_areas = stop_areas;
+            collections.stop_points = stop_points;
+            collections.stop_locations = stop_locations;
+            collections.levels = read_opt_collection(&mut handler, "levels.txt").unwrap();
+
+            super::remove_unknown_levels(&mut collections).unwrap();
+            let stop_point = collections.stop_points.get("stoppoint_id").unwrap();
+            assert_eq!(Some("2".to_string()), stop_point.level_id);
+            let stop_area = collections.stop_areas.get("stoparea_id").unwrap();
+            assert_eq!(None, stop_area.level_id);
+            let entrance = collections.stop_locations.get("entrance_id").unwrap();
+            assert_eq!(Some("1".to_string()), entrance.level_id);
+            let node = collections.stop_locations.get("node_id").unwrap();
+            assert_eq!(None, node.level_id);
+        })
+    }
+    #[test]
+    fn remove_trips_without_enough_stop_times() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
+                              route_1,agency_1,1,My line 1,3,8F7A32,FFFFFF";
+
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                             sp:01,my stop point name 1,0.1,1.2,0,\n\
+                             sp:02,my stop point name 2,0.2,1.5,0,";
+
+        let trips_content =
+            "trip_id,route_id,direction_id,service_id,wheelchair_accessible,bikes_allowed\n\
+             1,route_1,0,service_1,1,\n\
+             2,route_1,0,service_1,,1\n\
+             3,route_1,0,service_1,,";
+
+        let stop_times_content =
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,stop_headsign\n\
+                                  1,06:00:00,06:00:00,sp:01,1,\n\
+                                  1,06:06:00,06:06:00,sp:02,2,\n\
+                                  2,07:00:00,07:00:00,sp:01,1,over there";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, _) =
+                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            collections.stop_points = stop_points;
+
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
+            assert_eq!(2, collections.trip_properties.len());
+            assert_eq!(1, collections.stop_time_headsigns.len());
+
+            super::remove_trips_without_enough_stop_times(&mut collections).unwrap();
+            assert_eq!(vec!["1"], extract_ids(&collections.vehicle_journeys));
+            let trip_property_id = collections
+                .vehicle_journeys
+                .get("1")
+                .unwrap()
+                .trip_property_id
+                .clone();
+            assert_eq!(
+                vec![trip_property_id.unwrap().as_str()],
+                extract_ids(&collections.trip_properties)
+            );
+            assert!(collections.stop_time_headsigns.is_empty());
+        });
+    }
+
     #[test]
     fn gtfs_stop_times_precision() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -3296,8 +5538,16 @@ mod tests {
                 super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, true, None).unwrap();
+            super::read_routes(&mut handler, &mut collections, false, &[], None).unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                true,
+                None,
+                OnDemandTransportCommentScope::Agency,
+                DuplicateStopSequenceStrategy::KeepFirst,
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -3312,6 +5562,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -3324,6 +5578,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -3336,6 +5594,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -3378,7 +5640,7 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, read_as_line).unwrap();
+            super::read_routes(&mut handler, &mut collections, read_as_line, &[], None).unwrap();
             collections
         }
 
@@ -0,0 +1,266 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Rewriting of the identifiers of a model for the GTFS consumers only
+//! accepting a restricted set of characters.
+
+use crate::{
+    file_handler::FileWriter, model::Model, timing, AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{Arc, Mutex, PoisonError},
+};
+use tracing::{info, warn};
+
+/// Rule of the identifier sanitization: the characters which are not ASCII
+/// letters or digits, nor one of `allowed_characters`, are replaced by
+/// `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdSanitization {
+    /// The characters allowed besides the ASCII letters and digits
+    pub allowed_characters: String,
+    /// The character replacing the characters which are not allowed
+    pub replacement: char,
+}
+
+impl Default for IdSanitization {
+    fn default() -> Self {
+        IdSanitization {
+            allowed_characters: "_:-".to_string(),
+            replacement: '_',
+        }
+    }
+}
+
+impl IdSanitization {
+    /// The identifier with its characters which are not allowed replaced.
+    ///
+    /// ```
+    /// # use transit_model::gtfs::IdSanitization;
+    /// let sanitization = IdSanitization::default();
+    /// assert_eq!("stop_1_Gare_d_Orl_ans", sanitization.sanitize("stop 1/Gare d'Orléans"));
+    /// assert_eq!("OIF:SA:8739322", sanitization.sanitize("OIF:SA:8739322"));
+    /// ```
+    pub fn sanitize(&self, id: &str) -> String {
+        id.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || self.allowed_characters.contains(c) {
+                    c
+                } else {
+                    self.replacement
+                }
+            })
+            .collect()
+    }
+
+    // The new identifier of each identifier changed by the sanitization. The
+    // identifiers which don't change are kept, the others getting a numeric
+    // suffix when their sanitized identifier is already used.
+    fn rewrites(&self, ids: &BTreeSet<String>) -> BTreeMap<String, String> {
+        let mut used: HashSet<String> = ids
+            .iter()
+            .filter(|id| self.sanitize(id) == **id)
+            .cloned()
+            .collect();
+        let mut rewrites = BTreeMap::new();
+        for id in ids {
+            let sanitized = self.sanitize(id);
+            if sanitized == *id {
+                continue;
+            }
+            let mut new_id = sanitized.clone();
+            let mut suffix = 0;
+            while used.contains(&new_id) {
+                suffix += 1;
+                new_id = format!("{}_{}", sanitized, suffix);
+            }
+            if suffix > 0 {
+                warn!(
+                    "identifier {:?} is sanitized as {:?}, {:?} being already used",
+                    id, new_id, sanitized
+                );
+            }
+            used.insert(new_id.clone());
+            rewrites.insert(id.clone(), new_id);
+        }
+        rewrites
+    }
+}
+
+/// Rewrites the identifiers of every object of the model with the given
+/// `IdSanitization`, the references between the objects being rewritten the
+/// same way. Returns the new model and the new identifier of each rewritten
+/// identifier.
+///
+/// An identifier is rewritten the same way whatever the type of its objects.
+/// When the sanitized identifier of an object is already used, it is
+/// disambiguated with a numeric suffix (`_1`, `_2`, ...).
+pub fn sanitize_ids(
+    model: Model,
+    sanitization: &IdSanitization,
+) -> Result<(Model, BTreeMap<String, String>)> {
+    let mut collections = model.into_collections();
+
+    // The identifiers of the model are the ones going through the prefix
+    // configuration, which sees every identifier and reference
+    let ids = Arc::new(Mutex::new(BTreeSet::new()));
+    let mut prefix_conf = PrefixConfiguration::default();
+    {
+        let ids = Arc::clone(&ids);
+        prefix_conf.set_id_rewriter(move |id| {
+            ids.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(id.to_string());
+            id.to_string()
+        });
+    }
+    collections.clone().prefix(&prefix_conf);
+    drop(prefix_conf);
+    let ids = std::mem::take(&mut *ids.lock().unwrap_or_else(PoisonError::into_inner));
+
+    let rewrites = sanitization.rewrites(&ids);
+    if rewrites.is_empty() {
+        return Ok((Model::new(collections)?, rewrites));
+    }
+    info!("Sanitizing {} identifiers", rewrites.len());
+    let mut prefix_conf = PrefixConfiguration::default();
+    {
+        let rewrites = Arc::new(rewrites.clone());
+        prefix_conf
+            .set_id_rewriter(move |id| rewrites.get(id).cloned().unwrap_or_else(|| id.to_string()));
+    }
+    collections.prefix(&prefix_conf);
+    Ok((Model::new(collections)?, rewrites))
+}
+
+#[derive(Serialize)]
+struct SanitizedId<'a> {
+    old_id: &'a str,
+    new_id: &'a str,
+}
+
+/// Writes the identifiers rewritten by [sanitize_ids] in `sanitized_ids.txt`,
+/// with the columns `old_id` and `new_id`.
+pub(crate) fn write_sanitized_ids<W: FileWriter>(
+    file_writer: &W,
+    rewrites: &BTreeMap<String, String>,
+) -> Result<()> {
+    let file = "sanitized_ids.txt";
+    info!("Writing {}", file);
+    let stage = timing::write_file(file);
+    stage.record_rows(rewrites.len());
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for (old_id, new_id) in rewrites {
+        wtr.serialize(SanitizedId { old_id, new_id })
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn rewrites_with_collisions() {
+        let ids: BTreeSet<String> = ["a b", "a/b", "a_b", "a_b_1", "clean"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        let rewrites = IdSanitization::default().rewrites(&ids);
+        let expected: BTreeMap<String, String> = [("a b", "a_b_2"), ("a/b", "a_b_3")]
+            .iter()
+            .map(|(old_id, new_id)| (old_id.to_string(), new_id.to_string()))
+            .collect();
+        assert_eq!(expected, rewrites);
+    }
+
+    #[test]
+    fn sanitize_ids_keeps_the_references() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.stop_points.get_mut("GDLB").unwrap().id = "GDL B/1".to_string();
+        let model = Model::new(collections).unwrap();
+        let vj_count = model.vehicle_journeys.len();
+
+        let (model, rewrites) = sanitize_ids(model, &IdSanitization::default()).unwrap();
+        assert_eq!(Some(&"GDL_B_1".to_string()), rewrites.get("GDL B/1"));
+        assert_eq!(1, rewrites.len());
+        assert!(model.stop_points.contains_id("GDL_B_1"));
+        assert_eq!(vj_count, model.vehicle_journeys.len());
+        let sp_idx = model.stop_points.get_idx("GDL_B_1").unwrap();
+        for vj_id in &["B42F1", "B42B1"] {
+            let vj = model.vehicle_journeys.get(vj_id).unwrap();
+            assert!(vj.stop_times.iter().any(|st| st.stop_point_idx == sp_idx));
+        }
+    }
+
+    #[test]
+    fn write_gtfs_with_sanitized_ids() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.stop_points.get_mut("GDLB").unwrap().id = "GDL B/1".to_string();
+        collections.lines.get_mut("B42").unwrap().id = "line é".to_string();
+        for route_id in ["B42F", "B42B"] {
+            collections.routes.get_mut(route_id).unwrap().line_id = "line é".to_string();
+        }
+        let model = Model::new(collections).unwrap();
+        let configuration = crate::gtfs::WriteConfiguration {
+            id_sanitization: Some(IdSanitization::default()),
+            ..Default::default()
+        };
+        let zip = crate::gtfs::write_to_zip_writer(
+            model,
+            std::io::Cursor::new(Vec::new()),
+            &configuration,
+        )
+        .unwrap()
+        .into_inner();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip.clone())).unwrap();
+        let mut content = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("sanitized_ids.txt").unwrap(),
+            &mut content,
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["old_id,new_id", "GDL B/1,GDL_B_1", "line é,line__"],
+            content.lines().collect::<Vec<_>>()
+        );
+        let model =
+            crate::gtfs::from_zip_reader(std::io::Cursor::new(zip), "sanitized.zip").unwrap();
+        assert!(model.lines.contains_id("line__"));
+        assert!(model.stop_points.contains_id("GDL_B_1"));
+        assert_eq!(6, model.vehicle_journeys.len());
+        assert_eq!(
+            2,
+            model
+                .vehicle_journeys
+                .get("B42F1")
+                .unwrap()
+                .stop_times
+                .len()
+        );
+    }
+}
@@ -13,36 +13,107 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, DirectionType, Route, RouteType, Shape, Stop, StopLocationType, StopTime, Transfer,
-    Trip,
+    Agency, Area, AreaStop, Attribution, DirectionType, FareAttribute, FareLegRule, FareMedia,
+    FareMediaType, FareProduct, FareRule, FeedInfo, GtfsNetwork, PaymentMethod, Route,
+    RouteNetwork, RouteType, Shape, Stop, StopLocationType, StopTime, Transfer, TransferType, Trip,
 };
+use crate::file_handler::FileWriter;
 use crate::gtfs::ExtendedRoute;
 use crate::model::{GetCorresponding, Model};
 use crate::objects;
 use crate::objects::Transfer as NtfsTransfer;
 use crate::objects::*;
+use crate::timing;
+use crate::utils::sorted_by_id;
 use crate::Result;
 use anyhow::Context;
 use geo::Geometry as GeoGeometry;
 use relational_types::IdxSet;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path;
 use tracing::{info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 
-pub fn write_transfers(path: &path::Path, transfers: &Collection<NtfsTransfer>) -> Result<()> {
-    if transfers.is_empty() {
+// Columns of `transfers.txt`, written when the model has transfers between
+// trips
+#[derive(Debug, Serialize, PartialEq)]
+struct TransferTrips<'a> {
+    from_trip_id: Option<&'a str>,
+    to_trip_id: Option<&'a str>,
+}
+
+// The transfer between trips, from the last stop of the first one to the
+// first stop of the second one
+fn trip_transfer<'a>(
+    trip_transfer: &'a TripTransfer,
+    vehicle_journeys: &CollectionWithId<VehicleJourney>,
+    stop_points: &CollectionWithId<StopPoint>,
+) -> Option<(Transfer, TransferTrips<'a>)> {
+    let from_stop_time = vehicle_journeys
+        .get(&trip_transfer.from_vehicle_journey_id)?
+        .stop_times
+        .last()?;
+    let to_stop_time = vehicle_journeys
+        .get(&trip_transfer.to_vehicle_journey_id)?
+        .stop_times
+        .first()?;
+    let transfer = Transfer {
+        from_stop_id: Some(stop_points[from_stop_time.stop_point_idx].id.clone()),
+        to_stop_id: Some(stop_points[to_stop_time.stop_point_idx].id.clone()),
+        transfer_type: if trip_transfer.in_seat {
+            TransferType::InSeat
+        } else {
+            TransferType::InSeatNotAllowed
+        },
+        min_transfer_time: None,
+        from_trip_id: None,
+        to_trip_id: None,
+    };
+    let trips = TransferTrips {
+        from_trip_id: Some(&trip_transfer.from_vehicle_journey_id),
+        to_trip_id: Some(&trip_transfer.to_vehicle_journey_id),
+    };
+    Some((transfer, trips))
+}
+
+pub fn write_transfers<W: FileWriter>(
+    file_writer: &W,
+    transfers: &Collection<NtfsTransfer>,
+    trip_transfers: &Collection<TripTransfer>,
+    vehicle_journeys: &CollectionWithId<VehicleJourney>,
+    stop_points: &CollectionWithId<StopPoint>,
+) -> Result<()> {
+    if transfers.is_empty() && trip_transfers.is_empty() {
         return Ok(());
     }
     info!("Writing transfers.txt");
-    let path = path.join("transfers.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let _stage = timing::write_file("transfers.txt");
+    let (writer, path) = file_writer.create_file("transfers.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
     for t in transfers.values() {
         if t.from_stop_id != t.to_stop_id {
-            wtr.serialize(Transfer::from(t))
-                .with_context(|| format!("Error reading {:?}", path))?;
+            if trip_transfers.is_empty() {
+                wtr.serialize(Transfer::from(t))
+            } else {
+                let no_trips = TransferTrips {
+                    from_trip_id: None,
+                    to_trip_id: None,
+                };
+                wtr.serialize((Transfer::from(t), no_trips))
+            }
+            .with_context(|| format!("Error reading {:?}", path))?;
+        }
+    }
+    for t in trip_transfers.values() {
+        match trip_transfer(t, vehicle_journeys, stop_points) {
+            Some(record) => wtr
+                .serialize(record)
+                .with_context(|| format!("Error reading {:?}", path))?,
+            None => warn!(
+                "the transfer between the trips {} and {} is not exported in transfers.txt: a trip has no stop time",
+                t.from_vehicle_journey_id, t.to_vehicle_journey_id
+            ),
         }
     }
 
@@ -52,16 +123,68 @@ pub fn write_transfers(path: &path::Path, transfers: &Collection<NtfsTransfer>)
     Ok(())
 }
 
-pub fn write_agencies(
-    path: &path::Path,
-    networks: &CollectionWithId<objects::Network>,
-) -> Result<()> {
+// The agency of a network, completed with the contact data of the company
+// operating its trips: the network gives the identifier, name, timezone and
+// language of the agency, the company its phone and email, its URL being
+// only used when the network has none.
+fn make_gtfs_agency(network: &objects::Network, company: Option<&objects::Company>) -> Agency {
+    let mut agency = Agency::from(network);
+    if let Some(company) = company {
+        if let (None, Some(url)) = (&network.url, &company.url) {
+            agency.url = url.clone();
+        }
+        agency.phone = company.phone.clone().or(agency.phone);
+        agency.email = company.mail.clone();
+    }
+    agency
+}
+
+// The company of each network, operating the most trips of the network (the
+// smallest identifier in case of a tie)
+fn get_network_companies(model: &Model) -> HashMap<&str, &objects::Company> {
+    let mut trip_counts: HashMap<&str, BTreeMap<&str, usize>> = HashMap::new();
+    for vj in model.vehicle_journeys.values() {
+        let network_id = model
+            .routes
+            .get(&vj.route_id)
+            .and_then(|route| model.lines.get(&route.line_id))
+            .map(|line| line.network_id.as_str());
+        if let Some(network_id) = network_id {
+            *trip_counts
+                .entry(network_id)
+                .or_default()
+                .entry(vj.company_id.as_str())
+                .or_default() += 1;
+        }
+    }
+    trip_counts
+        .into_iter()
+        .filter_map(|(network_id, company_counts)| {
+            company_counts
+                .into_iter()
+                // `max_by_key` keeps the last of the maximums, the smallest
+                // identifier in the reverse order
+                .rev()
+                .max_by_key(|(_, count)| *count)
+                .and_then(|(company_id, _)| model.companies.get(company_id))
+                .map(|company| (network_id, company))
+        })
+        .collect()
+}
+
+/// Writing the agencies in 'agency.txt', one for each network, their contact
+/// data coming from the company operating the trips of the network (see
+/// `make_gtfs_agency`).
+pub fn write_agencies<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
     info!("Writing agency.txt");
-    let path = path.join("agency.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for n in networks.values() {
-        wtr.serialize(Agency::from(n))
+    let stage = timing::write_file("agency.txt");
+    stage.record_rows(model.networks.len());
+    let network_companies = get_network_companies(model);
+    let (writer, path) = file_writer.create_file("agency.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for n in model.networks.values() {
+        let company = network_companies.get(n.id.as_str()).copied();
+        wtr.serialize(make_gtfs_agency(n, company))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
 
@@ -71,6 +194,84 @@ pub fn write_agencies(
     Ok(())
 }
 
+/// Writing `feed_info.txt` from the `feed_infos` of the model. When they are
+/// not given, `feed_start_date` and `feed_end_date` are the validity period
+/// of the datasets; given dates outside of this period are reported.
+pub fn write_feed_info<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let get = |key: &str| model.feed_infos.get(key).filter(|v| !v.is_empty()).cloned();
+    let validity_period = model.calculate_validity_period().ok();
+    let feed_date = |key: &str, dataset_date: Option<Date>| match get(key) {
+        Some(value) => {
+            match (Date::parse_from_str(&value, "%Y%m%d"), validity_period) {
+                (Ok(date), Some((start_date, end_date)))
+                    if date < start_date || date > end_date =>
+                {
+                    warn!(
+                        "feed_info.txt: {} {} is outside of the validity period of the datasets from {} to {}",
+                        key,
+                        value,
+                        start_date.format("%Y%m%d"),
+                        end_date.format("%Y%m%d")
+                    );
+                }
+                (Err(_), _) => warn!("feed_info.txt: invalid {} {:?}", key, value),
+                _ => {}
+            }
+            Some(value)
+        }
+        None => dataset_date.map(|date| date.format("%Y%m%d").to_string()),
+    };
+    let feed_info = FeedInfo {
+        feed_publisher_name: get("feed_publisher_name"),
+        feed_publisher_url: get("feed_publisher_url"),
+        feed_lang: get("feed_lang"),
+        default_lang: get("default_lang"),
+        feed_start_date: feed_date("feed_start_date", validity_period.map(|(start, _)| start)),
+        feed_end_date: feed_date("feed_end_date", validity_period.map(|(_, end)| end)),
+        feed_version: get("feed_version"),
+        feed_contact_email: get("feed_contact_email"),
+        feed_contact_url: get("feed_contact_url"),
+    };
+    if feed_info == FeedInfo::default() {
+        return Ok(());
+    }
+    write_csv(file_writer, "feed_info.txt", &[feed_info])
+}
+
+/// Writing `attributions.txt`, with a producer attribution for each
+/// contributor and an operator attribution for each company having a URL
+pub fn write_attributions<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let producers = model.contributors.values().map(|contributor| Attribution {
+        attribution_id: contributor.id.clone(),
+        organization_name: contributor.name.clone(),
+        is_producer: true,
+        is_operator: false,
+        attribution_url: contributor.website.clone(),
+        attribution_email: None,
+        attribution_phone: None,
+        attribution_license: contributor.license.clone(),
+    });
+    let operators = model
+        .companies
+        .values()
+        .filter(|company| company.url.is_some())
+        .map(|company| Attribution {
+            attribution_id: company.id.clone(),
+            organization_name: company.name.clone(),
+            is_producer: false,
+            is_operator: true,
+            attribution_url: company.url.clone(),
+            attribution_email: company.mail.clone(),
+            attribution_phone: company.phone.clone(),
+            attribution_license: None,
+        });
+    let attributions: Vec<Attribution> = producers.chain(operators).collect();
+    if attributions.is_empty() {
+        return Ok(());
+    }
+    write_csv(file_writer, "attributions.txt", &attributions)
+}
+
 /// get the first comment ordered by name
 fn get_first_comment_name<T: objects::CommentLinks>(
     obj: &T,
@@ -173,31 +374,71 @@ fn ntfs_stop_location_to_gtfs_stop(
     }
 }
 
-pub fn write_stops(
-    path: &path::Path,
+// How the stops are written in 'stops.txt' (see the fields of the same name
+// of `WriteConfiguration`)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StopsOptions<'a> {
+    pub skip_generated_stop_areas: bool,
+    pub stop_code_source: Option<&'a str>,
+}
+
+pub fn write_stops<W: FileWriter>(
+    file_writer: &W,
     stop_points: &CollectionWithId<objects::StopPoint>,
     stop_areas: &CollectionWithId<objects::StopArea>,
     stop_locations: &CollectionWithId<objects::StopLocation>,
     comments: &CollectionWithId<objects::Comment>,
     equipments: &CollectionWithId<objects::Equipment>,
+    options: StopsOptions,
 ) -> Result<()> {
+    let StopsOptions {
+        skip_generated_stop_areas,
+        stop_code_source,
+    } = options;
     let file = "stops.txt";
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let _stage = timing::write_file(file);
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    // The stop area generated for a stop point without parent station (see
+    // `StopArea::from<StopPoint>`)
+    let is_generated = |sp: &objects::StopPoint| {
+        skip_generated_stop_areas && sp.stop_area_id == format!("Navitia:{}", sp.id)
+    };
     info!("Writing {} from StopPoint", file);
     for sp in stop_points.values() {
-        wtr.serialize(ntfs_stop_point_to_gtfs_stop(sp, comments, equipments))
+        let mut stop = ntfs_stop_point_to_gtfs_stop(sp, comments, equipments);
+        if is_generated(sp) {
+            stop.parent_station = None;
+        }
+        if let Some(code) = stop_code_source.and_then(|system| get_stop_code(sp, system)) {
+            stop.code = Some(code);
+        }
+        wtr.serialize(stop)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
+    let generated_stop_areas: HashSet<&str> = stop_points
+        .values()
+        .filter(|sp| is_generated(sp))
+        .map(|sp| sp.stop_area_id.as_str())
+        .collect();
     info!("Writing {} from StopArea", file);
-    for sa in stop_areas.values() {
-        wtr.serialize(ntfs_stop_area_to_gtfs_stop(sa, comments, equipments))
+    for sa in stop_areas
+        .values()
+        .filter(|sa| !generated_stop_areas.contains(sa.id.as_str()))
+    {
+        let mut stop = ntfs_stop_area_to_gtfs_stop(sa, comments, equipments);
+        if let Some(code) = stop_code_source.and_then(|system| get_stop_code(sa, system)) {
+            stop.code = Some(code);
+        }
+        wtr.serialize(stop)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     info!("Writing {} from StopLocation", file);
-    for sl in stop_locations.values() {
+    for sl in stop_locations
+        .values()
+        .filter(|sl| is_gtfs_stop_location(sl))
+    {
         wtr.serialize(ntfs_stop_location_to_gtfs_stop(sl, comments, equipments))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
@@ -208,6 +449,71 @@ pub fn write_stops(
     Ok(())
 }
 
+// The `stop_code` of a stop, taken from its codes of the given system. The
+// smallest one is used when the stop has several codes of this system.
+fn get_stop_code<T: Id<T> + Codes>(stop: &T, system: &str) -> Option<String> {
+    let mut codes = stop
+        .codes()
+        .iter()
+        .filter(|(code_system, _)| *code_system == system)
+        .map(|(_, value)| value);
+    let code = codes.next()?;
+    if codes.next().is_some() {
+        warn!(
+            "stop {} has several codes of the system {:?}, {:?} is used as stop_code",
+            stop.id(),
+            system,
+            code
+        );
+    }
+    Some(code.clone())
+}
+
+// The geographic zones of the NTFS have no equivalent in the GTFS, the other
+// stop locations are written in 'stops.txt'
+fn is_gtfs_stop_location(sl: &objects::StopLocation) -> bool {
+    sl.stop_type != StopType::Zone
+}
+
+/// Writing the pathways in 'pathways.txt'. The pathways from or to a stop
+/// which is not written in 'stops.txt' are skipped.
+pub fn write_pathways<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let is_exported = |stop_id: &str| {
+        model.stop_points.contains_id(stop_id)
+            || model
+                .stop_locations
+                .get(stop_id)
+                .is_some_and(is_gtfs_stop_location)
+    };
+    let (pathways, skipped): (Vec<_>, Vec<_>) = model
+        .pathways
+        .values()
+        .partition(|pw| is_exported(&pw.from_stop_id) && is_exported(&pw.to_stop_id));
+    for pathway in skipped {
+        warn!(
+            "pathway {} is not exported, its stops {} and {} are not both exported in stops.txt",
+            pathway.id, pathway.from_stop_id, pathway.to_stop_id
+        );
+    }
+    if pathways.is_empty() {
+        return Ok(());
+    }
+    let file = "pathways.txt";
+    info!("Writing {}", file);
+    let stage = timing::write_file(file);
+    stage.record_rows(pathways.len());
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for pathway in pathways {
+        wtr.serialize(pathway)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+
+    Ok(())
+}
+
 fn get_gtfs_direction_id_from_ntfs_route(route: &objects::Route) -> DirectionType {
     match route.direction_type.as_deref() {
         Some("forward") | Some("clockwise") | Some("inbound") => DirectionType::Forward,
@@ -215,7 +521,50 @@ fn get_gtfs_direction_id_from_ntfs_route(route: &objects::Route) -> DirectionTyp
     }
 }
 
-fn make_gtfs_trip_from_ntfs_vj(vj: &objects::VehicleJourney, model: &Model) -> Trip {
+// With a single GTFS route per line, the `direction_id` is the only way to
+// tell the routes of the line apart: the unknown direction types are `0`
+// (reported by `write_trips`)
+fn is_backward_direction_type(direction_type: Option<&str>) -> Option<bool> {
+    match direction_type {
+        Some("forward") | Some("clockwise") | Some("inbound") | None => Some(false),
+        Some("backward") | Some("anticlockwise") | Some("outbound") => Some(true),
+        Some(_) => None,
+    }
+}
+
+fn get_gtfs_direction_id_of_line_route(route: &objects::Route) -> DirectionType {
+    match is_backward_direction_type(route.direction_type.as_deref()) {
+        Some(true) => DirectionType::Backward,
+        _ => DirectionType::Forward,
+    }
+}
+
+// The geometry of a trip, or else the one of its route
+fn get_vj_geometry_id<'a>(vj: &'a objects::VehicleJourney, model: &'a Model) -> Option<&'a str> {
+    vj.geometry_id.as_deref().or_else(|| {
+        model
+            .routes
+            .get(&vj.route_id)
+            .and_then(|route| route.geometry_id.as_deref())
+    })
+}
+
+// The shape of a trip is its geometry as long as it is a LINESTRING, written
+// in 'shapes.txt'
+fn get_gtfs_shape_id<'a>(vj: &'a objects::VehicleJourney, model: &'a Model) -> Option<&'a str> {
+    get_vj_geometry_id(vj, model).filter(|geometry_id| {
+        model
+            .geometries
+            .get(geometry_id)
+            .is_some_and(|geometry| matches!(geometry.geometry, GeoGeometry::LineString(_)))
+    })
+}
+
+fn make_gtfs_trip_from_ntfs_vj(
+    vj: &objects::VehicleJourney,
+    model: &Model,
+    one_route_per_line: bool,
+) -> Trip {
     let mut wheelchair_and_bike = (Availability::default(), Availability::default());
     if let Some(tp_id) = &vj.trip_property_id {
         if let Some(tp) = &model.trip_properties.get(tp_id) {
@@ -224,33 +573,61 @@ fn make_gtfs_trip_from_ntfs_vj(vj: &objects::VehicleJourney, model: &Model) -> T
     }
     let route = &model.routes.get(&vj.route_id).unwrap();
     let line_idx = &model.lines.get_idx(&route.line_id).unwrap();
-    let route_id = &get_line_physical_modes(*line_idx, &model.physical_modes, model)
-        .into_iter()
-        .find(|pmo| pmo.inner.id == vj.physical_mode_id)
-        .map(|pm| get_gtfs_route_id_from_ntfs_line_id(&route.line_id, &pm))
-        .unwrap();
+    let route_id = if one_route_per_line {
+        route.line_id.clone()
+    } else {
+        get_line_physical_modes(*line_idx, &model.physical_modes, model)
+            .into_iter()
+            .find(|pmo| pmo.inner.id == vj.physical_mode_id)
+            .map(|pm| get_gtfs_route_id_from_ntfs_line_id(&route.line_id, &pm))
+            .unwrap()
+    };
+    let direction = if one_route_per_line {
+        get_gtfs_direction_id_of_line_route(route)
+    } else {
+        get_gtfs_direction_id_from_ntfs_route(route)
+    };
 
     Trip {
-        route_id: route_id.to_string(),
+        route_id,
         service_id: vj.service_id.clone(),
         id: vj.id.clone(),
         headsign: vj.headsign.clone(),
         short_name: vj.short_name.clone(),
-        direction: get_gtfs_direction_id_from_ntfs_route(route),
+        direction,
         block_id: vj.block_id.clone(),
-        shape_id: vj.geometry_id.clone(),
+        shape_id: get_gtfs_shape_id(vj, model).map(str::to_string),
         wheelchair_accessible: wheelchair_and_bike.0,
         bikes_allowed: wheelchair_and_bike.1,
     }
 }
 
-pub fn write_trips(path: &path::Path, model: &Model) -> Result<()> {
+/// Writing the trips in 'trips.txt'. With `one_route_per_line`, the trips of
+/// a line all belong to the GTFS route of the line and the `direction_id`
+/// comes from the `direction_type` of their route.
+pub fn write_trips<W: FileWriter>(
+    file_writer: &W,
+    model: &Model,
+    one_route_per_line: bool,
+) -> Result<()> {
+    if one_route_per_line {
+        for route in model.routes.values() {
+            if is_backward_direction_type(route.direction_type.as_deref()).is_none() {
+                warn!(
+                    "route {}: unknown direction_type {:?}, direction_id 0 is used for its trips",
+                    route.id,
+                    route.direction_type.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
     info!("Writing trips.txt");
-    let path = path.join("trips.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for vj in model.vehicle_journeys.values() {
-        wtr.serialize(make_gtfs_trip_from_ntfs_vj(vj, model))
+    let stage = timing::write_file("trips.txt");
+    stage.record_rows(model.vehicle_journeys.len());
+    let (writer, path) = file_writer.create_file("trips.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for vj in sorted_by_id(&model.vehicle_journeys) {
+        wtr.serialize(make_gtfs_trip_from_ntfs_vj(vj, model, one_route_per_line))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
 
@@ -285,8 +662,8 @@ where
         })
 }
 
-pub fn write_stop_extensions(
-    path: &path::Path,
+pub fn write_stop_extensions<W: FileWriter>(
+    file_writer: &W,
     stop_points: &CollectionWithId<StopPoint>,
     stop_areas: &CollectionWithId<StopArea>,
 ) -> Result<()> {
@@ -297,9 +674,10 @@ pub fn write_stop_extensions(
         return Ok(());
     }
     info!("Writing stop_extensions.txt");
-    let path = path.join("stop_extensions.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file("stop_extensions.txt");
+    stage.record_rows(stop_extensions.len());
+    let (writer, path) = file_writer.create_file("stop_extensions.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
     for se in stop_extensions {
         wtr.serialize(se)
             .with_context(|| format!("Error reading {:?}", path))?;
@@ -309,6 +687,50 @@ pub fn write_stop_extensions(
 
     Ok(())
 }
+
+#[derive(Debug, Serialize, PartialEq)]
+struct IdMapping<'a> {
+    object_type: &'static str,
+    ntfs_id: &'a str,
+    source_code_system: &'a str,
+    source_value: &'a str,
+}
+
+fn id_mappings_from_collection_with_id<T>(
+    collection: &CollectionWithId<T>,
+) -> impl Iterator<Item = IdMapping<'_>>
+where
+    T: Id<T> + Codes + GetObjectType,
+{
+    collection.values().flat_map(|obj| {
+        obj.codes().iter().map(move |(system, value)| IdMapping {
+            object_type: T::get_object_type().as_str(),
+            ntfs_id: obj.id(),
+            source_code_system: system,
+            source_value: value,
+        })
+    })
+}
+
+pub fn write_id_mapping(path: &path::Path, model: &Model) -> Result<()> {
+    info!("Writing {:?}", path);
+    let mut wtr =
+        csv::Writer::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let mappings = id_mappings_from_collection_with_id(&model.networks)
+        .chain(id_mappings_from_collection_with_id(&model.stop_areas))
+        .chain(id_mappings_from_collection_with_id(&model.stop_points))
+        .chain(id_mappings_from_collection_with_id(&model.routes))
+        .chain(id_mappings_from_collection_with_id(&model.vehicle_journeys));
+    for mapping in mappings {
+        wtr.serialize(mapping)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+
+    Ok(())
+}
+
 #[derive(Debug)]
 struct PhysicalModeWithOrder<'a> {
     inner: &'a objects::PhysicalMode,
@@ -400,24 +822,111 @@ fn make_gtfs_route_from_ntfs_line(line: &objects::Line, pm: &PhysicalModeWithOrd
         color: line.color.clone(),
         text_color: line.text_color.clone(),
         sort_order: line.sort_order,
+        continuous_pickup: line_continuous_stopping(line, "continuous_pickup"),
+        continuous_drop_off: line_continuous_stopping(line, "continuous_drop_off"),
+    }
+}
+
+// The continuous stopping of a line, stored in its properties
+fn line_continuous_stopping(line: &objects::Line, name: &str) -> Option<u8> {
+    line.object_properties
+        .get(name)
+        .and_then(|value| value.parse().ok())
+}
+
+// Columns of `routes.txt` and `stop_times.txt`, written when the model has
+// continuous stoppings
+#[derive(Debug, Serialize, PartialEq)]
+struct ContinuousStopping {
+    continuous_pickup: Option<u8>,
+    continuous_drop_off: Option<u8>,
+}
+
+// Custom columns of `routes.txt`, written when the model has brandings
+#[derive(Debug, Serialize, PartialEq)]
+struct RouteBranding<'a> {
+    route_branding_id: Option<&'a str>,
+    route_branding_name: Option<&'a str>,
+    route_branding_url: Option<&'a str>,
+    route_branding_image: Option<&'a str>,
+}
+
+// The branding of the line, or else the one of its network
+fn make_route_branding<'a>(model: &'a Model, line: &'a objects::Line) -> RouteBranding<'a> {
+    let branding = line
+        .branding_id
+        .as_ref()
+        .or_else(|| {
+            model
+                .networks
+                .get(&line.network_id)
+                .and_then(|network| network.branding_id.as_ref())
+        })
+        .and_then(|branding_id| model.brandings.get(branding_id));
+    RouteBranding {
+        route_branding_id: branding.map(|b| b.id.as_str()),
+        route_branding_name: branding.map(|b| b.name.as_str()),
+        route_branding_url: branding.and_then(|b| b.url.as_deref()),
+        route_branding_image: branding.and_then(|b| b.image.as_deref()),
     }
 }
 
-pub fn write_routes(path: &path::Path, model: &Model, extend_route_type: bool) -> Result<()> {
+// Serialize a route followed by the optional columns
+fn serialize_route<W: std::io::Write, R: Serialize>(
+    wtr: &mut csv::Writer<W>,
+    route: R,
+    branding: Option<RouteBranding<'_>>,
+    continuous_stopping: Option<ContinuousStopping>,
+) -> csv::Result<()> {
+    match (branding, continuous_stopping) {
+        (Some(branding), Some(continuous_stopping)) => {
+            wtr.serialize((route, branding, continuous_stopping))
+        }
+        (Some(branding), None) => wtr.serialize((route, branding)),
+        (None, Some(continuous_stopping)) => wtr.serialize((route, continuous_stopping)),
+        (None, None) => wtr.serialize(route),
+    }
+}
+
+pub fn write_routes<W: FileWriter>(
+    file_writer: &W,
+    model: &Model,
+    extend_route_type: bool,
+    one_route_per_line: bool,
+) -> Result<()> {
     info!("Writing routes.txt");
-    let path = path.join("routes.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let _stage = timing::write_file("routes.txt");
+    let (writer, path) = file_writer.create_file("routes.txt")?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    let with_brandings = !model.brandings.is_empty();
+    let with_continuous_stoppings = model.lines.values().any(|line| {
+        line.object_properties.contains_key("continuous_pickup")
+            || line.object_properties.contains_key("continuous_drop_off")
+    });
     for (from, l) in &model.lines {
-        for pm in &get_line_physical_modes(from, &model.physical_modes, model) {
+        let mut physical_modes = get_line_physical_modes(from, &model.physical_modes, model);
+        if one_route_per_line {
+            // The route of the line has the type of its main physical mode
+            physical_modes.truncate(1);
+        }
+        for pm in &physical_modes {
             let route = make_gtfs_route_from_ntfs_line(l, pm);
-            if extend_route_type {
-                wtr.serialize(ExtendedRoute::from(route))
-                    .with_context(|| format!("Error reading {:?}", path))?;
+            let branding = with_brandings.then(|| make_route_branding(model, l));
+            let continuous_stopping = with_continuous_stoppings.then_some(ContinuousStopping {
+                continuous_pickup: route.continuous_pickup,
+                continuous_drop_off: route.continuous_drop_off,
+            });
+            let result = if extend_route_type {
+                serialize_route(
+                    &mut wtr,
+                    ExtendedRoute::from(route),
+                    branding,
+                    continuous_stopping,
+                )
             } else {
-                wtr.serialize(route)
-                    .with_context(|| format!("Error reading {:?}", path))?;
-            }
+                serialize_route(&mut wtr, route, branding, continuous_stopping)
+            };
+            result.with_context(|| format!("Error reading {:?}", path))?;
         }
     }
 
@@ -427,42 +936,109 @@ pub fn write_routes(path: &path::Path, model: &Model, extend_route_type: bool) -
     Ok(())
 }
 
-pub fn write_stop_times(
-    path: &path::Path,
+/// Writes `stop_times.txt`, ordered by trip_id then stop_sequence. The stop
+/// times are serialized one by one into the csv writer, whose buffer is
+/// drained into the file as it fills, so that no row is kept in memory
+/// whatever the size of the model.
+pub fn write_stop_times<W: FileWriter>(
+    file_writer: &W,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
     stop_points: &CollectionWithId<StopPoint>,
-    stop_times_headsigns: &HashMap<(String, u32), String>,
+    stop_times_headsigns: &HashMap<(IdString, u32), IdString>,
+    frequencies: &Collection<Frequency>,
 ) -> Result<()> {
     info!("Writing stop_times.txt");
-    let stop_times_path = path.join("stop_times.txt");
-    let mut st_wtr = csv::Writer::from_path(&stop_times_path)
-        .with_context(|| format!("Error reading {:?}", stop_times_path))?;
-    for (vj_idx, vj) in vehicle_journeys {
+    let stage = timing::write_file("stop_times.txt");
+    let mut stop_times_count = 0;
+    let (stop_times_writer, stop_times_path) = file_writer.create_file("stop_times.txt")?;
+    let mut st_wtr = csv::Writer::from_writer(stop_times_writer);
+    let with_continuous_stoppings = vehicle_journeys.values().any(|vj| {
+        vj.stop_times
+            .iter()
+            .any(|st| st.continuous_pickup.is_some() || st.continuous_drop_off.is_some())
+    });
+    let frequency_vj_ids: HashSet<&str> = frequencies
+        .values()
+        .map(|frequency| frequency.vehicle_journey_id.as_str())
+        .collect();
+    for vj in sorted_by_id(vehicle_journeys) {
+        let vj_id = IdString::from(&vj.id);
+        // the times of a frequency trip are relative to its first departure,
+        // the actual departures being given by frequencies.txt
+        let offset = if frequency_vj_ids.contains(vj.id.as_str()) {
+            vj.stop_times
+                .iter()
+                .map(|st| st.arrival_time.min(st.departure_time))
+                .min()
+                .unwrap_or_default()
+        } else {
+            Time::default()
+        };
         for st in &vj.stop_times {
-            st_wtr
-                .serialize(StopTime {
-                    stop_id: stop_points[st.stop_point_idx].id.clone(),
-                    trip_id: vj.id.clone(),
-                    stop_sequence: st.sequence,
-                    arrival_time: Some(st.arrival_time),
-                    departure_time: Some(st.departure_time),
-                    pickup_type: st.pickup_type,
-                    drop_off_type: st.drop_off_type,
-                    local_zone_id: st.local_zone_id,
-                    stop_headsign: stop_times_headsigns
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
-                        .cloned(),
-                    timepoint: matches!(st.precision, None | Some(StopTimePrecision::Exact)),
-                })
-                .with_context(|| format!("Error reading {:?}", st_wtr))?;
+            let stop_time = StopTime {
+                stop_id: IdString::from(&stop_points[st.stop_point_idx].id),
+                trip_id: vj_id.clone(),
+                stop_sequence: st.sequence,
+                arrival_time: Some(st.arrival_time - offset),
+                departure_time: Some(st.departure_time - offset),
+                pickup_type: st.pickup_type,
+                drop_off_type: st.drop_off_type,
+                local_zone_id: st.local_zone_id,
+                stop_headsign: stop_times_headsigns
+                    .get(&(vj_id.clone(), st.sequence))
+                    .cloned(),
+                timepoint: matches!(st.precision, None | Some(StopTimePrecision::Exact)),
+                continuous_pickup: st.continuous_pickup,
+                continuous_drop_off: st.continuous_drop_off,
+                shape_dist_traveled: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
+            };
+            let result = if with_continuous_stoppings {
+                st_wtr.serialize((
+                    stop_time,
+                    ContinuousStopping {
+                        continuous_pickup: st.continuous_pickup,
+                        continuous_drop_off: st.continuous_drop_off,
+                    },
+                ))
+            } else {
+                st_wtr.serialize(stop_time)
+            };
+            result.with_context(|| format!("Error reading {:?}", stop_times_path))?;
+            stop_times_count += 1;
         }
     }
     st_wtr
         .flush()
         .with_context(|| format!("Error reading {:?}", stop_times_path))?;
+    stage.record_rows(stop_times_count);
     Ok(())
 }
 
+/// Write the frequencies of the vehicle journeys of the model, as
+/// headway-based frequencies (`exact_times` being always 0).
+pub fn write_frequencies<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let frequencies: Vec<Frequency> = model
+        .frequencies
+        .values()
+        .filter(|frequency| {
+            model
+                .vehicle_journeys
+                .contains_id(&frequency.vehicle_journey_id)
+        })
+        .map(|frequency| Frequency {
+            exact_times: false,
+            ..frequency.clone()
+        })
+        .collect();
+    crate::utils::write_collection(
+        file_writer,
+        "frequencies.txt",
+        &Collection::new(frequencies),
+    )
+}
+
 fn ntfs_geometry_to_gtfs_shapes(g: &objects::Geometry) -> impl Iterator<Item = Shape> + '_ {
     let points = match g.geometry {
         GeoGeometry::LineString(ref linestring) => &linestring.0[..],
@@ -483,19 +1059,26 @@ fn ntfs_geometry_to_gtfs_shapes(g: &objects::Geometry) -> impl Iterator<Item = S
     })
 }
 
-pub fn write_shapes(
-    path: &path::Path,
-    geometries: &CollectionWithId<objects::Geometry>,
-) -> Result<()> {
-    let shapes: Vec<_> = geometries
+/// Writing the geometries of the trips, or of their routes, in 'shapes.txt'.
+/// The trips with the same geometry share its shape.
+pub fn write_shapes<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let geometry_ids: HashSet<&str> = model
+        .vehicle_journeys
         .values()
+        .filter_map(|vj| get_vj_geometry_id(vj, model))
+        .collect();
+    let shapes: Vec<_> = model
+        .geometries
+        .values()
+        .filter(|geometry| geometry_ids.contains(geometry.id.as_str()))
         .flat_map(ntfs_geometry_to_gtfs_shapes)
         .collect();
     if !shapes.is_empty() {
         info!("Writing shapes.txt");
-        let path = path.join("shapes.txt");
-        let mut wtr =
-            csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+        let stage = timing::write_file("shapes.txt");
+        stage.record_rows(shapes.len());
+        let (writer, path) = file_writer.create_file("shapes.txt")?;
+        let mut wtr = csv::Writer::from_writer(writer);
         wtr.flush()
             .with_context(|| format!("Error reading {:?}", path))?;
         for shape in shapes {
@@ -507,11 +1090,483 @@ pub fn write_shapes(
     Ok(())
 }
 
+const DEFAULT_FARE_MEDIA_ID: &str = "default_fare_media";
+
+fn write_csv<T: Serialize, W: FileWriter>(
+    file_writer: &W,
+    file_name: &str,
+    rows: &[T],
+) -> Result<()> {
+    info!("Writing {}", file_name);
+    let stage = timing::write_file(file_name);
+    stage.record_rows(rows.len());
+    let (writer, path) = file_writer.create_file(file_name)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for row in rows {
+        wtr.serialize(row)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(())
+}
+
+// Format the amount with the number of decimals of the currency, `None` if
+// the amount would lose precision.
+fn format_fare_amount(price: &objects::TicketPrice) -> Option<String> {
+    let decimals = iso4217::alpha3(&price.currency)
+        .map(|currency| currency.exp.max(0) as u32)
+        .unwrap_or(2);
+    let amount = price.price.round_dp(decimals);
+    if amount != price.price {
+        return None;
+    }
+    Some(format!("{:.*}", decimals as usize, amount))
+}
+
+fn group_by_id<'a, T>(
+    objects: impl Iterator<Item = &'a T>,
+    id: impl Fn(&'a T) -> &'a str,
+) -> HashMap<&'a str, Vec<&'a T>> {
+    let mut groups: HashMap<&str, Vec<&T>> = HashMap::new();
+    for object in objects {
+        groups.entry(id(object)).or_default().push(object);
+    }
+    groups
+}
+
+// The prices and the uses of each ticket, and the perimeters and the
+// restrictions of each ticket use, gathered once for all the tickets
+struct TicketIndex<'a> {
+    prices: HashMap<&'a str, Vec<&'a objects::TicketPrice>>,
+    uses: HashMap<&'a str, Vec<&'a objects::TicketUse>>,
+    perimeters: HashMap<&'a str, Vec<&'a objects::TicketUsePerimeter>>,
+    restrictions: HashMap<&'a str, Vec<&'a objects::TicketUseRestriction>>,
+}
+
+impl<'a> TicketIndex<'a> {
+    fn new(model: &'a Model) -> Self {
+        TicketIndex {
+            prices: group_by_id(model.ticket_prices.values(), |p| p.ticket_id.as_str()),
+            uses: group_by_id(model.ticket_uses.values(), |tu| tu.ticket_id.as_str()),
+            perimeters: group_by_id(model.ticket_use_perimeters.values(), |p| {
+                p.ticket_use_id.as_str()
+            }),
+            restrictions: group_by_id(model.ticket_use_restrictions.values(), |r| {
+                r.ticket_use_id.as_str()
+            }),
+        }
+    }
+
+    fn prices(&self, ticket_id: &str) -> &[&'a objects::TicketPrice] {
+        self.prices.get(ticket_id).map_or(&[], Vec::as_slice)
+    }
+
+    fn uses(&self, ticket_id: &str) -> &[&'a objects::TicketUse] {
+        self.uses.get(ticket_id).map_or(&[], Vec::as_slice)
+    }
+
+    fn perimeters(&self, ticket_use_id: &str) -> &[&'a objects::TicketUsePerimeter] {
+        self.perimeters
+            .get(ticket_use_id)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn restrictions(&self, ticket_use_id: &str) -> &[&'a objects::TicketUseRestriction] {
+        self.restrictions
+            .get(ticket_use_id)
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+// The flat amount and the currency of a ticket, or the reason why it has
+// none.
+fn get_ticket_amount(
+    ticket: &objects::Ticket,
+    index: &TicketIndex,
+) -> std::result::Result<(String, String), String> {
+    let price = match index.prices(&ticket.id) {
+        [price] => *price,
+        [] => return Err("it has no price".to_string()),
+        [first, others @ ..] => {
+            if others
+                .iter()
+                .any(|p| p.price != first.price || p.currency != first.currency)
+            {
+                return Err("its price changes over time".to_string());
+            }
+            *first
+        }
+    };
+    let amount = format_fare_amount(price).ok_or_else(|| {
+        format!(
+            "price {} has too many decimals for {}",
+            price.price, price.currency
+        )
+    })?;
+    Ok((amount, price.currency.clone()))
+}
+
+fn make_fare_product(ticket: &objects::Ticket, index: &TicketIndex) -> Option<FareProduct> {
+    let (amount, currency) = match get_ticket_amount(ticket, index) {
+        Ok(amount) => amount,
+        Err(reason) => {
+            warn!("ticket {} is not exported: {}", ticket.id, reason);
+            return None;
+        }
+    };
+    Some(FareProduct {
+        id: ticket.id.clone(),
+        name: Some(ticket.name.clone()),
+        fare_media_id: Some(DEFAULT_FARE_MEDIA_ID.to_string()),
+        amount,
+        currency,
+    })
+}
+
+// The networks of a ticket use, `None` if its perimeters can't be expressed
+// with GTFS-Fares v2 leg rules.
+fn get_fare_networks(ticket_use: &objects::TicketUse, index: &TicketIndex) -> Option<Vec<String>> {
+    let mut networks = Vec::new();
+    for perimeter in index.perimeters(&ticket_use.id) {
+        match (&perimeter.object_type, &perimeter.perimeter_action) {
+            (objects::ObjectType::Network, PerimeterAction::Included) => {
+                networks.push(perimeter.object_id.clone())
+            }
+            _ => {
+                warn!(
+                    "ticket_use {} is not exported: only included networks are supported as perimeter, found {} {}",
+                    ticket_use.id,
+                    perimeter.object_type.as_str(),
+                    perimeter.object_id,
+                );
+                return None;
+            }
+        }
+    }
+    Some(networks)
+}
+
+// The line of `fare_attributes.txt` of a ticket and its `fare_rules.txt`, or
+// the reason why the ticket can't be expressed with them. The network of the
+// ticket use becomes the agency, its lines become routes and the zone
+// restrictions become origin and destination.
+fn make_fare_attribute(
+    ticket: &objects::Ticket,
+    model: &Model,
+    index: &TicketIndex,
+) -> std::result::Result<(FareAttribute, Vec<FareRule>), String> {
+    let (price, currency) = get_ticket_amount(ticket, index)?;
+    let ticket_use = match index.uses(&ticket.id) {
+        [ticket_use] => *ticket_use,
+        _ => return Err("it must have exactly one ticket_use".to_string()),
+    };
+    let mut networks = BTreeSet::new();
+    let mut route_ids = Vec::new();
+    for perimeter in index.perimeters(&ticket_use.id) {
+        match (&perimeter.object_type, &perimeter.perimeter_action) {
+            (objects::ObjectType::Network, PerimeterAction::Included) => {
+                networks.insert(perimeter.object_id.clone());
+            }
+            (objects::ObjectType::Line, PerimeterAction::Included) => {
+                let idx = model
+                    .lines
+                    .get_idx(&perimeter.object_id)
+                    .ok_or_else(|| format!("its line {} is not found", perimeter.object_id))?;
+                networks.insert(model.lines[idx].network_id.clone());
+                route_ids.extend(
+                    get_line_physical_modes(idx, &model.physical_modes, model)
+                        .iter()
+                        .map(|pm| get_gtfs_route_id_from_ntfs_line_id(&perimeter.object_id, pm)),
+                );
+            }
+            (object_type, _) => {
+                return Err(format!(
+                    "only included networks and lines are supported as perimeter, found {} {}",
+                    object_type.as_str(),
+                    perimeter.object_id
+                ))
+            }
+        }
+    }
+    let agency_id = match networks.into_iter().collect::<Vec<_>>().as_slice() {
+        [] => None,
+        [network_id] => Some(network_id.clone()),
+        _ => return Err("it is valid on several networks".to_string()),
+    };
+    let mut zones = Vec::new();
+    for restriction in index.restrictions(&ticket_use.id) {
+        if restriction.restriction_type != RestrictionType::Zone {
+            return Err("only zone restrictions are supported".to_string());
+        }
+        zones.push((
+            Some(restriction.use_origin.clone()),
+            Some(restriction.use_destination.clone()),
+        ));
+    }
+    // Without zone restriction, a single rule per route
+    if zones.is_empty() && !route_ids.is_empty() {
+        zones.push((None, None));
+    }
+    let routes: Vec<_> = if route_ids.is_empty() {
+        vec![None]
+    } else {
+        route_ids.into_iter().map(Some).collect()
+    };
+    let mut rules = Vec::new();
+    for route_id in &routes {
+        for (origin_id, destination_id) in &zones {
+            rules.push(FareRule {
+                fare_id: ticket.id.clone(),
+                route_id: route_id.clone(),
+                origin_id: origin_id.clone(),
+                destination_id: destination_id.clone(),
+                contains_id: None,
+            });
+        }
+    }
+    let transfers = match ticket_use.max_transfers {
+        Some(max_transfers) if max_transfers > 2 => {
+            return Err(format!(
+                "{} transfers are allowed but at most 2 can be expressed",
+                max_transfers
+            ))
+        }
+        max_transfers => max_transfers,
+    };
+    if ticket_use.alighting_time_limit.is_some() {
+        warn!(
+            "ticket_use {}: alighting_time_limit is not exported in fare_attributes.txt",
+            ticket_use.id
+        );
+    }
+    let fare_attribute = FareAttribute {
+        id: ticket.id.clone(),
+        price,
+        currency,
+        payment_method: PaymentMethod::OnBoard,
+        transfers,
+        agency_id,
+        // Time limits are in minutes in the NTFS and in seconds in the GTFS
+        transfer_duration: ticket_use.boarding_time_limit.map(|limit| limit * 60),
+    };
+    Ok((fare_attribute, rules))
+}
+
+/// Exports the tickets of the model with a flat price and a single ticket use
+/// in `fare_attributes.txt` and `fare_rules.txt` (GTFS fares v1), with their
+/// transfers. The included networks of the perimeter become the agency and
+/// the included lines become rules on their routes; the zone restrictions
+/// become rules with an origin and a destination. The tickets that can't be
+/// expressed (OD restrictions, prices changing over time, ...) are listed in
+/// a warning.
+pub fn write_fares_v1<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    let mut fare_attributes = Vec::new();
+    let mut fare_rules = Vec::new();
+    let mut skipped_tickets = Vec::new();
+    let index = TicketIndex::new(model);
+    for ticket in model.tickets.values() {
+        match make_fare_attribute(ticket, model, &index) {
+            Ok((fare_attribute, rules)) => {
+                fare_attributes.push(fare_attribute);
+                fare_rules.extend(rules);
+            }
+            Err(reason) => skipped_tickets.push(format!("{} ({})", ticket.id, reason)),
+        }
+    }
+    if !skipped_tickets.is_empty() {
+        warn!(
+            "{} tickets are not exported in fare_attributes.txt: {}",
+            skipped_tickets.len(),
+            skipped_tickets.join(", ")
+        );
+    }
+    if !fare_attributes.is_empty() {
+        write_csv(file_writer, "fare_attributes.txt", &fare_attributes)?;
+    }
+    if !fare_rules.is_empty() {
+        write_csv(file_writer, "fare_rules.txt", &fare_rules)?;
+    }
+    Ok(())
+}
+
+/// Exports the tickets of the model as GTFS-Fares v2 files: `fare_media.txt`,
+/// `fare_products.txt`, `fare_leg_rules.txt` and the `areas.txt`,
+/// `stop_areas.txt`, `networks.txt` and `route_networks.txt` they reference.
+///
+/// A ticket becomes a fare product (with a flat amount) and each of its ticket
+/// uses becomes leg rules, one per perimeter network and restriction. OD
+/// restrictions use the stop areas as areas and zone restrictions use the
+/// fare zones of the stop points. Tickets that can't be expressed are
+/// reported and skipped.
+pub fn write_fares_v2<W: FileWriter>(file_writer: &W, model: &Model) -> Result<()> {
+    if model.tickets.is_empty() {
+        return Ok(());
+    }
+    let index = TicketIndex::new(model);
+    let mut zone_stops: HashMap<&str, Vec<&StopPoint>> = HashMap::new();
+    for stop_point in model.stop_points.values() {
+        if let Some(zone_id) = &stop_point.fare_zone_id {
+            zone_stops.entry(zone_id).or_default().push(stop_point);
+        }
+    }
+    // The areas of the origin-destination restrictions are named after their
+    // stop area, prefixed when a fare zone has the same id: the stop area
+    // would be read back as the zone
+    let od_area_id = |stop_area_id: &str| {
+        if zone_stops.contains_key(stop_area_id) {
+            format!("stop_area:{}", stop_area_id)
+        } else {
+            stop_area_id.to_string()
+        }
+    };
+    let mut fare_products = Vec::new();
+    let mut fare_leg_rules = Vec::new();
+    let mut od_areas = BTreeSet::new();
+    let mut zone_areas = BTreeSet::new();
+    let mut used_networks = BTreeSet::new();
+    for ticket in model.tickets.values() {
+        let fare_product = match make_fare_product(ticket, &index) {
+            Some(fare_product) => fare_product,
+            None => continue,
+        };
+        let mut ticket_leg_rules = Vec::new();
+        let mut expressible = true;
+        for ticket_use in index.uses(&ticket.id) {
+            let networks = match get_fare_networks(ticket_use, &index) {
+                Some(networks) if networks.is_empty() => vec![None],
+                Some(networks) => networks.into_iter().map(Some).collect(),
+                None => {
+                    expressible = false;
+                    break;
+                }
+            };
+            let restrictions = index.restrictions(&ticket_use.id);
+            for network_id in networks {
+                if restrictions.is_empty() {
+                    ticket_leg_rules.push(FareLegRule {
+                        leg_group_id: Some(ticket_use.id.clone()),
+                        network_id: network_id.clone(),
+                        from_area_id: None,
+                        to_area_id: None,
+                        fare_product_id: ticket.id.clone(),
+                    });
+                }
+                for restriction in restrictions {
+                    let (from_area_id, to_area_id) = match restriction.restriction_type {
+                        RestrictionType::OriginDestination => {
+                            od_areas.insert(restriction.use_origin.as_str());
+                            od_areas.insert(restriction.use_destination.as_str());
+                            (
+                                od_area_id(&restriction.use_origin),
+                                od_area_id(&restriction.use_destination),
+                            )
+                        }
+                        RestrictionType::Zone => {
+                            zone_areas.insert(restriction.use_origin.as_str());
+                            zone_areas.insert(restriction.use_destination.as_str());
+                            (
+                                restriction.use_origin.clone(),
+                                restriction.use_destination.clone(),
+                            )
+                        }
+                    };
+                    ticket_leg_rules.push(FareLegRule {
+                        leg_group_id: Some(ticket_use.id.clone()),
+                        network_id: network_id.clone(),
+                        from_area_id: Some(from_area_id),
+                        to_area_id: Some(to_area_id),
+                        fare_product_id: ticket.id.clone(),
+                    });
+                }
+            }
+        }
+        if !expressible {
+            warn!(
+                "ticket {} is not exported: its perimeter can't be expressed",
+                ticket.id
+            );
+            continue;
+        }
+        used_networks.extend(ticket_leg_rules.iter().filter_map(|r| r.network_id.clone()));
+        fare_products.push(fare_product);
+        fare_leg_rules.extend(ticket_leg_rules);
+    }
+
+    let mut areas = BTreeMap::new();
+    let mut area_stops = Vec::new();
+    for stop_area_id in od_areas {
+        let area_id = od_area_id(stop_area_id);
+        let name = model.stop_areas.get(stop_area_id).map(|sa| sa.name.clone());
+        areas.insert(area_id.clone(), name);
+        area_stops.push(AreaStop {
+            area_id,
+            stop_id: stop_area_id.to_string(),
+        });
+    }
+    for zone_id in zone_areas {
+        areas.insert(zone_id.to_string(), None);
+        area_stops.extend(
+            zone_stops
+                .get(zone_id)
+                .into_iter()
+                .flatten()
+                .map(|sp| AreaStop {
+                    area_id: zone_id.to_string(),
+                    stop_id: sp.id.clone(),
+                }),
+        );
+    }
+    let areas: Vec<_> = areas
+        .into_iter()
+        .map(|(id, name)| Area { id, name })
+        .collect();
+
+    let networks: Vec<_> = used_networks
+        .iter()
+        .map(|network_id| GtfsNetwork {
+            id: network_id.clone(),
+            name: model.networks.get(network_id).map(|n| n.name.clone()),
+        })
+        .collect();
+    let mut route_networks = Vec::new();
+    for (idx, line) in &model.lines {
+        if !used_networks.contains(&line.network_id) {
+            continue;
+        }
+        for pm in &get_line_physical_modes(idx, &model.physical_modes, model) {
+            route_networks.push(RouteNetwork {
+                network_id: line.network_id.clone(),
+                route_id: get_gtfs_route_id_from_ntfs_line_id(&line.id, pm),
+            });
+        }
+    }
+
+    let fare_media = [FareMedia {
+        id: DEFAULT_FARE_MEDIA_ID.to_string(),
+        name: None,
+        media_type: FareMediaType::NoMedia,
+    }];
+    write_csv(file_writer, "fare_media.txt", &fare_media)?;
+    write_csv(file_writer, "fare_products.txt", &fare_products)?;
+    write_csv(file_writer, "fare_leg_rules.txt", &fare_leg_rules)?;
+    if !areas.is_empty() {
+        write_csv(file_writer, "areas.txt", &areas)?;
+        write_csv(file_writer, "stop_areas.txt", &area_stops)?;
+    }
+    if !networks.is_empty() {
+        write_csv(file_writer, "networks.txt", &networks)?;
+        write_csv(file_writer, "route_networks.txt", &route_networks)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         calendars::write_calendar_dates,
+        file_handler::PathFileWriter,
         gtfs::{Route, RouteType, StopLocationType, Transfer, TransferType},
         model::Collections,
         objects::{Calendar, CommentLinksT, Coord, StopPoint, StopTime, Transfer as NtfsTransfer},
@@ -533,6 +1588,7 @@ mod tests {
             address: Some("somewhere".to_string()),
             sort_order: Some(1),
             codes: Default::default(),
+            branding_id: None,
         });
 
         let expected_agency = Agency {
@@ -560,6 +1616,7 @@ mod tests {
             address: None,
             sort_order: None,
             codes: Default::default(),
+            branding_id: None,
         });
 
         let expected_agency = Agency {
@@ -569,10 +1626,90 @@ mod tests {
             timezone: chrono_tz::Europe::Paris,
             lang: None,
             phone: None,
-            email: None,
+            email: None,
+        };
+
+        assert_eq!(expected_agency, agency);
+    }
+
+    #[test]
+    fn write_agency_with_company_contact() {
+        let network = objects::Network {
+            id: "network".to_string(),
+            name: "The Network".to_string(),
+            url: Some("http://network.example".to_string()),
+            timezone: Some(chrono_tz::Europe::Madrid),
+            phone: Some("0123456789".to_string()),
+            ..Default::default()
+        };
+        let company = objects::Company {
+            id: "company".to_string(),
+            name: "The Company".to_string(),
+            url: Some("http://company.example".to_string()),
+            mail: Some("contact@company.example".to_string()),
+            phone: Some("9876543210".to_string()),
+            ..Default::default()
+        };
+
+        let agency = make_gtfs_agency(&network, Some(&company));
+        let expected_agency = Agency {
+            id: Some("network".to_string()),
+            name: "The Network".to_string(),
+            url: "http://network.example".to_string(),
+            timezone: chrono_tz::Europe::Madrid,
+            lang: None,
+            phone: Some("9876543210".to_string()),
+            email: Some("contact@company.example".to_string()),
+        };
+        assert_eq!(expected_agency, agency);
+
+        // the company URL is only used when the network has none, and the
+        // phone of the network when the company has none
+        let network = objects::Network {
+            url: None,
+            ..network
+        };
+        let company = objects::Company {
+            phone: None,
+            ..company
         };
+        let agency = make_gtfs_agency(&network, Some(&company));
+        assert_eq!("http://company.example", agency.url);
+        assert_eq!(Some("0123456789".to_string()), agency.phone);
+        assert_eq!(Some("contact@company.example".to_string()), agency.email);
+    }
 
-        assert_eq!(expected_agency, agency);
+    #[test]
+    fn network_company_operates_the_most_trips() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .companies
+            .push(objects::Company {
+                id: "ATC".to_string(),
+                name: "Another Transport Company".to_string(),
+                phone: Some("0123456789".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        // the first `count` of the 6 trips are operated by ATC, the other
+        // ones by TGC
+        let network_company = |count: usize| {
+            let mut collections = collections.clone();
+            let vj_idxs: Vec<_> = collections.vehicle_journeys.indexes().take(count).collect();
+            for vj_idx in vj_idxs {
+                collections.vehicle_journeys.index_mut(vj_idx).company_id = "ATC".to_string();
+            }
+            let model = Model::new(collections).unwrap();
+            let companies = get_network_companies(&model);
+            companies["TGN"].id.clone()
+        };
+
+        assert_eq!("TGC", network_company(2));
+        // on a tie, the smallest identifier
+        assert_eq!("ATC", network_company(3));
+        assert_eq!("ATC", network_company(4));
     }
 
     #[test]
@@ -618,9 +1755,9 @@ mod tests {
             name: "sp_name_1".to_string(),
             code: Some("1234".to_string()),
             codes: vec![
-                ("object_system:2".to_string(), "object_code:2".to_string()),
-                ("gtfs_stop_code".to_string(), "1234".to_string()),
-                ("gtfs_stop_code".to_string(), "5678".to_string()),
+                ("object_system:2".into(), "object_code:2".to_string()),
+                ("gtfs_stop_code".into(), "1234".to_string()),
+                ("gtfs_stop_code".into(), "5678".to_string()),
             ]
             .into_iter()
             .collect(),
@@ -744,9 +1881,9 @@ mod tests {
             id: "sa_1".to_string(),
             name: "sa_name_1".to_string(),
             codes: vec![
-                ("object_system:2".to_string(), "object_code:2".to_string()),
-                ("gtfs_stop_code".to_string(), "5678".to_string()),
-                ("gtfs_stop_code".to_string(), "1234".to_string()),
+                ("object_system:2".into(), "object_code:2".to_string()),
+                ("gtfs_stop_code".into(), "5678".to_string()),
+                ("gtfs_stop_code".into(), "1234".to_string()),
             ]
             .into_iter()
             .collect(),
@@ -815,6 +1952,13 @@ mod tests {
                 ..Default::default()
             })
             .unwrap();
+        collections
+            .geometries
+            .push(objects::Geometry {
+                id: "Geometry:Line:Relation:6883353".to_string(),
+                geometry: line_string![(x: 1.1, y: 2.2), (x: 3.3, y: 4.4)].into(),
+            })
+            .unwrap();
         collections
             .commercial_modes
             .push(objects::CommercialMode {
@@ -865,6 +2009,7 @@ mod tests {
                 id: "Bus".to_string(),
                 name: "Bus".to_string(),
                 co2_emission: None,
+                ..Default::default()
             })
             .unwrap();
         collections
@@ -873,6 +2018,7 @@ mod tests {
                 id: "Coach".to_string(),
                 name: "Coach".to_string(),
                 co2_emission: None,
+                ..Default::default()
             })
             .unwrap();
         collections
@@ -918,6 +2064,10 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: None,
                     precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
                 objects::StopTime {
                     stop_point_idx: collections.stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -930,6 +2080,10 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
             ],
             journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -961,6 +2115,10 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: None,
                     precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
                 objects::StopTime {
                     stop_point_idx: collections.stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -973,6 +2131,10 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
             ],
             journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -993,18 +2155,72 @@ mod tests {
             bikes_allowed: Availability::NotAvailable,
         };
         let model = Model::new(collections).unwrap();
-        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj, &model));
+        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj, &model, false));
 
         expected.route_id = "OIF:002002002:BDEOIF829:Coach".to_string();
         expected.id = "OIF:87604986-1_11595-1:Coach".to_string();
-        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj_coach, &model));
+        assert_eq!(
+            expected,
+            make_gtfs_trip_from_ntfs_vj(&vj_coach, &model, false)
+        );
+
+        // A single route for the line, whatever the physical mode
+        expected.route_id = "OIF:002002002:BDEOIF829".to_string();
+        assert_eq!(
+            expected,
+            make_gtfs_trip_from_ntfs_vj(&vj_coach, &model, true)
+        );
+    }
+
+    #[test]
+    fn gtfs_direction_id_of_line_route() {
+        let direction = |direction_type: Option<&str>| {
+            get_gtfs_direction_id_of_line_route(&objects::Route {
+                direction_type: direction_type.map(str::to_string),
+                ..Default::default()
+            })
+        };
+        assert_eq!(DirectionType::Forward, direction(Some("forward")));
+        assert_eq!(DirectionType::Forward, direction(Some("inbound")));
+        assert_eq!(DirectionType::Forward, direction(None));
+        assert_eq!(DirectionType::Backward, direction(Some("backward")));
+        assert_eq!(DirectionType::Backward, direction(Some("anticlockwise")));
+        assert_eq!(DirectionType::Backward, direction(Some("outbound")));
+        assert_eq!(DirectionType::Forward, direction(Some("north")));
+    }
+
+    #[test]
+    fn write_trip_with_unknown_bikes_allowed() {
+        let trip = |bikes_allowed| Trip {
+            route_id: "route:1".to_string(),
+            service_id: "service:1".to_string(),
+            id: "trip:1".to_string(),
+            headsign: None,
+            short_name: None,
+            direction: DirectionType::Forward,
+            block_id: None,
+            shape_id: None,
+            wheelchair_accessible: Availability::Available,
+            bikes_allowed,
+        };
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(trip(Availability::InformationNotAvailable))
+            .unwrap();
+        wtr.serialize(trip(Availability::NotAvailable)).unwrap();
+        let csv = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            "route_id,service_id,trip_id,trip_headsign,trip_short_name,direction_id,block_id,shape_id,wheelchair_accessible,bikes_allowed\n\
+             route:1,service:1,trip:1,,,0,,,1,\n\
+             route:1,service:1,trip:1,,,0,,,1,2\n",
+            csv
+        );
     }
 
     #[test]
     fn ntfs_object_code_to_stop_extensions() {
-        let mut sa_codes: BTreeSet<(String, String)> = BTreeSet::new();
-        sa_codes.insert(("sa name 1".to_string(), "sa_code_1".to_string()));
-        sa_codes.insert(("sa name 2".to_string(), "sa_code_2".to_string()));
+        let mut sa_codes: BTreeSet<(IdString, String)> = BTreeSet::new();
+        sa_codes.insert(("sa name 1".into(), "sa_code_1".to_string()));
+        sa_codes.insert(("sa name 2".into(), "sa_code_2".to_string()));
         let stop_areas = CollectionWithId::from(StopArea {
             id: "sa:01".to_string(),
             name: "sa:01".to_string(),
@@ -1021,10 +2237,10 @@ mod tests {
             level_id: Some("level0".to_string()),
             equipment_id: None,
         });
-        let mut sp_codes: BTreeSet<(String, String)> = BTreeSet::new();
-        sp_codes.insert(("sp name 1".to_string(), "sp_code_1".to_string()));
-        sp_codes.insert(("sp name 2".to_string(), "sp_code_2".to_string()));
-        sp_codes.insert(("sp name 3".to_string(), "sp_code_3".to_string()));
+        let mut sp_codes: BTreeSet<(IdString, String)> = BTreeSet::new();
+        sp_codes.insert(("sp name 1".into(), "sp_code_1".to_string()));
+        sp_codes.insert(("sp name 2".into(), "sp_code_2".to_string()));
+        sp_codes.insert(("sp name 3".into(), "sp_code_3".to_string()));
         let stop_points = CollectionWithId::from(StopPoint {
             id: "sp:01".to_string(),
             name: "sp:01".to_string(),
@@ -1039,7 +2255,12 @@ mod tests {
             ..Default::default()
         });
         let tmp_dir = tempdir().expect("create temp dir");
-        write_stop_extensions(tmp_dir.path(), &stop_points, &stop_areas).unwrap();
+        write_stop_extensions(
+            &PathFileWriter::new(tmp_dir.path()),
+            &stop_points,
+            &stop_areas,
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("stop_extensions.txt");
         let mut output_file = File::open(output_file_path.clone())
             .unwrap_or_else(|_| panic!("file {:?} not found", output_file_path));
@@ -1062,7 +2283,12 @@ mod tests {
         let stop_areas = CollectionWithId::default();
         let stop_points = CollectionWithId::default();
         let tmp_dir = tempdir().expect("create temp dir");
-        write_stop_extensions(tmp_dir.path(), &stop_points, &stop_areas).unwrap();
+        write_stop_extensions(
+            &PathFileWriter::new(tmp_dir.path()),
+            &stop_points,
+            &stop_areas,
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("stop_extensions.txt");
         assert!(!output_file_path.exists());
         tmp_dir.close().expect("delete temp dir");
@@ -1114,18 +2340,52 @@ mod tests {
             min_transfer_time: Some(42),
             real_min_transfer_time: None,
             equipment_id: None,
+            transfer_type: objects::TransferType::Possible,
         });
 
         let expected = Transfer {
-            from_stop_id: "sp:01".to_string(),
-            to_stop_id: "sp:02".to_string(),
+            from_stop_id: Some("sp:01".to_string()),
+            to_stop_id: Some("sp:02".to_string()),
             transfer_type: TransferType::WithTransferTime,
             min_transfer_time: Some(42),
+            from_trip_id: None,
+            to_trip_id: None,
         };
 
         assert_eq!(expected, transfer);
     }
 
+    #[test]
+    fn ntfs_transfers_to_gtfs_transfer_types() {
+        let transfer = |min_transfer_time, transfer_type| {
+            let transfer = Transfer::from(&NtfsTransfer {
+                from_stop_id: "sp:01".to_string(),
+                to_stop_id: "sp:02".to_string(),
+                min_transfer_time,
+                real_min_transfer_time: min_transfer_time,
+                equipment_id: None,
+                transfer_type,
+            });
+            (transfer.transfer_type, transfer.min_transfer_time)
+        };
+        assert_eq!(
+            (TransferType::Recommended, None),
+            transfer(None, objects::TransferType::Possible)
+        );
+        assert_eq!(
+            (TransferType::Timed, None),
+            transfer(Some(0), objects::TransferType::Possible)
+        );
+        assert_eq!(
+            (TransferType::WithTransferTime, Some(120)),
+            transfer(Some(120), objects::TransferType::Possible)
+        );
+        assert_eq!(
+            (TransferType::NotPossible, None),
+            transfer(Some(86400), objects::TransferType::NotPossible)
+        );
+    }
+
     #[test]
     fn write_calendar_file_from_calendar() {
         let mut dates = BTreeSet::new();
@@ -1145,7 +2405,7 @@ mod tests {
         ])
         .unwrap();
         let tmp_dir = tempdir().expect("create temp dir");
-        write_calendar_dates(tmp_dir.path(), &calendar).unwrap();
+        write_calendar_dates(&PathFileWriter::new(tmp_dir.path()), &calendar).unwrap();
         assert!(!tmp_dir.path().join("calendar_dates.txt").exists());
 
         let output_file_path = tmp_dir.path().join("calendar.txt");
@@ -1188,6 +2448,10 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             },
             StopTime {
                 stop_point_idx: stop_points.get_idx("sp:01").unwrap(),
@@ -1200,6 +2464,10 @@ mod tests {
                 drop_off_type: 1,
                 local_zone_id: Some(3),
                 precision: Some(StopTimePrecision::Estimated),
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             },
         ];
         let vehicle_journeys = CollectionWithId::from(VehicleJourney {
@@ -1221,13 +2489,14 @@ mod tests {
             journey_pattern_id: Some(String::from("jp:01")),
         });
         let mut stop_times_headsigns = HashMap::new();
-        stop_times_headsigns.insert(("vj:01".to_string(), 1), "somewhere".to_string());
+        stop_times_headsigns.insert(("vj:01".into(), 1), "somewhere".into());
         let tmp_dir = tempdir().expect("create temp dir");
         write_stop_times(
-            tmp_dir.path(),
+            &PathFileWriter::new(tmp_dir.path()),
             &vehicle_journeys,
             &stop_points,
             &stop_times_headsigns,
+            &Collection::default(),
         )
         .unwrap();
         let output_file_path = tmp_dir.path().join("stop_times.txt");
@@ -1250,6 +2519,7 @@ mod tests {
             id: "Bus".to_string(),
             name: "Bus".to_string(),
             co2_emission: Some(6.2),
+            ..Default::default()
         });
 
         assert_eq!(RouteType::Bus, route_type);
@@ -1258,11 +2528,62 @@ mod tests {
             id: "Other".to_string(),
             name: "Other".to_string(),
             co2_emission: None,
+            ..Default::default()
         });
 
         assert_eq!(RouteType::UnknownMode, route_type);
     }
 
+    #[test]
+    fn ntfs_physical_modes_to_basic_and_extended_gtfs_route_types() {
+        let expected = [
+            ("Tramway", "0", "900"),
+            ("RailShuttle", "0", "900"),
+            ("Metro", "1", "400"),
+            ("LocalTrain", "2", "100"),
+            ("LongDistanceTrain", "2", "100"),
+            ("RapidTransit", "2", "100"),
+            ("Train", "2", "100"),
+            ("BusRapidTransit", "3", "700"),
+            ("Bus", "3", "700"),
+            ("Coach", "3", "200"),
+            ("Boat", "4", "1200"),
+            ("Ferry", "4", "1200"),
+            ("Funicular", "7", "1400"),
+            ("Shuttle", "7", "1400"),
+            ("SuspendedCableCar", "6", "1300"),
+            ("Air", "3", "1100"),
+            ("Taxi", "3", "1500"),
+        ];
+        for (physical_mode_id, basic, extended) in expected {
+            let route_type = RouteType::from(&objects::PhysicalMode {
+                id: physical_mode_id.to_string(),
+                name: physical_mode_id.to_string(),
+                co2_emission: None,
+                ..Default::default()
+            });
+            assert_eq!(basic, route_type.to_gtfs_value(), "{}", physical_mode_id);
+            assert_eq!(
+                extended,
+                route_type.to_gtfs_extended_value(),
+                "{}",
+                physical_mode_id
+            );
+            // the extended route_type is read back as the same mode
+            let read_route_type: RouteType = serde_json::from_str(extended).unwrap();
+            assert_eq!(route_type, read_route_type, "{}", physical_mode_id);
+        }
+
+        let route_type = RouteType::from(&objects::PhysicalMode {
+            id: "Other".to_string(),
+            name: "Other".to_string(),
+            co2_emission: None,
+            ..Default::default()
+        });
+        assert_eq!("3", route_type.to_gtfs_value());
+        assert_eq!("700", route_type.to_gtfs_extended_value());
+    }
+
     #[test]
     fn ntfs_minial_line_to_gtfs_route() {
         let pm = PhysicalModeWithOrder {
@@ -1270,6 +2591,7 @@ mod tests {
                 id: "Bus".to_string(),
                 name: "Bus".to_string(),
                 co2_emission: Some(6.2),
+                ..Default::default()
             },
             is_lowest: true,
         };
@@ -1291,6 +2613,7 @@ mod tests {
             geometry_id: None,
             opening_time: None,
             closing_time: None,
+            branding_id: None,
         };
 
         let expected = Route {
@@ -1304,6 +2627,8 @@ mod tests {
             color: None,
             text_color: None,
             sort_order: None,
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
 
         assert_eq!(expected, make_gtfs_route_from_ntfs_line(&line, &pm));
@@ -1316,6 +2641,7 @@ mod tests {
                 id: "Unknown".to_string(),
                 name: "unknown".to_string(),
                 co2_emission: Some(6.2),
+                ..Default::default()
             },
             is_lowest: false,
         };
@@ -1345,6 +2671,7 @@ mod tests {
             geometry_id: Some("Geometry:Line:Relation:6883353".to_string()),
             opening_time: Some(objects::Time::new(9, 0, 0)),
             closing_time: Some(objects::Time::new(18, 0, 0)),
+            branding_id: None,
         };
 
         let expected = Route {
@@ -1366,11 +2693,503 @@ mod tests {
                 blue: 45,
             }),
             sort_order: Some(1342),
+            continuous_pickup: None,
+            continuous_drop_off: None,
         };
 
         assert_eq!(expected, make_gtfs_route_from_ntfs_line(&line, &pm));
     }
 
+    #[test]
+    fn write_routes_with_brandings() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.brandings = CollectionWithId::new(vec![
+            objects::Branding {
+                id: "noctilien".to_string(),
+                name: "Noctilien".to_string(),
+                url: Some("http://www.noctilien.fr".to_string()),
+                image: Some("picto:N".to_string()),
+            },
+            objects::Branding {
+                id: "express".to_string(),
+                name: "Express".to_string(),
+                url: None,
+                image: None,
+            },
+        ])
+        .unwrap();
+        collections.networks.get_mut("TGN").unwrap().branding_id = Some("noctilien".to_string());
+        collections.lines.get_mut("B42").unwrap().branding_id = Some("express".to_string());
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_routes(&PathFileWriter::new(tmp_dir.path()), &model, false, false).unwrap();
+
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("routes.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            vec![
+                "route_id,agency_id,route_short_name,route_long_name,route_desc,route_type,\
+                 route_url,route_color,route_text_color,route_sort_order,route_branding_id,\
+                 route_branding_name,route_branding_url,route_branding_image",
+                "M1,TGN,,Metro 1,,1,,,,,noctilien,Noctilien,http://www.noctilien.fr,picto:N",
+                "B42,TGN,,Bus 42,,3,,,,,express,Express,,",
+                "RERA,TGN,,RER A,,2,,,,,noctilien,Noctilien,http://www.noctilien.fr,picto:N",
+                "RERA:Bus,TGN,,RER A,,3,,,,,noctilien,Noctilien,http://www.noctilien.fr,picto:N",
+            ],
+            content.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn write_shapes_of_trips_and_routes() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.geometries = CollectionWithId::new(vec![
+            objects::Geometry {
+                id: "geo_trip".to_string(),
+                geometry: line_string![(x: 2.37, y: 48.84), (x: 2.38, y: 48.85)].into(),
+            },
+            objects::Geometry {
+                id: "geo_route".to_string(),
+                geometry: line_string![(x: 2.39, y: 48.86), (x: 2.40, y: 48.87)].into(),
+            },
+            objects::Geometry {
+                id: "geo_point".to_string(),
+                geometry: point!(x: 2.41, y: 48.88).into(),
+            },
+            objects::Geometry {
+                id: "geo_unused".to_string(),
+                geometry: line_string![(x: 2.42, y: 48.89), (x: 2.43, y: 48.90)].into(),
+            },
+        ])
+        .unwrap();
+        for trip_id in &["B42F1", "B42B1"] {
+            collections
+                .vehicle_journeys
+                .get_mut(trip_id)
+                .unwrap()
+                .geometry_id = Some("geo_trip".to_string());
+        }
+        collections.routes.get_mut("M1B").unwrap().geometry_id = Some("geo_route".to_string());
+        collections.routes.get_mut("M1F").unwrap().geometry_id = Some("geo_point".to_string());
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_shapes(&PathFileWriter::new(tmp_dir.path()), &model).unwrap();
+
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("shapes.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            vec![
+                "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence",
+                "geo_trip,48.84,2.37,0",
+                "geo_trip,48.85,2.38,1",
+                "geo_route,48.86,2.39,0",
+                "geo_route,48.87,2.4,1",
+            ],
+            content.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn write_feed_info_from_datasets() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .feed_infos
+            .insert("feed_publisher_name".to_string(), "Hove".to_string());
+        collections
+            .feed_infos
+            .insert("feed_end_date".to_string(), "20180115".to_string());
+        collections
+            .feed_infos
+            .insert("ntfs_version".to_string(), "0.12.0".to_string());
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_feed_info(&PathFileWriter::new(tmp_dir.path()), &model).unwrap();
+
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("feed_info.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            vec![
+                "feed_publisher_name,feed_publisher_url,feed_lang,default_lang,feed_start_date,\
+                 feed_end_date,feed_version,feed_contact_email,feed_contact_url",
+                "Hove,,,,20180101,20180115,,,",
+            ],
+            content.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn write_attributions_of_contributors_and_companies() {
+        let attributions = |company_url: Option<&str>| {
+            let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+                .unwrap()
+                .into_collections();
+            {
+                let mut contributor = collections.contributors.get_mut("TGC").unwrap();
+                contributor.license = Some("ODbL".to_string());
+                contributor.website = Some("http://producer.example".to_string());
+            }
+            {
+                let mut company = collections.companies.get_mut("TGC").unwrap();
+                company.url = company_url.map(str::to_string);
+                company.mail = Some("contact@operator.example".to_string());
+            }
+            let model = Model::new(collections).unwrap();
+            let tmp_dir = tempdir().expect("create temp dir");
+            write_attributions(&PathFileWriter::new(tmp_dir.path()), &model).unwrap();
+            let mut content = String::new();
+            File::open(tmp_dir.path().join("attributions.txt"))
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content.lines().map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let header = "attribution_id,organization_name,is_producer,is_operator,attribution_url,\
+                      attribution_email,attribution_phone,attribution_license";
+        let producer = "TGC,The Great Contributor,1,0,http://producer.example,,,ODbL";
+        assert_eq!(vec![header, producer], attributions(None));
+        assert_eq!(
+            vec![
+                header,
+                producer,
+                "TGC,The Great Company,0,1,http://operator.example,contact@operator.example,,",
+            ],
+            attributions(Some("http://operator.example"))
+        );
+    }
+
+    #[test]
+    fn write_frequencies_and_relative_stop_times() {
+        // B42F1 departs from GDLB at 10:10 and arrives at MTPB at 10:20
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.frequencies = Collection::new(vec![
+            Frequency {
+                vehicle_journey_id: "B42F1".to_string(),
+                start_time: Time::new(7, 0, 0),
+                end_time: Time::new(9, 0, 0),
+                headway_secs: 600,
+                exact_times: true,
+            },
+            Frequency {
+                vehicle_journey_id: "unknown".to_string(),
+                start_time: Time::new(7, 0, 0),
+                end_time: Time::new(9, 0, 0),
+                headway_secs: 600,
+                exact_times: false,
+            },
+        ]);
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        let file_writer = PathFileWriter::new(tmp_dir.path());
+        write_frequencies(&file_writer, &model).unwrap();
+        write_stop_times(
+            &file_writer,
+            &model.vehicle_journeys,
+            &model.stop_points,
+            &model.stop_time_headsigns,
+            &model.frequencies,
+        )
+        .unwrap();
+
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("frequencies.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            vec![
+                "trip_id,start_time,end_time,headway_secs,exact_times",
+                "B42F1,07:00:00,09:00:00,600,0",
+            ],
+            content.lines().collect::<Vec<_>>()
+        );
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("stop_times.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        let frequency_stop_times: Vec<_> = content
+            .lines()
+            .filter(|line| line.starts_with("B42F1,"))
+            .collect();
+        assert_eq!(
+            vec![
+                "B42F1,00:00:00,00:00:00,GDLB,10,0,1,,,1",
+                "B42F1,00:10:00,00:10:00,MTPB,20,1,0,,,1",
+            ],
+            frequency_stop_times
+        );
+    }
+
+    #[test]
+    fn write_pathways_between_exported_stops() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.stop_locations = CollectionWithId::new(vec![
+            objects::StopLocation {
+                id: "entrance".to_string(),
+                name: "Entrance".to_string(),
+                parent_id: Some("GDL".to_string()),
+                stop_type: StopType::StopEntrance,
+                ..Default::default()
+            },
+            objects::StopLocation {
+                id: "zone".to_string(),
+                name: "Zone".to_string(),
+                stop_type: StopType::Zone,
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let pathway = |id: &str, from_stop_id: &str, to_stop_id: &str| Pathway {
+            id: id.to_string(),
+            from_stop_id: from_stop_id.to_string(),
+            to_stop_id: to_stop_id.to_string(),
+            is_bidirectional: true,
+            ..Default::default()
+        };
+        collections.pathways = CollectionWithId::new(vec![
+            pathway("pw_entrance", "entrance", "GDLM"),
+            pathway("pw_zone", "zone", "GDLM"),
+            pathway("pw_station", "NATM", "NAT"),
+        ])
+        .unwrap();
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        let file_writer = PathFileWriter::new(tmp_dir.path());
+        write_pathways(&file_writer, &model).unwrap();
+        write_stops(
+            &file_writer,
+            &model.stop_points,
+            &model.stop_areas,
+            &model.stop_locations,
+            &model.comments,
+            &model.equipments,
+            StopsOptions::default(),
+        )
+        .unwrap();
+
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("pathways.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            vec![
+                "pathway_id,from_stop_id,to_stop_id,pathway_mode,is_bidirectional,length,\
+                 traversal_time,stair_count,max_slope,min_width,signposted_as,\
+                 reversed_signposted_as",
+                "pw_entrance,entrance,GDLM,1,1,,,,,,,",
+            ],
+            content.lines().collect::<Vec<_>>()
+        );
+        let mut content = String::new();
+        File::open(tmp_dir.path().join("stops.txt"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        let stops: Vec<_> = content.lines().skip(1).collect();
+        assert!(stops.iter().any(|stop| stop.starts_with("entrance,")));
+        assert!(!stops.iter().any(|stop| stop.starts_with("zone,")));
+    }
+
+    #[test]
+    fn write_stop_code_from_object_codes() {
+        let stop_areas = CollectionWithId::new(vec![
+            objects::StopArea {
+                id: "sa_1".to_string(),
+                ..Default::default()
+            },
+            objects::StopArea {
+                id: "sa_2".to_string(),
+                codes: vec![("gtfs_stop_code".into(), "42".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let stop_points = CollectionWithId::new(vec![
+            StopPoint {
+                id: "sp_1".to_string(),
+                code: Some("ntfs_code".to_string()),
+                codes: vec![
+                    ("gtfs_stop_code".into(), "5678".to_string()),
+                    ("gtfs_stop_code".into(), "1234".to_string()),
+                    ("other".into(), "0000".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                stop_area_id: "sa_1".to_string(),
+                ..Default::default()
+            },
+            StopPoint {
+                id: "sp_2".to_string(),
+                code: Some("ntfs_code".to_string()),
+                stop_area_id: "sa_2".to_string(),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_stops(
+            &PathFileWriter::new(tmp_dir.path()),
+            &stop_points,
+            &stop_areas,
+            &CollectionWithId::default(),
+            &CollectionWithId::default(),
+            &CollectionWithId::default(),
+            StopsOptions {
+                stop_code_source: Some("gtfs_stop_code"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut reader = csv::Reader::from_path(tmp_dir.path().join("stops.txt")).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        let column = |name: &str| headers.iter().position(|h| h == name).unwrap();
+        let (stop_id, stop_code) = (column("stop_id"), column("stop_code"));
+        let stop_codes: BTreeMap<String, String> = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[stop_id].to_string(), record[stop_code].to_string())
+            })
+            .collect();
+        assert_eq!("1234", stop_codes["sp_1"]);
+        assert_eq!("ntfs_code", stop_codes["sp_2"]);
+        assert_eq!("", stop_codes["sa_1"]);
+        assert_eq!("42", stop_codes["sa_2"]);
+    }
+
+    #[test]
+    fn fare_zone_round_trips_through_zone_id() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .stop_points
+            .get_mut("GDLM")
+            .unwrap()
+            .fare_zone_id = Some("zone_1".to_string());
+        let model = Model::new(collections).unwrap();
+        let zip = crate::gtfs::write_to_zip_writer(
+            model,
+            std::io::Cursor::new(Vec::new()),
+            &crate::gtfs::WriteConfiguration::default(),
+        )
+        .unwrap()
+        .into_inner();
+
+        let model = crate::gtfs::from_zip_reader(std::io::Cursor::new(zip), "gtfs.zip").unwrap();
+        assert_eq!(
+            Some("zone_1"),
+            model
+                .stop_points
+                .get("GDLM")
+                .unwrap()
+                .fare_zone_id
+                .as_deref()
+        );
+        assert_eq!(None, model.stop_points.get("NATM").unwrap().fare_zone_id);
+    }
+
+    #[test]
+    fn od_area_apart_from_zone_of_same_id() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .stop_points
+            .get_mut("NATM")
+            .unwrap()
+            .fare_zone_id = Some("GDL".to_string());
+        collections.tickets = CollectionWithId::from(Ticket {
+            id: "ticket".to_string(),
+            name: "Ticket".to_string(),
+            comment: None,
+        });
+        collections.ticket_prices = Collection::from(TicketPrice {
+            ticket_id: "ticket".to_string(),
+            price: rust_decimal_macros::dec!(1.9),
+            currency: "EUR".to_string(),
+            ticket_validity_start: Date::from_ymd_opt(2018, 1, 1).unwrap(),
+            ticket_validity_end: Date::from_ymd_opt(2018, 12, 31).unwrap(),
+        });
+        collections.ticket_uses = CollectionWithId::from(TicketUse {
+            id: "ticket_use".to_string(),
+            ticket_id: "ticket".to_string(),
+            max_transfers: None,
+            boarding_time_limit: None,
+            alighting_time_limit: None,
+        });
+        collections.ticket_use_restrictions = Collection::new(vec![
+            TicketUseRestriction {
+                ticket_use_id: "ticket_use".to_string(),
+                restriction_type: RestrictionType::OriginDestination,
+                use_origin: "GDL".to_string(),
+                use_destination: "NAT".to_string(),
+            },
+            TicketUseRestriction {
+                ticket_use_id: "ticket_use".to_string(),
+                restriction_type: RestrictionType::Zone,
+                use_origin: "GDL".to_string(),
+                use_destination: "GDL".to_string(),
+            },
+        ]);
+        let model = Model::new(collections).unwrap();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_fares_v2(&PathFileWriter::new(tmp_dir.path()), &model).unwrap();
+
+        let read = |file: &str| {
+            let mut content = String::new();
+            File::open(tmp_dir.path().join(file))
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(
+            "leg_group_id,network_id,from_area_id,to_area_id,fare_product_id\n\
+            ticket_use,,stop_area:GDL,NAT,ticket\n\
+            ticket_use,,GDL,GDL,ticket\n",
+            read("fare_leg_rules.txt")
+        );
+        assert_eq!(
+            "area_id,area_name\n\
+            GDL,\n\
+            NAT,Nation\n\
+            stop_area:GDL,Gare de Lyon\n",
+            read("areas.txt")
+        );
+        assert_eq!(
+            "area_id,stop_id\n\
+            stop_area:GDL,GDL\n\
+            NAT,NAT\n\
+            GDL,NATM\n",
+            read("stop_areas.txt")
+        );
+    }
+
     #[test]
     fn ntfs_tranfers_at_same_stop_point() {
         let tmp_dir = tempdir().expect("create temp dir");
@@ -1382,6 +3201,7 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_type: objects::TransferType::Possible,
             },
             NtfsTransfer {
                 from_stop_id: String::from("101938"),
@@ -1389,6 +3209,7 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_type: objects::TransferType::Possible,
             },
             NtfsTransfer {
                 from_stop_id: String::from("101937"),
@@ -1396,6 +3217,7 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_type: objects::TransferType::Possible,
             },
             NtfsTransfer {
                 from_stop_id: String::from("101938"),
@@ -1403,10 +3225,18 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_type: objects::TransferType::Possible,
             },
         ]);
 
-        write_transfers(tmp_dir.path(), &transfers).unwrap();
+        write_transfers(
+            &PathFileWriter::new(tmp_dir.path()),
+            &transfers,
+            &Collection::default(),
+            &CollectionWithId::default(),
+            &CollectionWithId::default(),
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("transfers.txt");
         let mut output_file = File::open(output_file_path.clone())
             .unwrap_or_else(|_| panic!("file {:?} not found", output_file_path));
@@ -1414,10 +3244,35 @@ mod tests {
         output_file.read_to_string(&mut output_contents).unwrap();
         assert_eq!(
             "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n\
-            101937,101938,2,\n\
-            101938,101937,2,\n",
+            101937,101938,0,\n\
+            101938,101937,0,\n",
             output_contents
         );
         tmp_dir.close().expect("delete temp dir");
     }
+
+    #[test]
+    fn fare_amount_with_currency_decimals() {
+        use rust_decimal_macros::dec;
+        let price = |price, currency: &str| TicketPrice {
+            ticket_id: "ticket:1".to_string(),
+            price,
+            currency: currency.to_string(),
+            ticket_validity_start: chrono::NaiveDate::from_ymd(2020, 1, 1),
+            ticket_validity_end: chrono::NaiveDate::from_ymd(2020, 12, 31),
+        };
+        assert_eq!(
+            Some("1.90".to_string()),
+            format_fare_amount(&price(dec!(1.9), "EUR"))
+        );
+        assert_eq!(
+            Some("250".to_string()),
+            format_fare_amount(&price(dec!(250.00), "JPY"))
+        );
+        assert_eq!(
+            Some("1.250".to_string()),
+            format_fare_amount(&price(dec!(1.25), "TND"))
+        );
+        assert_eq!(None, format_fare_amount(&price(dec!(1.905), "EUR")));
+    }
 }
@@ -0,0 +1,209 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Identifiers sharing their allocation.
+//!
+//! The same identifiers are repeated a lot in some structures, for instance
+//! the identifier of a vehicle journey in the keys of the stop time maps of
+//! [Collections](crate::model::Collections). An [IdString] is an immutable
+//! string that is cheap to clone, and an [Interner] gives the same
+//! [IdString] for equal strings so that they are stored only once.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Borrow, collections::HashSet, fmt, ops::Deref, sync::Arc};
+
+/// An immutable string, cloned without allocation
+///
+/// It can be used as a `&str` and compares, hashes and serializes as a
+/// string.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IdString(Arc<str>);
+
+impl IdString {
+    /// The string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for IdString {
+    fn default() -> Self {
+        IdString::from("")
+    }
+}
+
+impl Deref for IdString {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for IdString {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for IdString {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for IdString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Debug for IdString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl From<&str> for IdString {
+    fn from(s: &str) -> Self {
+        IdString(Arc::from(s))
+    }
+}
+
+impl From<&String> for IdString {
+    fn from(s: &String) -> Self {
+        IdString(Arc::from(s.as_str()))
+    }
+}
+
+impl From<String> for IdString {
+    fn from(s: String) -> Self {
+        IdString(Arc::from(s))
+    }
+}
+
+impl From<IdString> for String {
+    fn from(id: IdString) -> Self {
+        id.0.to_string()
+    }
+}
+
+impl PartialEq<str> for IdString {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for IdString {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for IdString {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<IdString> for str {
+    fn eq(&self, other: &IdString) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<IdString> for &str {
+    fn eq(&self, other: &IdString) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<IdString> for String {
+    fn eq(&self, other: &IdString) -> bool {
+        **self == *other.0
+    }
+}
+
+impl Serialize for IdString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for IdString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(IdString::from)
+    }
+}
+
+/// Gives the same [IdString] for equal strings
+///
+/// An interner is meant to be used while building a structure (for instance
+/// when reading a dataset) and dropped afterwards: the strings are freed
+/// when their last [IdString] is dropped.
+#[derive(Debug, Default)]
+pub struct Interner(HashSet<IdString>);
+
+impl Interner {
+    /// The [IdString] of `s`, allocated only the first time
+    pub fn intern(&mut self, s: &str) -> IdString {
+        if let Some(id) = self.0.get(s) {
+            return id.clone();
+        }
+        let id = IdString::from(s);
+        self.0.insert(id.clone());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+
+    #[test]
+    fn interned_strings_share_their_allocation() {
+        let mut interner = Interner::default();
+        let id = interner.intern("vj:1");
+        let other = interner.intern(&String::from("vj:1"));
+        assert!(Arc::ptr_eq(&id.0, &other.0));
+        assert!(!Arc::ptr_eq(&id.0, &interner.intern("vj:2").0));
+        assert!(!Arc::ptr_eq(&id.0, &IdString::from("vj:1").0));
+    }
+
+    #[test]
+    fn used_as_str() {
+        let mut map = HashMap::new();
+        map.insert(IdString::from("vj:1"), 1);
+        assert_eq!(Some(&1), map.get("vj:1"));
+        assert_eq!(IdString::from("vj:1"), "vj:1");
+        assert_eq!("vj:1", IdString::from("vj:1").to_string());
+        let (a, b) = (IdString::from("a"), IdString::from("b"));
+        assert!(a < b);
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        let ids = vec![IdString::from("vj:1"), IdString::from("")];
+        let json = serde_json::to_string(&ids).unwrap();
+        assert_eq!(r#"["vj:1",""]"#, json);
+        assert_eq!(ids, serde_json::from_str::<Vec<IdString>>(&json).unwrap());
+    }
+}
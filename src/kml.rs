@@ -0,0 +1,335 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the networks to [KML](https://developers.google.com/kml), for a
+//! visual inspection in Google Earth or any GIS.
+
+use crate::{
+    model::Model,
+    objects::{Coord, Line, Network, Rgb, Route, StopArea, StopPoint, VehicleJourney},
+    Result,
+};
+use anyhow::{anyhow, Context};
+use geo::Geometry as GeoGeometry;
+use minidom::Element;
+use minidom_writer::ElementWriter;
+use relational_types::IdxSet;
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
+use tracing::info;
+use typed_index_collection::Idx;
+
+const KML_NAMESPACE: &str = "http://www.opengis.net/kml/2.2";
+const STOP_POINT_STYLE: &str = "stop_point";
+// Color of the lines without color
+const DEFAULT_COLOR: Rgb = Rgb {
+    red: 0x33,
+    green: 0x66,
+    blue: 0xCC,
+};
+
+fn text_element(name: &str, text: impl Into<String>) -> Element {
+    Element::builder(name).append(text.into()).build()
+}
+
+fn coordinates(coords: impl IntoIterator<Item = Coord>) -> Element {
+    let coords: Vec<String> = coords
+        .into_iter()
+        .map(|coord| format!("{},{}", coord.lon, coord.lat))
+        .collect();
+    text_element("coordinates", coords.join(" "))
+}
+
+fn line_string(coords: impl IntoIterator<Item = Coord>) -> Element {
+    Element::builder("LineString")
+        .append(text_element("tessellate", "1"))
+        .append(coordinates(coords))
+        .build()
+}
+
+// KML colors are written as `aabbggrr`
+fn kml_color(color: &Rgb) -> String {
+    format!("ff{:02x}{:02x}{:02x}", color.blue, color.green, color.red)
+}
+
+fn line_style_id(line: &Line) -> String {
+    format!("line:{}", line.id)
+}
+
+fn line_style(line: &Line) -> Element {
+    let color = line.color.as_ref().unwrap_or(&DEFAULT_COLOR);
+    Element::builder("Style")
+        .attr("id", line_style_id(line))
+        .append(
+            Element::builder("LineStyle")
+                .append(text_element("color", kml_color(color)))
+                .append(text_element("width", "4"))
+                .build(),
+        )
+        .build()
+}
+
+fn stop_point_style() -> Element {
+    let icon = Element::builder("Icon")
+        .append(text_element(
+            "href",
+            "http://maps.google.com/mapfiles/kml/shapes/placemark_circle.png",
+        ))
+        .build();
+    Element::builder("Style")
+        .attr("id", STOP_POINT_STYLE)
+        .append(
+            Element::builder("IconStyle")
+                .append(text_element("scale", "0.8"))
+                .append(icon)
+                .build(),
+        )
+        .append(
+            Element::builder("LabelStyle")
+                .append(text_element("scale", "0.7"))
+                .build(),
+        )
+        .build()
+}
+
+fn stop_point_placemark(stop_point: &StopPoint) -> Element {
+    Element::builder("Placemark")
+        .attr("id", stop_point.id.as_str())
+        .append(text_element("name", stop_point.name.as_str()))
+        .append(text_element("description", stop_point.id.as_str()))
+        .append(text_element("styleUrl", format!("#{}", STOP_POINT_STYLE)))
+        .append(
+            Element::builder("Point")
+                .append(coordinates(std::iter::once(stop_point.coord)))
+                .build(),
+        )
+        .build()
+}
+
+fn stop_area_folder(stop_area: &StopArea, stop_points: &[&StopPoint]) -> Element {
+    Element::builder("Folder")
+        .append(text_element("name", stop_area.name.as_str()))
+        .append(text_element("description", stop_area.id.as_str()))
+        .append_all(stop_points.iter().map(|sp| stop_point_placemark(sp)))
+        .build()
+}
+
+// The geometry of the route, or the stop points of its longest vehicle
+// journey chained together
+fn route_geometry(model: &Model, route_idx: Idx<Route>) -> Option<Element> {
+    let route = &model.routes[route_idx];
+    let geometry = route
+        .geometry_id
+        .as_ref()
+        .and_then(|geometry_id| model.geometries.get(geometry_id));
+    match geometry.map(|geometry| &geometry.geometry) {
+        Some(GeoGeometry::LineString(line_string)) => {
+            return Some(self::line_string(
+                line_string.0.iter().map(|c| Coord { lon: c.x, lat: c.y }),
+            ))
+        }
+        Some(GeoGeometry::MultiLineString(multi_line_string)) => {
+            return Some(
+                Element::builder("MultiGeometry")
+                    .append_all(multi_line_string.0.iter().map(|line_string| {
+                        self::line_string(
+                            line_string.0.iter().map(|c| Coord { lon: c.x, lat: c.y }),
+                        )
+                    }))
+                    .build(),
+            )
+        }
+        _ => {}
+    }
+    let vj_idxs: IdxSet<VehicleJourney> = model.get_corresponding_from_idx(route_idx);
+    let vj = vj_idxs
+        .into_iter()
+        .map(|vj_idx| &model.vehicle_journeys[vj_idx])
+        .max_by_key(|vj| vj.stop_times.len())?;
+    Some(line_string(vj.stop_times.iter().map(|stop_time| {
+        model.stop_points[stop_time.stop_point_idx].coord
+    })))
+}
+
+fn line_folder(model: &Model, line_idx: Idx<Line>) -> Element {
+    let line = &model.lines[line_idx];
+    let name = match &line.code {
+        Some(code) if !code.is_empty() => format!("{} - {}", code, line.name),
+        _ => line.name.clone(),
+    };
+    let route_idxs: IdxSet<Route> = model.get_corresponding_from_idx(line_idx);
+    let placemarks = route_idxs.into_iter().filter_map(|route_idx| {
+        let route = &model.routes[route_idx];
+        let geometry = route_geometry(model, route_idx)?;
+        Some(
+            Element::builder("Placemark")
+                .attr("id", route.id.as_str())
+                .append(text_element("name", route.name.as_str()))
+                .append(text_element("description", route.id.as_str()))
+                .append(text_element(
+                    "styleUrl",
+                    format!("#{}", line_style_id(line)),
+                ))
+                .append(geometry)
+                .build(),
+        )
+    });
+    Element::builder("Folder")
+        .append(text_element("name", name))
+        .append(text_element("description", line.id.as_str()))
+        .append_all(placemarks)
+        .build()
+}
+
+fn network_document(model: &Model, network_idx: Idx<Network>) -> Element {
+    let network = &model.networks[network_idx];
+    let line_idxs: IdxSet<Line> = model.get_corresponding_from_idx(network_idx);
+    let stop_area_idxs: IdxSet<StopArea> = model.get_corresponding_from_idx(network_idx);
+    let stop_point_idxs: IdxSet<StopPoint> = model.get_corresponding_from_idx(network_idx);
+    let mut stop_points_by_area: BTreeMap<&str, Vec<&StopPoint>> = BTreeMap::new();
+    for stop_point_idx in stop_point_idxs {
+        let stop_point = &model.stop_points[stop_point_idx];
+        stop_points_by_area
+            .entry(stop_point.stop_area_id.as_str())
+            .or_default()
+            .push(stop_point);
+    }
+
+    let stop_area_folders = stop_area_idxs.into_iter().map(|stop_area_idx| {
+        let stop_area = &model.stop_areas[stop_area_idx];
+        let stop_points = stop_points_by_area
+            .get(stop_area.id.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        stop_area_folder(stop_area, stop_points)
+    });
+    let stops_folder = Element::builder("Folder")
+        .append(text_element("name", "Stops"))
+        .append_all(stop_area_folders)
+        .build();
+    let lines_folder = Element::builder("Folder")
+        .append(text_element("name", "Lines"))
+        .append_all(
+            line_idxs
+                .iter()
+                .map(|line_idx| line_folder(model, *line_idx)),
+        )
+        .build();
+
+    Element::builder("Document")
+        .append(text_element("name", network.name.as_str()))
+        .append(text_element("description", network.id.as_str()))
+        .append(stop_point_style())
+        .append_all(
+            line_idxs
+                .iter()
+                .map(|line_idx| line_style(&model.lines[*line_idx])),
+        )
+        .append(stops_folder)
+        .append(lines_folder)
+        .build()
+}
+
+/// Writes the network `network_id` of the model in KML: one placemark for
+/// each stop point served by the network, grouped in a folder by stop area,
+/// and one line string for each route, from its geometry or else from the
+/// stop points of its longest vehicle journey, with the color of its line.
+pub fn write_network<W: Write>(model: &Model, network_id: &str, writer: W) -> Result<()> {
+    let network_idx = model
+        .networks
+        .get_idx(network_id)
+        .ok_or_else(|| anyhow!("network {:?} not found", network_id))?;
+    let kml = Element::builder("kml")
+        .attr("xmlns", KML_NAMESPACE)
+        .append(network_document(model, network_idx))
+        .build();
+    ElementWriter::pretty(writer).write(&kml)?;
+    Ok(())
+}
+
+/// Writes each network of the model in a `<network_id>.kml` file of the
+/// directory `path` with [write_network], the characters of the identifier
+/// that are not alphanumeric, `-`, `_` or `.` being replaced by `_`.
+pub fn write_to_dir<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path).with_context(|| format!("Error creating {:?}", path))?;
+    for network in model.networks.values() {
+        let file_name: String = network
+            .id
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let file_path = path.join(format!("{}.kml", file_name));
+        info!("Writing {:?}", file_path);
+        let file =
+            File::create(&file_path).with_context(|| format!("Error creating {:?}", file_path))?;
+        write_network(model, &network.id, std::io::BufWriter::new(file))
+            .with_context(|| format!("Error writing {:?}", file_path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn kml(model: &Model) -> String {
+        let mut buffer = Vec::new();
+        write_network(model, "TGN", &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn network_with_escaped_names() {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections.networks.get_mut("TGN").unwrap().name = "Bus & <Co>".to_string();
+        collections.lines.get_mut("M1").unwrap().color = Some(Rgb {
+            red: 0xFF,
+            green: 0x80,
+            blue: 0x00,
+        });
+        let model = Model::new(collections).unwrap();
+        let kml = kml(&model);
+
+        assert!(kml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert!(kml.contains("<kml xmlns=\"http://www.opengis.net/kml/2.2\">"));
+        assert!(kml.contains("<name>Bus &amp; &lt;Co&gt;</name>"));
+        assert!(kml.contains("<color>ff0080ff</color>"));
+        assert_eq!(model.stop_points.len(), kml.matches("<Point>").count());
+        assert_eq!(6, kml.matches("<LineString>").count());
+        // The first route of the metro 1 chains its 4 stop points
+        let first_coordinates = kml
+            .split("<LineString>")
+            .nth(1)
+            .and_then(|s| s.split("<coordinates>").nth(1))
+            .and_then(|s| s.split("</coordinates>").next())
+            .unwrap();
+        assert_eq!(4, first_coordinates.split(' ').count());
+    }
+
+    #[test]
+    fn unknown_network() {
+        let model = Model::new(Default::default()).unwrap();
+        let error = write_network(&model, "network", Vec::new()).unwrap_err();
+        assert_eq!("network \"network\" not found", error.to_string());
+    }
+}
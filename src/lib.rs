@@ -55,28 +55,40 @@ mod utils;
 mod add_prefix;
 pub mod serde_utils;
 pub use add_prefix::{AddPrefix, PrefixConfiguration};
+pub mod anonymize;
 pub mod calendars;
 #[macro_use]
 pub mod objects;
 pub mod configuration;
-mod enhancers;
+pub mod enhancers;
+pub mod fares;
 #[cfg(not(feature = "parser"))]
 pub(crate) mod file_handler;
 #[cfg(feature = "parser")]
 pub mod file_handler;
 pub mod gtfs;
+pub mod id_string;
+pub mod kml;
+pub mod merge;
 pub mod model;
 #[cfg(feature = "proj")]
 pub mod netex_france;
 pub mod netex_utils;
 pub mod ntfs;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 #[cfg(not(feature = "parser"))]
 pub(crate) mod parser;
 #[cfg(feature = "parser")]
 pub mod parser;
+pub mod report;
 #[doc(hidden)]
 pub mod test_utils;
+#[doc(hidden)]
+pub mod testing;
+pub mod timing;
 pub mod transfers;
+pub mod validator;
 pub mod validity_period;
 mod version_utils;
 pub mod vptranslator;
@@ -86,7 +98,7 @@ pub mod vptranslator;
 pub(crate) const STOP_TIMES_INIT_CAPACITY: usize = 50;
 
 /// Current version of the NTFS format
-pub const NTFS_VERSION: &str = "0.12.1";
+pub const NTFS_VERSION: &str = "0.12.2";
 
 /// The max distance in meters to compute the transfer
 pub const TRANSFER_MAX_DISTANCE: &str = "300";
@@ -108,6 +120,7 @@ pub type Error = anyhow::Error;
 /// The corresponding result type used by the crate.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+pub use crate::merge::merge_datasets;
 pub use crate::model::Model;
 
 pub use crate::version_utils::{binary_full_version, GIT_VERSION};
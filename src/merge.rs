@@ -0,0 +1,599 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Merge of several datasets into one.
+//!
+//! The identifiers of the datasets usually need to be prefixed before the
+//! merge (see [AddPrefix]) so that they don't collide. The objects that still
+//! have the same identifier are handled with a [ConflictStrategy], except
+//! the physical and commercial modes: they are a shared referential whose
+//! identifiers are never prefixed, and the first mode is always kept.
+//!
+//! The feed infos are combined key by key, the value of the first dataset
+//! defining a key being kept. The `feed_start_date`, `feed_end_date` and
+//! `feed_creation_*` values are anyway computed again when writing an NTFS.
+
+use crate::{
+    model::Collections, ntfs, objects::StopPoint, transfers::generates_transfers, AddPrefix, Model,
+    PrefixConfiguration, Result,
+};
+use anyhow::{anyhow, bail, Context};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id, Idx};
+
+/// How to handle two objects with the same identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Fail the merge
+    #[default]
+    Fail,
+    /// Keep the object already merged, ignoring the new one
+    KeepFirst,
+    /// Replace the object already merged by the new one
+    KeepLast,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fail" => Ok(ConflictStrategy::Fail),
+            "keep_first" => Ok(ConflictStrategy::KeepFirst),
+            "keep_last" => Ok(ConflictStrategy::KeepLast),
+            _ => bail!(
+                "unknown conflict strategy {:?}, expected 'fail', 'keep_first' or 'keep_last'",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConflictStrategy::Fail => write!(f, "fail"),
+            ConflictStrategy::KeepFirst => write!(f, "keep_first"),
+            ConflictStrategy::KeepLast => write!(f, "keep_last"),
+        }
+    }
+}
+
+/// Two objects with the same identifier, resolved with the
+/// [ConflictStrategy] of the merge
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Collision {
+    /// Type of the objects (`trip`, `stop_point`, `feed_info`...)
+    pub object_type: &'static str,
+    /// Identifier of the objects, or key of the feed info
+    pub object_id: String,
+}
+
+fn merge_collection_with_id<T: Id<T>>(
+    collection: &mut CollectionWithId<T>,
+    mut other: CollectionWithId<T>,
+    object_type: &'static str,
+    strategy: ConflictStrategy,
+    collisions: &mut Vec<Collision>,
+) -> Result<()> {
+    for object in other.take() {
+        let idx = match collection.get_idx(object.id()) {
+            Some(idx) => idx,
+            None => {
+                collection
+                    .push(object)
+                    .expect("the identifier is not in the collection");
+                continue;
+            }
+        };
+        let object_id = object.id().to_string();
+        match strategy {
+            ConflictStrategy::Fail => {
+                bail!("{} {:?} is in several datasets", object_type, object_id)
+            }
+            ConflictStrategy::KeepFirst => {}
+            ConflictStrategy::KeepLast => *collection.index_mut(idx) = object,
+        }
+        collisions.push(Collision {
+            object_type,
+            object_id,
+        });
+    }
+    Ok(())
+}
+
+/// Merges `other` into `collections`, the objects with an identifier
+/// already in `collections` being handled with `strategy`.
+///
+/// The objects attached to a vehicle journey (frequencies and stop time
+/// headsigns, identifiers and comments) follow it: they are ignored with the
+/// vehicle journey, or replace the ones of the vehicle journey it replaces.
+/// The validity periods of the datasets are then recomputed (see
+/// [Collections::update_validity_period_from_calendars]).
+/// Returns the collisions that were resolved.
+pub fn merge_collections(
+    collections: &mut Collections,
+    mut other: Collections,
+    strategy: ConflictStrategy,
+) -> Result<Vec<Collision>> {
+    let mut collisions = Vec::new();
+    let other_stop_point_ids: HashMap<Idx<StopPoint>, String> = other
+        .stop_points
+        .iter()
+        .map(|(idx, stop_point)| (idx, stop_point.id.clone()))
+        .collect();
+
+    collections
+        .physical_modes
+        .merge_with(other.physical_modes.take(), |_, _| {});
+    collections
+        .commercial_modes
+        .merge_with(other.commercial_modes.take(), |_, _| {});
+
+    macro_rules! merge_with_id {
+        ($($field:ident: $object_type:expr),* $(,)?) => {
+            $(merge_collection_with_id(
+                &mut collections.$field,
+                std::mem::take(&mut other.$field),
+                $object_type,
+                strategy,
+                &mut collisions,
+            )?;)*
+        };
+    }
+    merge_with_id!(
+        contributors: "contributor",
+        datasets: "dataset",
+        networks: "network",
+        lines: "line",
+        line_groups: "line_group",
+        routes: "route",
+        stop_areas: "stop_area",
+        stop_points: "stop_point",
+        stop_locations: "stop_location",
+        calendars: "calendar",
+        companies: "company",
+        comments: "comment",
+        equipments: "equipment",
+        trip_properties: "trip_property",
+        geometries: "geometry",
+        tickets: "ticket",
+        ticket_uses: "ticket_use",
+        pathways: "pathway",
+        levels: "level",
+        booking_rules: "booking_rule",
+        grid_calendars: "grid_calendar",
+        addresses: "address",
+        brandings: "branding",
+    );
+
+    // The stop times refer to the stop points by index
+    let mut vehicle_journeys = other.vehicle_journeys.take();
+    for vj in &mut vehicle_journeys {
+        for stop_time in &mut vj.stop_times {
+            let stop_point_id = &other_stop_point_ids[&stop_time.stop_point_idx];
+            stop_time.stop_point_idx = collections
+                .stop_points
+                .get_idx(stop_point_id)
+                .ok_or_else(|| anyhow!("stop point {:?} not found", stop_point_id))?;
+        }
+    }
+    let vj_collisions_start = collisions.len();
+    merge_collection_with_id(
+        &mut collections.vehicle_journeys,
+        CollectionWithId::new(vehicle_journeys)?,
+        "trip",
+        strategy,
+        &mut collisions,
+    )?;
+    let colliding_vjs: HashSet<String> = collisions[vj_collisions_start..]
+        .iter()
+        .map(|collision| collision.object_id.clone())
+        .collect();
+    if !colliding_vjs.is_empty() {
+        // The objects of the ignored or replaced vehicle journeys are removed
+        let ignored = match strategy {
+            ConflictStrategy::KeepLast => &mut *collections,
+            _ => &mut other,
+        };
+        ignored
+            .frequencies
+            .retain(|frequency| !colliding_vjs.contains(&frequency.vehicle_journey_id));
+        ignored.trip_transfers.retain(|trip_transfer| {
+            !colliding_vjs.contains(&trip_transfer.from_vehicle_journey_id)
+                && !colliding_vjs.contains(&trip_transfer.to_vehicle_journey_id)
+        });
+        ignored
+            .stop_time_headsigns
+            .retain(|(vj_id, _), _| !colliding_vjs.contains(vj_id.as_str()));
+        ignored
+            .stop_time_ids
+            .retain(|(vj_id, _), _| !colliding_vjs.contains(vj_id.as_str()));
+        ignored
+            .stop_time_comments
+            .retain(|(vj_id, _), _| !colliding_vjs.contains(vj_id.as_str()));
+    }
+    collections
+        .stop_time_headsigns
+        .extend(other.stop_time_headsigns.drain());
+    collections
+        .stop_time_ids
+        .extend(other.stop_time_ids.drain());
+    collections
+        .stop_time_comments
+        .extend(other.stop_time_comments.drain());
+
+    macro_rules! merge_without_id {
+        ($($field:ident),* $(,)?) => {
+            $(collections.$field.merge(std::mem::take(&mut other.$field));)*
+        };
+    }
+    merge_without_id!(
+        frequencies,
+        transfers,
+        trip_transfers,
+        admin_stations,
+        prices_v1,
+        od_fares_v1,
+        fares_v1,
+        ticket_prices,
+        ticket_use_perimeters,
+        ticket_use_restrictions,
+        grid_exception_dates,
+        grid_periods,
+        grid_rel_calendar_line,
+        occupancies,
+        line_group_links,
+        translations,
+    );
+
+    for (key, value) in std::mem::take(&mut other.feed_infos) {
+        match collections.feed_infos.get(&key) {
+            None => {
+                collections.feed_infos.insert(key, value);
+            }
+            Some(first_value) if *first_value != value => collisions.push(Collision {
+                object_type: "feed_info",
+                object_id: key,
+            }),
+            Some(_) => {}
+        }
+    }
+    collections.skipped_geometries |= other.skipped_geometries;
+    // The ignored or replaced vehicle journeys may leave a dataset with
+    // fewer dates
+    collections.update_validity_period_from_calendars();
+    Ok(collisions)
+}
+
+/// Parameters of the transfers generated between the datasets
+#[derive(Debug, Clone)]
+pub struct TransfersConfiguration {
+    /// The max distance in meters to compute the transfer
+    pub max_distance: f64,
+    /// The walking speed in meters per second
+    pub walking_speed: f64,
+    /// Waiting time at stop in seconds
+    pub waiting_time: u32,
+}
+
+/// Options of [merge_datasets]
+#[derive(Debug, Default)]
+pub struct MergeOptions {
+    /// Prefix configuration of each input, in the order of the inputs, the
+    /// inputs without prefix configuration being merged as they are
+    pub prefixes: Vec<PrefixConfiguration>,
+    /// How to handle the objects with the same identifier in several inputs
+    pub conflict_strategy: ConflictStrategy,
+    /// If set, the transfers between the stop points of different inputs are
+    /// generated
+    pub transfers: Option<TransfersConfiguration>,
+}
+
+/// What was merged from an input
+#[derive(Debug, Serialize)]
+pub struct InputReport {
+    /// Path of the input
+    pub path: PathBuf,
+    /// Number of objects of the input by type, after the prefixes
+    pub object_counts: BTreeMap<&'static str, usize>,
+    /// Collisions with the objects of the previous inputs
+    pub collisions: Vec<Collision>,
+}
+
+/// Report of [merge_datasets]
+#[derive(Debug, Serialize)]
+pub struct MergeReport {
+    /// Strategy used to resolve the collisions
+    pub conflict_strategy: ConflictStrategy,
+    /// One report for each input, in the order of the merge
+    pub inputs: Vec<InputReport>,
+    /// Number of objects of the merged dataset by type
+    pub object_counts: BTreeMap<&'static str, usize>,
+}
+
+pub(crate) fn object_counts(collections: &Collections) -> BTreeMap<&'static str, usize> {
+    vec![
+        ("contributor", collections.contributors.len()),
+        ("dataset", collections.datasets.len()),
+        ("network", collections.networks.len()),
+        ("line", collections.lines.len()),
+        ("route", collections.routes.len()),
+        ("trip", collections.vehicle_journeys.len()),
+        (
+            "stop_time",
+            collections
+                .vehicle_journeys
+                .values()
+                .map(|vj| vj.stop_times.len())
+                .sum(),
+        ),
+        ("stop_area", collections.stop_areas.len()),
+        ("stop_point", collections.stop_points.len()),
+        ("calendar", collections.calendars.len()),
+        ("company", collections.companies.len()),
+        ("transfer", collections.transfers.len()),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Reads the NTFS `inputs` (directories or ZIP archives), prefixes them and
+/// merges them in this order with [merge_collections].
+///
+/// Returns the merged model and a report of the merge.
+pub fn merge_datasets<P: AsRef<Path>>(
+    inputs: Vec<P>,
+    options: &MergeOptions,
+) -> Result<(Model, MergeReport)> {
+    let mut merged = Collections::default();
+    let mut input_reports = Vec::with_capacity(inputs.len());
+    // Index of the input of each stop point
+    let mut stop_point_inputs: HashMap<String, usize> = HashMap::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let path = input.as_ref();
+        info!("Merging {:?}", path);
+        let mut collections = ntfs::read_collections(path)?;
+        if let Some(prefix_conf) = options.prefixes.get(index) {
+            collections.prefix(prefix_conf);
+        }
+        let object_counts = object_counts(&collections);
+        for stop_point in collections.stop_points.values() {
+            let stop_point_input = stop_point_inputs.entry(stop_point.id.clone());
+            match options.conflict_strategy {
+                ConflictStrategy::KeepLast => *stop_point_input.or_default() = index,
+                _ => {
+                    stop_point_input.or_insert(index);
+                }
+            }
+        }
+        let collisions = merge_collections(&mut merged, collections, options.conflict_strategy)
+            .with_context(|| format!("Error merging {:?}", path))?;
+        info!("{} collisions resolved with {:?}", collisions.len(), path);
+        input_reports.push(InputReport {
+            path: path.to_path_buf(),
+            object_counts,
+            collisions,
+        });
+    }
+
+    let mut model = Model::new(merged)?;
+    if let Some(transfers) = &options.transfers {
+        let input_of = |model: &Model, idx: Idx<StopPoint>| {
+            stop_point_inputs.get(&model.stop_points[idx].id).copied()
+        };
+        model = generates_transfers(
+            model,
+            transfers.max_distance,
+            transfers.walking_speed,
+            transfers.waiting_time,
+            Some(Box::new(|model, from_idx, to_idx| {
+                input_of(model, from_idx) != input_of(model, to_idx)
+            })),
+        )?;
+    }
+    let report = MergeReport {
+        conflict_strategy: options.conflict_strategy,
+        inputs: input_reports,
+        object_counts: object_counts(&model),
+    };
+    Ok((model, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Calendar, Date};
+    use pretty_assertions::assert_eq;
+
+    fn collections(prefix: Option<&str>) -> Collections {
+        let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        if let Some(prefix) = prefix {
+            let mut prefix_conf = PrefixConfiguration::default();
+            prefix_conf.set_data_prefix(prefix);
+            collections.prefix(&prefix_conf);
+        }
+        collections
+    }
+
+    fn stop_point_ids<'a>(collections: &'a Collections, vj_id: &str) -> Vec<&'a str> {
+        collections
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                collections.stop_points[stop_time.stop_point_idx]
+                    .id
+                    .as_str()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_prefixed_datasets() {
+        let mut merged = collections(Some("A"));
+        let collisions =
+            merge_collections(&mut merged, collections(Some("B")), ConflictStrategy::Fail).unwrap();
+        assert_eq!(Vec::<Collision>::new(), collisions);
+        let input = collections(None);
+        assert_eq!(2 * input.stop_points.len(), merged.stop_points.len());
+        assert_eq!(
+            2 * input.vehicle_journeys.len(),
+            merged.vehicle_journeys.len()
+        );
+        // Shared referential
+        assert_eq!(input.physical_modes.len(), merged.physical_modes.len());
+        assert_eq!(vec!["B:GDLB", "B:MTPB"], stop_point_ids(&merged, "B:B42F1"));
+        Model::new(merged).unwrap();
+    }
+
+    #[test]
+    fn fail_on_collision() {
+        let mut merged = collections(None);
+        let error =
+            merge_collections(&mut merged, collections(None), ConflictStrategy::Fail).unwrap_err();
+        assert_eq!(
+            "contributor \"TGC\" is in several datasets",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn keep_first_or_last() {
+        let first = || {
+            let mut collections = collections(None);
+            collections
+                .feed_infos
+                .insert("feed_publisher_name".to_string(), "first".to_string());
+            collections
+        };
+        let last = || {
+            let mut collections = collections(None);
+            collections
+                .vehicle_journeys
+                .get_mut("M1F1")
+                .unwrap()
+                .headsign = Some("last".to_string());
+            collections
+                .feed_infos
+                .insert("feed_publisher_name".to_string(), "last".to_string());
+            collections
+        };
+        let trip_collision = Collision {
+            object_type: "trip",
+            object_id: "M1F1".to_string(),
+        };
+        let feed_info_collision = Collision {
+            object_type: "feed_info",
+            object_id: "feed_publisher_name".to_string(),
+        };
+
+        let mut merged = first();
+        let collisions =
+            merge_collections(&mut merged, last(), ConflictStrategy::KeepFirst).unwrap();
+        assert!(collisions.contains(&trip_collision));
+        assert!(collisions.contains(&feed_info_collision));
+        // The headsign of the fixture, its last stop
+        let vj = merged.vehicle_journeys.get("M1F1").unwrap();
+        assert_eq!(Some("Charles de Gaulle (Metro)"), vj.headsign.as_deref());
+        assert_eq!(6, merged.vehicle_journeys.len());
+
+        let mut merged = first();
+        let collisions =
+            merge_collections(&mut merged, last(), ConflictStrategy::KeepLast).unwrap();
+        assert!(collisions.contains(&trip_collision));
+        let vj = merged.vehicle_journeys.get("M1F1").unwrap();
+        assert_eq!(Some("last"), vj.headsign.as_deref());
+        // The first value of a feed info is always kept
+        assert_eq!("first", merged.feed_infos["feed_publisher_name"]);
+        Model::new(merged).unwrap();
+    }
+
+    #[test]
+    fn recompute_validity_periods() {
+        let date = Date::from_ymd_opt(2018, 1, 10).unwrap();
+        let last = || {
+            let mut collections = collections(None);
+            let mut calendar = Calendar::new("last".to_string());
+            calendar.dates.insert(date);
+            collections.calendars.push(calendar).unwrap();
+            let mut vehicle_journeys = collections.vehicle_journeys.take();
+            for vj in &mut vehicle_journeys {
+                vj.service_id = "last".to_string();
+            }
+            collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys).unwrap();
+            collections
+        };
+        let period = |collections: &Collections| {
+            let dataset = collections.datasets.get("TGDS").unwrap();
+            (dataset.start_date, dataset.end_date)
+        };
+
+        let mut merged = collections(None);
+        let first_period = period(&merged);
+        merge_collections(&mut merged, last(), ConflictStrategy::KeepFirst).unwrap();
+        assert_eq!(first_period, period(&merged));
+
+        let mut merged = collections(None);
+        merge_collections(&mut merged, last(), ConflictStrategy::KeepLast).unwrap();
+        assert_eq!((date, date), period(&merged));
+    }
+
+    #[test]
+    fn merge_ntfs_with_transfers() {
+        let mut options = MergeOptions {
+            transfers: Some(TransfersConfiguration {
+                max_distance: 500.0,
+                walking_speed: 0.785,
+                waiting_time: 60,
+            }),
+            ..Default::default()
+        };
+        for prefix in &["A", "B"] {
+            let mut prefix_conf = PrefixConfiguration::default();
+            prefix_conf.set_data_prefix(prefix);
+            options.prefixes.push(prefix_conf);
+        }
+        let input = "tests/fixtures/minimal_ntfs";
+        let (model, report) = merge_datasets(vec![input, input], &options).unwrap();
+        assert_eq!(2, report.inputs.len());
+        let stop_points = report.inputs[0].object_counts["stop_point"];
+        assert_eq!(2 * stop_points, model.stop_points.len());
+        assert_eq!(2 * stop_points, report.object_counts["stop_point"]);
+        // Each stop point is at the same place as its copy in the other input
+        assert!(model.transfers.values().any(|transfer| {
+            transfer.from_stop_id.starts_with("A:") && transfer.to_stop_id.starts_with("B:")
+        }));
+        // No transfer is generated inside an input
+        let transfers_in_a = model
+            .transfers
+            .values()
+            .filter(|transfer| {
+                transfer.from_stop_id.starts_with("A:") && transfer.to_stop_id.starts_with("A:")
+            })
+            .count();
+        assert_eq!(report.inputs[0].object_counts["transfer"], transfers_in_a);
+    }
+}
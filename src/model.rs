@@ -14,7 +14,7 @@
 
 //! Definition of the navitia transit model.
 
-use crate::{enhancers, objects::*, Error, Result};
+use crate::{enhancers, objects::*, report::Report, timing, Error, Result};
 use anyhow::{anyhow, bail};
 use chrono::NaiveDate;
 use derivative::Derivative;
@@ -24,8 +24,9 @@ use relational_types::{GetCorresponding, IdxSet, ManyToMany, OneToMany, Relation
 use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
 use std::{
+    borrow::Borrow,
     cmp::{self, Ordering, Reverse},
-    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryFrom,
     hash::{Hash, Hasher},
     ops,
@@ -33,6 +34,11 @@ use std::{
 use tracing::{debug, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 
+pub use crate::enhancers::{
+    BoundingBox, ContactFieldsMode, CoordinatesConfiguration, CoordinatesStrategy,
+    SchoolPeriodsConfiguration, HOLIDAYS_ONLY, MIXED, SCHOOL_ONLY, SCHOOL_PERIOD_PROPERTY,
+};
+
 /// Physical mode for Air
 pub const AIR_PHYSICAL_MODE: &str = "Air";
 /// Physical mode for Bike
@@ -67,7 +73,7 @@ pub const TRAIN_PHYSICAL_MODE: &str = "Train";
 pub const TRAMWAY_PHYSICAL_MODE: &str = "Tramway";
 
 /// The set of collections representing the model.
-#[derive(Derivative, Serialize, Deserialize, Debug)]
+#[derive(Derivative, Serialize, Deserialize, Debug, Clone)]
 #[derivative(Default)]
 #[allow(missing_docs)]
 pub struct Collections {
@@ -76,6 +82,8 @@ pub struct Collections {
     pub networks: CollectionWithId<Network>,
     pub commercial_modes: CollectionWithId<CommercialMode>,
     pub lines: CollectionWithId<Line>,
+    pub line_groups: CollectionWithId<LineGroup>,
+    pub line_group_links: Collection<LineGroupLink>,
     pub routes: CollectionWithId<Route>,
     pub vehicle_journeys: CollectionWithId<VehicleJourney>,
     pub frequencies: Collection<Frequency>,
@@ -89,18 +97,29 @@ pub struct Collections {
     pub comments: CollectionWithId<Comment>,
     pub equipments: CollectionWithId<Equipment>,
     pub transfers: Collection<Transfer>,
+    pub trip_transfers: Collection<TripTransfer>,
     pub trip_properties: CollectionWithId<TripProperty>,
     pub geometries: CollectionWithId<Geometry>,
     pub admin_stations: Collection<AdminStation>,
+    /// Headsigns by (vehicle_journey_id, stop_sequence)
+    ///
+    /// The keys and values are [IdString]s (`String`s before 0.50.0), the
+    /// identifiers read from a dataset being shared between the entries.
+    /// [Collections::stop_time_headsign] gives a headsign as a `&str`.
     #[serde(skip)]
-    //HashMap<(vehicle_journey_id, stop_sequence), headsign>,
-    pub stop_time_headsigns: HashMap<(String, u32), String>,
+    pub stop_time_headsigns: HashMap<(IdString, u32), IdString>,
+    /// Stop time ids by (vehicle_journey_id, stop_sequence)
+    ///
+    /// See [Collections::stop_time_headsigns] for the [IdString]s;
+    /// [Collections::stop_time_id] gives an id as a `&str`.
     #[serde(skip)]
-    //HashMap<(vehicle_journey_id, stop_sequence), stop_time_id>,
-    pub stop_time_ids: HashMap<(String, u32), String>,
+    pub stop_time_ids: HashMap<(IdString, u32), IdString>,
+    /// Comment ids by (vehicle_journey_id, stop_sequence)
+    ///
+    /// See [Collections::stop_time_headsigns] for the [IdString]s;
+    /// [Collections::stop_time_comment] gives a comment id as a `&str`.
     #[serde(skip)]
-    //HashMap<(vehicle_journey_id, stop_sequence), comment_id>
-    pub stop_time_comments: HashMap<(String, u32), String>,
+    pub stop_time_comments: HashMap<(IdString, u32), IdString>,
     pub prices_v1: Collection<PriceV1>,
     pub od_fares_v1: Collection<OdFareV1>,
     pub fares_v1: Collection<FareV1>,
@@ -111,11 +130,112 @@ pub struct Collections {
     pub ticket_use_restrictions: Collection<TicketUseRestriction>,
     pub pathways: CollectionWithId<Pathway>,
     pub levels: CollectionWithId<Level>,
+    pub booking_rules: CollectionWithId<BookingRule>,
     pub grid_calendars: CollectionWithId<GridCalendar>,
     pub grid_exception_dates: Collection<GridExceptionDate>,
     pub grid_periods: Collection<GridPeriod>,
     pub grid_rel_calendar_line: Collection<GridRelCalendarLine>,
+    pub occupancies: Collection<Occupancy>,
     pub addresses: CollectionWithId<Address>,
+    pub brandings: CollectionWithId<Branding>,
+    pub translations: Collection<Translation>,
+    // True if the geometries were not read: the `geometry_id` of the objects
+    // are kept without being checked, and the model can't be written
+    #[serde(default)]
+    pub skipped_geometries: bool,
+}
+
+/// A reference from an object to an object that doesn't exist, found by
+/// [Collections::check_references]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingReference {
+    /// Type of the referencing object (`trip`, `route`, `stop_point`...)
+    pub object_type: &'static str,
+    /// Identifier of the referencing object, `None` for the objects without
+    /// identifier (transfers, frequencies...)
+    pub object_id: Option<String>,
+    /// Field containing the reference
+    pub field: &'static str,
+    /// Identifier of the missing object
+    pub referenced_id: String,
+}
+
+impl DanglingReference {
+    /// Whether [Model::new] fails because of this reference
+    pub(crate) fn breaks_relation(&self) -> bool {
+        matches!(
+            (self.object_type, self.field),
+            ("trip", "route_id")
+                | ("trip", "physical_mode_id")
+                | ("trip", "dataset_id")
+                | ("trip", "service_id")
+                | ("trip", "company_id")
+                | ("route", "line_id")
+                | ("line", "network_id")
+                | ("line", "commercial_mode_id")
+                | ("stop_point", "stop_area_id")
+                | ("dataset", "contributor_id")
+        )
+    }
+}
+
+impl std::fmt::Display for DanglingReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.object_type)?;
+        if let Some(object_id) = &self.object_id {
+            write!(f, " {:?}", object_id)?;
+        }
+        write!(f, ": {} {:?} not found", self.field, self.referenced_id)
+    }
+}
+
+// A (vehicle_journey_id, stop_sequence) key of the stop time maps, for them
+// to be looked up with a `&str` without allocating an `IdString`. Both keys
+// hash and compare as a `(&str, u32)`.
+trait StopTimeKey {
+    fn key(&self) -> (&str, u32);
+}
+
+impl StopTimeKey for (IdString, u32) {
+    fn key(&self) -> (&str, u32) {
+        (self.0.as_str(), self.1)
+    }
+}
+
+impl StopTimeKey for (&str, u32) {
+    fn key(&self) -> (&str, u32) {
+        *self
+    }
+}
+
+impl<'a> Borrow<dyn StopTimeKey + 'a> for (IdString, u32) {
+    fn borrow(&self) -> &(dyn StopTimeKey + 'a) {
+        self
+    }
+}
+
+impl Hash for dyn StopTimeKey + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialEq for dyn StopTimeKey + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for dyn StopTimeKey + '_ {}
+
+fn stop_time_value<'a>(
+    values: &'a HashMap<(IdString, u32), IdString>,
+    vehicle_journey_id: &str,
+    stop_sequence: u32,
+) -> Option<&'a str> {
+    values
+        .get(&(vehicle_journey_id, stop_sequence) as &dyn StopTimeKey)
+        .map(IdString::as_str)
 }
 
 impl Collections {
@@ -145,16 +265,35 @@ impl Collections {
         });
     }
 
+    /// The headsign of the stop time of `vehicle_journey_id` at `stop_sequence`
+    pub fn stop_time_headsign(&self, vehicle_journey_id: &str, stop_sequence: u32) -> Option<&str> {
+        stop_time_value(&self.stop_time_headsigns, vehicle_journey_id, stop_sequence)
+    }
+
+    /// The id of the stop time of `vehicle_journey_id` at `stop_sequence`
+    pub fn stop_time_id(&self, vehicle_journey_id: &str, stop_sequence: u32) -> Option<&str> {
+        stop_time_value(&self.stop_time_ids, vehicle_journey_id, stop_sequence)
+    }
+
+    /// The comment id of the stop time of `vehicle_journey_id` at `stop_sequence`
+    pub fn stop_time_comment(&self, vehicle_journey_id: &str, stop_sequence: u32) -> Option<&str> {
+        stop_time_value(&self.stop_time_comments, vehicle_journey_id, stop_sequence)
+    }
+
     /// Restrict the validity period of the current `Collections` with the start_date and end_date
     pub fn restrict_period(&mut self, start_date: NaiveDate, end_date: NaiveDate) -> Result<()> {
+        let period = ValidityPeriod {
+            start_date,
+            end_date,
+        };
         let mut calendars = self.calendars.take();
         for calendar in calendars.iter_mut() {
-            calendar.dates = calendar
-                .dates
-                .iter()
-                .cloned()
-                .filter(|date| *date >= start_date && *date <= end_date)
-                .collect();
+            // The dates of a calendar outside the period are dropped at once
+            if calendar.overlaps_period(&period) {
+                calendar.intersect_with_period(&period);
+            } else {
+                calendar.dates.clear();
+            }
         }
         let mut data_sets = self.datasets.take();
         for data_set in data_sets.iter_mut() {
@@ -163,9 +302,536 @@ impl Collections {
         }
         self.datasets = CollectionWithId::new(data_sets)?;
         self.calendars = CollectionWithId::new(calendars)?;
+        self.update_validity_period_from_calendars();
         Ok(())
     }
 
+    /// List all the references to objects that don't exist, without failing
+    /// on the first one as [Model::new] does.
+    ///
+    /// The checked references are the ones between the public transport
+    /// objects, the stops, the comments, the geometries (unless they were
+    /// skipped when reading), the equipments, the levels, the addresses, the
+    /// brandings, the fares, the grid calendars and the translations.
+    pub fn check_references(&self) -> Vec<DanglingReference> {
+        fn check<T>(
+            references: &mut Vec<DanglingReference>,
+            (object_type, object_id): (&'static str, Option<&str>),
+            collection: &CollectionWithId<T>,
+            (field, referenced_id): (&'static str, &str),
+        ) {
+            if !collection.contains_id(referenced_id) {
+                references.push(DanglingReference {
+                    object_type,
+                    object_id: object_id.map(str::to_string),
+                    field,
+                    referenced_id: referenced_id.to_string(),
+                });
+            }
+        }
+        fn check_option<T>(
+            references: &mut Vec<DanglingReference>,
+            object: (&'static str, Option<&str>),
+            collection: &CollectionWithId<T>,
+            (field, referenced_id): (&'static str, &Option<String>),
+        ) {
+            if let Some(referenced_id) = referenced_id {
+                check(references, object, collection, (field, referenced_id));
+            }
+        }
+        fn check_comments(
+            references: &mut Vec<DanglingReference>,
+            object: (&'static str, Option<&str>),
+            comments: &CollectionWithId<Comment>,
+            comment_links: &CommentLinksT,
+        ) {
+            for comment_id in comment_links {
+                check(references, object, comments, ("comment_id", comment_id));
+            }
+        }
+
+        let mut refs = Vec::new();
+        for vj in self.vehicle_journeys.values() {
+            let object = ("trip", Some(vj.id.as_str()));
+            check(&mut refs, object, &self.routes, ("route_id", &vj.route_id));
+            check(
+                &mut refs,
+                object,
+                &self.physical_modes,
+                ("physical_mode_id", &vj.physical_mode_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.datasets,
+                ("dataset_id", &vj.dataset_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.calendars,
+                ("service_id", &vj.service_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.companies,
+                ("company_id", &vj.company_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.trip_properties,
+                ("trip_property_id", &vj.trip_property_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &vj.geometry_id),
+            );
+            check_comments(&mut refs, object, &self.comments, &vj.comment_links);
+        }
+        for ((vj_id, _), comment_id) in &self.stop_time_comments {
+            let object = ("stop_time", Some(vj_id.as_str()));
+            check(
+                &mut refs,
+                object,
+                &self.vehicle_journeys,
+                ("trip_id", vj_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.comments,
+                ("comment_id", comment_id),
+            );
+        }
+        for frequency in self.frequencies.values() {
+            check(
+                &mut refs,
+                ("frequency", None),
+                &self.vehicle_journeys,
+                ("trip_id", &frequency.vehicle_journey_id),
+            );
+        }
+        for route in self.routes.values() {
+            let object = ("route", Some(route.id.as_str()));
+            check(&mut refs, object, &self.lines, ("line_id", &route.line_id));
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &route.geometry_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.stop_areas,
+                ("destination_id", &route.destination_id),
+            );
+            check_comments(&mut refs, object, &self.comments, &route.comment_links);
+        }
+        for line in self.lines.values() {
+            let object = ("line", Some(line.id.as_str()));
+            check(
+                &mut refs,
+                object,
+                &self.networks,
+                ("network_id", &line.network_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.commercial_modes,
+                ("commercial_mode_id", &line.commercial_mode_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &line.geometry_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.brandings,
+                ("branding_id", &line.branding_id),
+            );
+            check_comments(&mut refs, object, &self.comments, &line.comment_links);
+        }
+        for network in self.networks.values() {
+            check_option(
+                &mut refs,
+                ("network", Some(network.id.as_str())),
+                &self.brandings,
+                ("branding_id", &network.branding_id),
+            );
+        }
+        for dataset in self.datasets.values() {
+            check(
+                &mut refs,
+                ("dataset", Some(dataset.id.as_str())),
+                &self.contributors,
+                ("contributor_id", &dataset.contributor_id),
+            );
+        }
+        for stop_point in self.stop_points.values() {
+            let object = ("stop_point", Some(stop_point.id.as_str()));
+            check(
+                &mut refs,
+                object,
+                &self.stop_areas,
+                ("stop_area_id", &stop_point.stop_area_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &stop_point.geometry_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.equipments,
+                ("equipment_id", &stop_point.equipment_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.levels,
+                ("level_id", &stop_point.level_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.addresses,
+                ("address_id", &stop_point.address_id),
+            );
+            check_comments(&mut refs, object, &self.comments, &stop_point.comment_links);
+        }
+        for stop_area in self.stop_areas.values() {
+            let object = ("stop_area", Some(stop_area.id.as_str()));
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &stop_area.geometry_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.equipments,
+                ("equipment_id", &stop_area.equipment_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.levels,
+                ("level_id", &stop_area.level_id),
+            );
+            check_comments(&mut refs, object, &self.comments, &stop_area.comment_links);
+        }
+        let stop_exists = |stop_id: &str| {
+            self.stop_points.contains_id(stop_id)
+                || self.stop_areas.contains_id(stop_id)
+                || self.stop_locations.contains_id(stop_id)
+        };
+        for stop_location in self.stop_locations.values() {
+            let object = ("stop_location", Some(stop_location.id.as_str()));
+            if let Some(parent_id) = &stop_location.parent_id {
+                if !stop_exists(parent_id) {
+                    refs.push(DanglingReference {
+                        object_type: "stop_location",
+                        object_id: Some(stop_location.id.clone()),
+                        field: "parent_id",
+                        referenced_id: parent_id.clone(),
+                    });
+                }
+            }
+            check_option(
+                &mut refs,
+                object,
+                &self.geometries,
+                ("geometry_id", &stop_location.geometry_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.equipments,
+                ("equipment_id", &stop_location.equipment_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.levels,
+                ("level_id", &stop_location.level_id),
+            );
+            check_comments(
+                &mut refs,
+                object,
+                &self.comments,
+                &stop_location.comment_links,
+            );
+        }
+        for pathway in self.pathways.values() {
+            for (field, stop_id) in &[
+                ("from_stop_id", &pathway.from_stop_id),
+                ("to_stop_id", &pathway.to_stop_id),
+            ] {
+                if !stop_exists(stop_id) {
+                    refs.push(DanglingReference {
+                        object_type: "pathway",
+                        object_id: Some(pathway.id.clone()),
+                        field,
+                        referenced_id: stop_id.to_string(),
+                    });
+                }
+            }
+        }
+        for transfer in self.transfers.values() {
+            let object = ("transfer", None);
+            check(
+                &mut refs,
+                object,
+                &self.stop_points,
+                ("from_stop_id", &transfer.from_stop_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.stop_points,
+                ("to_stop_id", &transfer.to_stop_id),
+            );
+            check_option(
+                &mut refs,
+                object,
+                &self.equipments,
+                ("equipment_id", &transfer.equipment_id),
+            );
+        }
+        for trip_transfer in self.trip_transfers.values() {
+            let object = ("trip_transfer", None);
+            check(
+                &mut refs,
+                object,
+                &self.vehicle_journeys,
+                ("from_trip_id", &trip_transfer.from_vehicle_journey_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.vehicle_journeys,
+                ("to_trip_id", &trip_transfer.to_vehicle_journey_id),
+            );
+        }
+        for admin_station in self.admin_stations.values() {
+            check(
+                &mut refs,
+                ("admin_station", None),
+                &self.stop_areas,
+                ("stop_id", &admin_station.stop_id),
+            );
+        }
+
+        for ticket_price in self.ticket_prices.values() {
+            check(
+                &mut refs,
+                ("ticket_price", None),
+                &self.tickets,
+                ("ticket_id", &ticket_price.ticket_id),
+            );
+        }
+        for ticket_use in self.ticket_uses.values() {
+            check(
+                &mut refs,
+                ("ticket_use", Some(ticket_use.id.as_str())),
+                &self.tickets,
+                ("ticket_id", &ticket_use.ticket_id),
+            );
+        }
+        for perimeter in self.ticket_use_perimeters.values() {
+            let object = ("ticket_use_perimeter", None);
+            check(
+                &mut refs,
+                object,
+                &self.ticket_uses,
+                ("ticket_use_id", &perimeter.ticket_use_id),
+            );
+            let field = ("object_id", perimeter.object_id.as_str());
+            match perimeter.object_type {
+                ObjectType::Line => check(&mut refs, object, &self.lines, field),
+                ObjectType::Network => check(&mut refs, object, &self.networks, field),
+                ObjectType::CommercialMode => {
+                    check(&mut refs, object, &self.commercial_modes, field)
+                }
+                _ => {}
+            }
+        }
+        for restriction in self.ticket_use_restrictions.values() {
+            let object = ("ticket_use_restriction", None);
+            check(
+                &mut refs,
+                object,
+                &self.ticket_uses,
+                ("ticket_use_id", &restriction.ticket_use_id),
+            );
+            if restriction.restriction_type == RestrictionType::OriginDestination {
+                check(
+                    &mut refs,
+                    object,
+                    &self.stop_areas,
+                    ("use_origin", &restriction.use_origin),
+                );
+                check(
+                    &mut refs,
+                    object,
+                    &self.stop_areas,
+                    ("use_destination", &restriction.use_destination),
+                );
+            }
+        }
+
+        for rel in self.grid_rel_calendar_line.values() {
+            let object = ("grid_rel_calendar_line", None);
+            check(
+                &mut refs,
+                object,
+                &self.grid_calendars,
+                ("grid_calendar_id", &rel.grid_calendar_id),
+            );
+            // An empty `line_id` means that `line_external_code` is used
+            if !rel.line_id.is_empty() {
+                check(&mut refs, object, &self.lines, ("line_id", &rel.line_id));
+            }
+        }
+        for exception_date in self.grid_exception_dates.values() {
+            check(
+                &mut refs,
+                ("grid_exception_date", None),
+                &self.grid_calendars,
+                ("grid_calendar_id", &exception_date.grid_calendar_id),
+            );
+        }
+        for period in self.grid_periods.values() {
+            check(
+                &mut refs,
+                ("grid_period", None),
+                &self.grid_calendars,
+                ("grid_calendar_id", &period.grid_calendar_id),
+            );
+        }
+        for line_group in self.line_groups.values() {
+            check(
+                &mut refs,
+                ("line_group", Some(&line_group.id)),
+                &self.lines,
+                ("main_line_id", &line_group.main_line_id),
+            );
+        }
+        for link in self.line_group_links.values() {
+            let object = ("line_group_link", None);
+            check(
+                &mut refs,
+                object,
+                &self.line_groups,
+                ("line_group_id", &link.line_group_id),
+            );
+            check(&mut refs, object, &self.lines, ("line_id", &link.line_id));
+        }
+        for occupancy in self.occupancies.values() {
+            let object = ("occupancy", None);
+            check(
+                &mut refs,
+                object,
+                &self.lines,
+                ("line_id", &occupancy.line_id),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.stop_areas,
+                ("from_stop_area", &occupancy.from_stop_area),
+            );
+            check(
+                &mut refs,
+                object,
+                &self.stop_areas,
+                ("to_stop_area", &occupancy.to_stop_area),
+            );
+        }
+        for translation in self.translations.values() {
+            if self.contains_object(&translation.object_type, &translation.object_id) == Some(false)
+            {
+                refs.push(DanglingReference {
+                    object_type: "translation",
+                    object_id: None,
+                    field: "object_id",
+                    referenced_id: translation.object_id.clone(),
+                });
+            }
+        }
+        if self.skipped_geometries {
+            refs.retain(|reference| reference.field != "geometry_id");
+        }
+        refs
+    }
+
+    // Whether the object exists, `None` for the types of objects which can't
+    // be referenced by their identifier
+    fn contains_object(&self, object_type: &ObjectType, object_id: &str) -> Option<bool> {
+        Some(match object_type {
+            ObjectType::StopArea => self.stop_areas.contains_id(object_id),
+            ObjectType::StopPoint => self.stop_points.contains_id(object_id),
+            ObjectType::Network => self.networks.contains_id(object_id),
+            ObjectType::Line => self.lines.contains_id(object_id),
+            ObjectType::Route => self.routes.contains_id(object_id),
+            ObjectType::VehicleJourney => self.vehicle_journeys.contains_id(object_id),
+            ObjectType::Ticket => self.tickets.contains_id(object_id),
+            ObjectType::Company => self.companies.contains_id(object_id),
+            ObjectType::CommercialMode => self.commercial_modes.contains_id(object_id),
+            ObjectType::PhysicalMode => self.physical_modes.contains_id(object_id),
+            ObjectType::Contributor => self.contributors.contains_id(object_id),
+            ObjectType::LineGroup => self.line_groups.contains_id(object_id),
+            ObjectType::StopTime => return None,
+        })
+    }
+
+    /// The translation in `language` of the field `field_name` (like `name`
+    /// or `headsign`) of an object, from the `translations`.
+    ///
+    /// ```
+    /// # use transit_model::{model::Collections, objects::{ObjectType, Translation}};
+    /// let mut collections = Collections::default();
+    /// collections.translations.push(Translation {
+    ///     object_type: ObjectType::Line,
+    ///     object_id: "M1".to_string(),
+    ///     field_name: "name".to_string(),
+    ///     language: "fr".to_string(),
+    ///     translation: "Métro 1".to_string(),
+    /// });
+    /// let translation = collections.translation(ObjectType::Line, "M1", "name", "fr");
+    /// assert_eq!(Some("Métro 1"), translation);
+    /// assert_eq!(None, collections.translation(ObjectType::Line, "M1", "name", "de"));
+    /// ```
+    pub fn translation(
+        &self,
+        object_type: ObjectType,
+        object_id: &str,
+        field_name: &str,
+        language: &str,
+    ) -> Option<&str> {
+        self.translations
+            .values()
+            .find(|translation| {
+                translation.object_type == object_type
+                    && translation.object_id == object_id
+                    && translation.field_name == field_name
+                    && translation.language == language
+            })
+            .map(|translation| translation.translation.as_str())
+    }
+
     /// Keep the collections consistent for the new model by purging unreferenced data by
     /// calendars
     pub fn sanitize(&mut self) -> Result<()> {
@@ -203,9 +869,7 @@ impl Collections {
         }
 
         self.calendars
-            .retain(log_predicate("Calendar", |cal: &Calendar| {
-                !cal.dates.is_empty()
-            }));
+            .retain(log_predicate("Calendar", |cal: &Calendar| !cal.is_empty()));
 
         let mut geometries_used = HashSet::<String>::new();
         let mut companies_used = HashSet::<String>::new();
@@ -219,6 +883,7 @@ impl Collections {
         let mut calendars_used = HashSet::<String>::new();
         let mut vjs_used = HashSet::<String>::new();
         let mut addresses_used = HashSet::<String>::new();
+        let mut booking_rules_used = HashSet::<String>::new();
 
         let stop_point_id_to_old_idx = self.stop_points.get_id_to_idx().clone();
 
@@ -242,6 +907,13 @@ impl Collections {
                 route_ids_used.insert(vj.route_id.clone());
                 for stop_time in &vj.stop_times {
                     stop_points_used.insert(self.stop_points[stop_time.stop_point_idx].id.clone());
+                    booking_rules_used.extend(
+                        stop_time
+                            .pickup_booking_rule_id
+                            .iter()
+                            .chain(&stop_time.drop_off_booking_rule_id)
+                            .cloned(),
+                    );
                 }
                 data_sets_used.insert(vj.dataset_id.clone());
                 physical_modes_used.insert(vj.physical_mode_id.clone());
@@ -381,6 +1053,27 @@ impl Collections {
                 }
             })
             .collect::<Vec<_>>();
+        let line_groups = self
+            .line_groups
+            .take()
+            .into_iter()
+            .filter(|lg| {
+                if line_ids_used.contains(&lg.main_line_id) {
+                    comments_used.extend(&mut lg.comment_links.iter().map(|cl| cl.to_string()));
+                    true
+                } else {
+                    log_object_removed("Line Group", &lg.id);
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
+        let line_group_ids_used: HashSet<&str> =
+            line_groups.iter().map(|lg| lg.id.as_str()).collect();
+        self.line_group_links.retain(|link| {
+            line_ids_used.contains(&link.line_id)
+                && line_group_ids_used.contains(link.line_group_id.as_str())
+        });
+        self.line_groups = CollectionWithId::new(line_groups)?;
         let mut contributors_used: HashSet<String> = HashSet::new();
         self.datasets = CollectionWithId::new(
             self.datasets
@@ -451,7 +1144,7 @@ impl Collections {
         comments_used.extend(self.stop_time_comments.iter().filter_map(
             |((vj_id, _), comment_id)| {
                 if vjs_used.contains(vj_id.as_str()) {
-                    Some(comment_id.clone())
+                    Some(comment_id.to_string())
                 } else {
                     None
                 }
@@ -481,12 +1174,13 @@ impl Collections {
         self.vehicle_journeys = CollectionWithId::new(vjs)?;
         self.stop_locations = CollectionWithId::new(stop_locations)?;
         self.stop_time_comments.retain(|(vj_id, _), comment_id| {
-            vehicle_journeys_used.contains(vj_id) && comments_used.contains(comment_id)
+            vehicle_journeys_used.contains(vj_id.as_str())
+                && comments_used.contains(comment_id.as_str())
         });
         self.stop_time_ids
-            .retain(|(vj_id, _), _| vehicle_journeys_used.contains(vj_id));
+            .retain(|(vj_id, _), _| vehicle_journeys_used.contains(vj_id.as_str()));
         self.stop_time_headsigns
-            .retain(|(vj_id, _), _| vehicle_journeys_used.contains(vj_id));
+            .retain(|(vj_id, _), _| vehicle_journeys_used.contains(vj_id.as_str()));
         self.grid_rel_calendar_line
             .retain(|grid_rel_calendar_line| {
                 line_ids_used.contains(&grid_rel_calendar_line.line_id)
@@ -546,14 +1240,48 @@ impl Collections {
         });
         self.frequencies
             .retain(|frequency| vehicle_journeys_used.contains(&frequency.vehicle_journey_id));
+        self.trip_transfers.retain(|trip_transfer| {
+            vehicle_journeys_used.contains(&trip_transfer.from_vehicle_journey_id)
+                && vehicle_journeys_used.contains(&trip_transfer.to_vehicle_journey_id)
+        });
         self.levels
             .retain(|level| level_id_used.contains(&level.id));
+        self.booking_rules.retain(log_predicate(
+            "Booking Rule",
+            |booking_rule: &BookingRule| booking_rules_used.contains(&booking_rule.id),
+        ));
         self.calendars.retain(|c| calendars_used.contains(&c.id));
         self.addresses
             .retain(|address| addresses_used.contains(&address.id));
+        let brandings_used: HashSet<&str> = self
+            .lines
+            .values()
+            .filter_map(|line| line.branding_id.as_deref())
+            .chain(
+                self.networks
+                    .values()
+                    .filter_map(|network| network.branding_id.as_deref()),
+            )
+            .collect();
+        self.brandings
+            .retain(log_predicate("Branding", |branding: &Branding| {
+                brandings_used.contains(branding.id.as_str())
+            }));
+        let mut translations = self.translations.take();
+        translations.retain(|translation| {
+            self.contains_object(&translation.object_type, &translation.object_id) != Some(false)
+        });
+        self.translations = Collection::new(translations);
+        let stop_areas = &self.stop_areas;
+        self.occupancies.retain(|occupancy| {
+            line_ids_used.contains(&occupancy.line_id)
+                && stop_areas.contains_id(&occupancy.from_stop_area)
+                && stop_areas.contains_id(&occupancy.to_stop_area)
+        });
 
         self.frequencies = dedup_collection(&mut self.frequencies);
         self.transfers = dedup_collection(&mut self.transfers);
+        self.trip_transfers = dedup_collection(&mut self.trip_transfers);
         self.admin_stations = dedup_collection(&mut self.admin_stations);
         self.prices_v1 = dedup_collection(&mut self.prices_v1);
         self.od_fares_v1 = dedup_collection(&mut self.od_fares_v1);
@@ -564,6 +1292,9 @@ impl Collections {
         self.grid_exception_dates = dedup_collection(&mut self.grid_exception_dates);
         self.grid_periods = dedup_collection(&mut self.grid_periods);
         self.grid_rel_calendar_line = dedup_collection(&mut self.grid_rel_calendar_line);
+        self.occupancies = dedup_collection(&mut self.occupancies);
+        self.line_group_links = dedup_collection(&mut self.line_group_links);
+        self.translations = dedup_collection(&mut self.translations);
 
         Ok(())
     }
@@ -807,6 +1538,25 @@ impl Collections {
         self.vehicle_journeys = CollectionWithId::new(vehicle_journeys).unwrap();
     }
 
+    /// Remove the calendars used by no vehicle journey, then recompute the
+    /// validity period of the datasets (see
+    /// [Collections::update_validity_period_from_calendars]).
+    ///
+    /// Returns the number of removed calendars.
+    pub fn remove_unused_calendars(&mut self) -> usize {
+        let service_ids: HashSet<&str> = self
+            .vehicle_journeys
+            .values()
+            .map(|vj| vj.service_id.as_str())
+            .collect();
+        let calendars_count = self.calendars.len();
+        self.calendars
+            .retain(|calendar| service_ids.contains(calendar.id.as_str()));
+        let removed = calendars_count - self.calendars.len();
+        self.update_validity_period_from_calendars();
+        removed
+    }
+
     /// Some comments are identical and can be deduplicated
     pub fn comment_deduplication(&mut self) {
         let duplicate2ref = self.get_comment_map_duplicate_to_referent();
@@ -815,6 +1565,7 @@ impl Collections {
         }
 
         replace_comment_duplicates_by_ref(&mut self.lines, &duplicate2ref);
+        replace_comment_duplicates_by_ref(&mut self.line_groups, &duplicate2ref);
         replace_comment_duplicates_by_ref(&mut self.routes, &duplicate2ref);
         replace_comment_duplicates_by_ref(&mut self.stop_areas, &duplicate2ref);
         replace_comment_duplicates_by_ref(&mut self.stop_points, &duplicate2ref);
@@ -890,6 +1641,7 @@ impl Collections {
         for comment_idx in comments_to_del {
             let comment_id = &self.comments[comment_idx].id;
             remove_comment(&mut self.lines, comment_id);
+            remove_comment(&mut self.line_groups, comment_id);
             remove_comment(&mut self.routes, comment_id);
             remove_comment(&mut self.vehicle_journeys, comment_id);
             remove_comment(&mut self.stop_areas, comment_id);
@@ -1150,7 +1902,11 @@ impl Collections {
     ///
     /// This function checks that all objects points to existing `Geometry` and,
     /// in the case it doesn't, fix the model by removing this pointer.
+    /// Nothing is done if the geometries were skipped when reading.
     fn check_geometries_coherence(&mut self) {
+        if self.skipped_geometries {
+            return;
+        }
         macro_rules! check_and_fix_object_geometries {
             ($collection:expr) => {
                 let objects_to_fix: Vec<String> = $collection
@@ -1193,6 +1949,155 @@ impl Collections {
             bail!("Cannot calculate validity period because there is no dataset")
         }
     }
+
+    /// Convert the fares v1 (`prices_v1`, `od_fares_v1` and `fares_v1`) into
+    /// fares v2 collections (`tickets`, `ticket_prices`, `ticket_uses`,
+    /// `ticket_use_perimeters` and `ticket_use_restrictions`).
+    ///
+    /// Each fares v1 ticket becomes a ticket and a ticket use with the same
+    /// identifier. The fares v1 rows that cannot be converted are skipped
+    /// with a warning. An error is returned if fares v2 are already defined.
+    pub fn fares_v1_to_v2(&mut self) -> Result<()> {
+        crate::fares::fares_v1_to_v2(self)
+    }
+
+    /// Check the coordinates of the stop areas and stop points: (0, 0), out
+    /// of range, probably swapped, or outside the bounding box of the dataset.
+    ///
+    /// Each issue is reported, fixed or the stop is removed, depending on the
+    /// strategy configured for its type. The returned report lists all the
+    /// issues with the modifications made, including the objects removed in
+    /// cascade.
+    pub fn check_coordinates(&mut self, config: &CoordinatesConfiguration) -> Report {
+        let mut report = Report::default();
+        enhancers::check_coordinates(self, config, &mut report);
+        report
+    }
+
+    /// Find the vehicle journeys with the same route, the same stop points and
+    /// the same times as another one, whose calendars overlap.
+    ///
+    /// If `merge` is true, only the first vehicle journey of each group is
+    /// kept: its calendar is replaced by the union of the calendars of the
+    /// group and it gets the codes of the removed vehicle journeys. The
+    /// returned report lists the duplicates, with the modifications made.
+    pub fn check_duplicate_vehicle_journeys(&mut self, merge: bool) -> Report {
+        let mut report = Report::default();
+        enhancers::check_duplicate_vehicle_journeys(self, merge, &mut report);
+        report
+    }
+
+    /// Replace the frequencies with `exact_times` by a vehicle journey for
+    /// each of their departures, identified by `<trip_id>:freq:<n>`.
+    ///
+    /// The headway-based frequencies are kept.
+    pub fn expand_exact_frequencies(&mut self) -> Result<()> {
+        enhancers::expand_exact_frequencies(self)
+    }
+
+    /// Tag each vehicle journey with the object property
+    /// `SCHOOL_PERIOD_PROPERTY` depending on how much of its dates are school
+    /// dates, the dates of the grid calendars of its line. The tags of a
+    /// previous run are replaced.
+    pub fn tag_school_periods(&mut self, config: &SchoolPeriodsConfiguration) {
+        enhancers::tag_school_periods(self, config);
+    }
+
+    /// Recompute the validity period of each dataset from the calendars of
+    /// its vehicle journeys.
+    ///
+    /// A vehicle journey with times beyond `24:00:00` runs on the following
+    /// days of its last date: the period is extended by the `days_offset` of
+    /// its last time.
+    ///
+    /// If a vehicle journey references an unknown dataset, the link between
+    /// vehicle journeys and datasets is ambiguous and every dataset gets the
+    /// union of all the calendars instead. A dataset without any vehicle
+    /// journey (or whose vehicle journeys have no date) has no period and is
+    /// removed with a warning: its vehicle journeys, running on no date, are
+    /// removed with their calendars when building the [Model] (see
+    /// [Collections::sanitize]).
+    pub fn update_validity_period_from_calendars(&mut self) {
+        fn extend(period: &mut Option<(Date, Date)>, calendar: &Calendar, days_offset: u32) {
+            if let (Some(first), Some(last)) = (
+                calendar.dates.iter().next(),
+                calendar.dates.iter().next_back(),
+            ) {
+                let last = *last + chrono::Duration::days(i64::from(days_offset));
+                *period = match *period {
+                    Some((start, end)) => Some((cmp::min(start, *first), cmp::max(end, last))),
+                    None => Some((*first, last)),
+                };
+            }
+        }
+
+        let mut periods: HashMap<&str, Option<(Date, Date)>> = self
+            .datasets
+            .values()
+            .map(|dataset| (dataset.id.as_str(), None))
+            .collect();
+        let mut is_ambiguous = false;
+        let mut calendar_days_offsets: HashMap<&str, u32> = HashMap::new();
+        for vj in self.vehicle_journeys.values() {
+            let calendar =
+                skip_error_and_warn!(self.calendars.get(&vj.service_id).ok_or_else(|| anyhow!(
+                    "Calendar {} not found for vehicle journey {}",
+                    vj.service_id,
+                    vj.id
+                )));
+            let days_offset = vj
+                .stop_times
+                .iter()
+                .map(|stop_time| cmp::max(stop_time.arrival_time, stop_time.departure_time))
+                .max()
+                .map_or(0, Time::days_offset);
+            let calendar_days_offset = calendar_days_offsets
+                .entry(calendar.id.as_str())
+                .or_default();
+            *calendar_days_offset = cmp::max(*calendar_days_offset, days_offset);
+            match periods.get_mut(vj.dataset_id.as_str()) {
+                Some(period) => extend(period, calendar, days_offset),
+                None => is_ambiguous = true,
+            }
+        }
+        if is_ambiguous {
+            let mut global_period = None;
+            for calendar in self.calendars.values() {
+                let days_offset = calendar_days_offsets
+                    .get(calendar.id.as_str())
+                    .copied()
+                    .unwrap_or_default();
+                extend(&mut global_period, calendar, days_offset);
+            }
+            for period in periods.values_mut() {
+                *period = global_period;
+            }
+        }
+        let periods: HashMap<String, Option<(Date, Date)>> = periods
+            .into_iter()
+            .map(|(id, period)| (id.to_string(), period))
+            .collect();
+
+        let mut datasets = self.datasets.take();
+        datasets.retain_mut(
+            |dataset| match periods.get(&dataset.id).cloned().flatten() {
+                Some((start_date, end_date)) => {
+                    dataset.start_date = start_date;
+                    dataset.end_date = end_date;
+                    true
+                }
+                None => {
+                    warn!(
+                        "dataset {} has no vehicle journey with a date, it is removed",
+                        dataset.id
+                    );
+                    false
+                }
+            },
+        );
+        self.datasets = CollectionWithId::new(datasets)
+            .expect("datasets identifiers are not modified so they are still unique");
+    }
 }
 
 /// The navitia transit model.
@@ -1248,7 +2153,7 @@ impl Model {
     /// ```
     /// # use transit_model::model::*;
     /// # use typed_index_collection::Collection;
-    /// # use transit_model::objects::Transfer;
+    /// # use transit_model::objects::{Transfer, TransferType};
     /// let mut collections = Collections::default();
     /// // This transfer is invalid as there is no stop points in collections
     /// // but objects not referenced are removed from the model
@@ -1258,130 +2163,213 @@ impl Model {
     ///     min_transfer_time: None,
     ///     real_min_transfer_time: None,
     ///     equipment_id: None,
+    ///     transfer_type: TransferType::Possible,
     /// });
     /// assert!(Model::new(collections).is_ok());
     /// ```
     pub fn new(mut c: Collections) -> Result<Self> {
-        enhancers::check_stop_times_order(&mut c);
-        c.comment_deduplication();
-        c.clean_comments();
-        c.sanitize()?;
-
-        let forward_vj_to_sp = c
-            .vehicle_journeys
-            .iter()
-            .map(|(idx, vj)| {
-                let sps = vj.stop_times.iter().map(|st| st.stop_point_idx).collect();
-                (idx, sps)
-            })
+        let _stage = timing::build_model();
+        timing::enhancer("check_stop_times_order", || {
+            enhancers::check_stop_times_order(&mut c)
+        });
+        timing::enhancer("comment_deduplication", || c.comment_deduplication());
+        timing::enhancer("clean_comments", || c.clean_comments());
+        timing::enhancer("sanitize", || c.sanitize())?;
+        timing::enhancer("check_ticket_use_references", || {
+            enhancers::check_ticket_use_references(&mut c)
+        });
+        let dangling_references: Vec<String> = c
+            .check_references()
+            .into_iter()
+            .filter(DanglingReference::breaks_relation)
+            .map(|reference| reference.to_string())
             .collect();
+        if !dangling_references.is_empty() {
+            bail!(
+                "{} dangling references:\n{}",
+                dangling_references.len(),
+                dangling_references.join("\n")
+            );
+        }
 
-        let forward_tr_to_sp = c
-            .transfers
-            .iter()
-            .map(|(idx, tr)| {
-                let mut stop_points = IdxSet::default();
-                stop_points.insert(c.stop_points.get_idx(&tr.from_stop_id).ok_or_else(|| {
-                    anyhow!("Invalid id: transfer.from_stop_id={:?}", tr.from_stop_id)
-                })?);
-                stop_points.insert(c.stop_points.get_idx(&tr.to_stop_id).ok_or_else(|| {
-                    anyhow!("Invalid id: transfer.to_stop_id={:?}", tr.to_stop_id)
-                })?);
-                Ok((idx, stop_points))
-            })
-            .collect::<Result<BTreeMap<_, _>, Error>>()?;
-        let vehicle_journeys_to_stop_points = ManyToMany::from_forward(forward_vj_to_sp);
-        let routes_to_vehicle_journeys =
-            OneToMany::new(&c.routes, &c.vehicle_journeys, "routes_to_vehicle_journeys")?;
-        let physical_modes_to_vehicle_journeys = OneToMany::new(
-            &c.physical_modes,
-            &c.vehicle_journeys,
-            "physical_modes_to_vehicle_journeys",
-        )?;
-        let datasets_to_vehicle_journeys = OneToMany::new(
-            &c.datasets,
-            &c.vehicle_journeys,
-            "datasets_to_vehicle_journeys",
-        )?;
-        let routes_to_stop_points = ManyToMany::from_relations_chain(
-            &routes_to_vehicle_journeys,
-            &vehicle_journeys_to_stop_points,
-        );
-        let physical_modes_to_stop_points = ManyToMany::from_relations_chain(
-            &physical_modes_to_vehicle_journeys,
-            &vehicle_journeys_to_stop_points,
-        );
-        let physical_modes_to_routes = ManyToMany::from_relations_sink(
-            &physical_modes_to_vehicle_journeys,
-            &routes_to_vehicle_journeys,
-        );
-        let datasets_to_stop_points = ManyToMany::from_relations_chain(
-            &datasets_to_vehicle_journeys,
-            &vehicle_journeys_to_stop_points,
-        );
-        let datasets_to_routes = ManyToMany::from_relations_sink(
-            &datasets_to_vehicle_journeys,
-            &routes_to_vehicle_journeys,
-        );
-        let datasets_to_physical_modes = ManyToMany::from_relations_sink(
-            &datasets_to_vehicle_journeys,
-            &physical_modes_to_vehicle_journeys,
-        );
-        let transfers_to_stop_points = ManyToMany::from_forward(forward_tr_to_sp);
-        let networks_to_lines = OneToMany::new(&c.networks, &c.lines, "networks_to_lines")?;
-        let commercial_modes_to_lines =
-            OneToMany::new(&c.commercial_modes, &c.lines, "commercial_modes_to_lines")?;
-        let lines_to_routes = OneToMany::new(&c.lines, &c.routes, "lines_to_routes")?;
-        let stop_areas_to_stop_points =
-            OneToMany::new(&c.stop_areas, &c.stop_points, "stop_areas_to_stop_points")?;
-        let contributors_to_datasets =
-            OneToMany::new(&c.contributors, &c.datasets, "contributors_to_datasets")?;
-        let companies_to_vehicle_journeys = OneToMany::new(
-            &c.companies,
-            &c.vehicle_journeys,
-            "companies_to_vehicle_journeys",
-        )?;
-        let calendars_to_vehicle_journeys = OneToMany::new(
-            &c.calendars,
-            &c.vehicle_journeys,
-            "calendars_to_vehicle_journeys",
-        )?;
-
-        c.update_stop_area_coords();
-        enhancers::fill_co2(&mut c);
-        c.enhance_trip_headsign();
-        c.enhance_route_names(&routes_to_vehicle_journeys);
-        c.enhance_route_directions();
-        c.check_geometries_coherence();
-        enhancers::adjust_lines_names(&mut c, &lines_to_routes);
-        c.enhance_line_opening_time();
-        c.pickup_drop_off_harmonisation();
-        enhancers::enhance_pickup_dropoff(&mut c);
-        enhancers::memory_shrink(&mut c);
-
-        Ok(Model {
-            routes_to_stop_points,
-            physical_modes_to_stop_points,
-            physical_modes_to_routes,
-            datasets_to_stop_points,
-            datasets_to_routes,
-            datasets_to_physical_modes,
-            transfers_to_stop_points,
-            datasets_to_vehicle_journeys,
-            routes_to_vehicle_journeys,
-            vehicle_journeys_to_stop_points,
-            physical_modes_to_vehicle_journeys,
-            networks_to_lines,
-            commercial_modes_to_lines,
-            lines_to_routes,
-            stop_areas_to_stop_points,
-            contributors_to_datasets,
-            companies_to_vehicle_journeys,
-            calendars_to_vehicle_journeys,
+        let mut model = Model {
             collections: c,
-        })
-    }
-
+            networks_to_lines: Default::default(),
+            commercial_modes_to_lines: Default::default(),
+            lines_to_routes: Default::default(),
+            routes_to_vehicle_journeys: Default::default(),
+            physical_modes_to_vehicle_journeys: Default::default(),
+            stop_areas_to_stop_points: Default::default(),
+            contributors_to_datasets: Default::default(),
+            datasets_to_vehicle_journeys: Default::default(),
+            companies_to_vehicle_journeys: Default::default(),
+            vehicle_journeys_to_stop_points: Default::default(),
+            transfers_to_stop_points: ManyToMany::from_forward(BTreeMap::new()),
+            calendars_to_vehicle_journeys: Default::default(),
+            routes_to_stop_points: Default::default(),
+            physical_modes_to_stop_points: Default::default(),
+            physical_modes_to_routes: Default::default(),
+            datasets_to_stop_points: Default::default(),
+            datasets_to_routes: Default::default(),
+            datasets_to_physical_modes: Default::default(),
+        };
+        for relation in RelationKind::ALL.iter() {
+            model.build_relation(*relation)?;
+        }
+
+        let c = &mut model.collections;
+        let routes_to_vehicle_journeys = &model.routes_to_vehicle_journeys;
+        let lines_to_routes = &model.lines_to_routes;
+        timing::enhancer("update_stop_area_coords", || c.update_stop_area_coords());
+        timing::enhancer("fill_co2", || enhancers::fill_co2(c, &HashMap::new()));
+        timing::enhancer("enhance_trip_headsign", || c.enhance_trip_headsign());
+        timing::enhancer("enhance_route_names", || {
+            c.enhance_route_names(routes_to_vehicle_journeys)
+        });
+        timing::enhancer("enhance_route_directions", || c.enhance_route_directions());
+        timing::enhancer("check_geometries_coherence", || {
+            c.check_geometries_coherence()
+        });
+        timing::enhancer("adjust_lines_names", || {
+            enhancers::adjust_lines_names(c, lines_to_routes)
+        });
+        timing::enhancer("enhance_line_opening_time", || {
+            c.enhance_line_opening_time()
+        });
+        timing::enhancer("pickup_drop_off_harmonisation", || {
+            c.pickup_drop_off_harmonisation()
+        });
+        timing::enhancer("enhance_pickup_dropoff", || {
+            enhancers::enhance_pickup_dropoff(c)
+        });
+        timing::enhancer("memory_shrink", || enhancers::memory_shrink(c));
+
+        Ok(model)
+    }
+
+    // Builds the relation from the collections, the relations it is built
+    // from being already built
+    fn build_relation(&mut self, relation: RelationKind) -> Result<()> {
+        let c = &self.collections;
+        match relation {
+            RelationKind::NetworksToLines => {
+                self.networks_to_lines = OneToMany::new(&c.networks, &c.lines, "networks_to_lines")?
+            }
+            RelationKind::CommercialModesToLines => {
+                self.commercial_modes_to_lines =
+                    OneToMany::new(&c.commercial_modes, &c.lines, "commercial_modes_to_lines")?
+            }
+            RelationKind::LinesToRoutes => {
+                self.lines_to_routes = OneToMany::new(&c.lines, &c.routes, "lines_to_routes")?
+            }
+            RelationKind::RoutesToVehicleJourneys => {
+                self.routes_to_vehicle_journeys =
+                    OneToMany::new(&c.routes, &c.vehicle_journeys, "routes_to_vehicle_journeys")?
+            }
+            RelationKind::PhysicalModesToVehicleJourneys => {
+                self.physical_modes_to_vehicle_journeys = OneToMany::new(
+                    &c.physical_modes,
+                    &c.vehicle_journeys,
+                    "physical_modes_to_vehicle_journeys",
+                )?
+            }
+            RelationKind::StopAreasToStopPoints => {
+                self.stop_areas_to_stop_points =
+                    OneToMany::new(&c.stop_areas, &c.stop_points, "stop_areas_to_stop_points")?
+            }
+            RelationKind::ContributorsToDatasets => {
+                self.contributors_to_datasets =
+                    OneToMany::new(&c.contributors, &c.datasets, "contributors_to_datasets")?
+            }
+            RelationKind::DatasetsToVehicleJourneys => {
+                self.datasets_to_vehicle_journeys = OneToMany::new(
+                    &c.datasets,
+                    &c.vehicle_journeys,
+                    "datasets_to_vehicle_journeys",
+                )?
+            }
+            RelationKind::CompaniesToVehicleJourneys => {
+                self.companies_to_vehicle_journeys = OneToMany::new(
+                    &c.companies,
+                    &c.vehicle_journeys,
+                    "companies_to_vehicle_journeys",
+                )?
+            }
+            RelationKind::VehicleJourneysToStopPoints => {
+                let forward_vj_to_sp = c
+                    .vehicle_journeys
+                    .iter()
+                    .map(|(idx, vj)| {
+                        let sps = vj.stop_times.iter().map(|st| st.stop_point_idx).collect();
+                        (idx, sps)
+                    })
+                    .collect();
+                self.vehicle_journeys_to_stop_points = ManyToMany::from_forward(forward_vj_to_sp)
+            }
+            RelationKind::TransfersToStopPoints => {
+                let forward_tr_to_sp = c
+                    .transfers
+                    .iter()
+                    .map(|(idx, tr)| {
+                        let mut stop_points = IdxSet::default();
+                        stop_points.insert(c.stop_points.get_idx(&tr.from_stop_id).ok_or_else(
+                            || anyhow!("Invalid id: transfer.from_stop_id={:?}", tr.from_stop_id),
+                        )?);
+                        stop_points.insert(c.stop_points.get_idx(&tr.to_stop_id).ok_or_else(
+                            || anyhow!("Invalid id: transfer.to_stop_id={:?}", tr.to_stop_id),
+                        )?);
+                        Ok((idx, stop_points))
+                    })
+                    .collect::<Result<BTreeMap<_, _>, Error>>()?;
+                self.transfers_to_stop_points = ManyToMany::from_forward(forward_tr_to_sp)
+            }
+            RelationKind::CalendarsToVehicleJourneys => {
+                self.calendars_to_vehicle_journeys = OneToMany::new(
+                    &c.calendars,
+                    &c.vehicle_journeys,
+                    "calendars_to_vehicle_journeys",
+                )?
+            }
+            RelationKind::RoutesToStopPoints => {
+                self.routes_to_stop_points = ManyToMany::from_relations_chain(
+                    &self.routes_to_vehicle_journeys,
+                    &self.vehicle_journeys_to_stop_points,
+                )
+            }
+            RelationKind::PhysicalModesToStopPoints => {
+                self.physical_modes_to_stop_points = ManyToMany::from_relations_chain(
+                    &self.physical_modes_to_vehicle_journeys,
+                    &self.vehicle_journeys_to_stop_points,
+                )
+            }
+            RelationKind::PhysicalModesToRoutes => {
+                self.physical_modes_to_routes = ManyToMany::from_relations_sink(
+                    &self.physical_modes_to_vehicle_journeys,
+                    &self.routes_to_vehicle_journeys,
+                )
+            }
+            RelationKind::DatasetsToStopPoints => {
+                self.datasets_to_stop_points = ManyToMany::from_relations_chain(
+                    &self.datasets_to_vehicle_journeys,
+                    &self.vehicle_journeys_to_stop_points,
+                )
+            }
+            RelationKind::DatasetsToRoutes => {
+                self.datasets_to_routes = ManyToMany::from_relations_sink(
+                    &self.datasets_to_vehicle_journeys,
+                    &self.routes_to_vehicle_journeys,
+                )
+            }
+            RelationKind::DatasetsToPhysicalModes => {
+                self.datasets_to_physical_modes = ManyToMany::from_relations_sink(
+                    &self.datasets_to_vehicle_journeys,
+                    &self.physical_modes_to_vehicle_journeys,
+                )
+            }
+        }
+        Ok(())
+    }
+
     /// Consumes collections,
     ///
     /// # Examples
@@ -1407,7 +2395,303 @@ impl Model {
     pub fn into_collections(self) -> Collections {
         self.collections
     }
+
+    /// The vehicle journeys of the same block as `vehicle_journey_idx` (see
+    /// `block_id`) that depart before and after it from their first stop,
+    /// ordered by departure time.
+    ///
+    /// Only the vehicle journeys running at least one day in common with
+    /// `vehicle_journey_idx` are returned, the other ones can't be linked to
+    /// it by a stay-in.
+    ///
+    /// ```
+    /// # use transit_model_builder::ModelBuilder;
+    /// let model = ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.block_id("block").st("SP1", "10:00:00", "10:01:00");
+    ///     })
+    ///     .vj("vj2", |vj| {
+    ///         vj.block_id("block").st("SP2", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build();
+    /// let vj1_idx = model.vehicle_journeys.get_idx("vj1").unwrap();
+    /// let vj2_idx = model.vehicle_journeys.get_idx("vj2").unwrap();
+    /// let (previous, next) = model.block_neighbours(vj2_idx);
+    /// assert_eq!(vec![vj1_idx], previous);
+    /// assert!(next.is_empty());
+    /// ```
+    pub fn block_neighbours(
+        &self,
+        vehicle_journey_idx: Idx<VehicleJourney>,
+    ) -> (Vec<Idx<VehicleJourney>>, Vec<Idx<VehicleJourney>>) {
+        let first_departure = |vj: &VehicleJourney| {
+            vj.stop_times
+                .first()
+                .map(|stop_time| stop_time.departure_time)
+        };
+        let vehicle_journey = &self.vehicle_journeys[vehicle_journey_idx];
+        let (block_id, departure) =
+            match (&vehicle_journey.block_id, first_departure(vehicle_journey)) {
+                (Some(block_id), Some(departure)) => (block_id, departure),
+                _ => return (vec![], vec![]),
+            };
+        let calendar = self.calendars.get(&vehicle_journey.service_id);
+        let mut neighbours: Vec<(Time, Idx<VehicleJourney>)> = self
+            .vehicle_journeys
+            .iter()
+            .filter(|(idx, vj)| {
+                *idx != vehicle_journey_idx && vj.block_id.as_ref() == Some(block_id)
+            })
+            .filter(
+                |(_, vj)| match (calendar, self.calendars.get(&vj.service_id)) {
+                    (Some(calendar), Some(other)) => calendar.overlaps(other),
+                    _ => false,
+                },
+            )
+            .filter_map(|(idx, vj)| first_departure(vj).map(|departure| (departure, idx)))
+            .collect();
+        neighbours.sort_unstable();
+        let previous = neighbours
+            .iter()
+            .filter(|(other, _)| *other < departure)
+            .map(|(_, idx)| *idx)
+            .collect();
+        let next = neighbours
+            .iter()
+            .filter(|(other, _)| *other > departure)
+            .map(|(_, idx)| *idx)
+            .collect();
+        (previous, next)
+    }
+
+    /// Updates the collections with `update` and rebuilds the model, like
+    /// `Model::new(collections)` would do, but only recomputing the relations
+    /// depending on the `changed` collections and running again the
+    /// enhancers reading them.
+    ///
+    /// A full rebuild is done when a collection other than the calendars
+    /// changed, as it may need the sanitization and the enhancers of
+    /// `Model::new`, or when a vehicle journey lost its calendar (emptied
+    /// calendars being removed), the removal cascading to the other
+    /// collections.
+    ///
+    /// ```
+    /// # use transit_model::model::*;
+    /// # fn run() -> transit_model::Result<()> {
+    /// let model: Model = Model::new(Collections::default())?;
+    /// let model = model.rebuild_partial(&[CollectionKind::Calendars], |collections| {
+    ///     // Remove a strike day
+    ///     if let Some(mut calendar) = collections.calendars.get_mut("weekdays") {
+    ///         calendar.dates.remove(&chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # run().unwrap()
+    /// ```
+    pub fn rebuild_partial<F>(mut self, changed: &[CollectionKind], update: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Collections) -> Result<()>,
+    {
+        update(&mut self.collections)?;
+        let enhancers: Vec<fn(&mut Collections)> = match changed
+            .iter()
+            .map(|kind| kind.enhancers())
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(enhancers) => enhancers.concat(),
+            None => return Model::new(self.collections),
+        };
+        let c = &mut self.collections;
+        if changed.contains(&CollectionKind::Calendars) {
+            c.calendars.retain(|calendar| !calendar.is_empty());
+            if !c
+                .vehicle_journeys
+                .values()
+                .all(|vj| c.calendars.contains_id(&vj.service_id))
+            {
+                return Model::new(self.collections);
+            }
+            // Same as `Collections::sanitize`, the unused calendars are
+            // removed
+            let calendars_used: HashSet<&str> = c
+                .vehicle_journeys
+                .values()
+                .map(|vj| vj.service_id.as_str())
+                .collect();
+            c.calendars
+                .retain(|calendar| calendars_used.contains(calendar.id.as_str()));
+        }
+        for enhancer in enhancers {
+            enhancer(c);
+        }
+
+        let relations: BTreeSet<RelationKind> = RelationKind::ALL
+            .iter()
+            .copied()
+            .filter(|relation| {
+                relation
+                    .dependencies()
+                    .iter()
+                    .any(|kind| changed.contains(kind))
+            })
+            .collect();
+        for relation in relations {
+            self.build_relation(relation)?;
+        }
+        Ok(self)
+    }
+
+    #[cfg(test)]
+    fn relations_fingerprint(&self) -> Vec<String> {
+        vec![
+            format!("{:?}", self.networks_to_lines),
+            format!("{:?}", self.commercial_modes_to_lines),
+            format!("{:?}", self.lines_to_routes),
+            format!("{:?}", self.routes_to_vehicle_journeys),
+            format!("{:?}", self.physical_modes_to_vehicle_journeys),
+            format!("{:?}", self.stop_areas_to_stop_points),
+            format!("{:?}", self.contributors_to_datasets),
+            format!("{:?}", self.datasets_to_vehicle_journeys),
+            format!("{:?}", self.companies_to_vehicle_journeys),
+            format!("{:?}", self.vehicle_journeys_to_stop_points),
+            format!("{:?}", self.transfers_to_stop_points),
+            format!("{:?}", self.calendars_to_vehicle_journeys),
+            format!("{:?}", self.routes_to_stop_points),
+            format!("{:?}", self.physical_modes_to_stop_points),
+            format!("{:?}", self.physical_modes_to_routes),
+            format!("{:?}", self.datasets_to_stop_points),
+            format!("{:?}", self.datasets_to_routes),
+            format!("{:?}", self.datasets_to_physical_modes),
+        ]
+    }
+}
+
+/// The collections of a [Model], to tell which ones changed when rebuilding
+/// it with [Model::rebuild_partial]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollectionKind {
+    /// `Collections::contributors`
+    Contributors,
+    /// `Collections::datasets`
+    Datasets,
+    /// `Collections::networks`
+    Networks,
+    /// `Collections::commercial_modes`
+    CommercialModes,
+    /// `Collections::lines`
+    Lines,
+    /// `Collections::routes`
+    Routes,
+    /// `Collections::vehicle_journeys`
+    VehicleJourneys,
+    /// `Collections::physical_modes`
+    PhysicalModes,
+    /// `Collections::stop_areas`
+    StopAreas,
+    /// `Collections::stop_points`
+    StopPoints,
+    /// `Collections::companies`
+    Companies,
+    /// `Collections::transfers`
+    Transfers,
+    /// `Collections::calendars`
+    Calendars,
+    /// Any other collection, no relation depending on it
+    Other,
+}
+
+impl CollectionKind {
+    // The enhancers of `Model::new` reading the collection, to run again
+    // when it changes, or `None` when a change of the collection may need
+    // the sanitization, the enhancers and the reference checks of a full
+    // rebuild
+    fn enhancers(self) -> Option<&'static [fn(&mut Collections)]> {
+        match self {
+            // The stay-ins between the vehicle journeys of a block need a
+            // date in common
+            CollectionKind::Calendars => Some(&[enhancers::enhance_pickup_dropoff]),
+            _ => None,
+        }
+    }
 }
+
+// The relations of a [Model], the shortcuts coming after the relations they
+// are built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RelationKind {
+    NetworksToLines,
+    CommercialModesToLines,
+    LinesToRoutes,
+    RoutesToVehicleJourneys,
+    PhysicalModesToVehicleJourneys,
+    StopAreasToStopPoints,
+    ContributorsToDatasets,
+    DatasetsToVehicleJourneys,
+    CompaniesToVehicleJourneys,
+    VehicleJourneysToStopPoints,
+    TransfersToStopPoints,
+    CalendarsToVehicleJourneys,
+    RoutesToStopPoints,
+    PhysicalModesToStopPoints,
+    PhysicalModesToRoutes,
+    DatasetsToStopPoints,
+    DatasetsToRoutes,
+    DatasetsToPhysicalModes,
+}
+
+impl RelationKind {
+    const ALL: [RelationKind; 18] = [
+        RelationKind::NetworksToLines,
+        RelationKind::CommercialModesToLines,
+        RelationKind::LinesToRoutes,
+        RelationKind::RoutesToVehicleJourneys,
+        RelationKind::PhysicalModesToVehicleJourneys,
+        RelationKind::StopAreasToStopPoints,
+        RelationKind::ContributorsToDatasets,
+        RelationKind::DatasetsToVehicleJourneys,
+        RelationKind::CompaniesToVehicleJourneys,
+        RelationKind::VehicleJourneysToStopPoints,
+        RelationKind::TransfersToStopPoints,
+        RelationKind::CalendarsToVehicleJourneys,
+        RelationKind::RoutesToStopPoints,
+        RelationKind::PhysicalModesToStopPoints,
+        RelationKind::PhysicalModesToRoutes,
+        RelationKind::DatasetsToStopPoints,
+        RelationKind::DatasetsToRoutes,
+        RelationKind::DatasetsToPhysicalModes,
+    ];
+
+    // The collections the relation is built from, directly or through the
+    // relations of a shortcut
+    fn dependencies(self) -> &'static [CollectionKind] {
+        use CollectionKind::*;
+        match self {
+            RelationKind::NetworksToLines => &[Networks, Lines],
+            RelationKind::CommercialModesToLines => &[CommercialModes, Lines],
+            RelationKind::LinesToRoutes => &[Lines, Routes],
+            RelationKind::RoutesToVehicleJourneys => &[Routes, VehicleJourneys],
+            RelationKind::PhysicalModesToVehicleJourneys => &[PhysicalModes, VehicleJourneys],
+            RelationKind::StopAreasToStopPoints => &[StopAreas, StopPoints],
+            RelationKind::ContributorsToDatasets => &[Contributors, Datasets],
+            RelationKind::DatasetsToVehicleJourneys => &[Datasets, VehicleJourneys],
+            RelationKind::CompaniesToVehicleJourneys => &[Companies, VehicleJourneys],
+            RelationKind::VehicleJourneysToStopPoints => &[VehicleJourneys, StopPoints],
+            RelationKind::TransfersToStopPoints => &[Transfers, StopPoints],
+            RelationKind::CalendarsToVehicleJourneys => &[Calendars, VehicleJourneys],
+            RelationKind::RoutesToStopPoints => &[Routes, VehicleJourneys, StopPoints],
+            RelationKind::PhysicalModesToStopPoints => {
+                &[PhysicalModes, VehicleJourneys, StopPoints]
+            }
+            RelationKind::PhysicalModesToRoutes => &[PhysicalModes, VehicleJourneys, Routes],
+            RelationKind::DatasetsToStopPoints => &[Datasets, VehicleJourneys, StopPoints],
+            RelationKind::DatasetsToRoutes => &[Datasets, VehicleJourneys, Routes],
+            RelationKind::DatasetsToPhysicalModes => &[Datasets, VehicleJourneys, PhysicalModes],
+        }
+    }
+}
+
 #[cfg(feature = "mutable-model")]
 impl Model {
     /// Add a Calendar inside the model
@@ -1542,6 +2826,10 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: Some(0),
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             };
             collections
                 .vehicle_journeys
@@ -1670,6 +2958,171 @@ mod tests {
         }
     }
 
+    mod update_validity_period_from_calendars {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn collections(vjs: &[(&str, &str)]) -> Collections {
+            let mut collections = Collections::default();
+            for (id, day) in &[("service_1", 1), ("service_2", 5), ("service_3", 9)] {
+                let mut calendar = Calendar::new(id.to_string());
+                calendar.dates.insert(NaiveDate::from_ymd(2020, 1, *day));
+                calendar
+                    .dates
+                    .insert(NaiveDate::from_ymd(2020, 1, *day + 1));
+                collections.calendars.push(calendar).unwrap();
+            }
+            for dataset_id in &["dataset_1", "dataset_2", "dataset_3"] {
+                collections
+                    .datasets
+                    .push(Dataset::new(
+                        dataset_id.to_string(),
+                        "contributor".to_string(),
+                    ))
+                    .unwrap();
+            }
+            for (index, (dataset_id, service_id)) in vjs.iter().enumerate() {
+                collections
+                    .vehicle_journeys
+                    .push(VehicleJourney {
+                        id: format!("vj_{}", index),
+                        dataset_id: dataset_id.to_string(),
+                        service_id: service_id.to_string(),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            collections
+        }
+
+        fn period(collections: &Collections, dataset_id: &str) -> (Date, Date) {
+            let dataset = collections.datasets.get(dataset_id).unwrap();
+            (dataset.start_date, dataset.end_date)
+        }
+
+        #[test]
+        fn period_by_dataset() {
+            let mut collections = collections(&[
+                ("dataset_1", "service_1"),
+                ("dataset_1", "service_2"),
+                ("dataset_2", "service_3"),
+            ]);
+            collections.update_validity_period_from_calendars();
+            assert_eq!(
+                (
+                    NaiveDate::from_ymd(2020, 1, 1),
+                    NaiveDate::from_ymd(2020, 1, 6)
+                ),
+                period(&collections, "dataset_1")
+            );
+            assert_eq!(
+                (
+                    NaiveDate::from_ymd(2020, 1, 9),
+                    NaiveDate::from_ymd(2020, 1, 10)
+                ),
+                period(&collections, "dataset_2")
+            );
+            // no vehicle journey, no period
+            assert!(!collections.datasets.contains_id("dataset_3"));
+        }
+
+        #[test]
+        fn global_period_when_dataset_is_unknown() {
+            let mut collections =
+                collections(&[("dataset_1", "service_2"), ("unknown", "service_2")]);
+            collections.update_validity_period_from_calendars();
+            for dataset_id in &["dataset_1", "dataset_2", "dataset_3"] {
+                assert_eq!(
+                    (
+                        NaiveDate::from_ymd(2020, 1, 1),
+                        NaiveDate::from_ymd(2020, 1, 10)
+                    ),
+                    period(&collections, dataset_id)
+                );
+            }
+        }
+
+        #[test]
+        fn remove_unused_calendars() {
+            let mut collections =
+                collections(&[("dataset_1", "service_1"), ("dataset_1", "service_2")]);
+            assert_eq!(1, collections.remove_unused_calendars());
+            assert_eq!(
+                vec!["service_1", "service_2"],
+                collections
+                    .calendars
+                    .values()
+                    .map(|calendar| calendar.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                (
+                    NaiveDate::from_ymd(2020, 1, 1),
+                    NaiveDate::from_ymd(2020, 1, 6)
+                ),
+                period(&collections, "dataset_1")
+            );
+            assert_eq!(0, collections.remove_unused_calendars());
+        }
+
+        #[test]
+        fn period_with_times_beyond_the_service_day() {
+            let mut collections =
+                collections(&[("dataset_1", "service_1"), ("dataset_2", "service_3")]);
+            let stop_point_idx = collections
+                .stop_points
+                .push(StopPoint {
+                    id: String::from("stop_point_id"),
+                    ..Default::default()
+                })
+                .unwrap();
+            let stop_time = |time: Time| StopTime {
+                stop_point_idx,
+                sequence: 0,
+                arrival_time: time,
+                departure_time: time,
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
+            };
+            collections
+                .vehicle_journeys
+                .get_mut("vj_0")
+                .unwrap()
+                .stop_times = vec![
+                stop_time(Time::new(23, 50, 0)),
+                stop_time(Time::new(25, 10, 0)),
+            ];
+            collections
+                .vehicle_journeys
+                .get_mut("vj_1")
+                .unwrap()
+                .stop_times = vec![stop_time(Time::new(49, 30, 0))];
+            collections.update_validity_period_from_calendars();
+            assert_eq!(
+                (
+                    NaiveDate::from_ymd(2020, 1, 1),
+                    NaiveDate::from_ymd(2020, 1, 3)
+                ),
+                period(&collections, "dataset_1")
+            );
+            assert_eq!(
+                (
+                    NaiveDate::from_ymd(2020, 1, 9),
+                    NaiveDate::from_ymd(2020, 1, 12)
+                ),
+                period(&collections, "dataset_2")
+            );
+        }
+    }
+
     mod clean_comments {
         use super::*;
         use pretty_assertions::assert_eq;
@@ -1860,6 +3313,10 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             };
             let stop_times: Vec<_> = stop_point_ids.into_iter().map(stop_time_at).collect();
             VehicleJourney {
@@ -2253,6 +3710,502 @@ mod tests {
         }
     }
 
+    mod block_neighbours {
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn same_block_on_a_common_day() {
+            let model = transit_model_builder::ModelBuilder::default()
+                .calendar("c1", &["2020-01-01", "2020-01-02"])
+                .calendar("c2", &["2020-01-02"])
+                .calendar("c3", &["2020-01-03"])
+                .vj("vj1", |vj| {
+                    vj.block_id("b1")
+                        .calendar("c1")
+                        .st("SP1", "10:00:00", "10:01:00")
+                        .st("SP2", "11:00:00", "11:01:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.block_id("b1")
+                        .calendar("c2")
+                        .st("SP2", "08:00:00", "08:01:00")
+                        .st("SP1", "09:00:00", "09:01:00");
+                })
+                .vj("vj3", |vj| {
+                    vj.block_id("b1")
+                        .calendar("c3")
+                        .st("SP2", "12:00:00", "12:01:00");
+                })
+                .vj("vj4", |vj| {
+                    vj.block_id("b2")
+                        .calendar("c1")
+                        .st("SP2", "12:00:00", "12:01:00");
+                })
+                .vj("vj5", |vj| {
+                    vj.block_id("b1")
+                        .calendar("c1")
+                        .st("SP2", "12:00:00", "12:01:00");
+                })
+                .build();
+            let idx = |id: &str| model.vehicle_journeys.get_idx(id).unwrap();
+
+            let (previous, next) = model.block_neighbours(idx("vj1"));
+            assert_eq!(vec![idx("vj2")], previous);
+            assert_eq!(vec![idx("vj5")], next);
+            let (previous, next) = model.block_neighbours(idx("vj3"));
+            assert!(previous.is_empty());
+            assert!(next.is_empty());
+        }
+    }
+
+    mod check_references {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn references(collections: &Collections) -> Vec<(&str, Option<String>, &str, String)> {
+            collections
+                .check_references()
+                .into_iter()
+                .map(|r| (r.object_type, r.object_id, r.field, r.referenced_id))
+                .collect()
+        }
+
+        fn collections() -> Collections {
+            let mut collections = Collections {
+                stop_points: CollectionWithId::from(StopPoint {
+                    id: "sp1".into(),
+                    stop_area_id: "unknown_stop_area".into(),
+                    ..Default::default()
+                }),
+                calendars: CollectionWithId::from(Calendar {
+                    id: "c1".into(),
+                    dates: vec![NaiveDate::from_ymd(2020, 1, 1)].into_iter().collect(),
+                }),
+                ..Default::default()
+            };
+            collections.vehicle_journeys = CollectionWithId::from(VehicleJourney {
+                id: "vj1".into(),
+                route_id: "unknown_route".into(),
+                physical_mode_id: "unknown_mode".into(),
+                dataset_id: "unknown_dataset".into(),
+                service_id: "c1".into(),
+                company_id: "unknown_company".into(),
+                stop_times: vec![StopTime {
+                    stop_point_idx: collections.stop_points.get_idx("sp1").unwrap(),
+                    sequence: 0,
+                    arrival_time: Time::new(10, 0, 0),
+                    departure_time: Time::new(10, 0, 0),
+                    boarding_duration: 0,
+                    alighting_duration: 0,
+                    pickup_type: 0,
+                    drop_off_type: 0,
+                    local_zone_id: None,
+                    precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
+                }],
+                ..Default::default()
+            });
+            collections
+        }
+
+        #[test]
+        fn list_all_dangling_references() {
+            let mut collections = collections();
+            collections
+                .vehicle_journeys
+                .get_mut("vj1")
+                .unwrap()
+                .comment_links
+                .insert("unknown_comment".into());
+            collections.transfers.push(Transfer {
+                from_stop_id: "sp1".into(),
+                to_stop_id: "unknown_stop_point".into(),
+                min_transfer_time: None,
+                real_min_transfer_time: None,
+                equipment_id: None,
+                transfer_type: TransferType::Possible,
+            });
+            collections.grid_periods.push(GridPeriod {
+                grid_calendar_id: "unknown_grid_calendar".into(),
+                start_date: NaiveDate::from_ymd(2020, 1, 1),
+                end_date: NaiveDate::from_ymd(2020, 1, 31),
+            });
+            let trip = |field, id: &str| ("trip", Some("vj1".to_string()), field, id.to_string());
+            assert_eq!(
+                vec![
+                    trip("route_id", "unknown_route"),
+                    trip("physical_mode_id", "unknown_mode"),
+                    trip("dataset_id", "unknown_dataset"),
+                    trip("company_id", "unknown_company"),
+                    trip("comment_id", "unknown_comment"),
+                    (
+                        "stop_point",
+                        Some("sp1".to_string()),
+                        "stop_area_id",
+                        "unknown_stop_area".to_string()
+                    ),
+                    (
+                        "transfer",
+                        None,
+                        "to_stop_id",
+                        "unknown_stop_point".to_string()
+                    ),
+                    (
+                        "grid_period",
+                        None,
+                        "grid_calendar_id",
+                        "unknown_grid_calendar".to_string()
+                    ),
+                ],
+                references(&collections)
+            );
+        }
+
+        #[test]
+        fn brandings_references() {
+            let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+                .unwrap()
+                .into_collections();
+            collections.brandings = CollectionWithId::new(vec![
+                Branding {
+                    id: "used".into(),
+                    name: "Used".into(),
+                    ..Default::default()
+                },
+                Branding {
+                    id: "unused".into(),
+                    name: "Unused".into(),
+                    ..Default::default()
+                },
+            ])
+            .unwrap();
+            collections.networks.get_mut("TGN").unwrap().branding_id = Some("used".into());
+            collections.lines.get_mut("M1").unwrap().branding_id = Some("unknown".into());
+            assert_eq!(
+                vec![(
+                    "line",
+                    Some("M1".to_string()),
+                    "branding_id",
+                    "unknown".to_string()
+                )],
+                references(&collections)
+            );
+
+            collections.sanitize().unwrap();
+            let brandings: Vec<_> = collections.brandings.values().map(|b| &b.id).collect();
+            assert_eq!(vec!["used"], brandings);
+        }
+
+        #[test]
+        fn model_error_lists_all_dangling_references() {
+            let error = match Model::new(collections()) {
+                Ok(_) => panic!("the model should not be built"),
+                Err(error) => error.to_string(),
+            };
+            assert_eq!(
+                "5 dangling references:\n\
+                 trip \"vj1\": route_id \"unknown_route\" not found\n\
+                 trip \"vj1\": physical_mode_id \"unknown_mode\" not found\n\
+                 trip \"vj1\": dataset_id \"unknown_dataset\" not found\n\
+                 trip \"vj1\": company_id \"unknown_company\" not found\n\
+                 stop_point \"sp1\": stop_area_id \"unknown_stop_area\" not found",
+                error
+            );
+        }
+    }
+
+    mod line_groups {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn sanitize_cascades_the_removed_lines() {
+            let mut collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+                .unwrap()
+                .into_collections();
+            for (id, main_line_id) in [("group:M1", "M1"), ("group:B42", "B42")] {
+                collections
+                    .line_groups
+                    .push(LineGroup {
+                        id: id.to_string(),
+                        name: id.to_string(),
+                        main_line_id: main_line_id.to_string(),
+                        comment_links: CommentLinksT::default(),
+                    })
+                    .unwrap();
+            }
+            for (line_group_id, line_id) in [
+                ("group:M1", "M1"),
+                ("group:M1", "B42"),
+                ("group:B42", "B42"),
+            ] {
+                collections.line_group_links.push(LineGroupLink {
+                    line_group_id: line_group_id.to_string(),
+                    line_id: line_id.to_string(),
+                });
+            }
+            collections
+                .vehicle_journeys
+                .retain(|vj| !vj.route_id.starts_with("B42"));
+
+            let model = Model::new(collections).unwrap();
+            assert!(!model.lines.contains_id("B42"));
+            assert_eq!(
+                vec!["group:M1"],
+                model
+                    .line_groups
+                    .values()
+                    .map(|lg| lg.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![LineGroupLink {
+                    line_group_id: "group:M1".to_string(),
+                    line_id: "M1".to_string(),
+                }],
+                model.line_group_links.values().cloned().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    mod rebuild_partial {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn stop_time(stop_point_idx: Idx<StopPoint>, sequence: u32, time: Time) -> StopTime {
+            StopTime {
+                stop_point_idx,
+                sequence,
+                arrival_time: time,
+                departure_time: time,
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
+            }
+        }
+
+        // 'vj:1' and 'vj:2' share a block, the stay-in between them being
+        // possible on 2020-01-06 only, the date of 'weekdays' in 'mondays'.
+        // 'vj:3' runs on the weekends.
+        fn collections() -> Collections {
+            let mut collections = Collections::default();
+            for (id, dates) in [
+                ("weekdays", vec![(2020, 1, 2), (2020, 1, 3), (2020, 1, 6)]),
+                ("mondays", vec![(2020, 1, 6)]),
+                ("weekends", vec![(2020, 1, 4), (2020, 1, 5)]),
+            ] {
+                collections
+                    .calendars
+                    .push(Calendar {
+                        id: id.to_string(),
+                        dates: dates
+                            .into_iter()
+                            .map(|(y, m, d)| Date::from_ymd_opt(y, m, d).unwrap())
+                            .collect(),
+                    })
+                    .unwrap();
+            }
+            let sp_idxs: Vec<Idx<StopPoint>> = ["sp:1", "sp:2", "sp:3"]
+                .iter()
+                .map(|id| {
+                    collections
+                        .stop_points
+                        .push(StopPoint {
+                            id: id.to_string(),
+                            stop_area_id: "sa:1".to_string(),
+                            ..Default::default()
+                        })
+                        .unwrap()
+                })
+                .collect();
+            collections.stop_areas.get_or_create("sa:1");
+            // The default objects referenced by the default vehicle journey
+            collections.routes.get_or_create("default_route");
+            collections.lines.get_or_create("default_line");
+            collections.networks.get_or_create("default_network");
+            collections
+                .commercial_modes
+                .get_or_create("default_commercial_mode");
+            collections
+                .physical_modes
+                .get_or_create("default_physical_mode");
+            collections.datasets.get_or_create("default_dataset");
+            collections
+                .contributors
+                .get_or_create("default_contributor");
+            collections.companies.get_or_create("default_company");
+            for (id, service_id, block_id, stop_times) in [
+                (
+                    "vj:1",
+                    "weekdays",
+                    Some("block:1"),
+                    vec![(0, Time::new(8, 0, 0)), (1, Time::new(9, 0, 0))],
+                ),
+                (
+                    "vj:2",
+                    "mondays",
+                    Some("block:1"),
+                    vec![(2, Time::new(10, 0, 0)), (0, Time::new(11, 0, 0))],
+                ),
+                (
+                    "vj:3",
+                    "weekends",
+                    None,
+                    vec![(0, Time::new(8, 0, 0)), (1, Time::new(9, 0, 0))],
+                ),
+            ] {
+                collections
+                    .vehicle_journeys
+                    .push(VehicleJourney {
+                        id: id.to_string(),
+                        service_id: service_id.to_string(),
+                        block_id: block_id.map(str::to_string),
+                        stop_times: stop_times
+                            .into_iter()
+                            .enumerate()
+                            .map(|(sequence, (sp, time))| {
+                                stop_time(sp_idxs[sp], sequence as u32, time)
+                            })
+                            .collect(),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            collections
+        }
+
+        // Rebuilds the model partially, checking that it gives the same
+        // result as a full rebuild
+        fn rebuild_partial(update: fn(&mut Collections) -> Result<()>) -> Model {
+            let model = Model::new(collections())
+                .unwrap()
+                .rebuild_partial(&[CollectionKind::Calendars], update)
+                .unwrap();
+            let mut collections = Model::new(collections()).unwrap().into_collections();
+            update(&mut collections).unwrap();
+            let expected = Model::new(collections).unwrap();
+            debug_assert_eq!(
+                serde_json::to_value(&expected).unwrap(),
+                serde_json::to_value(&model).unwrap()
+            );
+            debug_assert_eq!(
+                expected.relations_fingerprint(),
+                model.relations_fingerprint()
+            );
+            model
+        }
+
+        // The pickup type of the last stop time and the drop off type of the
+        // first one of the vehicle journey
+        fn stay_in_types(model: &Model, vj_id: &str) -> (u8, u8) {
+            let stop_times = &model.vehicle_journeys.get(vj_id).unwrap().stop_times;
+            (
+                stop_times.last().unwrap().pickup_type,
+                stop_times.first().unwrap().drop_off_type,
+            )
+        }
+
+        #[test]
+        fn calendars_only() {
+            let model = rebuild_partial(|collections| {
+                collections
+                    .calendars
+                    .get_mut("weekends")
+                    .unwrap()
+                    .dates
+                    .remove(&Date::from_ymd_opt(2020, 1, 5).unwrap());
+                collections.calendars.push(Calendar {
+                    id: "unused".to_string(),
+                    dates: vec![Date::from_ymd_opt(2020, 1, 1).unwrap()]
+                        .into_iter()
+                        .collect(),
+                })?;
+                Ok(())
+            });
+            assert!(!model.calendars.contains_id("unused"));
+            let weekends_idx = model.calendars.get_idx("weekends").unwrap();
+            assert_eq!(1, model.calendars[weekends_idx].dates.len());
+            let vjs: IdxSet<VehicleJourney> = model.get_corresponding_from_idx(weekends_idx);
+            assert_eq!(
+                vec!["vj:3"],
+                vjs.iter()
+                    .map(|idx| model.vehicle_journeys[*idx].id.as_str())
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn calendars_of_a_block() {
+            let model = Model::new(collections()).unwrap();
+            // The stay-in is possible on 2020-01-06
+            assert_eq!(0, stay_in_types(&model, "vj:1").0);
+            assert_eq!(0, stay_in_types(&model, "vj:2").1);
+            let model = rebuild_partial(|collections| {
+                collections
+                    .calendars
+                    .get_mut("weekdays")
+                    .unwrap()
+                    .dates
+                    .remove(&Date::from_ymd_opt(2020, 1, 6).unwrap());
+                Ok(())
+            });
+            assert_eq!(1, stay_in_types(&model, "vj:1").0);
+            assert_eq!(1, stay_in_types(&model, "vj:2").1);
+        }
+
+        #[test]
+        fn emptied_calendar_removes_its_vehicle_journeys() {
+            let model = rebuild_partial(|collections| {
+                collections
+                    .calendars
+                    .get_mut("weekends")
+                    .unwrap()
+                    .dates
+                    .clear();
+                Ok(())
+            });
+            assert!(!model.calendars.contains_id("weekends"));
+            assert!(!model.vehicle_journeys.contains_id("vj:3"));
+            assert_eq!(2, model.vehicle_journeys.len());
+        }
+    }
+
+    mod stop_time_values {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn looked_up_by_str() {
+            let mut collections = Collections::default();
+            let vj_id = IdString::from("vj:1");
+            collections
+                .stop_time_headsigns
+                .insert((vj_id.clone(), 1), IdString::from("Terminus"));
+            collections
+                .stop_time_ids
+                .insert((vj_id.clone(), 1), IdString::from("st:1"));
+            collections
+                .stop_time_comments
+                .insert((vj_id, 2), IdString::from("comment:1"));
+
+            assert_eq!(Some("Terminus"), collections.stop_time_headsign("vj:1", 1));
+            assert_eq!(Some("st:1"), collections.stop_time_id("vj:1", 1));
+            assert_eq!(Some("comment:1"), collections.stop_time_comment("vj:1", 2));
+            assert_eq!(None, collections.stop_time_comment("vj:1", 1));
+            assert_eq!(None, collections.stop_time_id("vj:2", 1));
+        }
+    }
+
     mod check_coord_integrity {
         use crate::objects::Coord;
 
@@ -0,0 +1,90 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use crate::{
+    netex_france::exporter::{Exporter, ObjectType},
+    objects::Branding,
+    Model,
+};
+use minidom::{Element, Node};
+
+pub struct BrandingExporter<'a> {
+    model: &'a Model,
+}
+
+// Publicly exposed methods
+impl<'a> BrandingExporter<'a> {
+    pub fn new(model: &'a Model) -> Self {
+        BrandingExporter { model }
+    }
+    pub fn export(&self) -> Vec<Element> {
+        self.model
+            .brandings
+            .values()
+            .map(|branding| self.export_branding(branding))
+            .collect()
+    }
+    pub fn generate_branding_ref(branding_id: &'a str) -> Element {
+        let branding_id = Exporter::generate_id(branding_id, ObjectType::Branding);
+        Element::builder("BrandingRef")
+            .attr("ref", branding_id)
+            .build()
+    }
+}
+
+// Internal methods
+impl<'a> BrandingExporter<'a> {
+    fn export_branding(&self, branding: &'a Branding) -> Element {
+        let element_builder = Element::builder(ObjectType::Branding.to_string())
+            .attr(
+                "id",
+                Exporter::generate_id(&branding.id, ObjectType::Branding),
+            )
+            .attr("version", "any");
+        let element_builder = element_builder.append(self.generate_name(branding));
+        let element_builder = if let Some(url_element) = self.generate_url(branding) {
+            element_builder.append(url_element)
+        } else {
+            element_builder
+        };
+        let element_builder = if let Some(image_element) = self.generate_image(branding) {
+            element_builder.append(image_element)
+        } else {
+            element_builder
+        };
+        element_builder.build()
+    }
+
+    fn generate_name(&self, branding: &'a Branding) -> Element {
+        Element::builder("Name")
+            .append(Node::Text(branding.name.to_owned()))
+            .build()
+    }
+
+    fn generate_url(&self, branding: &'a Branding) -> Option<Element> {
+        branding.url.as_ref().map(|url| {
+            Element::builder("Url")
+                .append(Node::Text(url.to_owned()))
+                .build()
+        })
+    }
+
+    fn generate_image(&self, branding: &'a Branding) -> Option<Element> {
+        branding.image.as_ref().map(|image| {
+            Element::builder("Image")
+                .append(Node::Text(image.to_owned()))
+                .build()
+        })
+    }
+}
@@ -16,8 +16,8 @@
 use crate::{
     model::Model,
     netex_france::{
-        CalendarExporter, CompanyExporter, LineExporter, NetworkExporter, OfferExporter,
-        StopExporter, TransferExporter,
+        BrandingExporter, CalendarExporter, CompanyExporter, LineExporter, NetworkExporter,
+        OfferExporter, StopExporter, TransferExporter,
     },
     netex_utils::FrameType,
     objects::{Date, Line, Network},
@@ -46,6 +46,7 @@ const NETEX_FRANCE_STOPS_FILENAME: &str = "arrets.xml";
 
 pub(in crate::netex_france) enum ObjectType {
     AccessibilityAssessment,
+    Branding,
     DayType,
     DayTypeAssignment,
     Line,
@@ -72,6 +73,7 @@ impl Display for ObjectType {
         use ObjectType::*;
         match self {
             AccessibilityAssessment => write!(f, "AccessibilityAssessment"),
+            Branding => write!(f, "Branding"),
             DayType => write!(f, "DayType"),
             DayTypeAssignment => write!(f, "DayTypeAssignment"),
             Line => write!(f, "Line"),
@@ -246,10 +248,12 @@ impl Exporter<'_> {
         let network_frames = self.create_networks_frames();
         let lines_frame = self.create_lines_frame()?;
         let companies_frame = self.create_companies_frame();
+        let brandings_frame = self.create_brandings_frame();
         let frames = network_frames
             .into_iter()
             .chain(iter::once(lines_frame))
-            .chain(iter::once(companies_frame));
+            .chain(iter::once(companies_frame))
+            .chain(brandings_frame);
         let composite_frame_id = self.generate_frame_id(
             FrameType::Composite,
             &format!("NETEX_{}", VersionType::Lines),
@@ -310,6 +314,26 @@ impl Exporter<'_> {
             .build()
     }
 
+    // Returns a 'ResourceFrame' containing a list of 'Branding' in
+    // 'typesOfValue', if there are any
+    fn create_brandings_frame(&self) -> Option<Element> {
+        if self.model.brandings.is_empty() {
+            return None;
+        }
+        let branding_exporter = BrandingExporter::new(self.model);
+        let brandings = branding_exporter.export();
+        let brandings_list = Element::builder("typesOfValue")
+            .append_all(brandings)
+            .build();
+        let resource_frame_id = self.generate_frame_id(FrameType::Resource, "brandings");
+        let frame = Element::builder(FrameType::Resource.to_string())
+            .attr("id", resource_frame_id)
+            .attr("version", "any")
+            .append(brandings_list)
+            .build();
+        Some(frame)
+    }
+
     fn write_stops<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
@@ -15,7 +15,7 @@
 use crate::{
     netex_france::{
         exporter::{Exporter, ObjectType},
-        NetexMode,
+        BrandingExporter, NetexMode,
     },
     objects::Line,
     Model, Result,
@@ -84,6 +84,12 @@ impl<'a> LineExporter<'a> {
             .ok_or_else(|| anyhow!("Unable to find modes for Line '{}'", line.id))?;
         let highest_netex_mode = NetexMode::calculate_highest_mode(netex_modes)
             .ok_or_else(|| anyhow!("Unable to resolve main NeTEx mode for Line {}", line.id))?;
+        // 'BrandingRef' comes first, as a property of any 'DataManagedObject'
+        let element_builder = if let Some(branding_id) = &line.branding_id {
+            element_builder.append(BrandingExporter::generate_branding_ref(branding_id))
+        } else {
+            element_builder
+        };
         let element_builder = element_builder
             .append(self.generate_name(line))
             .append(self.generate_transport_mode(highest_netex_mode));
@@ -14,6 +14,8 @@
 
 //! Module to handle Netex France profile
 
+mod brandings;
+use brandings::BrandingExporter;
 mod calendars;
 use calendars::CalendarExporter;
 mod companies;
@@ -36,7 +38,7 @@ use stops::StopExporter;
 mod transfers;
 use transfers::TransferExporter;
 
-use crate::{model::Model, Result};
+use crate::{model::Model, utils::zip_to, Result};
 use chrono::{DateTime, FixedOffset, TimeZone};
 
 /// Configuration options for exporting a NeTEx France.
@@ -104,7 +106,7 @@ pub fn write_to_zip<P: AsRef<std::path::Path>>(
 ) -> Result<()> {
     let output_dir = tempfile::tempdir()?;
     write(model, output_dir.path(), config)?;
-    crate::utils::zip_to(output_dir.path(), path)?;
+    zip_to(output_dir.path(), path)?;
     output_dir.close()?;
     Ok(())
 }
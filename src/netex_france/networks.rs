@@ -13,7 +13,10 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use crate::{
-    netex_france::exporter::{Exporter, ObjectType},
+    netex_france::{
+        exporter::{Exporter, ObjectType},
+        BrandingExporter,
+    },
     objects::{Line, Network},
     Model,
 };
@@ -46,6 +49,12 @@ impl<'a> NetworkExporter<'a> {
                 Exporter::generate_id(&network.id, ObjectType::Network),
             )
             .attr("version", "any");
+        // 'BrandingRef' comes first, as a property of any 'DataManagedObject'
+        let element_builder = if let Some(branding_id) = &network.branding_id {
+            element_builder.append(BrandingExporter::generate_branding_ref(branding_id))
+        } else {
+            element_builder
+        };
         let element_builder = element_builder.append(self.generate_name(network));
         let line_ref_elements = self
             .model
@@ -415,18 +415,10 @@ impl<'a> OfferExporter<'a> {
     }
 
     fn export_timetabled_passing_time(stop_time: &'a StopTime) -> Element {
-        let arrival_day_offset = stop_time.arrival_time.hours() / 24;
-        let arrival_time = Time::new(
-            stop_time.arrival_time.hours() % 24,
-            stop_time.arrival_time.minutes(),
-            stop_time.arrival_time.seconds(),
-        );
-        let departure_day_offset = stop_time.departure_time.hours() / 24;
-        let departure_time = Time::new(
-            stop_time.departure_time.hours() % 24,
-            stop_time.departure_time.minutes(),
-            stop_time.departure_time.seconds(),
-        );
+        let arrival_day_offset = stop_time.arrival_time.days_offset();
+        let arrival_time = stop_time.arrival_time.time_of_day();
+        let departure_day_offset = stop_time.departure_time.days_offset();
+        let departure_time = stop_time.departure_time.time_of_day();
         Element::builder(ObjectType::TimetabledPassingTime.to_string())
             .append(Self::generate_arrival_time(arrival_time))
             .append(Self::generate_arrival_day_offset(arrival_day_offset))
@@ -810,6 +802,10 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
                 StopTime {
                     stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -822,6 +818,10 @@ mod tests {
                     drop_off_type: 1,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 },
             ],
             ..Default::default()
@@ -853,6 +853,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -865,6 +869,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 ..Default::default()
@@ -907,6 +915,10 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: Some(1),
                     precision: Some(StopTimePrecision::Exact),
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 }],
                 ..Default::default()
             })
@@ -955,6 +967,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp_id_2").unwrap(),
@@ -967,6 +983,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: Some(1),
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 ..Default::default()
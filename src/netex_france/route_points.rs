@@ -118,6 +118,10 @@ mod tests {
                 drop_off_type: 0,
                 local_zone_id: None,
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             }
         }
 
@@ -16,25 +16,35 @@
 //! format management.
 
 mod read;
+mod verify;
 mod write;
 
 use crate::{
-    calendars::{manage_calendars, write_calendar_dates},
-    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    calendars::{manage_calendars, write_calendar_dates, write_calendar_dates_only},
+    enhancers::{self, ContactFieldsMode},
+    file_handler::{
+        FileHandler, FileWriter, MemoryFileWriter, PathFileHandler, PathFileWriter, ZipHandler,
+    },
     model::{Collections, Model},
     objects::*,
+    parser::collect_read_report,
+    report::ReadReport,
     serde_utils::*,
+    timing,
     utils::*,
     Result,
 };
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, ensure, Context};
 use chrono::{DateTime, FixedOffset};
 use chrono_tz::Tz;
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
-use std::path;
-use tempfile::tempdir;
-use tracing::info;
+use std::{
+    io::{Seek, Write},
+    path,
+};
+use tracing::{info, warn};
+use tracing_core::subscriber::NoSubscriber;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct StopTime {
@@ -58,6 +68,30 @@ struct StopTime {
     stop_time_id: Option<String>,
     #[serde(rename = "stop_time_precision")]
     precision: Option<StopTimePrecision>,
+    // Written by `write_vehicle_journeys_and_stop_times` only when used
+    #[serde(default, skip_serializing)]
+    continuous_pickup: Option<u8>,
+    #[serde(default, skip_serializing)]
+    continuous_drop_off: Option<u8>,
+    #[serde(default, skip_serializing)]
+    pickup_booking_rule_id: Option<String>,
+    #[serde(default, skip_serializing)]
+    drop_off_booking_rule_id: Option<String>,
+}
+
+// Columns of `stop_times.txt`, written when the model has continuous
+// stoppings
+#[derive(Serialize)]
+struct ContinuousStopping {
+    continuous_pickup: Option<u8>,
+    continuous_drop_off: Option<u8>,
+}
+
+// Columns of `stop_times.txt`, written when the model has booking rules
+#[derive(Serialize)]
+struct StopTimeBookingRules<'a> {
+    pickup_booking_rule_id: Option<&'a str>,
+    drop_off_booking_rule_id: Option<&'a str>,
 }
 
 #[derive(Derivative, Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -145,7 +179,7 @@ struct CommentLink {
 struct Code {
     object_type: ObjectType,
     object_id: String,
-    object_system: String,
+    object_system: IdString,
     object_code: String,
 }
 
@@ -181,22 +215,29 @@ fn has_fares_v1(collections: &Collections) -> bool {
 /// files in the given directory.
 pub fn from_dir<P: AsRef<path::Path>>(p: P) -> Result<Model> {
     let mut file_handle = PathFileHandler::new(p.as_ref().to_path_buf());
-    read_file_handler(&mut file_handle)
+    read_file_handler(&mut file_handle, &ReadConfiguration::default())
 }
 /// Imports a `Model` from a zip file containing the
 /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md).
 pub fn from_zip<P: AsRef<path::Path>>(p: P) -> Result<Model> {
     let reader = std::fs::File::open(p.as_ref())?;
     let mut file_handler = ZipHandler::new(reader, p)?;
-    read_file_handler(&mut file_handler)
+    read_file_handler(&mut file_handler, &ReadConfiguration::default())
 }
 
 /// Imports `Collections` from a zip file containing the
 /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md).
 pub fn collections_from_zip<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
-    let reader = std::fs::File::open(p.as_ref())?;
+    zip_collections(p.as_ref(), &ReadConfiguration::default())
+}
+
+fn zip_collections(p: &path::Path, configuration: &ReadConfiguration) -> Result<Collections> {
+    let reader = std::fs::File::open(p)?;
     let mut file_handler = ZipHandler::new(reader, p)?;
-    read_collections_file_handler(&mut file_handler)
+    if let Some(memory_budget) = configuration.zip_memory_budget {
+        file_handler.decompress_in_memory(memory_budget)?;
+    }
+    read_collections_file_handler(&mut file_handler, configuration)
 }
 
 /// Imports `Collections` from the
@@ -204,7 +245,7 @@ pub fn collections_from_zip<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
 /// files in the given directory.
 pub fn collections_from_dir<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
     let mut file_handle = PathFileHandler::new(p.as_ref().to_path_buf());
-    read_collections_file_handler(&mut file_handle)
+    read_collections_file_handler(&mut file_handle, &ReadConfiguration::default())
 }
 
 /// Imports a `Model` from an object implementing `Read` and `Seek` and containing a zip file with a
@@ -227,7 +268,140 @@ where
     R: std::io::Seek + std::io::Read,
 {
     let mut file_handler = ZipHandler::new(reader, &source_name)?;
-    read_file_handler(&mut file_handler)
+    read_file_handler(&mut file_handler, &ReadConfiguration::default())
+}
+
+/// Parameters of the NTFS import
+#[derive(Debug, Default, Clone)]
+pub struct ReadConfiguration {
+    /// If true, `geometries.txt` is not read: the `geometry_id` of the objects
+    /// are kept without being checked, and the model can't be written (see
+    /// `Collections::skipped_geometries`)
+    pub skip_geometries: bool,
+    /// If set, the files of a zipped NTFS are decompressed in parallel before
+    /// being parsed, as long as they fit in this number of bytes (see
+    /// `ZipHandler::decompress_in_memory`)
+    pub zip_memory_budget: Option<usize>,
+    /// If true, the files of a NTFS directory are memory-mapped instead of
+    /// being read (see `PathFileHandler::with_mmap`), a zipped NTFS being
+    /// still read from the archive
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+}
+
+/// Parameters of the NTFS export
+#[derive(Derivative, Debug, Clone)]
+#[derivative(Default)]
+pub struct WriteConfiguration {
+    /// If true, the calendars are only written in `calendar_dates.txt`, every
+    /// active date being an addition, without any `calendar.txt`
+    pub calendar_dates_only: bool,
+    /// The version of the NTFS specification written, the content introduced
+    /// by a later version being left out with a warning
+    pub version: NtfsVersion,
+    /// If true, the written NTFS is read back and the number of objects of
+    /// each collection is compared with the model, an error being returned
+    /// when the NTFS can't be read or when the numbers differ. On by default
+    /// in the debug builds only.
+    #[derivative(Default(value = "cfg!(debug_assertions)"))]
+    pub verify: bool,
+}
+
+/// Version of the NTFS specification
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derivative(Default)]
+pub enum NtfsVersion {
+    /// NTFS 0.11, without the levels, the pathways, the entrances, the
+    /// generic nodes and the boarding areas, nor the booking rules
+    V0_11,
+    /// NTFS 0.12, the current version (see [NTFS_VERSION](crate::NTFS_VERSION))
+    #[derivative(Default)]
+    V0_12,
+}
+
+impl std::str::FromStr for NtfsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        match version {
+            "0.11" => Ok(NtfsVersion::V0_11),
+            "0.12" => Ok(NtfsVersion::V0_12),
+            _ => Err(anyhow!(
+                "unknown NTFS version {:?}, expected 0.11 or 0.12",
+                version
+            )),
+        }
+    }
+}
+
+impl NtfsVersion {
+    /// The version written as `ntfs_version` in `feed_infos.txt`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NtfsVersion::V0_11 => "0.11.0",
+            NtfsVersion::V0_12 => crate::NTFS_VERSION,
+        }
+    }
+
+    // The version of a `ntfs_version` of `feed_infos.txt`, the versions before
+    // 0.12 being all handled as 0.11, `None` if it can't be parsed
+    fn parse(version: &str) -> Option<NtfsVersion> {
+        let mut numbers = version.trim().split('.').map(str::parse::<u32>);
+        match (numbers.next()?.ok()?, numbers.next()?.ok()?) {
+            (0, minor) if minor < 12 => Some(NtfsVersion::V0_11),
+            _ => Some(NtfsVersion::V0_12),
+        }
+    }
+
+    // The content of the collections which can't be written in this version
+    fn unsupported_content(self, collections: &Collections) -> Vec<String> {
+        if self >= NtfsVersion::V0_12 {
+            return vec![];
+        }
+        let stop_locations = collections
+            .stop_locations
+            .values()
+            .filter(|sl| sl.stop_type != StopType::Zone)
+            .count();
+        let stops_with_level = collections
+            .stop_points
+            .values()
+            .filter(|sp| sp.level_id.is_some())
+            .count()
+            + collections
+                .stop_areas
+                .values()
+                .filter(|sa| sa.level_id.is_some())
+                .count()
+            + collections
+                .stop_locations
+                .values()
+                .filter(|sl| sl.level_id.is_some())
+                .count();
+        let stop_times_with_booking_rule = collections
+            .vehicle_journeys
+            .values()
+            .flat_map(|vj| &vj.stop_times)
+            .filter(|st| {
+                st.pickup_booking_rule_id.is_some() || st.drop_off_booking_rule_id.is_some()
+            })
+            .count();
+        [
+            (collections.levels.len(), "levels"),
+            (collections.pathways.len(), "pathways"),
+            (stop_locations, "entrances, generic nodes or boarding areas"),
+            (stops_with_level, "stops with a level_id"),
+            (collections.booking_rules.len(), "booking rules"),
+            (
+                stop_times_with_booking_rule,
+                "stop times with a booking rule",
+            ),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, content)| format!("{} {}", count, content))
+        .collect()
+    }
 }
 
 /// Imports a `Model` from the
@@ -237,19 +411,27 @@ where
 /// If the default file type mechanism is not enough, you can use
 /// [from_zip] or [from_dir].
 pub fn read<P: AsRef<path::Path>>(path: P) -> Result<Model> {
-    let p = path.as_ref();
-    if p.is_file() {
-        // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
-        Ok(from_zip(p).with_context(|| format!("impossible to read zipped ntfs {:?}", p))?)
-    } else if p.is_dir() {
-        Ok(from_dir(p)
-            .with_context(|| format!("impossible to read ntfs directory from {:?}", p))?)
-    } else {
-        Err(anyhow!(
-            "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
-            p
-        ))
-    }
+    read_with_configuration(path, &ReadConfiguration::default())
+}
+
+/// Imports a `Model` like [read], also returning the report of the columns
+/// missing in the files, read with their default values, and of the rows
+/// skipped because they couldn't be read.
+pub fn read_with_report<P: AsRef<path::Path>>(path: P) -> Result<(Model, ReadReport)> {
+    let (model, report) = collect_read_report(|| read(path));
+    Ok((model?, report))
+}
+
+/// Imports a `Model` like [read], using the given `ReadConfiguration`.
+pub fn read_with_configuration<P: AsRef<path::Path>>(
+    path: P,
+    configuration: &ReadConfiguration,
+) -> Result<Model> {
+    let collections = read_collections_with_configuration(path, configuration)?;
+    info!("Indexing");
+    let res = Model::new(collections)?;
+    info!("Loading NTFS done");
+    Ok(res)
 }
 
 /// Imports `Collections` from the
@@ -259,14 +441,33 @@ pub fn read<P: AsRef<path::Path>>(path: P) -> Result<Model> {
 /// If the default file type mechanism is not enough, you can use
 /// [from_zip] or [from_dir].
 pub fn read_collections<P: AsRef<path::Path>>(path: P) -> Result<Collections> {
+    read_collections_with_configuration(path, &ReadConfiguration::default())
+}
+
+/// Imports `Collections` like [read_collections], using the given
+/// `ReadConfiguration`.
+pub fn read_collections_with_configuration<P: AsRef<path::Path>>(
+    path: P,
+    configuration: &ReadConfiguration,
+) -> Result<Collections> {
     let p = path.as_ref();
     if p.is_file() {
         // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
-        Ok(collections_from_zip(p)
+        Ok(zip_collections(p, configuration)
             .with_context(|| format!("impossible to read zipped ntfs {:?}", p))?)
     } else if p.is_dir() {
-        Ok(collections_from_dir(p)
-            .with_context(|| format!("impossible to read ntfs directory from {:?}", p))?)
+        #[cfg(feature = "mmap")]
+        let mut file_handler = if configuration.mmap {
+            PathFileHandler::with_mmap(p.to_path_buf())
+        } else {
+            PathFileHandler::new(p.to_path_buf())
+        };
+        #[cfg(not(feature = "mmap"))]
+        let mut file_handler = PathFileHandler::new(p.to_path_buf());
+        Ok(
+            read_collections_file_handler(&mut file_handler, configuration)
+                .with_context(|| format!("impossible to read ntfs directory from {:?}", p))?,
+        )
     } else {
         Err(anyhow!(
             "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
@@ -275,22 +476,26 @@ pub fn read_collections<P: AsRef<path::Path>>(path: P) -> Result<Collections> {
     }
 }
 
-fn read_file_handler<H>(file_handler: &mut H) -> Result<Model>
+fn read_file_handler<H>(file_handler: &mut H, configuration: &ReadConfiguration) -> Result<Model>
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let collections = read_collections_file_handler(file_handler)?;
+    let collections = read_collections_file_handler(file_handler, configuration)?;
     info!("Indexing");
     let res = Model::new(collections)?;
     info!("Loading NTFS done");
     Ok(res)
 }
 
-fn read_collections_file_handler<H>(file_handler: &mut H) -> Result<Collections>
+fn read_collections_file_handler<H>(
+    file_handler: &mut H,
+    configuration: &ReadConfiguration,
+) -> Result<Collections>
 where
     for<'a> &'a mut H: FileHandler,
 {
     info!("Loading NTFS from {:?}", file_handler.source_name());
+    let _stage = timing::read_collections("ntfs");
     let mut collections = Collections {
         contributors: make_collection_with_id(file_handler, "contributors.txt")?,
         datasets: make_collection_with_id(file_handler, "datasets.txt")?,
@@ -312,27 +517,87 @@ where
         ticket_use_perimeters: make_opt_collection(file_handler, "ticket_use_perimeters.txt")?,
         ticket_use_restrictions: make_opt_collection(file_handler, "ticket_use_restrictions.txt")?,
         levels: make_opt_collection_with_id(file_handler, "levels.txt")?,
+        booking_rules: make_opt_collection_with_id(file_handler, "booking_rules.txt")?,
         grid_calendars: make_opt_collection_with_id(file_handler, "grid_calendars.txt")?,
         grid_exception_dates: make_opt_collection(file_handler, "grid_exception_dates.txt")?,
         grid_periods: make_opt_collection(file_handler, "grid_periods.txt")?,
         grid_rel_calendar_line: make_opt_collection(file_handler, "grid_rel_calendar_line.txt")?,
         addresses: make_opt_collection_with_id(file_handler, "addresses.txt")?,
+        brandings: make_opt_collection_with_id(file_handler, "brandings.txt")?,
+        translations: make_opt_collection(file_handler, "translations.txt")?,
         ..Default::default()
     };
     manage_calendars(file_handler, &mut collections)?;
-    read::manage_geometries(&mut collections, file_handler)?;
+    if configuration.skip_geometries {
+        collections.skipped_geometries = true;
+    } else {
+        read::manage_geometries(&mut collections, file_handler)?;
+    }
     read::manage_feed_infos(&mut collections, file_handler)?;
     read::manage_stops(&mut collections, file_handler)?;
     read::manage_pathways(&mut collections, file_handler)?;
+    read::manage_occupancies(&mut collections, file_handler)?;
+    read::manage_line_groups(&mut collections, file_handler)?;
     read::manage_stop_times(&mut collections, file_handler)?;
     read::manage_codes(&mut collections, file_handler)?;
     read::manage_comments(&mut collections, file_handler)?;
     read::manage_object_properties(&mut collections, file_handler)?;
     read::manage_fares_v1(&mut collections, file_handler)?;
+    check_ntfs_version(&collections);
     read::manage_companies_on_vj(&mut collections)?;
+    enhancers::check_contact_fields(&mut collections, ContactFieldsMode::Loose)?;
     Ok(collections)
 }
 
+// Warns about the content of the NTFS introduced after the `ntfs_version`
+// declared in `feed_infos.txt`
+fn check_ntfs_version(collections: &Collections) {
+    let declared = match collections.feed_infos.get("ntfs_version") {
+        Some(declared) => declared,
+        None => return,
+    };
+    match NtfsVersion::parse(declared) {
+        Some(version) => {
+            let unsupported = version.unsupported_content(collections);
+            if !unsupported.is_empty() {
+                warn!(
+                    "feed_infos.txt declares the NTFS {}, which doesn't support the {} found",
+                    declared,
+                    unsupported.join(", ")
+                );
+            }
+        }
+        None => warn!("invalid ntfs_version {:?} in feed_infos.txt", declared),
+    }
+}
+
+// Writing of one or several files, labelled for the error messages
+type WriteTask<'a> = (&'static str, Box<dyn FnOnce() -> Result<()> + Send + 'a>);
+
+// Run the tasks in parallel (see `parallel_map`), each one writing its own
+// files, and return the error of the first failing task in the order of
+// `tasks`. The spans of the tasks are reported to the subscriber of the
+// calling thread, under its current span.
+fn run_write_tasks(tasks: Vec<WriteTask>) -> Result<()> {
+    // Setting the subscriber of a thread, even an empty one, would stop the
+    // `log` records of `tracing` for the whole process
+    let dispatch = tracing::dispatcher::get_default(|dispatch| {
+        (!dispatch.is::<NoSubscriber>()).then(|| dispatch.clone())
+    });
+    let span = tracing::Span::current();
+    let labels: Vec<&str> = tasks.iter().map(|(label, _)| *label).collect();
+    let results = parallel_map(tasks, |(_, task)| match &dispatch {
+        Some(dispatch) => tracing::dispatcher::with_default(dispatch, || span.in_scope(task)),
+        None => task(),
+    });
+    for (label, result) in labels.into_iter().zip(results) {
+        result
+            .unwrap_or_else(|| Err(anyhow!("the writing thread panicked")))
+            .with_context(|| format!("Error writing {}", label))?;
+    }
+    Ok(())
+}
+
 /// Exports a `Model` to the
 /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
 /// files in the given directory.
@@ -340,76 +605,295 @@ pub fn write<P: AsRef<path::Path>>(
     model: &Model,
     path: P,
     current_datetime: DateTime<FixedOffset>,
+) -> Result<()> {
+    write_with_configuration(
+        model,
+        path,
+        current_datetime,
+        &WriteConfiguration::default(),
+    )
+}
+
+/// Exports a `Model` like [write], using the given `WriteConfiguration`.
+pub fn write_with_configuration<P: AsRef<path::Path>>(
+    model: &Model,
+    path: P,
+    current_datetime: DateTime<FixedOffset>,
+    configuration: &WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     std::fs::create_dir_all(path)?;
     info!("Writing NTFS to {:?}", path);
-
-    write::write_feed_infos(path, model, current_datetime)?;
-    write_collection_with_id(path, "contributors.txt", &model.contributors)?;
-    write_collection_with_id(path, "datasets.txt", &model.datasets)?;
-    write_collection_with_id(path, "networks.txt", &model.networks)?;
-    write_collection_with_id(path, "commercial_modes.txt", &model.commercial_modes)?;
-    write_collection_with_id(path, "companies.txt", &model.companies)?;
-    write_collection_with_id(path, "lines.txt", &model.lines)?;
-    write_collection_with_id(path, "physical_modes.txt", &model.physical_modes)?;
-    write_collection_with_id(path, "equipments.txt", &model.equipments)?;
-    write_collection_with_id(path, "routes.txt", &model.routes)?;
-    write_collection_with_id(path, "trip_properties.txt", &model.trip_properties)?;
-    write_collection_with_id(path, "geometries.txt", &model.geometries)?;
-    write_collection(path, "transfers.txt", &model.transfers)?;
-    write_collection(path, "admin_stations.txt", &model.admin_stations)?;
-    write_collection_with_id(path, "tickets.txt", &model.tickets)?;
-    write_collection_with_id(path, "ticket_uses.txt", &model.ticket_uses)?;
-    write_collection(path, "ticket_prices.txt", &model.ticket_prices)?;
-    write_collection(
-        path,
-        "ticket_use_perimeters.txt",
-        &model.ticket_use_perimeters,
-    )?;
-    write_collection(
-        path,
-        "ticket_use_restrictions.txt",
-        &model.ticket_use_restrictions,
-    )?;
-    write_collection_with_id(path, "grid_calendars.txt", &model.grid_calendars)?;
-    write_collection(
-        path,
-        "grid_exception_dates.txt",
-        &model.grid_exception_dates,
-    )?;
-    write_collection(path, "grid_periods.txt", &model.grid_periods)?;
-    write_collection(
-        path,
-        "grid_rel_calendar_line.txt",
-        &model.grid_rel_calendar_line,
-    )?;
-    write::write_vehicle_journeys_and_stop_times(
-        path,
-        &model.vehicle_journeys,
-        &model.stop_points,
-        &model.stop_time_headsigns,
-        &model.stop_time_ids,
-    )?;
-    write_collection(path, "frequencies.txt", &model.frequencies)?;
-    write_calendar_dates(path, &model.calendars)?;
-    write::write_stops(
-        path,
-        &model.stop_points,
-        &model.stop_areas,
-        &model.stop_locations,
+    write_file_writer(
+        model,
+        &PathFileWriter::new(path),
+        current_datetime,
+        configuration,
     )?;
-    write::write_comments(path, model)?;
-    write::write_codes(path, model)?;
-    write::write_object_properties(path, model)?;
-    write::write_fares_v1(path, model)?;
-    write_collection_with_id(path, "pathways.txt", &model.pathways)?;
-    write_collection_with_id(path, "levels.txt", &model.levels)?;
-    write_collection_with_id(path, "addresses.txt", &model.addresses)?;
-
+    if configuration.verify {
+        verify::check_written_ntfs(
+            model,
+            || read(path).map(Model::into_collections),
+            configuration.version,
+        )?;
+    }
     Ok(())
 }
 
+fn write_file_writer<W: FileWriter>(
+    model: &Model,
+    file_writer: &W,
+    current_datetime: DateTime<FixedOffset>,
+    configuration: &WriteConfiguration,
+) -> Result<()> {
+    ensure!(
+        !model.skipped_geometries,
+        "the geometries were skipped when reading, the model can't be written"
+    );
+    let version = configuration.version;
+    let unsupported = version.unsupported_content(model);
+    if !unsupported.is_empty() {
+        warn!(
+            "the NTFS {} doesn't support the {}, which are not written",
+            version.as_str(),
+            unsupported.join(", ")
+        );
+    }
+    let stop_time_ids = write::stop_time_ids_with_comments(model);
+    let mut tasks: Vec<WriteTask> = vec![
+        (
+            "feed_infos.txt",
+            Box::new(|| write::write_feed_infos(file_writer, model, current_datetime, version)),
+        ),
+        (
+            "contributors.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "contributors.txt", &model.contributors)
+            }),
+        ),
+        (
+            "datasets.txt",
+            Box::new(|| write_collection_with_id(file_writer, "datasets.txt", &model.datasets)),
+        ),
+        (
+            "networks.txt",
+            Box::new(|| write_collection_with_id(file_writer, "networks.txt", &model.networks)),
+        ),
+        (
+            "commercial_modes.txt",
+            Box::new(|| {
+                write_collection_with_id(
+                    file_writer,
+                    "commercial_modes.txt",
+                    &model.commercial_modes,
+                )
+            }),
+        ),
+        (
+            "companies.txt",
+            Box::new(|| write_collection_with_id(file_writer, "companies.txt", &model.companies)),
+        ),
+        (
+            "lines.txt",
+            Box::new(|| write_collection_with_id(file_writer, "lines.txt", &model.lines)),
+        ),
+        (
+            "physical_modes.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "physical_modes.txt", &model.physical_modes)
+            }),
+        ),
+        (
+            "equipments.txt",
+            Box::new(|| write_collection_with_id(file_writer, "equipments.txt", &model.equipments)),
+        ),
+        (
+            "routes.txt",
+            Box::new(|| write_collection_with_id(file_writer, "routes.txt", &model.routes)),
+        ),
+        (
+            "trip_properties.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "trip_properties.txt", &model.trip_properties)
+            }),
+        ),
+        (
+            "geometries.txt",
+            Box::new(|| write_collection_with_id(file_writer, "geometries.txt", &model.geometries)),
+        ),
+        (
+            "transfers.txt",
+            Box::new(|| write_collection(file_writer, "transfers.txt", &model.transfers)),
+        ),
+        (
+            "admin_stations.txt",
+            Box::new(|| write_collection(file_writer, "admin_stations.txt", &model.admin_stations)),
+        ),
+        (
+            "tickets.txt",
+            Box::new(|| write_collection_with_id(file_writer, "tickets.txt", &model.tickets)),
+        ),
+        (
+            "ticket_uses.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "ticket_uses.txt", &model.ticket_uses)
+            }),
+        ),
+        (
+            "ticket_prices.txt",
+            Box::new(|| write_collection(file_writer, "ticket_prices.txt", &model.ticket_prices)),
+        ),
+        (
+            "ticket_use_perimeters.txt",
+            Box::new(|| {
+                write_collection(
+                    file_writer,
+                    "ticket_use_perimeters.txt",
+                    &model.ticket_use_perimeters,
+                )
+            }),
+        ),
+        (
+            "ticket_use_restrictions.txt",
+            Box::new(|| {
+                write_collection(
+                    file_writer,
+                    "ticket_use_restrictions.txt",
+                    &model.ticket_use_restrictions,
+                )
+            }),
+        ),
+        (
+            "grid_calendars.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "grid_calendars.txt", &model.grid_calendars)
+            }),
+        ),
+        (
+            "grid_exception_dates.txt",
+            Box::new(|| {
+                write_collection(
+                    file_writer,
+                    "grid_exception_dates.txt",
+                    &model.grid_exception_dates,
+                )
+            }),
+        ),
+        (
+            "grid_periods.txt",
+            Box::new(|| write_collection(file_writer, "grid_periods.txt", &model.grid_periods)),
+        ),
+        (
+            "grid_rel_calendar_line.txt",
+            Box::new(|| {
+                write_collection(
+                    file_writer,
+                    "grid_rel_calendar_line.txt",
+                    &model.grid_rel_calendar_line,
+                )
+            }),
+        ),
+        (
+            "trips.txt and stop_times.txt",
+            Box::new(|| {
+                write::write_vehicle_journeys_and_stop_times(
+                    file_writer,
+                    &model.vehicle_journeys,
+                    &model.stop_points,
+                    &model.stop_time_headsigns,
+                    &stop_time_ids,
+                    version,
+                )
+            }),
+        ),
+        (
+            "frequencies.txt",
+            Box::new(|| write_collection(file_writer, "frequencies.txt", &model.frequencies)),
+        ),
+        (
+            "calendar.txt and calendar_dates.txt",
+            Box::new(|| {
+                if configuration.calendar_dates_only {
+                    write_calendar_dates_only(file_writer, &model.calendars)
+                } else {
+                    write_calendar_dates(file_writer, &model.calendars)
+                }
+            }),
+        ),
+        (
+            "stops.txt",
+            Box::new(|| {
+                write::write_stops(
+                    file_writer,
+                    &model.stop_points,
+                    &model.stop_areas,
+                    &model.stop_locations,
+                    version,
+                )
+            }),
+        ),
+        (
+            "comments.txt",
+            Box::new(|| write::write_comments(file_writer, model, &stop_time_ids)),
+        ),
+        (
+            "object_codes.txt",
+            Box::new(|| write::write_codes(file_writer, model)),
+        ),
+        (
+            "object_properties.txt",
+            Box::new(|| write::write_object_properties(file_writer, model)),
+        ),
+        (
+            "fares v1 files",
+            Box::new(|| write::write_fares_v1(file_writer, model)),
+        ),
+        (
+            "pathways.txt",
+            Box::new(|| write_collection_with_id(file_writer, "pathways.txt", &model.pathways)),
+        ),
+        (
+            "levels.txt",
+            Box::new(|| write_collection_with_id(file_writer, "levels.txt", &model.levels)),
+        ),
+        (
+            "booking_rules.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "booking_rules.txt", &model.booking_rules)
+            }),
+        ),
+        (
+            "addresses.txt",
+            Box::new(|| write_collection_with_id(file_writer, "addresses.txt", &model.addresses)),
+        ),
+        (
+            "brandings.txt",
+            Box::new(|| write_collection_with_id(file_writer, "brandings.txt", &model.brandings)),
+        ),
+        (
+            "line_groups.txt and line_group_links.txt",
+            Box::new(|| {
+                write_collection_with_id(file_writer, "line_groups.txt", &model.line_groups)?;
+                write_collection(file_writer, "line_group_links.txt", &model.line_group_links)
+            }),
+        ),
+        (
+            "occupancies.txt",
+            Box::new(|| write_collection(file_writer, "occupancies.txt", &model.occupancies)),
+        ),
+        (
+            "translations.txt",
+            Box::new(|| write_collection(file_writer, "translations.txt", &model.translations)),
+        ),
+    ];
+    if version < NtfsVersion::V0_12 {
+        tasks.retain(|(label, _)| {
+            !["levels.txt", "pathways.txt", "booking_rules.txt"].contains(label)
+        });
+    }
+    run_write_tasks(tasks)
+}
+
 /// Exports a `Model` to a
 /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
 /// ZIP archive at the given full path.
@@ -417,16 +901,80 @@ pub fn write_to_zip<P: AsRef<path::Path>>(
     model: &Model,
     path: P,
     current_datetime: DateTime<FixedOffset>,
+) -> Result<()> {
+    write_to_zip_with_configuration(
+        model,
+        path,
+        current_datetime,
+        &WriteConfiguration::default(),
+    )
+}
+
+/// Exports a `Model` like [write_to_zip], using the given
+/// `WriteConfiguration`.
+pub fn write_to_zip_with_configuration<P: AsRef<path::Path>>(
+    model: &Model,
+    path: P,
+    current_datetime: DateTime<FixedOffset>,
+    configuration: &WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     info!("Writing NTFS to ZIP File {:?}", path);
-    let input_tmp_dir = tempdir()?;
-    write(model, input_tmp_dir.path(), current_datetime)?;
-    zip_to(input_tmp_dir.path(), path)?;
-    input_tmp_dir.close()?;
+    // The files are written in a temporary directory, then copied in the
+    // archive, not to hold the whole NTFS in memory
+    let output_dir = tempfile::tempdir()?;
+    write_file_writer(
+        model,
+        &PathFileWriter::new(output_dir.path()),
+        current_datetime,
+        configuration,
+    )?;
+    zip_to(output_dir.path(), path)?;
+    output_dir.close()?;
+    if configuration.verify {
+        verify::check_written_ntfs(
+            model,
+            || read(path).map(Model::into_collections),
+            configuration.version,
+        )?;
+    }
     Ok(())
 }
 
+/// Exports a `Model` to a
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// ZIP archive written in `writer`, the files being prepared in memory
+/// without any temporary file. The archive isn't read back, whatever
+/// [WriteConfiguration::verify].
+///
+/// ```
+/// # fn main() -> transit_model::Result<()> {
+/// let model = transit_model::ntfs::read("tests/fixtures/minimal_ntfs")?;
+/// let zip = transit_model::ntfs::write_to_zip_writer(
+///     &model,
+///     std::io::Cursor::new(Vec::new()),
+///     chrono::DateTime::parse_from_rfc3339("2019-04-03T17:19:00Z")?,
+/// )?;
+/// let model = transit_model::ntfs::from_zip_reader(zip, "in-memory NTFS")?;
+/// assert_eq!(1, model.networks.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_to_zip_writer<W: Write + Seek>(
+    model: &Model,
+    writer: W,
+    current_datetime: DateTime<FixedOffset>,
+) -> Result<W> {
+    let file_writer = MemoryFileWriter::new();
+    write_file_writer(
+        model,
+        &file_writer,
+        current_datetime,
+        &WriteConfiguration::default(),
+    )?;
+    file_writer.write_zip(writer)
+}
+
 #[cfg(test)]
 mod tests {
     use super::Collections;
@@ -443,14 +991,16 @@ mod tests {
     };
     use typed_index_collection::{Collection, CollectionWithId, Id};
 
-    fn test_serialize_deserialize_collection_with_id<T>(objects: Vec<T>)
+    fn test_serialize_deserialize_collection_with_id<T>(mut objects: Vec<T>)
     where
         T: Id<T> + PartialEq + Debug + serde::Serialize,
         for<'de> T: serde::Deserialize<'de>,
     {
+        // the objects are written sorted by identifier
+        objects.sort_by(|a, b| a.id().cmp(b.id()));
         let collection = CollectionWithId::new(objects).unwrap();
         test_in_tmp_dir(|path| {
-            write_collection_with_id(path, "file.txt", &collection).unwrap();
+            write_collection_with_id(&PathFileWriter::new(path), "file.txt", &collection).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_collection_with_id(&mut handler, "file.txt").unwrap();
             assert_eq!(collection, des_collection);
@@ -464,7 +1014,7 @@ mod tests {
     {
         let collection = Collection::new(objects);
         test_in_tmp_dir(|path| {
-            write_collection(path, "file.txt", &collection).unwrap();
+            write_collection(&PathFileWriter::new(path), "file.txt", &collection).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_opt_collection(&mut handler, "file.txt").unwrap();
             assert_eq!(collection, des_collection);
@@ -499,7 +1049,13 @@ mod tests {
         };
 
         test_in_tmp_dir(|path| {
-            write::write_feed_infos(path, &collections, get_test_datetime()).unwrap();
+            write::write_feed_infos(
+                &PathFileWriter::new(path),
+                &collections,
+                get_test_datetime(),
+                NtfsVersion::V0_12,
+            )
+            .unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             read::manage_feed_infos(&mut collections, &mut handler).unwrap();
             assert_eq!(
@@ -513,7 +1069,7 @@ mod tests {
                     ("feed_end_date".to_string(), "20180131".to_string()),
                     ("feed_publisher_name".to_string(), "Nicaragua".to_string()),
                     ("feed_start_date".to_string(), "20180130".to_string()),
-                    ("ntfs_version".to_string(), "0.12.1".to_string()),
+                    ("ntfs_version".to_string(), "0.12.2".to_string()),
                     ("tartare_platform".to_string(), "dev".to_string()),
                 ],
                 collections
@@ -538,6 +1094,7 @@ mod tests {
                 address: Some("somewhere".to_string()),
                 sort_order: Some(1),
                 codes: KeysValues::default(),
+                branding_id: Some("OIF:SAVAC".to_string()),
             },
             Network {
                 id: "OIF:102".to_string(),
@@ -549,6 +1106,7 @@ mod tests {
                 address: None,
                 sort_order: None,
                 codes: KeysValues::default(),
+                branding_id: None,
             },
         ]);
     }
@@ -559,10 +1117,12 @@ mod tests {
             CommercialMode {
                 id: "boarding_landing".to_string(),
                 name: "Boarding - Landing".to_string(),
+                ..Default::default()
             },
             CommercialMode {
                 id: "bus".to_string(),
                 name: "Bus".to_string(),
+                ..Default::default()
             },
         ]);
     }
@@ -574,6 +1134,9 @@ mod tests {
                 id: "OIF:101".to_string(),
                 name: "Foo".to_string(),
                 address: Some("foo address".to_string()),
+                postcode: Some("75001".to_string()),
+                city: Some("Paris".to_string()),
+                registration_number: Some("123 456 789".to_string()),
                 url: Some("http://www.foo.fr/".to_string()),
                 mail: Some("contact@foo.fr".to_string()),
                 phone: Some("0123456789".to_string()),
@@ -583,6 +1146,9 @@ mod tests {
                 id: "OIF:102".to_string(),
                 name: "Bar".to_string(),
                 address: None,
+                postcode: None,
+                city: None,
+                registration_number: None,
                 url: None,
                 mail: None,
                 phone: None,
@@ -619,6 +1185,7 @@ mod tests {
                 geometry_id: Some("Geometry:Line:Relation:6883353".to_string()),
                 opening_time: Some(Time::new(9, 0, 0)),
                 closing_time: Some(Time::new(18, 0, 0)),
+                branding_id: Some("OIF:Noctilien".to_string()),
             },
             Line {
                 id: "OIF:002002003:3OIF829".to_string(),
@@ -637,6 +1204,135 @@ mod tests {
                 geometry_id: None,
                 opening_time: None,
                 closing_time: None,
+                branding_id: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn brandings_serialization_deserialization() {
+        test_serialize_deserialize_collection_with_id(vec![
+            Branding {
+                id: "OIF:Noctilien".to_string(),
+                name: "Noctilien".to_string(),
+                url: Some("http://www.noctilien.fr".to_string()),
+                image: Some("http://www.noctilien.fr/logo.png".to_string()),
+            },
+            Branding {
+                id: "OIF:SAVAC".to_string(),
+                name: "SAVAC".to_string(),
+                url: None,
+                image: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn booking_rules_serialization_deserialization() {
+        test_serialize_deserialize_collection_with_id(vec![
+            BookingRule {
+                id: "OIF:BR:1".to_string(),
+                booking_type: BookingType::SameDay,
+                prior_notice_duration_min: Some(60),
+                message: Some("Book one hour before".to_string()),
+                phone_number: Some("+33 1 23 45 67 89".to_string()),
+                booking_url: Some("https://example.com/booking".to_string()),
+                ..Default::default()
+            },
+            BookingRule {
+                id: "OIF:BR:2".to_string(),
+                booking_type: BookingType::PriorDays,
+                prior_notice_last_day: Some(1),
+                prior_notice_last_time: Some(Time::new(17, 0, 0)),
+                ..Default::default()
+            },
+        ]);
+    }
+
+    #[test]
+    fn translations_serialization_deserialization() {
+        test_serialize_deserialize_collection(vec![
+            Translation {
+                object_type: ObjectType::StopArea,
+                object_id: "OIF:SA:8739384".to_string(),
+                field_name: "name".to_string(),
+                language: "en".to_string(),
+                translation: "Versailles Chateau".to_string(),
+            },
+            Translation {
+                object_type: ObjectType::VehicleJourney,
+                object_id: "OIF:90014407-1_425283-1".to_string(),
+                field_name: "headsign".to_string(),
+                language: "en-US".to_string(),
+                translation: "Airport".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn line_groups_serialization_deserialization() {
+        test_serialize_deserialize_collection_with_id(vec![
+            LineGroup {
+                id: "OIF:LG:1".to_string(),
+                name: "RER".to_string(),
+                main_line_id: "OIF:800:A".to_string(),
+                comment_links: CommentLinksT::default(),
+            },
+            LineGroup {
+                id: "OIF:LG:2".to_string(),
+                name: "Transilien, RER".to_string(),
+                main_line_id: "OIF:800:P".to_string(),
+                comment_links: CommentLinksT::default(),
+            },
+        ]);
+        test_serialize_deserialize_collection(vec![
+            LineGroupLink {
+                line_group_id: "OIF:LG:1".to_string(),
+                line_id: "OIF:800:A".to_string(),
+            },
+            LineGroupLink {
+                line_group_id: "OIF:LG:1".to_string(),
+                line_id: "OIF:800:B".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn occupancies_serialization_deserialization() {
+        test_serialize_deserialize_collection(vec![
+            Occupancy {
+                line_id: "OIF:002002002:BDEOIF829".to_string(),
+                from_stop_area: "OIF:SA:8739384".to_string(),
+                to_stop_area: "OIF:SA:8754730".to_string(),
+                from_date: chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+                to_date: chrono::NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+                from_time: Time::new(7, 0, 0),
+                to_time: Time::new(9, 30, 0),
+                monday: true,
+                tuesday: true,
+                wednesday: true,
+                thursday: true,
+                friday: true,
+                saturday: false,
+                sunday: false,
+                occupancy: 80,
+            },
+            Occupancy {
+                line_id: "OIF:002002002:BDEOIF829".to_string(),
+                from_stop_area: "OIF:SA:8754730".to_string(),
+                to_stop_area: "OIF:SA:8739384".to_string(),
+                from_date: chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+                to_date: chrono::NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+                from_time: Time::new(17, 0, 0),
+                to_time: Time::new(19, 0, 0),
+                monday: false,
+                tuesday: false,
+                wednesday: false,
+                thursday: false,
+                friday: false,
+                saturday: true,
+                sunday: true,
+                occupancy: 35,
             },
         ]);
     }
@@ -648,16 +1344,19 @@ mod tests {
                 id: "Bus".to_string(),
                 name: "Bus".to_string(),
                 co2_emission: Some(6.2),
+                ..Default::default()
             },
             PhysicalMode {
                 id: "Funicular".to_string(),
                 name: "Funicular".to_string(),
                 co2_emission: None,
+                ..Default::default()
             },
             PhysicalMode {
                 id: "SuspendedCableCar".to_string(),
                 name: "Suspended Cable Car".to_string(),
                 co2_emission: None,
+                ..Default::default()
             },
         ]);
     }
@@ -750,6 +1449,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     objects::StopTime {
                         stop_point_idx: stop_points.get_idx("OIF:SP:36:2127").unwrap(),
@@ -762,6 +1465,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: Some("OIF:BR:1".to_string()),
                     },
                 ],
                 journey_pattern_id: Some(String::from("OIF:JP:1")),
@@ -788,23 +1495,21 @@ mod tests {
         .unwrap();
 
         let mut headsigns = HashMap::new();
-        headsigns.insert(
-            ("OIF:87604986-1_11595-1".to_string(), 1),
-            "somewhere".to_string(),
-        );
+        headsigns.insert(("OIF:87604986-1_11595-1".into(), 1), "somewhere".into());
         let mut stop_time_ids = HashMap::new();
         stop_time_ids.insert(
-            ("OIF:87604986-1_11595-1".to_string(), 0),
-            "StopTime:OIF:87604986-1_11595-1:0".to_string(),
+            ("OIF:87604986-1_11595-1".into(), 0),
+            "StopTime:OIF:87604986-1_11595-1:0".into(),
         );
 
         test_in_tmp_dir(|path| {
             write::write_vehicle_journeys_and_stop_times(
-                path,
+                &PathFileWriter::new(path),
                 &vehicle_journeys,
                 &stop_points,
                 &headsigns,
                 &stop_time_ids,
+                NtfsVersion::V0_12,
             )
             .unwrap();
 
@@ -830,12 +1535,14 @@ mod tests {
                 name: "Foo".to_string(),
                 license: Some("ODbL".to_string()),
                 website: Some("http://www.foo.com".to_string()),
+                ..Default::default()
             },
             Contributor {
                 id: "Bar".to_string(),
                 name: "Bar".to_string(),
                 license: None,
                 website: None,
+                ..Default::default()
             },
         ]);
     }
@@ -892,6 +1599,7 @@ mod tests {
                 min_transfer_time: Some(20),
                 real_min_transfer_time: Some(30),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_type: TransferType::Possible,
             },
             Transfer {
                 from_stop_id: "st_1".to_string(),
@@ -899,6 +1607,7 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: Some("eq_1".to_string()),
+                transfer_type: TransferType::Possible,
             },
         ];
         let expected_transfers = vec![
@@ -908,6 +1617,7 @@ mod tests {
                 min_transfer_time: Some(20),
                 real_min_transfer_time: Some(30),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_type: TransferType::Possible,
             },
             Transfer {
                 from_stop_id: "st_1".to_string(),
@@ -915,12 +1625,13 @@ mod tests {
                 min_transfer_time: Some(0),
                 real_min_transfer_time: Some(0),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_type: TransferType::Possible,
             },
         ];
         let collection = Collection::new(transfers);
         let expected_collection = Collection::new(expected_transfers);
         test_in_tmp_dir(|path| {
-            write_collection(path, "file.txt", &collection).unwrap();
+            write_collection(&PathFileWriter::new(path), "file.txt", &collection).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_opt_collection(&mut handler, "file.txt").unwrap();
             assert_eq!(expected_collection, des_collection);
@@ -950,7 +1661,7 @@ mod tests {
 
         test_in_tmp_dir(|path| {
             let mut handler = PathFileHandler::new(path.to_path_buf());
-            write_calendar_dates(path, &calendars).unwrap();
+            write_calendar_dates(&PathFileWriter::new(path), &calendars).unwrap();
 
             let mut collections = Collections::default();
             manage_calendars(&mut handler, &mut collections).unwrap();
@@ -1033,7 +1744,14 @@ mod tests {
         let stop_locations: CollectionWithId<StopLocation> = CollectionWithId::default();
 
         test_in_tmp_dir(|path| {
-            write::write_stops(path, &stop_points, &stop_areas, &stop_locations).unwrap();
+            write::write_stops(
+                &PathFileWriter::new(path),
+                &stop_points,
+                &stop_areas,
+                &stop_locations,
+                NtfsVersion::V0_12,
+            )
+            .unwrap();
 
             let mut collections = Collections::default();
             let mut handler = PathFileHandler::new(path.to_path_buf());
@@ -1044,6 +1762,274 @@ mod tests {
         });
     }
 
+    #[test]
+    fn calendar_dates_only_round_trip() {
+        let model = read("tests/fixtures/minimal_ntfs").unwrap();
+        test_in_tmp_dir(|path| {
+            let configuration = WriteConfiguration {
+                calendar_dates_only: true,
+                ..Default::default()
+            };
+            write_with_configuration(&model, path, get_test_datetime(), &configuration).unwrap();
+            assert!(!path.join("calendar.txt").exists());
+
+            let read_model = read(path).unwrap();
+            for calendar in model.calendars.values() {
+                assert_eq!(Some(calendar), read_model.calendars.get(&calendar.id));
+            }
+            assert_eq!(model.calendars.len(), read_model.calendars.len());
+            assert_eq!(
+                model.vehicle_journeys.len(),
+                read_model.vehicle_journeys.len()
+            );
+        });
+    }
+
+    #[test]
+    fn co2_emissions_round_trip() {
+        let mut collections = read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        collections
+            .physical_modes
+            .get_mut("Bus")
+            .unwrap()
+            .co2_emission = Some(12.345);
+        let model = Model::new(collections).unwrap();
+        test_in_tmp_dir(|path| {
+            write(&model, path, get_test_datetime()).unwrap();
+            let physical_modes = std::fs::read_to_string(path.join("physical_modes.txt")).unwrap();
+            let read_model = read(path).unwrap();
+            assert_eq!(
+                Some(12.345),
+                read_model.physical_modes.get("Bus").unwrap().co2_emission
+            );
+
+            test_in_tmp_dir(|rewritten_path| {
+                write(&read_model, rewritten_path, get_test_datetime()).unwrap();
+                assert_eq!(
+                    physical_modes,
+                    std::fs::read_to_string(rewritten_path.join("physical_modes.txt")).unwrap()
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn stop_time_comments_without_stop_time_id_round_trip() {
+        use crate::AddPrefix;
+        let mut collections = read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        let vj_id = collections
+            .vehicle_journeys
+            .values()
+            .next()
+            .unwrap()
+            .id
+            .clone();
+        collections
+            .comments
+            .push(Comment {
+                id: "comment:1".to_string(),
+                comment_type: CommentType::Information,
+                label: None,
+                name: "Request the stop to the driver".to_string(),
+                url: None,
+            })
+            .unwrap();
+        collections
+            .stop_time_comments
+            .insert((IdString::from(&vj_id), 1), IdString::from("comment:1"));
+        let mut prefix_conf = crate::PrefixConfiguration::default();
+        prefix_conf.set_data_prefix("pre");
+        collections.prefix(&prefix_conf);
+        let model = Model::new(collections).unwrap();
+        let vj_id = format!("pre:{}", vj_id);
+
+        test_in_tmp_dir(|path| {
+            write(&model, path, get_test_datetime()).unwrap();
+            let stop_times = std::fs::read_to_string(path.join("stop_times.txt")).unwrap();
+            assert!(stop_times.contains(&format!("{}-1", vj_id)));
+
+            let read_model = read(path).unwrap();
+            assert_eq!(
+                Some(&IdString::from("pre:comment:1")),
+                read_model
+                    .stop_time_comments
+                    .get(&(IdString::from(&vj_id), 1))
+            );
+            assert_eq!(1, read_model.stop_time_comments.len());
+        });
+    }
+
+    #[test]
+    fn write_an_older_ntfs_version() {
+        let model = read("tests/fixtures/gtfs2ntfs/full_output").unwrap();
+        assert!(!model.levels.is_empty());
+        assert!(model
+            .stop_locations
+            .values()
+            .any(|sl| sl.stop_type != StopType::Zone));
+        test_in_tmp_dir(|path| {
+            let configuration = WriteConfiguration {
+                version: NtfsVersion::V0_11,
+                ..Default::default()
+            };
+            write_with_configuration(&model, path, get_test_datetime(), &configuration).unwrap();
+            assert!(!path.join("levels.txt").exists());
+            assert!(!path.join("pathways.txt").exists());
+
+            let read_model = read(path).unwrap();
+            assert_eq!(
+                Some("0.11.0"),
+                read_model
+                    .feed_infos
+                    .get("ntfs_version")
+                    .map(String::as_str)
+            );
+            assert!(read_model
+                .stop_locations
+                .values()
+                .all(|sl| sl.stop_type == StopType::Zone));
+            assert!(read_model
+                .stop_points
+                .values()
+                .all(|sp| sp.level_id.is_none()));
+            assert_eq!(model.stop_points.len(), read_model.stop_points.len());
+            assert_eq!(
+                model.vehicle_journeys.len(),
+                read_model.vehicle_journeys.len()
+            );
+        });
+    }
+
+    #[test]
+    fn verify_the_written_ntfs() {
+        let mut collections = read("tests/fixtures/gtfs2ntfs/full_output")
+            .unwrap()
+            .into_collections();
+        // The comments of the stop locations are not written
+        collections
+            .comments
+            .push(Comment {
+                id: "comment:entrance".to_string(),
+                name: "Closed on sundays".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let stop_location_id = collections
+            .stop_locations
+            .values()
+            .next()
+            .unwrap()
+            .id
+            .clone();
+        collections
+            .stop_locations
+            .get_mut(&stop_location_id)
+            .unwrap()
+            .comment_links
+            .insert("comment:entrance".to_string());
+        let model = Model::new(collections).unwrap();
+        let comments = model.comments.len();
+        test_in_tmp_dir(|path| {
+            let configuration = WriteConfiguration {
+                verify: true,
+                ..Default::default()
+            };
+            let error = write_with_configuration(&model, path, get_test_datetime(), &configuration)
+                .unwrap_err();
+            assert_eq!(
+                format!(
+                    "the written NTFS doesn't read back as the model: {} comments written but {} read",
+                    comments,
+                    comments - 1
+                ),
+                error.to_string()
+            );
+
+            let configuration = WriteConfiguration {
+                verify: false,
+                ..Default::default()
+            };
+            write_with_configuration(&model, path, get_test_datetime(), &configuration).unwrap();
+        });
+    }
+
+    #[test]
+    fn parse_ntfs_version() {
+        assert_eq!(Some(NtfsVersion::V0_11), NtfsVersion::parse("0.9.2"));
+        assert_eq!(Some(NtfsVersion::V0_11), NtfsVersion::parse("0.11.5"));
+        assert_eq!(Some(NtfsVersion::V0_12), NtfsVersion::parse("0.12"));
+        assert_eq!(
+            Some(NtfsVersion::V0_12),
+            NtfsVersion::parse(crate::NTFS_VERSION)
+        );
+        assert_eq!(None, NtfsVersion::parse("twelve"));
+    }
+
+    #[test]
+    fn codes_of_every_object_type_round_trip() {
+        fn codes<T: Id<T> + Codes>(
+            collection: &CollectionWithId<T>,
+        ) -> std::collections::BTreeMap<String, KeysValues> {
+            collection
+                .values()
+                .map(|object| (object.id().to_string(), object.codes().clone()))
+                .collect()
+        }
+        fn add_code<T: Id<T> + Codes>(collection: &mut CollectionWithId<T>, id: &str) {
+            collection
+                .get_mut(id)
+                .unwrap()
+                .codes_mut()
+                .insert(("system".into(), format!("code of {}", id)));
+        }
+        let mut collections = read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        add_code(&mut collections.contributors, "TGC");
+        add_code(&mut collections.networks, "TGN");
+        add_code(&mut collections.companies, "TGC");
+        add_code(&mut collections.commercial_modes, "Bus");
+        add_code(&mut collections.physical_modes, "Bus");
+        add_code(&mut collections.lines, "B42");
+        add_code(&mut collections.routes, "B42F");
+        add_code(&mut collections.vehicle_journeys, "B42F1");
+        add_code(&mut collections.stop_areas, "GDL");
+        add_code(&mut collections.stop_points, "GDLB");
+        let model = Model::new(collections).unwrap();
+        let zip = write_to_zip_writer(
+            &model,
+            std::io::Cursor::new(Vec::new()),
+            get_test_datetime(),
+        )
+        .unwrap()
+        .into_inner();
+
+        let read_model = from_zip_reader(std::io::Cursor::new(zip), "ntfs.zip").unwrap();
+        assert_eq!(codes(&model.contributors), codes(&read_model.contributors));
+        assert_eq!(codes(&model.networks), codes(&read_model.networks));
+        assert_eq!(codes(&model.companies), codes(&read_model.companies));
+        assert_eq!(
+            codes(&model.commercial_modes),
+            codes(&read_model.commercial_modes)
+        );
+        assert_eq!(
+            codes(&model.physical_modes),
+            codes(&read_model.physical_modes)
+        );
+        assert_eq!(codes(&model.lines), codes(&read_model.lines));
+        assert_eq!(codes(&model.routes), codes(&read_model.routes));
+        assert_eq!(
+            codes(&model.vehicle_journeys),
+            codes(&read_model.vehicle_journeys)
+        );
+        assert_eq!(codes(&model.stop_areas), codes(&read_model.stop_areas));
+        assert_eq!(codes(&model.stop_points), codes(&read_model.stop_points));
+    }
+
     #[test]
     fn comments_codes_object_properties_serialization_deserialization() {
         let mut ser_collections = Collections::default();
@@ -1076,7 +2062,7 @@ mod tests {
             id: "sp_1".to_string(),
             name: "sp_name_1".to_string(),
             codes: btree_set_from_vec(vec![(
-                "object_system:1".to_string(),
+                "object_system:1".into(),
                 "object_code:1".to_string(),
             )]),
             object_properties: properties_map![(
@@ -1098,7 +2084,7 @@ mod tests {
             id: "sa_1".to_string(),
             name: "sa_name_1".to_string(),
             codes: btree_set_from_vec(vec![(
-                "object_system:2".to_string(),
+                "object_system:2".into(),
                 "object_code:2".to_string(),
             )]),
             object_properties: properties_map![(
@@ -1124,7 +2110,7 @@ mod tests {
             name: "3".to_string(),
             code: None,
             codes: btree_set_from_vec(vec![(
-                "object_system:3".to_string(),
+                "object_system:3".into(),
                 "object_code:3".to_string(),
             )]),
             object_properties: properties_map![(
@@ -1142,6 +2128,7 @@ mod tests {
             geometry_id: None,
             opening_time: None,
             closing_time: None,
+            branding_id: None,
         });
 
         let routes = CollectionWithId::from(Route {
@@ -1149,8 +2136,8 @@ mod tests {
             name: "Hôtels - Hôtels".to_string(),
             direction_type: None,
             codes: btree_set_from_vec(vec![
-                ("object_system:4".to_string(), "object_code:4".to_string()),
-                ("object_system:5".to_string(), "object_code:5".to_string()),
+                ("object_system:4".into(), "object_code:4".to_string()),
+                ("object_system:5".into(), "object_code:5".to_string()),
             ]),
             object_properties: properties_map![(
                 "prop_name:4".to_string(),
@@ -1165,7 +2152,7 @@ mod tests {
         let vehicle_journeys = CollectionWithId::from(VehicleJourney {
             id: "VJ:1".to_string(),
             codes: btree_set_from_vec(vec![(
-                "object_system:6".to_string(),
+                "object_system:6".into(),
                 "object_code:6".to_string(),
             )]),
             object_properties: properties_map![(
@@ -1194,6 +2181,10 @@ mod tests {
                 drop_off_type: 2,
                 local_zone_id: None,
                 precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
             }],
             journey_pattern_id: None,
         });
@@ -1208,12 +2199,13 @@ mod tests {
             address: None,
             sort_order: None,
             codes: KeysValues::default(),
+            branding_id: None,
         });
 
         let mut stop_time_ids = HashMap::new();
-        stop_time_ids.insert((("VJ:1").to_string(), 0), "StopTime:VJ:1:0".to_string());
+        stop_time_ids.insert(("VJ:1".into(), 0), "StopTime:VJ:1:0".into());
         let mut stop_time_comments = HashMap::new();
-        stop_time_comments.insert(("VJ:1".to_string(), 0), "c:2".to_string());
+        stop_time_comments.insert(("VJ:1".into(), 0), "c:2".into());
 
         ser_collections.comments = comments;
         ser_collections.stop_areas = stop_areas;
@@ -1227,27 +2219,49 @@ mod tests {
         ser_collections.stop_time_comments = stop_time_comments;
 
         test_in_tmp_dir(|path| {
-            write_collection_with_id(path, "lines.txt", &ser_collections.lines).unwrap();
+            write_collection_with_id(
+                &PathFileWriter::new(path),
+                "lines.txt",
+                &ser_collections.lines,
+            )
+            .unwrap();
             write::write_stops(
-                path,
+                &PathFileWriter::new(path),
                 &ser_collections.stop_points,
                 &ser_collections.stop_areas,
                 &ser_collections.stop_locations,
+                NtfsVersion::V0_12,
+            )
+            .unwrap();
+            write_collection_with_id(
+                &PathFileWriter::new(path),
+                "routes.txt",
+                &ser_collections.routes,
+            )
+            .unwrap();
+            write_collection_with_id(
+                &PathFileWriter::new(path),
+                "networks.txt",
+                &ser_collections.networks,
             )
             .unwrap();
-            write_collection_with_id(path, "routes.txt", &ser_collections.routes).unwrap();
-            write_collection_with_id(path, "networks.txt", &ser_collections.networks).unwrap();
             write::write_vehicle_journeys_and_stop_times(
-                path,
+                &PathFileWriter::new(path),
                 &ser_collections.vehicle_journeys,
                 &ser_collections.stop_points,
                 &ser_collections.stop_time_headsigns,
                 &ser_collections.stop_time_ids,
+                NtfsVersion::V0_12,
             )
             .unwrap();
-            write::write_comments(path, &ser_collections).unwrap();
-            write::write_codes(path, &ser_collections).unwrap();
-            write::write_object_properties(path, &ser_collections).unwrap();
+            write::write_comments(
+                &PathFileWriter::new(path),
+                &ser_collections,
+                &ser_collections.stop_time_ids,
+            )
+            .unwrap();
+            write::write_codes(&PathFileWriter::new(path), &ser_collections).unwrap();
+            write::write_object_properties(&PathFileWriter::new(path), &ser_collections).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
 
             let mut des_collections = Collections {
@@ -1542,6 +2556,71 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn fares_v1_to_v2_round_trip() {
+        let price = |id: &str| PriceV1 {
+            id: id.to_string(),
+            start_date: chrono::NaiveDate::from_ymd(2019, 1, 1),
+            end_date: chrono::NaiveDate::from_ymd(2020, 1, 1),
+            price: 190,
+            name: format!("Ticket {}", id),
+            ignored: "".to_string(),
+            comment: "".to_string(),
+            currency_type: Some("centime".to_string()),
+        };
+        let fare = |after_change: &str, start_trip: &str, end_trip: &str, ticket_id: &str| FareV1 {
+            before_change: "*".to_string(),
+            after_change: after_change.to_string(),
+            start_trip: start_trip.to_string(),
+            end_trip: end_trip.to_string(),
+            global_condition: "".to_string(),
+            ticket_id: ticket_id.to_string(),
+        };
+        let mut collections = Collections {
+            prices_v1: Collection::new(vec![price("T1"), price("T2")]),
+            ..Default::default()
+        };
+        collections.fares_v1 = Collection::new(vec![
+            fare("network=network:N1", "nb_changes<3", "", "T1"),
+            fare(
+                "line=line:L1",
+                "stoparea=stop_area:SA1",
+                "stoparea=stop_area:SA2",
+                "T2",
+            ),
+        ]);
+        collections.fares_v1_to_v2().unwrap();
+        assert_eq!(2, collections.tickets.len());
+        assert_eq!(2, collections.ticket_uses.len());
+
+        test_in_tmp_dir(|path| {
+            write::write_fares_v1(&PathFileWriter::new(path), &collections).unwrap();
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            let mut read_collections = Collections::default();
+            read::manage_fares_v1(&mut read_collections, &mut handler).unwrap();
+            assert_eq!(collections.tickets.len(), read_collections.prices_v1.len());
+
+            read_collections.fares_v1_to_v2().unwrap();
+            assert_eq!(collections.tickets.len(), read_collections.tickets.len());
+            assert_eq!(
+                collections.ticket_prices.len(),
+                read_collections.ticket_prices.len()
+            );
+            assert_eq!(
+                collections.ticket_use_perimeters.len(),
+                read_collections.ticket_use_perimeters.len()
+            );
+            assert_eq!(
+                collections.ticket_use_restrictions.len(),
+                read_collections.ticket_use_restrictions.len()
+            );
+            for ticket_use in collections.ticket_uses.values() {
+                let read_ticket_use = read_collections.ticket_uses.get(&ticket_use.id).unwrap();
+                assert_eq!(ticket_use, read_ticket_use);
+            }
+        });
+    }
+
     #[test]
     fn tickets_serialization_deserialization() {
         test_serialize_deserialize_collection_with_id(vec![
@@ -1634,4 +2713,39 @@ mod tests {
             },
         ]);
     }
+
+    #[test]
+    fn write_tasks_report_the_first_error() {
+        let tasks: Vec<WriteTask> = vec![
+            ("a.txt", Box::new(|| Ok(()))),
+            ("b.txt", Box::new(|| Err(anyhow!("disk full")))),
+            ("c.txt", Box::new(|| Err(anyhow!("permission denied")))),
+        ];
+        let error = run_write_tasks(tasks).unwrap_err();
+        assert_eq!("Error writing b.txt: disk full", format!("{:#}", error));
+    }
+
+    #[test]
+    fn write_tasks_survive_a_panic() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let written = AtomicUsize::new(0);
+        let mut tasks: Vec<WriteTask> = (0..100)
+            .map(|_| -> WriteTask {
+                (
+                    "file.txt",
+                    Box::new(|| {
+                        written.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }),
+                )
+            })
+            .collect();
+        tasks.insert(50, ("panic.txt", Box::new(|| panic!("bug"))));
+        let error = run_write_tasks(tasks).unwrap_err();
+        assert_eq!(
+            "Error writing panic.txt: the writing thread panicked",
+            format!("{:#}", error)
+        );
+        assert_eq!(100, written.load(Ordering::Relaxed));
+    }
 }
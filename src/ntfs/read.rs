@@ -14,11 +14,13 @@
 
 use super::{Code, CommentLink, ObjectProperty, Stop, StopLocationType, StopTime};
 use crate::file_handler::FileHandler;
+use crate::id_string::Interner;
 use crate::model::Collections;
 use crate::ntfs::has_fares_v2;
 use crate::objects::*;
 use crate::parser::{read_objects, read_objects_loose};
-use crate::utils::make_opt_collection_with_id;
+use crate::timing;
+use crate::utils::{collection_with_id_skipping_duplicates, make_opt_collection_with_id};
 use crate::Result;
 use anyhow::{anyhow, bail, ensure, Context};
 use serde::{Deserialize, Serialize};
@@ -158,9 +160,9 @@ where
     for<'a> &'a mut H: FileHandler,
 {
     let stops = read_objects::<_, Stop>(file_handler, "stops.txt", true)?;
-    let mut stop_areas: CollectionWithId<StopArea> = CollectionWithId::default();
-    let mut stop_points: CollectionWithId<StopPoint> = CollectionWithId::default();
-    let mut stop_locations: CollectionWithId<StopLocation> = CollectionWithId::default();
+    let mut stop_areas = Vec::new();
+    let mut stop_points = Vec::new();
+    let mut stop_locations = Vec::new();
     for stop in stops {
         match stop.location_type {
             StopLocationType::StopPoint | StopLocationType::GeographicArea => {
@@ -169,21 +171,21 @@ where
                     let mut stop_area = StopArea::from(stop_point.clone());
                     stop_point.stop_area_id = stop_area.id.clone();
                     stop_area.visible = stop.location_type == StopLocationType::StopPoint;
-                    skip_error_and_warn!(stop_areas.push(stop_area));
+                    stop_areas.push(stop_area);
                 };
-                skip_error_and_warn!(stop_points.push(stop_point));
+                stop_points.push(stop_point);
             }
             StopLocationType::StopArea => {
-                skip_error_and_warn!(stop_areas.push(StopArea::try_from(stop)?));
+                stop_areas.push(StopArea::try_from(stop)?);
             }
             _ => {
-                skip_error_and_warn!(stop_locations.push(StopLocation::try_from(stop)?));
+                stop_locations.push(StopLocation::try_from(stop)?);
             }
         }
     }
-    collections.stop_areas = stop_areas;
-    collections.stop_points = stop_points;
-    collections.stop_locations = stop_locations;
+    collections.stop_points = collection_with_id_skipping_duplicates(stop_points);
+    collections.stop_areas = collection_with_id_skipping_duplicates(stop_areas);
+    collections.stop_locations = collection_with_id_skipping_duplicates(stop_locations);
     Ok(())
 }
 
@@ -208,16 +210,18 @@ where
         }
         Some(reader) => {
             info!("Reading {}", basename);
+            let stage = timing::read_file(&basename);
             let mut rdr = csv::ReaderBuilder::new()
                 .flexible(true)
                 .has_headers(has_headers)
                 .trim(csv::Trim::All)
                 .delimiter(b';')
                 .from_reader(reader);
-            let res = rdr
+            let res: Vec<T> = rdr
                 .deserialize()
                 .collect::<Result<_, _>>()
                 .with_context(|| format!("Error reading {:?}", path))?;
+            stage.record_rows(res.len());
             Ok(Collection::new(res))
         }
     }
@@ -262,6 +266,7 @@ where
     let stop_times = read_objects::<_, StopTime>(file_handler, "stop_times.txt", true)?;
     let mut headsigns = HashMap::new();
     let mut stop_time_ids = HashMap::new();
+    let mut interner = Interner::default();
     for stop_time in stop_times {
         let stop_point_idx = collections
             .stop_points
@@ -286,8 +291,8 @@ where
 
         if let Some(headsign) = stop_time.stop_headsign {
             headsigns.insert(
-                (stop_time.trip_id.clone(), stop_time.stop_sequence),
-                headsign,
+                (interner.intern(&stop_time.trip_id), stop_time.stop_sequence),
+                interner.intern(&headsign),
             );
         }
         let datetime_estimated = stop_time.datetime_estimated.map_or_else(
@@ -305,8 +310,8 @@ where
 
         if let Some(stop_time_id) = stop_time.stop_time_id {
             stop_time_ids.insert(
-                (stop_time.trip_id.clone(), stop_time.stop_sequence),
-                stop_time_id,
+                (interner.intern(&stop_time.trip_id), stop_time.stop_sequence),
+                stop_time_id.into(),
             );
         }
 
@@ -325,6 +330,10 @@ where
                 drop_off_type: stop_time.drop_off_type,
                 local_zone_id: stop_time.local_zone_id,
                 precision,
+                continuous_pickup: stop_time.continuous_pickup,
+                continuous_drop_off: stop_time.continuous_drop_off,
+                pickup_booking_rule_id: stop_time.pickup_booking_rule_id,
+                drop_off_booking_rule_id: stop_time.drop_off_booking_rule_id,
             });
     }
     collections.stop_time_headsigns = headsigns;
@@ -364,7 +373,9 @@ where
     for<'a> &'a mut H: FileHandler,
 {
     let codes = read_objects::<_, Code>(file_handler, "object_codes.txt", false)?;
-    for code in codes {
+    let mut interner = Interner::default();
+    for mut code in codes {
+        code.object_system = interner.intern(&code.object_system);
         match code.object_type {
             ObjectType::StopArea => insert_code(&mut collections.stop_areas, code),
             ObjectType::StopPoint => insert_code(&mut collections.stop_points, code),
@@ -373,6 +384,9 @@ where
             ObjectType::Route => insert_code(&mut collections.routes, code),
             ObjectType::VehicleJourney => insert_code(&mut collections.vehicle_journeys, code),
             ObjectType::Company => insert_code(&mut collections.companies, code),
+            ObjectType::CommercialMode => insert_code(&mut collections.commercial_modes, code),
+            ObjectType::PhysicalMode => insert_code(&mut collections.physical_modes, code),
+            ObjectType::Contributor => insert_code(&mut collections.contributors, code),
             _ => bail!(
                 "Problem reading {:?}: code does not support {}",
                 file_handler.source_name(),
@@ -448,14 +462,18 @@ where
 }
 
 fn insert_stop_time_comment_link(
-    stop_time_comments: &mut HashMap<(String, u32), String>,
-    stop_time_ids: &HashMap<&String, (String, u32)>,
+    stop_time_comments: &mut HashMap<(IdString, u32), IdString>,
+    stop_time_ids: &HashMap<&str, (IdString, u32)>,
     comments: &CollectionWithId<Comment>,
     comment_link: &CommentLink,
+    interner: &mut Interner,
 ) -> Result<()> {
-    if let Some(vehicle_journey_id) = stop_time_ids.get(&comment_link.object_id) {
+    if let Some(vehicle_journey_id) = stop_time_ids.get(comment_link.object_id.as_str()) {
         if comments.contains_id(&comment_link.comment_id) {
-            stop_time_comments.insert(vehicle_journey_id.clone(), comment_link.comment_id.clone());
+            stop_time_comments.insert(
+                vehicle_journey_id.clone(),
+                interner.intern(&comment_link.comment_id),
+            );
         } else {
             bail!(
                 "comment.txt: comment_id={} not found",
@@ -488,8 +506,9 @@ where
     let stop_time_ids = collections
         .stop_time_ids
         .iter()
-        .map(|(k, v)| (v, k.clone()))
+        .map(|(k, v)| (v.as_str(), k.clone()))
         .collect();
+    let mut interner = Interner::default();
     info!("Reading comment_links.txt");
     for comment_link in comment_links {
         match comment_link.object_type {
@@ -525,8 +544,13 @@ where
                 &stop_time_ids,
                 &collections.comments,
                 &comment_link,
+                &mut interner,
+            )),
+            ObjectType::LineGroup => skip_error_and_warn!(insert_comment_link(
+                &mut collections.line_groups,
+                &collections.comments,
+                &comment_link,
             )),
-            ObjectType::LineGroup => warn!("line_groups.txt is not parsed yet"),
             _ => warn!(
                 "comment does not support {}",
                 comment_link.object_type.as_str()
@@ -668,6 +692,83 @@ where
     Ok(())
 }
 
+pub(crate) fn manage_occupancies<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file = "occupancies.txt";
+    let mut occupancies = Vec::new();
+    for occupancy in read_objects_loose::<_, Occupancy>(file_handler, file, false)? {
+        if !collections.lines.contains_id(&occupancy.line_id) {
+            warn!(
+                "Problem reading {:?}: line_id={:?} not found, occupancy skipped",
+                file, occupancy.line_id
+            );
+            continue;
+        }
+        if let Some(stop_area_id) = [&occupancy.from_stop_area, &occupancy.to_stop_area]
+            .iter()
+            .find(|id| !collections.stop_areas.contains_id(id))
+        {
+            warn!(
+                "Problem reading {:?}: stop_area_id={:?} not found, occupancy skipped",
+                file, stop_area_id
+            );
+            continue;
+        }
+        occupancies.push(occupancy);
+    }
+    collections.occupancies = Collection::new(occupancies);
+    Ok(())
+}
+
+pub(crate) fn manage_line_groups<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file = "line_groups.txt";
+    let mut line_groups = Vec::new();
+    for line_group in read_objects_loose::<_, LineGroup>(file_handler, file, false)? {
+        if !collections.lines.contains_id(&line_group.main_line_id) {
+            warn!(
+                "Problem reading {:?}: main_line_id={:?} of line_group_id={:?} not found, line group skipped",
+                file, line_group.main_line_id, line_group.id
+            );
+            continue;
+        }
+        line_groups.push(line_group);
+    }
+    collections.line_groups = CollectionWithId::new(line_groups)?;
+
+    let file = "line_group_links.txt";
+    let mut line_group_links = Vec::new();
+    for link in read_objects_loose::<_, LineGroupLink>(file_handler, file, false)? {
+        if !collections.line_groups.contains_id(&link.line_group_id) {
+            warn!(
+                "Problem reading {:?}: line_group_id={:?} not found, link skipped",
+                file, link.line_group_id
+            );
+            continue;
+        }
+        if !collections.lines.contains_id(&link.line_id) {
+            warn!(
+                "Problem reading {:?}: line_id={:?} not found, link skipped",
+                file, link.line_id
+            );
+            continue;
+        }
+        line_group_links.push(link);
+    }
+    collections.line_group_links = Collection::new(line_group_links);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -803,6 +904,10 @@ mod tests {
                         drop_off_type: 0,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:02").unwrap(),
@@ -815,6 +920,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Approximate),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:03").unwrap(),
@@ -827,6 +936,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:04").unwrap(),
@@ -839,6 +952,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Exact),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                     objects::StopTime {
                         stop_point_idx: collections.stop_points.get_idx("sp:05").unwrap(),
@@ -851,6 +968,10 @@ mod tests {
                         drop_off_type: 1,
                         local_zone_id: None,
                         precision: Some(StopTimePrecision::Estimated),
+                        continuous_pickup: None,
+                        continuous_drop_off: None,
+                        pickup_booking_rule_id: None,
+                        drop_off_booking_rule_id: None,
                     },
                 ],
                 collections.vehicle_journeys.into_vec()[0].stop_times
@@ -876,4 +997,60 @@ mod tests {
             assert_eq!(code.1, "source_code");
         });
     }
+
+    #[test]
+    fn line_groups_with_unknown_main_line_are_skipped() {
+        test_in_tmp_dir(|path| {
+            generate_minimal_ntfs(path);
+            let line_groups_content = "line_group_id,line_group_name,main_line_id\n\
+            group_1,Group 1,line_1\n\
+            group_2,Group 2,unknown_line";
+            let line_group_links_content = "line_group_id,line_id\n\
+            group_1,line_1\n\
+            group_1,unknown_line\n\
+            group_2,line_1";
+            create_file_with_content(path, "line_groups.txt", line_groups_content);
+            create_file_with_content(path, "line_group_links.txt", line_group_links_content);
+
+            let mut collections = make_collection(path);
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            manage_line_groups(&mut collections, &mut handler).unwrap();
+
+            assert_eq!(1, collections.line_groups.len());
+            let line_group = collections.line_groups.get("group_1").unwrap();
+            assert_eq!("Group 1", line_group.name);
+            assert_eq!("line_1", line_group.main_line_id);
+            assert_eq!(
+                vec![LineGroupLink {
+                    line_group_id: "group_1".to_string(),
+                    line_id: "line_1".to_string(),
+                }],
+                collections.line_group_links.take()
+            );
+        });
+    }
+
+    #[test]
+    fn occupancies_with_unknown_line_or_stop_area_are_skipped() {
+        test_in_tmp_dir(|path| {
+            generate_minimal_ntfs(path);
+            let occupancies_content = "line_id,from_stop_area,to_stop_area,from_date,to_date,from_time,to_time,monday,tuesday,wednesday,thursday,friday,saturday,sunday,occupancy\n\
+            line_1,Navitia:sp:01,Navitia:sp:02,20200101,20201231,07:00:00,09:30:00,1,1,1,1,1,0,0,80\n\
+            unknown_line,Navitia:sp:01,Navitia:sp:02,20200101,20201231,07:00:00,09:30:00,1,1,1,1,1,0,0,80\n\
+            line_1,Navitia:sp:01,unknown_stop_area,20200101,20201231,07:00:00,09:30:00,1,1,1,1,1,0,0,80";
+            create_file_with_content(path, "occupancies.txt", occupancies_content);
+
+            let mut collections = make_collection(path);
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            manage_occupancies(&mut collections, &mut handler).unwrap();
+
+            assert_eq!(1, collections.occupancies.len());
+            let occupancy = collections.occupancies.values().next().unwrap();
+            assert_eq!("line_1", occupancy.line_id);
+            assert_eq!("Navitia:sp:02", occupancy.to_stop_area);
+            assert_eq!(Time::new(9, 30, 0), occupancy.to_time);
+            assert!(occupancy.friday && !occupancy.saturday);
+            assert_eq!(80, occupancy.occupancy);
+        });
+    }
 }
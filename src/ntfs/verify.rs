@@ -0,0 +1,145 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Verification of a written NTFS, read back and compared with the model it
+//! was written from.
+
+use super::NtfsVersion;
+use crate::{model::Collections, Result};
+use anyhow::{bail, Context};
+use std::collections::BTreeMap;
+use tracing::info;
+
+// The number of objects of each collection written in a NTFS of the given
+// version
+fn object_counts(collections: &Collections, version: NtfsVersion) -> BTreeMap<&'static str, usize> {
+    let mut counts: BTreeMap<&'static str, usize> = [
+        ("contributors", collections.contributors.len()),
+        ("datasets", collections.datasets.len()),
+        ("networks", collections.networks.len()),
+        ("commercial_modes", collections.commercial_modes.len()),
+        ("lines", collections.lines.len()),
+        ("line_groups", collections.line_groups.len()),
+        ("routes", collections.routes.len()),
+        ("vehicle_journeys", collections.vehicle_journeys.len()),
+        (
+            "stop_times",
+            collections
+                .vehicle_journeys
+                .values()
+                .map(|vj| vj.stop_times.len())
+                .sum(),
+        ),
+        ("frequencies", collections.frequencies.len()),
+        ("physical_modes", collections.physical_modes.len()),
+        ("stop_areas", collections.stop_areas.len()),
+        ("stop_points", collections.stop_points.len()),
+        ("calendars", collections.calendars.len()),
+        ("companies", collections.companies.len()),
+        ("comments", collections.comments.len()),
+        ("equipments", collections.equipments.len()),
+        ("transfers", collections.transfers.len()),
+        ("trip_properties", collections.trip_properties.len()),
+        ("geometries", collections.geometries.len()),
+        ("tickets", collections.tickets.len()),
+        ("ticket_uses", collections.ticket_uses.len()),
+        ("ticket_prices", collections.ticket_prices.len()),
+        ("addresses", collections.addresses.len()),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    // The stop locations, the levels, the pathways and the booking rules
+    // are left out of the older versions
+    if version >= NtfsVersion::V0_12 {
+        counts.insert("stop_locations", collections.stop_locations.len());
+        counts.insert("levels", collections.levels.len());
+        counts.insert("pathways", collections.pathways.len());
+        counts.insert("booking_rules", collections.booking_rules.len());
+    }
+    counts
+}
+
+/// Checks that the NTFS written from `collections` reads back, with as many
+/// objects in each collection.
+pub(crate) fn check_written_ntfs(
+    collections: &Collections,
+    read_back: impl FnOnce() -> Result<Collections>,
+    version: NtfsVersion,
+) -> Result<()> {
+    info!("Verifying the written NTFS");
+    let read_back = read_back().context("the written NTFS can't be read back")?;
+    let expected = object_counts(collections, version);
+    let read = object_counts(&read_back, version);
+    let mismatches: Vec<String> = expected
+        .iter()
+        .filter(|(name, count)| read[*name] != **count)
+        .map(|(name, count)| format!("{} {} written but {} read", count, name, read[name]))
+        .collect();
+    if !mismatches.is_empty() {
+        bail!(
+            "the written NTFS doesn't read back as the model: {}",
+            mismatches.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::StopPoint;
+    use typed_index_collection::CollectionWithId;
+
+    fn collections() -> Collections {
+        Collections {
+            stop_points: CollectionWithId::new(vec![
+                StopPoint {
+                    id: "sp_1".to_string(),
+                    ..Default::default()
+                },
+                StopPoint {
+                    id: "sp_2".to_string(),
+                    ..Default::default()
+                },
+            ])
+            .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mismatching_counts() {
+        let mut read_back = collections();
+        read_back.stop_points.retain(|sp| sp.id != "sp_1");
+        let error =
+            check_written_ntfs(&collections(), || Ok(read_back), NtfsVersion::V0_12).unwrap_err();
+        assert_eq!(
+            "the written NTFS doesn't read back as the model: 2 stop_points written but 1 read",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn unreadable_ntfs() {
+        let error = check_written_ntfs(
+            &collections(),
+            || Err(anyhow::anyhow!("invalid reference")),
+            NtfsVersion::V0_12,
+        )
+        .unwrap_err();
+        assert_eq!("the written NTFS can't be read back", error.to_string());
+        assert_eq!("invalid reference", error.root_cause().to_string());
+    }
+}
@@ -12,28 +12,35 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use super::{Code, CommentLink, ObjectProperty, Result, Stop, StopLocationType, StopTime};
+use super::{
+    Code, CommentLink, ContinuousStopping, NtfsVersion, ObjectProperty, Result, Stop,
+    StopLocationType, StopTime, StopTimeBookingRules,
+};
+use crate::file_handler::FileWriter;
 use crate::model::Collections;
 use crate::ntfs::{has_fares_v1, has_fares_v2};
 use crate::objects::*;
-use crate::NTFS_VERSION;
+use crate::timing;
+use crate::utils::sorted_by_id;
 use anyhow::{anyhow, bail, Context};
 use chrono::{DateTime, Duration, FixedOffset};
 use csv::Writer;
 use rust_decimal::{prelude::ToPrimitive, Decimal};
-use std::collections::{BTreeSet, HashMap};
-use std::fs::File;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path;
 use tracing::{info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id};
 
-pub fn write_feed_infos(
-    path: &path::Path,
+pub fn write_feed_infos<W: FileWriter>(
+    file_writer: &W,
     collections: &Collections,
     current_datetime: DateTime<FixedOffset>,
+    version: NtfsVersion,
 ) -> Result<()> {
     info!("Writing feed_infos.txt");
-    let path = path.join("feed_infos.txt");
+    let stage = timing::write_file("feed_infos.txt");
+    let (writer, path) = file_writer.create_file("feed_infos.txt")?;
     let mut feed_infos = collections.feed_infos.clone();
     feed_infos.insert(
         "feed_creation_date".to_string(),
@@ -47,7 +54,7 @@ pub fn write_feed_infos(
         "feed_creation_datetime".to_string(),
         current_datetime.to_rfc3339(),
     );
-    feed_infos.insert("ntfs_version".to_string(), NTFS_VERSION.to_string());
+    feed_infos.insert("ntfs_version".to_string(), version.as_str().to_string());
     let (start_date, end_date) = collections.calculate_validity_period()?;
     feed_infos.insert(
         "feed_start_date".to_string(),
@@ -58,8 +65,8 @@ pub fn write_feed_infos(
         end_date.format("%Y%m%d").to_string(),
     );
 
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    stage.record_rows(feed_infos.len());
+    let mut wtr = csv::Writer::from_writer(writer);
     wtr.write_record(&["feed_info_param", "feed_info_value"])
         .with_context(|| format!("Error reading {:?}", path))?;
     for feed_info in feed_infos {
@@ -71,49 +78,84 @@ pub fn write_feed_infos(
     Ok(())
 }
 
-pub fn write_vehicle_journeys_and_stop_times(
-    path: &path::Path,
+/// Writes `trips.txt` and `stop_times.txt`, ordered by trip_id then
+/// stop_sequence. The stop times are serialized one by one into the csv
+/// writer, whose buffer is drained into the file as it fills, so that no row
+/// is kept in memory whatever the size of the model.
+pub fn write_vehicle_journeys_and_stop_times<W: FileWriter>(
+    file_writer: &W,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
     stop_points: &CollectionWithId<StopPoint>,
-    stop_time_headsigns: &HashMap<(String, u32), String>,
-    stop_time_ids: &HashMap<(String, u32), String>,
+    stop_time_headsigns: &HashMap<(IdString, u32), IdString>,
+    stop_time_ids: &HashMap<(IdString, u32), IdString>,
+    version: NtfsVersion,
 ) -> Result<()> {
     info!("Writing trips.txt and stop_times.txt");
-    let trip_path = path.join("trips.txt");
-    let stop_times_path = path.join("stop_times.txt");
-    let mut vj_wtr = csv::Writer::from_path(&trip_path)
-        .with_context(|| format!("Error reading {:?}", trip_path))?;
-    let mut st_wtr = csv::Writer::from_path(&stop_times_path)
-        .with_context(|| format!("Error reading {:?}", stop_times_path))?;
-    for (vj_idx, vj) in vehicle_journeys.iter() {
+    let stage = timing::write_file("stop_times.txt");
+    let mut stop_times_count = 0;
+    let (trip_writer, trip_path) = file_writer.create_file("trips.txt")?;
+    let (stop_times_writer, stop_times_path) = file_writer.create_file("stop_times.txt")?;
+    let mut vj_wtr = csv::Writer::from_writer(trip_writer);
+    let mut st_wtr = csv::Writer::from_writer(stop_times_writer);
+    let with_continuous_stoppings = vehicle_journeys.values().any(|vj| {
+        vj.stop_times
+            .iter()
+            .any(|st| st.continuous_pickup.is_some() || st.continuous_drop_off.is_some())
+    });
+    let with_booking_rules = version >= NtfsVersion::V0_12
+        && vehicle_journeys.values().any(|vj| {
+            vj.stop_times.iter().any(|st| {
+                st.pickup_booking_rule_id.is_some() || st.drop_off_booking_rule_id.is_some()
+            })
+        });
+    for vj in sorted_by_id(vehicle_journeys) {
         vj_wtr
             .serialize(vj)
             .with_context(|| format!("Error reading {:?}", trip_path))?;
+        let vj_id = IdString::from(&vj.id);
 
         for st in &vj.stop_times {
             let precision = st.precision.clone();
-            st_wtr
-                .serialize(StopTime {
-                    stop_id: stop_points[st.stop_point_idx].id.clone(),
-                    trip_id: vj.id.clone(),
-                    stop_sequence: st.sequence,
-                    arrival_time: st.arrival_time,
-                    departure_time: st.departure_time,
-                    boarding_duration: st.boarding_duration,
-                    alighting_duration: st.alighting_duration,
-                    pickup_type: st.pickup_type,
-                    drop_off_type: st.drop_off_type,
-                    datetime_estimated: None,
-                    local_zone_id: st.local_zone_id,
-                    stop_headsign: stop_time_headsigns
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
-                        .cloned(),
-                    stop_time_id: stop_time_ids
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
-                        .cloned(),
-                    precision,
-                })
-                .with_context(|| format!("Error reading {:?}", st_wtr))?;
+            let stop_time = StopTime {
+                stop_id: stop_points[st.stop_point_idx].id.clone(),
+                trip_id: vj.id.clone(),
+                stop_sequence: st.sequence,
+                arrival_time: st.arrival_time,
+                departure_time: st.departure_time,
+                boarding_duration: st.boarding_duration,
+                alighting_duration: st.alighting_duration,
+                pickup_type: st.pickup_type,
+                drop_off_type: st.drop_off_type,
+                datetime_estimated: None,
+                local_zone_id: st.local_zone_id,
+                stop_headsign: stop_time_headsigns
+                    .get(&(vj_id.clone(), st.sequence))
+                    .map(IdString::to_string),
+                stop_time_id: stop_time_ids
+                    .get(&(vj_id.clone(), st.sequence))
+                    .map(IdString::to_string),
+                precision,
+                continuous_pickup: st.continuous_pickup,
+                continuous_drop_off: st.continuous_drop_off,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
+            };
+            let continuous_stopping = ContinuousStopping {
+                continuous_pickup: st.continuous_pickup,
+                continuous_drop_off: st.continuous_drop_off,
+            };
+            let booking_rules = StopTimeBookingRules {
+                pickup_booking_rule_id: st.pickup_booking_rule_id.as_deref(),
+                drop_off_booking_rule_id: st.drop_off_booking_rule_id.as_deref(),
+            };
+            let result = match (with_continuous_stoppings, with_booking_rules) {
+                (true, true) => st_wtr.serialize((stop_time, continuous_stopping, booking_rules)),
+                (true, false) => st_wtr.serialize((stop_time, continuous_stopping)),
+                (false, true) => st_wtr.serialize((stop_time, booking_rules)),
+                (false, false) => st_wtr.serialize(stop_time),
+            };
+            result.with_context(|| format!("Error reading {:?}", stop_times_path))?;
+            stop_times_count += 1;
         }
     }
     st_wtr
@@ -122,12 +164,13 @@ pub fn write_vehicle_journeys_and_stop_times(
     vj_wtr
         .flush()
         .with_context(|| format!("Error reading {:?}", trip_path))?;
+    stage.record_rows(stop_times_count);
 
     Ok(())
 }
 
-fn do_write_fares_v1(
-    base_path: &path::Path,
+fn do_write_fares_v1<W: FileWriter>(
+    file_writer: &W,
     prices_v1: &Collection<PriceV1>,
     od_fares_v1: &Collection<OdFareV1>,
     fares_v1: &Collection<FareV1>,
@@ -141,10 +184,10 @@ fn do_write_fares_v1(
     builder.has_headers(false);
 
     info!("Writing {}", file_prices);
-    let path = base_path.join(file_prices);
-    let mut prices_wtr = builder
-        .from_path(&path)
-        .with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file(file_prices);
+    stage.record_rows(prices_v1.len());
+    let (writer, path) = file_writer.create_file(file_prices)?;
+    let mut prices_wtr = builder.from_writer(writer);
     for price_v1 in prices_v1.values() {
         prices_wtr
             .serialize(price_v1)
@@ -153,14 +196,15 @@ fn do_write_fares_v1(
     prices_wtr
         .flush()
         .with_context(|| format!("Error reading {:?}", path))?;
+    drop(stage);
 
     builder.has_headers(true);
 
     info!("Writing {}", file_od_fares);
-    let path = base_path.join(file_od_fares);
-    let mut od_fares_wtr = builder
-        .from_path(&path)
-        .with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file(file_od_fares);
+    stage.record_rows(od_fares_v1.len());
+    let (writer, path) = file_writer.create_file(file_od_fares)?;
+    let mut od_fares_wtr = builder.from_writer(writer);
     for od_fare_v1 in od_fares_v1.values() {
         od_fares_wtr
             .serialize(od_fare_v1)
@@ -181,6 +225,7 @@ fn do_write_fares_v1(
     od_fares_wtr
         .flush()
         .with_context(|| format!("Error reading {:?}", path))?;
+    drop(stage);
 
     if fares_v1.is_empty() {
         info!("Writing skipped {}", file_fares);
@@ -188,10 +233,10 @@ fn do_write_fares_v1(
     }
 
     info!("Writing {}", file_fares);
-    let path = base_path.join(file_fares);
-    let mut fares_wtr = builder
-        .from_path(&path)
-        .with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file(file_fares);
+    stage.record_rows(fares_v1.len());
+    let (writer, path) = file_writer.create_file(file_fares)?;
+    let mut fares_wtr = builder.from_writer(writer);
     for fare_v1 in fares_v1.values() {
         fares_wtr
             .serialize(fare_v1)
@@ -216,6 +261,7 @@ struct Perimeter<'p> {
     included_networks: Vec<&'p str>,
     included_lines: Vec<&'p str>,
     excluded_lines: Vec<&'p str>,
+    commercial_modes: Vec<&'p str>,
 }
 
 fn extract_perimeter_for_ticket_use<'id, 'p>(
@@ -225,6 +271,7 @@ fn extract_perimeter_for_ticket_use<'id, 'p>(
     let mut included_networks = Vec::new();
     let mut included_lines = Vec::new();
     let mut excluded_lines = Vec::new();
+    let mut commercial_modes = Vec::new();
     for perimeter in ticket_use_perimeters
         .values()
         .filter(|p| p.ticket_use_id == ticket_use_id)
@@ -239,6 +286,9 @@ fn extract_perimeter_for_ticket_use<'id, 'p>(
             (ObjectType::Line, PerimeterAction::Excluded) => {
                 excluded_lines.push(perimeter.object_id.as_str());
             }
+            (ObjectType::CommercialMode, _) => {
+                commercial_modes.push(perimeter.object_id.as_str());
+            }
             _ => {
                 bail!(
                     "Badly formed ticket_use_perimeter : \n {:?} \n\
@@ -256,6 +306,7 @@ fn extract_perimeter_for_ticket_use<'id, 'p>(
         included_networks,
         included_lines,
         excluded_lines,
+        commercial_modes,
     })
 }
 
@@ -292,6 +343,15 @@ fn construct_fare_v1_from_v2(fares: &Fares) -> Result<(BTreeSet<PriceV1>, BTreeS
         let perimeter =
             extract_perimeter_for_ticket_use(&ticket_use.id, fares.ticket_use_perimeters)?;
 
+        if !perimeter.commercial_modes.is_empty() {
+            warn!(
+                "The ticket_use_id {} is ignored since commercial modes can't be \
+                 expressed as fares v1.",
+                ticket_use.id
+            );
+            continue;
+        }
+
         if perimeter.included_lines.len() + perimeter.included_networks.len() == 0 {
             warn!(
                 "The ticket_use_id {} is ignored since it has no included line or network, \
@@ -478,24 +538,24 @@ fn construct_fare_v1_from_v2(fares: &Fares) -> Result<(BTreeSet<PriceV1>, BTreeS
     Ok((prices_v1, fares_v1))
 }
 
-fn do_write_fares_v1_from_v2(base_path: &path::Path, fares: &Fares) -> Result<()> {
+fn do_write_fares_v1_from_v2<W: FileWriter>(file_writer: &W, fares: &Fares) -> Result<()> {
     let (prices_v1, fares_v1) = construct_fare_v1_from_v2(fares)?;
 
     if prices_v1.is_empty() || fares_v1.is_empty() {
         bail!("Cannot convert Fares V2 to V1. Prices or fares are empty.")
     }
     do_write_fares_v1(
-        base_path,
+        file_writer,
         &Collection::new(prices_v1.into_iter().collect()),
         &Collection::default(),
         &Collection::new(fares_v1.into_iter().collect()),
     )
 }
 
-pub fn write_fares_v1(base_path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_fares_v1<W: FileWriter>(file_writer: &W, collections: &Collections) -> Result<()> {
     if has_fares_v2(collections) {
         return do_write_fares_v1_from_v2(
-            base_path,
+            file_writer,
             &Fares {
                 tickets: &collections.tickets,
                 ticket_prices: &collections.ticket_prices,
@@ -507,7 +567,7 @@ pub fn write_fares_v1(base_path: &path::Path, collections: &Collections) -> Resu
     }
     if has_fares_v1(collections) {
         return do_write_fares_v1(
-            base_path,
+            file_writer,
             &collections.prices_v1,
             &collections.od_fares_v1,
             &collections.fares_v1,
@@ -516,17 +576,23 @@ pub fn write_fares_v1(base_path: &path::Path, collections: &Collections) -> Resu
     Ok(())
 }
 
-pub fn write_stops(
-    path: &path::Path,
+pub fn write_stops<W: FileWriter>(
+    file_writer: &W,
     stop_points: &CollectionWithId<StopPoint>,
     stop_areas: &CollectionWithId<StopArea>,
     stop_locations: &CollectionWithId<StopLocation>,
+    version: NtfsVersion,
 ) -> Result<()> {
-    fn write_stop_locations(
-        wtr: &mut Writer<File>,
+    fn write_stop_locations<T: std::io::Write>(
+        wtr: &mut Writer<T>,
         stop_locations: &CollectionWithId<StopLocation>,
+        with_levels: bool,
     ) -> Result<()> {
-        for sl in stop_locations.values() {
+        // Before NTFS 0.12, the only stop locations are the geographic areas
+        for sl in stop_locations
+            .values()
+            .filter(|sl| with_levels || sl.stop_type == StopType::Zone)
+        {
             let (lon, lat) = sl.coord.into();
             wtr.serialize(Stop {
                 id: sl.id.clone(),
@@ -541,18 +607,20 @@ pub fn write_stops(
                 timezone: sl.timezone,
                 equipment_id: sl.equipment_id.clone(),
                 geometry_id: sl.geometry_id.clone(),
-                level_id: sl.level_id.clone(),
+                level_id: sl.level_id.clone().filter(|_| with_levels),
                 platform_code: None,
                 address_id: None,
             })?;
         }
         Ok(())
     }
+    let with_levels = version >= NtfsVersion::V0_12;
     let file = "stops.txt";
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file(file);
+    stage.record_rows(stop_points.len() + stop_areas.len() + stop_locations.len());
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
     for st in stop_points.values() {
         let location_type = if st.stop_type == StopType::Zone {
             StopLocationType::GeographicArea
@@ -572,7 +640,7 @@ pub fn write_stops(
             timezone: st.timezone,
             equipment_id: st.equipment_id.clone(),
             geometry_id: st.geometry_id.clone(),
-            level_id: st.level_id.clone(),
+            level_id: st.level_id.clone().filter(|_| with_levels),
             platform_code: st.platform_code.clone(),
             address_id: st.address_id.clone(),
         })
@@ -593,13 +661,13 @@ pub fn write_stops(
             timezone: sa.timezone,
             equipment_id: sa.equipment_id.clone(),
             geometry_id: sa.geometry_id.clone(),
-            level_id: sa.level_id.clone(),
+            level_id: sa.level_id.clone().filter(|_| with_levels),
             platform_code: None,
             address_id: None,
         })
         .with_context(|| format!("Error reading {:?}", path))?;
     }
-    write_stop_locations(&mut wtr, stop_locations)
+    write_stop_locations(&mut wtr, stop_locations, with_levels)
         .with_context(|| format!("Error reading {:?}", path))?;
     wtr.flush()
         .with_context(|| format!("Error reading {:?}", path))?;
@@ -616,7 +684,7 @@ where
     T: Id<T> + CommentLinks + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collection.values() {
+    for obj in sorted_by_id(collection) {
         for comment_id in obj.comment_links().iter() {
             wtr.serialize(CommentLink {
                 object_id: obj.id().to_string(),
@@ -630,18 +698,53 @@ where
     Ok(())
 }
 
+/// The identifiers of the stop times written in `stop_times.txt`, an
+/// identifier `<trip_id>-<sequence>` being created for each commented stop
+/// time without one, so that its comments can be linked.
+pub fn stop_time_ids_with_comments(
+    collections: &Collections,
+) -> Cow<'_, HashMap<(IdString, u32), IdString>> {
+    let mut missing: Vec<&(IdString, u32)> = collections
+        .stop_time_comments
+        .keys()
+        .filter(|vj_sequence| !collections.stop_time_ids.contains_key(*vj_sequence))
+        .collect();
+    if missing.is_empty() {
+        return Cow::Borrowed(&collections.stop_time_ids);
+    }
+    // sorted so that the suffixes of the colliding identifiers are stable
+    missing.sort_unstable();
+    let mut stop_time_ids = collections.stop_time_ids.clone();
+    let mut used: HashSet<String> = stop_time_ids.values().map(IdString::to_string).collect();
+    for (vj_id, sequence) in missing {
+        let base_id = format!("{}-{}", vj_id, sequence);
+        let mut stop_time_id = base_id.clone();
+        let mut suffix = 0;
+        while used.contains(&stop_time_id) {
+            suffix += 1;
+            stop_time_id = format!("{}-{}", base_id, suffix);
+        }
+        used.insert(stop_time_id.clone());
+        stop_time_ids.insert((vj_id.clone(), *sequence), IdString::from(stop_time_id));
+    }
+    Cow::Owned(stop_time_ids)
+}
+
 fn write_stop_time_comment_links<W>(
     wtr: &mut csv::Writer<W>,
-    stop_time_ids: &HashMap<(String, u32), String>,
-    stop_time_comments: &HashMap<(String, u32), String>,
+    stop_time_ids: &HashMap<(IdString, u32), IdString>,
+    stop_time_comments: &HashMap<(IdString, u32), IdString>,
     path: &path::Path,
 ) -> Result<()>
 where
     W: ::std::io::Write,
 {
-    for (idx_sequence, id_comment) in stop_time_comments {
-        let st_id = &stop_time_ids[idx_sequence];
-
+    let mut comment_links: Vec<(&IdString, &IdString)> = stop_time_comments
+        .iter()
+        .map(|(idx_sequence, id_comment)| (&stop_time_ids[idx_sequence], id_comment))
+        .collect();
+    comment_links.sort_unstable();
+    for (st_id, id_comment) in comment_links {
         wtr.serialize(CommentLink {
             object_id: st_id.to_string(),
             object_type: ObjectType::StopTime,
@@ -653,20 +756,24 @@ where
     Ok(())
 }
 
-pub fn write_comments(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_comments<W: FileWriter>(
+    file_writer: &W,
+    collections: &Collections,
+    stop_time_ids: &HashMap<(IdString, u32), IdString>,
+) -> Result<()> {
     if collections.comments.is_empty() {
         return Ok(());
     }
     info!("Writing comments.txt and comment_links.txt");
+    let _stage = timing::write_file("comment_links.txt");
 
-    let comments_path = path.join("comments.txt");
-    let comment_links_path = path.join("comment_links.txt");
+    let (comments_writer, comments_path) = file_writer.create_file("comments.txt")?;
+    let (comment_links_writer, comment_links_path) =
+        file_writer.create_file("comment_links.txt")?;
 
-    let mut c_wtr = csv::Writer::from_path(&comments_path)
-        .with_context(|| format!("Error reading {:?}", comments_path))?;
-    let mut cl_wtr = csv::Writer::from_path(&comment_links_path)
-        .with_context(|| format!("Error reading {:?}", comment_links_path))?;
-    for c in collections.comments.values() {
+    let mut c_wtr = csv::Writer::from_writer(comments_writer);
+    let mut cl_wtr = csv::Writer::from_writer(comment_links_writer);
+    for c in sorted_by_id(&collections.comments) {
         c_wtr
             .serialize(c)
             .with_context(|| format!("Error reading {:?}", comments_path))?;
@@ -700,12 +807,16 @@ pub fn write_comments(path: &path::Path, collections: &Collections) -> Result<()
 
     write_stop_time_comment_links(
         &mut cl_wtr,
-        &collections.stop_time_ids,
+        stop_time_ids,
         &collections.stop_time_comments,
         &comment_links_path,
     )?;
 
-    // TODO: add line_groups
+    write_comment_links_from_collection_with_id(
+        &mut cl_wtr,
+        &collections.line_groups,
+        &comment_links_path,
+    )?;
 
     cl_wtr
         .flush()
@@ -726,7 +837,7 @@ where
     T: Id<T> + Codes + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collections.values() {
+    for obj in sorted_by_id(collections) {
         for c in obj.codes() {
             wtr.serialize(Code {
                 object_id: obj.id().to_string(),
@@ -741,7 +852,7 @@ where
     Ok(())
 }
 
-pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_codes<W: FileWriter>(file_writer: &W, collections: &Collections) -> Result<()> {
     fn collection_has_no_codes<T: Codes>(collection: &CollectionWithId<T>) -> bool {
         collection.values().all(|c| c.codes().is_empty())
     }
@@ -752,16 +863,19 @@ pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
         && collection_has_no_codes(&collections.routes)
         && collection_has_no_codes(&collections.vehicle_journeys)
         && collection_has_no_codes(&collections.companies)
+        && collection_has_no_codes(&collections.commercial_modes)
+        && collection_has_no_codes(&collections.physical_modes)
+        && collection_has_no_codes(&collections.contributors)
     {
         return Ok(());
     }
 
     info!("Writing object_codes.txt");
+    let _stage = timing::write_file("object_codes.txt");
 
-    let path = path.join("object_codes.txt");
+    let (writer, path) = file_writer.create_file("object_codes.txt")?;
 
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut wtr = csv::Writer::from_writer(writer);
     write_codes_from_collection_with_id(&mut wtr, &collections.stop_areas, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.stop_points, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.networks, &path)?;
@@ -769,6 +883,9 @@ pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
     write_codes_from_collection_with_id(&mut wtr, &collections.routes, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.vehicle_journeys, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.companies, &path)?;
+    write_codes_from_collection_with_id(&mut wtr, &collections.commercial_modes, &path)?;
+    write_codes_from_collection_with_id(&mut wtr, &collections.physical_modes, &path)?;
+    write_codes_from_collection_with_id(&mut wtr, &collections.contributors, &path)?;
 
     wtr.flush()
         .with_context(|| format!("Error reading {:?}", path))?;
@@ -785,7 +902,7 @@ where
     T: Id<T> + Properties + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collection.values() {
+    for obj in sorted_by_id(collection) {
         for c in obj.properties() {
             wtr.serialize(ObjectProperty {
                 object_id: obj.id().to_string(),
@@ -800,7 +917,10 @@ where
     Ok(())
 }
 
-pub fn write_object_properties(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_object_properties<W: FileWriter>(
+    file_writer: &W,
+    collections: &Collections,
+) -> Result<()> {
     fn collection_has_no_object_properties<T: Properties>(
         collection: &CollectionWithId<T>,
     ) -> bool {
@@ -816,11 +936,11 @@ pub fn write_object_properties(path: &path::Path, collections: &Collections) ->
     }
 
     info!("Writing object_properties.txt");
+    let _stage = timing::write_file("object_properties.txt");
 
-    let path = path.join("object_properties.txt");
+    let (writer, path) = file_writer.create_file("object_properties.txt")?;
 
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut wtr = csv::Writer::from_writer(writer);
     write_object_properties_from_collection_with_id(&mut wtr, &collections.stop_areas, &path)?;
     write_object_properties_from_collection_with_id(&mut wtr, &collections.stop_points, &path)?;
     write_object_properties_from_collection_with_id(&mut wtr, &collections.lines, &path)?;
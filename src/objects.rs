@@ -31,6 +31,8 @@ use std::str::FromStr;
 use thiserror::Error;
 use typed_index_collection::{impl_id, impl_with_id, Idx, WithId};
 
+pub use crate::id_string::IdString;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ObjectType {
@@ -45,6 +47,9 @@ pub enum ObjectType {
     LineGroup,
     Ticket,
     Company,
+    CommercialMode,
+    PhysicalMode,
+    Contributor,
 }
 
 pub trait GetObjectType {
@@ -64,12 +69,16 @@ impl ObjectType {
             ObjectType::LineGroup => "line_group",
             ObjectType::Ticket => "ticket",
             ObjectType::Company => "company",
+            ObjectType::CommercialMode => "commercial_mode",
+            ObjectType::PhysicalMode => "physical_mode",
+            ObjectType::Contributor => "contributor",
         }
     }
 }
 
-// We use a BTreeSet<(String,String)> because Hash{Map,Set} are memory costy.
-pub type KeysValues = BTreeSet<(String, String)>;
+// We use a BTreeSet<(IdString,String)> because Hash{Map,Set} are memory costy.
+// The systems of the codes are repeated on many objects and are interned.
+pub type KeysValues = BTreeSet<(IdString, String)>;
 
 pub trait Codes {
     fn codes(&self) -> &KeysValues;
@@ -147,7 +156,7 @@ macro_rules! impl_comment_links {
     };
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Contributor {
     #[serde(rename = "contributor_id")]
     pub id: String,
@@ -157,6 +166,8 @@ pub struct Contributor {
     pub license: Option<String>,
     #[serde(rename = "contributor_website")]
     pub website: Option<String>,
+    #[serde(skip)]
+    pub codes: KeysValues,
 }
 
 impl AddPrefix for Contributor {
@@ -172,14 +183,22 @@ impl Default for Contributor {
             name: "Default contributor".to_string(),
             license: Some("Unknown license".to_string()),
             website: None,
+            codes: KeysValues::default(),
         }
     }
 }
 
 impl_with_id!(Contributor);
 impl_id!(Contributor);
+impl_codes!(Contributor);
+
+impl GetObjectType for Contributor {
+    fn get_object_type() -> ObjectType {
+        ObjectType::Contributor
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum DatasetType {
     #[serde(rename = "0")]
     Theorical,
@@ -210,7 +229,7 @@ impl Default for ValidityPeriod {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Dataset {
     #[serde(rename = "dataset_id")]
     pub id: String,
@@ -292,7 +311,7 @@ impl WithId for Dataset {
     }
 }
 
-#[derive(Derivative, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Derivative, Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[derivative(Default)]
 pub struct CommercialMode {
     #[derivative(Default(value = "\"default_commercial_mode\".into()"))]
@@ -301,11 +320,20 @@ pub struct CommercialMode {
     #[derivative(Default(value = "\"default commercial mode\".into()"))]
     #[serde(rename = "commercial_mode_name")]
     pub name: String,
+    #[serde(skip)]
+    pub codes: KeysValues,
 }
 impl_id!(CommercialMode);
+impl_codes!(CommercialMode);
 
 impl_with_id!(CommercialMode);
 
+impl GetObjectType for CommercialMode {
+    fn get_object_type() -> ObjectType {
+        ObjectType::CommercialMode
+    }
+}
+
 #[derive(Clone, Derivative, Serialize, Deserialize, Debug)]
 #[derivative(Default)]
 pub struct PhysicalMode {
@@ -316,9 +344,18 @@ pub struct PhysicalMode {
     #[serde(rename = "physical_mode_name")]
     pub name: String,
     pub co2_emission: Option<f32>,
+    #[serde(skip)]
+    pub codes: KeysValues,
 }
 
 impl_id!(PhysicalMode);
+impl_codes!(PhysicalMode);
+
+impl GetObjectType for PhysicalMode {
+    fn get_object_type() -> ObjectType {
+        ObjectType::PhysicalMode
+    }
+}
 
 impl Hash for PhysicalMode {
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -372,6 +409,7 @@ pub struct Network {
     pub address: Option<String>,
     #[serde(rename = "network_sort_order")]
     pub sort_order: Option<u32>,
+    pub branding_id: Option<String>,
 }
 
 impl_id!(Network);
@@ -387,6 +425,10 @@ impl GetObjectType for Network {
 impl AddPrefix for Network {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.id = prefix_conf.referential_prefix(self.id.as_str());
+        self.branding_id = self
+            .branding_id
+            .take()
+            .map(|id| prefix_conf.referential_prefix(id.as_str()));
     }
 }
 
@@ -499,6 +541,7 @@ pub struct Line {
     pub opening_time: Option<Time>,
     #[serde(rename = "line_closing_time")]
     pub closing_time: Option<Time>,
+    pub branding_id: Option<String>,
 }
 
 impl_id!(Line);
@@ -512,6 +555,10 @@ impl AddPrefix for Line {
             .geometry_id
             .take()
             .map(|id| prefix_conf.schedule_prefix(id.as_str()));
+        self.branding_id = self
+            .branding_id
+            .take()
+            .map(|id| prefix_conf.referential_prefix(id.as_str()));
         self.comment_links.prefix(prefix_conf);
     }
 }
@@ -527,6 +574,53 @@ impl GetObjectType for Line {
     }
 }
 
+/// A group of lines, `main_line_id` being its most representative line
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LineGroup {
+    #[serde(rename = "line_group_id")]
+    pub id: String,
+    #[serde(rename = "line_group_name")]
+    pub name: String,
+    pub main_line_id: String,
+    #[serde(skip)]
+    pub comment_links: CommentLinksT,
+}
+
+impl_id!(LineGroup);
+impl_id!(LineGroup, Line, main_line_id);
+impl_comment_links!(LineGroup);
+
+impl AddPrefix for LineGroup {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.referential_prefix(self.id.as_str());
+        self.main_line_id = prefix_conf.referential_prefix(self.main_line_id.as_str());
+        self.comment_links.prefix(prefix_conf);
+    }
+}
+
+impl GetObjectType for LineGroup {
+    fn get_object_type() -> ObjectType {
+        ObjectType::LineGroup
+    }
+}
+
+/// A line belonging to a line group
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct LineGroupLink {
+    pub line_group_id: String,
+    pub line_id: String,
+}
+
+impl_id!(LineGroupLink, LineGroup, line_group_id);
+impl_id!(LineGroupLink, Line, line_id);
+
+impl AddPrefix for LineGroupLink {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.line_group_id = prefix_conf.referential_prefix(self.line_group_id.as_str());
+        self.line_id = prefix_conf.referential_prefix(self.line_id.as_str());
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Derivative, Clone)]
 #[derivative(Default)]
 pub struct Route {
@@ -651,6 +745,15 @@ impl AddPrefix for VehicleJourney {
             .geometry_id
             .take()
             .map(|id| prefix_conf.schedule_prefix(id.as_str()));
+        for stop_time in &mut self.stop_times {
+            for booking_rule_id in stop_time
+                .pickup_booking_rule_id
+                .iter_mut()
+                .chain(&mut stop_time.drop_off_booking_rule_id)
+            {
+                *booking_rule_id = prefix_conf.schedule_prefix(booking_rule_id.as_str());
+            }
+        }
         self.comment_links.prefix(prefix_conf);
     }
 }
@@ -722,6 +825,12 @@ pub struct Frequency {
     pub start_time: Time,
     pub end_time: Time,
     pub headway_secs: u32,
+    #[serde(
+        default,
+        deserialize_with = "de_from_u8",
+        serialize_with = "ser_from_bool"
+    )]
+    pub exact_times: bool,
 }
 
 impl AddPrefix for Frequency {
@@ -734,7 +843,7 @@ impl AddPrefix for Frequency {
 pub enum TimeError {
     #[error("Time format should be HH:MM:SS")]
     WrongFormat,
-    #[error("Minutes and Seconds should be in [0..59] range")]
+    #[error("Minutes and Seconds should be in [0..59] range, and the time not too large")]
     WrongValue,
 }
 impl From<std::num::ParseIntError> for TimeError {
@@ -761,6 +870,24 @@ impl Time {
     pub fn total_seconds(self) -> u32 {
         self.0
     }
+    /// Number of days after the service day, a time like `49:30:00` being
+    /// 2 days after it
+    pub fn days_offset(self) -> u32 {
+        self.hours() / 24
+    }
+    /// The time in the day given by `days_offset`, between `00:00:00` and
+    /// `23:59:59`
+    pub fn time_of_day(self) -> Time {
+        Time(self.0 % (24 * 60 * 60))
+    }
+    /// `self + other`, or `None` on overflow
+    pub fn checked_add(self, other: Time) -> Option<Time> {
+        self.0.checked_add(other.0).map(Time)
+    }
+    /// `self - other`, or `None` if `other` is later than `self`
+    pub fn checked_sub(self, other: Time) -> Option<Time> {
+        self.0.checked_sub(other.0).map(Time)
+    }
 }
 impl Add for Time {
     type Output = Time;
@@ -801,7 +928,11 @@ impl FromStr for Time {
         if minutes > 59 || seconds > 59 {
             return Err(TimeError::WrongValue);
         }
-        Ok(Time::new(hours, minutes, seconds))
+        hours
+            .checked_mul(60 * 60)
+            .and_then(|seconds_of_hours| seconds_of_hours.checked_add(minutes * 60 + seconds))
+            .map(Time)
+            .ok_or(TimeError::WrongValue)
     }
 }
 
@@ -862,6 +993,10 @@ pub struct StopTime {
     pub drop_off_type: u8,
     pub local_zone_id: Option<u16>,
     pub precision: Option<StopTimePrecision>,
+    pub continuous_pickup: Option<u8>,
+    pub continuous_drop_off: Option<u8>,
+    pub pickup_booking_rule_id: Option<String>,
+    pub drop_off_booking_rule_id: Option<String>,
 }
 
 impl Ord for StopTime {
@@ -1261,9 +1396,11 @@ pub struct Pathway {
     pub is_bidirectional: bool,
     #[serde(default, deserialize_with = "de_option_positive_decimal")]
     pub length: Option<Decimal>,
+    #[serde(default, deserialize_with = "de_option_positive_integer")]
     pub traversal_time: Option<u32>,
     #[serde(default, deserialize_with = "de_option_non_null_integer")]
     pub stair_count: Option<i16>,
+    #[serde(default, deserialize_with = "de_option_float")]
     pub max_slope: Option<f32>,
     #[serde(default, deserialize_with = "de_option_positive_float")]
     pub min_width: Option<f32>,
@@ -1295,6 +1432,55 @@ impl AddPrefix for Level {
 }
 impl_id!(Level);
 
+/// When an on demand transport has to be booked
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Derivative)]
+#[derivative(Default)]
+pub enum BookingType {
+    /// Booking up to the departure
+    #[derivative(Default)]
+    #[serde(rename = "0")]
+    RealTime,
+    /// Booking on the same day, with a prior notice
+    #[serde(rename = "1")]
+    SameDay,
+    /// Booking on a previous day
+    #[serde(rename = "2")]
+    PriorDays,
+}
+
+/// How to book the pickup or the drop off of a stop time with on demand
+/// transport
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct BookingRule {
+    #[serde(rename = "booking_rule_id")]
+    pub id: String,
+    pub booking_type: BookingType,
+    /// Minimum number of minutes before the departure to book, for a
+    /// same day booking
+    pub prior_notice_duration_min: Option<u32>,
+    /// Maximum number of minutes before the departure to book, for a same
+    /// day booking
+    pub prior_notice_duration_max: Option<u32>,
+    /// Last day before the departure to book, for a booking on a previous
+    /// day
+    pub prior_notice_last_day: Option<u32>,
+    pub prior_notice_last_time: Option<Time>,
+    /// First day before the departure to book
+    pub prior_notice_start_day: Option<u32>,
+    pub prior_notice_start_time: Option<Time>,
+    pub message: Option<String>,
+    pub phone_number: Option<String>,
+    pub info_url: Option<String>,
+    pub booking_url: Option<String>,
+}
+
+impl AddPrefix for BookingRule {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.schedule_prefix(self.id.as_str());
+    }
+}
+impl_id!(BookingRule);
+
 pub type Date = chrono::NaiveDate;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -1325,6 +1511,53 @@ impl Calendar {
     pub fn overlaps(&self, other: &Self) -> bool {
         !self.dates.is_disjoint(&other.dates)
     }
+
+    /// Returns true if at least one date of the calendar is within the
+    /// validity period (bounds included)
+    pub fn overlaps_period(&self, period: &ValidityPeriod) -> bool {
+        period.start_date <= period.end_date
+            && self
+                .dates
+                .range(period.start_date..=period.end_date)
+                .next()
+                .is_some()
+    }
+
+    /// Returns true if the calendar has no date
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_empty()
+    }
+
+    /// Add all the dates of `other` to the calendar
+    pub fn union_with(&mut self, other: &Self) {
+        self.dates.extend(other.dates.iter().copied());
+    }
+
+    /// Only keep the dates also present in `other`
+    pub fn intersect_with(&mut self, other: &Self) {
+        self.dates.retain(|date| other.dates.contains(date));
+    }
+
+    /// Only keep the dates within the validity period (bounds included)
+    pub fn intersect_with_period(&mut self, period: &ValidityPeriod) {
+        self.dates
+            .retain(|date| (period.start_date..=period.end_date).contains(date));
+    }
+
+    /// Remove all the dates present in `other`
+    pub fn subtract(&mut self, other: &Self) {
+        self.dates.retain(|date| !other.dates.contains(date));
+    }
+
+    /// Returns a copy of the calendar with every date moved by `days` (which
+    /// can be negative)
+    pub fn shifted_by(&self, days: i64) -> Self {
+        let shift = chrono::Duration::days(days);
+        Calendar {
+            id: self.id.clone(),
+            dates: self.dates.iter().map(|date| *date + shift).collect(),
+        }
+    }
 }
 
 impl AddPrefix for Calendar {
@@ -1342,7 +1575,7 @@ impl WithId for Calendar {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Company {
     #[serde(rename = "company_id")]
     pub id: String,
@@ -1350,6 +1583,12 @@ pub struct Company {
     pub name: String,
     #[serde(rename = "company_address")]
     pub address: Option<String>,
+    #[serde(rename = "company_postcode")]
+    pub postcode: Option<String>,
+    #[serde(rename = "company_city")]
+    pub city: Option<String>,
+    #[serde(rename = "company_registration_number")]
+    pub registration_number: Option<String>,
     #[serde(rename = "company_url")]
     pub url: Option<String>,
     #[serde(rename = "company_mail")]
@@ -1369,6 +1608,9 @@ impl Default for Company {
             id: "default_company".to_string(),
             name: "Default Company".to_string(),
             address: None,
+            postcode: None,
+            city: None,
+            registration_number: None,
             url: None,
             mail: None,
             phone: None,
@@ -1391,7 +1633,7 @@ impl_with_id!(Company);
 
 #[derive(Derivative)]
 #[derivative(Default(bound = ""))]
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum CommentType {
     #[derivative(Default)]
@@ -1399,7 +1641,7 @@ pub enum CommentType {
     OnDemandTransport,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Default, Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Comment {
     #[serde(rename = "comment_id")]
     pub id: String,
@@ -1484,6 +1726,18 @@ impl Equipment {
     }
 }
 
+/// Whether a transfer between two stop points is possible, read from the
+/// optional `transfer_type` column of the NTFS 'transfers.txt' (with the
+/// values of the GTFS)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransferType {
+    #[default]
+    #[serde(rename = "0")]
+    Possible,
+    #[serde(rename = "3")]
+    NotPossible,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Derivative)]
 #[derivative(PartialEq)]
 pub struct Transfer {
@@ -1497,6 +1751,9 @@ pub struct Transfer {
     pub real_min_transfer_time: Option<u32>,
     #[derivative(PartialEq = "ignore")]
     pub equipment_id: Option<String>,
+    #[serde(default, deserialize_with = "de_with_empty_default")]
+    #[derivative(PartialEq = "ignore")]
+    pub transfer_type: TransferType,
 }
 
 impl AddPrefix for Transfer {
@@ -1510,6 +1767,26 @@ impl AddPrefix for Transfer {
     }
 }
 
+/// A transfer from the last stop of a vehicle journey to the first stop of
+/// the next one, the vehicle going on (GTFS `transfer_type` 4 and 5).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TripTransfer {
+    pub from_vehicle_journey_id: String,
+    pub to_vehicle_journey_id: String,
+    /// The passengers can stay on board, otherwise they must alight and
+    /// board again
+    pub in_seat: bool,
+}
+
+impl AddPrefix for TripTransfer {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.from_vehicle_journey_id =
+            prefix_conf.schedule_prefix(self.from_vehicle_journey_id.as_str());
+        self.to_vehicle_journey_id =
+            prefix_conf.schedule_prefix(self.to_vehicle_journey_id.as_str());
+    }
+}
+
 impl Hash for Transfer {
     fn hash<H: Hasher>(&self, state: &mut H) {
         (&self.from_stop_id, &self.to_stop_id).hash(state);
@@ -1573,7 +1850,7 @@ impl TripProperty {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Geometry {
     #[serde(rename = "geometry_id")]
     pub id: String,
@@ -1682,7 +1959,7 @@ impl AddPrefix for FareV1 {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Ticket {
     #[serde(rename = "ticket_id")]
     pub id: String,
@@ -1734,7 +2011,7 @@ impl AddPrefix for TicketPrice {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct TicketUse {
     #[serde(rename = "ticket_use_id")]
     pub id: String,
@@ -1771,7 +2048,10 @@ pub struct TicketUsePerimeter {
 impl AddPrefix for TicketUsePerimeter {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.ticket_use_id = prefix_conf.referential_prefix(self.ticket_use_id.as_str());
-        self.object_id = prefix_conf.referential_prefix(self.object_id.as_str());
+        // Commercial modes are shared between datasets and never prefixed
+        if self.object_type != ObjectType::CommercialMode {
+            self.object_id = prefix_conf.referential_prefix(self.object_id.as_str());
+        }
     }
 }
 
@@ -1794,12 +2074,16 @@ pub struct TicketUseRestriction {
 impl AddPrefix for TicketUseRestriction {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.ticket_use_id = prefix_conf.referential_prefix(self.ticket_use_id.as_str());
-        self.use_origin = prefix_conf.referential_prefix(self.use_origin.as_str());
-        self.use_destination = prefix_conf.referential_prefix(self.use_destination.as_str());
+        // The ends of a zone restriction are `fare_zone_id`, which are not
+        // prefixed, while the ends of an OD restriction are stop areas
+        if self.restriction_type == RestrictionType::OriginDestination {
+            self.use_origin = prefix_conf.referential_prefix(self.use_origin.as_str());
+            self.use_destination = prefix_conf.referential_prefix(self.use_destination.as_str());
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GridCalendar {
     #[serde(rename = "grid_calendar_id")]
     pub id: String,
@@ -1862,6 +2146,58 @@ pub struct GridPeriod {
 }
 impl_id!(GridPeriod, GridCalendar, grid_calendar_id);
 
+impl GridCalendar {
+    fn is_active_on(&self, weekday: chrono::Weekday) -> bool {
+        use chrono::Weekday::*;
+        match weekday {
+            Mon => self.monday,
+            Tue => self.tuesday,
+            Wed => self.wednesday,
+            Thu => self.thursday,
+            Fri => self.friday,
+            Sat => self.saturday,
+            Sun => self.sunday,
+        }
+    }
+
+    /// Expand the grid calendar into the dates on which it applies, from its
+    /// periods (bounds included) and exception dates. Only the periods and
+    /// exception dates referencing this grid calendar are considered.
+    pub fn dates<'a, P, E>(&self, periods: P, exception_dates: E) -> BTreeSet<Date>
+    where
+        P: IntoIterator<Item = &'a GridPeriod>,
+        E: IntoIterator<Item = &'a GridExceptionDate>,
+    {
+        use chrono::Datelike;
+        let mut dates = BTreeSet::new();
+        for period in periods
+            .into_iter()
+            .filter(|p| p.grid_calendar_id == self.id)
+        {
+            // walk day by day so that 29 February is neither skipped nor
+            // counted twice when the period spans a leap day
+            let mut date = period.start_date;
+            while date <= period.end_date {
+                if self.is_active_on(date.weekday()) {
+                    dates.insert(date);
+                }
+                date += chrono::Duration::days(1);
+            }
+        }
+        for exception_date in exception_dates
+            .into_iter()
+            .filter(|e| e.grid_calendar_id == self.id)
+        {
+            if exception_date.r#type {
+                dates.insert(exception_date.date);
+            } else {
+                dates.remove(&exception_date.date);
+            }
+        }
+        dates
+    }
+}
+
 impl AddPrefix for GridPeriod {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.grid_calendar_id = prefix_conf.referential_prefix(self.grid_calendar_id.as_str());
@@ -1884,6 +2220,52 @@ impl AddPrefix for GridRelCalendarLine {
     }
 }
 
+/// Expected occupancy of a line between two stop areas, on the days of a
+/// period and between two times
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+pub struct Occupancy {
+    pub line_id: String,
+    pub from_stop_area: String,
+    pub to_stop_area: String,
+    #[serde(
+        deserialize_with = "de_from_date_string",
+        serialize_with = "ser_from_naive_date"
+    )]
+    pub from_date: Date,
+    #[serde(
+        deserialize_with = "de_from_date_string",
+        serialize_with = "ser_from_naive_date"
+    )]
+    pub to_date: Date,
+    pub from_time: Time,
+    pub to_time: Time,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub monday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub tuesday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub wednesday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub thursday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub friday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub saturday: bool,
+    #[serde(deserialize_with = "de_from_u8", serialize_with = "ser_from_bool")]
+    pub sunday: bool,
+    /// Expected occupancy, in percent
+    pub occupancy: u8,
+}
+impl_id!(Occupancy, Line, line_id);
+
+impl AddPrefix for Occupancy {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.line_id = prefix_conf.referential_prefix(self.line_id.as_str());
+        self.from_stop_area = prefix_conf.referential_prefix(self.from_stop_area.as_str());
+        self.to_stop_area = prefix_conf.referential_prefix(self.to_stop_area.as_str());
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
 pub struct Address {
     #[serde(rename = "address_id")]
@@ -1900,6 +2282,52 @@ impl AddPrefix for Address {
     }
 }
 
+/// The commercial identity of lines or networks: the name they are
+/// marketed under, and a logo or a pictogram
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct Branding {
+    #[serde(rename = "branding_id")]
+    pub id: String,
+    #[serde(rename = "branding_name")]
+    pub name: String,
+    #[serde(rename = "branding_url")]
+    pub url: Option<String>,
+    /// URL of the logo, or code of the pictogram
+    #[serde(rename = "branding_image")]
+    pub image: Option<String>,
+}
+
+impl_id!(Branding);
+
+impl AddPrefix for Branding {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.referential_prefix(self.id.as_str());
+    }
+}
+
+/// The translation of a field of an object (the `name` of a stop, the
+/// `headsign` of a trip...) in a language
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Translation {
+    pub object_type: ObjectType,
+    pub object_id: String,
+    pub field_name: String,
+    /// An IETF BCP 47 language code, like `fr` or `en-US`
+    pub language: String,
+    pub translation: String,
+}
+
+impl AddPrefix for Translation {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.object_id = match self.object_type {
+            ObjectType::VehicleJourney => prefix_conf.schedule_prefix(self.object_id.as_str()),
+            // The modes are shared between datasets and never prefixed
+            ObjectType::CommercialMode | ObjectType::PhysicalMode => return,
+            _ => prefix_conf.referential_prefix(self.object_id.as_str()),
+        };
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1999,6 +2427,36 @@ mod tests {
         assert!(de("00:00:AA").is_err());
     }
 
+    #[test]
+    fn time_beyond_the_service_day() {
+        for (time, days_offset, time_of_day) in [
+            ("25:10:00", 1, "01:10:00"),
+            ("47:59:59", 1, "23:59:59"),
+            ("52:30:15", 2, "04:30:15"),
+        ] {
+            let parsed: Time = time.parse().unwrap();
+            assert_eq!(time, parsed.to_string());
+            assert_eq!(days_offset, parsed.days_offset());
+            assert_eq!(time_of_day, parsed.time_of_day().to_string());
+        }
+        assert!("1193047:00:00".parse::<Time>().is_err());
+    }
+
+    #[test]
+    fn time_checked_arithmetic() {
+        let time = Time::new(47, 30, 0);
+        assert_eq!(
+            Some(Time::new(49, 0, 0)),
+            time.checked_add(Time::new(1, 30, 0))
+        );
+        assert_eq!(
+            Some(Time::new(23, 30, 0)),
+            time.checked_sub(Time::new(24, 0, 0))
+        );
+        assert_eq!(None, Time::new(1, 0, 0).checked_sub(time));
+        assert_eq!(None, Time(u32::MAX).checked_add(Time::new(0, 0, 1)));
+    }
+
     // distance between COORD1 and COORD2 is 357.64 from
     // https://gps-coordinates.org/distance-between-coordinates.php
     const COORD1: Coord = Coord {
@@ -2034,6 +2492,201 @@ mod tests {
         );
     }
 
+    mod calendar_set_operations {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn calendar(days: &[u32]) -> Calendar {
+            Calendar {
+                id: "c".to_string(),
+                dates: days.iter().map(|d| Date::from_ymd(2020, 1, *d)).collect(),
+            }
+        }
+
+        #[test]
+        fn union_with() {
+            let mut c = calendar(&[1, 2]);
+            c.union_with(&calendar(&[2, 3]));
+            assert_eq!(calendar(&[1, 2, 3]), c);
+
+            let mut c = calendar(&[]);
+            c.union_with(&calendar(&[1]));
+            assert_eq!(calendar(&[1]), c);
+
+            let mut c = calendar(&[1]);
+            c.union_with(&calendar(&[]));
+            assert_eq!(calendar(&[1]), c);
+        }
+
+        #[test]
+        fn intersect_with() {
+            let mut c = calendar(&[1, 2, 3]);
+            c.intersect_with(&calendar(&[2, 3, 4]));
+            assert_eq!(calendar(&[2, 3]), c);
+
+            let mut c = calendar(&[1, 2]);
+            c.intersect_with(&calendar(&[]));
+            assert!(c.is_empty());
+
+            let mut c = calendar(&[]);
+            c.intersect_with(&calendar(&[1]));
+            assert!(c.is_empty());
+        }
+
+        #[test]
+        fn intersect_with_period() {
+            let period = |start, end| ValidityPeriod {
+                start_date: Date::from_ymd_opt(2020, 1, start).unwrap(),
+                end_date: Date::from_ymd_opt(2020, 1, end).unwrap(),
+            };
+            let mut c = calendar(&[1, 2, 3, 10]);
+            c.intersect_with_period(&period(2, 3));
+            assert_eq!(calendar(&[2, 3]), c);
+
+            let mut c = calendar(&[1, 10]);
+            c.intersect_with_period(&period(2, 9));
+            assert!(c.is_empty());
+
+            let mut c = calendar(&[]);
+            c.intersect_with_period(&period(1, 31));
+            assert!(c.is_empty());
+        }
+
+        #[test]
+        fn subtract() {
+            let mut c = calendar(&[1, 2, 3]);
+            c.subtract(&calendar(&[2, 4]));
+            assert_eq!(calendar(&[1, 3]), c);
+
+            let mut c = calendar(&[1, 2]);
+            c.subtract(&calendar(&[]));
+            assert_eq!(calendar(&[1, 2]), c);
+
+            let mut c = calendar(&[]);
+            c.subtract(&calendar(&[1]));
+            assert!(c.is_empty());
+        }
+
+        #[test]
+        fn shifted_by() {
+            assert_eq!(calendar(&[2, 3, 11]), calendar(&[1, 2, 10]).shifted_by(1));
+            assert_eq!(calendar(&[1, 2, 10]), calendar(&[2, 3, 11]).shifted_by(-1));
+            assert_eq!(
+                vec![Date::from_ymd(2020, 2, 29), Date::from_ymd(2020, 3, 1)],
+                calendar(&[30, 31])
+                    .shifted_by(30)
+                    .dates
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            );
+            assert!(calendar(&[]).shifted_by(1).is_empty());
+        }
+
+        #[test]
+        fn is_empty() {
+            assert!(calendar(&[]).is_empty());
+            assert!(!calendar(&[1]).is_empty());
+        }
+
+        #[test]
+        fn overlaps_period() {
+            let period = |start, end| ValidityPeriod {
+                start_date: Date::from_ymd(2020, 1, start),
+                end_date: Date::from_ymd(2020, 1, end),
+            };
+            assert!(calendar(&[1, 10]).overlaps_period(&period(10, 12)));
+            assert!(calendar(&[1, 10]).overlaps_period(&period(1, 1)));
+            assert!(!calendar(&[1, 10]).overlaps_period(&period(2, 9)));
+            assert!(!calendar(&[]).overlaps_period(&period(1, 31)));
+            assert!(!calendar(&[1, 10]).overlaps_period(&period(10, 1)));
+        }
+    }
+
+    mod grid_calendar_dates {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn grid_calendar() -> GridCalendar {
+            GridCalendar {
+                id: "gc".to_string(),
+                name: "Every day".to_string(),
+                monday: true,
+                tuesday: true,
+                wednesday: true,
+                thursday: true,
+                friday: true,
+                saturday: true,
+                sunday: true,
+            }
+        }
+
+        fn grid_period(start_date: Date, end_date: Date) -> GridPeriod {
+            GridPeriod {
+                grid_calendar_id: "gc".to_string(),
+                start_date,
+                end_date,
+            }
+        }
+
+        #[test]
+        fn period_spanning_leap_day() {
+            let periods = vec![grid_period(
+                Date::from_ymd(2024, 2, 28),
+                Date::from_ymd(2024, 3, 1),
+            )];
+            let dates = grid_calendar().dates(&periods, &[]);
+            let expected: BTreeSet<Date> = vec![
+                Date::from_ymd(2024, 2, 28),
+                Date::from_ymd(2024, 2, 29),
+                Date::from_ymd(2024, 3, 1),
+            ]
+            .into_iter()
+            .collect();
+            assert_eq!(expected, dates);
+        }
+
+        #[test]
+        fn exception_on_leap_day() {
+            let periods = vec![grid_period(
+                Date::from_ymd(2024, 2, 28),
+                Date::from_ymd(2024, 3, 1),
+            )];
+            let removed = vec![GridExceptionDate {
+                grid_calendar_id: "gc".to_string(),
+                date: Date::from_ymd(2024, 2, 29),
+                r#type: false,
+            }];
+            let dates = grid_calendar().dates(&periods, &removed);
+            let expected: BTreeSet<Date> =
+                vec![Date::from_ymd(2024, 2, 28), Date::from_ymd(2024, 3, 1)]
+                    .into_iter()
+                    .collect();
+            assert_eq!(expected, dates);
+
+            // 2024-02-29 is a Thursday
+            let mut thursdays = grid_calendar();
+            thursdays.monday = false;
+            thursdays.wednesday = false;
+            thursdays.friday = false;
+            let added = vec![GridExceptionDate {
+                grid_calendar_id: "gc".to_string(),
+                date: Date::from_ymd(2024, 2, 29),
+                r#type: true,
+            }];
+            let dates = thursdays.dates(&[], &added);
+            let expected: BTreeSet<Date> = vec![Date::from_ymd(2024, 2, 29)].into_iter().collect();
+            assert_eq!(expected, dates);
+        }
+
+        #[test]
+        fn periods_of_other_grid_calendars_are_ignored() {
+            let mut period = grid_period(Date::from_ymd(2023, 2, 28), Date::from_ymd(2023, 3, 1));
+            period.grid_calendar_id = "other".to_string();
+            let dates = grid_calendar().dates(&[period], &[]);
+            assert!(dates.is_empty());
+        }
+    }
+
     mod sort_and_check_stop_times {
         use super::*;
 
@@ -2056,6 +2709,10 @@ mod tests {
                     drop_off_type: 0,
                     local_zone_id: None,
                     precision: None,
+                    continuous_pickup: None,
+                    continuous_drop_off: None,
+                    pickup_booking_rule_id: None,
+                    drop_off_booking_rule_id: None,
                 })
                 .collect()
         }
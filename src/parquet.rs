@@ -0,0 +1,547 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the main collections to [Parquet](https://parquet.apache.org/)
+//! files with typed columns, for analytics tools like DuckDB or Spark.
+//!
+//! This module is available with the `parquet` feature. The export can't be
+//! read back into a [Model](crate::Model).
+//!
+//! [write] creates the following files, the columns marked with `?` being
+//! nullable. The times are a number of seconds since midnight, and can be
+//! greater than 24h.
+//!
+//! `stops.parquet`, the stop areas then the stop points:
+//! * `stop_id`: Utf8
+//! * `stop_name`: Utf8
+//! * `location_type`: UInt8, `0` for a stop point, `1` for a stop area
+//! * `parent_station` ?: Utf8, the stop area of a stop point
+//! * `stop_lon`: Float64
+//! * `stop_lat`: Float64
+//! * `stop_timezone` ?: Utf8
+//! * `visible`: Boolean
+//! * `fare_zone_id` ?: Utf8
+//! * `platform_code` ?: Utf8
+//!
+//! `lines.parquet`:
+//! * `line_id`: Utf8
+//! * `line_code` ?: Utf8
+//! * `line_name`: Utf8
+//! * `network_id`: Utf8
+//! * `commercial_mode_id`: Utf8
+//! * `line_color` ?: Utf8, like `FF0000`
+//! * `line_text_color` ?: Utf8
+//! * `line_sort_order` ?: UInt32
+//!
+//! `routes.parquet`:
+//! * `route_id`: Utf8
+//! * `route_name`: Utf8
+//! * `direction_type` ?: Utf8
+//! * `line_id`: Utf8
+//! * `destination_id` ?: Utf8
+//!
+//! `trips.parquet`, the vehicle journeys:
+//! * `trip_id`: Utf8
+//! * `route_id`: Utf8
+//! * `service_id`: Utf8
+//! * `physical_mode_id`: Utf8
+//! * `company_id`: Utf8
+//! * `dataset_id`: Utf8
+//! * `trip_headsign` ?: Utf8
+//! * `trip_short_name` ?: Utf8
+//! * `block_id` ?: Utf8
+//!
+//! `stop_times.parquet`:
+//! * `trip_id`: Utf8
+//! * `stop_sequence`: UInt32
+//! * `stop_id`: Utf8
+//! * `arrival_time`: UInt32
+//! * `departure_time`: UInt32
+//! * `boarding_duration`: UInt16
+//! * `alighting_duration`: UInt16
+//! * `pickup_type`: UInt8
+//! * `drop_off_type`: UInt8
+//! * `local_zone_id` ?: UInt16
+//!
+//! `calendar_dates.parquet`, one row for each date of each calendar:
+//! * `service_id`: Utf8
+//! * `date`: Date32
+
+use crate::{
+    model::Collections,
+    objects::{Line, Route, StopArea, StopPoint, VehicleJourney},
+    Result,
+};
+use ::parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use anyhow::Context;
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, RecordBatch, StringArray, UInt16Array,
+    UInt32Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::Datelike;
+use std::{fs::File, path::Path, sync::Arc};
+use tracing::info;
+
+// Number of days from 0001-01-01 to 1970-01-01
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+// The stop times are written by batches of this number of vehicle journeys
+const VEHICLE_JOURNEYS_BY_BATCH: usize = 1024;
+
+fn field(name: &str, data_type: DataType) -> Field {
+    Field::new(name, data_type, false)
+}
+
+fn nullable_field(name: &str, data_type: DataType) -> Field {
+    Field::new(name, data_type, true)
+}
+
+fn strings<'a>(values: impl IntoIterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(values.into_iter().map(Some).collect::<StringArray>())
+}
+
+fn optional_strings<'a>(values: impl IntoIterator<Item = Option<&'a str>>) -> ArrayRef {
+    Arc::new(values.into_iter().collect::<StringArray>())
+}
+
+fn stops_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("stop_id", DataType::Utf8),
+        field("stop_name", DataType::Utf8),
+        field("location_type", DataType::UInt8),
+        nullable_field("parent_station", DataType::Utf8),
+        field("stop_lon", DataType::Float64),
+        field("stop_lat", DataType::Float64),
+        nullable_field("stop_timezone", DataType::Utf8),
+        field("visible", DataType::Boolean),
+        nullable_field("fare_zone_id", DataType::Utf8),
+        nullable_field("platform_code", DataType::Utf8),
+    ]))
+}
+
+fn stops_batch(collections: &Collections) -> Result<RecordBatch> {
+    let stop_areas: Vec<&StopArea> = collections.stop_areas.values().collect();
+    let stop_points: Vec<&StopPoint> = collections.stop_points.values().collect();
+    let stop_area_ids = stop_areas.iter().map(|sa| sa.id.as_str());
+    let stop_point_ids = stop_points.iter().map(|sp| sp.id.as_str());
+    let stop_area_names = stop_areas.iter().map(|sa| sa.name.as_str());
+    let stop_point_names = stop_points.iter().map(|sp| sp.name.as_str());
+    let location_types = std::iter::repeat_n(1, stop_areas.len())
+        .chain(std::iter::repeat_n(0, stop_points.len()))
+        .collect::<UInt8Array>();
+    let parent_stations = std::iter::repeat_n(None, stop_areas.len())
+        .chain(stop_points.iter().map(|sp| Some(sp.stop_area_id.as_str())));
+    let coords: Vec<_> = stop_areas
+        .iter()
+        .map(|sa| sa.coord)
+        .chain(stop_points.iter().map(|sp| sp.coord))
+        .collect();
+    let timezones = stop_areas
+        .iter()
+        .map(|sa| sa.timezone)
+        .chain(stop_points.iter().map(|sp| sp.timezone))
+        .map(|timezone| timezone.map(|tz| tz.name()));
+    let visibles = stop_areas
+        .iter()
+        .map(|sa| Some(sa.visible))
+        .chain(stop_points.iter().map(|sp| Some(sp.visible)))
+        .collect::<BooleanArray>();
+    let fare_zone_ids = std::iter::repeat_n(None, stop_areas.len())
+        .chain(stop_points.iter().map(|sp| sp.fare_zone_id.as_deref()));
+    let platform_codes = std::iter::repeat_n(None, stop_areas.len())
+        .chain(stop_points.iter().map(|sp| sp.platform_code.as_deref()));
+
+    Ok(RecordBatch::try_new(
+        stops_schema(),
+        vec![
+            strings(stop_area_ids.chain(stop_point_ids)),
+            strings(stop_area_names.chain(stop_point_names)),
+            Arc::new(location_types),
+            optional_strings(parent_stations),
+            Arc::new(coords.iter().map(|c| c.lon).collect::<Float64Array>()),
+            Arc::new(coords.iter().map(|c| c.lat).collect::<Float64Array>()),
+            optional_strings(timezones),
+            Arc::new(visibles),
+            optional_strings(fare_zone_ids),
+            optional_strings(platform_codes),
+        ],
+    )?)
+}
+
+fn lines_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("line_id", DataType::Utf8),
+        nullable_field("line_code", DataType::Utf8),
+        field("line_name", DataType::Utf8),
+        field("network_id", DataType::Utf8),
+        field("commercial_mode_id", DataType::Utf8),
+        nullable_field("line_color", DataType::Utf8),
+        nullable_field("line_text_color", DataType::Utf8),
+        nullable_field("line_sort_order", DataType::UInt32),
+    ]))
+}
+
+fn lines_batch(collections: &Collections) -> Result<RecordBatch> {
+    let lines: Vec<&Line> = collections.lines.values().collect();
+    let colors: Vec<_> = lines
+        .iter()
+        .map(|line| line.color.as_ref().map(ToString::to_string))
+        .collect();
+    let text_colors: Vec<_> = lines
+        .iter()
+        .map(|line| line.text_color.as_ref().map(ToString::to_string))
+        .collect();
+    Ok(RecordBatch::try_new(
+        lines_schema(),
+        vec![
+            strings(lines.iter().map(|line| line.id.as_str())),
+            optional_strings(lines.iter().map(|line| line.code.as_deref())),
+            strings(lines.iter().map(|line| line.name.as_str())),
+            strings(lines.iter().map(|line| line.network_id.as_str())),
+            strings(lines.iter().map(|line| line.commercial_mode_id.as_str())),
+            optional_strings(colors.iter().map(Option::as_deref)),
+            optional_strings(text_colors.iter().map(Option::as_deref)),
+            Arc::new(
+                lines
+                    .iter()
+                    .map(|line| line.sort_order)
+                    .collect::<UInt32Array>(),
+            ),
+        ],
+    )?)
+}
+
+fn routes_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("route_id", DataType::Utf8),
+        field("route_name", DataType::Utf8),
+        nullable_field("direction_type", DataType::Utf8),
+        field("line_id", DataType::Utf8),
+        nullable_field("destination_id", DataType::Utf8),
+    ]))
+}
+
+fn routes_batch(collections: &Collections) -> Result<RecordBatch> {
+    let routes: Vec<&Route> = collections.routes.values().collect();
+    Ok(RecordBatch::try_new(
+        routes_schema(),
+        vec![
+            strings(routes.iter().map(|route| route.id.as_str())),
+            strings(routes.iter().map(|route| route.name.as_str())),
+            optional_strings(routes.iter().map(|route| route.direction_type.as_deref())),
+            strings(routes.iter().map(|route| route.line_id.as_str())),
+            optional_strings(routes.iter().map(|route| route.destination_id.as_deref())),
+        ],
+    )?)
+}
+
+fn trips_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("trip_id", DataType::Utf8),
+        field("route_id", DataType::Utf8),
+        field("service_id", DataType::Utf8),
+        field("physical_mode_id", DataType::Utf8),
+        field("company_id", DataType::Utf8),
+        field("dataset_id", DataType::Utf8),
+        nullable_field("trip_headsign", DataType::Utf8),
+        nullable_field("trip_short_name", DataType::Utf8),
+        nullable_field("block_id", DataType::Utf8),
+    ]))
+}
+
+fn trips_batch(collections: &Collections) -> Result<RecordBatch> {
+    let vjs: Vec<&VehicleJourney> = collections.vehicle_journeys.values().collect();
+    Ok(RecordBatch::try_new(
+        trips_schema(),
+        vec![
+            strings(vjs.iter().map(|vj| vj.id.as_str())),
+            strings(vjs.iter().map(|vj| vj.route_id.as_str())),
+            strings(vjs.iter().map(|vj| vj.service_id.as_str())),
+            strings(vjs.iter().map(|vj| vj.physical_mode_id.as_str())),
+            strings(vjs.iter().map(|vj| vj.company_id.as_str())),
+            strings(vjs.iter().map(|vj| vj.dataset_id.as_str())),
+            optional_strings(vjs.iter().map(|vj| vj.headsign.as_deref())),
+            optional_strings(vjs.iter().map(|vj| vj.short_name.as_deref())),
+            optional_strings(vjs.iter().map(|vj| vj.block_id.as_deref())),
+        ],
+    )?)
+}
+
+fn stop_times_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("trip_id", DataType::Utf8),
+        field("stop_sequence", DataType::UInt32),
+        field("stop_id", DataType::Utf8),
+        field("arrival_time", DataType::UInt32),
+        field("departure_time", DataType::UInt32),
+        field("boarding_duration", DataType::UInt16),
+        field("alighting_duration", DataType::UInt16),
+        field("pickup_type", DataType::UInt8),
+        field("drop_off_type", DataType::UInt8),
+        nullable_field("local_zone_id", DataType::UInt16),
+    ]))
+}
+
+fn stop_times_batch(collections: &Collections, vjs: &[&VehicleJourney]) -> Result<RecordBatch> {
+    let stop_times: Vec<_> = vjs
+        .iter()
+        .flat_map(|vj| vj.stop_times.iter().map(move |st| (vj.id.as_str(), st)))
+        .collect();
+    Ok(RecordBatch::try_new(
+        stop_times_schema(),
+        vec![
+            strings(stop_times.iter().map(|(vj_id, _)| *vj_id)),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.sequence)
+                    .collect::<UInt32Array>(),
+            ),
+            strings(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| collections.stop_points[st.stop_point_idx].id.as_str()),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.arrival_time.total_seconds())
+                    .collect::<UInt32Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.departure_time.total_seconds())
+                    .collect::<UInt32Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.boarding_duration)
+                    .collect::<UInt16Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.alighting_duration)
+                    .collect::<UInt16Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.pickup_type)
+                    .collect::<UInt8Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.drop_off_type)
+                    .collect::<UInt8Array>(),
+            ),
+            Arc::new(
+                stop_times
+                    .iter()
+                    .map(|(_, st)| st.local_zone_id)
+                    .collect::<UInt16Array>(),
+            ),
+        ],
+    )?)
+}
+
+fn calendar_dates_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field("service_id", DataType::Utf8),
+        field("date", DataType::Date32),
+    ]))
+}
+
+fn calendar_dates_batch(collections: &Collections) -> Result<RecordBatch> {
+    let dates: Vec<_> = collections
+        .calendars
+        .values()
+        .flat_map(|calendar| calendar.dates.iter().map(move |date| (calendar, date)))
+        .collect();
+    Ok(RecordBatch::try_new(
+        calendar_dates_schema(),
+        vec![
+            strings(dates.iter().map(|(calendar, _)| calendar.id.as_str())),
+            Arc::new(
+                dates
+                    .iter()
+                    .map(|(_, date)| Some(date.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE))
+                    .collect::<Date32Array>(),
+            ),
+        ],
+    )?)
+}
+
+fn write_batches<I>(path: &Path, file_name: &str, schema: SchemaRef, batches: I) -> Result<()>
+where
+    I: IntoIterator<Item = Result<RecordBatch>>,
+{
+    info!("Writing {}", file_name);
+    let path = path.join(file_name);
+    let file = File::create(&path).with_context(|| format!("Error creating {:?}", path))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema, Some(properties))
+        .with_context(|| format!("Error writing {:?}", path))?;
+    for batch in batches {
+        writer
+            .write(&batch?)
+            .with_context(|| format!("Error writing {:?}", path))?;
+    }
+    writer
+        .close()
+        .with_context(|| format!("Error writing {:?}", path))?;
+    Ok(())
+}
+
+/// Exports the stops, lines, routes, trips, stop times and calendar dates
+/// of the collections to Parquet files in the directory `path`, with the
+/// schemas described in the [module documentation](self).
+///
+/// ```
+/// # fn main() -> transit_model::Result<()> {
+/// let model = transit_model::ntfs::read("tests/fixtures/minimal_ntfs/")?;
+/// let output_dir = tempfile::tempdir()?;
+/// transit_model::parquet::write(&model, output_dir.path())?;
+/// assert!(output_dir.path().join("stop_times.parquet").is_file());
+/// # Ok(())
+/// # }
+/// ```
+pub fn write<P: AsRef<Path>>(collections: &Collections, path: P) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path).with_context(|| format!("Error creating {:?}", path))?;
+    info!("Writing Parquet files to {:?}", path);
+
+    write_batches(
+        path,
+        "stops.parquet",
+        stops_schema(),
+        Some(stops_batch(collections)),
+    )?;
+    write_batches(
+        path,
+        "lines.parquet",
+        lines_schema(),
+        Some(lines_batch(collections)),
+    )?;
+    write_batches(
+        path,
+        "routes.parquet",
+        routes_schema(),
+        Some(routes_batch(collections)),
+    )?;
+    write_batches(
+        path,
+        "trips.parquet",
+        trips_schema(),
+        Some(trips_batch(collections)),
+    )?;
+    let vjs: Vec<&VehicleJourney> = collections.vehicle_journeys.values().collect();
+    write_batches(
+        path,
+        "stop_times.parquet",
+        stop_times_schema(),
+        vjs.chunks(VEHICLE_JOURNEYS_BY_BATCH)
+            .map(|vjs| stop_times_batch(collections, vjs)),
+    )?;
+    write_batches(
+        path,
+        "calendar_dates.parquet",
+        calendar_dates_schema(),
+        Some(calendar_dates_batch(collections)),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow_array::{
+        cast::AsArray,
+        types::{Date32Type, Float64Type, UInt32Type},
+        Array,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn read(path: &Path, file_name: &str) -> RecordBatch {
+        let file = File::open(path.join(file_name)).unwrap();
+        let mut batches: Vec<RecordBatch> = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .with_batch_size(1 << 16)
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect();
+        assert_eq!(1, batches.len());
+        batches.remove(0)
+    }
+
+    #[test]
+    fn typed_columns() {
+        let collections = crate::ntfs::read("tests/fixtures/minimal_ntfs")
+            .unwrap()
+            .into_collections();
+        let output_dir = tempfile::tempdir().unwrap();
+        write(&collections, output_dir.path()).unwrap();
+
+        let stops = read(output_dir.path(), "stops.parquet");
+        assert_eq!(stops_schema(), stops.schema());
+        assert_eq!(
+            collections.stop_areas.len() + collections.stop_points.len(),
+            stops.num_rows()
+        );
+        let parent_stations = stops.column_by_name("parent_station").unwrap();
+        assert_eq!(collections.stop_areas.len(), parent_stations.null_count());
+        let stop_point = collections.stop_points.get("CDGM").unwrap();
+        let stop_ids = stops.column_by_name("stop_id").unwrap().as_string::<i32>();
+        let row = (0..stops.num_rows())
+            .find(|&row| stop_ids.value(row) == "CDGM")
+            .unwrap();
+        assert_eq!("CDG", parent_stations.as_string::<i32>().value(row));
+        let lons = stops.column_by_name("stop_lon").unwrap();
+        let lons = lons.as_primitive::<Float64Type>();
+        assert_eq!(stop_point.coord.lon, lons.value(row));
+
+        let stop_times = read(output_dir.path(), "stop_times.parquet");
+        assert_eq!(stop_times_schema(), stop_times.schema());
+        // 4 stop times for each metro and RER trip, 2 for each bus trip and
+        // 3 more for the zones served by the last RER trip
+        assert_eq!(23, stop_times.num_rows());
+        let vj = collections.vehicle_journeys.values().next().unwrap();
+        let arrival_times = stop_times.column_by_name("arrival_time").unwrap();
+        let arrival_times = arrival_times.as_primitive::<UInt32Type>();
+        assert_eq!(
+            vj.stop_times[0].arrival_time.total_seconds(),
+            arrival_times.value(0)
+        );
+
+        let calendar_dates = read(output_dir.path(), "calendar_dates.parquet");
+        assert_eq!(calendar_dates_schema(), calendar_dates.schema());
+        // One row for every weekday of 2018
+        assert_eq!(261, calendar_dates.num_rows());
+        let dates = calendar_dates.column_by_name("date").unwrap();
+        let dates = dates.as_primitive::<Date32Type>();
+        let first_date = collections.calendars.get("Week").unwrap().dates.first();
+        assert!((0..dates.len()).any(|row| dates.value_as_date(row).as_ref() == first_date));
+
+        for file_name in ["lines.parquet", "routes.parquet", "trips.parquet"] {
+            assert!(read(output_dir.path(), file_name).num_rows() > 0);
+        }
+    }
+}
@@ -13,12 +13,138 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 //! Some utilities for input dataset to the library.
 
-use crate::{file_handler::FileHandler, Result};
+use crate::{
+    file_handler::FileHandler,
+    report::{ReadReport, SkippedRow},
+    timing, Result,
+};
 use anyhow::{anyhow, bail, Context};
+use serde::de::{self, Visitor};
 use skip_error::SkipError;
+use std::{cell::RefCell, io::Read};
 use tracing::info;
 use typed_index_collection::{CollectionWithId, Id};
 
+thread_local! {
+    // The report of the files read by the thread, while collected by
+    // `collect_read_report`
+    static READ_REPORT: RefCell<Option<ReadReport>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, returning with its result the report of the files read meanwhile
+/// by [read_objects] and [read_objects_loose] in the calling thread.
+pub(crate) fn collect_read_report<T>(f: impl FnOnce() -> T) -> (T, ReadReport) {
+    let previous = READ_REPORT.with(|report| report.replace(Some(ReadReport::default())));
+    let result = f();
+    let report = READ_REPORT
+        .with(|report| report.replace(previous))
+        .unwrap_or_default();
+    (result, report)
+}
+
+// The names of the fields of the struct `O`, that is the columns expected in
+// a file, empty if `O` isn't deserialized as a struct
+fn struct_fields<O>() -> &'static [&'static str]
+where
+    O: for<'de> serde::Deserialize<'de>,
+{
+    // A deserializer only keeping the fields of the struct it's asked for
+    struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de, 'a> de::Deserializer<'de> for FieldsDeserializer<'a> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields found"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = O::deserialize(FieldsDeserializer(&mut fields));
+    fields
+}
+
+// Report the fields of `O` absent from the headers of the file, when the read
+// report is collected
+fn report_missing_columns<O, R>(basename: &str, rdr: &mut csv::Reader<R>)
+where
+    O: for<'de> serde::Deserialize<'de>,
+    R: Read,
+{
+    READ_REPORT.with(|report| {
+        if let (Some(report), Ok(headers)) = (report.borrow_mut().as_mut(), rdr.headers()) {
+            let missing_columns: Vec<String> = struct_fields::<O>()
+                .iter()
+                .filter(|field| !headers.iter().any(|header| header == **field))
+                .map(|field| field.to_string())
+                .collect();
+            if !missing_columns.is_empty() {
+                report
+                    .files
+                    .entry(basename.to_string())
+                    .or_default()
+                    .missing_columns = missing_columns;
+            }
+        }
+    });
+}
+
+// Report a row of the file skipped because of `error`, when the read report
+// is collected
+fn report_skipped_row(basename: &str, error: &csv::Error) {
+    READ_REPORT.with(|report| {
+        if let Some(report) = report.borrow_mut().as_mut() {
+            report
+                .files
+                .entry(basename.to_string())
+                .or_default()
+                .skipped_rows
+                .push(SkippedRow {
+                    line: error.position().map(csv::Position::line),
+                    reason: error.to_string(),
+                });
+        }
+    });
+}
+
+// A CSV reader of a file, trimming the fields and accepting rows of any
+// length. A file already in memory is read from its slice (see
+// `FileHandler::as_slice`).
+fn csv_reader<'r, H>(reader: &'r mut H::Reader) -> csv::Reader<Box<dyn Read + 'r>>
+where
+    H: FileHandler,
+    H::Reader: 'r,
+{
+    let reader: Box<dyn Read + 'r> = if H::as_slice(reader).is_some() {
+        Box::new(H::as_slice(reader).unwrap_or_default())
+    } else {
+        Box::new(reader)
+    };
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+}
+
 /// Read a vector of objects from a zip in a file_handler
 pub fn read_objects<H, O>(
     file_handler: &mut H,
@@ -41,16 +167,17 @@ where
         (None, true) => {
             bail!("file {:?} not found", path)
         }
-        (Some(reader), _) => {
+        (Some(mut reader), _) => {
             info!("Reading {}", basename);
-            let mut rdr = csv::ReaderBuilder::new()
-                .flexible(true)
-                .trim(csv::Trim::All)
-                .from_reader(reader);
-            Ok(rdr
+            let stage = timing::read_file(&basename);
+            let mut rdr = csv_reader::<&mut H>(&mut reader);
+            report_missing_columns::<O, _>(&basename, &mut rdr);
+            let objects: Vec<O> = rdr
                 .deserialize()
                 .collect::<Result<_, _>>()
-                .with_context(|| format!("Error reading {:?}", path))?)
+                .with_context(|| format!("Error reading {:?}", path))?;
+            stage.record_rows(objects.len());
+            Ok(objects)
         }
     }
 }
@@ -77,21 +204,167 @@ where
         (None, true) => {
             bail!("file {:?} not found", path)
         }
-        (Some(reader), _) => {
+        (Some(mut reader), _) => {
             info!("Reading {}", basename);
-            let mut rdr = csv::ReaderBuilder::new()
-                .flexible(true)
-                .trim(csv::Trim::All)
-                .from_reader(reader);
-            let objects = rdr
+            let stage = timing::read_file(&basename);
+            let mut rdr = csv_reader::<&mut H>(&mut reader);
+            report_missing_columns::<O, _>(&basename, &mut rdr);
+            let objects: Vec<O> = rdr
                 .deserialize()
-                .map(|object| object.with_context(|| format!("Error reading {:?}", path)))
+                .map(|object| {
+                    object
+                        .inspect_err(|error| report_skipped_row(&basename, error))
+                        .with_context(|| format!("Error reading {:?}", path))
+                })
                 .skip_error_and_warn()
                 .collect();
+            stage.record_rows(objects.len());
             Ok(objects)
         }
     }
 }
+/// Read the records of a file one by one, calling `f` with the headers and
+/// each record.
+///
+/// The record is reused from one line to the next: `f` can deserialize it
+/// with [csv::StringRecord::deserialize] into a type borrowing its `&str`
+/// fields, avoiding to allocate a `String` for each of them. The trimming and
+/// the flexibility are the same as [read_objects], and the errors of `f` get
+/// the file as context.
+pub(crate) fn read_records<H, F>(
+    file_handler: &mut H,
+    file_name: &str,
+    required_file: bool,
+    mut f: F,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+    F: FnMut(&csv::StringRecord, &csv::StringRecord) -> Result<()>,
+{
+    let (reader, path) = file_handler.get_file_if_exists(file_name)?;
+    let file_name = path.file_name();
+    let basename = file_name.map_or(path.to_string_lossy(), |b| b.to_string_lossy());
+
+    match (reader, required_file) {
+        (None, false) => {
+            info!("Skipping {}", basename);
+            Ok(())
+        }
+        (None, true) => {
+            bail!("file {:?} not found", path)
+        }
+        (Some(mut reader), _) => {
+            info!("Reading {}", basename);
+            let stage = timing::read_file(&basename);
+            let mut rdr = csv_reader::<&mut H>(&mut reader);
+            let headers = rdr
+                .headers()
+                .with_context(|| format!("Error reading {:?}", path))?
+                .clone();
+            let mut record = csv::StringRecord::new();
+            let mut rows = 0;
+            while rdr
+                .read_record(&mut record)
+                .with_context(|| format!("Error reading {:?}", path))?
+            {
+                f(&headers, &record).with_context(|| format!("Error reading {:?}", path))?;
+                rows += 1;
+            }
+            stage.record_rows(rows);
+            Ok(())
+        }
+    }
+}
+
+/// The records of a file, read at once.
+///
+/// The objects deserialized by [Records::deserialize] can borrow their
+/// `&str` fields from the records instead of allocating a `String` for each
+/// of them, which is interesting when they are immediately transformed into
+/// other structures. The whole file being kept in memory, huge files like
+/// `stop_times.txt` should rather be streamed with [read_records].
+pub(crate) struct Records {
+    path: std::path::PathBuf,
+    headers: csv::StringRecord,
+    records: Vec<csv::StringRecord>,
+}
+
+impl Records {
+    /// Deserialize all the records, with the same trimming and flexibility
+    /// as [read_objects]
+    pub(crate) fn deserialize<'a, O>(&'a self) -> Result<Vec<O>>
+    where
+        O: serde::Deserialize<'a>,
+    {
+        self.records
+            .iter()
+            .map(|record| record.deserialize(Some(&self.headers)))
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Error reading {:?}", self.path))
+    }
+}
+
+/// Read all the records of a file from a file_handler, the file being
+/// loaded in memory once.
+pub(crate) fn read_all_records<H>(
+    file_handler: &mut H,
+    file_name: &str,
+    required_file: bool,
+) -> Result<Records>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let (reader, path) = file_handler.get_file_if_exists(file_name)?;
+    let file_name = path.file_name();
+    let basename = file_name.map_or(path.to_string_lossy(), |b| b.to_string_lossy());
+
+    match (reader, required_file) {
+        (None, false) => {
+            info!("Skipping {}", basename);
+            Ok(Records {
+                path,
+                headers: csv::StringRecord::new(),
+                records: vec![],
+            })
+        }
+        (None, true) => {
+            bail!("file {:?} not found", path)
+        }
+        (Some(mut reader), _) => {
+            info!("Reading {}", basename);
+            let stage = timing::read_file(&basename);
+            // A file already in memory is parsed without being copied
+            let mut rdr = if <&mut H as FileHandler>::as_slice(&reader).is_some() {
+                csv_reader::<&mut H>(&mut reader)
+            } else {
+                let mut buffer = Vec::new();
+                reader
+                    .read_to_end(&mut buffer)
+                    .with_context(|| format!("Error reading {:?}", path))?;
+                let buffer: Box<dyn Read> = Box::new(std::io::Cursor::new(buffer));
+                csv::ReaderBuilder::new()
+                    .flexible(true)
+                    .trim(csv::Trim::All)
+                    .from_reader(buffer)
+            };
+            let headers = rdr
+                .headers()
+                .with_context(|| format!("Error reading {:?}", path))?
+                .clone();
+            let records: Vec<_> = rdr
+                .records()
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("Error reading {:?}", path))?;
+            stage.record_rows(records.len());
+            Ok(Records {
+                path,
+                headers,
+                records,
+            })
+        }
+    }
+}
+
 /// Read a CollectionId from a zip in a file_handler
 pub fn read_collection<H, O>(file_handler: &mut H, file_name: &str) -> Result<CollectionWithId<O>>
 where
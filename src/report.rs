@@ -0,0 +1,282 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Machine-readable reports of the issues found in the data.
+//!
+//! Each [ReportIssue] has a stable [IssueCode] and a [Severity], so that the
+//! issues can be routed or ignored without parsing their message.
+
+use crate::Result;
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs::File, path::Path, str::FromStr};
+
+/// Severity of an [ReportIssue]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The data is valid but may be improved
+    Info,
+    /// The data is fixed or partially removed when building the model
+    Warning,
+    /// The data can't be used
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(anyhow!(
+                "'{}' is not a severity, expected 'info', 'warning' or 'error'",
+                s
+            )),
+        }
+    }
+}
+
+/// Stable identifier of the kind of a [ReportIssue]
+///
+/// The codes are part of the public API: within a major version of the
+/// crate, a code is never renamed nor removed and its meaning doesn't change,
+/// but new codes may be added. The serialized form (`snake_case`, also
+/// accepted by [FromStr]) is the one to use to route or ignore the issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IssueCode {
+    /// An object references an object that doesn't exist
+    DanglingReference,
+    /// A transfer references a stop point that doesn't exist
+    DanglingTransfer,
+    /// A ticket use restriction or perimeter references a missing object
+    InvalidTicketUseReference,
+    /// A ticket price or a ticket use references a ticket that doesn't exist
+    DanglingTicketReference,
+    /// A calendar has no date
+    EmptyCalendar,
+    /// A calendar is not used by any vehicle journey
+    UnusedCalendar,
+    /// A vehicle journey runs outside the validity period of its dataset
+    OutsideValidityPeriod,
+    /// The model can't be built from the collections
+    InvalidModel,
+    /// A stop has the coordinates (0, 0)
+    NullCoordinates,
+    /// The longitude or the latitude of a stop is out of range
+    OutOfRangeCoordinates,
+    /// The longitude and the latitude of a stop are probably swapped
+    SwappedCoordinates,
+    /// A stop is outside the bounding box of the dataset
+    OutsideBoundingBox,
+    /// An object is removed because a stop it references is removed
+    CascadeRemoval,
+    /// A vehicle journey has the same route, stops and times as another one
+    /// on overlapping dates
+    DuplicateVehicleJourney,
+    /// The speed of a vehicle journey between two consecutive stops is above
+    /// the maximal speed of its physical mode
+    ExcessiveSpeed,
+    /// A vehicle journey travels between two distinct stops in no time
+    ZeroDurationSegment,
+    /// The URL of a network, a company or a contributor is not valid
+    InvalidUrl,
+    /// The email of a company is not valid
+    InvalidEmail,
+    /// A URL or an email is valid once normalized, for example a URL
+    /// without scheme
+    NonNormalizedContactField,
+    /// A transfer has no reverse transfer
+    AsymmetricTransfer,
+    /// A transfer is from a stop point to itself
+    SelfTransfer,
+}
+
+impl fmt::Display for IssueCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(code)) => write!(f, "{}", code),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl FromStr for IssueCode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| anyhow!("'{}' is not an issue code", s))
+    }
+}
+
+/// An issue found in the data
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportIssue {
+    /// Kind of the issue
+    pub code: IssueCode,
+    /// Severity of the issue
+    pub severity: Severity,
+    /// Type of the object concerned by the issue, if any
+    pub object_type: Option<String>,
+    /// Identifier of the object concerned by the issue, if any
+    pub object_id: Option<String>,
+    /// Human readable description of the issue
+    pub message: String,
+    /// Human readable description of the modification of the data made to
+    /// fix the issue, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modification: Option<String>,
+}
+
+/// Issues found in the data, with their count by severity
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Number of issues by severity
+    pub counts: BTreeMap<Severity, usize>,
+    /// All the issues
+    pub issues: Vec<ReportIssue>,
+}
+
+impl Report {
+    pub(crate) fn add(
+        &mut self,
+        code: IssueCode,
+        severity: Severity,
+        object: Option<(&str, &str)>,
+        message: String,
+    ) {
+        self.push(ReportIssue {
+            code,
+            severity,
+            object_type: object.map(|(object_type, _)| object_type.to_string()),
+            object_id: object.map(|(_, object_id)| object_id.to_string()),
+            message,
+            modification: None,
+        });
+    }
+
+    pub(crate) fn push(&mut self, issue: ReportIssue) {
+        *self.counts.entry(issue.severity).or_insert(0) += 1;
+        self.issues.push(issue);
+    }
+
+    /// Remove the issues with one of the `codes`
+    pub fn ignore(&mut self, codes: &[IssueCode]) {
+        self.issues.retain(|issue| !codes.contains(&issue.code));
+        self.counts.clear();
+        for issue in &self.issues {
+            *self.counts.entry(issue.severity).or_insert(0) += 1;
+        }
+    }
+
+    /// The highest severity of the issues, `None` if there is no issue
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.counts.keys().max().copied()
+    }
+
+    /// Write the report as JSON in the file `path`
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Error creating {:?}", path))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("Error writing {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// A row of a file which couldn't be read and was skipped
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedRow {
+    /// Line of the row in the file, if known
+    pub line: Option<u64>,
+    /// Why the row couldn't be read
+    pub reason: String,
+}
+
+/// Columns missing in a file and rows skipped when reading it
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct FileReadReport {
+    /// The columns absent from the header of the file, their values being
+    /// the default ones
+    pub missing_columns: Vec<String>,
+    /// The rows which couldn't be read
+    pub skipped_rows: Vec<SkippedRow>,
+}
+
+/// Report of the reading of the files of a dataset, only listing the files
+/// with missing columns or skipped rows
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ReadReport {
+    /// The report of each file, by file name
+    pub files: BTreeMap<String, FileReadReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn issue_code_as_string() {
+        assert_eq!(
+            "dangling_reference",
+            IssueCode::DanglingReference.to_string()
+        );
+        assert_eq!(
+            IssueCode::ZeroDurationSegment,
+            "zero_duration_segment".parse().unwrap()
+        );
+        assert!("DanglingReference".parse::<IssueCode>().is_err());
+    }
+
+    #[test]
+    fn ignore_issues() {
+        let mut report = Report::default();
+        report.add(
+            IssueCode::UnusedCalendar,
+            Severity::Info,
+            None,
+            String::new(),
+        );
+        report.add(
+            IssueCode::EmptyCalendar,
+            Severity::Warning,
+            None,
+            String::new(),
+        );
+        report.add(
+            IssueCode::EmptyCalendar,
+            Severity::Warning,
+            None,
+            String::new(),
+        );
+        report.ignore(&[IssueCode::EmptyCalendar]);
+        assert_eq!(1, report.issues.len());
+        assert_eq!(Some(Severity::Info), report.max_severity());
+        assert_eq!(None, report.counts.get(&Severity::Warning));
+    }
+}
@@ -16,7 +16,7 @@
 use crate::objects::Date;
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
-use tracing::error;
+use tracing::{error, warn};
 use wkt::ToWkt;
 
 /// deserialize u8 as bool
@@ -178,67 +178,114 @@ where
     }
 }
 
+// Deserialize an optional value, an invalid one being logged and ignored
+fn de_option_checked<'de, D, T>(
+    de: D,
+    is_valid: impl FnOnce(&T) -> bool,
+    expected: &str,
+) -> Option<T>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de> + std::fmt::Display,
+{
+    use serde::Deserialize;
+    match Option::<T>::deserialize(de) {
+        Ok(Some(value)) if is_valid(&value) => Some(value),
+        Ok(Some(value)) => {
+            warn!("invalid value {}, expected {}", value, expected);
+            None
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    }
+}
+
 /// deserialize optional positive decimal
-/// return an error if negative float number
+/// an invalid or negative number is logged and ignored
 pub fn de_option_positive_decimal<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    use serde::{
-        de::{Error, Unexpected::Other},
-        Deserialize,
-    };
-    let option = <Option<Decimal> as Deserialize<'de>>::deserialize(deserializer)?;
-    match option {
-        Some(number) if number.is_sign_positive() => Ok(option),
-        None => Ok(None),
-        _ => Err(D::Error::invalid_value(
-            Other("strictly negative float number"),
-            &"positive float number",
-        )),
-    }
+    Ok(de_option_checked(
+        deserializer,
+        |number: &Decimal| number.is_sign_positive(),
+        "positive float number",
+    ))
 }
 
 /// deserialize optional positive float
-/// return an error if negative float number
+/// an invalid or negative number is logged and ignored
 pub fn de_option_positive_float<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    use serde::{
-        de::{Error, Unexpected::Other},
-        Deserialize,
-    };
-    let option = <Option<f32> as Deserialize<'de>>::deserialize(deserializer)?;
-    match option {
-        Some(number) if number.is_sign_positive() => Ok(option),
-        None => Ok(None),
-        _ => Err(D::Error::invalid_value(
-            Other("strictly negative float number"),
-            &"positive float number",
-        )),
-    }
+    Ok(de_option_checked(
+        deserializer,
+        |number: &f32| number.is_sign_positive(),
+        "positive float number",
+    ))
+}
+
+/// deserialize optional strictly positive integer
+/// an invalid or null number is logged and ignored
+pub fn de_option_positive_integer<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(de_option_checked(
+        deserializer,
+        |number: &u32| *number > 0,
+        "strictly positive integer",
+    ))
 }
 
 /// deserialize optional integer
-/// return an error if value is equal to 0
+/// an invalid number or 0 is logged and ignored
 pub fn de_option_non_null_integer<'de, D>(deserializer: D) -> Result<Option<i16>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    use serde::{
-        de::{Error, Unexpected::Other},
-        Deserialize,
+    Ok(de_option_checked(
+        deserializer,
+        |number: &i16| *number != 0,
+        "non null number",
+    ))
+}
+
+/// deserialize optional float
+/// an invalid number is logged and ignored
+pub fn de_option_float<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(de_option_checked(
+        deserializer,
+        |number: &f32| number.is_finite(),
+        "float number",
+    ))
+}
+
+/// Normalize a currency code into a valid ISO-4217 code.
+///
+/// The code is trimmed and uppercased, and the most common currency symbols
+/// (`€`, `$`, `£`, `¥`) are mapped to their code. Returns `None` if the
+/// result is not a known ISO-4217 code.
+pub fn normalize_currency_code(currency_code: &str) -> Option<&'static str> {
+    let currency_code = match currency_code.trim() {
+        "€" => "EUR".to_string(),
+        "$" => "USD".to_string(),
+        "£" => "GBP".to_string(),
+        "¥" => "JPY".to_string(),
+        code => code.to_uppercase(),
     };
-    let option = <Option<i16> as Deserialize<'de>>::deserialize(deserializer)?;
-    match option {
-        Some(number) if number != 0 => Ok(option),
-        None => Ok(None),
-        _ => Err(D::Error::invalid_value(Other("0"), &"non null number")),
-    }
+    iso4217::alpha3(&currency_code).map(|currency_code| currency_code.alpha3)
 }
 
-/// deserialize currency code (ISO-4217)
+/// deserialize currency code (ISO-4217), normalized with
+/// `normalize_currency_code`
 /// return an error if unrecognized currency code
 pub fn de_currency_code<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
@@ -249,25 +296,26 @@ where
         Deserialize,
     };
     let string = String::deserialize(deserializer)?;
-    let currency_code = iso4217::alpha3(&string).ok_or_else(|| {
+    let currency_code = normalize_currency_code(&string).ok_or_else(|| {
         D::Error::invalid_value(
             Other("unrecognized currency code (ISO-4217)"),
             &"3-letters currency code (ISO-4217)",
         )
     })?;
-    Ok(String::from(currency_code.alpha3))
+    Ok(String::from(currency_code))
 }
 
-/// serialize currency code (ISO-4217)
+/// serialize currency code (ISO-4217), normalized with
+/// `normalize_currency_code`
 /// return an error if unrecognized currency code
 pub fn ser_currency_code<S>(currency_code: &str, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
     use serde::ser::Error;
-    let currency_code = iso4217::alpha3(currency_code)
+    let currency_code = normalize_currency_code(currency_code)
         .ok_or_else(|| S::Error::custom("The String is not a valid currency code (ISO-4217)"))?;
-    serializer.serialize_str(currency_code.alpha3)
+    serializer.serialize_str(currency_code)
 }
 
 /// serialize geometry to wkt
@@ -351,6 +399,32 @@ mod tests {
             assert_eq!("EUR", wrapper.currency_code);
         }
 
+        #[test]
+        fn test_de_normalized_currency_code() {
+            for input in &["eur", " Eur ", "€"] {
+                let json = format!("{{\"currency_code\":\"{}\"}}", input);
+                let wrapper: CurrencyCodeWrapper = serde_json::from_str(&json).unwrap();
+                assert_eq!("EUR", wrapper.currency_code);
+            }
+        }
+
+        #[test]
+        fn test_normalize_currency_code() {
+            assert_eq!(Some("USD"), normalize_currency_code("$"));
+            assert_eq!(Some("GBP"), normalize_currency_code("gbp"));
+            assert_eq!(None, normalize_currency_code("euros"));
+            assert_eq!(None, normalize_currency_code(""));
+        }
+
+        #[test]
+        fn test_ser_normalized_currency_code() {
+            let wrapper = CurrencyCodeWrapper {
+                currency_code: "eur".to_string(),
+            };
+            let json = serde_json::to_string(&wrapper).unwrap();
+            assert_eq!("{\"currency_code\":\"EUR\"}", json);
+        }
+
         #[test]
         fn test_de_invalid_currency_code() {
             let result: Result<CurrencyCodeWrapper, _> =
@@ -410,4 +484,54 @@ mod tests {
             );
         }
     }
+
+    mod deserialize_option_checked {
+        use super::*;
+        use pretty_assertions::assert_eq;
+        use rust_decimal_macros::dec;
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize)]
+        struct PathwayWrapper {
+            #[serde(default, deserialize_with = "de_option_positive_decimal")]
+            length: Option<Decimal>,
+            #[serde(default, deserialize_with = "de_option_positive_integer")]
+            traversal_time: Option<u32>,
+            #[serde(default, deserialize_with = "de_option_non_null_integer")]
+            stair_count: Option<i16>,
+            #[serde(default, deserialize_with = "de_option_float")]
+            max_slope: Option<f32>,
+        }
+
+        fn read(csv: &str) -> PathwayWrapper {
+            csv::Reader::from_reader(csv.as_bytes())
+                .deserialize()
+                .next()
+                .unwrap()
+                .unwrap()
+        }
+
+        #[test]
+        fn valid_values() {
+            let wrapper = read("length,traversal_time,stair_count,max_slope\n4.2,60,-12,-0.1\n");
+            assert_eq!(Some(dec!(4.2)), wrapper.length);
+            assert_eq!(Some(60), wrapper.traversal_time);
+            assert_eq!(Some(-12), wrapper.stair_count);
+            assert_eq!(Some(-0.1), wrapper.max_slope);
+        }
+
+        #[test]
+        fn invalid_values_are_ignored() {
+            let wrapper = read("length,traversal_time,stair_count,max_slope\n-4.2,0,0,steep\n");
+            assert_eq!(None, wrapper.length);
+            assert_eq!(None, wrapper.traversal_time);
+            assert_eq!(None, wrapper.stair_count);
+            assert_eq!(None, wrapper.max_slope);
+            let wrapper = read("length,traversal_time,stair_count,max_slope\nfar,-1,many,\n");
+            assert_eq!(None, wrapper.length);
+            assert_eq!(None, wrapper.traversal_time);
+            assert_eq!(None, wrapper.stair_count);
+            assert_eq!(None, wrapper.max_slope);
+        }
+    }
 }
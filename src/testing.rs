@@ -0,0 +1,339 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Generation of synthetic datasets, for benchmarks and stress tests.
+//!
+//! ```
+//! use transit_model::testing::SyntheticDataset;
+//!
+//! let model = SyntheticDataset {
+//!     lines: 2,
+//!     trips_per_route: 3,
+//!     ..Default::default()
+//! }
+//! .model()?;
+//! assert_eq!(2 * 2 * 3, model.vehicle_journeys.len());
+//! # Ok::<(), transit_model::Error>(())
+//! ```
+
+use crate::{
+    model::{Collections, Model},
+    objects::*,
+    Result,
+};
+use chrono::{Datelike, Duration, Weekday};
+use typed_index_collection::CollectionWithId;
+
+/// A small random number generator (SplitMix64)
+///
+/// It is implemented here so that a dataset only depends on its seed, whatever
+/// the platform and the version of the dependencies.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Create a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// Next random number
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `[start, end)`, `end` being greater than `start`
+    pub fn gen_range(&mut self, start: u32, end: u32) -> u32 {
+        start + (self.next_u64() % u64::from(end - start)) as u32
+    }
+
+    /// Random number in `[0, 1)`
+    pub fn gen_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Parameters of a synthetic dataset
+///
+/// Each line has a forward and a backward route serving its own stops, laid
+/// out as a random walk around Paris so that some lines cross each other.
+/// The trips run on weekdays, on weekends or every day of the period.
+#[derive(Debug, Clone)]
+pub struct SyntheticDataset {
+    /// Seed of the random generator, the same parameters giving the same
+    /// dataset
+    pub seed: u64,
+    /// Number of lines
+    pub lines: usize,
+    /// Number of stops of each line
+    pub stops_per_line: usize,
+    /// Number of trips of each route
+    pub trips_per_route: usize,
+    /// First day of the dataset
+    pub start_date: Date,
+    /// Number of days of the dataset
+    pub days: u32,
+}
+
+impl Default for SyntheticDataset {
+    fn default() -> Self {
+        SyntheticDataset {
+            seed: 0,
+            lines: 10,
+            stops_per_line: 20,
+            trips_per_route: 50,
+            start_date: Date::from_ymd_opt(2020, 1, 1).expect("2020-01-01 is a valid date"),
+            days: 28,
+        }
+    }
+}
+
+const CALENDARS: [&str; 3] = ["weekdays", "weekends", "daily"];
+
+impl SyntheticDataset {
+    /// Last day of the dataset
+    pub fn end_date(&self) -> Date {
+        self.start_date + Duration::days(i64::from(self.days.max(1)) - 1)
+    }
+
+    /// Generate the `Collections` of the dataset
+    pub fn collections(&self) -> Collections {
+        let mut rng = Rng::new(self.seed);
+        let mut collections = Collections {
+            contributors: CollectionWithId::from(Contributor {
+                id: "synthetic".to_string(),
+                name: "Synthetic contributor".to_string(),
+                ..Default::default()
+            }),
+            datasets: CollectionWithId::from(Dataset {
+                id: "synthetic".to_string(),
+                contributor_id: "synthetic".to_string(),
+                start_date: self.start_date,
+                end_date: self.end_date(),
+                ..Default::default()
+            }),
+            networks: CollectionWithId::from(Network {
+                id: "network".to_string(),
+                name: "Synthetic network".to_string(),
+                timezone: Some(chrono_tz::Europe::Paris),
+                ..Default::default()
+            }),
+            companies: CollectionWithId::from(Company {
+                id: "company".to_string(),
+                name: "Synthetic company".to_string(),
+                ..Default::default()
+            }),
+            commercial_modes: CollectionWithId::from(CommercialMode {
+                id: "Bus".to_string(),
+                name: "Bus".to_string(),
+                ..Default::default()
+            }),
+            physical_modes: CollectionWithId::from(PhysicalMode {
+                id: "Bus".to_string(),
+                name: "Bus".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        collections.calendars = CollectionWithId::new(
+            CALENDARS
+                .iter()
+                .map(|&id| Calendar {
+                    id: id.to_string(),
+                    dates: (0..self.days)
+                        .map(|day| self.start_date + Duration::days(i64::from(day)))
+                        .filter(|date| {
+                            let is_weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+                            match id {
+                                "weekdays" => !is_weekend,
+                                "weekends" => is_weekend,
+                                _ => true,
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+        )
+        .expect("the calendar identifiers are unique");
+
+        let mut stop_areas = Vec::new();
+        let mut stop_points = Vec::new();
+        for line in 0..self.lines {
+            let mut coord = Coord {
+                lon: 2.25 + 0.2 * rng.gen_f64(),
+                lat: 48.80 + 0.1 * rng.gen_f64(),
+            };
+            let mut heading = 2.0 * std::f64::consts::PI * rng.gen_f64();
+            for stop in 0..self.stops_per_line {
+                stop_areas.push(StopArea {
+                    id: format!("sa:{}:{}", line, stop),
+                    name: format!("Stop {}:{}", line, stop),
+                    visible: true,
+                    coord,
+                    ..Default::default()
+                });
+                stop_points.push(StopPoint {
+                    id: format!("sp:{}:{}", line, stop),
+                    name: format!("Stop {}:{}", line, stop),
+                    visible: true,
+                    coord,
+                    stop_area_id: format!("sa:{}:{}", line, stop),
+                    stop_type: StopType::Point,
+                    ..Default::default()
+                });
+                // Steps of 300 to 600 meters, the heading slowly changing
+                heading += rng.gen_f64() - 0.5;
+                let step = 0.0027 * (1.0 + rng.gen_f64());
+                coord.lon += step * heading.cos() / 0.66;
+                coord.lat += step * heading.sin();
+            }
+        }
+        collections.stop_areas =
+            CollectionWithId::new(stop_areas).expect("the stop area identifiers are unique");
+        collections.stop_points =
+            CollectionWithId::new(stop_points).expect("the stop point identifiers are unique");
+
+        let mut lines = Vec::with_capacity(self.lines);
+        let mut routes = Vec::with_capacity(2 * self.lines);
+        let mut vehicle_journeys = Vec::with_capacity(2 * self.lines * self.trips_per_route);
+        for line in 0..self.lines {
+            let line_id = format!("line:{}", line);
+            lines.push(Line {
+                id: line_id.clone(),
+                code: Some(format!("L{}", line)),
+                name: format!("Line {}", line),
+                network_id: "network".to_string(),
+                commercial_mode_id: "Bus".to_string(),
+                ..Default::default()
+            });
+            let mut stop_point_idxs: Vec<_> = (0..self.stops_per_line)
+                .map(|stop| {
+                    collections
+                        .stop_points
+                        .get_idx(&format!("sp:{}:{}", line, stop))
+                        .expect("the stop point exists")
+                })
+                .collect();
+            for direction in &["forward", "backward"] {
+                let route_id = format!("route:{}:{}", line, direction);
+                routes.push(Route {
+                    id: route_id.clone(),
+                    name: format!("Line {} {}", line, direction),
+                    direction_type: Some(direction.to_string()),
+                    line_id: line_id.clone(),
+                    ..Default::default()
+                });
+                if *direction == "backward" {
+                    stop_point_idxs.reverse();
+                }
+                for trip in 0..self.trips_per_route {
+                    // Departures between 5h and 22h, 1 to 3 minutes between
+                    // stops and up to 30 seconds at each stop
+                    let mut time = 5 * 3600 + rng.gen_range(0, 17 * 3600);
+                    let mut stop_times = Vec::with_capacity(stop_point_idxs.len());
+                    for (sequence, &stop_point_idx) in stop_point_idxs.iter().enumerate() {
+                        let arrival_time = Time::new(0, 0, time);
+                        time += rng.gen_range(0, 30);
+                        stop_times.push(StopTime {
+                            stop_point_idx,
+                            sequence: sequence as u32,
+                            arrival_time,
+                            departure_time: Time::new(0, 0, time),
+                            boarding_duration: 0,
+                            alighting_duration: 0,
+                            pickup_type: 0,
+                            drop_off_type: 0,
+                            local_zone_id: None,
+                            precision: None,
+                            continuous_pickup: None,
+                            continuous_drop_off: None,
+                            pickup_booking_rule_id: None,
+                            drop_off_booking_rule_id: None,
+                        });
+                        time += rng.gen_range(60, 180);
+                    }
+                    let calendar = CALENDARS[rng.gen_range(0, CALENDARS.len() as u32) as usize];
+                    vehicle_journeys.push(VehicleJourney {
+                        id: format!("trip:{}:{}:{}", line, direction, trip),
+                        route_id: route_id.clone(),
+                        physical_mode_id: "Bus".to_string(),
+                        dataset_id: "synthetic".to_string(),
+                        service_id: calendar.to_string(),
+                        company_id: "company".to_string(),
+                        stop_times,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        collections.lines = CollectionWithId::new(lines).expect("the line identifiers are unique");
+        collections.routes =
+            CollectionWithId::new(routes).expect("the route identifiers are unique");
+        collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)
+            .expect("the vehicle journey identifiers are unique");
+        collections
+    }
+
+    /// Generate the `Model` of the dataset
+    pub fn model(&self) -> Result<Model> {
+        Model::new(self.collections())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn same_seed_same_dataset() {
+        let dataset = SyntheticDataset {
+            lines: 3,
+            stops_per_line: 5,
+            trips_per_route: 4,
+            ..Default::default()
+        };
+        let collections = dataset.collections();
+        assert_eq!(3 * 5, collections.stop_points.len());
+        assert_eq!(3 * 2, collections.routes.len());
+        assert_eq!(3 * 2 * 4, collections.vehicle_journeys.len());
+        let dump = |collections: &Collections| {
+            format!(
+                "{:?}{:?}",
+                collections.stop_points.values().collect::<Vec<_>>(),
+                collections.vehicle_journeys.values().collect::<Vec<_>>()
+            )
+        };
+        assert_eq!(dump(&collections), dump(&dataset.collections()));
+        let other_seed = SyntheticDataset {
+            seed: 1,
+            ..dataset.clone()
+        };
+        assert_ne!(dump(&collections), dump(&other_seed.collections()));
+    }
+
+    #[test]
+    fn valid_model() {
+        let model = SyntheticDataset::default().model().unwrap();
+        assert_eq!(10, model.lines.len());
+        assert!(model.stop_points.values().all(|stop_point| {
+            (2.0..2.7).contains(&stop_point.coord.lon)
+                && (48.6..49.1).contains(&stop_point.coord.lat)
+        }));
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Timing of the major stages of a conversion.
+//!
+//! Each stage is a [tracing] span at the `INFO` level whose name is stable,
+//! so log pipelines can rely on it:
+//!
+//! | span                 | fields                        | stage                                |
+//! |----------------------|-------------------------------|--------------------------------------|
+//! | [READ_FILE]          | `file`, `rows`, `duration_ms` | reading of an input file             |
+//! | [READ_COLLECTIONS]   | `format`, `duration_ms`       | assembly of the `Collections`        |
+//! | [BUILD_MODEL]        | `duration_ms`                 | `Model::new`, building the relations |
+//! | [ENHANCER]           | `name`, `duration_ms`         | one cleaning or enhancing step       |
+//! | [WRITE_FILE]         | `file`, `rows`, `duration_ms` | writing of an output file            |
+//!
+//! `rows` is left empty when the number of lines isn't known without
+//! additional work. A writer producing several files at once, like the NTFS
+//! `trips.txt` and `stop_times.txt`, has a single span named after its
+//! biggest file.
+//!
+//! [TimingLayer] aggregates these spans to list the top time consumers.
+//! Without a `tracing` subscriber, the spans are logged with the `log` crate
+//! under the `transit_model::timing` target, and their entering and exiting
+//! under the `tracing::span` targets, which can be filtered out.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{
+    field::{Empty, Field, Visit},
+    info_span,
+    span::{Attributes, EnteredSpan, Id, Record},
+    Span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// Name of the span of the reading of an input file
+pub const READ_FILE: &str = "read_file";
+/// Name of the span of the assembly of the `Collections` of a format
+pub const READ_COLLECTIONS: &str = "read_collections";
+/// Name of the span of `Model::new`
+pub const BUILD_MODEL: &str = "build_model";
+/// Name of the span of a cleaning or enhancing step
+pub const ENHANCER: &str = "enhancer";
+/// Name of the span of the writing of an output file
+pub const WRITE_FILE: &str = "write_file";
+
+const STAGES: [&str; 5] = [
+    READ_FILE,
+    READ_COLLECTIONS,
+    BUILD_MODEL,
+    ENHANCER,
+    WRITE_FILE,
+];
+
+/// A stage being timed: its span is entered until the stage is dropped,
+/// `duration_ms` being recorded just before.
+pub(crate) struct Stage {
+    span: EnteredSpan,
+    start: Instant,
+}
+
+impl Stage {
+    fn enter(span: Span) -> Self {
+        Stage {
+            span: span.entered(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the number of lines read or written
+    pub(crate) fn record_rows(&self, rows: usize) {
+        self.span.record("rows", rows as u64);
+    }
+}
+
+impl Drop for Stage {
+    fn drop(&mut self) {
+        self.span
+            .record("duration_ms", self.start.elapsed().as_millis() as u64);
+    }
+}
+
+pub(crate) fn read_file(file: &str) -> Stage {
+    Stage::enter(info_span!(
+        READ_FILE,
+        file,
+        rows = Empty,
+        duration_ms = Empty
+    ))
+}
+
+pub(crate) fn read_collections(format: &'static str) -> Stage {
+    Stage::enter(info_span!(READ_COLLECTIONS, format, duration_ms = Empty))
+}
+
+pub(crate) fn build_model() -> Stage {
+    Stage::enter(info_span!(BUILD_MODEL, duration_ms = Empty))
+}
+
+/// Run the step `f` in an [ENHANCER] span
+pub(crate) fn enhancer<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let _stage = Stage::enter(info_span!(ENHANCER, name, duration_ms = Empty));
+    f()
+}
+
+pub(crate) fn write_file(file: &str) -> Stage {
+    Stage::enter(info_span!(
+        WRITE_FILE,
+        file,
+        rows = Empty,
+        duration_ms = Empty
+    ))
+}
+
+/// The aggregated timing of a stage, for a given file, format or enhancer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageTiming {
+    /// Name of the span, one of the constants of this module
+    pub stage: &'static str,
+    /// The `file`, `format` or `name` field of the span
+    pub target: Option<String>,
+    /// Number of spans aggregated
+    pub count: usize,
+    /// Total of the recorded `rows`
+    pub rows: Option<u64>,
+    /// Total time spent in the stage
+    pub duration: Duration,
+}
+
+impl fmt::Display for StageTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stage)?;
+        if let Some(target) = &self.target {
+            write!(f, " {}", target)?;
+        }
+        write!(f, ": {:.3}s", self.duration.as_secs_f64())?;
+        if let Some(rows) = self.rows {
+            write!(f, " ({} rows)", rows)?;
+        }
+        if self.count > 1 {
+            write!(f, " in {} times", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SpanFields {
+    target: Option<String>,
+    rows: Option<u64>,
+}
+
+impl Visit for SpanFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "rows" {
+            self.rows = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if matches!(field.name(), "file" | "format" | "name") {
+            self.target = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if matches!(field.name(), "file" | "format" | "name") {
+            self.target = Some(format!("{:?}", value));
+        }
+    }
+}
+
+// The timings aggregated by span name and target
+type Timings = BTreeMap<(&'static str, Option<String>), StageTiming>;
+
+struct SpanTiming {
+    start: Instant,
+    fields: SpanFields,
+}
+
+/// A [tracing_subscriber] layer aggregating the duration of the stages of
+/// this module, its clones sharing the same timings.
+///
+/// ```
+/// # use tracing_subscriber::layer::SubscriberExt;
+/// # use transit_model::timing::TimingLayer;
+/// let timing = TimingLayer::default();
+/// let subscriber = tracing_subscriber::registry().with(timing.clone());
+/// tracing::subscriber::with_default(subscriber, || {
+///     transit_model::Model::new(Default::default()).unwrap();
+/// });
+/// assert_eq!("build_model", timing.top(1)[0].stage);
+/// ```
+#[derive(Clone, Default)]
+pub struct TimingLayer {
+    timings: Arc<Mutex<Timings>>,
+}
+
+impl TimingLayer {
+    /// The timings of all the stages, the longest first
+    pub fn summary(&self) -> Vec<StageTiming> {
+        let mut timings: Vec<StageTiming> = self
+            .timings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        timings.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+        timings
+    }
+
+    /// The `n` longest stages
+    pub fn top(&self, n: usize) -> Vec<StageTiming> {
+        let mut timings = self.summary();
+        timings.truncate(n);
+        timings
+    }
+
+    /// Print the longest stages on the standard error, for the
+    /// `--timing-summary` option of the binaries
+    pub fn print_summary(&self) {
+        eprintln!("Top time consumers:");
+        for stage in self.top(SUMMARY_SIZE) {
+            eprintln!("  {}", stage);
+        }
+    }
+}
+
+// Number of stages printed by `TimingLayer::print_summary`
+const SUMMARY_SIZE: usize = 10;
+
+/// Set up the logger of the binaries: the events are logged on the standard
+/// output, filtered by the `RUST_LOG` environment variable (`INFO` by
+/// default), and the stages are aggregated by `timing` if given.
+#[cfg(feature = "logger")]
+pub fn init_logger(timing: Option<TimingLayer>) {
+    use tracing_subscriber::{
+        filter::{EnvFilter, LevelFilter},
+        layer::SubscriberExt as _,
+        util::SubscriberInitExt as _,
+    };
+    let default_level = LevelFilter::INFO;
+    let rust_log =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
+    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
+        eprintln!(
+            "invalid {}, falling back to level '{}' - {}",
+            EnvFilter::DEFAULT_ENV,
+            default_level,
+            e,
+        );
+        EnvFilter::new(default_level.to_string())
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(env_filter_subscriber))
+        .with(timing)
+        .init();
+}
+
+impl<S> Layer<S> for TimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !STAGES.contains(&attrs.metadata().name()) {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(SpanTiming {
+                start: Instant::now(),
+                fields,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                values.record(&mut timing.fields);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let timing = match span.extensions_mut().remove::<SpanTiming>() {
+            Some(timing) => timing,
+            None => return,
+        };
+        let stage = span.metadata().name();
+        let duration = timing.start.elapsed();
+        let mut timings = self
+            .timings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let target = timing.fields.target;
+        let aggregated = timings
+            .entry((stage, target.clone()))
+            .or_insert_with(|| StageTiming {
+                stage,
+                target,
+                count: 0,
+                rows: None,
+                duration: Duration::ZERO,
+            });
+        aggregated.count += 1;
+        aggregated.duration += duration;
+        if let Some(rows) = timing.fields.rows {
+            *aggregated.rows.get_or_insert(0) += rows;
+        }
+    }
+}
@@ -12,14 +12,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-//! See function generates_transfers
+//! See functions generates_transfers and generates_reverse_transfers
 
 use crate::{
     model::Model,
-    objects::{Coord, StopPoint, Transfer},
+    objects::{Coord, StopPoint, Transfer, TransferType},
     Result,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tracing::{info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Idx};
 
@@ -89,6 +89,7 @@ fn generate_transfers_from_sp(
                     min_transfer_time: Some(transfer_time),
                     real_min_transfer_time: Some(transfer_time + waiting_time),
                     equipment_id: None,
+                    transfer_type: TransferType::Possible,
                 },
             );
         }
@@ -148,3 +149,169 @@ pub fn generates_transfers(
     collections.transfers = Collection::new(new_transfers);
     Model::new(collections)
 }
+
+/// Find the transfers whose reverse transfer (from `to_stop_id` to
+/// `from_stop_id`) doesn't exist. Transfers from a stop point to itself are
+/// never asymmetric.
+pub fn find_asymmetric_transfers(transfers: &Collection<Transfer>) -> Vec<&Transfer> {
+    let pairs: HashSet<(&str, &str)> = transfers
+        .values()
+        .map(|t| (t.from_stop_id.as_str(), t.to_stop_id.as_str()))
+        .collect();
+    transfers
+        .values()
+        .filter(|t| {
+            t.from_stop_id != t.to_stop_id
+                && !pairs.contains(&(t.to_stop_id.as_str(), t.from_stop_id.as_str()))
+        })
+        .collect()
+}
+
+// A transfer faster than this ratio of the walking speed is considered as a
+// data entry error
+const MAX_WALKING_SPEED_RATIO: f64 = 2.0;
+
+fn reverse_transfer(
+    transfer: &Transfer,
+    from: &StopPoint,
+    to: &StopPoint,
+    walking_speed: f64,
+    waiting_time: u32,
+) -> Transfer {
+    let mut reverse = Transfer {
+        from_stop_id: transfer.to_stop_id.clone(),
+        to_stop_id: transfer.from_stop_id.clone(),
+        ..transfer.clone()
+    };
+    if from.coord == Coord::default() || to.coord == Coord::default() {
+        return reverse;
+    }
+    let walking_time = from.coord.distance_to(&to.coord) / walking_speed;
+    let is_plausible = transfer
+        .min_transfer_time
+        .is_some_and(|time| f64::from(time) * MAX_WALKING_SPEED_RATIO >= walking_time);
+    if !is_plausible {
+        let transfer_time = walking_time as u32;
+        reverse.min_transfer_time = Some(transfer_time);
+        reverse.real_min_transfer_time = Some(transfer_time + waiting_time);
+    }
+    reverse
+}
+
+/// Generates the missing reverse transfers
+///
+/// For each transfer found by [find_asymmetric_transfers], the reverse
+/// transfer is created with the same durations and equipment. If
+/// `min_transfer_time` is missing or shorter than the time needed to walk
+/// the distance between the stop points at twice the `walking_speed` (in
+/// meters per second), the durations are probably wrong and are computed from
+/// the distance instead, `waiting_time` (in seconds) being added to the
+/// `real_min_transfer_time`.
+///
+/// Transfers referencing an unknown stop point are not reversed.
+pub fn generates_reverse_transfers(
+    model: Model,
+    walking_speed: f64,
+    waiting_time: u32,
+) -> Result<Model> {
+    info!("Generating reverse transfers...");
+    let reverse_transfers: Vec<Transfer> = find_asymmetric_transfers(&model.transfers)
+        .into_iter()
+        .filter_map(|transfer| {
+            let from = model.stop_points.get(&transfer.from_stop_id)?;
+            let to = model.stop_points.get(&transfer.to_stop_id)?;
+            Some(reverse_transfer(
+                transfer,
+                from,
+                to,
+                walking_speed,
+                waiting_time,
+            ))
+        })
+        .collect();
+    info!("{} reverse transfers generated", reverse_transfers.len());
+
+    let mut collections = model.into_collections();
+    let mut transfers = collections.transfers.take();
+    transfers.extend(reverse_transfers);
+    transfers.sort_unstable_by(|t1, t2| {
+        (&t1.from_stop_id, &t1.to_stop_id).cmp(&(&t2.from_stop_id, &t2.to_stop_id))
+    });
+    collections.transfers = Collection::new(transfers);
+    Model::new(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn transfer(from: &str, to: &str, min_transfer_time: Option<u32>) -> Transfer {
+        Transfer {
+            from_stop_id: from.to_string(),
+            to_stop_id: to.to_string(),
+            min_transfer_time,
+            real_min_transfer_time: min_transfer_time.map(|time| time + 10),
+            equipment_id: None,
+            transfer_type: TransferType::Possible,
+        }
+    }
+
+    #[test]
+    fn asymmetric_transfers() {
+        let transfers = Collection::new(vec![
+            transfer("sp_1", "sp_2", Some(60)),
+            transfer("sp_2", "sp_1", Some(60)),
+            transfer("sp_1", "sp_3", Some(60)),
+            transfer("sp_3", "sp_3", Some(0)),
+        ]);
+        assert_eq!(
+            vec![&transfer("sp_1", "sp_3", Some(60))],
+            find_asymmetric_transfers(&transfers)
+        );
+    }
+
+    #[test]
+    fn reverse_durations() {
+        // About 111 m apart
+        let from = StopPoint {
+            id: "sp_1".to_string(),
+            coord: Coord {
+                lon: 2.0,
+                lat: 48.0,
+            },
+            ..Default::default()
+        };
+        let to = StopPoint {
+            id: "sp_2".to_string(),
+            coord: Coord {
+                lon: 2.0,
+                lat: 48.001,
+            },
+            ..Default::default()
+        };
+        let reverse = reverse_transfer(&transfer("sp_1", "sp_2", Some(120)), &from, &to, 1.0, 60);
+        assert_eq!(
+            (Some(120), Some(130)),
+            (reverse.min_transfer_time, reverse.real_min_transfer_time)
+        );
+        assert_eq!("sp_2", reverse.from_stop_id);
+
+        // Faster than 2 m/s
+        let reverse = reverse_transfer(&transfer("sp_1", "sp_2", Some(20)), &from, &to, 1.0, 60);
+        assert_eq!(
+            (Some(111), Some(171)),
+            (reverse.min_transfer_time, reverse.real_min_transfer_time)
+        );
+
+        // Without coordinates, the durations are kept
+        let reverse = reverse_transfer(
+            &transfer("sp_1", "sp_2", None),
+            &from,
+            &StopPoint::default(),
+            1.0,
+            60,
+        );
+        assert_eq!(None, reverse.min_transfer_time);
+    }
+}
@@ -12,41 +12,47 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use crate::{file_handler::FileHandler, parser::read_objects};
+use crate::{
+    file_handler::{FileHandler, FileWriter},
+    parser::read_objects,
+    timing,
+};
 use anyhow::Context;
-use skip_error::skip_error_and_warn;
 use std::{
-    fs,
-    io::{Read, Write},
+    collections::HashSet,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
     path,
+    sync::{Mutex, PoisonError},
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id};
 use walkdir::WalkDir;
 
-pub fn zip_to<P, R>(source_path: P, zip_file: R) -> crate::Result<()>
+/// Writes the files of the `source_path` directory in the `zip_file` ZIP
+/// archive, sorted by name. Each file is copied into its entry by chunks,
+/// without being loaded in memory.
+pub(crate) fn zip_to<P, R>(source_path: P, zip_file: R) -> crate::Result<()>
 where
     P: AsRef<path::Path>,
     R: AsRef<path::Path>,
 {
     let source_path = source_path.as_ref();
-    let file = fs::File::create(zip_file.as_ref())?;
+    let zip_file = zip_file.as_ref();
+    let file =
+        fs::File::create(zip_file).with_context(|| format!("Error writing {:?}", zip_file))?;
     let mut zip = zip::ZipWriter::new(file);
     let options =
         zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-    let mut buffer = Vec::new();
-    for entry in WalkDir::new(source_path) {
+    for entry in WalkDir::new(source_path).sort_by_file_name() {
         let path = entry?.path().to_owned();
         if path.is_file() {
-            let name = path.strip_prefix(path::Path::new(source_path))?.to_owned();
+            let name = path.strip_prefix(source_path)?.to_owned();
             if let Some(name) = name.to_str() {
                 debug!("adding {:?} as {:?} ...", path, name);
                 zip.start_file(name, options)?;
-                let mut f = fs::File::open(path)?;
-
-                f.read_to_end(&mut buffer)?;
-                zip.write_all(&*buffer)?;
-                buffer.clear();
+                let mut f = fs::File::open(&path)?;
+                io::copy(&mut f, &mut zip)?;
             }
         }
     }
@@ -54,6 +60,36 @@ where
     Ok(())
 }
 
+/// Builds a `CollectionWithId` at once from `objects`, keeping the first
+/// object of each identifier and warning about the others.
+///
+/// The duplicates are found in one pass with a set sized from the number of
+/// objects (`typed_index_collection` doesn't allow to size the identifier map
+/// of the collection itself).
+pub(crate) fn collection_with_id_skipping_duplicates<T>(objects: Vec<T>) -> CollectionWithId<T>
+where
+    T: Id<T>,
+{
+    let mut ids = HashSet::with_capacity(objects.len());
+    let is_first: Vec<bool> = objects.iter().map(|obj| ids.insert(obj.id())).collect();
+    drop(ids);
+    let objects = objects
+        .into_iter()
+        .zip(is_first)
+        .filter_map(|(obj, is_first)| {
+            if !is_first {
+                warn!(
+                    "{}",
+                    typed_index_collection::Error::IdentifierAlreadyExists(obj)
+                );
+                return None;
+            }
+            Some(obj)
+        })
+        .collect();
+    CollectionWithId::new(objects).expect("duplicate identifiers have been removed")
+}
+
 pub(crate) fn make_collection_with_id<T, H>(
     file_handler: &mut H,
     file: &str,
@@ -62,11 +98,8 @@ where
     for<'de> T: Id<T> + serde::Deserialize<'de>,
     for<'a> &'a mut H: FileHandler,
 {
-    let mut collection = CollectionWithId::<T>::default();
-    for object in read_objects::<_, T>(file_handler, file, true)? {
-        skip_error_and_warn!(collection.push(object));
-    }
-    Ok(collection)
+    let objects = read_objects::<_, T>(file_handler, file, true)?;
+    Ok(collection_with_id_skipping_duplicates(objects))
 }
 
 pub(crate) fn make_opt_collection<T, H>(
@@ -89,59 +122,102 @@ where
     for<'de> T: Id<T> + serde::Deserialize<'de>,
     for<'a> &'a mut H: FileHandler,
 {
-    let mut collection = CollectionWithId::<T>::default();
-    for object in read_objects::<_, T>(file_handler, file, false)? {
-        skip_error_and_warn!(collection.push(object));
-    }
-    Ok(collection)
+    let objects = read_objects::<_, T>(file_handler, file, false)?;
+    Ok(collection_with_id_skipping_duplicates(objects))
 }
 
-pub fn write_collection_with_id<T>(
-    path: &path::Path,
+/// The objects of `collection` sorted by identifier, the writers using this
+/// order so that the same model is always written the same way.
+pub(crate) fn sorted_by_id<T: Id<T>>(collection: &CollectionWithId<T>) -> Vec<&T> {
+    let mut objects: Vec<&T> = collection.values().collect();
+    objects.sort_unstable_by(|a, b| a.id().cmp(b.id()));
+    objects
+}
+
+/// Calls `f` on each of the `items` from a bounded number of threads (one by
+/// available core), each thread taking the next item once done with the
+/// previous one.
+///
+/// The results are in the order of `items`, `None` standing for a call to
+/// `f` that panicked.
+pub(crate) fn parallel_map<T, R, F>(items: Vec<T>, f: F) -> Vec<Option<R>>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let workers = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(items.len());
+    let mut results: Vec<Option<R>> = std::iter::repeat_with(|| None).take(items.len()).collect();
+    let queue = Mutex::new(items.into_iter().zip(results.iter_mut()));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap_or_else(PoisonError::into_inner).next();
+                match next {
+                    // The thread goes on with the next items after a panic
+                    Some((item, result)) => {
+                        *result = panic::catch_unwind(AssertUnwindSafe(|| f(item))).ok()
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+    results
+}
+
+pub fn write_collection_with_id<T, W>(
+    file_writer: &W,
     file: &str,
     collection: &CollectionWithId<T>,
 ) -> crate::Result<()>
 where
+    W: FileWriter,
     T: Id<T> + serde::Serialize,
 {
     if collection.is_empty() {
         return Ok(());
     }
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for obj in collection.values() {
+    let stage = timing::write_file(file);
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    for obj in sorted_by_id(collection) {
         wtr.serialize(obj)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     wtr.flush()
         .with_context(|| format!("Error reading {:?}", path))?;
+    stage.record_rows(collection.len());
 
     Ok(())
 }
 
-pub fn write_collection<T>(
-    path: &path::Path,
+pub fn write_collection<T, W>(
+    file_writer: &W,
     file: &str,
     collection: &Collection<T>,
 ) -> crate::Result<()>
 where
+    W: FileWriter,
     T: serde::Serialize,
 {
     if collection.is_empty() {
         return Ok(());
     }
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let stage = timing::write_file(file);
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv::Writer::from_writer(writer);
     for obj in collection.values() {
         wtr.serialize(obj)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     wtr.flush()
         .with_context(|| format!("Error reading {:?}", path))?;
+    stage.record_rows(collection.len());
 
     Ok(())
 }
@@ -0,0 +1,583 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Consistency checks of `Collections`, gathered in a machine-readable
+//! [Report].
+//!
+//! The checks are run on the collections as read, before the construction of
+//! the [Model] which silently removes or fixes some inconsistencies. Each
+//! issue has a stable [IssueCode] and a [Severity].
+
+use crate::{
+    enhancers::{
+        check_coordinates, check_duplicate_vehicle_journeys, find_invalid_contact_fields,
+        find_invalid_ticket_use_references, ContactFieldIssue, CoordinatesConfiguration,
+        InvalidTicketUseReference,
+    },
+    model::{
+        Collections, Model, BUS_PHYSICAL_MODE, BUS_RAPID_TRANSIT_PHYSICAL_MODE,
+        COACH_PHYSICAL_MODE, FERRY_PHYSICAL_MODE, LOCAL_TRAIN_PHYSICAL_MODE,
+        LONG_DISTANCE_TRAIN_PHYSICAL_MODE, METRO_PHYSICAL_MODE, RAPID_TRANSIT_PHYSICAL_MODE,
+        TRAIN_PHYSICAL_MODE, TRAMWAY_PHYSICAL_MODE,
+    },
+    objects::Coord,
+    transfers::find_asymmetric_transfers,
+    Result,
+};
+use std::collections::BTreeMap;
+
+pub use crate::report::{IssueCode, Report, ReportIssue, Severity};
+
+fn check_dangling_references(report: &mut Report, c: &Collections) {
+    for reference in c.check_references() {
+        let (code, severity) = match reference.object_type {
+            // Reported by `check_fares`
+            "ticket_price" | "ticket_use" | "ticket_use_perimeter" | "ticket_use_restriction" => {
+                continue
+            }
+            // Removed when building the model
+            "transfer" if reference.field != "equipment_id" => {
+                (IssueCode::DanglingTransfer, Severity::Warning)
+            }
+            _ if reference.breaks_relation() => (IssueCode::DanglingReference, Severity::Error),
+            _ => (IssueCode::DanglingReference, Severity::Warning),
+        };
+        report.push(ReportIssue {
+            code,
+            severity,
+            object_type: Some(reference.object_type.to_string()),
+            object_id: reference.object_id.clone(),
+            message: reference.to_string(),
+            modification: None,
+        });
+    }
+}
+
+fn check_fares(report: &mut Report, c: &Collections) {
+    for price in c.ticket_prices.values() {
+        if !c.tickets.contains_id(&price.ticket_id) {
+            report.add(
+                IssueCode::DanglingTicketReference,
+                Severity::Warning,
+                Some(("ticket", &price.ticket_id)),
+                format!("ticket_price: ticket {:?} not found", price.ticket_id),
+            );
+        }
+    }
+    for ticket_use in c.ticket_uses.values() {
+        if !c.tickets.contains_id(&ticket_use.ticket_id) {
+            report.add(
+                IssueCode::DanglingTicketReference,
+                Severity::Warning,
+                Some(("ticket", &ticket_use.ticket_id)),
+                format!(
+                    "ticket_use {:?}: ticket {:?} not found",
+                    ticket_use.id, ticket_use.ticket_id
+                ),
+            );
+        }
+    }
+    for invalid_reference in find_invalid_ticket_use_references(c) {
+        let ticket_use_id = match &invalid_reference {
+            InvalidTicketUseReference::Restriction(restriction, _) => &restriction.ticket_use_id,
+            InvalidTicketUseReference::Perimeter(perimeter, _) => &perimeter.ticket_use_id,
+        };
+        report.add(
+            IssueCode::InvalidTicketUseReference,
+            Severity::Warning,
+            Some(("ticket_use", ticket_use_id)),
+            invalid_reference.message(c),
+        );
+    }
+}
+
+fn check_calendars(report: &mut Report, c: &Collections) {
+    let mut used_calendars = std::collections::HashSet::new();
+    for vj in c.vehicle_journeys.values() {
+        used_calendars.insert(vj.service_id.as_str());
+        let calendar = c.calendars.get(&vj.service_id);
+        let dataset = c.datasets.get(&vj.dataset_id);
+        if let (Some(calendar), Some(dataset)) = (calendar, dataset) {
+            if calendar
+                .dates
+                .iter()
+                .any(|date| *date < dataset.start_date || dataset.end_date < *date)
+            {
+                report.add(
+                    IssueCode::OutsideValidityPeriod,
+                    Severity::Warning,
+                    Some(("trip", &vj.id)),
+                    format!(
+                        "trip {:?} runs outside the validity period of dataset {:?} ({} to {})",
+                        vj.id, dataset.id, dataset.start_date, dataset.end_date
+                    ),
+                );
+            }
+        }
+    }
+    for calendar in c.calendars.values() {
+        if calendar.dates.is_empty() {
+            report.add(
+                IssueCode::EmptyCalendar,
+                Severity::Warning,
+                Some(("calendar", &calendar.id)),
+                format!("calendar {:?} has no date", calendar.id),
+            );
+        } else if !used_calendars.contains(calendar.id.as_str()) {
+            report.add(
+                IssueCode::UnusedCalendar,
+                Severity::Info,
+                Some(("calendar", &calendar.id)),
+                format!("calendar {:?} is not used by any trip", calendar.id),
+            );
+        }
+    }
+}
+
+/// Maximal plausible speed in km/h of buses and coaches
+pub const DEFAULT_BUS_MAX_SPEED: f64 = 110.;
+/// Maximal plausible speed in km/h of trains, metros and tramways
+pub const DEFAULT_RAIL_MAX_SPEED: f64 = 360.;
+/// Maximal plausible speed in km/h of ferries
+pub const DEFAULT_FERRY_MAX_SPEED: f64 = 80.;
+
+/// Configuration of the checks run by [validate_with_configuration]
+#[derive(Debug, Clone)]
+pub struct ValidationConfiguration {
+    /// Maximal plausible speed in km/h between two consecutive stops, by
+    /// physical mode identifier. The vehicle journeys of the other physical
+    /// modes are not checked.
+    pub max_speeds: BTreeMap<String, f64>,
+}
+
+impl Default for ValidationConfiguration {
+    fn default() -> Self {
+        let bus_modes = [
+            BUS_PHYSICAL_MODE,
+            BUS_RAPID_TRANSIT_PHYSICAL_MODE,
+            COACH_PHYSICAL_MODE,
+        ];
+        let rail_modes = [
+            LOCAL_TRAIN_PHYSICAL_MODE,
+            LONG_DISTANCE_TRAIN_PHYSICAL_MODE,
+            METRO_PHYSICAL_MODE,
+            RAPID_TRANSIT_PHYSICAL_MODE,
+            TRAIN_PHYSICAL_MODE,
+            TRAMWAY_PHYSICAL_MODE,
+        ];
+        let max_speeds = bus_modes
+            .iter()
+            .map(|mode| (mode.to_string(), DEFAULT_BUS_MAX_SPEED))
+            .chain(
+                rail_modes
+                    .iter()
+                    .map(|mode| (mode.to_string(), DEFAULT_RAIL_MAX_SPEED)),
+            )
+            .chain(std::iter::once((
+                FERRY_PHYSICAL_MODE.to_string(),
+                DEFAULT_FERRY_MAX_SPEED,
+            )))
+            .collect();
+        ValidationConfiguration { max_speeds }
+    }
+}
+
+fn check_speeds(report: &mut Report, c: &Collections, config: &ValidationConfiguration) {
+    let is_located = |coord: &Coord| *coord != Coord::default() && coord.is_valid();
+    for vj in c.vehicle_journeys.values() {
+        let max_speed = match config.max_speeds.get(&vj.physical_mode_id) {
+            Some(max_speed) => *max_speed,
+            None => continue,
+        };
+        for stop_times in vj.stop_times.windows(2) {
+            let (from, to) = (&stop_times[0], &stop_times[1]);
+            let from_coord = &c.stop_points[from.stop_point_idx].coord;
+            let to_coord = &c.stop_points[to.stop_point_idx].coord;
+            if !is_located(from_coord) || !is_located(to_coord) {
+                continue;
+            }
+            let distance = from_coord.distance_to(to_coord);
+            let duration = i64::from(to.arrival_time.total_seconds())
+                - i64::from(from.departure_time.total_seconds());
+            if duration < 0 || distance == 0. {
+                continue;
+            }
+            if duration == 0 {
+                report.add(
+                    IssueCode::ZeroDurationSegment,
+                    Severity::Info,
+                    Some(("trip", &vj.id)),
+                    format!(
+                        "trip {:?} travels {:.0} m in no time between the stop times of sequence {} and {}",
+                        vj.id, distance, from.sequence, to.sequence
+                    ),
+                );
+                continue;
+            }
+            let speed = distance / duration as f64 * 3.6;
+            if speed > max_speed {
+                report.add(
+                    IssueCode::ExcessiveSpeed,
+                    Severity::Warning,
+                    Some(("trip", &vj.id)),
+                    format!(
+                        "trip {:?} travels at {:.0} km/h between the stop times of sequence {} and {}, above {} km/h for physical mode {:?}",
+                        vj.id, speed, from.sequence, to.sequence, max_speed, vj.physical_mode_id
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn check_transfers(report: &mut Report, c: &Collections) {
+    for transfer in find_asymmetric_transfers(&c.transfers) {
+        report.add(
+            IssueCode::AsymmetricTransfer,
+            Severity::Warning,
+            Some(("transfer", &transfer.from_stop_id)),
+            format!(
+                "transfer from {:?} to {:?} has no reverse transfer",
+                transfer.from_stop_id, transfer.to_stop_id
+            ),
+        );
+    }
+    for transfer in c
+        .transfers
+        .values()
+        .filter(|t| t.from_stop_id == t.to_stop_id)
+    {
+        report.add(
+            IssueCode::SelfTransfer,
+            Severity::Info,
+            Some(("transfer", &transfer.from_stop_id)),
+            format!(
+                "transfer from {:?} to the same stop point",
+                transfer.from_stop_id
+            ),
+        );
+    }
+}
+
+fn check_contact_fields(report: &mut Report, c: &Collections) {
+    for field in find_invalid_contact_fields(c) {
+        let (code, severity) = match field.issue {
+            ContactFieldIssue::Invalid if field.is_email() => {
+                (IssueCode::InvalidEmail, Severity::Warning)
+            }
+            ContactFieldIssue::Invalid => (IssueCode::InvalidUrl, Severity::Warning),
+            ContactFieldIssue::Normalized(_) => {
+                (IssueCode::NonNormalizedContactField, Severity::Info)
+            }
+        };
+        report.add(
+            code,
+            severity,
+            Some((field.object_type, &field.object_id)),
+            field.message(),
+        );
+    }
+}
+
+/// Run all the consistency checks on `collections` with the default
+/// [ValidationConfiguration], then build the [Model].
+///
+/// See [validate_with_configuration].
+pub fn validate(collections: Collections) -> (Report, Result<Model>) {
+    validate_with_configuration(collections, &ValidationConfiguration::default())
+}
+
+/// Run all the consistency checks on `collections` then build the [Model].
+///
+/// The coordinates of the stops are checked with the default
+/// [CoordinatesConfiguration], which only reports the issues, and the
+/// duplicate vehicle journeys are reported without being merged. The returned
+/// [Report] contains the issues found by the checks and, if the model can't
+/// be built, an issue [IssueCode::InvalidModel].
+pub fn validate_with_configuration(
+    mut collections: Collections,
+    config: &ValidationConfiguration,
+) -> (Report, Result<Model>) {
+    let mut report = Report::default();
+    check_dangling_references(&mut report, &collections);
+    check_fares(&mut report, &collections);
+    check_contact_fields(&mut report, &collections);
+    check_transfers(&mut report, &collections);
+    check_calendars(&mut report, &collections);
+    check_speeds(&mut report, &collections, config);
+    check_coordinates(
+        &mut collections,
+        &CoordinatesConfiguration::default(),
+        &mut report,
+    );
+    check_duplicate_vehicle_journeys(&mut collections, false, &mut report);
+    let model = Model::new(collections);
+    if let Err(err) = &model {
+        let message = err
+            .chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>()
+            .join(": ");
+        report.add(IssueCode::InvalidModel, Severity::Error, None, message);
+    }
+    (report, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{
+        Calendar, Company, Contributor, Date, StopPoint, StopTime, Ticket, TicketPrice, Time,
+        Transfer, TransferType, VehicleJourney,
+    };
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+    use typed_index_collection::{Collection, CollectionWithId};
+
+    fn codes(report: &Report) -> Vec<IssueCode> {
+        report.issues.iter().map(|issue| issue.code).collect()
+    }
+
+    #[test]
+    fn valid_collections() {
+        let (report, model) = validate(Collections::default());
+        assert!(model.is_ok());
+        assert_eq!(None, report.max_severity());
+    }
+
+    #[test]
+    fn dangling_references() {
+        let mut collections = Collections::default();
+        collections
+            .vehicle_journeys
+            .push(VehicleJourney {
+                id: "vj".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        // The vehicle journey is silently removed when building the model
+        let (report, model) = validate(collections);
+        assert!(model.is_ok());
+        assert_eq!(Some(Severity::Error), report.max_severity());
+        assert_eq!(vec![IssueCode::DanglingReference; 5], codes(&report));
+        assert_eq!(Some(&5), report.counts.get(&Severity::Error));
+        assert_eq!(Some("vj"), report.issues[0].object_id.as_deref());
+    }
+
+    #[test]
+    fn fares_and_calendars() {
+        let mut collections = Collections {
+            calendars: CollectionWithId::new(vec![
+                Calendar::new("empty".to_string()),
+                Calendar {
+                    id: "unused".to_string(),
+                    dates: vec![Date::from_ymd(2020, 1, 1)].into_iter().collect(),
+                },
+            ])
+            .unwrap(),
+            ..Default::default()
+        };
+        collections.ticket_prices.push(TicketPrice {
+            ticket_id: "unknown".to_string(),
+            price: dec!(1.0),
+            currency: "EUR".to_string(),
+            ticket_validity_start: Date::from_ymd(2020, 1, 1),
+            ticket_validity_end: Date::from_ymd(2020, 12, 31),
+        });
+        collections
+            .tickets
+            .push(Ticket {
+                id: "ticket".to_string(),
+                name: "Ticket".to_string(),
+                comment: None,
+            })
+            .unwrap();
+        let (report, model) = validate(collections);
+        assert!(model.is_ok());
+        assert_eq!(
+            vec![
+                IssueCode::DanglingTicketReference,
+                IssueCode::EmptyCalendar,
+                IssueCode::UnusedCalendar,
+            ],
+            codes(&report)
+        );
+        assert_eq!(Some(Severity::Warning), report.max_severity());
+        assert_eq!(Some(&1), report.counts.get(&Severity::Info));
+    }
+
+    fn collections_with_speeds() -> Collections {
+        let stop_points = [
+            ("SP1", 2.35, 48.85),
+            ("SP2", 2.36, 48.85),
+            ("SP3", 2.36, 48.95),
+            ("SP4", 2.37, 48.95),
+        ];
+        let mut collections = Collections {
+            stop_points: CollectionWithId::new(
+                stop_points
+                    .iter()
+                    .map(|(id, lon, lat)| StopPoint {
+                        id: id.to_string(),
+                        coord: Coord {
+                            lon: *lon,
+                            lat: *lat,
+                        },
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        // SP1 -> SP2: 730 m in 1 minute, SP2 -> SP3: 11 km in 1 minute,
+        // SP3 -> SP4: 730 m in no time
+        let times = [(0, 0), (1, 1), (2, 2), (2, 3)];
+        let stop_times = stop_points
+            .iter()
+            .zip(times.iter())
+            .enumerate()
+            .map(|(sequence, ((id, _, _), (arrival, departure)))| StopTime {
+                stop_point_idx: collections.stop_points.get_idx(id).unwrap(),
+                sequence: sequence as u32,
+                arrival_time: Time::new(10, *arrival, 0),
+                departure_time: Time::new(10, *departure, 0),
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                precision: None,
+                continuous_pickup: None,
+                continuous_drop_off: None,
+                pickup_booking_rule_id: None,
+                drop_off_booking_rule_id: None,
+            })
+            .collect();
+        collections.vehicle_journeys = CollectionWithId::from(VehicleJourney {
+            id: "vj".to_string(),
+            physical_mode_id: "Bus".to_string(),
+            stop_times,
+            ..Default::default()
+        });
+        collections
+    }
+
+    #[test]
+    fn implausible_speeds() {
+        let mut report = Report::default();
+        let collections = collections_with_speeds();
+        check_speeds(
+            &mut report,
+            &collections,
+            &ValidationConfiguration::default(),
+        );
+        assert_eq!(
+            vec![IssueCode::ExcessiveSpeed, IssueCode::ZeroDurationSegment],
+            codes(&report)
+        );
+        assert_eq!(
+            "trip \"vj\" travels at 667 km/h between the stop times of sequence 1 and 2, \
+             above 110 km/h for physical mode \"Bus\"",
+            report.issues[0].message
+        );
+
+        let mut report = Report::default();
+        let config = ValidationConfiguration {
+            max_speeds: vec![("Bus".to_string(), 30.)].into_iter().collect(),
+        };
+        check_speeds(&mut report, &collections, &config);
+        assert_eq!(
+            vec![
+                IssueCode::ExcessiveSpeed,
+                IssueCode::ExcessiveSpeed,
+                IssueCode::ZeroDurationSegment
+            ],
+            codes(&report)
+        );
+
+        let mut report = Report::default();
+        let config = ValidationConfiguration {
+            max_speeds: BTreeMap::new(),
+        };
+        check_speeds(&mut report, &collections, &config);
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn invalid_contact_fields() {
+        let mut report = Report::default();
+        let collections = Collections {
+            companies: CollectionWithId::from(Company {
+                id: "C1".to_string(),
+                url: Some("example.com".to_string()),
+                mail: Some("contact".to_string()),
+                ..Default::default()
+            }),
+            contributors: CollectionWithId::from(Contributor {
+                id: "CT1".to_string(),
+                website: Some("http://".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        check_contact_fields(&mut report, &collections);
+        assert_eq!(
+            vec![
+                IssueCode::NonNormalizedContactField,
+                IssueCode::InvalidEmail,
+                IssueCode::InvalidUrl
+            ],
+            codes(&report)
+        );
+        assert_eq!(Some(Severity::Warning), report.max_severity());
+    }
+
+    #[test]
+    fn asymmetric_and_self_transfers() {
+        let mut report = Report::default();
+        let transfer = |from: &str, to: &str| Transfer {
+            from_stop_id: from.to_string(),
+            to_stop_id: to.to_string(),
+            min_transfer_time: Some(60),
+            real_min_transfer_time: Some(60),
+            equipment_id: None,
+            transfer_type: TransferType::Possible,
+        };
+        let collections = Collections {
+            transfers: Collection::new(vec![
+                transfer("sp_1", "sp_2"),
+                transfer("sp_2", "sp_1"),
+                transfer("sp_1", "sp_3"),
+                transfer("sp_1", "sp_1"),
+            ]),
+            ..Default::default()
+        };
+        check_transfers(&mut report, &collections);
+        assert_eq!(
+            vec![IssueCode::AsymmetricTransfer, IssueCode::SelfTransfer],
+            codes(&report)
+        );
+        assert_eq!(
+            "transfer from \"sp_1\" to \"sp_3\" has no reverse transfer",
+            report.issues[0].message
+        );
+    }
+
+    #[test]
+    fn severity_from_str() {
+        assert_eq!(Severity::Warning, "warning".parse().unwrap());
+        assert!("fatal".parse::<Severity>().is_err());
+    }
+}
@@ -213,5 +213,30 @@ mod tests {
                 );
             });
         }
+
+        #[test]
+        fn test_compute_dataset_validity_period_around_leap_day() {
+            let calendars_content = "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+                                 1,1,1,1,1,1,1,1,20240228,20240301";
+
+            let calendar_dates_content = "service_id,date,exception_type\n\
+                                      1,20240301,2\n\
+                                      1,20240228,2";
+
+            test_in_tmp_dir(|path| {
+                let mut handler = PathFileHandler::new(path.to_path_buf());
+                create_file_with_content(path, "calendar.txt", calendars_content);
+                create_file_with_content(path, "calendar_dates.txt", calendar_dates_content);
+
+                let mut collections = Collections::default();
+                let (_, mut dataset, _) = read_config(None::<&str>).unwrap();
+
+                calendars::manage_calendars(&mut handler, &mut collections).unwrap();
+                compute_dataset_validity_period(&mut dataset, &collections.calendars).unwrap();
+
+                assert_eq!(chrono::NaiveDate::from_ymd(2024, 2, 29), dataset.start_date);
+                assert_eq!(chrono::NaiveDate::from_ymd(2024, 2, 29), dataset.end_date);
+            });
+        }
     }
 }
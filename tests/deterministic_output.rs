@@ -0,0 +1,99 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use pretty_assertions::assert_eq;
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+};
+#[cfg(feature = "proj")]
+use std::{fs, path::Path};
+#[cfg(feature = "proj")]
+use transit_model::netex_france;
+use transit_model::{gtfs, model::Model, ntfs, test_utils::*};
+
+// The name and content of each file of the archive, the timestamps of the
+// entries being ignored
+fn zip_entries(zip: Vec<u8>) -> BTreeMap<String, Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i).unwrap();
+            let mut content = vec![];
+            file.read_to_end(&mut content).unwrap();
+            (file.name().to_string(), content)
+        })
+        .collect()
+}
+
+#[cfg(feature = "proj")]
+fn dir_entries(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.unwrap().into_path())
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let name = path
+                .strip_prefix(dir)
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            (name, fs::read(path).unwrap())
+        })
+        .collect()
+}
+
+// The model is read for each export, as the order of its maps is not the same
+// from one read to the other
+fn read_model() -> Model {
+    ntfs::read("tests/fixtures/ntfs").unwrap()
+}
+
+#[test]
+fn write_ntfs_twice() {
+    let write = || {
+        let zip =
+            ntfs::write_to_zip_writer(&read_model(), Cursor::new(Vec::new()), get_test_datetime())
+                .unwrap();
+        zip_entries(zip.into_inner())
+    };
+    assert_eq!(write(), write());
+}
+
+#[test]
+fn write_gtfs_twice() {
+    let write = || {
+        let zip = gtfs::write_to_zip_writer(
+            read_model(),
+            Cursor::new(Vec::new()),
+            &gtfs::WriteConfiguration::default(),
+        )
+        .unwrap();
+        zip_entries(zip.into_inner())
+    };
+    assert_eq!(write(), write());
+}
+
+#[test]
+#[cfg(feature = "proj")]
+fn write_netex_france_twice() {
+    let write = || {
+        let output_dir = tempfile::tempdir().unwrap();
+        let config = netex_france::WriteConfiguration::new("Participant")
+            .current_datetime(get_test_datetime());
+        netex_france::write(&read_model(), output_dir.path(), config).unwrap();
+        dir_entries(output_dir.path())
+    };
+    assert_eq!(write(), write());
+}
@@ -14,11 +14,16 @@
 
 use std::collections::BTreeMap;
 use transit_model::{
-    configuration::read_config,
-    gtfs, ntfs,
-    objects::{Contributor, Dataset},
+    configuration::{self, read_config},
+    gtfs,
+    model::ContactFieldsMode,
+    ntfs,
+    objects::{
+        CommentType, Contributor, Coord, Dataset, KeysValues, ObjectType, Pathway, StopLocation,
+        StopType,
+    },
     test_utils::*,
-    PrefixConfiguration,
+    Model, PrefixConfiguration,
 };
 
 #[test]
@@ -37,7 +42,23 @@ fn test_gtfs() {
             prefix_conf: Some(prefix_conf),
             on_demand_transport: false,
             on_demand_transport_comment: None,
+            on_demand_transport_comment_scope: gtfs::OnDemandTransportCommentScope::Agency,
             read_as_line: false,
+            contact_fields_mode: ContactFieldsMode::Loose,
+            default_agency_id: None,
+            skip_geometries: false,
+            zip_memory_budget: None,
+            skip_calendar_deduplication: false,
+            keep_unused_calendars: false,
+            stable_ids: false,
+            apply_stop_timezones: false,
+            shape_simplification_tolerance: None,
+            route_type_modes: vec![],
+            restrict_to_feed_period: false,
+            detect_encoding: false,
+            duplicate_stop_sequences: gtfs::DuplicateStopSequenceStrategy::KeepFirst,
+            keep_incomplete_trips: false,
+            datasets: None,
         };
         let model = transit_model::gtfs::Reader::new(configuration)
             .parse(input_dir)
@@ -57,6 +78,40 @@ fn test_minimal_gtfs() {
     });
 }
 
+#[test]
+fn test_read_many_gtfs() {
+    let prefix_conf = |prefix: &str| {
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix(prefix);
+        prefix_conf
+    };
+    let input_dir = "./tests/fixtures/gtfs2ntfs/minimal/input";
+    let single = transit_model::gtfs::read(input_dir).unwrap();
+    let model = transit_model::gtfs::read_many(vec![
+        (input_dir, prefix_conf("A")),
+        ("./tests/fixtures/zipped_gtfs/gtfs.zip", prefix_conf("B")),
+    ])
+    .unwrap();
+    assert_eq!(2, model.datasets.len());
+    assert_eq!(2 * single.stop_points.len(), model.stop_points.len());
+    assert_eq!(
+        2 * single.vehicle_journeys.len(),
+        model.vehicle_journeys.len()
+    );
+    assert_eq!(single.physical_modes.len(), model.physical_modes.len());
+    assert!(model.stop_points.contains_id("A:stop:11"));
+    assert!(model.stop_points.contains_id("B:stop:11"));
+
+    let error = match transit_model::gtfs::read_many(vec![
+        (input_dir, prefix_conf("A")),
+        (input_dir, prefix_conf("A")),
+    ]) {
+        Ok(_) => panic!("the identifiers of the inputs collide"),
+        Err(error) => error,
+    };
+    assert!(format!("{:#}", error).contains("is in several datasets"));
+}
+
 #[test]
 fn test_gtfs_physical_modes() {
     test_in_tmp_dir(|path| {
@@ -142,7 +197,23 @@ fn test_minimal_gtfs_with_odt_comment() {
             on_demand_transport_comment: Some(
                 "Service à réservation {agency_name} {agency_phone}".to_string(),
             ),
+            on_demand_transport_comment_scope: gtfs::OnDemandTransportCommentScope::Agency,
             read_as_line: false,
+            contact_fields_mode: ContactFieldsMode::Loose,
+            default_agency_id: None,
+            skip_geometries: false,
+            zip_memory_budget: None,
+            skip_calendar_deduplication: false,
+            keep_unused_calendars: false,
+            stable_ids: false,
+            apply_stop_timezones: false,
+            shape_simplification_tolerance: None,
+            route_type_modes: vec![],
+            restrict_to_feed_period: false,
+            detect_encoding: false,
+            duplicate_stop_sequences: gtfs::DuplicateStopSequenceStrategy::KeepFirst,
+            keep_incomplete_trips: false,
+            datasets: None,
         };
         let model = transit_model::gtfs::Reader::new(configuration)
             .parse(input_dir)
@@ -171,7 +242,23 @@ fn test_minimal_gtfs_frequencies_with_odt_comment() {
             on_demand_transport_comment: Some(
                 "Service à réservation {agency_name} {agency_phone}".to_string(),
             ),
+            on_demand_transport_comment_scope: gtfs::OnDemandTransportCommentScope::Agency,
             read_as_line: false,
+            contact_fields_mode: ContactFieldsMode::Loose,
+            default_agency_id: None,
+            skip_geometries: false,
+            zip_memory_budget: None,
+            skip_calendar_deduplication: false,
+            keep_unused_calendars: false,
+            stable_ids: false,
+            apply_stop_timezones: false,
+            shape_simplification_tolerance: None,
+            route_type_modes: vec![],
+            restrict_to_feed_period: false,
+            detect_encoding: false,
+            duplicate_stop_sequences: gtfs::DuplicateStopSequenceStrategy::KeepFirst,
+            keep_incomplete_trips: false,
+            datasets: None,
         };
 
         let model = transit_model::gtfs::Reader::new(configuration)
@@ -186,6 +273,40 @@ fn test_minimal_gtfs_frequencies_with_odt_comment() {
     });
 }
 
+#[test]
+fn test_minimal_gtfs_with_odt_comment_by_line() {
+    let input_dir = "./tests/fixtures/gtfs2ntfs/minimal/input";
+    let configuration = gtfs::Configuration {
+        on_demand_transport_comment: Some("Service à réservation {agency_name}".to_string()),
+        on_demand_transport_comment_scope: gtfs::OnDemandTransportCommentScope::Line,
+        ..Default::default()
+    };
+    let model = transit_model::gtfs::Reader::new(configuration)
+        .parse(input_dir)
+        .unwrap();
+    let odt_comments: Vec<(&str, &str)> = model
+        .comments
+        .values()
+        .filter(|comment| comment.comment_type == CommentType::OnDemandTransport)
+        .map(|comment| (comment.id.as_str(), comment.name.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("ODT:route:2", "Service à réservation mon agence"),
+            ("ODT:route:3", "Service à réservation my agency"),
+        ],
+        odt_comments
+    );
+    let mut linked_comments: Vec<&str> = model
+        .stop_time_comments
+        .values()
+        .map(|comment_id| comment_id.as_str())
+        .collect();
+    linked_comments.sort_unstable();
+    linked_comments.dedup();
+    assert_eq!(vec!["ODT:route:2", "ODT:route:3"], linked_comments);
+}
+
 #[test]
 fn test_minimal_gtfs_with_routes_comments() {
     test_in_tmp_dir(|path| {
@@ -206,6 +327,12 @@ fn test_minimal_gtfs_with_routes_as_lines_comments() {
         let input_dir = "./tests/fixtures/gtfs2ntfs/routes_comments/input";
         let configuration = gtfs::Configuration {
             read_as_line: true,
+            contact_fields_mode: ContactFieldsMode::Loose,
+            skip_geometries: false,
+            zip_memory_budget: None,
+            skip_calendar_deduplication: false,
+            stable_ids: false,
+            datasets: None,
             ..Default::default()
         };
         let model = transit_model::gtfs::Reader::new(configuration)
@@ -219,3 +346,686 @@ fn test_minimal_gtfs_with_routes_as_lines_comments() {
         );
     });
 }
+
+#[test]
+fn test_stable_ids_of_frequencies() {
+    let input_dir = "./tests/fixtures/gtfs2ntfs/frequencies/input";
+    // The departure time of each trip
+    let read = |path: &std::path::Path, stable_ids| {
+        let configuration = gtfs::Configuration {
+            stable_ids,
+            ..Default::default()
+        };
+        let model = gtfs::Reader::new(configuration).parse(path).unwrap();
+        model
+            .vehicle_journeys
+            .values()
+            .map(|vj| (vj.id.clone(), vj.stop_times[0].departure_time))
+            .collect::<BTreeMap<_, _>>()
+    };
+    test_in_tmp_dir(|path| {
+        for entry in std::fs::read_dir(input_dir).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), path.join(entry.file_name())).unwrap();
+        }
+        let original = read(path, true);
+        let original_sequential = read(path, false);
+
+        // A frequency added before the existing ones of `trip:1`
+        let frequencies = path.join("frequencies.txt");
+        let content = std::fs::read_to_string(&frequencies).unwrap().replacen(
+            "trip:1,07:00:00",
+            "trip:1,06:00:00,06:30:00,1800,\ntrip:1,07:00:00",
+            1,
+        );
+        std::fs::write(&frequencies, content).unwrap();
+        let modified = read(path, true);
+        let modified_sequential = read(path, false);
+
+        assert!(original.contains_key("trip:1-070000"));
+        assert!(original.contains_key("trip:5-244000"));
+        assert!(modified.contains_key("trip:1-060000"));
+        assert_eq!(original.len() + 1, modified.len());
+        for (id, departure_time) in &original {
+            assert_eq!(Some(departure_time), modified.get(id), "{}", id);
+        }
+        // The numbered identifiers of `trip:1` are shifted
+        assert_ne!(
+            original_sequential["trip:1-0"],
+            modified_sequential["trip:1-0"]
+        );
+    });
+}
+
+#[test]
+fn test_gtfs_with_several_datasets() {
+    let datasets = configuration::read_datasets_config(Some(
+        "./tests/fixtures/gtfs2ntfs/config_datasets.json",
+    ))
+    .unwrap();
+    let mut prefix_conf = PrefixConfiguration::default();
+    prefix_conf.set_data_prefix("ME");
+    let configuration = gtfs::Configuration {
+        prefix_conf: Some(prefix_conf),
+        datasets: Some(datasets),
+        ..Default::default()
+    };
+    let model = gtfs::Reader::new(configuration)
+        .parse("./tests/fixtures/gtfs")
+        .unwrap();
+
+    let dataset_of = |vj_id: &str| {
+        model
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .dataset_id
+            .clone()
+    };
+    assert_eq!("ME:MetroDatasetId", dataset_of("ME:trip:3-0"));
+    assert_eq!("ME:BusDatasetId", dataset_of("ME:trip:6"));
+    // trip:5 runs after midnight, on the day after its service
+    let bus_dataset = model.datasets.get("ME:BusDatasetId").unwrap();
+    assert_eq!("ME:BusContributorId", bus_dataset.contributor_id);
+    assert_eq!(
+        (
+            chrono::NaiveDate::from_ymd_opt(2018, 1, 5).unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2018, 1, 7).unwrap()
+        ),
+        (bus_dataset.start_date, bus_dataset.end_date)
+    );
+    let metro_dataset = model.datasets.get("ME:MetroDatasetId").unwrap();
+    assert_eq!(
+        chrono::NaiveDate::from_ymd_opt(2018, 1, 3).unwrap(),
+        metro_dataset.end_date
+    );
+    assert_eq!(2, model.contributors.len());
+}
+
+#[test]
+fn test_gtfs_translations() {
+    test_in_tmp_dir(|path| {
+        let input = path.join("gtfs");
+        std::fs::create_dir(&input).unwrap();
+        for entry in std::fs::read_dir("./tests/fixtures/gtfs").unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), input.join(entry.file_name())).unwrap();
+        }
+        std::fs::write(
+            input.join("translations.txt"),
+            "table_name,field_name,language,translation,record_id,record_sub_id,field_value\n\
+             agency,agency_name,fr,mon agence FR,1,,\n\
+             stops,stop_name,fr,plop FR,stoparea:1,,\n\
+             stops,stop_name,en,pouet EN,,,pouet\n\
+             routes,route_short_name,fr,ma route 1 FR,route:2,,\n\
+             trips,trip_headsign,en,pouet EN,trip:5,,\n\
+             stops,stop_name,de,unbekannt,unknown_stop,,\n\
+             stop_times,stop_headsign,fr,pouet FR,trip:3,1,\n",
+        )
+        .unwrap();
+        let model = gtfs::read(&input).unwrap();
+
+        assert_eq!(
+            Some("mon agence FR"),
+            model.translation(ObjectType::Network, "1", "name", "fr")
+        );
+        assert_eq!(
+            Some("plop FR"),
+            model.translation(ObjectType::StopArea, "stoparea:1", "name", "fr")
+        );
+        assert_eq!(
+            Some("pouet EN"),
+            model.translation(ObjectType::StopPoint, "stop:11", "name", "en")
+        );
+        assert_eq!(
+            Some("ma route 1 FR"),
+            model.translation(ObjectType::Line, "route:2", "code", "fr")
+        );
+        // The trips generated from a frequency share its translations
+        assert_eq!(
+            Some("pouet EN"),
+            model.translation(ObjectType::VehicleJourney, "trip:5-1", "headsign", "en")
+        );
+        assert!(model.translations.values().all(|t| t.language != "de"));
+        assert!(model
+            .translations
+            .values()
+            .all(|t| t.field_name != "stop_headsign"));
+
+        let output = path.join("ntfs");
+        ntfs::write(&model, &output, get_test_datetime()).unwrap();
+        let written = ntfs::read(&output).unwrap();
+        assert_eq!(model.translations, written.translations);
+    });
+}
+
+#[test]
+fn test_gtfs_attributions() {
+    test_in_tmp_dir(|path| {
+        let input = path.join("gtfs");
+        std::fs::create_dir(&input).unwrap();
+        for entry in std::fs::read_dir("./tests/fixtures/gtfs").unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), input.join(entry.file_name())).unwrap();
+        }
+        std::fs::write(
+            input.join("attributions.txt"),
+            "attribution_id,agency_id,route_id,trip_id,organization_name,is_producer,is_operator,is_authority,attribution_url,attribution_email,attribution_phone\n\
+             a1,,,,The Producer,1,,,http://producer.example.com,,\n\
+             a2,1,,,The Operator,,1,,http://operator.example.com,contact@operator.example.com,\n\
+             a3,,route:3,,Route Operator,,1,,,,\n\
+             a4,,,trip:5,Trip Operator,,1,,,,\n\
+             a5,,,unknown_trip,Nobody,,1,,,,\n",
+        )
+        .unwrap();
+        let model = gtfs::read(&input).unwrap();
+
+        let contributor = model.contributors.values().next().unwrap();
+        assert_eq!("The Producer", contributor.name);
+        assert_eq!(
+            Some("http://producer.example.com"),
+            contributor.website.as_deref()
+        );
+        let company = model.companies.get("1").unwrap();
+        assert_eq!("mon agence", company.name);
+        assert_eq!(Some("http://kisio.org"), company.url.as_deref());
+        assert_eq!(
+            Some("contact@operator.example.com"),
+            company.mail.as_deref()
+        );
+        let attribution = |codes: &KeysValues| -> Vec<String> {
+            codes
+                .iter()
+                .filter(|(system, _)| system == "attribution")
+                .map(|(_, code)| code.clone())
+                .collect()
+        };
+        assert_eq!(vec!["The Operator"], attribution(&company.codes));
+        let route = model.routes.get("route:3").unwrap();
+        assert_eq!(vec!["Route Operator"], attribution(&route.codes));
+        // The trips generated from a frequency share its attributions
+        for vehicle_journey_id in &["trip:5-0", "trip:5-1", "trip:5-2"] {
+            let vehicle_journey = model.vehicle_journeys.get(vehicle_journey_id).unwrap();
+            assert_eq!(vec!["Trip Operator"], attribution(&vehicle_journey.codes));
+        }
+
+        let output = path.join("ntfs");
+        ntfs::write(&model, &output, get_test_datetime()).unwrap();
+        let written = ntfs::read(&output).unwrap();
+        assert_eq!(
+            vec!["Route Operator"],
+            attribution(&written.routes.get("route:3").unwrap().codes)
+        );
+        assert_eq!(
+            vec!["Trip Operator"],
+            attribution(&written.vehicle_journeys.get("trip:5-0").unwrap().codes)
+        );
+        assert_eq!(
+            "The Producer",
+            written.contributors.values().next().unwrap().name
+        );
+    });
+}
+
+#[test]
+fn test_gtfs_continuous_stopping() {
+    test_in_tmp_dir(|path| {
+        let input = path.join("gtfs");
+        std::fs::create_dir(&input).unwrap();
+        for entry in std::fs::read_dir("./tests/fixtures/gtfs").unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), input.join(entry.file_name())).unwrap();
+        }
+        std::fs::write(
+            input.join("routes.txt"),
+            "route_id,route_short_name,route_long_name,route_type,agency_id,continuous_pickup,continuous_drop_off\n\
+             route:2,ma route 1,,1,1,0,\n\
+             route:3,ma route 2,,1,2,7,3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            input.join("stop_times.txt"),
+            "trip_id,stop_sequence,stop_id,arrival_time,departure_time,continuous_pickup,continuous_drop_off\n\
+             trip:4,0,stop:11,07:23:00,07:23:00,,\n\
+             trip:4,1,stop:22,07:32:00,07:32:00,2,\n\
+             trip:4,2,stop:33,07:40:00,07:42:00,,\n\
+             trip:6,0,stop:61,14:40:00,14:40:00,,9\n\
+             trip:6,1,stop:61,15:20:00,15:20:00,1,\n",
+        )
+        .unwrap();
+        let model = gtfs::read(&input).unwrap();
+
+        let property = |line_id: &str, name: &str| {
+            model
+                .lines
+                .get(line_id)
+                .unwrap()
+                .object_properties
+                .get(name)
+                .cloned()
+        };
+        assert_eq!(
+            Some("0".to_string()),
+            property("route:2", "continuous_pickup")
+        );
+        assert_eq!(None, property("route:2", "continuous_drop_off"));
+        // The invalid value of the route is ignored
+        assert_eq!(None, property("route:3", "continuous_pickup"));
+        assert_eq!(
+            Some("3".to_string()),
+            property("route:3", "continuous_drop_off")
+        );
+        let continuous_stoppings = |model: &Model, vehicle_journey_id: &str| -> Vec<_> {
+            model
+                .vehicle_journeys
+                .get(vehicle_journey_id)
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|st| (st.continuous_pickup, st.continuous_drop_off))
+                .collect()
+        };
+        // The stop times without value take the one of the route
+        assert_eq!(
+            vec![(Some(0), None), (Some(2), None), (Some(0), None)],
+            continuous_stoppings(&model, "trip:4-0")
+        );
+        assert_eq!(
+            vec![(None, Some(3)), (Some(1), Some(3))],
+            continuous_stoppings(&model, "trip:6")
+        );
+
+        let ntfs_output = path.join("ntfs");
+        ntfs::write(&model, &ntfs_output, get_test_datetime()).unwrap();
+        let from_ntfs = ntfs::read(&ntfs_output).unwrap();
+        assert_eq!(
+            continuous_stoppings(&model, "trip:4-0"),
+            continuous_stoppings(&from_ntfs, "trip:4-0")
+        );
+        assert_eq!(
+            model.lines.get("route:3").unwrap().object_properties,
+            from_ntfs.lines.get("route:3").unwrap().object_properties
+        );
+
+        let gtfs_output = path.join("gtfs_output");
+        gtfs::write(model, &gtfs_output, false).unwrap();
+        let from_gtfs = gtfs::read(&gtfs_output).unwrap();
+        assert_eq!(
+            continuous_stoppings(&from_ntfs, "trip:6"),
+            continuous_stoppings(&from_gtfs, "trip:6")
+        );
+        assert_eq!(
+            Some("3"),
+            from_gtfs
+                .lines
+                .values()
+                .find(|line| line.code.as_deref() == Some("ma route 2"))
+                .and_then(|line| line.object_properties.get("continuous_drop_off"))
+                .map(String::as_str)
+        );
+    });
+}
+
+#[test]
+fn test_gtfs_trip_transfers() {
+    test_in_tmp_dir(|path| {
+        let input = path.join("gtfs");
+        std::fs::create_dir(&input).unwrap();
+        for entry in std::fs::read_dir("./tests/fixtures/gtfs").unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), input.join(entry.file_name())).unwrap();
+        }
+        std::fs::write(
+            input.join("transfers.txt"),
+            "from_stop_id,to_stop_id,from_trip_id,to_trip_id,transfer_type,min_transfer_time\n\
+             stop:11,stop:61,,,2,60\n\
+             ,,trip:4-0,trip:6,4,\n\
+             stop:61,stop:51,trip:6,trip:5-0,5,\n\
+             ,,trip:6,unknown,4,\n\
+             ,,trip:6,,5,\n",
+        )
+        .unwrap();
+        let model = gtfs::read(&input).unwrap();
+
+        assert_eq!(1, model.transfers.len());
+        let mut trip_transfers: Vec<_> = model
+            .trip_transfers
+            .values()
+            .map(|t| {
+                (
+                    t.from_vehicle_journey_id.as_str(),
+                    t.to_vehicle_journey_id.as_str(),
+                    t.in_seat,
+                )
+            })
+            .collect();
+        trip_transfers.sort_unstable();
+        assert_eq!(
+            vec![("trip:4-0", "trip:6", true), ("trip:6", "trip:5-0", false)],
+            trip_transfers
+        );
+
+        let gtfs_output = path.join("gtfs_output");
+        gtfs::write(model, &gtfs_output, false).unwrap();
+        let transfers = std::fs::read_to_string(gtfs_output.join("transfers.txt")).unwrap();
+        let mut lines = transfers.lines();
+        assert_eq!(
+            Some("from_stop_id,to_stop_id,transfer_type,min_transfer_time,from_trip_id,to_trip_id"),
+            lines.next()
+        );
+        assert_eq!(Some("stop:11,stop:61,2,60,,"), lines.next());
+        let mut trip_lines: Vec<_> = lines.collect();
+        trip_lines.sort_unstable();
+        assert_eq!(
+            vec![
+                "stop:33,stop:61,4,,trip:4-0,trip:6",
+                "stop:61,stop:51,5,,trip:6,trip:5-0"
+            ],
+            trip_lines
+        );
+    });
+}
+
+fn copy_gtfs(path: &std::path::Path) -> std::path::PathBuf {
+    let input = path.join("gtfs");
+    std::fs::create_dir(&input).unwrap();
+    for entry in std::fs::read_dir("./tests/fixtures/gtfs").unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), input.join(entry.file_name())).unwrap();
+    }
+    input
+}
+
+fn copy_gtfs_with_feed_info(path: &std::path::Path, feed_info: &str) -> std::path::PathBuf {
+    let input = copy_gtfs(path);
+    std::fs::write(input.join("feed_info.txt"), feed_info).unwrap();
+    input
+}
+
+#[test]
+fn test_gtfs_with_detected_encoding() {
+    test_in_tmp_dir(|path| {
+        let input = copy_gtfs(path);
+        let stops = std::fs::read_to_string(input.join("stops.txt")).unwrap();
+        let latin1 = stops
+            .replace("stoparea:1,plop,", "stoparea:1,H\u{f4}tel de Ville,")
+            .chars()
+            .map(|c| c as u8)
+            .collect::<Vec<u8>>();
+        std::fs::write(input.join("stops.txt"), latin1).unwrap();
+
+        assert!(gtfs::read(&input).is_err());
+        let configuration = gtfs::Configuration {
+            detect_encoding: true,
+            ..Default::default()
+        };
+        let model = gtfs::Reader::new(configuration).parse(&input).unwrap();
+        assert_eq!(
+            "Hôtel de Ville",
+            model.stop_areas.get("stoparea:1").unwrap().name
+        );
+    });
+}
+
+#[test]
+fn test_gtfs_restricted_to_feed_period() {
+    let feed_info =
+        "feed_publisher_name,feed_publisher_url,feed_lang,feed_start_date,feed_end_date\n\
+                     Hove,http://www.hove.com,fr,20180102,20180105\n";
+    test_in_tmp_dir(|path| {
+        let input = copy_gtfs_with_feed_info(path, feed_info);
+        let model = gtfs::read(&input).unwrap();
+        let dataset = model.datasets.values().next().unwrap();
+        assert_eq!(chrono::NaiveDate::from_ymd(2018, 1, 2), dataset.start_date);
+        assert_eq!(chrono::NaiveDate::from_ymd(2018, 1, 5), dataset.end_date);
+        // The calendars are kept by default
+        let calendar = model.calendars.get("service:1").unwrap();
+        assert!(calendar
+            .dates
+            .contains(&chrono::NaiveDate::from_ymd(2018, 1, 1)));
+
+        let configuration = gtfs::Configuration {
+            restrict_to_feed_period: true,
+            ..Default::default()
+        };
+        let model = gtfs::Reader::new(configuration).parse(&input).unwrap();
+        let dataset = model.datasets.values().next().unwrap();
+        assert_eq!(chrono::NaiveDate::from_ymd(2018, 1, 2), dataset.start_date);
+        assert_eq!(chrono::NaiveDate::from_ymd(2018, 1, 5), dataset.end_date);
+        let calendar = model.calendars.get("service:1").unwrap();
+        assert!(!calendar
+            .dates
+            .contains(&chrono::NaiveDate::from_ymd(2018, 1, 1)));
+        let calendar = model.calendars.get("service:2").unwrap();
+        assert!(!calendar
+            .dates
+            .contains(&chrono::NaiveDate::from_ymd(2018, 1, 6)));
+    });
+}
+
+#[test]
+fn test_gtfs_outside_of_feed_period() {
+    let feed_info =
+        "feed_publisher_name,feed_publisher_url,feed_lang,feed_start_date,feed_end_date\n\
+                     Hove,http://www.hove.com,fr,20190101,20191231\n";
+    test_in_tmp_dir(|path| {
+        let input = copy_gtfs_with_feed_info(path, feed_info);
+        let error = gtfs::read(&input).err().unwrap();
+        assert_eq!(
+            "the validity period of dataset default_dataset from 20180101 to 20180106 does not intersect the period of feed_info.txt from 20190101 to 20191231",
+            format!("{}", error.root_cause())
+        );
+    });
+}
+
+#[test]
+fn test_gtfs_apply_stop_timezones() {
+    test_in_tmp_dir(|path| {
+        let files = [
+            (
+                "agency.txt",
+                "agency_id,agency_name,agency_url,agency_timezone\n\
+                 1,agency,http://example.com,Europe/Paris\n",
+            ),
+            (
+                "stops.txt",
+                "stop_id,stop_name,stop_lat,stop_lon,stop_timezone\n\
+                 paris,Paris,48.8,2.3,\n\
+                 london,London,51.5,-0.1,Europe/London\n\
+                 dover,Dover,51.1,1.3,Europe/London\n",
+            ),
+            (
+                "routes.txt",
+                "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                 r,1,R,Route,2\n",
+            ),
+            (
+                "trips.txt",
+                "route_id,service_id,trip_id\n\
+                 r,winter,winter_trip\n\
+                 r,summer,summer_trip\n",
+            ),
+            (
+                "calendar_dates.txt",
+                "service_id,date,exception_type\n\
+                 winter,20200115,1\n\
+                 summer,20200715,1\n",
+            ),
+            (
+                "stop_times.txt",
+                "trip_id,stop_sequence,stop_id,arrival_time,departure_time\n\
+                 winter_trip,0,paris,23:00:00,23:00:00\n\
+                 winter_trip,1,london,23:30:00,23:40:00\n\
+                 winter_trip,2,dover,00:10:00,00:10:00\n\
+                 summer_trip,0,london,09:00:00,09:00:00\n\
+                 summer_trip,1,paris,12:00:00,12:00:00\n",
+            ),
+        ];
+        let input = path.join("gtfs");
+        std::fs::create_dir(&input).unwrap();
+        for (file, content) in &files {
+            std::fs::write(input.join(file), content).unwrap();
+        }
+        let times = |model: &Model, vehicle_journey_id: &str| -> Vec<String> {
+            model
+                .vehicle_journeys
+                .get(vehicle_journey_id)
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|st| format!("{}-{}", st.arrival_time, st.departure_time))
+                .collect()
+        };
+
+        let model = gtfs::read(&input).unwrap();
+        assert_eq!(
+            vec!["09:00:00-09:00:00", "12:00:00-12:00:00"],
+            times(&model, "summer_trip")
+        );
+
+        let configuration = gtfs::Configuration {
+            apply_stop_timezones: true,
+            ..Default::default()
+        };
+        let model = gtfs::Reader::new(configuration).parse(&input).unwrap();
+        // London is 1 hour behind Paris, and the times after midnight in
+        // London stay after the previous ones
+        assert_eq!(
+            vec![
+                "23:00:00-23:00:00",
+                "24:30:00-24:40:00",
+                "25:10:00-25:10:00"
+            ],
+            times(&model, "winter_trip")
+        );
+        assert_eq!(
+            vec!["10:00:00-10:00:00", "12:00:00-12:00:00"],
+            times(&model, "summer_trip")
+        );
+    });
+}
+
+#[test]
+fn test_write_split_by_network() {
+    test_in_tmp_dir(|path| {
+        let mut collections = ntfs::read_collections("./tests/fixtures/minimal_ntfs").unwrap();
+        collections.remove_stop_zones();
+        for id in &["TGN:Bus", "Empty"] {
+            collections
+                .networks
+                .push(transit_model::objects::Network {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        collections.lines.get_mut("B42").unwrap().network_id = "TGN:Bus".to_string();
+        // Nation is only served by TGN, its node and its entrance being
+        // linked to the bus stop of Gare de Lyon by pathways
+        for (id, stop_type, parent_id) in &[
+            ("GDL:E", StopType::StopEntrance, "GDL"),
+            ("NAT:N", StopType::GenericNode, "NAT"),
+            ("NAT:E", StopType::StopEntrance, "NAT"),
+            ("CHA:E", StopType::StopEntrance, "CHA"),
+        ] {
+            collections
+                .stop_locations
+                .push(StopLocation {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    stop_type: stop_type.clone(),
+                    parent_id: Some(parent_id.to_string()),
+                    coord: Coord {
+                        lon: 2.372987,
+                        lat: 48.844746,
+                    },
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        for (id, from_stop_id, to_stop_id) in &[
+            ("PW:1", "GDLB", "NAT:N"),
+            ("PW:2", "NAT:E", "NAT:N"),
+            ("PW:3", "CHA:E", "CHAM"),
+        ] {
+            collections
+                .pathways
+                .push(Pathway {
+                    id: id.to_string(),
+                    from_stop_id: from_stop_id.to_string(),
+                    to_stop_id: to_stop_id.to_string(),
+                    is_bidirectional: true,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let model = Model::new(collections).unwrap();
+        gtfs::write_split_by_network(model, path, &gtfs::WriteConfiguration::default()).unwrap();
+
+        let mut file_names: Vec<_> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        file_names.sort_unstable();
+        assert_eq!(vec!["TGN.zip", "TGN_Bus.zip"], file_names);
+
+        let bus = gtfs::read(path.join("TGN_Bus.zip")).unwrap();
+        let line_ids: Vec<_> = bus.lines.values().map(|line| &line.id[..]).collect();
+        assert_eq!(vec!["B42"], line_ids);
+        assert_eq!(2, bus.vehicle_journeys.len());
+        let mut stop_points: Vec<_> = bus.stop_points.values().map(|sp| &sp.id[..]).collect();
+        stop_points.sort_unstable();
+        assert_eq!(vec!["GDLB", "MTPB"], stop_points);
+
+        let tgn = gtfs::read(path.join("TGN.zip")).unwrap();
+        assert!(tgn.stop_points.get("MTPB").is_none());
+        // Gare de Lyon is served by both networks
+        assert!(bus.stop_areas.get("GDL").is_some());
+        assert!(tgn.stop_areas.get("GDL").is_some());
+
+        // The locations are kept by their parent or by the pathways to them
+        let mut stop_locations: Vec<_> = bus.stop_locations.values().map(|sl| &sl.id[..]).collect();
+        stop_locations.sort_unstable();
+        assert_eq!(vec!["GDL:E", "NAT:E", "NAT:N"], stop_locations);
+        let mut pathways: Vec<_> = bus.pathways.values().map(|pw| &pw.id[..]).collect();
+        pathways.sort_unstable();
+        assert_eq!(vec!["PW:1", "PW:2"], pathways);
+        assert!(tgn.stop_locations.get("CHA:E").is_some());
+        assert!(tgn.pathways.get("PW:3").is_some());
+    });
+}
+
+#[test]
+fn test_gtfs_entrances_round_trip() {
+    test_in_tmp_dir(|path| {
+        let fixture_dir = "./tests/fixtures/gtfs2ntfs/entrances";
+        let model = gtfs::read(format!("{}/input", fixture_dir)).unwrap();
+        let ntfs_path = path.join("ntfs");
+        ntfs::write(&model, &ntfs_path, get_test_datetime()).unwrap();
+        compare_output_dir_with_expected(
+            &ntfs_path,
+            Some(vec!["stops.txt", "equipments.txt", "levels.txt"]),
+            format!("{}/ntfs_output", fixture_dir),
+        );
+
+        let model = ntfs::read(&ntfs_path).unwrap();
+        let gtfs_path = path.join("gtfs");
+        gtfs::write(model, &gtfs_path, false).unwrap();
+        compare_output_dir_with_expected(
+            &gtfs_path,
+            Some(vec!["stops.txt", "levels.txt"]),
+            format!("{}/gtfs_output", fixture_dir),
+        );
+
+        // The GTFS written back converts to the same NTFS stops
+        let model = gtfs::read(&gtfs_path).unwrap();
+        let ntfs_path = path.join("ntfs_again");
+        ntfs::write(&model, &ntfs_path, get_test_datetime()).unwrap();
+        compare_output_dir_with_expected(
+            &ntfs_path,
+            Some(vec!["stops.txt", "equipments.txt", "levels.txt"]),
+            format!("{}/ntfs_output", fixture_dir),
+        );
+    });
+}
@@ -0,0 +1,91 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use pretty_assertions::assert_eq;
+use std::io::{Cursor, Write};
+use transit_model::{gtfs, ntfs};
+
+// The files are embedded in the test so that the conversion never reads the
+// disk
+const GTFS_FILES: [(&str, &str); 6] = [
+    (
+        "agency.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/agency.txt"),
+    ),
+    (
+        "calendar_dates.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/calendar_dates.txt"),
+    ),
+    (
+        "routes.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/routes.txt"),
+    ),
+    (
+        "stop_times.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/stop_times.txt"),
+    ),
+    (
+        "stops.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/stops.txt"),
+    ),
+    (
+        "trips.txt",
+        include_str!("fixtures/gtfs2ntfs/minimal/input/trips.txt"),
+    ),
+];
+
+fn gtfs_zip() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in &GTFS_FILES {
+        zip.start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn gtfs_to_ntfs_to_gtfs_in_memory() {
+    // Any temporary file would fail to be created
+    std::env::set_var("TMPDIR", "/nonexistent/transit_model");
+    let current_datetime = chrono::DateTime::parse_from_rfc3339("2019-04-03T17:19:00Z").unwrap();
+
+    let gtfs_model = gtfs::from_zip_reader(Cursor::new(gtfs_zip()), "gtfs.zip").unwrap();
+    let ntfs_zip =
+        ntfs::write_to_zip_writer(&gtfs_model, Cursor::new(Vec::new()), current_datetime)
+            .unwrap()
+            .into_inner();
+
+    let ntfs_model = ntfs::from_zip_reader(Cursor::new(ntfs_zip), "ntfs.zip").unwrap();
+    assert_eq!(gtfs_model.networks.len(), ntfs_model.networks.len());
+    assert_eq!(gtfs_model.stop_points.len(), ntfs_model.stop_points.len());
+    assert_eq!(
+        gtfs_model.vehicle_journeys.len(),
+        ntfs_model.vehicle_journeys.len()
+    );
+    let vehicle_journeys = ntfs_model.vehicle_journeys.len();
+    let stop_points = ntfs_model.stop_points.len();
+
+    let gtfs_zip = gtfs::write_to_zip_writer(
+        ntfs_model,
+        Cursor::new(Vec::new()),
+        &gtfs::WriteConfiguration::default(),
+    )
+    .unwrap()
+    .into_inner();
+
+    let model = gtfs::from_zip_reader(Cursor::new(gtfs_zip), "gtfs.zip").unwrap();
+    assert_eq!(vehicle_journeys, model.vehicle_journeys.len());
+    assert_eq!(stop_points, model.stop_points.len());
+}
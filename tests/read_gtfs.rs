@@ -26,6 +26,27 @@ fn zipped_gtfs_reading() {
     assert_eq!(ntm.stop_areas.len(), 1);
 }
 
+#[test]
+fn zipped_gtfs_decompressed_in_memory() {
+    let configuration = transit_model::gtfs::Configuration {
+        zip_memory_budget: Some(1024),
+        ..Default::default()
+    };
+    let model = transit_model::gtfs::Reader::new(configuration)
+        .parse("tests/fixtures/zipped_gtfs/gtfs.zip")
+        .unwrap();
+    let expected = transit_model::gtfs::read("tests/fixtures/zipped_gtfs/gtfs.zip").unwrap();
+    assert_eq!(expected.stop_points.len(), model.stop_points.len());
+    assert_eq!(
+        expected.vehicle_journeys.len(),
+        model.vehicle_journeys.len()
+    );
+    assert_eq!(
+        serde_json::to_value(expected.into_collections()).unwrap(),
+        serde_json::to_value(model.into_collections()).unwrap()
+    );
+}
+
 #[test]
 fn gtfs_with_config_reading() {
     let mut feed = std::collections::BTreeMap::<_, _>::default();
@@ -97,6 +97,71 @@ fn zipped_minimal() {
     test_minimal_ntfs(&ntm);
 }
 
+#[test]
+fn minimal_with_report() {
+    let (ntm, report) =
+        transit_model::ntfs::read_with_report("tests/fixtures/minimal_ntfs/").unwrap();
+    test_minimal_ntfs(&ntm);
+    let routes = &report.files["routes.txt"];
+    assert!(routes
+        .missing_columns
+        .contains(&"destination_id".to_string()));
+    assert!(routes.skipped_rows.is_empty());
+    let stops = &report.files["stops.txt"];
+    assert!(stops.missing_columns.contains(&"visible".to_string()));
+    assert!(!stops.missing_columns.contains(&"stop_name".to_string()));
+}
+
+#[test]
+fn report_of_the_skipped_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    for entry in std::fs::read_dir("tests/fixtures/minimal_ntfs/").unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.path().join(path.file_name().unwrap())).unwrap();
+    }
+    create_file_with_content(
+        dir.path(),
+        "geometries.txt",
+        "geometry_id,geometry_wkt\n\
+         geo_1,\"LINESTRING(2.37 48.84, 2.39 48.85)\"\n\
+         geo_2,NOT A GEOMETRY",
+    );
+    let (_, report) = transit_model::ntfs::read_with_report(dir.path()).unwrap();
+    let geometries = &report.files["geometries.txt"];
+    assert!(geometries.missing_columns.is_empty());
+    assert_eq!(1, geometries.skipped_rows.len());
+    assert_eq!(Some(3), geometries.skipped_rows[0].line);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn minimal_with_mmap() {
+    let configuration = transit_model::ntfs::ReadConfiguration {
+        mmap: true,
+        ..Default::default()
+    };
+    let ntm = transit_model::ntfs::read_with_configuration(
+        "tests/fixtures/minimal_ntfs/",
+        &configuration,
+    )
+    .unwrap();
+    test_minimal_ntfs(&ntm);
+}
+
+#[test]
+fn zipped_minimal_decompressed_in_memory() {
+    let configuration = transit_model::ntfs::ReadConfiguration {
+        zip_memory_budget: Some(usize::MAX),
+        ..Default::default()
+    };
+    let ntm = transit_model::ntfs::read_with_configuration(
+        "tests/fixtures/zipped_ntfs/minimal_ntfs.zip",
+        &configuration,
+    )
+    .unwrap();
+    test_minimal_ntfs(&ntm);
+}
+
 #[test]
 #[should_panic(
     expected = "file \"tests/fixtures/i_m_not_here\" is neither a file nor a directory, cannot read a ntfs from it"
@@ -160,6 +225,19 @@ fn test_minimal_fares_stay_same() {
     });
 }
 
+#[test]
+fn test_ticket_uses_stay_same() {
+    let ntm = transit_model::ntfs::read("tests/fixtures/ntfs/").unwrap();
+    test_in_tmp_dir(|output_dir| {
+        transit_model::ntfs::write(&ntm, output_dir, get_test_datetime()).unwrap();
+        compare_output_dir_with_expected(
+            output_dir,
+            Some(vec!["ticket_uses.txt"]),
+            "tests/fixtures/ntfs2ntfs/ticket_uses",
+        );
+    });
+}
+
 #[test]
 fn test_minimal_platforms_stay_same() {
     let ntm = transit_model::ntfs::read("tests/fixtures/ntfs2ntfs/platforms").unwrap();
@@ -233,8 +311,8 @@ fn ntfs() {
     );
     assert_eq!(None, iter.next());
 
-    let mut stop_time_comments = HashMap::<(String, u32), String>::new();
-    stop_time_comments.insert(("RERAB1".to_string(), 5), "RERACOM1".to_string());
+    let mut stop_time_comments = HashMap::<(IdString, u32), IdString>::new();
+    stop_time_comments.insert(("RERAB1".into(), 5), "RERACOM1".into());
 
     assert_eq!(stop_time_comments, pt_objects.stop_time_comments);
 }
@@ -314,6 +392,7 @@ fn sanitize_frequencies() {
         start_time: Time::new(0, 0, 0),
         end_time: Time::new(0, 0, 0),
         headway_secs: 0,
+        exact_times: false,
     };
     collections.frequencies.push(frequency);
     collections.sanitize().unwrap();
@@ -424,7 +503,7 @@ fn ntfs_with_duplicated_ids() {
             .iter()
             .filter(|log| log.level == tracing::log::Level::Warn)
             .collect::<Vec<_>>();
-        assert_eq!(19, captured_warn_logs.len());
+        assert_eq!(20, captured_warn_logs.len());
         let expected_logs = vec![
             "identifier ME:DefaultContributorId already exists", // Contributors
             "identifier ME:4bf028c5-276a-411c-9f56-3fc1e9d005d0 already exists", // Datasets
@@ -445,6 +524,7 @@ fn ntfs_with_duplicated_ids() {
             "identifier ME:node:1 already exists",               // StopLocations
             "identifier ME:path1 already exists",                // Pathways
             "identifier ME:4bf028:1 already exists",             // Comments
+            "feed_infos.txt declares the NTFS 0.11.4, which doesn't support the 2 levels, 3 pathways",
         ];
         for (i, expected_log) in expected_logs.iter().enumerate() {
             assert!(captured_warn_logs[i].body.contains(expected_log));
@@ -467,3 +547,30 @@ fn ntfs_with_duplicated_objects_without_id() {
     assert_eq!(1, model.grid_periods.len());
     assert_eq!(2, model.grid_rel_calendar_line.len());
 }
+
+#[test]
+fn skip_geometries() {
+    let configuration = transit_model::ntfs::ReadConfiguration {
+        skip_geometries: true,
+        ..Default::default()
+    };
+    let model = transit_model::ntfs::read_with_configuration(
+        "tests/fixtures/restrict-validity-period/input",
+        &configuration,
+    )
+    .unwrap();
+    assert!(model.skipped_geometries);
+    assert!(model.geometries.is_empty());
+    assert_eq!(
+        Some("geo:2:kept"),
+        model.routes.get("M1F").unwrap().geometry_id.as_deref()
+    );
+    assert!(model.check_references().is_empty());
+    test_in_tmp_dir(|path| {
+        let error = transit_model::ntfs::write(&model, path, get_test_datetime()).unwrap_err();
+        assert_eq!(
+            "the geometries were skipped when reading, the model can't be written",
+            error.to_string()
+        );
+    });
+}
@@ -57,3 +57,35 @@ fn test_restrict_no_panic() {
         transit_model::ntfs::write(&new_model, path, get_test_datetime()).unwrap();
     });
 }
+
+#[test]
+fn test_restrict_removes_dataset_without_date() {
+    test_in_tmp_dir(|path| {
+        let objects =
+            transit_model::ntfs::read(Path::new("./tests/fixtures/restrict-validity-period/input"))
+                .unwrap();
+        let mut collections = objects.into_collections();
+        // the trips of 'dataset:removed' run from October to December
+        collections
+            .restrict_period(
+                NaiveDate::from_ymd_opt(2018, 5, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2018, 5, 31).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            vec!["dataset:kept"],
+            collections
+                .datasets
+                .values()
+                .map(|dataset| dataset.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        let new_model = Model::new(collections).unwrap();
+        transit_model::gtfs::write(new_model, path, false).unwrap();
+        assert_eq!(
+            "feed_publisher_name,feed_publisher_url,feed_lang,default_lang,feed_start_date,feed_end_date,feed_version,feed_contact_email,feed_contact_url\n\
+            ,,,,20180501,20180501,,,\n",
+            std::fs::read_to_string(path.join("feed_info.txt")).unwrap()
+        );
+    });
+}
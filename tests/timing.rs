@@ -0,0 +1,62 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+// A separate test binary: setting a subscriber, even for a single thread,
+// stops the `log` records of `tracing` that other tests capture
+
+use tracing_subscriber::layer::SubscriberExt as _;
+use transit_model::{
+    model::Model,
+    ntfs,
+    timing::{TimingLayer, BUILD_MODEL, ENHANCER, READ_COLLECTIONS, READ_FILE, WRITE_FILE},
+};
+
+#[test]
+fn stages_of_a_conversion() {
+    let collections = ntfs::read("tests/fixtures/minimal_ntfs")
+        .unwrap()
+        .into_collections();
+    let timing = TimingLayer::default();
+    let subscriber = tracing_subscriber::registry().with(timing.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        let model = Model::new(collections).unwrap();
+        let ntfs = ntfs::write_to_zip_writer(
+            &model,
+            std::io::Cursor::new(Vec::new()),
+            chrono::DateTime::parse_from_rfc3339("2019-04-03T17:19:00Z").unwrap(),
+        )
+        .unwrap();
+        ntfs::from_zip_reader(ntfs, "ntfs.zip").unwrap();
+    });
+
+    let summary = timing.summary();
+    let find = |stage: &str, target: Option<&str>| {
+        summary
+            .iter()
+            .find(|timing| timing.stage == stage && timing.target.as_deref() == target)
+            .unwrap_or_else(|| panic!("no span {} {:?}", stage, target))
+    };
+    assert_eq!(2, find(BUILD_MODEL, None).count);
+    find(ENHANCER, Some("sanitize"));
+    find(READ_COLLECTIONS, Some("ntfs"));
+    // Written by a thread of its own
+    let written = find(WRITE_FILE, Some("stop_times.txt"));
+    let read = find(READ_FILE, Some("stop_times.txt"));
+    // The 23 stop times of the fixture
+    assert_eq!(Some(23), written.rows);
+    assert_eq!(written.rows, read.rows);
+    assert!(summary
+        .windows(2)
+        .all(|timings| timings[0].duration >= timings[1].duration));
+}
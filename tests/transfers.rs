@@ -38,6 +38,21 @@ fn test_generates_transfers() {
     });
 }
 
+#[test]
+fn test_generates_reverse_transfers() {
+    test_in_tmp_dir(|path| {
+        let input_dir = "tests/fixtures/transfers/mono_contributor/input";
+        let model = transit_model::ntfs::read(input_dir).unwrap();
+        let model = transfers::generates_reverse_transfers(model, 0.785, 120).unwrap();
+        transit_model::ntfs::write(&model, path, get_test_datetime()).unwrap();
+        compare_output_dir_with_expected(
+            path,
+            Some(vec!["transfers.txt"]),
+            "./tests/fixtures/transfers/reverse_transfers/output",
+        );
+    });
+}
+
 #[test]
 fn test_generates_all_multi_contributors_transfers() {
     test_in_tmp_dir(|path| {
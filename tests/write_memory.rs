@@ -0,0 +1,246 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+use transit_model::{
+    gtfs, model::Model, ntfs, objects::IdString, test_utils::*, testing::SyntheticDataset,
+};
+
+// Allocator keeping track of the memory allocated and of its peak
+struct PeakAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAllocator = PeakAllocator;
+
+// The counters being global, the measures can't run concurrently
+static MEASURE: Mutex<()> = Mutex::new(());
+
+// The memory allocated by `f` at its peak, on top of the memory allocated
+// before its call
+fn peak_memory<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    (result, PEAK.load(Ordering::Relaxed) - before)
+}
+
+// The stop times are ordered by trip_id then stop_sequence
+fn check_stop_times_order(stop_times: impl std::io::Read) {
+    let mut reader = csv::Reader::from_reader(stop_times);
+    let headers = reader.headers().unwrap().clone();
+    let trip_id = headers.iter().position(|h| h == "trip_id").unwrap();
+    let stop_sequence = headers.iter().position(|h| h == "stop_sequence").unwrap();
+    let keys: Vec<(String, u32)> = reader
+        .records()
+        .map(|record| {
+            let record = record.unwrap();
+            (
+                record[trip_id].to_string(),
+                record[stop_sequence].parse().unwrap(),
+            )
+        })
+        .collect();
+    assert!(keys.windows(2).all(|keys| keys[0] < keys[1]));
+}
+
+// The rows of stop_times.txt are never materialized: the memory allocated
+// while writing, whatever the output, stays far below the size of the file
+fn check_stop_times(path: &Path, peak: usize) {
+    let (size, stop_times): (usize, Box<dyn std::io::Read>) = if path.is_dir() {
+        let path = path.join("stop_times.txt");
+        (
+            std::fs::metadata(&path).unwrap().len() as usize,
+            Box::new(File::open(path).unwrap()),
+        )
+    } else {
+        let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut content = Vec::new();
+        archive
+            .by_name("stop_times.txt")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        (content.len(), Box::new(std::io::Cursor::new(content)))
+    };
+    assert!(
+        peak < size / 10,
+        "{:?}: {} bytes allocated to write a stop_times.txt of {} bytes",
+        path,
+        peak,
+        size
+    );
+    check_stop_times_order(stop_times);
+}
+
+#[test]
+fn stop_times_are_streamed() {
+    let _measure = MEASURE.lock().unwrap_or_else(PoisonError::into_inner);
+    let dataset = SyntheticDataset {
+        lines: 40,
+        stops_per_line: 30,
+        trips_per_route: 50,
+        ..Default::default()
+    };
+    let model = dataset.model().unwrap();
+    // Not to measure the reading of the written NTFS
+    let ntfs_configuration = ntfs::WriteConfiguration {
+        verify: false,
+        ..Default::default()
+    };
+    let gtfs_configuration = gtfs::WriteConfiguration::default();
+    test_in_tmp_dir(|path| {
+        let ntfs_path = path.join("ntfs");
+        let (_, peak) = peak_memory(|| {
+            ntfs::write_with_configuration(
+                &model,
+                &ntfs_path,
+                get_test_datetime(),
+                &ntfs_configuration,
+            )
+            .unwrap()
+        });
+        check_stop_times(&ntfs_path, peak);
+
+        let ntfs_zip = path.join("ntfs.zip");
+        let (_, peak) = peak_memory(|| {
+            ntfs::write_to_zip_with_configuration(
+                &model,
+                &ntfs_zip,
+                get_test_datetime(),
+                &ntfs_configuration,
+            )
+            .unwrap()
+        });
+        check_stop_times(&ntfs_zip, peak);
+
+        let gtfs_path = path.join("gtfs");
+        let model = dataset.model().unwrap();
+        let (_, peak) = peak_memory(|| {
+            gtfs::write_with_configuration(model, &gtfs_path, &gtfs_configuration).unwrap()
+        });
+        check_stop_times(&gtfs_path, peak);
+
+        let gtfs_zip = path.join("gtfs.zip");
+        let model = dataset.model().unwrap();
+        let (_, peak) = peak_memory(|| {
+            gtfs::write_to_zip_with_configuration(model, &gtfs_zip, &gtfs_configuration).unwrap()
+        });
+        check_stop_times(&gtfs_zip, peak);
+    });
+}
+
+// The memory freed by dropping `value`
+fn freed_memory<T>(value: T) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    drop(value);
+    before - ALLOCATED.load(Ordering::Relaxed)
+}
+
+type StopTimeMap<K> = HashMap<(K, u32), K>;
+
+fn owned_map(map: &StopTimeMap<IdString>) -> StopTimeMap<String> {
+    let mut owned: StopTimeMap<String> = map
+        .iter()
+        .map(|((vj_id, sequence), value)| ((vj_id.to_string(), *sequence), value.to_string()))
+        .collect();
+    owned.shrink_to_fit();
+    owned
+}
+
+// The stop time maps read from a NTFS share the ids of their vehicle
+// journeys and their headsigns, taking a third less memory than `String`s
+#[test]
+fn stop_time_ids_are_interned() {
+    let _measure = MEASURE.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut collections = SyntheticDataset {
+        lines: 20,
+        stops_per_line: 20,
+        trips_per_route: 20,
+        ..Default::default()
+    }
+    .collections();
+    for vj in collections.vehicle_journeys.values() {
+        for stop_time in &vj.stop_times {
+            let key = (IdString::from(vj.id.as_str()), stop_time.sequence);
+            collections
+                .stop_time_headsigns
+                .insert(key.clone(), IdString::from(vj.route_id.as_str()));
+            collections.stop_time_ids.insert(
+                key,
+                IdString::from(format!("st:{}:{}", vj.id, stop_time.sequence)),
+            );
+        }
+    }
+    let model = Model::new(collections).unwrap();
+    let ntfs_configuration = ntfs::WriteConfiguration {
+        verify: false,
+        ..Default::default()
+    };
+    test_in_tmp_dir(|path| {
+        ntfs::write_with_configuration(&model, path, get_test_datetime(), &ntfs_configuration)
+            .unwrap();
+        let mut collections = ntfs::read(path).unwrap().into_collections();
+        let vj = collections.vehicle_journeys.values().next().unwrap();
+        let sequence = vj.stop_times[0].sequence;
+        assert_eq!(
+            Some(vj.route_id.as_str()),
+            collections.stop_time_headsign(&vj.id, sequence)
+        );
+        assert_eq!(
+            Some(format!("st:{}:{}", vj.id, sequence).as_str()),
+            collections.stop_time_id(&vj.id, sequence)
+        );
+
+        let mut headsigns = std::mem::take(&mut collections.stop_time_headsigns);
+        let mut ids = std::mem::take(&mut collections.stop_time_ids);
+        headsigns.shrink_to_fit();
+        ids.shrink_to_fit();
+        let owned = freed_memory((owned_map(&headsigns), owned_map(&ids)));
+        let interned = freed_memory((headsigns, ids));
+        assert!(
+            interned < owned * 3 / 4,
+            "{} bytes for the interned maps, {} bytes for the maps of `String`s",
+            interned,
+            owned
+        );
+    });
+}
@@ -0,0 +1,125 @@
+// Copyright 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see
+// <http://www.gnu.org/licenses/>.
+
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tracing::info;
+use transit_model::{
+    validator::{validate_with_configuration, IssueCode, Severity, ValidationConfiguration},
+    Result,
+};
+
+lazy_static::lazy_static! {
+    pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
+}
+
+fn get_version() -> &'static str {
+    &GIT_VERSION
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "transit-model-validator",
+    about = "Check the consistency of a NTFS or a GTFS and write a JSON report.",
+    version = get_version(),
+    rename_all = "kebab-case"
+)]
+struct Opt {
+    /// Input directory or ZIP archive.
+    #[structopt(short, long, parse(from_os_str), default_value = ".")]
+    input: PathBuf,
+
+    /// Read the input as a GTFS instead of a NTFS.
+    #[structopt(long)]
+    gtfs: bool,
+
+    /// Output JSON report.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Exit with an error if an issue has at least this severity
+    /// ('info', 'warning' or 'error').
+    #[structopt(long, default_value = "error")]
+    fail_on: Severity,
+
+    /// Maximal plausible speed in km/h between two stops for a physical mode,
+    /// as 'physical_mode_id=speed' (for example 'Bus=90'). Can be repeated.
+    #[structopt(
+        long = "max-speed",
+        number_of_values = 1,
+        parse(try_from_str = parse_max_speed)
+    )]
+    max_speeds: Vec<(String, f64)>,
+
+    /// Ignore the issues with this code in the report (for example
+    /// 'unused_calendar'). Can be repeated.
+    #[structopt(long = "ignore-issue", number_of_values = 1)]
+    ignore_issues: Vec<IssueCode>,
+
+    /// Don't read the geometries (geometries.txt or shapes.txt), the
+    /// references to them are then not checked.
+    #[structopt(long)]
+    skip_geometries: bool,
+}
+
+fn parse_max_speed(s: &str) -> Result<(String, f64)> {
+    let (physical_mode_id, speed) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not formatted as 'physical_mode_id=speed'", s))?;
+    Ok((physical_mode_id.to_string(), speed.parse()?))
+}
+
+fn run(opt: Opt) -> Result<Option<Severity>> {
+    info!("Launching transit-model-validator...");
+    let collections = if opt.gtfs {
+        let configuration = transit_model::gtfs::Configuration {
+            skip_geometries: opt.skip_geometries,
+            ..Default::default()
+        };
+        transit_model::gtfs::Reader::new(configuration).parse_collections(opt.input)?
+    } else {
+        let configuration = transit_model::ntfs::ReadConfiguration {
+            skip_geometries: opt.skip_geometries,
+            ..Default::default()
+        };
+        transit_model::ntfs::read_collections_with_configuration(opt.input, &configuration)?
+    };
+    let mut config = ValidationConfiguration::default();
+    config.max_speeds.extend(opt.max_speeds);
+    let (mut report, _) = validate_with_configuration(collections, &config);
+    report.ignore(&opt.ignore_issues);
+    report.write(opt.output)?;
+    Ok(report.max_severity())
+}
+
+fn main() {
+    transit_model::timing::init_logger(None);
+    let opt = Opt::from_args();
+    let fail_on = opt.fail_on;
+    match run(opt) {
+        Ok(Some(severity)) if severity >= fail_on => {
+            eprintln!("Issues with severity '{}' found", severity);
+            std::process::exit(2);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            for cause in err.chain() {
+                eprintln!("{}", cause);
+            }
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use assert_cmd::prelude::*;
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn test_valid_ntfs() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("transit-model-validator")
+        .expect("Failed to find binary 'transit-model-validator'")
+        .arg("--input")
+        .arg("../tests/fixtures/minimal_ntfs/")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .arg("--fail-on")
+        .arg("info")
+        .assert()
+        .success();
+    assert!(report.is_file());
+}
+
+#[test]
+fn test_valid_gtfs() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("transit-model-validator")
+        .expect("Failed to find binary 'transit-model-validator'")
+        .arg("--input")
+        .arg("../tests/fixtures/gtfs/")
+        .arg("--gtfs")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .assert()
+        .success();
+    assert!(report.is_file());
+}
+
+#[test]
+fn test_fail_on_severity() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("transit-model-validator")
+        .expect("Failed to find binary 'transit-model-validator'")
+        .arg("--input")
+        .arg("tests/fixtures/unused_calendar/")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .assert()
+        .success();
+    Command::cargo_bin("transit-model-validator")
+        .expect("Failed to find binary 'transit-model-validator'")
+        .arg("--input")
+        .arg("tests/fixtures/unused_calendar/")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .arg("--fail-on")
+        .arg("info")
+        .assert()
+        .code(2);
+    let report = std::fs::read_to_string(report).unwrap();
+    assert!(report.contains("\"unused_calendar\""));
+}
+
+#[test]
+fn test_ignore_issue() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report = output_dir.path().join("report.json");
+    Command::cargo_bin("transit-model-validator")
+        .expect("Failed to find binary 'transit-model-validator'")
+        .arg("--input")
+        .arg("tests/fixtures/unused_calendar/")
+        .arg("--output")
+        .arg(report.to_str().unwrap())
+        .arg("--fail-on")
+        .arg("info")
+        .arg("--ignore-issue")
+        .arg("unused_calendar")
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(report).unwrap();
+    assert!(!report.contains("\"unused_calendar\""));
+}